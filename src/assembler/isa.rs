@@ -0,0 +1,671 @@
+// One entry per instruction: how it is written, what it does, whether it sends a request to the
+// model server, and an example line.
+pub struct InstructionInfo {
+    pub mnemonic: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+    pub uses_model: bool,
+    pub example: &'static str,
+}
+
+pub const INSTRUCTIONS: &[InstructionInfo] = &[
+    // Data movement.
+    InstructionInfo {
+        mnemonic: "ls",
+        usage: "ls rd, str",
+        description: "Load string into rd. ${xN} is replaced by the value of xN when the instruction runs.",
+        uses_model: false,
+        example: "ls x1, \"Summarise the text below.\"",
+    },
+    InstructionInfo {
+        mnemonic: "fmt",
+        usage: "fmt rd, str, rs1, rs2, ...",
+        description: "Load str into rd with each {N} replaced by the value of rsN when the instruction runs.",
+        uses_model: false,
+        example: "fmt x1, \"score: {1} (threshold {2})\", x3, x4",
+    },
+    InstructionInfo {
+        mnemonic: "li",
+        usage: "li rd, imm",
+        description: "Load immediate into rd.",
+        uses_model: false,
+        example: "li x2, 100",
+    },
+    InstructionInfo {
+        mnemonic: "lc",
+        usage: "lc rd, str",
+        description: "Load the content of the file at path str into rd.",
+        uses_model: false,
+        example: "lc x1, \"examples/data/user_post.txt\"",
+    },
+    InstructionInfo {
+        mnemonic: "argn",
+        usage: "argn rd, str",
+        description: "Load the program argument named str (passed with --arg name=value) into rd.",
+        uses_model: false,
+        example: "argn x1, \"topic\"",
+    },
+    InstructionInfo {
+        mnemonic: "arg",
+        usage: "arg rd, imm",
+        description: "Load the positional run argument at index imm (0 is the first after the program path) into rd.",
+        uses_model: false,
+        example: "arg x1, 0",
+    },
+    InstructionInfo {
+        mnemonic: "argc",
+        usage: "argc rd",
+        description: "Load the number of positional run arguments into rd.",
+        uses_model: false,
+        example: "argc x1",
+    },
+    InstructionInfo {
+        mnemonic: "ld",
+        usage: "ld rd, str1, str2",
+        description: "List the files under the directory str1 matching the glob str2 into rd, sorted and newline-separated. Links to directories are not followed.",
+        uses_model: false,
+        example: "ld x1, \"examples/data\", \"*.txt\"",
+    },
+    InstructionInfo {
+        mnemonic: "le",
+        usage: "le rd, str1[, str2]",
+        description: "Load the environment variable named str1 into rd as text, or str2 if it is not set. Without str2, an unset variable is an error.",
+        uses_model: false,
+        example: "le x1, \"USER_NAME\", \"there\"",
+    },
+    InstructionInfo {
+        mnemonic: "sw",
+        usage: "sw addr, rs",
+        description: "Store a copy of rs in data memory at addr, a number or a register holding one.",
+        uses_model: false,
+        example: "sw x5, x1",
+    },
+    InstructionInfo {
+        mnemonic: "lw",
+        usage: "lw rd, addr",
+        description: "Load the word at addr in data memory into rd. Words never stored load as none.",
+        uses_model: false,
+        example: "lw x1, 0",
+    },
+    InstructionInfo {
+        mnemonic: "mv",
+        usage: "mv rd, rs",
+        description: "Copy rs into rd.",
+        uses_model: false,
+        example: "mv x2, x1",
+    },
+    InstructionInfo {
+        mnemonic: "mvc",
+        usage: "mvc rdc, rsc",
+        description: "Copy the context register rsc into rdc. c0 is always empty, so 'mvc rdc, c0' clears rdc.",
+        uses_model: false,
+        example: "mvc c10, c0",
+    },
+    // Stack operations.
+    InstructionInfo {
+        mnemonic: "spush",
+        usage: "spush rs",
+        description: "Push a copy of rs onto the data stack.",
+        uses_model: false,
+        example: "spush x1",
+    },
+    InstructionInfo {
+        mnemonic: "spop",
+        usage: "spop rd",
+        description: "Pop the top of the data stack into rd. Popping an empty stack is an error.",
+        uses_model: false,
+        example: "spop x1",
+    },
+    InstructionInfo {
+        mnemonic: "speek",
+        usage: "speek rd",
+        description: "Copy the top of the data stack into rd without removing it.",
+        uses_model: false,
+        example: "speek x1",
+    },
+    // Control flow.
+    InstructionInfo {
+        mnemonic: "beq",
+        usage: "beq rs1, rs2, label_name",
+        description: "Go to label if rs1 = rs2.",
+        uses_model: false,
+        example: "beq x31, x3, ABORT",
+    },
+    InstructionInfo {
+        mnemonic: "blt",
+        usage: "blt rs1, rs2, label_name",
+        description: "Go to label if rs1 < rs2.",
+        uses_model: false,
+        example: "blt x3, x2, RETRY",
+    },
+    InstructionInfo {
+        mnemonic: "ble",
+        usage: "ble rs1, rs2, label_name",
+        description: "Go to label if rs1 <= rs2.",
+        uses_model: false,
+        example: "ble x3, x2, RETRY",
+    },
+    InstructionInfo {
+        mnemonic: "bgt",
+        usage: "bgt rs1, rs2, label_name",
+        description: "Go to label if rs1 > rs2.",
+        uses_model: false,
+        example: "bgt x3, x2, REJECT",
+    },
+    InstructionInfo {
+        mnemonic: "bge",
+        usage: "bge rs1, rs2, label_name",
+        description: "Go to label if rs1 >= rs2.",
+        uses_model: false,
+        example: "bge x3, x2, REJECT",
+    },
+    InstructionInfo {
+        mnemonic: "jmp",
+        usage: "jmp label_name",
+        description: "Go to label.",
+        uses_model: false,
+        example: "jmp DONE",
+    },
+    InstructionInfo {
+        mnemonic: "ckpt",
+        usage: "ckpt str",
+        description: "Save the state of the run under the name str when --checkpoint-dir is set, otherwise do nothing.",
+        uses_model: false,
+        example: "ckpt \"after-summary\"",
+    },
+    InstructionInfo {
+        mnemonic: "exit",
+        usage: "exit [imm | rs]",
+        description: "Exit the program with status imm or the integer in rs, or 0 if none is given.",
+        uses_model: false,
+        example: "exit 1",
+    },
+    // I/O.
+    InstructionInfo {
+        mnemonic: "put",
+        usage: "put rs",
+        description: "Print the value of rs.",
+        uses_model: false,
+        example: "put x1",
+    },
+    InstructionInfo {
+        mnemonic: "pln",
+        usage: "pln rs",
+        description: "Print the value of rs followed by a newline.",
+        uses_model: false,
+        example: "pln x1",
+    },
+    InstructionInfo {
+        mnemonic: "err",
+        usage: "err rs",
+        description: "Print the value of rs to stderr followed by a newline.",
+        uses_model: false,
+        example: "err x1",
+    },
+    InstructionInfo {
+        mnemonic: "pcx",
+        usage: "pcx rsc",
+        description: "Print the content of the context register rsc.",
+        uses_model: false,
+        example: "pcx c1",
+    },
+    InstructionInfo {
+        mnemonic: "in",
+        usage: "in rd[, str]",
+        description: "Read a line from stdin into rd without its line ending, printing the prompt str first if given. A closed stdin reads as an empty line.",
+        uses_model: false,
+        example: "in x1, \"> \"",
+    },
+    InstructionInfo {
+        mnemonic: "wf",
+        usage: "wf str, rs",
+        description: "Write the value of rs to the file at path str, replacing it and creating missing directories.",
+        uses_model: false,
+        example: "wf \"out/summary.txt\", x1",
+    },
+    InstructionInfo {
+        mnemonic: "af",
+        usage: "af str, rs",
+        description: "Append the value of rs to the file at path str, creating it and missing directories.",
+        uses_model: false,
+        example: "af \"out/log.txt\", x1",
+    },
+    // Generative operations.
+    InstructionInfo {
+        mnemonic: "inf",
+        usage: "inf rd, rs, rsc",
+        description: "Use rs as the next message and store the response in rd using context register rsc.",
+        uses_model: true,
+        example: "inf x4, x3, c1",
+    },
+    InstructionInfo {
+        mnemonic: "ask",
+        usage: "ask rd, rs1, rs2",
+        description: "Answer the question rs1 using only the text in rs2 and store the answer in rd, or UNKNOWN if the text does not contain it.",
+        uses_model: true,
+        example: "ask x3, x1, x2",
+    },
+    InstructionInfo {
+        mnemonic: "trl",
+        usage: "trl rd, rs1, str | rs2",
+        description: "Translate the text in rs1 into the language str or the text in rs2 and store the translation in rd.",
+        uses_model: true,
+        example: "trl x2, x1, \"French\"",
+    },
+    // Guardrails operations.
+    InstructionInfo {
+        mnemonic: "eval",
+        usage: "eval rd, rs, rsc",
+        description: "Boolean evaluation of the question rs and store the response in rd (0 = false/no, 1 = true/yes) using context register rsc.",
+        uses_model: true,
+        example: "eval x3, x2, c1",
+    },
+    InstructionInfo {
+        mnemonic: "sim",
+        usage: "sim rd, rs1, rs2",
+        description: "Similarity between rs1 and rs2 and store the result in rd (0 - 100).",
+        uses_model: true,
+        example: "sim x3, x1, x2",
+    },
+    InstructionInfo {
+        mnemonic: "emb",
+        usage: "emb rd, rs[, imm]",
+        description: "Embed the text in rs and store the vector in rd, so SIM can reuse it without another request. With imm, embed the imm registers from rs into the imm registers from rd, all with one request.",
+        uses_model: true,
+        example: "emb x5, x1, 3",
+    },
+    InstructionInfo {
+        mnemonic: "bsim",
+        usage: "bsim rs1, rs2, imm, label_name",
+        description: "Go to label if the similarity between rs1 and rs2 is at least imm (0 - 100). No register is changed.",
+        uses_model: true,
+        example: "bsim x1, x2, 80, refund",
+    },
+    InstructionInfo {
+        mnemonic: "cls",
+        usage: "cls rd, rs, str[, ri]",
+        description: "Classify the text in rs with exactly one of the labels in str, separated by '|'. Store the label in rd and its index in the list (from 0) in ri, if given. Asks once more if the reply is not a label, then fails.",
+        uses_model: true,
+        example: "cls x2, x1, \"billing|bug|feature\", x3",
+    },
+    InstructionInfo {
+        mnemonic: "score",
+        usage: "score rd, rs, str",
+        description: "Rate how well the text in rs meets the criteria in str, from 0 to 100, and store the number in rd. Asks once more if the reply has no number, then fails.",
+        uses_model: true,
+        example: "score x2, x1, \"polite and on topic\"",
+    },
+    InstructionInfo {
+        mnemonic: "json",
+        usage: "json rd, rs, str",
+        description: "Extract the fields named in str, separated by ',' or given as a JSON object, from the text in rs as a JSON object and store its text in rd. Sends the reason back once if the reply is not a JSON object with every field, then fails.",
+        uses_model: true,
+        example: "json x2, x1, \"name, email, address.city\"",
+    },
+    // Model selection.
+    InstructionInfo {
+        mnemonic: "setmodel",
+        usage: "setmodel str",
+        description: "Use the text model named str for the model requests of the instructions that follow, instead of TEXT_MODEL.",
+        uses_model: false,
+        example: "setmodel \"qwen3-0.6b\"",
+    },
+    InstructionInfo {
+        mnemonic: "setemb",
+        usage: "setemb str",
+        description: "Use the embedding model named str for the embeddings of the instructions that follow, instead of EMBEDDING_MODEL.",
+        uses_model: false,
+        example: "setemb \"nomic-embed-text\"",
+    },
+    // Sampling.
+    InstructionInfo {
+        mnemonic: "settemp",
+        usage: "settemp imm | rs",
+        description: "Set the temperature of later text model requests to imm or the integer in rs divided by 100 (0 - 200 for 0.0 - 2.0).",
+        uses_model: false,
+        example: "settemp 90",
+    },
+    InstructionInfo {
+        mnemonic: "settopp",
+        usage: "settopp imm | rs",
+        description: "Set the top-p of later text model requests to imm or the integer in rs divided by 100 (0 - 100 for 0.0 - 1.0).",
+        uses_model: false,
+        example: "settopp 95",
+    },
+    InstructionInfo {
+        mnemonic: "setmaxtok",
+        usage: "setmaxtok imm | rs",
+        description: "Limit later text model responses to imm or the integer in rs tokens, or remove the limit with -1.",
+        uses_model: false,
+        example: "setmaxtok 256",
+    },
+    // System prompt.
+    InstructionInfo {
+        mnemonic: "setsys",
+        usage: "setsys str | rs",
+        description: "Use str or the text in rs as the system message of later text model requests. An empty string sends no system message.",
+        uses_model: false,
+        example: "setsys \"Answer with one word.\"",
+    },
+    // Vector store.
+    InstructionInfo {
+        mnemonic: "vput",
+        usage: "vput str | rs1, rs2",
+        description: "Store the text in rs2 in the vector store under the key str or the text in rs1, replacing any text stored under that key. It is embedded by the next VQUERY or at the end of the run, together with every other text put in between.",
+        uses_model: false,
+        example: "vput \"refunds\", x1",
+    },
+    InstructionInfo {
+        mnemonic: "vquery",
+        usage: "vquery rd, rs, imm",
+        description: "Store the imm texts in the vector store most similar to the text in rs into rd, most similar first and separated by a blank line. An empty store gives an empty string.",
+        uses_model: true,
+        example: "vquery x2, x1, 3",
+    },
+    InstructionInfo {
+        mnemonic: "vclr",
+        usage: "vclr",
+        description: "Remove every text from the vector store, and from VECTOR_STORE_FILE when it is set.",
+        uses_model: false,
+        example: "vclr",
+    },
+    // Context operations.
+    InstructionInfo {
+        mnemonic: "psh",
+        usage: "psh rdc, rs, \"user\"|\"assistant\"|\"system\"",
+        description: "Push rs into the context stack rdc with role.",
+        uses_model: false,
+        example: "psh c1, x1, \"user\"",
+    },
+    InstructionInfo {
+        mnemonic: "pshu",
+        usage: "pshu rdc, rs",
+        description: "Push rs into the context stack rdc as a user message. Short for 'psh rdc, rs, \"user\"'.",
+        uses_model: false,
+        example: "pshu c1, x1",
+    },
+    InstructionInfo {
+        mnemonic: "psha",
+        usage: "psha rdc, rs",
+        description: "Push rs into the context stack rdc as an assistant message. Short for 'psh rdc, rs, \"assistant\"'.",
+        uses_model: false,
+        example: "psha c1, x2",
+    },
+    InstructionInfo {
+        mnemonic: "pshs",
+        usage: "pshs rdc, rs",
+        description: "Push rs into the context stack rdc as a system message. Short for 'psh rdc, rs, \"system\"'.",
+        uses_model: false,
+        example: "pshs c1, x1",
+    },
+    InstructionInfo {
+        mnemonic: "pop",
+        usage: "pop rd, rsc",
+        description: "Pop the bottom of the context stack rsc into rd.",
+        uses_model: false,
+        example: "pop x1, c1",
+    },
+    InstructionInfo {
+        mnemonic: "drp",
+        usage: "drp rsc",
+        description: "Drop the bottom of the context stack rsc.",
+        uses_model: false,
+        example: "drp c1",
+    },
+    InstructionInfo {
+        mnemonic: "ctxlen",
+        usage: "ctxlen rd, rsc",
+        description: "Store the number of messages in the context stack rsc in rd.",
+        uses_model: false,
+        example: "ctxlen x1, c1",
+    },
+    InstructionInfo {
+        mnemonic: "ctxtrim",
+        usage: "ctxtrim rdc, imm",
+        description: "Keep only the imm most recent messages of the context stack rdc, and a first system message.",
+        uses_model: false,
+        example: "ctxtrim c1, 10",
+    },
+    InstructionInfo {
+        mnemonic: "ctxpeek",
+        usage: "ctxpeek rd, rsc",
+        description: "Copy the bottom of the context stack rsc into rd without removing it.",
+        uses_model: false,
+        example: "ctxpeek x1, c1",
+    },
+    InstructionInfo {
+        mnemonic: "ctxins",
+        usage: "ctxins rdc, imm, rs, \"user\"|\"assistant\"|\"system\"",
+        description: "Insert rs into the context stack rdc with role as message imm, counted from the oldest message from 0.",
+        uses_model: false,
+        example: "ctxins c1, 0, x1, \"user\"",
+    },
+    InstructionInfo {
+        mnemonic: "ctxsave",
+        usage: "ctxsave \"path\", rsc",
+        description: "Write the context stack rsc to the file at path as JSON, replacing the file in one step.",
+        uses_model: false,
+        example: "ctxsave \"session.json\", c1",
+    },
+    InstructionInfo {
+        mnemonic: "ctxload",
+        usage: "ctxload rdc, \"path\"[, label]",
+        description: "Replace the context stack rdc with one saved by ctxsave. If the file does not exist, jump to label, or stop the program if there is none.",
+        uses_model: false,
+        example: "ctxload c1, \"session.json\", fresh",
+    },
+    // Arithmetic operations.
+    InstructionInfo {
+        mnemonic: "subi",
+        usage: "subi rd, num",
+        description: "Decrement the value in rd by num.",
+        uses_model: false,
+        example: "subi x31, 1",
+    },
+    InstructionInfo {
+        mnemonic: "addn",
+        usage: "addn rd, rs1, rs2",
+        description: "Store the integer sum of rs1 and rs2 in rd. Runs locally without the model.",
+        uses_model: false,
+        example: "addn x3, x1, x2",
+    },
+    InstructionInfo {
+        mnemonic: "subn",
+        usage: "subn rd, rs1, rs2",
+        description: "Store rs1 minus rs2 in rd as an integer.",
+        uses_model: false,
+        example: "subn x3, x1, x2",
+    },
+    InstructionInfo {
+        mnemonic: "muln",
+        usage: "muln rd, rs1, rs2",
+        description: "Store the integer product of rs1 and rs2 in rd.",
+        uses_model: false,
+        example: "muln x3, x1, x2",
+    },
+    InstructionInfo {
+        mnemonic: "divn",
+        usage: "divn rd, rs1, rs2",
+        description: "Store rs1 divided by rs2 in rd, rounded toward zero. Dividing by zero is an error.",
+        uses_model: false,
+        example: "divn x3, x1, x2",
+    },
+    InstructionInfo {
+        mnemonic: "addi",
+        usage: "addi rd, num",
+        description: "Increment the value in rd by num.",
+        uses_model: false,
+        example: "addi x1, 1",
+    },
+    InstructionInfo {
+        mnemonic: "inc",
+        usage: "inc rd, num",
+        description: "Another name for addi, assembled to the same byte code.",
+        uses_model: false,
+        example: "inc x1, 1",
+    },
+    InstructionInfo {
+        mnemonic: "muli",
+        usage: "muli rd, num",
+        description: "Multiply the value in rd by num.",
+        uses_model: false,
+        example: "muli x1, 10",
+    },
+    InstructionInfo {
+        mnemonic: "divi",
+        usage: "divi rd, num",
+        description: "Divide the value in rd by num, rounded toward zero. Dividing by zero is an error.",
+        uses_model: false,
+        example: "divi x1, 2",
+    },
+    // String operations.
+    InstructionInfo {
+        mnemonic: "fmtn",
+        usage: "fmtn rd, rs, str",
+        description: "Format the number in rs as text into rd using the spec [0][width][,][%], e.g. \"04%\" gives 007%.",
+        uses_model: false,
+        example: "fmtn x2, x1, \"04%\"",
+    },
+    InstructionInfo {
+        mnemonic: "esc",
+        usage: "esc rd, rs[, str]",
+        description: "Copy rs into rd as text with the template syntax of str escaped: every ${ to $${ for \"ls\" (the default), or every { and } to {{ and }} for \"fmt\".",
+        uses_model: false,
+        example: "esc x2, x1, \"fmt\"",
+    },
+    InstructionInfo {
+        mnemonic: "cat",
+        usage: "cat rd, rs1, rs2[, str]",
+        description: "Join rs1 and rs2 into rd as text, with the separator str between them if given. Numbers are joined as they print.",
+        uses_model: false,
+        example: "cat x3, x1, x2, \", \"",
+    },
+    InstructionInfo {
+        mnemonic: "len",
+        usage: "len rd, rs",
+        description: "Store the number of characters (not bytes) in the text in rs into rd.",
+        uses_model: false,
+        example: "len x2, x1",
+    },
+    InstructionInfo {
+        mnemonic: "tok",
+        usage: "tok rd, rs",
+        description: "Store about how many tokens the text in rs is into rd, estimated locally or counted by the server when TOKENIZE_REMOTE is true.",
+        uses_model: false,
+        example: "tok x2, x1",
+    },
+    InstructionInfo {
+        mnemonic: "upper",
+        usage: "upper rd, rs",
+        description: "Copy the text in rs into rd in upper case, following Unicode case mapping.",
+        uses_model: false,
+        example: "upper x2, x1",
+    },
+    InstructionInfo {
+        mnemonic: "lower",
+        usage: "lower rd, rs",
+        description: "Copy the text in rs into rd in lower case, following Unicode case mapping.",
+        uses_model: false,
+        example: "lower x2, x1",
+    },
+    InstructionInfo {
+        mnemonic: "trim",
+        usage: "trim rd, rs",
+        description: "Copy the text in rs into rd without leading and trailing whitespace.",
+        uses_model: false,
+        example: "trim x2, x1",
+    },
+    InstructionInfo {
+        mnemonic: "chunk",
+        usage: "chunk rd, rs, imm[, addr]",
+        description: "Split the text in rs into chunks of at most imm characters, ending each at a paragraph, sentence or word boundary where one fits. Store them in data memory from addr (0 if not given) on, one per word, and the number of chunks in rd.",
+        uses_model: false,
+        example: "chunk x2, x1, 2000, 100",
+    },
+    InstructionInfo {
+        mnemonic: "jget",
+        usage: "jget rd, rs, str",
+        description: "Store the value at the field path str, such as 'address.city' or 'items.0', of the JSON text in rs in rd. Strings become text, whole numbers numbers, and anything else its JSON text.",
+        uses_model: false,
+        example: "jget x3, x2, \"address.city\"",
+    },
+];
+
+impl InstructionInfo {
+    // The usage, description and example, as printed by `isa` and the debugger's help command.
+    pub fn help(&self) -> String {
+        let mut help = format!("{}\n  {}\n", self.usage, self.description);
+
+        if self.uses_model {
+            help.push_str("  Sends a request to the model server.\n");
+        }
+
+        help.push_str(&format!("  Example: {}", self.example));
+        help
+    }
+}
+
+pub fn lookup(mnemonic: &str) -> Option<&'static InstructionInfo> {
+    let mnemonic = mnemonic.to_lowercase();
+    INSTRUCTIONS.iter().find(|info| info.mnemonic == mnemonic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Assembler;
+
+    // The operands of an example that name labels: identifiers outside strings that are not
+    // registers.
+    fn labels(example: &str) -> Vec<&str> {
+        let mut in_string = false;
+
+        example
+            .split(|c: char| {
+                if c == '"' {
+                    in_string = !in_string;
+                }
+
+                in_string || c == '"' || c == ',' || c.is_whitespace()
+            })
+            .skip(1)
+            .filter(|word| word.starts_with(|c: char| c.is_ascii_alphabetic()))
+            .filter(|word| {
+                let number = word.get(1..).unwrap_or("");
+                !(word.starts_with(['x', 'c']) && number.parse::<u32>().is_ok())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_example_assembles() {
+        for info in INSTRUCTIONS {
+            let mut source = format!("{}\n", info.example);
+
+            for label in labels(info.example) {
+                source.push_str(&format!("{}:\n", label));
+            }
+
+            source.push_str("exit 0\n");
+
+            if let Err(e) = Assembler::new(source.clone(), "example.aasm").assemble() {
+                panic!(
+                    "The example for '{}' does not assemble: {}\n{}",
+                    info.mnemonic, e, source
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_example_uses_its_instruction() {
+        for info in INSTRUCTIONS {
+            assert_eq!(
+                info.example.split_whitespace().next(),
+                Some(info.mnemonic),
+                "{}",
+                info.example
+            );
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use miniserde::json::{self, Value};
+
+// Fields JSON asks for are written as one string: a list such as "name, address.city", or a JSON
+// object such as '{"name": "", "address": {"city": ""}}' whose keys name them.
+pub const SEPARATOR: char = ',';
+
+// The keys of a field path such as "address.city". A number picks an item of an array, so
+// "items.0.name" is the name of the first item. Keys cannot contain '.'.
+pub fn path(path: &str) -> Result<Vec<&str>, String> {
+    let keys = path.split('.').map(str::trim).collect::<Vec<_>>();
+
+    if keys.iter().any(|key| key.is_empty()) {
+        return Err(format!("Field path '{}' contains an empty key.", path));
+    }
+
+    Ok(keys)
+}
+
+// The leaf paths of a JSON object, with nested objects joined by '.'. An empty object is a leaf,
+// since it names no field below it.
+fn leaves(object: &Value, prefix: &str, paths: &mut Vec<String>) {
+    match object {
+        Value::Object(object) if !object.is_empty() || prefix.is_empty() => {
+            for (key, value) in object.iter() {
+                let path = match prefix {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", prefix, key),
+                };
+                leaves(value, &path, paths);
+            }
+        }
+        _ => paths.push(prefix.to_string()),
+    }
+}
+
+// The field paths a JSON field list names, in order. The assembler checks the list when it is
+// built and the executor reads it again from the data segment, so both report the same problems.
+pub fn parse(list: &str) -> Result<Vec<String>, String> {
+    let fields = if list.trim_start().starts_with('{') {
+        let schema = match json::from_str::<Value>(list) {
+            Ok(schema @ Value::Object(_)) => schema,
+            _ => return Err(format!("Field schema '{}' is not a JSON object.", list)),
+        };
+
+        let mut fields = Vec::new();
+        leaves(&schema, "", &mut fields);
+        fields
+    } else {
+        list.split(SEPARATOR)
+            .map(|field| field.trim().to_string())
+            .collect()
+    };
+
+    if fields.is_empty() {
+        return Err(format!("Field list '{}' names no fields.", list));
+    }
+
+    for (index, field) in fields.iter().enumerate() {
+        if field.is_empty() {
+            return Err(format!("Field list '{}' contains an empty field.", list));
+        }
+
+        path(field)?;
+
+        if fields[..index].contains(field) {
+            return Err(format!(
+                "Field list '{}' repeats the field '{}'.",
+                list, field
+            ));
+        }
+    }
+
+    Ok(fields)
+}
+
+// The fields as the prompt shows them: a schema as written, or a list one field per line.
+pub fn describe(list: &str, fields: &[String]) -> String {
+    if list.trim_start().starts_with('{') {
+        return list.trim().to_string();
+    }
+
+    fields
+        .iter()
+        .map(|field| format!("- {}", field))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// The value at a field path, or None when a key is missing or an array is too short.
+pub fn lookup<'a>(value: &'a Value, path: &[&str]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, key| match value {
+        Value::Object(object) => object.get(*key),
+        Value::Array(array) => key.parse::<usize>().ok().and_then(|index| array.get(index)),
+        _ => None,
+    })
+}
+
+// A reply with any Markdown code fence around it removed, since models often add one.
+pub fn unfenced(reply: &str) -> &str {
+    let reply = reply.trim();
+
+    let Some(body) = reply
+        .strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+    else {
+        return reply;
+    };
+
+    // The opening fence may name the language, as in '```json'.
+    match body.split_once('\n') {
+        Some((language, rest)) if !language.contains('{') => rest.trim(),
+        _ => body.trim(),
+    }
+}
+
+// Why a reply is not a JSON object with every field, worded to follow "invalid because".
+pub fn check(reply: &str, fields: &[String]) -> Result<(), String> {
+    let value = json::from_str::<Value>(reply).map_err(|_| "it is not valid JSON".to_string())?;
+
+    if !matches!(value, Value::Object(_)) {
+        return Err("it is not a JSON object".to_string());
+    }
+
+    let missing = fields
+        .iter()
+        .filter(|field| path(field).map_or(true, |keys| lookup(&value, &keys).is_none()))
+        .map(|field| format!("'{}'", field))
+        .collect::<Vec<_>>();
+
+    match missing.len() {
+        0 => Ok(()),
+        1 => Err(format!("it is missing the field {}", missing[0])),
+        _ => Err(format!("it is missing the fields {}", missing.join(", "))),
+    }
+}
@@ -0,0 +1,49 @@
+// Labels CLS chooses from are written as one string, such as "billing|bug|feature".
+pub const SEPARATOR: char = '|';
+
+fn same(label_a: &str, label_b: &str) -> bool {
+    label_a.to_lowercase() == label_b.to_lowercase()
+}
+
+// The labels in a CLS label list, trimmed and in order. The assembler checks the list when it is
+// built and the executor reads it again from the data segment, so both report the same problems.
+pub fn parse(list: &str) -> Result<Vec<&str>, String> {
+    let labels = list.split(SEPARATOR).map(str::trim).collect::<Vec<_>>();
+
+    if labels.len() < 2 {
+        return Err(format!(
+            "Label list '{}' needs at least two labels separated by '{}'.",
+            list, SEPARATOR
+        ));
+    }
+
+    if labels.iter().any(|label| label.is_empty()) {
+        return Err(format!("Label list '{}' contains an empty label.", list));
+    }
+
+    // Replies are matched without regard to case, so labels differing only in case are the same.
+    if let Some((index, label)) = labels
+        .iter()
+        .enumerate()
+        .find(|(index, label)| labels[..*index].iter().any(|earlier| same(earlier, label)))
+    {
+        return Err(format!(
+            "Label list '{}' repeats the label '{}' at position {}.",
+            list,
+            label,
+            index + 1
+        ));
+    }
+
+    Ok(labels)
+}
+
+// The index of the label a model reply names. Case, surrounding whitespace, quotes and a final full
+// stop are ignored, since models add them to otherwise exact answers; anything else is no match.
+pub fn find(labels: &[&str], reply: &str) -> Option<usize> {
+    let quote = |character| matches!(character, '"' | '\'' | '`' | '*');
+    let reply = reply.trim().trim_matches(quote);
+    let reply = reply.strip_suffix('.').unwrap_or(reply).trim_matches(quote);
+
+    labels.iter().position(|label| same(label, reply.trim()))
+}
@@ -1,53 +1,205 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs::{canonicalize, read_to_string};
+use std::path::PathBuf;
 
-use crate::assembler::opcode::OpCode;
+use miniserde::{Deserialize, Serialize};
+
+use crate::assembler::opcode::{AddressMode, ImmediateType, OpCode};
 use crate::assembler::scanner::Scanner;
 use crate::assembler::scanner::token::{Token, TokenType};
+use crate::constants::{
+    BYTE_CODE_MAGIC, BYTE_CODE_VERSION, DEBUG_INFO_MAGIC, MAX_DATA_SEGMENT_WORDS, MAX_STRING_BYTES,
+    MAX_TEXT_SEGMENT_WORDS,
+};
 use crate::exception::{BaseException, Exception};
 
+pub mod isa;
+pub mod json_fields;
+pub mod labels;
 pub mod opcode;
 pub mod roles;
 mod scanner;
 
-const HEADER_SIZE: u32 = 2;
+// Magic word, format version, instruction section pointer, data section pointer, text segment size,
+// data segment size, source hash, and the data segment offset of the source path.
+const HEADER_SIZE: u32 = 8;
+const INSTRUCTION_SIZE: usize = 4;
+// Emitted in place of a label address until the label is defined.
+const LABEL_PLACEHOLDER: u32 = 0;
+
+// 32-bit FNV-1a hash.
+pub fn hash_bytes(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811C_9DC5, |hash, &byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+// Hash of the source, used to detect byte code built from an older version of it.
+pub fn source_hash(source: &str) -> u32 {
+    hash_bytes(source.as_bytes())
+}
+
+// Strings in the data segment are a word holding their length in bytes, followed by their UTF-8
+// bytes four to a word. The last word is padded with zeros.
+pub fn pack_string(value: &str) -> Vec<[u8; 4]> {
+    let length = value.len() as u32;
+    let bytes = value.as_bytes().chunks(4).map(|chunk| {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        word
+    });
+
+    std::iter::once(length.to_be_bytes()).chain(bytes).collect()
+}
+
+// Bytes of the string packed at the start of the words. None if the words end before it does.
+pub fn unpack_string(words: &[[u8; 4]]) -> Option<&[u8]> {
+    let (length, bytes) = words.split_first()?;
+    let length = usize::try_from(u32::from_be_bytes(*length)).ok()?;
+
+    bytes.as_flattened().get(..length)
+}
+
+// Words a packed string of the given length in bytes takes up, including its length word.
+pub fn string_words(length: usize) -> usize {
+    1 + length.div_ceil(4)
+}
+
+// Returns the source path and hash recorded in the header of the given byte code, if any.
+pub fn source_reference(byte_code: &[u8]) -> Option<(String, u32)> {
+    let (words, _) = byte_code.as_chunks::<4>();
+    let word = |index: usize| words.get(index).map(|word| u32::from_be_bytes(*word));
+
+    if word(0) != Some(BYTE_CODE_MAGIC) || word(1) != Some(BYTE_CODE_VERSION) {
+        return None;
+    }
+
+    let data_section_pointer = usize::try_from(word(3)?).ok()?;
+    let hash = word(6)?;
+    let path_offset = usize::try_from(word(7)?).ok()?;
+
+    let path = unpack_string(words.get(data_section_pointer.checked_add(path_offset)?..)?)?;
+
+    Some((String::from_utf8(path.to_vec()).ok()?, hash))
+}
 
 impl From<TokenType> for OpCode {
     fn from(token_type: TokenType) -> Self {
         match token_type {
             // Data movement.
-            TokenType::LoadString => OpCode::LoadString,
+            TokenType::LoadString | TokenType::Format => OpCode::LoadString,
             TokenType::LoadImmediate => OpCode::LoadImmediate,
             TokenType::LoadContent => OpCode::LoadContent,
+            TokenType::LoadArgument => OpCode::LoadArgument,
+            TokenType::LoadPositionalArgument => OpCode::LoadPositionalArgument,
+            TokenType::LoadArgumentCount => OpCode::LoadArgumentCount,
+            TokenType::LoadDirectory => OpCode::LoadDirectory,
+            TokenType::LoadEnvironment => OpCode::LoadEnvironment,
+            TokenType::StoreWord => OpCode::StoreWord,
+            TokenType::LoadWord => OpCode::LoadWord,
             TokenType::Move => OpCode::Move,
             // Control flow.
             TokenType::BranchEqual => OpCode::BranchEqual,
             TokenType::BranchLessEqual => OpCode::BranchLessEqual,
             TokenType::BranchLess => OpCode::BranchLess,
             TokenType::BranchGreaterEqual => OpCode::BranchGreaterEqual,
+            TokenType::Jump => OpCode::Jump,
             TokenType::BranchGreater => OpCode::BranchGreater,
             TokenType::Exit => OpCode::Exit,
+            TokenType::Checkpoint => OpCode::Checkpoint,
             // I/O.
             TokenType::Print => OpCode::Print,
             TokenType::PrintLine => OpCode::PrintLine,
+            TokenType::PrintError => OpCode::PrintError,
             TokenType::PrintContext => OpCode::PrintContext,
+            TokenType::Input => OpCode::Input,
+            TokenType::WriteFile => OpCode::WriteFile,
+            TokenType::AppendFile => OpCode::AppendFile,
             // Generative operations.
             TokenType::Inference => OpCode::Inference,
+            TokenType::Ask => OpCode::Ask,
+            TokenType::Translate => OpCode::Translate,
             // Cognitive operations.
             TokenType::Evaluate => OpCode::Evaluate,
             // Guardrails operations.
             TokenType::Similarity => OpCode::Similarity,
+            TokenType::Embed => OpCode::Embed,
+            TokenType::BranchSimilar => OpCode::BranchSimilar,
+            TokenType::Classify => OpCode::Classify,
+            TokenType::Score => OpCode::Score,
+            TokenType::Json => OpCode::Json,
+            // Model selection.
+            TokenType::SetModel => OpCode::SetModel,
+            TokenType::SetEmbeddingModel => OpCode::SetEmbeddingModel,
+            // Sampling.
+            TokenType::SetTemperature => OpCode::SetTemperature,
+            TokenType::SetTopP => OpCode::SetTopP,
+            TokenType::SetMaxTokens => OpCode::SetMaxTokens,
+            // System prompt.
+            TokenType::SetSystemPrompt => OpCode::SetSystemPrompt,
+            // Vector store.
+            TokenType::VectorPut => OpCode::VectorPut,
+            TokenType::VectorQuery => OpCode::VectorQuery,
+            TokenType::VectorClear => OpCode::VectorClear,
             // Context operations.
-            TokenType::ContextPush => OpCode::ContextPush,
+            TokenType::ContextPush
+            | TokenType::ContextPushUser
+            | TokenType::ContextPushAssistant
+            | TokenType::ContextPushSystem => OpCode::ContextPush,
             TokenType::ContextPop => OpCode::ContextPop,
             TokenType::ContextDrop => OpCode::ContextDrop,
             TokenType::MoveContext => OpCode::MoveContext,
+            TokenType::ContextLength => OpCode::ContextLength,
+            TokenType::ContextTrim => OpCode::ContextTrim,
+            TokenType::ContextPeek => OpCode::ContextPeek,
+            TokenType::ContextInsert => OpCode::ContextInsert,
+            TokenType::ContextSave => OpCode::ContextSave,
+            TokenType::ContextLoad => OpCode::ContextLoad,
+            // Stack operations.
+            TokenType::StackPush => OpCode::StackPush,
+            TokenType::StackPop => OpCode::StackPop,
+            TokenType::StackPeek => OpCode::StackPeek,
             // Arithmetic operations.
             TokenType::SubtractImmediate => OpCode::SubtractImmediate,
+            TokenType::AddImmediate => OpCode::AddImmediate,
+            TokenType::MultiplyImmediate => OpCode::MultiplyImmediate,
+            TokenType::DivideImmediate => OpCode::DivideImmediate,
+            TokenType::AddNumber => OpCode::AddNumber,
+            TokenType::SubtractNumber => OpCode::SubtractNumber,
+            TokenType::MultiplyNumber => OpCode::MultiplyNumber,
+            TokenType::DivideNumber => OpCode::DivideNumber,
+            // String operations.
+            TokenType::FormatNumber => OpCode::FormatNumber,
+            TokenType::Escape => OpCode::Escape,
+            TokenType::Concatenate => OpCode::Concatenate,
+            TokenType::Length => OpCode::Length,
+            TokenType::TokenCount => OpCode::TokenCount,
+            TokenType::Upper => OpCode::Upper,
+            TokenType::Lower => OpCode::Lower,
+            TokenType::Trim => OpCode::Trim,
+            TokenType::Chunk => OpCode::Chunk,
+            TokenType::JsonGet => OpCode::JsonGet,
             // Misc.
             TokenType::Comma
+            | TokenType::Plus
+            | TokenType::Minus
+            | TokenType::Star
+            | TokenType::Slash
+            | TokenType::LeftParen
+            | TokenType::RightParen
+            | TokenType::Const
+            | TokenType::Equ
+            | TokenType::Macro
+            | TokenType::EndMacro
+            | TokenType::Include
+            | TokenType::Data
+            | TokenType::Text
+            | TokenType::DataString
             | TokenType::Identifier
             | TokenType::String
             | TokenType::Number
+            | TokenType::Character
             | TokenType::Label
             | TokenType::Eof
             | TokenType::Error => OpCode::NoOp,
@@ -55,48 +207,194 @@ impl From<TokenType> for OpCode {
     }
 }
 
-struct UnresolvedLabel {
-    indices: Vec<usize>,
+// A folded numeric immediate. Integers and floats never mix within one expression.
+#[derive(Clone, Copy)]
+enum Immediate {
+    Integer(i32),
+    Float(f32),
+}
+
+impl fmt::Display for Immediate {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Immediate::Integer(value) => write!(formatter, "{}", value),
+            Immediate::Float(value) => write!(formatter, "{:?}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    // Reported without failing the build.
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(formatter, "Error"),
+            Severity::Warning => write!(formatter, "Warning"),
+        }
+    }
+}
+
+// An assembly error or warning with the location of the token it was reported at.
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub lexeme: String,
+    pub scan_error: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "[{} Line {}:{}] {}:",
+            self.path.display(),
+            self.line,
+            self.column,
+            self.severity
+        )?;
+
+        if let Some(error) = &self.scan_error {
+            write!(formatter, " {}", error)?;
+        }
+
+        write!(formatter, " at '{}'. {}", self.lexeme, self.message)
+    }
+}
+
+// A label in the symbol table, at the byte code index that branches to it jump to.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Symbol {
+    pub name: String,
+    pub byte_code_index: u32,
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+struct Label {
+    byte_code_index: usize,
+    // Where the label is defined, for warnings.
     token: Token,
 }
 
+struct UnresolvedLabel {
+    // The text segment index and token of every use, in the order they were parsed.
+    uses: Vec<(usize, Token)>,
+}
+
+impl UnresolvedLabel {
+    // An index is only ever emitted once, so a repeat is ignored rather than patched twice.
+    fn add_use(&mut self, index: usize, token: Token) {
+        if !self.uses.iter().any(|(existing, _)| *existing == index) {
+            self.uses.push((index, token));
+        }
+    }
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Token>,
+    line: usize,
+    expansions: usize,
+}
+
+struct SourceFile {
+    path: PathBuf,
+    source: String,
+    // The file that included this one, if any.
+    parent: Option<usize>,
+}
+
 pub struct Assembler {
     data_segment: Vec<[u8; 4]>,
     text_segment: Vec<[u8; 4]>,
 
-    source: String,
+    // The program's own source is file 0, followed by any included files.
+    files: Vec<SourceFile>,
     scanner: Scanner,
 
     previous: Option<Token>,
     current: Option<Token>,
+    // Tokens from macro expansions, consumed before scanning further.
+    expanded: VecDeque<Token>,
 
-    labels: HashMap<String, usize>,
+    labels: HashMap<String, Label>,
     unresolved_labels: HashMap<String, UnresolvedLabel>,
-
-    had_error: bool,
+    // Labels used as an operand anywhere in the program.
+    referenced_labels: HashSet<String>,
+    constants: HashMap<String, Immediate>,
+    macros: HashMap<String, Macro>,
+    // Named strings declared in the .data section, by data segment offset.
+    data_labels: HashMap<String, u32>,
+    unresolved_data_labels: HashMap<String, UnresolvedLabel>,
+    in_data_section: bool,
+
+    diagnostics: Vec<Diagnostic>,
     panic_mode: bool,
+    warn_unused_labels: bool,
+
+    // The keyword of the instruction being parsed and, for each instruction emitted, its text segment
+    // index and keyword. Written to the byte code as debug info when enabled.
+    instruction_token: Option<Token>,
+    instruction_tokens: Vec<(usize, Token)>,
+    debug_info: bool,
 }
 
 impl Assembler {
-    pub fn new(source: String) -> Self {
-        let scanner = Scanner::new(source.clone());
+    pub fn new(source: String, source_path: &str) -> Self {
+        let scanner = Scanner::new(source.clone(), 0);
 
         Assembler {
             data_segment: Vec::new(),
             text_segment: Vec::new(),
-            source,
+            files: vec![SourceFile {
+                path: PathBuf::from(source_path),
+                source,
+                parent: None,
+            }],
             scanner,
             previous: None,
             current: None,
+            expanded: VecDeque::new(),
             labels: HashMap::new(),
             unresolved_labels: HashMap::new(),
-            had_error: false,
+            referenced_labels: HashSet::new(),
+            constants: HashMap::new(),
+            macros: HashMap::new(),
+            data_labels: HashMap::new(),
+            unresolved_data_labels: HashMap::new(),
+            in_data_section: false,
+            diagnostics: Vec::new(),
             panic_mode: false,
+            warn_unused_labels: true,
+            instruction_token: None,
+            instruction_tokens: Vec::new(),
+            debug_info: false,
         }
     }
 
-    fn lexeme(&self, token: &Token) -> &str {
-        &self.source[token.start()..token.end()]
+    // Labels that are defined but never referenced are reported as warnings unless disabled.
+    pub fn set_warn_unused_labels(&mut self, enabled: bool) {
+        self.warn_unused_labels = enabled;
+    }
+
+    // Appends a table mapping each instruction back to its source line, used in runtime errors.
+    pub fn set_debug_info(&mut self, enabled: bool) {
+        self.debug_info = enabled;
+    }
+
+    fn lexeme<'a>(&'a self, token: &'a Token) -> &'a str {
+        token
+            .lexeme()
+            .unwrap_or(&self.files[token.file()].source[token.start()..token.end()])
     }
 
     fn error_at(&mut self, token: &Token, message: &str) {
@@ -105,17 +403,56 @@ impl Assembler {
         }
 
         self.panic_mode = true;
-        eprint!("[Line {}:{}] Error:", token.line(), token.column());
+        self.report(Severity::Error, token, message);
+    }
+
+    fn warning_at(&mut self, token: &Token, message: &str) {
+        self.report(Severity::Warning, token, message);
+    }
+
+    fn report(&mut self, severity: Severity, token: &Token, message: &str) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            path: self.files[token.file()].path.clone(),
+            line: token.line(),
+            column: token.column(),
+            lexeme: self.lexeme(token).to_string(),
+            scan_error: token
+                .error()
+                .filter(|_| token.token_type() == &TokenType::Error),
+            message: message.to_string(),
+        });
+    }
+
+    // Every error and warning reported so far, in the order they were found.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    // Skips the rest of the line an error was reported on so that assembly can carry on and report later errors.
+    // The next statement starts at the first token on a new line.
+    fn synchronize(&mut self) {
+        let Some(error) = self.diagnostics.last() else {
+            self.panic_mode = false;
+            return;
+        };
+        let (error_path, error_line) = (error.path.clone(), error.line);
+
+        while let Some(token) = &self.current {
+            let starts_line =
+                token.line() != error_line || self.files[token.file()].path != error_path;
+
+            if token.token_type() == &TokenType::Eof
+                || (starts_line && token.token_type() != &TokenType::Error)
+            {
+                break;
+            }
 
-        if token.token_type() == &TokenType::Error
-            && let Some(error) = token.error()
-        {
-            eprint!(" {}", error);
+            // Scanning errors in skipped tokens are not reported while panicking.
+            let _ = self.advance();
         }
 
-        eprint!(" at '{}'.", self.lexeme(token));
-        eprintln!(" {}", message);
-        self.had_error = true;
+        self.panic_mode = false;
     }
 
     fn error_at_current(&mut self, message: &str) -> Result<(), Exception> {
@@ -155,7 +492,10 @@ impl Assembler {
     fn advance(&mut self) -> Result<(), Exception> {
         self.previous = self.current.clone();
 
-        let token = self.scanner.scan_token();
+        let token = match self.expanded.pop_front() {
+            Some(token) => token,
+            None => self.scanner.scan_token(),
+        };
         self.current = Some(token.clone());
 
         if token.token_type() == &TokenType::Error {
@@ -169,17 +509,23 @@ impl Assembler {
         Ok(())
     }
 
-    fn consume(&mut self, token_type: &TokenType, message: &str) -> Result<(), Exception> {
-        if self
-            .current
+    fn check(&self, token_type: &TokenType) -> bool {
+        self.current
             .as_ref()
             .map(|token| token.token_type() == token_type)
             .unwrap_or(false)
-        {
+    }
+
+    fn consume(&mut self, token_type: &TokenType, message: &str) -> Result<(), Exception> {
+        if self.check(token_type) {
             self.advance()?;
             Ok(())
         } else {
-            self.error_at_previous(message)
+            self.error_at_previous(message)?;
+            Err(Exception::Assembler(BaseException::new(
+                message.to_string(),
+                None,
+            )))
         }
     }
 
@@ -195,15 +541,252 @@ impl Assembler {
         Ok(self.lexeme(token))
     }
 
-    fn number(&mut self, message: &str) -> Result<u32, Exception> {
+    fn character(&mut self) -> Result<i32, Exception> {
+        self.consume(&TokenType::Character, "Expected character literal.")?;
+        let lexeme = self.previous_lexeme()?;
+        let inner = &lexeme[1..lexeme.len() - 1];
+
+        let value = match inner {
+            "\\n" => Some('\n'),
+            "\\t" => Some('\t'),
+            "\\'" => Some('\''),
+            "\\\\" => Some('\\'),
+            _ => {
+                let mut chars = inner.chars();
+
+                match (chars.next(), chars.next()) {
+                    (Some(ch), None) if ch != '\\' => Some(ch),
+                    _ => None,
+                }
+            }
+        };
+
+        match value {
+            // Every char is at most 0x10FFFF, which fits.
+            Some(ch) => Ok(u32::from(ch).cast_signed()),
+            None => {
+                let message = format!(
+                    "Invalid character literal '{}'. Expected a single character or one of the escapes \\n, \\t, \\' and \\\\.",
+                    inner
+                );
+                self.error_at_previous(&message)?;
+                Err(Exception::Assembler(BaseException::new(message, None)))
+            }
+        }
+    }
+
+    // Consumes the current token if it is one of the given operators.
+    fn operator(&mut self, operators: &[TokenType]) -> Result<Option<Token>, Exception> {
+        if !operators.iter().any(|operator| self.check(operator)) {
+            return Ok(None);
+        }
+
+        self.advance()?;
+        Ok(self.previous.clone())
+    }
+
+    fn fold(
+        &mut self,
+        operator: &Token,
+        left: Immediate,
+        right: Immediate,
+    ) -> Result<Immediate, Exception> {
+        let symbol = match operator.token_type() {
+            TokenType::Plus => '+',
+            TokenType::Minus => '-',
+            TokenType::Star => '*',
+            _ => '/',
+        };
+
+        let value = match (left, right) {
+            (Immediate::Integer(_), Immediate::Integer(0))
+            | (Immediate::Float(_), Immediate::Float(0.0))
+                if symbol == '/' =>
+            {
+                let message = format!(
+                    "Division by zero in constant expression '{} / {}'.",
+                    left, right
+                );
+                self.error_at(operator, &message);
+                return Err(Exception::Assembler(BaseException::new(message, None)));
+            }
+            (Immediate::Integer(left), Immediate::Integer(right)) => match symbol {
+                '+' => left.checked_add(right),
+                '-' => left.checked_sub(right),
+                '*' => left.checked_mul(right),
+                _ => left.checked_div(right),
+            }
+            .map(Immediate::Integer),
+            (Immediate::Float(left), Immediate::Float(right)) => Some(match symbol {
+                '+' => left + right,
+                '-' => left - right,
+                '*' => left * right,
+                _ => left / right,
+            })
+            .filter(|value| value.is_finite())
+            .map(Immediate::Float),
+            _ => {
+                let message = format!(
+                    "Cannot mix integer and float operands in constant expression '{} {} {}'.",
+                    left, symbol, right
+                );
+                self.error_at(operator, &message);
+                return Err(Exception::Assembler(BaseException::new(message, None)));
+            }
+        };
+
+        value.ok_or_else(|| {
+            let message = format!(
+                "Constant expression '{} {} {}' is out of range. Expected an integer between {} and {}, or a finite float.",
+                left,
+                symbol,
+                right,
+                i32::MIN,
+                i32::MAX
+            );
+            self.error_at(operator, &message);
+            Exception::Assembler(BaseException::new(message, None))
+        })
+    }
+
+    // Numeric immediates are constant expressions folded at assemble time.
+    // Unary '-' binds tightest, then '*' and '/', then '+' and '-'. Parentheses group.
+    fn number(&mut self, message: &str) -> Result<Immediate, Exception> {
+        let mut value = self.term(message)?;
+
+        while let Some(operator) = self.operator(&[TokenType::Plus, TokenType::Minus])? {
+            let right = self.term(message)?;
+            value = self.fold(&operator, value, right)?;
+        }
+
+        Ok(value)
+    }
+
+    // A constant expression that must fold to an integer, such as the SUBI decrement.
+    fn integer(&mut self, message: &str) -> Result<i32, Exception> {
+        let token = self.current_token()?;
+
+        match self.number(message)? {
+            Immediate::Integer(value) => Ok(value),
+            Immediate::Float(value) => {
+                let message = format!(
+                    "Expected an integer, but the expression is the float {:?}.",
+                    value
+                );
+                self.error_at(&token, &message);
+                Err(Exception::Assembler(BaseException::new(message, None)))
+            }
+        }
+    }
+
+    fn term(&mut self, message: &str) -> Result<Immediate, Exception> {
+        let mut value = self.factor(message)?;
+
+        while let Some(operator) = self.operator(&[TokenType::Star, TokenType::Slash])? {
+            let right = self.factor(message)?;
+            value = self.fold(&operator, value, right)?;
+        }
+
+        Ok(value)
+    }
+
+    fn factor(&mut self, message: &str) -> Result<Immediate, Exception> {
+        if let Some(operator) = self.operator(&[TokenType::Minus])? {
+            // Negate literals while parsing so that i32::MIN can be written directly.
+            if self.check(&TokenType::Number) {
+                return self.literal("-", message);
+            }
+
+            return match self.factor(message)? {
+                Immediate::Float(value) => Ok(Immediate::Float(-value)),
+                Immediate::Integer(value) => {
+                    value.checked_neg().map(Immediate::Integer).ok_or_else(|| {
+                        let message = format!(
+                            "Constant expression '-{}' is out of range. Expected a value between {} and {}.",
+                            value,
+                            i32::MIN,
+                            i32::MAX
+                        );
+                        self.error_at(&operator, &message);
+                        Exception::Assembler(BaseException::new(message, None))
+                    })
+                }
+            };
+        }
+
+        if self.check(&TokenType::Character) {
+            return self.character().map(Immediate::Integer);
+        }
+
+        if self.check(&TokenType::LeftParen) {
+            self.advance()?;
+            let value = self.number(message)?;
+            self.consume(&TokenType::RightParen, "Expected ')' after expression.")?;
+            return Ok(value);
+        }
+
+        // No instruction can appear in an expression, so a mnemonic here names a constant.
+        if self.check(&TokenType::Identifier) || self.check_mnemonic() {
+            self.advance()?;
+            let name = self.previous_lexeme()?.to_string();
+
+            return match self.constants.get(&name) {
+                Some(value) => Ok(*value),
+                None => {
+                    let message = format!("Undefined constant '{}'.", name);
+                    self.error_at_previous(&message)?;
+                    Err(Exception::Assembler(BaseException::new(message, None)))
+                }
+            };
+        }
+
+        self.literal("", message)
+    }
+
+    // Numbers with a fractional part are floats; all others are integers. Integers were once read as
+    // u32, so one above i32::MAX is still accepted and wraps to the same word, with a warning that it
+    // now reads as negative.
+    fn literal(&mut self, sign: &str, message: &str) -> Result<Immediate, Exception> {
         self.consume(&TokenType::Number, message)?;
-        let previous_lexeme = self.previous_lexeme()?;
+        let previous_lexeme = format!("{}{}", sign, self.previous_lexeme()?);
+
+        let value = if previous_lexeme.contains('.') {
+            previous_lexeme
+                .parse::<f32>()
+                .map_err(|error| error.to_string())
+                .and_then(|value| {
+                    value
+                        .is_finite()
+                        .then_some(Immediate::Float(value))
+                        .ok_or_else(|| "float out of range".to_string())
+                })
+        } else {
+            match previous_lexeme.parse::<i32>() {
+                Ok(value) => Ok(Immediate::Integer(value)),
+                Err(error) => match previous_lexeme.parse::<u32>() {
+                    Ok(value) => {
+                        let wrapped = value.cast_signed();
+                        let message = format!(
+                            "Integer {} is above {} and is stored as {}. Write {} to keep the same byte code without this warning.",
+                            value,
+                            i32::MAX,
+                            wrapped,
+                            wrapped
+                        );
+                        let token = self.previous_token()?;
+                        self.warning_at(&token, &message);
+                        Ok(Immediate::Integer(wrapped))
+                    }
+                    Err(_) => Err(error.to_string()),
+                },
+            }
+        };
 
-        match previous_lexeme.parse::<u32>() {
+        match value {
             Ok(value) => Ok(value),
             Err(error) => {
                 let message = format!("Failed to parse number from lexeme '{}'.", previous_lexeme);
-                let _ = self.error_at_current(&message);
+                let _ = self.error_at_previous(&message);
                 Err(Exception::Assembler(BaseException::caused_by(
                     message, error,
                 )))
@@ -244,7 +827,24 @@ impl Assembler {
         Ok(register_number)
     }
 
+    // Only LS strings are interpolated, so a '${xN}' placeholder in any other string operand is kept
+    // as written, with a warning.
     fn string(&mut self, message: &str) -> Result<String, Exception> {
+        let string = self.literal_string(message)?;
+
+        if let Some(placeholder) = Self::placeholder_in(&string) {
+            let message = format!(
+                "'{}' is only replaced in LS strings, so it is used as written here. Load the text with LS and pass its register instead.",
+                placeholder
+            );
+            let token = self.previous_token()?;
+            self.warning_at(&token, &message);
+        }
+
+        Ok(string)
+    }
+
+    fn literal_string(&mut self, message: &str) -> Result<String, Exception> {
         self.consume(&TokenType::String, message)?;
         let lexeme = self.previous_lexeme()?;
 
@@ -252,152 +852,801 @@ impl Assembler {
         Ok(inner.replace("\\n", "\n").replace("\\\"", "\""))
     }
 
-    fn identifier(&mut self, message: &str) -> Result<&str, Exception> {
-        self.consume(&TokenType::Identifier, message)?;
-        self.previous_lexeme()
-    }
+    // The first '${xN}' in text, ignoring case.
+    fn placeholder_in(text: &str) -> Option<&str> {
+        text.match_indices("${").find_map(|(start, _)| {
+            let rest = &text[start + 2..];
+            let end = rest.find('}')?;
+            let name = rest[..end].strip_prefix(['x', 'X'])?;
 
-    fn label(&mut self) -> Result<(), Exception> {
-        self.consume(&TokenType::Label, "Expected label name.")?;
-        let label_name = self.previous_lexeme()?.trim_end_matches(':').to_string();
-        let byte_code_index = self.text_segment.len();
-        self.labels.insert(label_name, byte_code_index);
-        Ok(())
+            (!name.is_empty() && name.bytes().all(|byte| byte.is_ascii_digit()))
+                .then(|| &text[start..start + 2 + end + 1])
+        })
     }
 
-    fn upsert_unresolved_label(&mut self, key: String) -> Result<(), Exception> {
-        let index = self.text_segment.len().saturating_sub(1);
+    fn template(&mut self, value: &str) -> Result<(String, Vec<(u32, u32)>), Exception> {
+        let mut text = String::new();
+        let mut placeholders = Vec::new();
+        let mut rest = value;
+
+        while let Some(ch) = rest.chars().next() {
+            // '$${' escapes a literal '${'.
+            if let Some(after) = rest.strip_prefix("$${") {
+                text.push_str("${");
+                rest = after;
+                continue;
+            }
 
-        if let Some(label) = self.unresolved_labels.get_mut(&key) {
-            label.indices.push(index);
-            return Ok(());
-        }
+            let Some(after) = rest.strip_prefix("${") else {
+                text.push(ch);
+                rest = &rest[ch.len_utf8()..];
+                continue;
+            };
 
-        let token = self.previous.clone().ok_or_else(|| {
-            Exception::Assembler(BaseException::new(
-                "Failed to retrieve previous token for unresolved label error reporting."
-                    .to_string(),
-                None,
-            ))
-        })?;
+            let Some(end) = after.find('}') else {
+                let message = "Unterminated placeholder. Expected '}' after '${'.".to_string();
+                self.error_at_previous(&message)?;
+                return Err(Exception::Assembler(BaseException::new(message, None)));
+            };
 
-        self.unresolved_labels.insert(
-            key,
-            UnresolvedLabel {
-                indices: vec![index],
-                token,
-            },
-        );
+            let name = &after[..end];
+            let register_number = name
+                .to_lowercase()
+                .strip_prefix('x')
+                .and_then(|number| number.parse::<u32>().ok())
+                .filter(|number| (0..=32).contains(number));
+
+            let Some(register_number) = register_number else {
+                let message = format!(
+                    "Invalid placeholder '${{{}}}'. Expected ${{xN}}, where N is between 0 and 32.",
+                    name
+                );
+                self.error_at_previous(&message)?;
+                return Err(Exception::Assembler(BaseException::new(message, None)));
+            };
 
-        Ok(())
+            let offset = self.checked_u32(text.len(), "Placeholder offset", "bytes")?;
+
+            placeholders.push((offset, register_number));
+            rest = &after[end + 1..];
+        }
+
+        Ok((text, placeholders))
     }
 
-    fn backpatch_labels(&mut self) -> Result<(), Exception> {
-        let mut error = None;
+    // Resolves the '{N}' placeholders of a 'fmt' string to the Nth listed register, counting from 1.
+    // '{{' and '}}' escape literal braces. Every operand must be used by at least one placeholder.
+    fn positional_template(
+        &mut self,
+        token: &Token,
+        value: &str,
+        operands: &[u32],
+    ) -> Result<(String, Vec<(u32, u32)>), Exception> {
+        let mut text = String::new();
+        let mut placeholders = Vec::new();
+        let mut used = vec![false; operands.len()];
+        let mut rest = value;
+
+        while let Some(ch) = rest.chars().next() {
+            if let Some(after) = rest.strip_prefix("{{").or_else(|| rest.strip_prefix("}}")) {
+                text.push(ch);
+                rest = after;
+                continue;
+            }
 
-        self.unresolved_labels.retain(|key, unresolved| {
-            let Some(byte_code_index) = self.labels.get(key) else {
-                return true; // keep unresolved
+            let Some(after) = rest.strip_prefix('{') else {
+                text.push(ch);
+                rest = &rest[ch.len_utf8()..];
+                continue;
             };
 
-            let index = match u32::try_from(*byte_code_index) {
-                Ok(v) => v,
-                Err(_) => {
-                    error = Some(format!(
-                        "Failed to convert byte code index to u32 for backpatching. Byte code index exceeds {}. Found byte code index: {}.",
-                        u32::MAX, byte_code_index
-                    ));
-                    return true;
-                }
+            let Some(end) = after.find('}') else {
+                let message = "Unterminated placeholder. Expected '}' after '{'.".to_string();
+                self.error_at(token, &message);
+                return Err(Exception::Assembler(BaseException::new(message, None)));
             };
 
-            let bytes = (HEADER_SIZE + index).to_be_bytes();
-            
-            for &idx in &unresolved.indices {
-                self.text_segment[idx] = bytes;
-            }
+            let name = &after[..end];
+            let index = name
+                .parse::<usize>()
+                .ok()
+                .filter(|index| (1..=operands.len()).contains(index));
+
+            let Some(index) = index else {
+                let message = format!(
+                    "Invalid placeholder '{{{}}}'. Expected {{N}}, where N is between 1 and the number of operands ({}).",
+                    name,
+                    operands.len()
+                );
+                self.error_at(token, &message);
+                return Err(Exception::Assembler(BaseException::new(message, None)));
+            };
 
-            false // remove resolved
-        });
+            let offset = self.checked_u32(text.len(), "Placeholder offset", "bytes")?;
+
+            placeholders.push((offset, operands[index - 1]));
+            used[index - 1] = true;
+            rest = &after[end + 1..];
+        }
 
-        if let Some(message) = error {
-            let _ = self.error_at_current(&message);
+        if let Some(unused) = used.iter().position(|used| !used) {
+            let message = format!(
+                "Operand {} (x{}) is not used by any placeholder. Add '{{{}}}' to the string or remove it.",
+                unused + 1,
+                operands[unused],
+                unused + 1
+            );
+            self.error_at(token, &message);
             return Err(Exception::Assembler(BaseException::new(message, None)));
         }
 
-        Ok(())
+        Ok((text, placeholders))
     }
 
-    fn emit_number(&mut self, value: u32) {
-        self.text_segment.push(value.to_be_bytes());
+    fn identifier(&mut self, message: &str) -> Result<&str, Exception> {
+        self.consume(&TokenType::Identifier, message)?;
+        self.previous_lexeme()
     }
 
-    fn emit_opcode(&mut self, op_code: OpCode) {
-        self.emit_number(op_code.into());
+    // Whether the current token is an instruction mnemonic, such as 'CHUNK'.
+    fn check_mnemonic(&self) -> bool {
+        self.current
+            .as_ref()
+            .is_some_and(|token| isa::lookup(self.lexeme(token)).is_some())
     }
 
-    fn emit_string(&mut self, value: &str) -> Result<u32, Exception> {
-        let nulled_value = format!("{}\0", value);
-        let words: Vec<[u8; 4]> = nulled_value
-            .bytes()
-            .map(|byte| u32::from(byte).to_be_bytes())
-            .collect();
-
-        let address = u32::try_from(self.data_segment.len()).map_err(|_| {
-            let message = format!(
-                "Failed to convert data segment length to u32 for string emission. Data segment length exceeds {}. Found data segment length: {}.",
-                u32::MAX,
-                self.data_segment.len()
-            );
-            let _ = self.error_at_current(&message);
-            Exception::Assembler(BaseException::new(message, None))
-        })?;
-
-        self.data_segment.extend(words);
-        Ok(address)
-    }
+    // '.const NAME value' and '.equ NAME, value' both define a constant. The comma is optional for either.
+    // A constant may share its name with an instruction, so adding an instruction never breaks a
+    // program that already uses its name for a constant.
+    fn constant(&mut self, token_type: &TokenType) -> Result<(), Exception> {
+        self.consume(token_type, "Expected '.const' or '.equ' directive.")?;
 
-    fn emit_label(&mut self, key: String) -> Result<(), Exception> {
-        self.emit_number(0);
-        self.upsert_unresolved_label(key)
-    }
+        let name = if self.check_mnemonic() {
+            self.advance()?;
+            self.previous_lexeme()?.to_string()
+        } else {
+            self.identifier("Expected constant name after directive.")?
+                .to_string()
+        };
 
-    fn emit_padding(&mut self, words: usize) {
-        for _ in 0..words {
-            self.emit_number(0);
+        if self.constants.contains_key(&name) {
+            let message = format!("Constant '{}' is already defined.", name);
+            self.error_at_previous(&message)?;
+            return Err(Exception::Assembler(BaseException::new(message, None)));
         }
-    }
 
-    fn validate_op_code(&mut self, op_code: OpCode) -> Result<(), Exception> {
-        if op_code == OpCode::NoOp {
-            let message = "Invalid opcode: NoOp is reserved for labels and placeholders and cannot be used in instructions.";
-            self.error_at_current(message)?;
-            return Err(Exception::Assembler(BaseException::new(
-                message.to_string(),
-                None,
-            )));
+        if self.check(&TokenType::Comma) {
+            self.advance()?;
         }
 
+        let value = self.number("Expected constant value after constant name.")?;
+        self.constants.insert(name, value);
+
         Ok(())
     }
 
-    fn validate_role(&mut self, role: &str) -> Result<(), Exception> {
-        if role.is_empty() {
-            self.error_at_previous("Role name cannot be empty.")?;
-            return Err(Exception::Assembler(BaseException::new(
-                "Role name cannot be empty.".to_string(),
+    fn current_token(&self) -> Result<Token, Exception> {
+        self.current.clone().ok_or_else(|| {
+            Exception::Assembler(BaseException::new(
+                "Unexpected end of input. No current token available.".to_string(),
                 None,
-            )));
-        }
+            ))
+        })
+    }
 
-        let lower = role.to_lowercase();
-        if !matches!(lower.as_str(), roles::USER_ROLE | roles::ASSISTANT_ROLE) {
-            let message = format!(
-                "Invalid role name '{}'. Expected '{}' or '{}'.",
-                role,
-                roles::USER_ROLE,
-                roles::ASSISTANT_ROLE
-            );
+    fn previous_token(&self) -> Result<Token, Exception> {
+        self.previous.clone().ok_or_else(|| {
+            Exception::Assembler(BaseException::new(
+                "No previous token available.".to_string(),
+                None,
+            ))
+        })
+    }
+
+    fn macro_definition(&mut self) -> Result<(), Exception> {
+        self.consume(&TokenType::Macro, "Expected '.macro' directive.")?;
+
+        // An invocation would read as the instruction, so a macro cannot take an instruction's name.
+        if self.check_mnemonic() {
+            let token = self.current_token()?;
+            let message = format!(
+                "Macro name '{}' is an instruction mnemonic. Rename the macro, for example to '{}_macro'.",
+                self.lexeme(&token),
+                self.lexeme(&token).to_lowercase()
+            );
+            self.error_at(&token, &message);
+            return Err(Exception::Assembler(BaseException::new(message, None)));
+        }
+
+        let name = self
+            .identifier("Expected macro name after '.macro'.")?
+            .to_string();
+        let name_token = self.previous.clone().ok_or_else(|| {
+            Exception::Assembler(BaseException::new(
+                "Failed to retrieve macro name token.".to_string(),
+                None,
+            ))
+        })?;
+        let line = name_token.line();
+
+        if let Some(existing) = self.macros.get(&name) {
+            let message = format!(
+                "Macro '{}' is already defined on line {}.",
+                name, existing.line
+            );
+            self.error_at_previous(&message)?;
+            return Err(Exception::Assembler(BaseException::new(message, None)));
+        }
+
+        // Parameters are the identifiers on the same line as the macro name.
+        let mut parameters = Vec::new();
+
+        loop {
+            let token = self.current_token()?;
+
+            if token.line() != line {
+                break;
+            }
+
+            match token.token_type() {
+                TokenType::Identifier => parameters.push(self.lexeme(&token).to_string()),
+                TokenType::Comma => {}
+                _ => break,
+            }
+
+            self.advance()?;
+        }
+
+        // Macros used inside the body are expanded now, so a macro can only use macros defined before it.
+        let mut body = Vec::new();
+
+        loop {
+            let token = self.current_token()?;
+
+            match token.token_type() {
+                TokenType::EndMacro => {
+                    self.advance()?;
+                    break;
+                }
+                TokenType::Eof => {
+                    let message = format!(
+                        "Unterminated macro '{}' defined on line {}. Expected '.endm'.",
+                        name, line
+                    );
+                    self.error_at(&name_token, &message);
+                    return Err(Exception::Assembler(BaseException::new(message, None)));
+                }
+                TokenType::Macro => {
+                    let message = format!(
+                        "Macro definitions cannot be nested inside macro '{}' defined on line {}.",
+                        name, line
+                    );
+                    self.error_at_current(&message)?;
+                    return Err(Exception::Assembler(BaseException::new(message, None)));
+                }
+                TokenType::Identifier if self.lexeme(&token) == name => {
+                    let message = format!(
+                        "Recursive invocation of macro '{}' defined on line {}.",
+                        name, line
+                    );
+                    self.error_at_current(&message)?;
+                    return Err(Exception::Assembler(BaseException::new(message, None)));
+                }
+                TokenType::Identifier if self.macros.contains_key(self.lexeme(&token)) => {
+                    let expansion = self.expand()?;
+                    body.extend(expansion);
+                }
+                _ => {
+                    body.push(token);
+                    self.advance()?;
+                }
+            }
+        }
+
+        self.macros.insert(
+            name,
+            Macro {
+                parameters,
+                body,
+                line,
+                expansions: 0,
+            },
+        );
+
+        Ok(())
+    }
+
+    // An argument is a single operand, or a constant expression built from operands, operators, and parentheses.
+    fn macro_argument(&mut self, name: &str, index: usize) -> Result<Vec<Token>, Exception> {
+        let is_operator = |token_type: &TokenType| {
+            matches!(
+                token_type,
+                TokenType::Plus | TokenType::Minus | TokenType::Star | TokenType::Slash
+            )
+        };
+
+        let mut tokens = Vec::new();
+        let mut depth = 0usize;
+
+        loop {
+            let token = self.current_token()?;
+
+            if matches!(token.token_type(), TokenType::Eof | TokenType::Comma) {
+                let message = format!("Expected argument {} for macro '{}'.", index + 1, name);
+                self.error_at_current(&message)?;
+                return Err(Exception::Assembler(BaseException::new(message, None)));
+            }
+
+            match token.token_type() {
+                TokenType::LeftParen => depth += 1,
+                TokenType::RightParen => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+
+            let expects_operand =
+                is_operator(token.token_type()) || token.token_type() == &TokenType::LeftParen;
+            tokens.push(token);
+            self.advance()?;
+
+            let next = self.current_token()?;
+            let continues = depth > 0 || expects_operand || is_operator(next.token_type());
+
+            if !continues {
+                return Ok(tokens);
+            }
+        }
+    }
+
+    // Parses an invocation of a macro and returns its body with the arguments substituted.
+    // Labels defined in the body are suffixed so that each expansion gets its own copy.
+    fn expand(&mut self) -> Result<Vec<Token>, Exception> {
+        self.consume(&TokenType::Identifier, "Expected macro name.")?;
+        let name = self.previous_lexeme()?.to_string();
+
+        let parameter_count = self
+            .macros
+            .get(&name)
+            .map(|definition| definition.parameters.len())
+            .unwrap_or(0);
+
+        let mut arguments = Vec::new();
+
+        for index in 0..parameter_count {
+            if index > 0 {
+                self.consume(
+                    &TokenType::Comma,
+                    &format!("Expected ',' between arguments to macro '{}'.", name),
+                )?;
+            }
+
+            arguments.push(self.macro_argument(&name, index)?);
+        }
+
+        let Some(definition) = self.macros.get_mut(&name) else {
+            return Err(Exception::Assembler(BaseException::new(
+                format!("Macro '{}' is not defined.", name),
+                None,
+            )));
+        };
+
+        definition.expansions += 1;
+        let suffix = format!("__{}_{}", name, definition.expansions);
+
+        let definition = &self.macros[&name];
+        let labels: HashSet<String> = definition
+            .body
+            .iter()
+            .filter(|token| token.token_type() == &TokenType::Label)
+            .map(|token| self.lexeme(token).trim_end_matches(':').to_string())
+            .collect();
+
+        Ok(definition
+            .body
+            .iter()
+            .flat_map(|token| {
+                let lexeme = self.lexeme(token);
+
+                match token.token_type() {
+                    TokenType::Identifier => {
+                        if let Some(index) = definition
+                            .parameters
+                            .iter()
+                            .position(|parameter| parameter == lexeme)
+                        {
+                            arguments[index].clone()
+                        } else if labels.contains(lexeme) {
+                            vec![token.with_lexeme(format!("{}{}", lexeme, suffix))]
+                        } else {
+                            vec![token.clone()]
+                        }
+                    }
+                    TokenType::Label => vec![token.with_lexeme(format!(
+                        "{}{}:",
+                        lexeme.trim_end_matches(':'),
+                        suffix
+                    ))],
+                    _ => vec![token.clone()],
+                }
+            })
+            .collect())
+    }
+
+    fn invoke_macro(&mut self) -> Result<(), Exception> {
+        let token = self.current_token()?;
+
+        if !self.macros.contains_key(self.lexeme(&token)) {
+            return self.error_at_current("Unexpected keyword.");
+        }
+
+        let expansion = self.expand()?;
+        self.splice(expansion);
+
+        Ok(())
+    }
+
+    // Inserts tokens ahead of the current token, which becomes the first of them.
+    fn splice(&mut self, tokens: Vec<Token>) {
+        let mut expanded: VecDeque<Token> = tokens.into();
+        expanded.extend(self.current.take());
+        expanded.append(&mut self.expanded);
+
+        self.expanded = expanded;
+        self.current = self.expanded.pop_front();
+    }
+
+    // Included paths are relative to the including file. A file may be included more than once,
+    // but not while it is already being included.
+    fn include(&mut self) -> Result<(), Exception> {
+        self.consume(&TokenType::Include, "Expected '.include' directive.")?;
+        let parent = self
+            .previous
+            .as_ref()
+            .map(|token| token.file())
+            .unwrap_or(0);
+
+        let path = self.string("Expected file path after '.include'.")?;
+        let path = self.files[parent]
+            .path
+            .parent()
+            .map(|directory| directory.join(&path))
+            .unwrap_or_else(|| PathBuf::from(&path));
+
+        let source = match read_to_string(&path) {
+            Ok(source) => source,
+            Err(e) => {
+                let message = format!("Failed to read included file '{}'.", path.display());
+                self.error_at_previous(&message)?;
+                return Err(Exception::Assembler(BaseException::caused_by(message, e)));
+            }
+        };
+
+        let canonical = canonicalize(&path).ok();
+        let mut ancestor = Some(parent);
+
+        while let Some(index) = ancestor {
+            if canonical.is_some() && canonicalize(&self.files[index].path).ok() == canonical {
+                let message = format!("Circular include of '{}'.", path.display());
+                self.error_at_previous(&message)?;
+                return Err(Exception::Assembler(BaseException::new(message, None)));
+            }
+
+            ancestor = self.files[index].parent;
+        }
+
+        let file = self.files.len();
+        let mut scanner = Scanner::new(source.clone(), file);
+        self.files.push(SourceFile {
+            path,
+            source,
+            parent: Some(parent),
+        });
+
+        let mut tokens = Vec::new();
+
+        loop {
+            let token = scanner.scan_token();
+
+            if token.token_type() == &TokenType::Eof {
+                break;
+            }
+
+            tokens.push(token);
+        }
+
+        self.splice(tokens);
+
+        Ok(())
+    }
+
+    fn data_entry(&mut self) -> Result<(), Exception> {
+        self.consume(&TokenType::Label, "Expected data label.")?;
+        let name = self.previous_lexeme()?.trim_end_matches(':').to_string();
+
+        if self.data_labels.contains_key(&name) {
+            let message = format!("Data label '{}' is already defined.", name);
+            self.error_at_previous(&message)?;
+            return Err(Exception::Assembler(BaseException::new(message, None)));
+        }
+
+        self.consume(
+            &TokenType::DataString,
+            "Expected '.string' after data label.",
+        )?;
+        let string = self.string("Expected string after '.string'.")?;
+
+        let pointer = self.emit_string(&string)?;
+        self.data_labels.insert(name, pointer);
+
+        Ok(())
+    }
+
+    fn data_section(&mut self, token_type: &TokenType) -> Result<(), Exception> {
+        self.consume(token_type, "Expected section directive.")?;
+        self.in_data_section = token_type == &TokenType::Data;
+        Ok(())
+    }
+
+    fn label(&mut self) -> Result<(), Exception> {
+        self.consume(&TokenType::Label, "Expected label name.")?;
+        let label_name = self.previous_lexeme()?.trim_end_matches(':').to_string();
+        let token = self.previous_token()?;
+        let byte_code_index = self.text_segment.len();
+        self.labels.insert(
+            label_name,
+            Label {
+                byte_code_index,
+                token,
+            },
+        );
+        Ok(())
+    }
+
+    fn upsert_unresolved_label(&mut self, key: String) -> Result<(), Exception> {
+        let index = self.text_segment.len().saturating_sub(1);
+        self.referenced_labels.insert(key.clone());
+
+        let token = self.previous.clone().ok_or_else(|| {
+            Exception::Assembler(BaseException::new(
+                "Failed to retrieve previous token for unresolved label error reporting."
+                    .to_string(),
+                None,
+            ))
+        })?;
+
+        self.unresolved_labels
+            .entry(key)
+            .or_insert(UnresolvedLabel { uses: Vec::new() })
+            .add_use(index, token);
+
+        Ok(())
+    }
+
+    // Writes a resolved address into every use of a label. A slot that no longer holds the placeholder
+    // means something else was written there, which is a bug in the assembler rather than the source.
+    fn patch_uses(&mut self, uses: &[(usize, Token)], value: u32) -> Result<(), Exception> {
+        for (index, token) in uses {
+            let current = self
+                .text_segment
+                .get(*index)
+                .copied()
+                .map(u32::from_be_bytes);
+
+            if current != Some(LABEL_PLACEHOLDER) {
+                let message = format!(
+                    "Internal error: label slot at text segment index {} holds {:?} instead of the placeholder.",
+                    index, current
+                );
+                self.error_at(token, &message);
+                return Err(Exception::Assembler(BaseException::new(message, None)));
+            }
+
+            self.text_segment[*index] = value.to_be_bytes();
+        }
+
+        Ok(())
+    }
+
+    fn backpatch_labels(&mut self) -> Result<(), Exception> {
+        // Sorted so that labels are always patched in the same order.
+        let mut resolved = self
+            .unresolved_labels
+            .keys()
+            .filter(|key| self.labels.contains_key(*key))
+            .cloned()
+            .collect::<Vec<_>>();
+        resolved.sort();
+
+        for key in resolved {
+            let byte_code_index = self.labels[&key].byte_code_index;
+            let address = u32::try_from(byte_code_index)
+                .ok()
+                .and_then(|index| index.checked_add(HEADER_SIZE));
+
+            let Some(address) = address else {
+                let message = Self::size_limit_message(
+                    "Label address",
+                    byte_code_index,
+                    u32::MAX as usize,
+                    "words",
+                );
+                let _ = self.error_at_current(&message);
+                return Err(Exception::Assembler(BaseException::new(message, None)));
+            };
+
+            if let Some(unresolved) = self.unresolved_labels.remove(&key) {
+                self.patch_uses(&unresolved.uses, address)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Reports an undefined label at its first use, listing any other places it is used.
+    fn report_undefined(&mut self, uses: &[(usize, Token)], message: &str) {
+        let Some((_, first)) = uses.first() else {
+            return;
+        };
+
+        let mut others = uses[1..]
+            .iter()
+            .map(|(_, token)| (token.file(), token.line(), token.column()))
+            .filter(|site| *site != (first.file(), first.line(), first.column()))
+            .collect::<Vec<_>>();
+
+        // Uses from repeated macro expansions share one place in the source.
+        others.sort();
+        others.dedup();
+
+        let message = if others.is_empty() {
+            message.to_string()
+        } else {
+            let sites = others
+                .iter()
+                .map(|(file, line, column)| {
+                    format!(
+                        "{} Line {}:{}",
+                        self.files[*file].path.display(),
+                        line,
+                        column
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{} Also referenced at {}.", message, sites)
+        };
+
+        self.error_at(first, &message);
+        self.panic_mode = false;
+    }
+
+    fn size_limit_message(what: &str, size: usize, limit: usize, unit: &str) -> String {
+        format!(
+            "{} of {} {} exceeds the limit of {} {}.",
+            what, size, unit, limit, unit
+        )
+    }
+
+    fn check_size_limit(
+        &mut self,
+        what: &str,
+        size: usize,
+        limit: usize,
+        unit: &str,
+    ) -> Result<(), Exception> {
+        if size <= limit {
+            return Ok(());
+        }
+
+        let message = Self::size_limit_message(what, size, limit, unit);
+        let _ = self.error_at_previous(&message);
+        Err(Exception::Assembler(BaseException::new(message, None)))
+    }
+
+    fn checked_u32(&mut self, value: usize, what: &str, unit: &str) -> Result<u32, Exception> {
+        u32::try_from(value).map_err(|_| {
+            let message = Self::size_limit_message(what, value, u32::MAX as usize, unit);
+            let _ = self.error_at_previous(&message);
+            Exception::Assembler(BaseException::new(message, None))
+        })
+    }
+
+    fn emit_number(&mut self, value: u32) {
+        self.text_segment.push(value.to_be_bytes());
+    }
+
+    fn emit_opcode(&mut self, op_code: OpCode) -> Result<(), Exception> {
+        self.check_size_limit(
+            "Text segment",
+            self.text_segment.len() + INSTRUCTION_SIZE,
+            MAX_TEXT_SEGMENT_WORDS,
+            "words",
+        )?;
+
+        if let Some(token) = self.instruction_token.clone() {
+            self.instruction_tokens
+                .push((self.text_segment.len(), token));
+        }
+
+        self.emit_number(op_code.into());
+        Ok(())
+    }
+
+    fn emit_data(&mut self, words: Vec<[u8; 4]>) -> Result<u32, Exception> {
+        self.check_size_limit(
+            "Data segment",
+            self.data_segment.len() + words.len(),
+            MAX_DATA_SEGMENT_WORDS,
+            "words",
+        )?;
+
+        let address = self.checked_u32(self.data_segment.len(), "Data segment", "words")?;
+        self.data_segment.extend(words);
+        Ok(address)
+    }
+
+    fn emit_string(&mut self, value: &str) -> Result<u32, Exception> {
+        self.check_size_limit("String", value.len(), MAX_STRING_BYTES, "bytes")?;
+        self.emit_data(pack_string(value))
+    }
+
+    // Placeholders are stored directly after the string's last word as (offset, register) pairs.
+    fn emit_placeholders(&mut self, placeholders: &[(u32, u32)]) -> Result<u32, Exception> {
+        let words = placeholders
+            .iter()
+            .flat_map(|(offset, register_number)| {
+                [offset.to_be_bytes(), register_number.to_be_bytes()]
+            })
+            .collect();
+
+        self.emit_data(words)?;
+        self.checked_u32(placeholders.len(), "Placeholder count", "placeholders")
+    }
+
+    fn emit_label(&mut self, key: String) -> Result<(), Exception> {
+        self.emit_number(LABEL_PLACEHOLDER);
+        self.upsert_unresolved_label(key)
+    }
+
+    fn emit_padding(&mut self, words: usize) {
+        for _ in 0..words {
+            self.emit_number(0);
+        }
+    }
+
+    fn validate_op_code(&mut self, op_code: OpCode) -> Result<(), Exception> {
+        if op_code == OpCode::NoOp {
+            let message = "Invalid opcode: NoOp is reserved for labels and placeholders and cannot be used in instructions.";
+            self.error_at_current(message)?;
+            return Err(Exception::Assembler(BaseException::new(
+                message.to_string(),
+                None,
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn validate_role(&mut self, role: &str) -> Result<(), Exception> {
+        if role.is_empty() {
+            self.error_at_previous("Role name cannot be empty.")?;
+            return Err(Exception::Assembler(BaseException::new(
+                "Role name cannot be empty.".to_string(),
+                None,
+            )));
+        }
+
+        let lower = role.to_lowercase();
+        if !matches!(
+            lower.as_str(),
+            roles::USER_ROLE | roles::ASSISTANT_ROLE | roles::SYSTEM_ROLE
+        ) {
+            let message = format!(
+                "Invalid role name '{}'. Expected '{}', '{}' or '{}'.",
+                role,
+                roles::USER_ROLE,
+                roles::ASSISTANT_ROLE,
+                roles::SYSTEM_ROLE
+            );
             self.error_at_previous(&message)?;
             return Err(Exception::Assembler(BaseException::new(message, None)));
         }
@@ -405,88 +1654,826 @@ impl Assembler {
         Ok(())
     }
 
-    fn branch(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+    fn branch(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let source_register_1 =
+            self.register("Expected source register 1 after branch keyword.", false)?;
+        self.consume(&TokenType::Comma, "Expected ',' after source register 1.")?;
+
+        let source_register_2 = self.register("Expected source register 2 after ','.", false)?;
+        self.consume(&TokenType::Comma, "Expected ',' after source register 2.")?;
+
+        let label_name = self
+            .identifier("Expected label name after ','.")?
+            .to_string();
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(source_register_1);
+        self.emit_number(source_register_2);
+        self.emit_label(label_name)
+    }
+
+    // 'bsim rs1, rs2, threshold, label'. An instruction only has three operand words, so the
+    // threshold shares the second register's word, in its upper 16 bits.
+    fn branch_similar(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let source_register_1 = self.register("Expected source register 1 after 'bsim'.", false)?;
+        self.consume(&TokenType::Comma, "Expected ',' after source register 1.")?;
+
+        let source_register_2 = self.register("Expected source register 2 after ','.", false)?;
+        self.consume(&TokenType::Comma, "Expected ',' after source register 2.")?;
+
+        let token = self.current_token()?;
+        let threshold = self.integer("Expected similarity threshold after ','.")?;
+
+        let Some(threshold) = u32::try_from(threshold)
+            .ok()
+            .filter(|threshold| *threshold <= 100)
+        else {
+            let message = format!(
+                "Similarity threshold {} is out of range. Similarity is scored from 0 to 100.",
+                threshold
+            );
+            self.error_at(&token, &message);
+            return Err(Exception::Assembler(BaseException::new(message, None)));
+        };
+
+        self.consume(
+            &TokenType::Comma,
+            "Expected ',' after similarity threshold.",
+        )?;
+
+        let label_name = self
+            .identifier("Expected label name after ','.")?
+            .to_string();
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(source_register_1);
+        self.emit_number(threshold << 16 | source_register_2);
+        self.emit_label(label_name)
+    }
+
+    // 'cls rd, rs, "a|b|c"' or 'cls rd, rs, "a|b|c", ri'. The index register shares the first
+    // word with the destination, like the second register and threshold of 'bsim'. 0 means none.
+    fn classify(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let destination_register =
+            self.register("Expected destination register after 'cls'.", false)?;
+        self.consume(
+            &TokenType::Comma,
+            "Expected ',' after destination register.",
+        )?;
+
+        let source_register = self.register("Expected source register after ','.", false)?;
+        self.consume(&TokenType::Comma, "Expected ',' after source register.")?;
+
+        let token = self.current_token()?;
+        let list = self.string("Expected label list after ','.")?;
+
+        if let Err(message) = labels::parse(&list) {
+            self.error_at(&token, &message);
+            return Err(Exception::Assembler(BaseException::new(message, None)));
+        }
+
+        let index_register = if self.check(&TokenType::Comma) {
+            self.consume(&TokenType::Comma, "Expected ',' after label list.")?;
+            self.register("Expected index register after ','.", false)?
+        } else {
+            0
+        };
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(index_register << 16 | destination_register);
+        self.emit_number(source_register);
+
+        let pointer = self.emit_string(&list)?;
+        self.emit_number(pointer);
+
+        Ok(())
+    }
+
+    // 'trl x2, x1, "french"' or 'trl x2, x1, x3'. The language is encoded like the key of 'vput',
+    // with both registers packed into one word to make room for its mode.
+    fn translate(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let destination_register =
+            self.register("Expected destination register after 'trl'.", false)?;
+        self.consume(
+            &TokenType::Comma,
+            "Expected ',' after destination register.",
+        )?;
+
+        let source_register = self.register("Expected source register after ','.", false)?;
+        self.consume(&TokenType::Comma, "Expected ',' after source register.")?;
+
+        let (language, mode) = if self.check_register() {
+            (
+                self.register("Expected language register after ','.", false)?,
+                AddressMode::Register,
+            )
+        } else {
+            let token = self.current_token()?;
+            let string =
+                self.string("Expected a language string or a register holding one after ','.")?;
+
+            if string.trim().is_empty() {
+                let message = "Target language is empty.".to_string();
+                self.error_at(&token, &message);
+                return Err(Exception::Assembler(BaseException::new(message, None)));
+            }
+
+            (self.emit_string(&string)?, AddressMode::Immediate)
+        };
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(source_register << 16 | destination_register);
+        self.emit_number(language);
+        self.emit_number(mode.into());
+
+        Ok(())
+    }
+
+    fn jump(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let label_name = self
+            .identifier("Expected label name after jump keyword.")?
+            .to_string();
+
+        self.emit_opcode(op_code)?;
+        self.emit_label(label_name)?;
+        self.emit_padding(2);
+
+        Ok(())
+    }
+
+    fn no_register_string(
+        &mut self,
+        token_type: &TokenType,
+        op_code: OpCode,
+    ) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let string = self.string(&format!("Expected string after '{:?}'.", op_code))?;
+
+        self.emit_opcode(op_code)?;
+
+        let pointer = self.emit_string(&string)?;
+        self.emit_number(pointer);
+        self.emit_padding(2);
+
+        Ok(())
+    }
+
+    // 'wf str, rs' names the string first but is encoded like 'lc rd, str', register then string pointer.
+    fn string_single_register(
+        &mut self,
+        token_type: &TokenType,
+        op_code: OpCode,
+        register_is_context: bool,
+    ) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let string = self.string(&format!("Expected string after '{:?}'.", op_code))?;
+        self.consume(&TokenType::Comma, "Expected ',' after string.")?;
+
+        let register = self.register("Expected source register after ','.", register_is_context)?;
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(register);
+
+        let pointer = self.emit_string(&string)?;
+        self.emit_number(pointer);
+        self.emit_padding(1);
+
+        Ok(())
+    }
+
+    fn single_register(
+        &mut self,
+        token_type: &TokenType,
+        op_code: OpCode,
+        register_is_context: bool,
+    ) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let register = self.register(
+            &format!("Expected register after '{:?}'.", op_code),
+            register_is_context,
+        )?;
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(register);
+        self.emit_padding(2);
+
+        Ok(())
+    }
+
+    fn single_register_string(
+        &mut self,
+        token_type: &TokenType,
+        op_code: OpCode,
+        validate_role: bool,
+        interpolate: bool,
+    ) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let register =
+            self.register(&format!("Expected register after '{:?}'.", op_code), false)?;
+        self.consume(&TokenType::Comma, "Expected ',' after register.")?;
+
+        if op_code == OpCode::LoadString && self.check(&TokenType::Identifier) {
+            return self.data_reference(op_code, register);
+        }
+
+        let string = if interpolate {
+            self.literal_string("Expected string after register.")?
+        } else {
+            self.string("Expected string after register.")?
+        };
+
+        if validate_role {
+            self.validate_role(&string)?;
+        }
+
+        let (string, placeholders) = if interpolate {
+            self.template(&string)?
+        } else {
+            (string, Vec::new())
+        };
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(register);
+
+        let pointer = self.emit_string(&string)?;
+        self.emit_number(pointer);
+
+        let placeholder_count = self.emit_placeholders(&placeholders)?;
+        self.emit_number(placeholder_count);
+
+        Ok(())
+    }
+
+    // 'fmt rd, str, rs1, rs2, ...' is encoded as an interpolated 'ls', with '{N}' standing for 'rsN'.
+    fn format(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let register = self.register("Expected destination register after 'fmt'.", false)?;
+        self.consume(&TokenType::Comma, "Expected ',' after register.")?;
+
+        let string = self.string("Expected format string after register.")?;
+        let string_token = self.previous_token()?;
+
+        let mut operands = Vec::new();
+
+        while self.check(&TokenType::Comma) {
+            self.advance()?;
+            operands.push(self.register("Expected register after ','.", false)?);
+        }
+
+        let (string, placeholders) = self.positional_template(&string_token, &string, &operands)?;
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(register);
+
+        let pointer = self.emit_string(&string)?;
+        self.emit_number(pointer);
+
+        let placeholder_count = self.emit_placeholders(&placeholders)?;
+        self.emit_number(placeholder_count);
+
+        Ok(())
+    }
+
+    // The string is optional, so the last word records whether there is one: a pointer of 0 is a valid string.
+    fn single_register_optional_string(
+        &mut self,
+        token_type: &TokenType,
+        op_code: OpCode,
+    ) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let register =
+            self.register(&format!("Expected register after '{:?}'.", op_code), false)?;
+
+        let string = if self.check(&TokenType::Comma) {
+            self.consume(&TokenType::Comma, "Expected ',' after register.")?;
+            Some(self.string("Expected string after ','.")?)
+        } else {
+            None
+        };
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(register);
+
+        match string {
+            Some(string) => {
+                let pointer = self.emit_string(&string)?;
+                self.emit_number(pointer);
+                self.emit_number(1);
+            }
+            None => self.emit_padding(2),
+        }
+
+        Ok(())
+    }
+
+    // Loads a string declared in the .data section. Data strings are not interpolated.
+    fn data_reference(&mut self, op_code: OpCode, register: u32) -> Result<(), Exception> {
+        let name = self
+            .identifier("Expected data label after register.")?
+            .to_string();
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(register);
+
+        match self.data_labels.get(&name) {
+            Some(&pointer) => self.emit_number(pointer),
+            None => {
+                self.emit_number(LABEL_PLACEHOLDER);
+
+                let index = self.text_segment.len() - 1;
+                let token = self.previous.clone().ok_or_else(|| {
+                    Exception::Assembler(BaseException::new(
+                        "Failed to retrieve previous token for unresolved data label error reporting."
+                            .to_string(),
+                        None,
+                    ))
+                })?;
+
+                self.unresolved_data_labels
+                    .entry(name)
+                    .or_insert(UnresolvedLabel { uses: Vec::new() })
+                    .add_use(index, token);
+            }
+        }
+
+        self.emit_number(0);
+
+        Ok(())
+    }
+
+    fn single_register_double_string(
+        &mut self,
+        token_type: &TokenType,
+        op_code: OpCode,
+    ) -> Result<(), Exception> {
         self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let register =
+            self.register(&format!("Expected register after '{:?}'.", op_code), false)?;
+        self.consume(&TokenType::Comma, "Expected ',' after register.")?;
+
+        let string_1 = self.string("Expected string after register.")?;
+        self.consume(&TokenType::Comma, "Expected ',' after string.")?;
+
+        let string_2 = self.string("Expected string after ','.")?;
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(register);
+
+        let pointer_1 = self.emit_string(&string_1)?;
+        self.emit_number(pointer_1);
+
+        let pointer_2 = self.emit_string(&string_2)?;
+        self.emit_number(pointer_2);
+
+        Ok(())
+    }
 
+    // The second string is emitted after the first, so its pointer is never 0 and 0 means there is none.
+    fn single_register_string_optional_string(
+        &mut self,
+        token_type: &TokenType,
+        op_code: OpCode,
+    ) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
         self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
 
-        let source_register_1 =
-            self.register("Expected source register 1 after branch keyword.", false)?;
-        self.consume(&TokenType::Comma, "Expected ',' after source register 1.")?;
+        let register =
+            self.register(&format!("Expected register after '{:?}'.", op_code), false)?;
+        self.consume(&TokenType::Comma, "Expected ',' after register.")?;
 
-        let source_register_2 = self.register("Expected source register 2 after ','.", false)?;
-        self.consume(&TokenType::Comma, "Expected ',' after source register 2.")?;
+        let string_1 = self.string("Expected string after register.")?;
 
-        let label_name = self
-            .identifier("Expected label name after ','.")?
-            .to_string();
+        let string_2 = if self.check(&TokenType::Comma) {
+            self.consume(&TokenType::Comma, "Expected ',' after string.")?;
+            Some(self.string("Expected string after ','.")?)
+        } else {
+            None
+        };
 
-        self.emit_opcode(op_code);
-        self.emit_number(source_register_1);
-        self.emit_number(source_register_2);
-        self.emit_label(label_name)
+        self.emit_opcode(op_code)?;
+        self.emit_number(register);
+
+        let pointer_1 = self.emit_string(&string_1)?;
+        self.emit_number(pointer_1);
+
+        match string_2 {
+            Some(string_2) => {
+                let pointer_2 = self.emit_string(&string_2)?;
+                self.emit_number(pointer_2);
+            }
+            None => self.emit_padding(1),
+        }
+
+        Ok(())
+    }
+
+    // A data memory address is either an integer expression or a register holding the address.
+    // Whether the current token names a general purpose register, such as 'x1'.
+    fn check_register(&self) -> bool {
+        self.current.as_ref().is_some_and(|token| {
+            let lexeme = self.lexeme(token);
+
+            token.token_type() == &TokenType::Identifier
+                && lexeme.len() > 1
+                && lexeme.starts_with(['x', 'X'])
+                && lexeme[1..].bytes().all(|byte| byte.is_ascii_digit())
+        })
+    }
+
+    fn address(&mut self) -> Result<(u32, AddressMode), Exception> {
+        if self.check_register() {
+            let register = self.register("Expected address register.", false)?;
+            return Ok((register, AddressMode::Register));
+        }
+
+        let token = self.current_token()?;
+        let address = self.integer("Expected an address or a register holding one.")?;
+
+        match u32::try_from(address) {
+            Ok(address) => Ok((address, AddressMode::Immediate)),
+            Err(_) => {
+                let message = format!(
+                    "Data memory address {} is negative. Addresses start at 0.",
+                    address
+                );
+                self.error_at(&token, &message);
+                Err(Exception::Assembler(BaseException::new(message, None)))
+            }
+        }
     }
 
-    fn no_register(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+    // 'sw addr, rs' and 'lw rd, addr'. The last word records whether the address is a register.
+    fn memory_access(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
         self.validate_op_code(op_code)?;
         self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
 
-        self.emit_opcode(op_code);
-        self.emit_padding(3);
+        let (register, (address, address_mode)) = if op_code == OpCode::StoreWord {
+            let address = self.address()?;
+            self.consume(&TokenType::Comma, "Expected ',' after address.")?;
+
+            let register = self.register(
+                &format!("Expected source register after '{:?}' address.", op_code),
+                false,
+            )?;
+
+            (register, address)
+        } else {
+            let register = self.register(
+                &format!("Expected destination register after '{:?}'.", op_code),
+                false,
+            )?;
+            self.consume(
+                &TokenType::Comma,
+                "Expected ',' after destination register.",
+            )?;
+
+            (register, self.address()?)
+        };
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(register);
+        self.emit_number(address);
+        self.emit_number(address_mode.into());
 
         Ok(())
     }
 
-    fn single_register(
+    // 'exit' or 'exit status', where the status is an immediate or a register holding one. The status
+    // is optional, so it is only read from the same line as the keyword. Bare 'exit' encodes as 'exit 0'.
+    fn exit(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let keyword = self.previous_token()?;
+        let has_status = self
+            .current
+            .as_ref()
+            .is_some_and(|token| token.file() == keyword.file() && token.line() == keyword.line());
+
+        let (status, status_mode) = if !has_status {
+            (0, AddressMode::Immediate)
+        } else if self.check_register() {
+            (
+                self.register("Expected exit status register.", false)?,
+                AddressMode::Register,
+            )
+        } else {
+            let token = self.current_token()?;
+            let status = self.integer("Expected an exit status or a register holding one.")?;
+
+            if !(0..=255).contains(&status) {
+                let message = format!(
+                    "Exit status {} is out of range. Statuses are 0 to 255.",
+                    status
+                );
+                self.error_at(&token, &message);
+                return Err(Exception::Assembler(BaseException::new(message, None)));
+            }
+
+            (status.cast_unsigned(), AddressMode::Immediate)
+        };
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(status);
+        self.emit_number(status_mode.into());
+        self.emit_padding(1);
+
+        Ok(())
+    }
+
+    // 'settemp 30' or 'settemp x1'. Encoded like EXIT, with the mode after the value. The value is
+    // checked when the instruction runs, since a register's is only known then.
+    fn integer_or_register(
         &mut self,
         token_type: &TokenType,
         op_code: OpCode,
-        register_is_context: bool,
     ) -> Result<(), Exception> {
         self.validate_op_code(op_code)?;
         self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
 
-        let register = self.register(
-            &format!("Expected register after '{:?}'.", op_code),
-            register_is_context,
-        )?;
+        let (value, mode) = if self.check_register() {
+            (
+                self.register(&format!("Expected register after '{:?}'.", op_code), false)?,
+                AddressMode::Register,
+            )
+        } else {
+            let value = self.integer(&format!(
+                "Expected an integer or a register holding one after '{:?}'.",
+                op_code
+            ))?;
+            (value.cast_unsigned(), AddressMode::Immediate)
+        };
 
-        self.emit_opcode(op_code);
-        self.emit_number(register);
-        self.emit_padding(2);
+        self.emit_opcode(op_code)?;
+        self.emit_number(value);
+        self.emit_number(mode.into());
+        self.emit_padding(1);
 
         Ok(())
     }
 
-    fn single_register_string(
+    // 'setsys "..."' or 'setsys x1'. Encoded like 'settemp', with a string pointer as the value.
+    fn string_or_register(
         &mut self,
         token_type: &TokenType,
         op_code: OpCode,
-        validate_role: bool,
     ) -> Result<(), Exception> {
         self.validate_op_code(op_code)?;
         self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
 
-        let register =
-            self.register(&format!("Expected register after '{:?}'.", op_code), false)?;
-        self.consume(&TokenType::Comma, "Expected ',' after register.")?;
+        let (value, mode) = if self.check_register() {
+            (
+                self.register(&format!("Expected register after '{:?}'.", op_code), false)?,
+                AddressMode::Register,
+            )
+        } else {
+            let string = self.string(&format!(
+                "Expected a string or a register holding one after '{:?}'.",
+                op_code
+            ))?;
+            (self.emit_string(&string)?, AddressMode::Immediate)
+        };
 
-        let string = self.string("Expected string after register.")?;
+        self.emit_opcode(op_code)?;
+        self.emit_number(value);
+        self.emit_number(mode.into());
+        self.emit_padding(1);
 
-        if validate_role {
-            self.validate_role(&string)?;
-        }
+        Ok(())
+    }
 
-        self.emit_opcode(op_code);
-        self.emit_number(register);
+    // 'vput "key", x1' or 'vput x2, x1'. The key is encoded like the value of 'setsys'.
+    fn vector_put(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
 
-        let pointer = self.emit_string(&string)?;
+        let (key, mode) = if self.check_register() {
+            (
+                self.register(&format!("Expected register after '{:?}'.", op_code), false)?,
+                AddressMode::Register,
+            )
+        } else {
+            let string = self.string(&format!(
+                "Expected a key string or a register holding one after '{:?}'.",
+                op_code
+            ))?;
+            (self.emit_string(&string)?, AddressMode::Immediate)
+        };
+
+        self.consume(&TokenType::Comma, "Expected ',' after key.")?;
+        let source_register = self.register("Expected source register after ','.", false)?;
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(key);
+        self.emit_number(mode.into());
+        self.emit_number(source_register);
+
+        Ok(())
+    }
+
+    // 'pshu c1, x1' is 'psh c1, x1, "user"', and 'psha' and 'pshs' push as assistant and system.
+    fn context_push_role(
+        &mut self,
+        token_type: &TokenType,
+        op_code: OpCode,
+        role: &str,
+    ) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let context_register = self.register("Expected context register after keyword.", true)?;
+        self.consume(&TokenType::Comma, "Expected ',' after context register.")?;
+        let source_register = self.register("Expected source register after ','.", false)?;
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(context_register);
+        self.emit_number(source_register);
+
+        let pointer = self.emit_string(role)?;
+        self.emit_number(pointer);
+
+        Ok(())
+    }
+
+    // 'ctxins c1, 0, x1, "user"' inserts x1 as the oldest message of c1. Both registers are packed
+    // into one word, as in 'chunk', to make room for the role.
+    fn context_insert(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let context_register = self.register("Expected context register after 'ctxins'.", true)?;
+        self.consume(&TokenType::Comma, "Expected ',' after context register.")?;
+
+        let token = self.current_token()?;
+        let index = self.integer("Expected message index after ','.")?;
+
+        let Ok(index) = u32::try_from(index) else {
+            let message = format!(
+                "Message index {} is out of range. It must be at least 0.",
+                index
+            );
+            self.error_at(&token, &message);
+            return Err(Exception::Assembler(BaseException::new(message, None)));
+        };
+
+        self.consume(&TokenType::Comma, "Expected ',' after message index.")?;
+        let source_register = self.register("Expected source register after ','.", false)?;
+        self.consume(&TokenType::Comma, "Expected ',' after source register.")?;
+
+        let role = self.string("Expected role after source register.")?;
+        self.validate_role(&role)?;
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(source_register << 16 | context_register);
+        self.emit_number(index);
+
+        let pointer = self.emit_string(&role)?;
+        self.emit_number(pointer);
+
+        Ok(())
+    }
+
+    // 'ctxload c1, "session.json", fresh' jumps to 'fresh' when the file does not exist. The label
+    // is optional, so a flag shares the first word with the register, since 0 is a valid address.
+    fn context_load(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let context_register = self.register("Expected context register after 'ctxload'.", true)?;
+        self.consume(&TokenType::Comma, "Expected ',' after context register.")?;
+
+        let path = self.string("Expected path after context register.")?;
+
+        let label_name = if self.check(&TokenType::Comma) {
+            self.consume(&TokenType::Comma, "Expected ',' after path.")?;
+            Some(
+                self.identifier("Expected label name after ','.")?
+                    .to_string(),
+            )
+        } else {
+            None
+        };
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(u32::from(label_name.is_some()) << 16 | context_register);
+
+        let pointer = self.emit_string(&path)?;
         self.emit_number(pointer);
+
+        match label_name {
+            Some(label_name) => self.emit_label(label_name),
+            None => {
+                self.emit_padding(1);
+                Ok(())
+            }
+        }
+    }
+
+    // 'ctxtrim c1, 10' keeps the 10 most recent messages of c1.
+    fn context_trim(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let context_register = self.register("Expected context register after 'ctxtrim'.", true)?;
+        self.consume(&TokenType::Comma, "Expected ',' after context register.")?;
+
+        let token = self.current_token()?;
+        let count = self.integer("Expected number of messages to keep after ','.")?;
+
+        let Ok(count) = u32::try_from(count) else {
+            let message = format!(
+                "Number of messages to keep {} is out of range. It must be at least 0.",
+                count
+            );
+            self.error_at(&token, &message);
+            return Err(Exception::Assembler(BaseException::new(message, None)));
+        };
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(context_register);
+        self.emit_number(count);
         self.emit_padding(1);
 
         Ok(())
     }
 
+    // 'vquery x1, x2, 3' writes the 3 stored texts closest to x2 into x1.
+    fn vector_query(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let destination_register =
+            self.register("Expected destination register after 'vquery'.", false)?;
+        self.consume(
+            &TokenType::Comma,
+            "Expected ',' after destination register.",
+        )?;
+
+        let source_register = self.register("Expected query register after ','.", false)?;
+        self.consume(&TokenType::Comma, "Expected ',' after query register.")?;
+
+        let token = self.current_token()?;
+        let count = self.integer("Expected number of matches after ','.")?;
+
+        let Some(count) = u32::try_from(count).ok().filter(|count| *count >= 1) else {
+            let message = format!(
+                "Number of matches {} is out of range. It must be at least 1.",
+                count
+            );
+            self.error_at(&token, &message);
+            return Err(Exception::Assembler(BaseException::new(message, None)));
+        };
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(destination_register);
+        self.emit_number(source_register);
+        self.emit_number(count);
+
+        Ok(())
+    }
+
+    fn no_operands(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        self.emit_opcode(op_code)?;
+        self.emit_padding(3);
+
+        Ok(())
+    }
+
     fn single_register_number(
         &mut self,
         token_type: &TokenType,
@@ -499,12 +2486,24 @@ impl Assembler {
             self.register(&format!("Expected register after '{:?}'.", op_code), false)?;
         self.consume(&TokenType::Comma, "Expected ',' after register.")?;
 
-        let number = self.number("Expected number after register.")?;
+        // Only LI takes floats. Integers are two's complement, so non-negative immediates encode
+        // exactly as they did when they were unsigned.
+        let (number, immediate_type) = match op_code {
+            OpCode::LoadImmediate => match self.number("Expected number after register.")? {
+                Immediate::Integer(value) => (value.cast_unsigned(), ImmediateType::Integer),
+                Immediate::Float(value) => (value.to_bits(), ImmediateType::Float),
+            },
+            _ => (
+                self.integer("Expected number after register.")?
+                    .cast_unsigned(),
+                ImmediateType::Integer,
+            ),
+        };
 
-        self.emit_opcode(op_code);
+        self.emit_opcode(op_code)?;
         self.emit_number(register);
         self.emit_number(number);
-        self.emit_padding(1);
+        self.emit_number(immediate_type.into());
 
         Ok(())
     }
@@ -533,7 +2532,7 @@ impl Assembler {
             source_register_is_context,
         )?;
 
-        self.emit_opcode(op_code);
+        self.emit_opcode(op_code)?;
         self.emit_number(destination_register);
         self.emit_number(source_register);
         self.emit_padding(1);
@@ -553,26 +2552,70 @@ impl Assembler {
 
         let destination_register = self.register(
             &format!("Expected destination register after '{:?}'.", op_code),
-            destination_register_is_context,
+            destination_register_is_context,
+        )?;
+        self.consume(
+            &TokenType::Comma,
+            "Expected ',' after destination register.",
+        )?;
+
+        let source_register = self.register(
+            &format!("Expected source register after '{:?}'.", op_code),
+            false,
+        )?;
+        self.consume(&TokenType::Comma, "Expected ',' after source register.")?;
+
+        let string = self.string("Expected string after source register.")?;
+
+        if validate_role {
+            self.validate_role(&string)?;
+        }
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(destination_register);
+        self.emit_number(source_register);
+
+        let pointer = self.emit_string(&string)?;
+        self.emit_number(pointer);
+
+        Ok(())
+    }
+
+    // 'json rd, rs, "fields"' and 'jget rd, rs, "path"'. Encoded like 'fmtn', after the fields or
+    // path is checked.
+    fn json_fields(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let destination_register = self.register(
+            &format!("Expected destination register after '{:?}'.", op_code),
+            false,
         )?;
         self.consume(
             &TokenType::Comma,
             "Expected ',' after destination register.",
         )?;
 
-        let source_register = self.register(
-            &format!("Expected source register after '{:?}'.", op_code),
-            false,
-        )?;
+        let source_register = self.register("Expected source register after ','.", false)?;
         self.consume(&TokenType::Comma, "Expected ',' after source register.")?;
 
-        let string = self.string("Expected string after source register.")?;
+        let token = self.current_token()?;
+        let (string, checked) = if op_code == OpCode::Json {
+            let string = self.string("Expected field list after ','.")?;
+            let checked = json_fields::parse(&string).map(|_| ());
+            (string, checked)
+        } else {
+            let string = self.string("Expected field path after ','.")?;
+            let checked = json_fields::path(&string).map(|_| ());
+            (string, checked)
+        };
 
-        if validate_role {
-            self.validate_role(&string)?;
+        if let Err(message) = checked {
+            self.error_at(&token, &message);
+            return Err(Exception::Assembler(BaseException::new(message, None)));
         }
 
-        self.emit_opcode(op_code);
+        self.emit_opcode(op_code)?;
         self.emit_number(destination_register);
         self.emit_number(source_register);
 
@@ -611,7 +2654,7 @@ impl Assembler {
             source_register_2_is_context,
         )?;
 
-        self.emit_opcode(op_code);
+        self.emit_opcode(op_code)?;
         self.emit_number(destination_register);
         self.emit_number(source_register_1);
         self.emit_number(source_register_2);
@@ -619,49 +2662,416 @@ impl Assembler {
         Ok(())
     }
 
+    // 'cat rd, rs1, rs2, str' has no room for the separator, so it is emitted as the load
+    // 'ls rd, "${rs1}str${rs2}"' instead, which joins the same way and fails the same way on an
+    // uninitialised register.
+
+    fn concatenate(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let destination_register = self.register(
+            &format!(
+                "Expected destination register after '{:?}' keyword.",
+                op_code
+            ),
+            false,
+        )?;
+        self.consume(
+            &TokenType::Comma,
+            "Expected ',' after destination register.",
+        )?;
+
+        let source_register_1 = self.register("Expected source register 1 after ','.", false)?;
+        self.consume(&TokenType::Comma, "Expected ',' after source register 1.")?;
+
+        let source_register_2 = self.register("Expected source register 2 after ','.", false)?;
+
+        if !self.check(&TokenType::Comma) {
+            self.emit_opcode(op_code)?;
+            self.emit_number(destination_register);
+            self.emit_number(source_register_1);
+            self.emit_number(source_register_2);
+
+            return Ok(());
+        }
+
+        self.consume(&TokenType::Comma, "Expected ',' after source register 2.")?;
+        let separator = self.string("Expected separator string after ','.")?;
+        let separator_length = self.checked_u32(separator.len(), "String", "bytes")?;
+
+        self.emit_opcode(OpCode::LoadString)?;
+        self.emit_number(destination_register);
+
+        let pointer = self.emit_string(&separator)?;
+        self.emit_number(pointer);
+
+        let placeholder_count = self.emit_placeholders(&[
+            (0, source_register_1),
+            (separator_length, source_register_2),
+        ])?;
+        self.emit_number(placeholder_count);
+
+        Ok(())
+    }
+
+    // 'esc rd, rs' escapes for an LS string, 'esc rd, rs, "fmt"' for a FMT string. The syntax takes
+    // the word a double register instruction leaves as padding, so 0 keeps the meaning of 'esc rd, rs'.
+    fn escape(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let destination_register =
+            self.register("Expected destination register after 'esc'.", false)?;
+        self.consume(
+            &TokenType::Comma,
+            "Expected ',' after destination register.",
+        )?;
+
+        let source_register = self.register("Expected source register after ','.", false)?;
+        let mut syntax = 0;
+
+        if self.check(&TokenType::Comma) {
+            self.advance()?;
+
+            syntax = match self
+                .string("Expected \"ls\" or \"fmt\" after ','.")?
+                .as_str()
+            {
+                "ls" => 0,
+                "fmt" => 1,
+                other => {
+                    let message = format!(
+                        "Invalid escape syntax '{}'. Expected \"ls\" or \"fmt\".",
+                        other
+                    );
+                    self.error_at_previous(&message)?;
+                    return Err(Exception::Assembler(BaseException::new(message, None)));
+                }
+            };
+        }
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(destination_register);
+        self.emit_number(source_register);
+        self.emit_number(syntax);
+
+        Ok(())
+    }
+
+    // 'chunk rd, rs, size' or 'chunk rd, rs, size, addr'. Both registers share the first word, like
+    // the second register and threshold of 'bsim'. The address defaults to 0.
+    fn chunk(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let destination_register =
+            self.register("Expected destination register after 'chunk'.", false)?;
+        self.consume(
+            &TokenType::Comma,
+            "Expected ',' after destination register.",
+        )?;
+
+        let source_register = self.register("Expected source register after ','.", false)?;
+        self.consume(&TokenType::Comma, "Expected ',' after source register.")?;
+
+        let token = self.current_token()?;
+        let size = self.integer("Expected chunk size after ','.")?;
+
+        let Some(size) = u32::try_from(size).ok().filter(|size| *size >= 1) else {
+            let message = format!(
+                "Chunk size {} is out of range. Chunks hold at least 1 character.",
+                size
+            );
+            self.error_at(&token, &message);
+            return Err(Exception::Assembler(BaseException::new(message, None)));
+        };
+
+        let address = if self.check(&TokenType::Comma) {
+            self.consume(&TokenType::Comma, "Expected ',' after chunk size.")?;
+
+            let token = self.current_token()?;
+            let address = self.integer("Expected data memory address after ','.")?;
+
+            u32::try_from(address).map_err(|_| {
+                let message = format!(
+                    "Data memory address {} is negative. Addresses start at 0.",
+                    address
+                );
+                self.error_at(&token, &message);
+                Exception::Assembler(BaseException::new(message, None))
+            })?
+        } else {
+            0
+        };
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(source_register << 16 | destination_register);
+        self.emit_number(size);
+        self.emit_number(address);
+
+        Ok(())
+    }
+
+    // 'emb x2, x1' or 'emb x5, x1, 3', which embeds x1 to x3 into x5 to x7 with one request.
+    fn embed(&mut self, token_type: &TokenType, op_code: OpCode) -> Result<(), Exception> {
+        self.validate_op_code(op_code)?;
+        self.consume(token_type, &format!("Expected '{:?}' keyword.", token_type))?;
+
+        let destination_register =
+            self.register("Expected destination register after 'emb'.", false)?;
+        self.consume(
+            &TokenType::Comma,
+            "Expected ',' after destination register.",
+        )?;
+
+        let source_register = self.register("Expected source register after ','.", false)?;
+
+        let count = if self.check(&TokenType::Comma) {
+            self.consume(&TokenType::Comma, "Expected ',' after source register.")?;
+
+            let token = self.current_token()?;
+            let count = self.integer("Expected number of registers after ','.")?;
+            let last = destination_register.max(source_register);
+
+            match u32::try_from(count) {
+                Ok(count) if count >= 1 && last + count - 1 <= 32 => count,
+                _ => {
+                    let message = format!(
+                        "Register count {} is out of range. EMB embeds at least 1 register, and the last must be x32 or below.",
+                        count
+                    );
+                    self.error_at(&token, &message);
+                    return Err(Exception::Assembler(BaseException::new(message, None)));
+                }
+            }
+        } else {
+            1
+        };
+
+        self.emit_opcode(op_code)?;
+        self.emit_number(destination_register);
+        self.emit_number(source_register);
+        self.emit_number(count);
+
+        Ok(())
+    }
+
     fn parse_instruction(&mut self, token_type: &TokenType) -> Result<(), Exception> {
         let op_code: OpCode = token_type.clone().into();
 
+        if self.in_data_section {
+            return match token_type {
+                TokenType::Label => self.data_entry(),
+                TokenType::Data | TokenType::Text => self.data_section(token_type),
+                TokenType::Const | TokenType::Equ => self.constant(token_type),
+                TokenType::Macro => self.macro_definition(),
+                TokenType::Include => self.include(),
+                _ => self.error_at_current(
+                    "Expected 'name: .string \"...\"' in the .data section. Use '.text' to return to instructions.",
+                ),
+            };
+        }
+
+        self.instruction_token = self.current.clone();
+
         match token_type {
             // Data movement.
-            TokenType::LoadString | TokenType::LoadContent => {
-                self.single_register_string(token_type, op_code, false)
+            TokenType::LoadString => self.single_register_string(token_type, op_code, false, true),
+            TokenType::Format => self.format(token_type, op_code),
+            TokenType::LoadContent | TokenType::LoadArgument => {
+                self.single_register_string(token_type, op_code, false, false)
+            }
+            TokenType::LoadDirectory => self.single_register_double_string(token_type, op_code),
+            TokenType::LoadEnvironment => {
+                self.single_register_string_optional_string(token_type, op_code)
             }
-            TokenType::LoadImmediate | TokenType::SubtractImmediate => {
+            TokenType::StoreWord | TokenType::LoadWord => self.memory_access(token_type, op_code),
+            TokenType::LoadImmediate | TokenType::LoadPositionalArgument => {
                 self.single_register_number(token_type, op_code)
             }
+            TokenType::LoadArgumentCount => self.single_register(token_type, op_code, false),
             TokenType::Move => self.double_register(token_type, op_code, false, false),
+            TokenType::Embed => self.embed(token_type, op_code),
             // Control flow.
             TokenType::BranchEqual
             | TokenType::BranchLess
             | TokenType::BranchLessEqual
             | TokenType::BranchGreater
             | TokenType::BranchGreaterEqual => self.branch(token_type, op_code),
-            TokenType::Exit => self.no_register(token_type, op_code),
+            TokenType::BranchSimilar => self.branch_similar(token_type, op_code),
+            TokenType::Classify => self.classify(token_type, op_code),
+            TokenType::Score => self.double_register_string(token_type, op_code, false, false),
+            TokenType::Json => self.json_fields(token_type, op_code),
+            TokenType::Jump => self.jump(token_type, op_code),
+            TokenType::Exit => self.exit(token_type, op_code),
+            TokenType::Checkpoint => self.no_register_string(token_type, op_code),
             TokenType::Label => self.label(),
+            // Directives.
+            TokenType::Const | TokenType::Equ => self.constant(token_type),
+            TokenType::Macro => self.macro_definition(),
+            TokenType::Include => self.include(),
+            TokenType::Data | TokenType::Text => self.data_section(token_type),
+            TokenType::EndMacro => self.error_at_current("Unexpected '.endm' without '.macro'."),
+            TokenType::Identifier => self.invoke_macro(),
             // I/O.
-            TokenType::Print | TokenType::PrintLine => {
+            TokenType::Print | TokenType::PrintLine | TokenType::PrintError => {
                 self.single_register(token_type, op_code, false)
             }
             TokenType::PrintContext => self.single_register(token_type, op_code, true),
+            TokenType::Input => self.single_register_optional_string(token_type, op_code),
+            TokenType::WriteFile | TokenType::AppendFile => {
+                self.string_single_register(token_type, op_code, false)
+            }
             // Generative, cognitive, and guardrails operations.
             TokenType::Inference | TokenType::Evaluate => {
                 self.triple_register(token_type, op_code, true)
             }
-            TokenType::Similarity => self.triple_register(token_type, op_code, false),
+            TokenType::Similarity | TokenType::Ask => {
+                self.triple_register(token_type, op_code, false)
+            }
+            TokenType::Translate => self.translate(token_type, op_code),
+            // Model selection.
+            TokenType::SetModel | TokenType::SetEmbeddingModel => {
+                self.no_register_string(token_type, op_code)
+            }
+            // Sampling.
+            TokenType::SetTemperature | TokenType::SetTopP | TokenType::SetMaxTokens => {
+                self.integer_or_register(token_type, op_code)
+            }
+            // System prompt.
+            TokenType::SetSystemPrompt => self.string_or_register(token_type, op_code),
+            // Vector store.
+            TokenType::VectorPut => self.vector_put(token_type, op_code),
+            TokenType::VectorQuery => self.vector_query(token_type, op_code),
+            TokenType::VectorClear => self.no_operands(token_type, op_code),
             // Context operations.
             TokenType::ContextPush => self.double_register_string(token_type, op_code, true, true),
+            TokenType::ContextPushUser => {
+                self.context_push_role(token_type, op_code, roles::USER_ROLE)
+            }
+            TokenType::ContextPushAssistant => {
+                self.context_push_role(token_type, op_code, roles::ASSISTANT_ROLE)
+            }
+            TokenType::ContextPushSystem => {
+                self.context_push_role(token_type, op_code, roles::SYSTEM_ROLE)
+            }
             TokenType::ContextPop => self.double_register(token_type, op_code, false, true),
             TokenType::ContextDrop => self.single_register(token_type, op_code, true),
             TokenType::MoveContext => self.double_register(token_type, op_code, true, true),
+            TokenType::ContextLength => self.double_register(token_type, op_code, false, true),
+            TokenType::ContextTrim => self.context_trim(token_type, op_code),
+            TokenType::ContextPeek => self.double_register(token_type, op_code, false, true),
+            TokenType::ContextInsert => self.context_insert(token_type, op_code),
+            TokenType::ContextSave => self.string_single_register(token_type, op_code, true),
+            TokenType::ContextLoad => self.context_load(token_type, op_code),
+            // Stack operations.
+            TokenType::StackPush | TokenType::StackPop | TokenType::StackPeek => {
+                self.single_register(token_type, op_code, false)
+            }
+            // Arithmetic operations.
+            TokenType::AddImmediate
+            | TokenType::SubtractImmediate
+            | TokenType::MultiplyImmediate
+            | TokenType::DivideImmediate => self.single_register_number(token_type, op_code),
+            TokenType::AddNumber
+            | TokenType::SubtractNumber
+            | TokenType::MultiplyNumber
+            | TokenType::DivideNumber => self.triple_register(token_type, op_code, false),
+            // String operations.
+            TokenType::FormatNumber => {
+                self.double_register_string(token_type, op_code, false, false)
+            }
+            TokenType::Escape => self.escape(token_type, op_code),
+            TokenType::Length
+            | TokenType::TokenCount
+            | TokenType::Upper
+            | TokenType::Lower
+            | TokenType::Trim => self.double_register(token_type, op_code, false, false),
+            TokenType::Chunk => self.chunk(token_type, op_code),
+            TokenType::JsonGet => self.json_fields(token_type, op_code),
+            TokenType::Concatenate => self.concatenate(token_type, op_code),
             _ => self.error_at_current("Unexpected keyword."),
         }
     }
 
+    fn warn_unused_labels(&mut self) {
+        let mut unused = self
+            .labels
+            .iter()
+            .filter(|(name, _)| !self.referenced_labels.contains(*name))
+            .map(|(_, label)| label.token.clone())
+            .collect::<Vec<_>>();
+
+        // Labels in a macro body are defined once per expansion at the same place, so report that place once.
+        unused.sort_by_key(|token| (token.file(), token.line(), token.column()));
+        unused.dedup_by_key(|token| (token.file(), token.line(), token.column()));
+
+        for token in unused {
+            self.warning_at(&token, "Label is defined but never referenced.");
+        }
+    }
+
+    // Every label defined in the program, ordered by address and then by name.
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols = self
+            .labels
+            .iter()
+            .map(|(name, label)| Symbol {
+                name: name.clone(),
+                byte_code_index: u32::try_from(label.byte_code_index)
+                    .unwrap_or(u32::MAX)
+                    .saturating_add(HEADER_SIZE),
+                file: self.files[label.token.file()].path.display().to_string(),
+                line: label.token.line(),
+                column: label.token.column(),
+            })
+            .collect::<Vec<_>>();
+
+        symbols.sort_by(|a, b| (a.byte_code_index, &a.name).cmp(&(b.byte_code_index, &b.name)));
+        symbols
+    }
+
+    // The magic word and entry count, followed by the byte code index, data segment offset of the file
+    // path, line and column of every instruction. File paths are emitted into the data segment.
+    fn debug_info(&mut self) -> Result<Vec<[u8; 4]>, Exception> {
+        let mut path_offsets: HashMap<usize, u32> = HashMap::new();
+        let mut entries = Vec::new();
+
+        for (index, token) in self.instruction_tokens.clone() {
+            let path_offset = match path_offsets.get(&token.file()) {
+                Some(offset) => *offset,
+                None => {
+                    let path = self.files[token.file()].path.display().to_string();
+                    let offset = self.emit_string(&path)?;
+                    path_offsets.insert(token.file(), offset);
+                    offset
+                }
+            };
+
+            let address = self.checked_u32(index, "Text segment", "words")? + HEADER_SIZE;
+            let line = self.checked_u32(token.line(), "Line number", "lines")?;
+            let column = self.checked_u32(token.column(), "Column number", "columns")?;
+
+            entries.extend([
+                address.to_be_bytes(),
+                path_offset.to_be_bytes(),
+                line.to_be_bytes(),
+                column.to_be_bytes(),
+            ]);
+        }
+
+        let count = self.checked_u32(self.instruction_tokens.len(), "Debug info", "entries")?;
+        let mut words = vec![DEBUG_INFO_MAGIC.to_be_bytes(), count.to_be_bytes()];
+        words.extend(entries);
+
+        Ok(words)
+    }
+
     pub fn assemble(&mut self) -> Result<Vec<u8>, Exception> {
         self.advance()?;
 
-        while !self.panic_mode {
+        loop {
             let token_type = self
                 .current
                 .as_ref()
@@ -672,42 +3082,93 @@ impl Assembler {
                 break;
             }
 
-            self.parse_instruction(&token_type)?;
-        }
+            let result = self.parse_instruction(&token_type);
 
-        if self.had_error {
-            return Err(Exception::Assembler(BaseException::new(
-                "Assembly failed due to errors.".to_string(),
-                None,
-            )));
+            // An error without a diagnostic is not a mistake in the source, so there is nothing to recover from.
+            if !self.panic_mode {
+                result?;
+                continue;
+            }
+
+            self.synchronize();
         }
 
         self.backpatch_labels()?;
 
-        if let Some((_, unresolved_label)) = self.unresolved_labels.iter().next() {
-            let token = unresolved_label.token.clone();
-            self.error_at(&token, "Undefined label referenced here.");
+        let mut data_labels = std::mem::take(&mut self.unresolved_data_labels)
+            .into_iter()
+            .collect::<Vec<_>>();
+        data_labels.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut undefined = Vec::new();
+
+        for (name, unresolved) in data_labels {
+            match self.data_labels.get(&name).copied() {
+                Some(pointer) => self.patch_uses(&unresolved.uses, pointer)?,
+                None => undefined.push((unresolved.uses, "Undefined data label referenced here.")),
+            }
+        }
+
+        undefined.extend(
+            std::mem::take(&mut self.unresolved_labels)
+                .into_values()
+                .map(|unresolved| (unresolved.uses, "Undefined label referenced here.")),
+        );
+
+        // Report in source order rather than hash map order.
+        undefined.sort_by_key(|(uses, _)| {
+            uses.first()
+                .map(|(_, token)| (token.file(), token.line(), token.column()))
+        });
+
+        for (uses, message) in undefined {
+            self.report_undefined(&uses, message);
+        }
+
+        if self.warn_unused_labels {
+            self.warn_unused_labels();
+        }
+
+        let errors = self
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .count();
 
+        if errors > 0 {
             return Err(Exception::Assembler(BaseException::new(
-                "Assembly failed due to errors.".to_string(),
+                format!("Assembly failed with {} error(s).", errors),
                 None,
             )));
         }
 
-        let mut byte_code: Vec<[u8; 4]> = Vec::new();
-        byte_code.push(HEADER_SIZE.to_be_bytes());
-
-        let text_segment_size = u32::try_from(self.text_segment.len()).map_err(|_| {
-            let message = format!(
-                "Failed to convert text segment size to u32. Text segment size exceeds {}. Found text segment size: {}",
-                u32::MAX,
-                self.text_segment.len()
-            );
-            let _ = self.error_at_current(&message);
-            Exception::Assembler(BaseException::new(message, None))
-        })?;
+        // Record an absolute path so that 'run' can find the source from any working directory.
+        let source_path = canonicalize(&self.files[0].path)
+            .unwrap_or_else(|_| self.files[0].path.clone())
+            .to_string_lossy()
+            .into_owned();
+        let source_path_offset = self.emit_string(&source_path)?;
+        let debug_info = if self.debug_info {
+            self.debug_info()?
+        } else {
+            Vec::new()
+        };
 
-        byte_code.push((HEADER_SIZE + text_segment_size).to_be_bytes());
+        let text_segment_size =
+            self.checked_u32(self.text_segment.len(), "Text segment", "words")?;
+        let data_segment_size =
+            self.checked_u32(self.data_segment.len(), "Data segment", "words")?;
+
+        let mut byte_code: Vec<[u8; 4]> = vec![
+            BYTE_CODE_MAGIC.to_be_bytes(),
+            BYTE_CODE_VERSION.to_be_bytes(),
+            HEADER_SIZE.to_be_bytes(),
+            (HEADER_SIZE + text_segment_size).to_be_bytes(),
+            text_segment_size.to_be_bytes(),
+            data_segment_size.to_be_bytes(),
+            source_hash(&self.files[0].source).to_be_bytes(),
+            source_path_offset.to_be_bytes(),
+        ];
 
         // Append the text segment.
         byte_code.extend(&self.text_segment);
@@ -715,6 +3176,444 @@ impl Assembler {
         // Append the data segment after the text segment.
         byte_code.extend(&self.data_segment);
 
+        // Append the debug info, if any, after the data segment.
+        byte_code.extend(debug_info);
+
         Ok(byte_code.into_iter().flatten().collect())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integers_above_i32_max_keep_their_old_encoding() {
+        let mut wide = Assembler::new("LI X1, 4294967295\nEXIT\n".to_string(), "wide.aasm");
+        let wide_code = wide.assemble().unwrap();
+        let signed_code = Assembler::new("LI X1, -1\nEXIT\n".to_string(), "wide.aasm")
+            .assemble()
+            .unwrap();
+
+        // Only the source hash in the header differs.
+        assert_eq!(wide_code[..24], signed_code[..24]);
+        assert_eq!(wide_code[28..], signed_code[28..]);
+        assert!(
+            wide.diagnostics()
+                .iter()
+                .any(|diagnostic| diagnostic.severity == Severity::Warning
+                    && diagnostic.message.contains("stored as -1"))
+        );
+
+        assert!(
+            Assembler::new("LI X1, 4294967296\nEXIT\n".to_string(), "wide.aasm")
+                .assemble()
+                .is_err()
+        );
+    }
+
+    fn data_segment_words(source: &str) -> u32 {
+        let byte_code = Assembler::new(source.to_string(), "strings.aasm")
+            .assemble()
+            .unwrap();
+        u32::from_be_bytes(byte_code[20..24].try_into().unwrap())
+    }
+
+    #[test]
+    fn strings_of_any_utf8_length_round_trip() {
+        let strings = [
+            "",
+            "a",
+            "ab",
+            "abc",
+            "abcd",
+            "abcde",
+            "é",
+            "aé",
+            "a€",
+            "😀",
+            "a😀b",
+            "日本語",
+            "ß€😀x",
+        ];
+
+        for string in strings {
+            let source = format!("LS X1, \"{}\"\nEXIT\n", string);
+            let byte_code = Assembler::new(source, "strings.aasm").assemble().unwrap();
+
+            let mut processor = crate::Processor::new(crate::Config::new("text", "embedding"));
+            processor.load(&byte_code).unwrap();
+            processor.run().unwrap();
+
+            assert_eq!(processor.register(1).unwrap().to_string(), string);
+
+            let listing = crate::Processor::disassemble(&byte_code).unwrap();
+            assert!(
+                listing[0].contains(&format!("\"{}\"", string)),
+                "{:?}",
+                listing
+            );
+        }
+
+        // A length word, then four bytes to a word with the last one padded.
+        let empty = data_segment_words("LS X1, \"\"\nEXIT\n");
+        assert_eq!(data_segment_words("LS X1, \"abcd\"\nEXIT\n"), empty + 1);
+        assert_eq!(data_segment_words("LS X1, \"abcde\"\nEXIT\n"), empty + 2);
+        assert_eq!(data_segment_words("LS X1, \"€\"\nEXIT\n"), empty + 1);
+        assert_eq!(data_segment_words("LS X1, \"a€\"\nEXIT\n"), empty + 1);
+        assert_eq!(data_segment_words("LS X1, \"ab€\"\nEXIT\n"), empty + 2);
+    }
+
+    #[test]
+    fn a_large_source_assembles_in_linear_time() {
+        let line = format!("LS X1, \"{}\"\n", "text é€😀 ".repeat(200));
+        let mut source = line.repeat(250);
+        source.push_str("LS X2, \"é€😀\"\nBAD X1\n");
+        assert!(source.len() > 500_000);
+
+        let start = std::time::Instant::now();
+        let mut assembler = Assembler::new(source, "large.aasm");
+        assert!(assembler.assemble().is_err());
+        let elapsed = start.elapsed();
+
+        // Minutes when scanning was quadratic, well under a second in a debug build now.
+        assert!(elapsed.as_secs() < 5, "took {:?}", elapsed);
+
+        let diagnostic = &assembler.diagnostics()[0];
+        assert_eq!((diagnostic.line, diagnostic.lexeme.as_str()), (252, "BAD"));
+    }
+
+    #[test]
+    fn constants_may_share_a_name_with_an_instruction() {
+        let source = ".const CHUNK 512\n.equ ask, 3\nLI X1, CHUNK * 4\nSUBI X1, (CHUNK + 1) / 2\nADDI X1, ask\nLS X2, \"text\"\nCHUNK X3, X2, CHUNK\nEXIT\n";
+        let byte_code = Assembler::new(source.to_string(), "const.aasm")
+            .assemble()
+            .unwrap();
+
+        let mut processor = crate::Processor::new(crate::Config::new("text", "embedding"));
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+
+        assert_eq!(processor.register(1).unwrap().to_string(), "1795");
+        assert_eq!(processor.register(3).unwrap().to_string(), "1");
+
+        let mut assembler = Assembler::new(
+            ".const CHUNK 1\n.const CHUNK 2\nLI X1, LS\n".to_string(),
+            "const.aasm",
+        );
+        assert!(assembler.assemble().is_err());
+        let messages = assembler
+            .diagnostics()
+            .iter()
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect::<Vec<_>>();
+        assert!(
+            messages.contains(&"Constant 'CHUNK' is already defined.")
+                && messages.contains(&"Undefined constant 'LS'."),
+            "{:?}",
+            messages
+        );
+    }
+
+    #[test]
+    fn a_macro_named_after_an_instruction_is_an_error() {
+        let mut assembler = Assembler::new(
+            ".macro ask dst, question\nLS X1, question\nINF dst, X1, C1\n.endm\n".to_string(),
+            "macro.aasm",
+        );
+        assert!(assembler.assemble().is_err());
+
+        let diagnostic = &assembler.diagnostics()[0];
+        assert_eq!((diagnostic.line, diagnostic.lexeme.as_str()), (1, "ask"));
+        assert_eq!(
+            diagnostic.message,
+            "Macro name 'ask' is an instruction mnemonic. Rename the macro, for example to 'ask_macro'."
+        );
+    }
+
+    #[test]
+    fn integers_past_u32_max_are_reported_at_the_literal() {
+        let mut largest = Assembler::new("li x1, 4294967295".to_string(), "wide.aasm");
+        assert!(largest.assemble().is_ok());
+
+        let mut past = Assembler::new("li x1, 4294967296".to_string(), "wide.aasm");
+        assert!(past.assemble().is_err());
+
+        let diagnostic = &past.diagnostics()[0];
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(
+            (
+                diagnostic.line,
+                diagnostic.column,
+                diagnostic.lexeme.as_str()
+            ),
+            (1, 8, "4294967296")
+        );
+    }
+
+    #[test]
+    fn placeholders_outside_ls_strings_are_kept_with_a_warning() {
+        let mut assembler = Assembler::new(
+            "LS X1, \"text\"\nLS X3, \"French\"\nTRL X2, X1, \"${x3}\"\nSCORE X4, X1, \"${y} and ${x}\"\nEXIT\n"
+                .to_string(),
+            "placeholders.aasm",
+        );
+        assert!(assembler.assemble().is_ok());
+
+        let warnings = assembler
+            .diagnostics()
+            .iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            warnings,
+            [(
+                3,
+                "'${x3}' is only replaced in LS strings, so it is used as written here. Load the text with LS and pass its register instead."
+            )]
+        );
+    }
+
+    #[test]
+    fn character_literals_are_numeric_immediates() {
+        let source = "LI X1, 'a'\nLI X2, '\\n'\nLI X3, '\\''\nLI X4, '\\\\'\nLI X5, 'é'\nLI X6, '\\t'\nEXIT\n";
+        let byte_code = Assembler::new(source.to_string(), "chars.aasm")
+            .assemble()
+            .unwrap();
+
+        let mut processor = crate::Processor::new(crate::Config::new("text", "embedding"));
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+
+        let values = (1..=6)
+            .map(|register| processor.register(register).unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(values, ["97", "10", "39", "92", "233", "9"]);
+
+        let mut assembler = Assembler::new("LI X1, 'ab'\nEXIT\n".to_string(), "chars.aasm");
+        assert!(assembler.assemble().is_err());
+
+        let diagnostic = &assembler.diagnostics()[0];
+        assert_eq!(
+            (
+                diagnostic.line,
+                diagnostic.column,
+                diagnostic.lexeme.as_str()
+            ),
+            (1, 8, "'ab'")
+        );
+        assert!(
+            diagnostic
+                .message
+                .starts_with("Invalid character literal 'ab'."),
+            "{}",
+            diagnostic
+        );
+
+        let mut unterminated = Assembler::new("LI X1, 'a\nEXIT\n".to_string(), "chars.aasm");
+        assert!(unterminated.assemble().is_err());
+        assert_eq!(
+            unterminated.diagnostics()[0].scan_error.as_deref(),
+            Some("Unterminated character literal.")
+        );
+    }
+
+    #[test]
+    fn numeric_immediates_fold_constant_expressions() {
+        let source = ".const N 10\nLI X1, 2 + 3 * 4\nLI X2, (2 + 3) * 4\nLI X3, N * (N - 1) / 2\nLI X4, 10 - 4 - 3\nLI X5, -(N + 2) * 3\nEXIT\n";
+        let byte_code = Assembler::new(source.to_string(), "fold.aasm")
+            .assemble()
+            .unwrap();
+
+        let mut processor = crate::Processor::new(crate::Config::new("text", "embedding"));
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+
+        let values = (1..=5)
+            .map(|register| processor.register(register).unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(values, ["14", "20", "45", "3", "-36"]);
+
+        for (source, column, message) in [
+            (
+                ".const N 0\nLI X1, 8 / N\n",
+                10,
+                "Division by zero in constant expression '8 / 0'.",
+            ),
+            (
+                "LI X1, 65536 * 65536\n",
+                14,
+                "Constant expression '65536 * 65536' is out of range.",
+            ),
+        ] {
+            let mut assembler = Assembler::new(source.to_string(), "fold.aasm");
+            assert!(assembler.assemble().is_err());
+
+            let diagnostic = assembler.diagnostics().last().unwrap();
+            assert_eq!(diagnostic.column, column, "{}", diagnostic);
+            assert!(diagnostic.message.starts_with(message), "{}", diagnostic);
+        }
+    }
+
+    #[test]
+    fn macros_expand_with_their_own_labels() {
+        let source = ".macro count_down dst, from, steps\nLI dst, from\nloop:\nSUBI dst, 1\nADDI steps, 1\nBGT dst, X0, loop\n.endm\n.macro greet dst, text\nLS dst, text\n.endm\nLI X4, 0\ncount_down X1, 3, X4\ncount_down X2, 5, X4\ngreet X3, \"hello\"\nEXIT\n";
+        let byte_code = Assembler::new(source.to_string(), "macro.aasm")
+            .assemble()
+            .unwrap();
+
+        let mut processor = crate::Processor::new(crate::Config::new("text", "embedding"));
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+
+        let values = (1..=4)
+            .map(|register| processor.register(register).unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(values, ["0", "0", "hello", "8"]);
+
+        let mut recursive = Assembler::new(
+            "LS X1, \"text\"\n.macro again dst\nPLN dst\nagain dst\n.endm\n".to_string(),
+            "macro.aasm",
+        );
+        assert!(recursive.assemble().is_err());
+
+        let diagnostic = &recursive.diagnostics()[0];
+        assert_eq!((diagnostic.line, diagnostic.lexeme.as_str()), (4, "again"));
+        assert_eq!(
+            diagnostic.message,
+            "Recursive invocation of macro 'again' defined on line 2."
+        );
+    }
+
+    #[test]
+    fn included_files_share_labels_and_report_their_own_locations() {
+        let root = std::env::temp_dir().join(format!("lpu-include-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("lib")).unwrap();
+        std::fs::write(
+            root.join("main.aasm"),
+            "JMP greet\nLS X2, \"skipped\"\ndone:\nEXIT\n.include \"lib/words.aasm\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("lib/words.aasm"),
+            "greet:\nLS X1, \"words\"\n.include \"finish.aasm\"\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("lib/finish.aasm"), "JMP done\n").unwrap();
+        std::fs::write(root.join("lib/broken.aasm"), "LS X1, \"ok\"\nLI X1,\n").unwrap();
+        std::fs::write(root.join("lib/loop.aasm"), ".include \"loop.aasm\"\n").unwrap();
+
+        let main = root.join("main.aasm");
+        let source = std::fs::read_to_string(&main).unwrap();
+        let byte_code = Assembler::new(source, main.to_str().unwrap())
+            .assemble()
+            .unwrap();
+
+        let mut processor = crate::Processor::new(crate::Config::new("text", "embedding"));
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+        assert_eq!(processor.register(1).unwrap().to_string(), "words");
+        assert_eq!(processor.register(2).unwrap().to_string(), "");
+
+        let mut broken = Assembler::new(
+            ".include \"lib/broken.aasm\"\n".to_string(),
+            main.to_str().unwrap(),
+        );
+        assert!(broken.assemble().is_err());
+        let diagnostic = &broken.diagnostics()[0];
+        assert_eq!(
+            (diagnostic.path.clone(), diagnostic.line),
+            (root.join("lib/broken.aasm"), 2)
+        );
+
+        let mut circular = Assembler::new(
+            ".include \"lib/loop.aasm\"\n".to_string(),
+            main.to_str().unwrap(),
+        );
+        let result = circular.assemble();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert!(result.is_err());
+        assert_eq!(
+            circular.diagnostics()[0].message,
+            format!(
+                "Circular include of '{}'.",
+                root.join("lib/loop.aasm").display()
+            )
+        );
+    }
+
+    #[test]
+    fn every_independent_error_is_reported_in_one_run() {
+        let source =
+            "LS X1, \"ok\"\nLI X1, X2\nPLN X1\nBOGUS X2\nLS X3, \"fine\"\nJMP nowhere\nEXIT\n";
+        let mut assembler = Assembler::new(source.to_string(), "errors.aasm");
+        assert!(assembler.assemble().is_err());
+
+        let errors = assembler
+            .diagnostics()
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .map(|diagnostic| (diagnostic.line, diagnostic.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert_eq!(
+            errors.iter().map(|(line, _)| *line).collect::<Vec<_>>(),
+            [2, 4, 6]
+        );
+    }
+
+    #[test]
+    fn many_forward_references_to_one_label_are_all_patched() {
+        let mut source = ".macro hop\nJMP target\n.endm\n".to_string();
+        let mut expected = Vec::new();
+
+        for use_index in 0..48 {
+            source.push_str(match use_index % 3 {
+                0 => "JMP target\n",
+                1 => "BEQ X1, X2, target\n",
+                _ => "hop\n",
+            });
+            expected.push("target".to_string());
+
+            let other = format!("other_{}", use_index % 6);
+            source.push_str(&format!("JMP {}\n", other));
+            expected.push(other);
+        }
+
+        for other in 0..6 {
+            source.push_str(&format!("other_{}:\nLI X1, {}\n", other, other));
+        }
+        source.push_str("target:\nEXIT\n");
+
+        let byte_code = Assembler::new(source, "labels.aasm").assemble().unwrap();
+        let listing = crate::Processor::disassemble(&byte_code).unwrap();
+        let address = |line: &str| line.split_whitespace().next().unwrap().to_string();
+
+        let uses = &listing[..expected.len()];
+        let others = &listing[expected.len()..expected.len() + 6];
+        let target = address(listing.last().unwrap());
+
+        for (line, label) in uses.iter().zip(&expected) {
+            let wanted = match label.strip_prefix("other_") {
+                Some(other) => address(&others[other.parse::<usize>().unwrap()]),
+                None => target.clone(),
+            };
+            let wanted = format!("0x{}", wanted[2..].trim_start_matches('0'));
+            assert!(line.ends_with(&wanted), "{} should go to {}", line, label);
+        }
+
+        let mut undefined = Assembler::new(
+            "JMP missing\nLI X1, 1\nBEQ X1, X2, missing\nJMP missing\n".to_string(),
+            "labels.aasm",
+        );
+        assert!(undefined.assemble().is_err());
+
+        let diagnostic = &undefined.diagnostics()[0];
+        assert_eq!((diagnostic.line, diagnostic.column), (1, 5));
+        assert_eq!(
+            diagnostic.message,
+            "Undefined label referenced here. Also referenced at labels.aasm Line 3:13, labels.aasm Line 4:5."
+        );
+    }
+}
@@ -5,29 +5,90 @@ pub enum OpCode {
     LoadContent = 0x01,
     LoadImmediate = 0x02,
     Move = 0x03,
+    LoadArgument = 0x16,
+    LoadPositionalArgument = 0x30,
+    LoadArgumentCount = 0x31,
+    LoadDirectory = 0x18,
+    LoadEnvironment = 0x2F,
+    StoreWord = 0x2B,
+    LoadWord = 0x2C,
     // Control flow.
     BranchEqual = 0x04,
     BranchLessEqual = 0x05,
     BranchLess = 0x06,
     BranchGreaterEqual = 0x07,
     BranchGreater = 0x08,
+    Jump = 0x17,
     Exit = 0x09,
+    Checkpoint = 0x1A,
     // I/O.
     Print = 0x0A,
     PrintLine = 0x0B,
+    PrintError = 0x32,
     PrintContext = 0x0C,
+    Input = 0x1B,
+    WriteFile = 0x2D,
+    AppendFile = 0x2E,
     // Generative operations.
     Inference = 0x0D,
+    Ask = 0x43,
+    Translate = 0x44,
     // Guardrails operations.
     Evaluate = 0x0E,
     Similarity = 0x0F,
+    Embed = 0x33,
+    BranchSimilar = 0x34,
+    Classify = 0x3F,
+    Score = 0x40,
+    Json = 0x41,
+    // Model selection.
+    SetModel = 0x35,
+    SetEmbeddingModel = 0x36,
+    // Sampling.
+    SetTemperature = 0x37,
+    SetTopP = 0x38,
+    SetMaxTokens = 0x39,
+    // System prompt.
+    SetSystemPrompt = 0x3A,
+    // Vector store.
+    VectorPut = 0x3B,
+    VectorQuery = 0x3C,
+    VectorClear = 0x3D,
     // Context operations.
     ContextPush = 0x10,
     ContextPop = 0x11,
     ContextDrop = 0x12,
     MoveContext = 0x13,
+    ContextLength = 0x45,
+    ContextTrim = 0x46,
+    ContextPeek = 0x47,
+    ContextInsert = 0x48,
+    ContextSave = 0x49,
+    ContextLoad = 0x4A,
+    // Stack operations.
+    StackPush = 0x28,
+    StackPop = 0x29,
+    StackPeek = 0x2A,
     // Arithmetic operations.
     SubtractImmediate = 0x14,
+    AddImmediate = 0x21,
+    MultiplyImmediate = 0x22,
+    DivideImmediate = 0x23,
+    AddNumber = 0x24,
+    SubtractNumber = 0x25,
+    MultiplyNumber = 0x26,
+    DivideNumber = 0x27,
+    // String operations.
+    FormatNumber = 0x15,
+    Escape = 0x19,
+    Concatenate = 0x1C,
+    Length = 0x1D,
+    Upper = 0x1E,
+    Lower = 0x1F,
+    Trim = 0x20,
+    Chunk = 0x3E,
+    JsonGet = 0x42,
+    TokenCount = 0x4B,
     // Misc.
     NoOp = 0xFF,
 }
@@ -54,7 +115,62 @@ impl OpCode {
         OpCode::ContextPop,
         OpCode::ContextDrop,
         OpCode::MoveContext,
+        OpCode::ContextLength,
+        OpCode::ContextTrim,
+        OpCode::ContextPeek,
+        OpCode::ContextInsert,
+        OpCode::ContextSave,
+        OpCode::ContextLoad,
         OpCode::SubtractImmediate,
+        OpCode::FormatNumber,
+        OpCode::LoadArgument,
+        OpCode::Jump,
+        OpCode::LoadDirectory,
+        OpCode::Escape,
+        OpCode::Checkpoint,
+        OpCode::Input,
+        OpCode::Concatenate,
+        OpCode::Length,
+        OpCode::TokenCount,
+        OpCode::Upper,
+        OpCode::Lower,
+        OpCode::Trim,
+        OpCode::AddImmediate,
+        OpCode::MultiplyImmediate,
+        OpCode::DivideImmediate,
+        OpCode::AddNumber,
+        OpCode::SubtractNumber,
+        OpCode::MultiplyNumber,
+        OpCode::DivideNumber,
+        OpCode::StackPush,
+        OpCode::StackPop,
+        OpCode::StackPeek,
+        OpCode::StoreWord,
+        OpCode::LoadWord,
+        OpCode::WriteFile,
+        OpCode::AppendFile,
+        OpCode::LoadEnvironment,
+        OpCode::LoadPositionalArgument,
+        OpCode::LoadArgumentCount,
+        OpCode::PrintError,
+        OpCode::Embed,
+        OpCode::BranchSimilar,
+        OpCode::SetModel,
+        OpCode::SetEmbeddingModel,
+        OpCode::SetTemperature,
+        OpCode::SetTopP,
+        OpCode::SetMaxTokens,
+        OpCode::SetSystemPrompt,
+        OpCode::VectorPut,
+        OpCode::VectorQuery,
+        OpCode::VectorClear,
+        OpCode::Chunk,
+        OpCode::Classify,
+        OpCode::Score,
+        OpCode::Json,
+        OpCode::JsonGet,
+        OpCode::Ask,
+        OpCode::Translate,
         OpCode::NoOp,
     ];
 
@@ -80,6 +196,60 @@ impl TryFrom<u32> for OpCode {
     }
 }
 
+// Kind of value in an LI instruction's immediate word, stored in its last word.
+// Integer is 0 so that byte code built before floats existed decodes unchanged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImmediateType {
+    Integer = 0x00,
+    Float = 0x01,
+}
+
+impl TryFrom<u32> for ImmediateType {
+    type Error = String;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(ImmediateType::Integer),
+            0x01 => Ok(ImmediateType::Float),
+            _ => Err(format!("Unknown immediate type value: 0x{:02X}", value)),
+        }
+    }
+}
+
+// How SW and LW read their address word, stored in their last word. EXIT reads its status word
+// the same way, from its third word.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AddressMode {
+    // The address word is the address.
+    Immediate = 0x00,
+    // The address word is a register number, and that register holds the address.
+    Register = 0x01,
+}
+
+impl TryFrom<u32> for AddressMode {
+    type Error = String;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(AddressMode::Immediate),
+            0x01 => Ok(AddressMode::Register),
+            _ => Err(format!("Unknown address mode value: 0x{:02X}", value)),
+        }
+    }
+}
+
+impl From<AddressMode> for u32 {
+    fn from(address_mode: AddressMode) -> u32 {
+        address_mode as u32
+    }
+}
+
+impl From<ImmediateType> for u32 {
+    fn from(immediate_type: ImmediateType) -> u32 {
+        immediate_type as u32
+    }
+}
+
 impl From<OpCode> for u32 {
     fn from(op: OpCode) -> u32 {
         op as u32
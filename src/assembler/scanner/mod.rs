@@ -2,25 +2,34 @@ use crate::assembler::scanner::token::{Token, TokenType};
 
 pub mod token;
 
+// Token starts and ends are byte offsets into the source, so the lexeme is a slice of it. Lines
+// and columns count characters from 1 and give where a token starts.
 pub struct Scanner {
     source: String,
+    file: usize,
     start: usize,
     current: usize,
     line: usize,
+    // Characters consumed on the current line.
     column: usize,
+    start_line: usize,
+    start_column: usize,
     source_len: usize,
 }
 
 impl Scanner {
-    pub fn new(source: String) -> Self {
+    pub fn new(source: String, file: usize) -> Self {
         let source_len = source.len();
 
         Scanner {
             source,
+            file,
             current: 0,
             start: 0,
             line: 1,
             column: 0,
+            start_line: 1,
+            start_column: 1,
             source_len,
         }
     }
@@ -37,28 +46,19 @@ impl Scanner {
         self.current >= self.source_len
     }
 
+    // Advancing at the end of the source is a no-op so that tokens ending the file are safe to consume.
     fn advance(&mut self) {
-        if self.current >= self.source_len {
-            panic!(
-                "Tried to advance past end of source. Source length: {}, current: {}",
-                self.source_len, self.current
-            )
+        if self.is_at_end() {
+            return;
         }
 
         self.current += self.peek().len_utf8();
         self.column += 1;
     }
 
+    // Returns '\0' at the end of the source.
     fn peek(&self) -> char {
-        self.source[self.current..]
-            .chars()
-            .next()
-            .unwrap_or_else(|| {
-                panic!(
-                    "Tried to peek past end of source. Source length: {}, current: {}",
-                    self.source_len, self.current
-                )
-            })
+        self.source[self.current..].chars().next().unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
@@ -70,8 +70,9 @@ impl Scanner {
             token_type,
             self.start,
             self.current,
-            self.line,
-            self.column,
+            self.start_line,
+            self.start_column,
+            self.file,
             None,
         )
     }
@@ -81,8 +82,9 @@ impl Scanner {
             TokenType::Error,
             self.start,
             self.current,
-            self.line,
-            self.column,
+            self.start_line,
+            self.start_column,
+            self.file,
             Some(message.to_string()),
         )
     }
@@ -94,10 +96,10 @@ impl Scanner {
                     self.advance();
                 }
                 '\n' => {
+                    self.advance();
+
                     self.line += 1;
                     self.column = 0;
-
-                    self.advance();
                 }
                 ';' => {
                     while !self.is_at_end() && self.peek() != '\n' {
@@ -109,13 +111,9 @@ impl Scanner {
         }
     }
 
+    // The ':' has already been consumed as part of the identifier.
     fn label(&mut self) -> Token {
-        let token = self.make_token(TokenType::Label);
-
-        // Consume the ':'.
-        self.advance();
-
-        token
+        self.make_token(TokenType::Label)
     }
 
     fn identifier(&mut self) -> Token {
@@ -134,6 +132,7 @@ impl Scanner {
 
         match TokenType::try_from(identifier.to_lowercase().as_str()) {
             Ok(token_type) => self.make_token(token_type),
+            Err(_) if identifier.starts_with('.') => self.make_error("Unknown directive."),
             Err(_) => self.make_token(TokenType::Identifier),
         }
     }
@@ -181,8 +180,12 @@ impl Scanner {
             }
 
             if self.peek() == '\n' {
+                self.advance();
+
                 self.line += 1;
                 self.column = 0;
+
+                continue;
             }
 
             self.advance();
@@ -197,10 +200,31 @@ impl Scanner {
         }
     }
 
+    fn character(&mut self) -> Token {
+        while !self.is_at_end() && self.peek() != '\'' && self.peek() != '\n' {
+            // Skip the escaped character so that '\'' does not end the literal.
+            if self.peek() == '\\' && self.peek_next() != '\n' {
+                self.advance();
+            }
+
+            self.advance();
+        }
+
+        if self.is_at_end() || self.peek() == '\n' {
+            self.make_error("Unterminated character literal.")
+        } else {
+            // Consume the closing quote.
+            self.advance();
+            self.make_token(TokenType::Character)
+        }
+    }
+
     pub fn scan_token(&mut self) -> Token {
         self.skip_whitespace();
 
         self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.column + 1;
 
         if self.is_at_end() {
             return self.make_token(TokenType::Eof);
@@ -220,8 +244,129 @@ impl Scanner {
         match ch {
             // Single-character tokens.
             ',' => self.make_token(TokenType::Comma),
+            '+' => self.make_token(TokenType::Plus),
+            '-' => self.make_token(TokenType::Minus),
+            '*' => self.make_token(TokenType::Star),
+            '/' => self.make_token(TokenType::Slash),
+            '(' => self.make_token(TokenType::LeftParen),
+            ')' => self.make_token(TokenType::RightParen),
+            // Directives.
+            '.' if Self::is_alpha(self.peek()) => self.identifier(),
             '"' => self.string(),
+            '\'' => self.character(),
             _ => self.make_error("Unexpected character"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Scans the whole source, returning each token's type and text up to and including Eof.
+    fn scan(source: &str) -> Vec<(TokenType, String)> {
+        let mut scanner = Scanner::new(source.to_string(), 0);
+        let mut tokens = Vec::new();
+
+        loop {
+            let token = scanner.scan_token();
+            let text = source[token.start()..token.end()].to_string();
+            let token_type = token.token_type().clone();
+            tokens.push((token_type.clone(), text));
+
+            if token_type == TokenType::Eof {
+                return tokens;
+            }
+
+            assert!(tokens.len() <= source.len() + 1, "no Eof in {:?}", tokens);
+        }
+    }
+
+    fn types(source: &str) -> Vec<TokenType> {
+        scan(source)
+            .into_iter()
+            .map(|(token_type, _)| token_type)
+            .collect()
+    }
+
+    #[test]
+    fn a_number_at_the_end_of_the_source() {
+        assert_eq!(
+            scan("LI X1, 42"),
+            [
+                (TokenType::LoadImmediate, "LI".to_string()),
+                (TokenType::Identifier, "X1".to_string()),
+                (TokenType::Comma, ",".to_string()),
+                (TokenType::Number, "42".to_string()),
+                (TokenType::Eof, String::new()),
+            ]
+        );
+        assert_eq!(scan("1.5")[0], (TokenType::Number, "1.5".to_string()));
+    }
+
+    #[test]
+    fn a_number_with_a_bare_dot_at_the_end_of_the_source() {
+        // The dot is not a fractional part, and does not start a directive.
+        let tokens = scan("LI X1, 5.");
+        assert_eq!(tokens[3], (TokenType::Number, "5".to_string()));
+        assert_eq!(tokens[4], (TokenType::Error, ".".to_string()));
+        assert_eq!(tokens[5].0, TokenType::Eof);
+    }
+
+    #[test]
+    fn an_identifier_at_the_end_of_the_source() {
+        assert_eq!(
+            scan("JMP loop"),
+            [
+                (TokenType::Jump, "JMP".to_string()),
+                (TokenType::Identifier, "loop".to_string()),
+                (TokenType::Eof, String::new()),
+            ]
+        );
+        assert_eq!(scan("done:")[0], (TokenType::Label, "done:".to_string()));
+    }
+
+    #[test]
+    fn a_register_at_the_end_of_the_source() {
+        assert_eq!(
+            types("PLN X12"),
+            [TokenType::PrintLine, TokenType::Identifier, TokenType::Eof]
+        );
+    }
+
+    #[test]
+    fn a_comma_at_the_end_of_the_source() {
+        assert_eq!(
+            types("MV X1,"),
+            [
+                TokenType::Move,
+                TokenType::Identifier,
+                TokenType::Comma,
+                TokenType::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn an_unterminated_comment_at_the_end_of_the_source() {
+        // Comments run to the end of the line, so one without a newline ends at the end of the source.
+        assert_eq!(types("EXIT ; done"), [TokenType::Exit, TokenType::Eof]);
+        assert_eq!(types(";"), [TokenType::Eof]);
+        // There are no block comments: '/*' is a slash and a star.
+        assert_eq!(
+            types("/*"),
+            [TokenType::Slash, TokenType::Star, TokenType::Eof]
+        );
+    }
+
+    #[test]
+    fn unterminated_literals_at_the_end_of_the_source() {
+        assert_eq!(
+            scan("LS X1, \"open")[3],
+            (TokenType::Error, "\"open".to_string())
+        );
+        assert_eq!(scan("LI X1, 'a")[3].0, TokenType::Error);
+        assert_eq!(scan("LI X1, '\\")[3].0, TokenType::Error);
+        assert_eq!(types("."), [TokenType::Error, TokenType::Eof]);
+    }
+}
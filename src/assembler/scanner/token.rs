@@ -2,14 +2,29 @@
 pub enum TokenType {
     // Single-character.
     Comma,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    LeftParen,
+    RightParen,
     // Literals.
     Identifier,
     String,
     Number,
+    Character,
     // Data movement keywords.
     LoadString,
+    Format,
     LoadImmediate,
     LoadContent,
+    LoadArgument,
+    LoadPositionalArgument,
+    LoadArgumentCount,
+    LoadDirectory,
+    LoadEnvironment,
+    StoreWord,
+    LoadWord,
     Move,
     // Control flow keywords.
     BranchEqual,
@@ -17,23 +32,89 @@ pub enum TokenType {
     BranchLess,
     BranchGreaterEqual,
     BranchGreater,
+    Jump,
     Exit,
+    Checkpoint,
     // I/O keywords.
     Print,
     PrintLine,
+    PrintError,
     PrintContext,
+    Input,
+    WriteFile,
+    AppendFile,
     // Generative operations keywords.
     Inference,
+    Ask,
+    Translate,
     // Guardrails operations keywords.
     Evaluate,
     Similarity,
+    Embed,
+    BranchSimilar,
+    Classify,
+    Score,
+    Json,
+    // Model selection keywords.
+    SetModel,
+    SetEmbeddingModel,
+    // Sampling keywords.
+    SetTemperature,
+    SetTopP,
+    SetMaxTokens,
+    // System prompt keywords.
+    SetSystemPrompt,
+    // Vector store keywords.
+    VectorPut,
+    VectorQuery,
+    VectorClear,
     // Context operations keywords.
     ContextPush,
+    ContextPushUser,
+    ContextPushAssistant,
+    ContextPushSystem,
     ContextPop,
     ContextDrop,
     MoveContext,
+    ContextLength,
+    ContextTrim,
+    ContextPeek,
+    ContextInsert,
+    ContextSave,
+    ContextLoad,
+    // Stack operations keywords.
+    StackPush,
+    StackPop,
+    StackPeek,
     // Arithmetic operations keywords.
     SubtractImmediate,
+    AddImmediate,
+    MultiplyImmediate,
+    DivideImmediate,
+    AddNumber,
+    SubtractNumber,
+    MultiplyNumber,
+    DivideNumber,
+    // String operations keywords.
+    FormatNumber,
+    Escape,
+    Concatenate,
+    Length,
+    TokenCount,
+    Upper,
+    Lower,
+    Trim,
+    Chunk,
+    JsonGet,
+    // Directives.
+    Const,
+    Equ,
+    Macro,
+    EndMacro,
+    Include,
+    Data,
+    Text,
+    DataString,
     // Misc keywords.
     Label,
     Eof,
@@ -47,32 +128,106 @@ impl TryFrom<&str> for TokenType {
         match value {
             // Data movement.
             "ls" => Ok(TokenType::LoadString),
+            "fmt" => Ok(TokenType::Format),
             "lc" => Ok(TokenType::LoadContent),
             "li" => Ok(TokenType::LoadImmediate),
+            "argn" => Ok(TokenType::LoadArgument),
+            "arg" => Ok(TokenType::LoadPositionalArgument),
+            "argc" => Ok(TokenType::LoadArgumentCount),
+            "ld" => Ok(TokenType::LoadDirectory),
+            "le" => Ok(TokenType::LoadEnvironment),
+            "sw" => Ok(TokenType::StoreWord),
+            "lw" => Ok(TokenType::LoadWord),
             "mv" => Ok(TokenType::Move),
             // Control flow.
             "beq" => Ok(TokenType::BranchEqual),
             "ble" => Ok(TokenType::BranchLessEqual),
             "blt" => Ok(TokenType::BranchLess),
             "bge" => Ok(TokenType::BranchGreaterEqual),
+            "jmp" => Ok(TokenType::Jump),
             "bgt" => Ok(TokenType::BranchGreater),
             "exit" => Ok(TokenType::Exit),
+            "ckpt" => Ok(TokenType::Checkpoint),
             // I/O.
             "put" => Ok(TokenType::Print),
             "pln" => Ok(TokenType::PrintLine),
+            "err" => Ok(TokenType::PrintError),
             "pcx" => Ok(TokenType::PrintContext),
+            "in" => Ok(TokenType::Input),
+            "wf" => Ok(TokenType::WriteFile),
+            "af" => Ok(TokenType::AppendFile),
             // Generative operations.
             "inf" => Ok(TokenType::Inference),
+            "ask" => Ok(TokenType::Ask),
+            "trl" => Ok(TokenType::Translate),
             // Guardrails operations.
             "eval" => Ok(TokenType::Evaluate),
             "sim" => Ok(TokenType::Similarity),
+            "emb" => Ok(TokenType::Embed),
+            "bsim" => Ok(TokenType::BranchSimilar),
+            "cls" => Ok(TokenType::Classify),
+            "score" => Ok(TokenType::Score),
+            "json" => Ok(TokenType::Json),
+            // Model selection.
+            "setmodel" => Ok(TokenType::SetModel),
+            "setemb" => Ok(TokenType::SetEmbeddingModel),
+            // Sampling.
+            "settemp" => Ok(TokenType::SetTemperature),
+            "settopp" => Ok(TokenType::SetTopP),
+            "setmaxtok" => Ok(TokenType::SetMaxTokens),
+            // System prompt.
+            "setsys" => Ok(TokenType::SetSystemPrompt),
+            // Vector store.
+            "vput" => Ok(TokenType::VectorPut),
+            "vquery" => Ok(TokenType::VectorQuery),
+            "vclr" => Ok(TokenType::VectorClear),
             // Context operations.
             "psh" => Ok(TokenType::ContextPush),
+            "pshu" => Ok(TokenType::ContextPushUser),
+            "psha" => Ok(TokenType::ContextPushAssistant),
+            "pshs" => Ok(TokenType::ContextPushSystem),
             "pop" => Ok(TokenType::ContextPop),
             "drp" => Ok(TokenType::ContextDrop),
             "mvc" => Ok(TokenType::MoveContext),
-            // Misc operations.
+            "ctxlen" => Ok(TokenType::ContextLength),
+            "ctxtrim" => Ok(TokenType::ContextTrim),
+            "ctxpeek" => Ok(TokenType::ContextPeek),
+            "ctxins" => Ok(TokenType::ContextInsert),
+            "ctxsave" => Ok(TokenType::ContextSave),
+            "ctxload" => Ok(TokenType::ContextLoad),
+            // Stack operations.
+            "spush" => Ok(TokenType::StackPush),
+            "spop" => Ok(TokenType::StackPop),
+            "speek" => Ok(TokenType::StackPeek),
+            // Arithmetic operations.
             "subi" => Ok(TokenType::SubtractImmediate),
+            "addi" | "inc" => Ok(TokenType::AddImmediate),
+            "muli" => Ok(TokenType::MultiplyImmediate),
+            "divi" => Ok(TokenType::DivideImmediate),
+            "addn" => Ok(TokenType::AddNumber),
+            "subn" => Ok(TokenType::SubtractNumber),
+            "muln" => Ok(TokenType::MultiplyNumber),
+            "divn" => Ok(TokenType::DivideNumber),
+            // String operations.
+            "fmtn" => Ok(TokenType::FormatNumber),
+            "esc" => Ok(TokenType::Escape),
+            "cat" => Ok(TokenType::Concatenate),
+            "len" => Ok(TokenType::Length),
+            "tok" => Ok(TokenType::TokenCount),
+            "upper" => Ok(TokenType::Upper),
+            "lower" => Ok(TokenType::Lower),
+            "trim" => Ok(TokenType::Trim),
+            "chunk" => Ok(TokenType::Chunk),
+            "jget" => Ok(TokenType::JsonGet),
+            // Directives.
+            ".const" => Ok(TokenType::Const),
+            ".equ" => Ok(TokenType::Equ),
+            ".macro" => Ok(TokenType::Macro),
+            ".endm" => Ok(TokenType::EndMacro),
+            ".include" => Ok(TokenType::Include),
+            ".data" => Ok(TokenType::Data),
+            ".text" => Ok(TokenType::Text),
+            ".string" => Ok(TokenType::DataString),
             _ => Err("String does not correspond to any known token type.".to_string()),
         }
     }
@@ -85,7 +240,11 @@ pub struct Token {
     end: usize,
     line: usize,
     column: usize,
+    // Index of the source file the token was scanned from.
+    file: usize,
     error: Option<String>,
+    // Replaces the source text of tokens renamed during macro expansion.
+    lexeme: Option<String>,
 }
 
 impl Token {
@@ -95,6 +254,7 @@ impl Token {
         end: usize,
         line: usize,
         column: usize,
+        file: usize,
         error: Option<String>,
     ) -> Token {
         Token {
@@ -103,10 +263,23 @@ impl Token {
             end,
             line,
             column,
+            file,
             error,
+            lexeme: None,
         }
     }
 
+    pub fn with_lexeme(&self, lexeme: String) -> Token {
+        Token {
+            lexeme: Some(lexeme),
+            ..self.clone()
+        }
+    }
+
+    pub fn lexeme(&self) -> Option<&str> {
+        self.lexeme.as_deref()
+    }
+
     pub fn token_type(&self) -> &TokenType {
         &self.token_type
     }
@@ -127,6 +300,10 @@ impl Token {
         self.column
     }
 
+    pub fn file(&self) -> usize {
+        self.file
+    }
+
     pub fn error(&self) -> Option<String> {
         self.error.clone()
     }
@@ -0,0 +1,233 @@
+use std::{
+    fs::{OpenOptions, metadata, remove_file, rename},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+// Distinguishes temporary files created by different threads of one process.
+static NEXT_TEMPORARY: AtomicUsize = AtomicUsize::new(0);
+
+// How often and how long lock() waits for another holder before giving up.
+const LOCK_ATTEMPTS: u32 = 200;
+const LOCK_RETRY_DELAY: Duration = Duration::from_millis(10);
+// A lock file older than this was left behind by a process that died holding it.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(60);
+
+// A hidden file next to the target, named after it.
+fn sibling(path: &Path, suffix: &str) -> io::Result<PathBuf> {
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Path has no file name."))?
+        .to_string_lossy();
+
+    Ok(directory.join(format!(".{}{}", name, suffix)))
+}
+
+// A new file next to the target, so that the rename stays on one file system.
+fn create_temporary(path: &Path) -> io::Result<(PathBuf, std::fs::File)> {
+    loop {
+        let temporary = sibling(
+            path,
+            &format!(
+                ".{}.{}.tmp",
+                process::id(),
+                NEXT_TEMPORARY.fetch_add(1, Ordering::Relaxed)
+            ),
+        )?;
+
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temporary)
+        {
+            Ok(file) => return Ok((temporary, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Like std::fs::write, but readers only ever see the old or the new contents in full, even when several
+// processes write the same path at once. The last rename wins.
+pub fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let path = path.as_ref();
+    let (temporary, mut file) = create_temporary(path)?;
+
+    let result = file
+        .write_all(contents.as_ref())
+        .and_then(|_| file.sync_all())
+        .and_then(|_| rename(&temporary, path));
+
+    if result.is_err() {
+        let _ = remove_file(&temporary);
+    }
+
+    result
+}
+
+// Held while a file is changed, so that writers in other threads and processes take turns. The lock
+// file is removed when this is dropped.
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = remove_file(&self.path);
+    }
+}
+
+fn is_stale(lock: &Path) -> bool {
+    metadata(lock)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age > STALE_LOCK_AGE)
+}
+
+// Takes the lock on path by creating a lock file next to it, which only one holder can create at a
+// time. Returns None when another holder keeps the lock for the whole wait, for the caller to decide
+// whether to skip its change or fail.
+pub fn lock(path: impl AsRef<Path>) -> io::Result<Option<Lock>> {
+    let lock = sibling(path.as_ref(), ".lock")?;
+
+    for _ in 0..LOCK_ATTEMPTS {
+        match OpenOptions::new().write(true).create_new(true).open(&lock) {
+            Ok(_) => return Ok(Some(Lock { path: lock })),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                if is_stale(&lock) {
+                    let _ = remove_file(&lock);
+                } else {
+                    thread::sleep(LOCK_RETRY_DELAY);
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(None)
+}
+
+// Writes path like write, holding its lock. Returns false without writing when the lock could not be
+// taken.
+pub fn write_locked(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<bool> {
+    let path = path.as_ref();
+
+    match lock(path)? {
+        Some(_lock) => write(path, contents).map(|()| true),
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs, sync::Arc};
+
+    use super::*;
+    use crate::{Assembler, Processor};
+
+    fn scratch(name: &str) -> PathBuf {
+        let directory = temp_dir().join(format!("lpu-atomic-{}-{}", name, process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn concurrent_writers_leave_a_complete_program() {
+        let directory = scratch("build");
+        let path = Arc::new(directory.join("prog.lpu"));
+
+        let programs = [
+            "LS X1, \"a\"\nPLN X1\nEXIT\n",
+            "LI X1, 1\nLOOP:\nADDI X1, 1\nJMP LOOP\n",
+        ]
+        .map(|source| {
+            Assembler::new(source.to_string(), "prog.aasm")
+                .assemble()
+                .unwrap()
+        });
+
+        let writers = programs
+            .iter()
+            .cloned()
+            .map(|byte_code| {
+                let path = Arc::clone(&path);
+
+                thread::spawn(move || {
+                    for _ in 0..50 {
+                        write(path.as_ref(), &byte_code).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for _ in 0..100 {
+            if let Ok(bytes) = fs::read(path.as_ref()) {
+                assert!(programs.contains(&bytes));
+            }
+        }
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let bytes = fs::read(path.as_ref()).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(Processor::verify(&bytes).is_ok());
+        assert!(programs.contains(&bytes));
+    }
+
+    #[test]
+    fn the_lock_lets_one_writer_at_a_time_change_a_file() {
+        let directory = scratch("lock");
+        let path = Arc::new(directory.join("count.txt"));
+        write(path.as_ref(), "0").unwrap();
+
+        let writers = (0..4)
+            .map(|_| {
+                let path = Arc::clone(&path);
+
+                thread::spawn(move || {
+                    for _ in 0..25 {
+                        let _lock = lock(path.as_ref()).unwrap().unwrap();
+                        let count: u32 =
+                            fs::read_to_string(path.as_ref()).unwrap().parse().unwrap();
+                        write(path.as_ref(), (count + 1).to_string()).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let count = fs::read_to_string(path.as_ref()).unwrap();
+        let lock_left = sibling(&path, ".lock").unwrap().exists();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(count, "100");
+        assert!(!lock_left);
+    }
+
+    #[test]
+    fn a_held_lock_makes_locked_writes_skip() {
+        let directory = scratch("held");
+        let path = directory.join("cache.json");
+
+        let held = lock(&path).unwrap().unwrap();
+        let written = write_locked(&path, "{}").unwrap();
+        drop(held);
+        let written_after = write_locked(&path, "{}").unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(!written);
+        assert!(written_after);
+    }
+}
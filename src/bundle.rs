@@ -0,0 +1,240 @@
+use crate::exception::{BaseException, Exception};
+
+const MAGIC: &[u8; 4] = b"LPUB";
+// Version 2 length-prefixes key-value entries, so values may hold newlines.
+const VERSION: u32 = 2;
+
+pub const PROGRAM_ENTRY: &str = "program";
+pub const CONFIG_ENTRY: &str = "config";
+pub const METADATA_ENTRY: &str = "metadata";
+// The prompt template files, keyed by path.
+pub const PROMPTS_ENTRY: &str = "prompts";
+// The model config file, keyed by path.
+pub const MODEL_CONFIG_ENTRY: &str = "model_config";
+
+pub type KeyValues = Vec<(String, String)>;
+
+// A bundle is a flat, length-prefixed container:
+// magic, version, entry count, then for each entry its name length, name, data length, and data.
+pub struct Bundle {
+    entries: Vec<(String, Vec<u8>)>,
+}
+
+impl Bundle {
+    pub fn new() -> Self {
+        Bundle {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn is_bundle(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    pub fn add(&mut self, name: &str, data: Vec<u8>) {
+        self.entries.push((name.to_string(), data));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.entries
+            .iter()
+            .find(|(entry_name, _)| entry_name == name)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    pub fn entries(&self) -> &[(String, Vec<u8>)] {
+        &self.entries
+    }
+
+    // A key-value entry is a sequence of key length, key, value length, and value, so values may
+    // hold any text, including newlines.
+    pub fn encode_key_values(pairs: &[(String, String)]) -> Result<Vec<u8>, Exception> {
+        let mut bytes = Vec::new();
+
+        for (key, value) in pairs {
+            bytes.extend(Self::length(key.len(), "key length")?);
+            bytes.extend(key.as_bytes());
+            bytes.extend(Self::length(value.len(), "value length")?);
+            bytes.extend(value.as_bytes());
+        }
+
+        Ok(bytes)
+    }
+
+    pub fn key_values(&self, name: &str) -> Result<KeyValues, Exception> {
+        let Some(data) = self.get(name) else {
+            return Ok(Vec::new());
+        };
+
+        let mut reader = Reader { data, offset: 0 };
+        let mut pairs = Vec::new();
+
+        while reader.offset < data.len() {
+            let key_length = reader.word("key length")? as usize;
+            let key = reader.text(key_length, "key", name)?;
+            let value_length = reader.word("value length")? as usize;
+            let value = reader.text(value_length, "value", name)?;
+
+            pairs.push((key, value));
+        }
+
+        Ok(pairs)
+    }
+
+    fn length(value: usize, what: &str) -> Result<[u8; 4], Exception> {
+        u32::try_from(value).map(u32::to_be_bytes).map_err(|_| {
+            Exception::Bundle(BaseException::new(
+                format!(
+                    "Failed to convert {} to u32. Length exceeds {}. Found length: {}.",
+                    what,
+                    u32::MAX,
+                    value
+                ),
+                None,
+            ))
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Exception> {
+        let mut bytes = Vec::new();
+        bytes.extend(MAGIC);
+        bytes.extend(VERSION.to_be_bytes());
+        bytes.extend(Self::length(self.entries.len(), "entry count")?);
+
+        for (name, data) in &self.entries {
+            bytes.extend(Self::length(name.len(), "entry name length")?);
+            bytes.extend(name.as_bytes());
+            bytes.extend(Self::length(data.len(), "entry data length")?);
+            bytes.extend(data);
+        }
+
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Exception> {
+        if !Self::is_bundle(data) {
+            return Err(Exception::Bundle(BaseException::new(
+                "Invalid bundle: missing 'LPUB' magic number.".to_string(),
+                None,
+            )));
+        }
+
+        let mut reader = Reader {
+            data,
+            offset: MAGIC.len(),
+        };
+
+        let version = reader.word("version")?;
+        if version != VERSION {
+            return Err(Exception::Bundle(BaseException::new(
+                format!(
+                    "Unsupported bundle version {}. Expected version {}.",
+                    version, VERSION
+                ),
+                None,
+            )));
+        }
+
+        let count = reader.word("entry count")?;
+        let mut bundle = Bundle::new();
+
+        for _ in 0..count {
+            let name_length = reader.word("entry name length")? as usize;
+            let name = String::from_utf8(reader.bytes(name_length, "entry name")?.to_vec())
+                .map_err(|e| {
+                    Exception::Bundle(BaseException::caused_by(
+                        "Bundle entry name is not valid UTF-8.",
+                        e.to_string(),
+                    ))
+                })?;
+
+            let data_length = reader.word("entry data length")? as usize;
+            let data = reader.bytes(data_length, "entry data")?.to_vec();
+
+            bundle.add(&name, data);
+        }
+
+        Ok(bundle)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, length: usize, what: &str) -> Result<&'a [u8], Exception> {
+        let bytes = self
+            .offset
+            .checked_add(length)
+            .and_then(|end| self.data.get(self.offset..end))
+            .ok_or_else(|| {
+                Exception::Bundle(BaseException::new(
+                    format!(
+                        "Truncated bundle: failed to read {} ({} bytes) at offset {}.",
+                        what, length, self.offset
+                    ),
+                    None,
+                ))
+            })?;
+
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    fn text(&mut self, length: usize, what: &str, entry: &str) -> Result<String, Exception> {
+        let bytes = self.bytes(length, what)?;
+
+        String::from_utf8(bytes.to_vec()).map_err(|e| {
+            Exception::Bundle(BaseException::caused_by(
+                format!("A {} in bundle entry '{}' is not valid UTF-8.", what, entry),
+                e.to_string(),
+            ))
+        })
+    }
+
+    fn word(&mut self, what: &str) -> Result<u32, Exception> {
+        let bytes = self.bytes(4, what)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_values_round_trip_with_newlines_and_equals_signs() {
+        let config = vec![
+            (
+                "SYSTEM_PROMPT".to_string(),
+                "line one\nline two\n\nkey=value".to_string(),
+            ),
+            ("TEXT_MODEL".to_string(), String::new()),
+        ];
+
+        let mut bundle = Bundle::new();
+        bundle.add(PROGRAM_ENTRY, vec![1, 2, 3, 4]);
+        bundle.add(CONFIG_ENTRY, Bundle::encode_key_values(&config).unwrap());
+
+        let bundle = Bundle::from_bytes(&bundle.to_bytes().unwrap()).unwrap();
+
+        assert_eq!(bundle.get(PROGRAM_ENTRY), Some([1, 2, 3, 4].as_slice()));
+        assert_eq!(bundle.key_values(CONFIG_ENTRY).unwrap(), config);
+        assert!(bundle.key_values(METADATA_ENTRY).unwrap().is_empty());
+    }
+
+    #[test]
+    fn a_truncated_key_value_entry_is_an_error() {
+        let pairs = [("KEY".to_string(), "value".to_string())];
+        let mut data = Bundle::encode_key_values(&pairs).unwrap();
+        data.pop();
+
+        let mut bundle = Bundle::new();
+        bundle.add(CONFIG_ENTRY, data);
+
+        let error = format!("{:?}", bundle.key_values(CONFIG_ENTRY).unwrap_err());
+        assert!(error.contains("Truncated bundle"), "{}", error);
+    }
+}
@@ -1,3 +1,16 @@
+use std::time::Duration;
+
+use crate::constants::{
+    CLASSIFY_RETRY_PROMPT, DEFAULT_ASK_PROMPT, DEFAULT_CLASSIFY_PROMPT, DEFAULT_DETERMINISTIC_SEED,
+    DEFAULT_EVAL_PROMPT, DEFAULT_JSON_PROMPT, DEFAULT_LLM_BASE_URL, DEFAULT_LLM_CACHE_SIZE,
+    DEFAULT_LLM_RETRY_ATTEMPTS, DEFAULT_LLM_RETRY_DELAY_MS, DEFAULT_LLM_RETRY_JITTER_MS,
+    DEFAULT_LLM_TIMEOUT_SECONDS, DEFAULT_MAX_CONCURRENT_REQUESTS, DEFAULT_MAX_DATA_WORDS,
+    DEFAULT_MAX_REGISTER_BYTES, DEFAULT_SCORE_PROMPT, DEFAULT_SIMILARITY_PROMPT,
+    DEFAULT_SYSTEM_PROMPT, DEFAULT_TRANSLATE_PROMPT, DETERMINISTIC_PRESET, JSON_REPAIR_PROMPT,
+    SCORE_RETRY_PROMPT,
+};
+use crate::proxy::ProxySettings;
+
 #[derive(Debug, Clone, Default)]
 pub struct TextModelOverrides {
     pub stream: Option<bool>,
@@ -13,6 +26,7 @@ pub struct TextModelOverrides {
     pub xtc_threshold: Option<f32>,
     pub typ_p: Option<f32>,
     pub max_tokens: Option<i32>,
+    pub samplers: Option<Vec<String>>,
     pub repeat_last_n: Option<u32>,
     pub repeat_penalty: Option<f32>,
     pub presence_penalty: Option<f32>,
@@ -22,14 +36,377 @@ pub struct TextModelOverrides {
     pub dry_allowed_length: Option<u32>,
     pub dry_penalty_last_n: Option<i32>,
     pub timings_per_token: Option<bool>,
+    pub seed: Option<u32>,
+    pub stop: Option<Vec<String>>,
+}
+
+impl TextModelOverrides {
+    // The settings a named preset fills in, or None if there is no preset of that name. The
+    // deterministic preset always picks the most likely token and seeds the server's sampler.
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            DETERMINISTIC_PRESET => Some(TextModelOverrides {
+                temperature: Some(0.0),
+                top_k: Some(1),
+                seed: Some(DEFAULT_DETERMINISTIC_SEED),
+                ..TextModelOverrides::default()
+            }),
+            _ => None,
+        }
+    }
+
+    // Each setting from self, or from fallback where self leaves it unset.
+    pub fn or(self, fallback: TextModelOverrides) -> Self {
+        TextModelOverrides {
+            stream: self.stream.or(fallback.stream),
+            return_progress: self.return_progress.or(fallback.return_progress),
+            reasoning_format: self.reasoning_format.or(fallback.reasoning_format),
+            temperature: self.temperature.or(fallback.temperature),
+            dynatemp_range: self.dynatemp_range.or(fallback.dynatemp_range),
+            dynatemp_exponent: self.dynatemp_exponent.or(fallback.dynatemp_exponent),
+            top_k: self.top_k.or(fallback.top_k),
+            top_p: self.top_p.or(fallback.top_p),
+            min_p: self.min_p.or(fallback.min_p),
+            xtc_probability: self.xtc_probability.or(fallback.xtc_probability),
+            xtc_threshold: self.xtc_threshold.or(fallback.xtc_threshold),
+            typ_p: self.typ_p.or(fallback.typ_p),
+            max_tokens: self.max_tokens.or(fallback.max_tokens),
+            samplers: self.samplers.or(fallback.samplers),
+            repeat_last_n: self.repeat_last_n.or(fallback.repeat_last_n),
+            repeat_penalty: self.repeat_penalty.or(fallback.repeat_penalty),
+            presence_penalty: self.presence_penalty.or(fallback.presence_penalty),
+            frequency_penalty: self.frequency_penalty.or(fallback.frequency_penalty),
+            dry_multiplier: self.dry_multiplier.or(fallback.dry_multiplier),
+            dry_base: self.dry_base.or(fallback.dry_base),
+            dry_allowed_length: self.dry_allowed_length.or(fallback.dry_allowed_length),
+            dry_penalty_last_n: self.dry_penalty_last_n.or(fallback.dry_penalty_last_n),
+            timings_per_token: self.timings_per_token.or(fallback.timings_per_token),
+            seed: self.seed.or(fallback.seed),
+            stop: self.stop.or(fallback.stop),
+        }
+    }
+}
+
+// The prompts EVAL and SIM wrap their operands in. {a} and {b} stand for the operands.
+#[derive(Debug, Clone)]
+pub struct PromptTemplates {
+    // {a} is the statement EVAL checks.
+    pub eval: String,
+    // {a} and {b} are the texts SIM compares when the text model estimates their similarity.
+    pub similarity: String,
+    // {a} is the text CLS classifies and {b} the labels it chooses from, one per line.
+    pub classify: String,
+    // {a} is the text SCORE rates and {b} the criteria it is rated against.
+    pub score: String,
+    // {a} is the text JSON extracts fields from and {b} the fields, one per line, or the schema.
+    pub json: String,
+    // {a} is the question ASK answers and {b} the context it answers from.
+    pub ask: String,
+    // {a} is the text TRL translates and {b} the language it translates into.
+    pub translate: String,
+}
+
+impl Default for PromptTemplates {
+    fn default() -> Self {
+        PromptTemplates {
+            eval: DEFAULT_EVAL_PROMPT.to_string(),
+            similarity: DEFAULT_SIMILARITY_PROMPT.to_string(),
+            classify: DEFAULT_CLASSIFY_PROMPT.to_string(),
+            score: DEFAULT_SCORE_PROMPT.to_string(),
+            json: DEFAULT_JSON_PROMPT.to_string(),
+            ask: DEFAULT_ASK_PROMPT.to_string(),
+            translate: DEFAULT_TRANSLATE_PROMPT.to_string(),
+        }
+    }
+}
+
+impl PromptTemplates {
+    pub fn eval(&self, statement: &str) -> String {
+        Self::render(&self.eval, statement, "")
+    }
+
+    pub fn similarity(&self, text_a: &str, text_b: &str) -> String {
+        Self::render(&self.similarity, text_a, text_b)
+    }
+
+    pub fn classify(&self, text: &str, labels: &[&str]) -> String {
+        let labels = labels
+            .iter()
+            .map(|label| format!("- {}", label))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Self::render(&self.classify, text, &labels)
+    }
+
+    // Asked once when the first reply is not one of the labels.
+    pub fn classify_retry(&self, text: &str, labels: &[&str]) -> String {
+        format!("{}{}", self.classify(text, labels), CLASSIFY_RETRY_PROMPT)
+    }
+
+    pub fn score(&self, text: &str, criteria: &str) -> String {
+        Self::render(&self.score, text, criteria)
+    }
+
+    // Asked once when the first reply has no number.
+    pub fn score_retry(&self, text: &str, criteria: &str) -> String {
+        format!("{}{}", self.score(text, criteria), SCORE_RETRY_PROMPT)
+    }
+
+    pub fn ask(&self, question: &str, context: &str) -> String {
+        Self::render(&self.ask, question, context)
+    }
+
+    pub fn translate(&self, text: &str, language: &str) -> String {
+        Self::render(&self.translate, text, language)
+    }
+
+    pub fn json(&self, text: &str, fields: &str) -> String {
+        Self::render(&self.json, text, fields)
+    }
+
+    // Asked once when the first reply is not usable, with the reason and the reply.
+    pub fn json_repair(&self, text: &str, fields: &str, reason: &str, reply: &str) -> String {
+        let repair = Self::render(JSON_REPAIR_PROMPT, reason, reply);
+
+        format!("{}{}", self.json(text, fields), repair)
+    }
+
+    // Replaces {a} and {b} in one pass, so braces inside the operands are left as they are.
+    fn render(template: &str, a: &str, b: &str) -> String {
+        let mut rendered = String::with_capacity(template.len() + a.len() + b.len());
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            let tail = &rest[start..];
+
+            rest = if let Some(after) = tail.strip_prefix("{a}") {
+                rendered.push_str(a);
+                after
+            } else if let Some(after) = tail.strip_prefix("{b}") {
+                rendered.push_str(b);
+                after
+            } else {
+                rendered.push('{');
+                &tail[1..]
+            };
+        }
+
+        rendered.push_str(rest);
+        rendered
+    }
+}
+
+// API the model server speaks. Requests and responses are converted to and from the OpenAI ones
+// for any other API.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LlmProvider {
+    OpenAI,
+    Ollama,
+}
+
+// How SIM and EVAL score similarity: cosine similarity of embeddings, or a score estimated by the text model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SimilarityBackend {
+    Embeddings,
+    Llm,
+}
+
+// What to do when the text model returns an empty response.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmptyResponsePolicy {
+    Error,
+    // Send the request once more, then fail if the response is still empty.
+    Retry,
+    Accept,
+}
+
+// What to do when the text model stops at the token limit, reported as finish_reason "length".
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TruncatedResponsePolicy {
+    Accept,
+    Error,
+    // Send the request once more with twice the token limit, then fail if it is still cut short.
+    Retry,
+}
+
+// What to do when a chat request is counted at more tokens than CONTEXT_TOKEN_BUDGET.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContextBudgetPolicy {
+    Warn,
+    Error,
+}
+
+// How model requests that fail with a transport error, 429 or a 5xx status are retried. The delay
+// doubles after each attempt and a random amount up to jitter is added to it.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    // Total attempts, including the first. 1 disables retries.
+    pub attempts: u32,
+    pub base_delay: Duration,
+    pub jitter: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: DEFAULT_LLM_RETRY_ATTEMPTS,
+            base_delay: Duration::from_millis(DEFAULT_LLM_RETRY_DELAY_MS),
+            jitter: Duration::from_millis(DEFAULT_LLM_RETRY_JITTER_MS),
+        }
+    }
+}
+
+// Whether responses recorded to LLM_CACHE_DIR may be added to, or are the only ones allowed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordingMode {
+    // Replay recorded responses and record the rest.
+    Record,
+    // Replay recorded responses and fail on any other request.
+    ReplayOnly,
 }
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub text_model: String,
     pub embedding_model: String,
+    pub embedding_dimensions: Option<usize>,
+    pub llm_provider: LlmProvider,
+    // Servers the requests are sent to, without a trailing slash. Embeddings go
+    // to the LLM server when no embeddings server is set.
+    pub llm_base_url: String,
+    pub embeddings_base_url: Option<String>,
+    // Sent as a bearer token with every request when set.
+    pub llm_api_key: Option<String>,
+    pub proxy: ProxySettings,
+    // Run without first checking that the model server answers and has the models.
+    pub skip_healthcheck: bool,
+    // Longest a single model request may take, from connecting to reading the whole response.
+    pub llm_timeout_seconds: u64,
+    pub retry_policy: RetryPolicy,
+    // Reuse the response to an identical chat or embedding request instead of sending it again.
+    pub llm_cache: bool,
+    pub llm_cache_size: usize,
+    // Directory model responses are recorded to and replayed from, byte for byte.
+    pub llm_cache_dir: Option<String>,
+    pub llm_recording_mode: RecordingMode,
+    // File the vector store is read from on first use and written back to. None keeps it in memory.
+    pub vector_store_file: Option<String>,
+    pub max_concurrent_requests: usize,
+    pub max_register_bytes: usize,
+    // Number of words SW can store to. Data memory grows up to this as addresses are first written.
+    pub max_data_words: usize,
+    // Most messages, and characters of their content, a context register keeps once INF or EVAL
+    // sends it, not counting a first system message. None is unlimited.
+    pub context_max_messages: Option<usize>,
+    pub context_max_chars: Option<usize>,
+    // Most tokens a chat request should hold, and what to do when one holds more. None is
+    // unlimited.
+    pub context_token_budget: Option<usize>,
+    pub on_context_over_budget: ContextBudgetPolicy,
+    // Whether tokens are counted by the server's tokenizer rather than estimated.
+    pub tokenize_remote: bool,
+    // Limits on the instructions executed and model requests sent by one run. None is unlimited.
+    pub max_instructions: Option<u64>,
+    pub max_llm_calls: Option<usize>,
+    pub similarity_backend: SimilarityBackend,
+    pub on_empty_response: EmptyResponsePolicy,
+    pub on_truncated_response: TruncatedResponsePolicy,
+    // Whether the <think> blocks of reasoning models are removed from responses.
+    pub strip_think_blocks: bool,
+    // System message sent first in every chat request. Empty sends none.
+    pub system_prompt: String,
+    pub prompts: PromptTemplates,
     pub text_model_overrides: TextModelOverrides,
+    pub warn_unused_labels: bool,
     pub debug_build: bool,
     pub debug_run: bool,
     pub debug_chat: bool,
+    // Print a summary of the work done after a run, as DEBUG_RUN also does.
+    pub stats: bool,
+    pub strict_utf8: bool,
+    pub require_fresh: bool,
+    pub checkpoint_dir: Option<String>,
+    pub from_checkpoint: Option<String>,
+    pub arguments: Vec<(String, String)>,
+    // Arguments after the program path that are not flags, read with ARG and counted by ARGC.
+    pub positional_arguments: Vec<String>,
+    // Byte code indexes to pause at before running the instruction there.
+    pub breakpoints: Vec<usize>,
+    // File the JSON lines trace of executed instructions is written to.
+    pub trace_file: Option<String>,
+}
+
+impl Config {
+    // The configuration used when nothing else is set, for embedding the processor without a .env file.
+    pub fn new(text_model: impl Into<String>, embedding_model: impl Into<String>) -> Self {
+        Config {
+            text_model: text_model.into(),
+            embedding_model: embedding_model.into(),
+            embedding_dimensions: None,
+            llm_provider: LlmProvider::OpenAI,
+            llm_base_url: DEFAULT_LLM_BASE_URL.to_string(),
+            embeddings_base_url: None,
+            llm_api_key: None,
+            proxy: ProxySettings::from_env(),
+            skip_healthcheck: false,
+            llm_timeout_seconds: DEFAULT_LLM_TIMEOUT_SECONDS,
+            retry_policy: RetryPolicy::default(),
+            llm_cache: false,
+            llm_cache_size: DEFAULT_LLM_CACHE_SIZE,
+            llm_cache_dir: None,
+            llm_recording_mode: RecordingMode::Record,
+            vector_store_file: None,
+            max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+            max_register_bytes: DEFAULT_MAX_REGISTER_BYTES,
+            max_data_words: DEFAULT_MAX_DATA_WORDS,
+            context_max_messages: None,
+            context_max_chars: None,
+            context_token_budget: None,
+            on_context_over_budget: ContextBudgetPolicy::Warn,
+            tokenize_remote: false,
+            max_instructions: None,
+            max_llm_calls: None,
+            similarity_backend: SimilarityBackend::Embeddings,
+            on_empty_response: EmptyResponsePolicy::Retry,
+            on_truncated_response: TruncatedResponsePolicy::Accept,
+            strip_think_blocks: true,
+            system_prompt: DEFAULT_SYSTEM_PROMPT.to_string(),
+            prompts: PromptTemplates::default(),
+            text_model_overrides: TextModelOverrides::default(),
+            warn_unused_labels: true,
+            debug_build: false,
+            debug_run: false,
+            debug_chat: false,
+            stats: false,
+            strict_utf8: false,
+            require_fresh: false,
+            checkpoint_dir: None,
+            from_checkpoint: None,
+            arguments: Vec::new(),
+            positional_arguments: Vec::new(),
+            breakpoints: Vec::new(),
+            trace_file: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_preset_only_fills_in_unset_settings() {
+        let configured = TextModelOverrides {
+            temperature: Some(0.7),
+            ..TextModelOverrides::default()
+        };
+        let overrides = configured.or(TextModelOverrides::preset(DETERMINISTIC_PRESET).unwrap());
+
+        assert_eq!(overrides.temperature, Some(0.7));
+        assert_eq!(overrides.top_k, Some(1));
+        assert_eq!(overrides.seed, Some(DEFAULT_DETERMINISTIC_SEED));
+        assert!(TextModelOverrides::preset("creative").is_none());
+    }
 }
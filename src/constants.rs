@@ -1,15 +1,168 @@
 pub const BUILD_DIR: &str = "build";
 
-pub const HELP_USAGE: &str = "Usage: build <file_path> | run <file_path>";
+pub const HELP_USAGE: &str = "Usage: build <file_path> | run <file_path> [<argument>...] [--strict-utf8] [--require-fresh] [--seed <n>] [--arg <name>=<value|@file>]... [--checkpoint-dir <dir> [--from-checkpoint <name>]] [--break <index|label>]... [--trace <file_path>] | bundle <file_path> [-o <output_path>] | info <file_path> | disasm <file_path> | check <file_path> | isa <mnemonic> | debug --from-trace <trace_file> <file_path>. Any command accepts --strict-config.";
+
+pub const BUNDLE_EXTENSION: &str = "lpub";
+
+pub const STRICT_UTF8_FLAG: &str = "--strict-utf8";
+pub const ARG_FLAG: &str = "--arg";
+pub const REQUIRE_FRESH_FLAG: &str = "--require-fresh";
+pub const SEED_FLAG: &str = "--seed";
+pub const CHECKPOINT_DIR_FLAG: &str = "--checkpoint-dir";
+pub const FROM_CHECKPOINT_FLAG: &str = "--from-checkpoint";
+pub const STRICT_CONFIG_FLAG: &str = "--strict-config";
+pub const BREAK_FLAG: &str = "--break";
+pub const TRACE_FLAG: &str = "--trace";
+pub const FROM_TRACE_FLAG: &str = "--from-trace";
+// Every later argument is positional, even one that looks like a flag.
+pub const END_OF_FLAGS: &str = "--";
+
+// Flags followed by a value, which is not a positional argument.
+pub const VALUE_FLAGS: &[&str] = &[
+    ARG_FLAG,
+    SEED_FLAG,
+    CHECKPOINT_DIR_FLAG,
+    FROM_CHECKPOINT_FLAG,
+    BREAK_FLAG,
+    TRACE_FLAG,
+];
+
+// Byte code size limits. Segment limits are in 32-bit words, string limits in bytes.
+pub const MAX_TEXT_SEGMENT_WORDS: usize = 1 << 24;
+pub const MAX_DATA_SEGMENT_WORDS: usize = 1 << 26;
+pub const MAX_STRING_BYTES: usize = 1 << 20;
+
+// Byte code starts with the magic word "LPU\0" and its format version, which is raised whenever the layout changes.
+pub const BYTE_CODE_MAGIC: u32 = u32::from_be_bytes(*b"LPU\0");
+pub const BYTE_CODE_VERSION: u32 = 2;
+
+// Marks the optional debug info section written after the data segment.
+pub const DEBUG_INFO_MAGIC: u32 = u32::from_be_bytes(*b"LDBG");
 
 // Model environment variable names.
 pub const TEXT_MODEL_ENV: &str = "TEXT_MODEL";
 pub const EMBEDDING_MODEL_ENV: &str = "EMBEDDING_MODEL";
+pub const EMBEDDING_DIMENSIONS_ENV: &str = "EMBEDDING_DIMENSIONS";
+pub const SIMILARITY_BACKEND_ENV: &str = "SIMILARITY_BACKEND";
+pub const ON_EMPTY_RESPONSE_ENV: &str = "ON_EMPTY_RESPONSE";
+pub const ON_TRUNCATED_RESPONSE_ENV: &str = "ON_TRUNCATED_RESPONSE";
+pub const STRIP_THINK_BLOCKS_ENV: &str = "STRIP_THINK_BLOCKS";
+
+// System prompt environment variable name. Set it empty to send no system message.
+pub const SYSTEM_PROMPT_ENV: &str = "SYSTEM_PROMPT";
+pub const DEFAULT_SYSTEM_PROMPT: &str =
+    "Provide exactly the requested output. Follow structural markers strictly.";
+
+// Prompt template directory environment variable name. Only a directory named here has to exist.
+pub const PROMPTS_DIR_ENV: &str = "PROMPTS_DIR";
+pub const DEFAULT_PROMPTS_DIR: &str = "prompts";
+
+// Built-in prompt templates. {a} and {b} stand for the operands.
+pub const DEFAULT_EVAL_PROMPT: &str =
+    "{a}\nAnswer with exactly one word: YES or NO, TRUE or FALSE.\n\nAnswer only:";
+pub const DEFAULT_SIMILARITY_PROMPT: &str = "Rate how similar in meaning Text A and Text B are on a scale from 0 (unrelated) to 100 (identical meaning). Reply with only the number.\nText A: {a}\nText B: {b}";
+pub const DEFAULT_CLASSIFY_PROMPT: &str = "Classify the text below with exactly one of these labels:\n{b}\nReply with only the label.\n\nText: {a}";
+pub const DEFAULT_SCORE_PROMPT: &str = "Rate how well the text below meets the criteria on a scale from 0 (not at all) to 100 (completely). Reply with only the number.\nCriteria: {b}\nText: {a}";
+pub const DEFAULT_JSON_PROMPT: &str = "Extract these fields from the text below. Reply with only a JSON object that has every field as a key, using nested objects for names joined by '.':\n{b}\n\nText: {a}";
+pub const DEFAULT_ASK_PROMPT: &str = "Answer the question using only the context below. If the context does not contain the answer, reply with exactly UNKNOWN.\n\nContext:\n{b}\n\nQuestion: {a}";
+// What ASK stores when the context does not answer the question.
+pub const ASK_UNKNOWN: &str = "UNKNOWN";
+pub const DEFAULT_TRANSLATE_PROMPT: &str = "Translate the text below into {b}. Reply with only the translation, without notes, explanations or quotes. You may start with one line naming the language of the text, such as \"Source language: German\".\n\nText:\n{a}";
+// The start of the optional first line of a TRL reply that names the language of the text.
+pub const TRANSLATE_SOURCE_PREFIX: &str = "Source language:";
+// Added to the classify prompt when the first reply is not one of the labels.
+pub const CLASSIFY_RETRY_PROMPT: &str = "\n\nYour previous reply was not one of the labels. Reply with exactly one label from the list, spelled as shown, and nothing else.";
+// Added to the score prompt when the first reply has no number.
+pub const SCORE_RETRY_PROMPT: &str =
+    "\n\nYour previous reply had no number. Reply with only a whole number from 0 to 100.";
+// Added to the JSON prompt when the first reply is not usable. {a} is the reason, which follows
+// "because", and {b} the reply.
+pub const JSON_REPAIR_PROMPT: &str = "\n\nYour previous output was invalid because {a}:\n{b}\n\nReply with only the corrected JSON object.";
+
+// Model config file environment variable name. Only a file named here has to exist.
+pub const LPU_CONFIG_ENV: &str = "LPU_CONFIG";
+pub const DEFAULT_LPU_CONFIG: &str = "lpu.toml";
+
+// Model server environment variable names. The embeddings server defaults to the LLM server.
+pub const LLM_BASE_URL_ENV: &str = "LLM_BASE_URL";
+pub const EMBEDDINGS_BASE_URL_ENV: &str = "EMBEDDINGS_BASE_URL";
+pub const LLM_API_KEY_ENV: &str = "LLM_API_KEY";
+pub const DEFAULT_LLM_BASE_URL: &str = "http://127.0.0.1:8080";
+
+// Startup check of the model server environment variable name, and the longest each of its requests
+// may take in seconds.
+pub const SKIP_HEALTHCHECK_ENV: &str = "SKIP_HEALTHCHECK";
+pub const HEALTHCHECK_TIMEOUT_SECONDS: u64 = 10;
+
+// API the model server speaks: "openai" (default) or "ollama", which listens on another port.
+pub const LLM_PROVIDER_ENV: &str = "LLM_PROVIDER";
+pub const DEFAULT_OLLAMA_BASE_URL: &str = "http://127.0.0.1:11434";
+
+// Model request timeout and retry environment variable names. Delays are in milliseconds.
+pub const LLM_TIMEOUT_SECONDS_ENV: &str = "LLM_TIMEOUT_SECONDS";
+pub const LLM_RETRY_ATTEMPTS_ENV: &str = "LLM_RETRY_ATTEMPTS";
+pub const LLM_RETRY_DELAY_MS_ENV: &str = "LLM_RETRY_DELAY_MS";
+pub const LLM_RETRY_JITTER_MS_ENV: &str = "LLM_RETRY_JITTER_MS";
+pub const DEFAULT_LLM_TIMEOUT_SECONDS: u64 = 60;
+pub const DEFAULT_LLM_RETRY_ATTEMPTS: u32 = 3;
+pub const DEFAULT_LLM_RETRY_DELAY_MS: u64 = 500;
+pub const DEFAULT_LLM_RETRY_JITTER_MS: u64 = 250;
+
+// Response cache environment variable names. The size is the most responses kept per endpoint.
+pub const LLM_CACHE_ENV: &str = "LLM_CACHE";
+pub const LLM_CACHE_SIZE_ENV: &str = "LLM_CACHE_SIZE";
+pub const DEFAULT_LLM_CACHE_SIZE: usize = 256;
+
+// Response recording environment variable names. Responses are recorded to and replayed from the
+// directory, or only replayed when the mode is replay-only.
+pub const LLM_CACHE_DIR_ENV: &str = "LLM_CACHE_DIR";
+pub const LLM_CACHE_MODE_ENV: &str = "LLM_CACHE_MODE";
+
+// Vector store environment variable name. When it is set, texts stored by VPUT are kept in the
+// file between runs.
+pub const VECTOR_STORE_FILE_ENV: &str = "VECTOR_STORE_FILE";
+
+// Request limit environment variable name.
+pub const MAX_CONCURRENT_REQUESTS_ENV: &str = "MAX_CONCURRENT_REQUESTS";
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 2;
+
+// Register size limit environment variable name. The limit is in bytes of text.
+pub const MAX_REGISTER_BYTES_ENV: &str = "MAX_REGISTER_BYTES";
+pub const DEFAULT_MAX_REGISTER_BYTES: usize = 8 << 20;
+
+// Data memory size limit environment variable name. The limit is in words, each holding one value.
+pub const MAX_DATA_WORDS_ENV: &str = "MAX_DATA_WORDS";
+pub const DEFAULT_MAX_DATA_WORDS: usize = 1 << 16;
+
+// Context window environment variable names. Before INF or EVAL sends a context register, its
+// oldest messages are dropped until it has at most this many messages and characters of content.
+pub const CONTEXT_MAX_MESSAGES_ENV: &str = "CONTEXT_MAX_MESSAGES";
+pub const CONTEXT_MAX_CHARS_ENV: &str = "CONTEXT_MAX_CHARS";
+
+// Context budget environment variable names. A chat request counted at more tokens than the budget
+// is warned about, or fails when ON_CONTEXT_OVER_BUDGET is "error".
+pub const CONTEXT_TOKEN_BUDGET_ENV: &str = "CONTEXT_TOKEN_BUDGET";
+pub const ON_CONTEXT_OVER_BUDGET_ENV: &str = "ON_CONTEXT_OVER_BUDGET";
+
+// Token counting environment variable name. When true, TOK and the context budget count tokens with
+// the server's tokenizer, the llama.cpp /tokenize endpoint, instead of estimating them.
+pub const TOKENIZE_REMOTE_ENV: &str = "TOKENIZE_REMOTE";
+// Characters of ASCII text the estimate counts as one token. Every other character is one token.
+pub const ESTIMATED_CHARS_PER_TOKEN: usize = 4;
+
+// Run budget environment variable names. Runs are unlimited when they are unset.
+pub const MAX_INSTRUCTIONS_ENV: &str = "MAX_INSTRUCTIONS";
+pub const MAX_LLM_CALLS_ENV: &str = "MAX_LLM_CALLS";
+
+// Assembler warning environment variable name.
+pub const WARN_UNUSED_LABELS_ENV: &str = "WARN_UNUSED_LABELS";
 
 // Debug environment variable names.
 pub const DEBUG_BUILD_ENV: &str = "DEBUG_BUILD";
 pub const DEBUG_RUN_ENV: &str = "DEBUG_RUN";
 pub const DEBUG_CHAT_ENV: &str = "DEBUG_CHAT";
+pub const TRACE_FILE_ENV: &str = "TRACE_FILE";
+pub const STATS_ENV: &str = "STATS";
 
 // Optional text model parameter environment variable names.
 pub const TEXT_MODEL_STREAM_ENV: &str = "TEXT_MODEL_STREAM";
@@ -34,3 +187,54 @@ pub const TEXT_MODEL_DRY_BASE_ENV: &str = "TEXT_MODEL_DRY_BASE";
 pub const TEXT_MODEL_DRY_ALLOWED_LENGTH_ENV: &str = "TEXT_MODEL_DRY_ALLOWED_LENGTH";
 pub const TEXT_MODEL_DRY_PENALTY_LAST_N_ENV: &str = "TEXT_MODEL_DRY_PENALTY_LAST_N";
 pub const TEXT_MODEL_TIMINGS_PER_TOKEN_ENV: &str = "TEXT_MODEL_TIMINGS_PER_TOKEN";
+pub const TEXT_MODEL_SEED_ENV: &str = "TEXT_MODEL_SEED";
+pub const TEXT_MODEL_STOP_ENV: &str = "TEXT_MODEL_STOP";
+// Read when TEXT_MODEL_SEED is unset, in this order.
+pub const LPU_SEED_ENV: &str = "LPU_SEED";
+pub const LLM_SEED_ENV: &str = "LLM_SEED";
+pub const TEXT_MODEL_PRESET_ENV: &str = "TEXT_MODEL_PRESET";
+
+// Sampling presets, filling in the settings that are not configured.
+pub const DETERMINISTIC_PRESET: &str = "deterministic";
+pub const TEXT_MODEL_PRESETS: &[&str] = &[DETERMINISTIC_PRESET];
+// Seed the deterministic preset sends when none is configured.
+pub const DEFAULT_DETERMINISTIC_SEED: u32 = 42;
+
+// Environment variable names packed into bundles. Debug flags are left to the machine running the bundle.
+pub const BUNDLE_CONFIG_KEYS: &[&str] = &[
+    TEXT_MODEL_ENV,
+    EMBEDDING_MODEL_ENV,
+    EMBEDDING_DIMENSIONS_ENV,
+    SIMILARITY_BACKEND_ENV,
+    ON_EMPTY_RESPONSE_ENV,
+    ON_TRUNCATED_RESPONSE_ENV,
+    STRIP_THINK_BLOCKS_ENV,
+    SYSTEM_PROMPT_ENV,
+    TEXT_MODEL_STREAM_ENV,
+    TEXT_MODEL_RETURN_PROGRESS_ENV,
+    TEXT_MODEL_REASONING_FORMAT_ENV,
+    TEXT_MODEL_TEMPERATURE_ENV,
+    TEXT_MODEL_DYNATEMP_RANGE_ENV,
+    TEXT_MODEL_DYNATEMP_EXPONENT_ENV,
+    TEXT_MODEL_TOP_K_ENV,
+    TEXT_MODEL_TOP_P_ENV,
+    TEXT_MODEL_MIN_P_ENV,
+    TEXT_MODEL_XTC_PROBABILITY_ENV,
+    TEXT_MODEL_XTC_THRESHOLD_ENV,
+    TEXT_MODEL_TYP_P_ENV,
+    TEXT_MODEL_MAX_TOKENS_ENV,
+    TEXT_MODEL_REPEAT_LAST_N_ENV,
+    TEXT_MODEL_REPEAT_PENALTY_ENV,
+    TEXT_MODEL_PRESENCE_PENALTY_ENV,
+    TEXT_MODEL_FREQUENCY_PENALTY_ENV,
+    TEXT_MODEL_DRY_MULTIPLIER_ENV,
+    TEXT_MODEL_DRY_BASE_ENV,
+    TEXT_MODEL_DRY_ALLOWED_LENGTH_ENV,
+    TEXT_MODEL_DRY_PENALTY_LAST_N_ENV,
+    TEXT_MODEL_TIMINGS_PER_TOKEN_ENV,
+    TEXT_MODEL_SEED_ENV,
+    LPU_SEED_ENV,
+    LLM_SEED_ENV,
+    TEXT_MODEL_PRESET_ENV,
+    TEXT_MODEL_STOP_ENV,
+];
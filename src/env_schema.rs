@@ -0,0 +1,327 @@
+use std::{fs::read_to_string, path::Path};
+
+use crate::{
+    constants,
+    exception::{BaseException, Exception},
+};
+
+// The values a configuration key accepts. Ranges are inclusive.
+enum Kind {
+    Text,
+    Bool,
+    Integer(i64, i64),
+    Float(f64, f64),
+    Choice(&'static [&'static str]),
+}
+
+const SCHEMA: &[(&str, Kind)] = &[
+    (constants::TEXT_MODEL_ENV, Kind::Text),
+    (constants::EMBEDDING_MODEL_ENV, Kind::Text),
+    (constants::LPU_CONFIG_ENV, Kind::Text),
+    (
+        constants::EMBEDDING_DIMENSIONS_ENV,
+        Kind::Integer(1, u32::MAX as i64),
+    ),
+    (
+        constants::LLM_PROVIDER_ENV,
+        Kind::Choice(&["openai", "ollama"]),
+    ),
+    (constants::LLM_BASE_URL_ENV, Kind::Text),
+    (constants::EMBEDDINGS_BASE_URL_ENV, Kind::Text),
+    (constants::LLM_API_KEY_ENV, Kind::Text),
+    (constants::SKIP_HEALTHCHECK_ENV, Kind::Bool),
+    (
+        constants::LLM_TIMEOUT_SECONDS_ENV,
+        Kind::Integer(1, u32::MAX as i64),
+    ),
+    (constants::LLM_RETRY_ATTEMPTS_ENV, Kind::Integer(1, 100)),
+    (
+        constants::LLM_RETRY_DELAY_MS_ENV,
+        Kind::Integer(0, u32::MAX as i64),
+    ),
+    (
+        constants::LLM_RETRY_JITTER_MS_ENV,
+        Kind::Integer(0, u32::MAX as i64),
+    ),
+    (constants::LLM_CACHE_ENV, Kind::Bool),
+    (
+        constants::LLM_CACHE_SIZE_ENV,
+        Kind::Integer(1, u32::MAX as i64),
+    ),
+    (constants::LLM_CACHE_DIR_ENV, Kind::Text),
+    (
+        constants::LLM_CACHE_MODE_ENV,
+        Kind::Choice(&["record", "replay-only"]),
+    ),
+    (constants::VECTOR_STORE_FILE_ENV, Kind::Text),
+    (
+        constants::SIMILARITY_BACKEND_ENV,
+        Kind::Choice(&["embeddings", "llm"]),
+    ),
+    (
+        constants::ON_EMPTY_RESPONSE_ENV,
+        Kind::Choice(&["error", "retry", "accept"]),
+    ),
+    (
+        constants::ON_TRUNCATED_RESPONSE_ENV,
+        Kind::Choice(&["accept", "retry", "error"]),
+    ),
+    (constants::STRIP_THINK_BLOCKS_ENV, Kind::Bool),
+    (constants::SYSTEM_PROMPT_ENV, Kind::Text),
+    (constants::PROMPTS_DIR_ENV, Kind::Text),
+    (
+        constants::MAX_CONCURRENT_REQUESTS_ENV,
+        Kind::Integer(1, u32::MAX as i64),
+    ),
+    (
+        constants::MAX_REGISTER_BYTES_ENV,
+        Kind::Integer(1, i64::MAX),
+    ),
+    (
+        constants::MAX_DATA_WORDS_ENV,
+        Kind::Integer(1, i32::MAX as i64),
+    ),
+    (
+        constants::CONTEXT_MAX_MESSAGES_ENV,
+        Kind::Integer(1, i64::MAX),
+    ),
+    (constants::CONTEXT_MAX_CHARS_ENV, Kind::Integer(1, i64::MAX)),
+    (
+        constants::CONTEXT_TOKEN_BUDGET_ENV,
+        Kind::Integer(1, i64::MAX),
+    ),
+    (
+        constants::ON_CONTEXT_OVER_BUDGET_ENV,
+        Kind::Choice(&["warn", "error"]),
+    ),
+    (constants::TOKENIZE_REMOTE_ENV, Kind::Bool),
+    (constants::MAX_INSTRUCTIONS_ENV, Kind::Integer(1, i64::MAX)),
+    (constants::MAX_LLM_CALLS_ENV, Kind::Integer(1, i64::MAX)),
+    (constants::WARN_UNUSED_LABELS_ENV, Kind::Bool),
+    (constants::DEBUG_BUILD_ENV, Kind::Bool),
+    (constants::DEBUG_RUN_ENV, Kind::Bool),
+    (constants::DEBUG_CHAT_ENV, Kind::Bool),
+    (constants::TRACE_FILE_ENV, Kind::Text),
+    (constants::STATS_ENV, Kind::Bool),
+    (constants::TEXT_MODEL_STREAM_ENV, Kind::Bool),
+    (constants::TEXT_MODEL_RETURN_PROGRESS_ENV, Kind::Bool),
+    (constants::TEXT_MODEL_REASONING_FORMAT_ENV, Kind::Text),
+    (constants::TEXT_MODEL_TEMPERATURE_ENV, Kind::Float(0.0, 2.0)),
+    (
+        constants::TEXT_MODEL_DYNATEMP_RANGE_ENV,
+        Kind::Float(0.0, f64::MAX),
+    ),
+    (
+        constants::TEXT_MODEL_DYNATEMP_EXPONENT_ENV,
+        Kind::Float(0.0, f64::MAX),
+    ),
+    (
+        constants::TEXT_MODEL_TOP_K_ENV,
+        Kind::Integer(0, u32::MAX as i64),
+    ),
+    (constants::TEXT_MODEL_TOP_P_ENV, Kind::Float(0.0, 1.0)),
+    (constants::TEXT_MODEL_MIN_P_ENV, Kind::Float(0.0, 1.0)),
+    (
+        constants::TEXT_MODEL_XTC_PROBABILITY_ENV,
+        Kind::Float(0.0, 1.0),
+    ),
+    (
+        constants::TEXT_MODEL_XTC_THRESHOLD_ENV,
+        Kind::Float(0.0, 1.0),
+    ),
+    (constants::TEXT_MODEL_TYP_P_ENV, Kind::Float(0.0, 1.0)),
+    (
+        constants::TEXT_MODEL_MAX_TOKENS_ENV,
+        Kind::Integer(-1, i32::MAX as i64),
+    ),
+    (
+        constants::TEXT_MODEL_REPEAT_LAST_N_ENV,
+        Kind::Integer(0, u32::MAX as i64),
+    ),
+    (
+        constants::TEXT_MODEL_REPEAT_PENALTY_ENV,
+        Kind::Float(0.0, f64::MAX),
+    ),
+    (
+        constants::TEXT_MODEL_PRESENCE_PENALTY_ENV,
+        Kind::Float(-2.0, 2.0),
+    ),
+    (
+        constants::TEXT_MODEL_FREQUENCY_PENALTY_ENV,
+        Kind::Float(-2.0, 2.0),
+    ),
+    (
+        constants::TEXT_MODEL_DRY_MULTIPLIER_ENV,
+        Kind::Float(0.0, f64::MAX),
+    ),
+    (
+        constants::TEXT_MODEL_DRY_BASE_ENV,
+        Kind::Float(0.0, f64::MAX),
+    ),
+    (
+        constants::TEXT_MODEL_DRY_ALLOWED_LENGTH_ENV,
+        Kind::Integer(0, u32::MAX as i64),
+    ),
+    (
+        constants::TEXT_MODEL_DRY_PENALTY_LAST_N_ENV,
+        Kind::Integer(-1, i32::MAX as i64),
+    ),
+    (constants::TEXT_MODEL_TIMINGS_PER_TOKEN_ENV, Kind::Bool),
+    (
+        constants::TEXT_MODEL_SEED_ENV,
+        Kind::Integer(0, u32::MAX as i64),
+    ),
+    (constants::LPU_SEED_ENV, Kind::Integer(0, u32::MAX as i64)),
+    (constants::LLM_SEED_ENV, Kind::Integer(0, u32::MAX as i64)),
+    (
+        constants::TEXT_MODEL_PRESET_ENV,
+        Kind::Choice(constants::TEXT_MODEL_PRESETS),
+    ),
+    (constants::TEXT_MODEL_STOP_ENV, Kind::Text),
+];
+
+// Keys further than this from every known key are not worth suggesting.
+const MAX_SUGGESTION_DISTANCE: usize = 3;
+
+// Levenshtein distance between two strings, counted in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+// The known key closest to an unknown one, if any is close enough to be a likely typo.
+pub fn did_you_mean<'a>(key: &str, known: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    known
+        .into_iter()
+        .map(|known| (edit_distance(key, known), known))
+        .filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known)| known)
+}
+
+fn check_value(kind: &Kind, value: &str) -> Option<String> {
+    match kind {
+        Kind::Text => None,
+        Kind::Bool => {
+            (value != "true" && value != "false").then(|| "Expected true or false.".to_string())
+        }
+        Kind::Integer(min, max) => match value.parse::<i64>() {
+            Ok(number) if (*min..=*max).contains(&number) => None,
+            _ if *max == i64::MAX => Some(format!("Expected a whole number of at least {}.", min)),
+            _ => Some(format!(
+                "Expected a whole number between {} and {}.",
+                min, max
+            )),
+        },
+        Kind::Float(min, max) => match value.parse::<f64>() {
+            Ok(number) if (*min..=*max).contains(&number) => None,
+            _ if *max == f64::MAX => Some(format!("Expected a number of at least {}.", min)),
+            _ => Some(format!("Expected a number between {} and {}.", min, max)),
+        },
+        Kind::Choice(choices) => {
+            (!choices.contains(&value)).then(|| format!("Expected one of: {}.", choices.join(", ")))
+        }
+    }
+}
+
+// Checks a value against the range of a known key. None when the value is in range or the key is
+// not known.
+pub fn check_setting(key: &str, value: &str) -> Option<String> {
+    let (_, kind) = SCHEMA.iter().find(|(known, _)| *known == key)?;
+    check_value(kind, value)
+}
+
+// Splits a .env line into its key and value, dropping quotes, 'export' and trailing comments.
+fn parse_line(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let (key, value) = line.split_once('=')?;
+    let key = key.trim();
+    let key = key.strip_prefix("export ").unwrap_or(key).trim();
+    let value = value.trim_start();
+
+    let value = match value.chars().next() {
+        Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or_default(),
+        _ => value.split(" #").next().unwrap_or_default().trim_end(),
+    };
+
+    Some((key, value))
+}
+
+// Checks every key in the .env file against the known configuration keys and their value ranges.
+// Problems are returned as warnings, or as an error when strict is set.
+pub fn check(path: &Path, strict: bool) -> Result<Vec<String>, Exception> {
+    let source = read_to_string(path).map_err(|e| {
+        Exception::Program(BaseException::caused_by(
+            format!("Failed to read '{}'.", path.display()),
+            e,
+        ))
+    })?;
+
+    let mut problems = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let Some((key, value)) = parse_line(line) else {
+            continue;
+        };
+
+        let location = format!("{} line {}: '{}'", path.display(), index + 1, line.trim());
+
+        match SCHEMA.iter().find(|(known, _)| *known == key) {
+            Some((_, kind)) => {
+                if let Some(message) = check_value(kind, value) {
+                    problems.push(format!(
+                        "Invalid value for {} at {}. {}",
+                        key, location, message
+                    ));
+                }
+            }
+            None => problems.push(
+                match did_you_mean(key, SCHEMA.iter().map(|(known, _)| *known)) {
+                    Some(suggestion) => format!(
+                        "Unknown key {} at {}. Did you mean {}?",
+                        key, location, suggestion
+                    ),
+                    None => format!("Unknown key {} at {}.", key, location),
+                },
+            ),
+        }
+    }
+
+    strict_problems(problems, strict)
+}
+
+// Returns the problems found with a configuration file to be printed as warnings, or as one error
+// when strict is set.
+pub fn strict_problems(problems: Vec<String>, strict: bool) -> Result<Vec<String>, Exception> {
+    if strict && !problems.is_empty() {
+        return Err(Exception::Program(BaseException::new(
+            format!(
+                "{} problem(s) found with {}:\n{}",
+                problems.len(),
+                constants::STRICT_CONFIG_FLAG,
+                problems.join("\n")
+            ),
+            None,
+        )));
+    }
+
+    Ok(problems)
+}
@@ -39,6 +39,8 @@ pub enum Exception {
     Assembler(BaseException),
     OpenAIChatCompletion(BaseException),
     OpenAIEmbeddings(BaseException),
+    OpenAITokenize(BaseException),
+    StartUp(BaseException),
     LanguageLogic(BaseException),
     ControlUnit(BaseException),
     Decoder(BaseException),
@@ -46,6 +48,8 @@ pub enum Exception {
     Processor(BaseException),
     Memory(BaseException),
     Register(BaseException),
+    Bundle(BaseException),
+    Checkpoint(BaseException),
 }
 
 impl Exception {
@@ -56,13 +60,17 @@ impl Exception {
             | Self::Assembler(e)
             | Self::OpenAIChatCompletion(e)
             | Self::OpenAIEmbeddings(e)
+            | Self::OpenAITokenize(e)
+            | Self::StartUp(e)
             | Self::LanguageLogic(e)
             | Self::ControlUnit(e)
             | Self::Decoder(e)
             | Self::Executor(e)
             | Self::Processor(e)
             | Self::Memory(e)
-            | Self::Register(e) => e,
+            | Self::Register(e)
+            | Self::Bundle(e)
+            | Self::Checkpoint(e) => e,
         }
     }
 
@@ -73,13 +81,17 @@ impl Exception {
             | Self::Assembler(e)
             | Self::OpenAIChatCompletion(e)
             | Self::OpenAIEmbeddings(e)
+            | Self::OpenAITokenize(e)
+            | Self::StartUp(e)
             | Self::LanguageLogic(e)
             | Self::ControlUnit(e)
             | Self::Decoder(e)
             | Self::Executor(e)
             | Self::Processor(e)
             | Self::Memory(e)
-            | Self::Register(e) => e,
+            | Self::Register(e)
+            | Self::Bundle(e)
+            | Self::Checkpoint(e) => e,
         }
     }
 }
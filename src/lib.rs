@@ -0,0 +1,92 @@
+//! An assembler and processor for programs whose instructions are carried out by a language model.
+//!
+//! Source is assembled into byte code with [`Assembler`] and run with [`Processor`]. Both are configured
+//! through [`Config`], which can be built in code with [`Config::new`] rather than read from a `.env` file.
+//!
+//! ```no_run
+//! use language_processor_unit::{Assembler, Config, Processor};
+//!
+//! let source = "LS X1, \"Hello\"\nPLN X1\nEXIT\n".to_string();
+//! let byte_code = Assembler::new(source, "hello.aasm").assemble()?;
+//!
+//! let mut processor = Processor::new(Config::new("LFM2-2.6B-Q5_K_M", "Qwen3-Embedding-0.6B-Q4_1-imat"));
+//! processor.load(&byte_code)?;
+//! processor.run()?;
+//! # Ok::<(), language_processor_unit::Exception>(())
+//! ```
+//!
+//! # Running without a model server
+//!
+//! [`Processor::set_backend`] sends chat and embedding requests to any [`LlmBackend`] instead of the
+//! model server. [`ScriptedBackend`] answers them from canned responses, so a program can be tested
+//! without a server or network:
+//!
+//! ```
+//! use language_processor_unit::{Assembler, Config, Processor, ScriptedBackend};
+//!
+//! let source = "LS X1, \"Name a colour.\"\nINF X2, X1, C1\nLS X3, \"red\"\nSIM X4, X2, X3\nPLN X4\nEXIT\n";
+//! let byte_code = Assembler::new(source.to_string(), "colour.aasm").assemble()?;
+//!
+//! let mut backend = ScriptedBackend::new();
+//! backend.push_response("Red");
+//! backend.set_embedding("Red", vec![1.0, 0.0]);
+//! backend.set_embedding("red", vec![0.8, 0.6]);
+//!
+//! let mut processor = Processor::new(Config::new("LFM2-2.6B-Q5_K_M", "Qwen3-Embedding-0.6B-Q4_1-imat"));
+//! processor.set_backend(Box::new(backend));
+//! processor.load(&byte_code)?;
+//! processor.run()?;
+//!
+//! assert_eq!(processor.stats().chat_completions, 1);
+//! assert_eq!(processor.stats().embeddings, 1);
+//! # Ok::<(), language_processor_unit::Exception>(())
+//! ```
+//!
+//! # Threads
+//!
+//! An [`Assembler`] or [`Processor`] owns the state of a run, including the registers, context stacks and
+//! chat timings, and changes it only through `&mut self`. Neither is shared between threads; instead
+//! each is [`Send`], so several programs can run at once with one processor per thread.
+//!
+//! What processors do share is [`Sync`]:
+//!
+//! - The limit on requests in flight to each model server (`MAX_CONCURRENT_REQUESTS`), guarded by a
+//!   mutex and set by the first processor to send to the server.
+//! - [`RunCounters`], the model request counts of a run, kept in atomics. [`Processor::share_with`]
+//!   makes processors count into one set, so [`Stats`] and `MAX_LLM_CALLS` cover all of them, and
+//!   [`Processor::counters`] lets another thread read them while programs run.
+//! - The response cache (`LLM_CACHE`), guarded by a mutex and also shared by `share_with`.
+//! - Files: recordings in `LLM_CACHE_DIR`, `CTXSAVE` and `VECTOR_STORE_FILE` are written under a lock
+//!   file (see [`atomic_file::lock`]), so processors in other threads or processes take turns.
+
+#![deny(unsafe_code)]
+
+pub mod assembler;
+pub mod atomic_file;
+pub mod config;
+pub mod constants;
+pub mod exception;
+pub mod processor;
+pub mod proxy;
+
+pub use assembler::{Assembler, Diagnostic, Severity, Symbol};
+pub use config::{
+    Config, ContextBudgetPolicy, EmptyResponsePolicy, SimilarityBackend, TextModelOverrides,
+    TruncatedResponsePolicy,
+};
+pub use exception::{BaseException, Exception};
+pub use processor::{
+    CapturedOutput, ChatTimings, ContextLength, JsonLinesTrace, LlmBackend, Processor, ProgramInfo,
+    ReplayState, RunCounters, ScriptedBackend, Stats, TokenUsage, TraceEvent, TraceHeader,
+    TraceReplay, TraceSink, Value, instruction,
+};
+
+// Fails to compile if a change ever stops an assembler or processor from being moved to another thread,
+// or the counters processors share from being used by several.
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+    assert_send::<Assembler>();
+    assert_send::<Processor>();
+    assert_sync::<RunCounters>();
+};
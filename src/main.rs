@@ -1,18 +1,31 @@
-mod assembler;
-mod config;
-mod constants;
-mod exception;
-mod processor;
+mod bundle;
+mod env_schema;
+mod model_file;
+mod prompt_files;
 
 use std::{
+    collections::HashMap,
     env,
-    fs::{read, read_to_string, write},
+    fs::{read, read_to_string},
+    io,
     path::Path,
+    process::ExitCode,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use language_processor_unit::{
+    assembler, atomic_file, constants, exception, processor,
+    proxy::{self, ProxySettings},
 };
 
 use crate::{
-    config::{Config, TextModelOverrides},
+    bundle::{Bundle, KeyValues},
     exception::{BaseException, Exception},
+    model_file::ModelFile,
+};
+use language_processor_unit::config::{
+    Config, ContextBudgetPolicy, EmptyResponsePolicy, LlmProvider, PromptTemplates, RecordingMode,
+    RetryPolicy, SimilarityBackend, TextModelOverrides, TruncatedResponsePolicy,
 };
 
 fn start_up() -> Result<(), Exception> {
@@ -24,65 +37,634 @@ fn start_up() -> Result<(), Exception> {
     })
 }
 
-fn env_required(key: &str) -> Result<String, Exception> {
-    env::var(key).map_err(|e| {
+// Configuration lookup in order of precedence: variables set explicitly in the process environment,
+// then the config embedded in a bundle (if any), then the .env file.
+struct Environment {
+    explicit: HashMap<String, String>,
+    bundle: HashMap<String, String>,
+    // The prompt template files and model config file packed into a bundle.
+    bundle_prompts: KeyValues,
+    bundle_model_config: Option<(String, String)>,
+    // --strict-config was passed, so problems found with configuration files are errors.
+    strict: bool,
+}
+
+impl Environment {
+    // A missing .env file is only an error when required, so commands that never talk to a model
+    // work without one.
+    fn load(bundle: &Bundle, strict: bool, required: bool) -> Result<Self, Exception> {
+        let explicit = env::vars().collect();
+
+        match dotenv::dotenv() {
+            Ok(path) => {
+                for warning in env_schema::check(&path, strict)? {
+                    eprintln!("Warning: {}", warning);
+                }
+            }
+            Err(dotenv::Error::Io(e)) if e.kind() == io::ErrorKind::NotFound && !required => {}
+            Err(_) => {
+                return Err(Exception::Program(BaseException::new(
+                    "Failed to load .env file".to_string(),
+                    None,
+                )));
+            }
+        }
+
+        Ok(Environment {
+            explicit,
+            bundle: bundle
+                .key_values(bundle::CONFIG_ENTRY)?
+                .into_iter()
+                .collect(),
+            bundle_prompts: bundle.key_values(bundle::PROMPTS_ENTRY)?,
+            bundle_model_config: bundle
+                .key_values(bundle::MODEL_CONFIG_ENTRY)?
+                .into_iter()
+                .next(),
+            strict,
+        })
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        self.explicit
+            .get(key)
+            .or_else(|| self.bundle.get(key))
+            .cloned()
+            .or_else(|| env::var(key).ok())
+    }
+}
+
+// The model config file named by LPU_CONFIG, which must exist, or lpu.toml, which may not.
+fn model_file_path(environment: &Environment) -> (String, bool) {
+    match environment.get(constants::LPU_CONFIG_ENV) {
+        Some(path) => (path, true),
+        None => (constants::DEFAULT_LPU_CONFIG.to_string(), false),
+    }
+}
+
+// Reads the model config file packed into the bundle, or else the one named by LPU_CONFIG or
+// lpu.toml when there is one, and returns it with its path. Setting LPU_CONFIG explicitly in the
+// process environment takes precedence over the bundle. Settings it leaves out keep their defaults.
+fn model_file(environment: &Environment) -> Result<(String, ModelFile), Exception> {
+    let (path, file) = match &environment.bundle_model_config {
+        Some((path, text)) if !environment.explicit.contains_key(constants::LPU_CONFIG_ENV) => (
+            path.clone(),
+            ModelFile::parse(path, text, environment.strict)?,
+        ),
+        _ => {
+            let (path, required) = model_file_path(environment);
+            let file = ModelFile::load(&path, required, environment.strict)?.unwrap_or_default();
+            (path, file)
+        }
+    };
+
+    for warning in &file.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+
+    Ok((path, file))
+}
+
+// The model named in the environment, which takes precedence, or in the model config file.
+fn model_name(
+    environment: &Environment,
+    key: &str,
+    from_file: Option<String>,
+    table: &str,
+    path: &str,
+) -> Result<String, Exception> {
+    environment.get(key).or(from_file).ok_or_else(|| {
+        Exception::Program(BaseException::new(
+            format!(
+                "{} must be set in the .env file, or as 'model' in the [{}] table of {}",
+                key, table, path
+            ),
+            None,
+        ))
+    })
+}
+
+fn env_bool(environment: &Environment, key: &str) -> bool {
+    environment.get(key).map(|v| v == "true").unwrap_or(false)
+}
+
+fn env_opt_bool(environment: &Environment, key: &str) -> Option<bool> {
+    environment.get(key).map(|v| v == "true")
+}
+
+fn env_opt<T: std::str::FromStr>(environment: &Environment, key: &str) -> Option<T> {
+    environment.get(key).and_then(|v| v.parse().ok())
+}
+
+// Collects the arguments after the program path that are neither flags nor flag values.
+fn positional_arguments(args: &[String]) -> Vec<String> {
+    let mut positional = Vec::new();
+    let mut rest = args.iter().skip(3);
+
+    while let Some(arg) = rest.next() {
+        if arg == constants::END_OF_FLAGS {
+            positional.extend(rest.cloned());
+            break;
+        }
+
+        if constants::VALUE_FLAGS.contains(&arg.as_str()) {
+            rest.next();
+        } else if !arg.starts_with("--") {
+            positional.push(arg.clone());
+        }
+    }
+
+    positional
+}
+
+// Collects '--arg name=value' pairs. A value of '@path' is replaced by the contents of that file.
+fn arguments(args: &[String]) -> Result<KeyValues, Exception> {
+    args.iter()
+        .zip(args.iter().skip(1))
+        .filter(|(flag, _)| *flag == constants::ARG_FLAG)
+        .map(|(_, argument)| {
+            let (name, value) = argument.split_once('=').ok_or_else(|| {
+                Exception::Program(BaseException::new(
+                    format!(
+                        "Invalid argument '{}'. Expected {} name=value.",
+                        argument,
+                        constants::ARG_FLAG
+                    ),
+                    None,
+                ))
+            })?;
+
+            let value = match value.strip_prefix('@') {
+                Some(path) => read_to_string(path).map_err(|e| {
+                    Exception::Program(BaseException::caused_by(
+                        format!("Failed to read file '{}' for argument '{}'.", path, name),
+                        e,
+                    ))
+                })?,
+                None => value.to_string(),
+            };
+
+            Ok((name.to_string(), value))
+        })
+        .collect()
+}
+
+// TEXT_MODEL_STOP is a JSON array so stop sequences can hold commas and line breaks.
+fn stop_sequences(environment: &Environment) -> Result<Option<Vec<String>>, Exception> {
+    let Some(value) = environment.get(constants::TEXT_MODEL_STOP_ENV) else {
+        return Ok(None);
+    };
+
+    miniserde::json::from_str::<Vec<String>>(&value).map(Some).map_err(|_| {
+        Exception::Program(BaseException::new(
+            format!(
+                "Invalid {} '{}'. Expected a JSON array of strings, such as [\"END\", \"\\n\\n\"].",
+                constants::TEXT_MODEL_STOP_ENV,
+                value
+            ),
+            None,
+        ))
+    })
+}
+
+// TEXT_MODEL_PRESET takes precedence over the preset in the model config file. Settings configured
+// anywhere else take precedence over the preset's.
+fn preset(
+    environment: &Environment,
+    file_preset: Option<String>,
+    path: &str,
+) -> Result<TextModelOverrides, Exception> {
+    let (name, source) = match environment.get(constants::TEXT_MODEL_PRESET_ENV) {
+        Some(name) => (name, constants::TEXT_MODEL_PRESET_ENV.to_string()),
+        None => match file_preset {
+            Some(name) => (name, format!("text_model.preset in '{}'", path)),
+            None => return Ok(TextModelOverrides::default()),
+        },
+    };
+
+    TextModelOverrides::preset(&name).ok_or_else(|| {
+        Exception::Program(BaseException::new(
+            format!(
+                "Invalid {} '{}'. Expected one of: {}.",
+                source,
+                name,
+                constants::TEXT_MODEL_PRESETS.join(", ")
+            ),
+            None,
+        ))
+    })
+}
+
+// The --seed flag takes precedence over TEXT_MODEL_SEED, then LPU_SEED, then LLM_SEED.
+fn seed(args: &[String]) -> Result<Option<u32>, Exception> {
+    flag_value(args, constants::SEED_FLAG)
+        .map(|value| {
+            value.parse().map_err(|_| {
+                Exception::Program(BaseException::new(
+                    format!(
+                        "Invalid {} '{}'. Expected a number between 0 and {}.",
+                        constants::SEED_FLAG,
+                        value,
+                        u32::MAX
+                    ),
+                    None,
+                ))
+            })
+        })
+        .transpose()
+}
+
+// Collects '--break' byte code indexes. Labels are looked up in the symbol table written next to the
+// byte code by 'build', so they only work for programs run from the build directory.
+fn breakpoints(args: &[String]) -> Result<Vec<usize>, Exception> {
+    let mut symbols: Option<Vec<assembler::Symbol>> = None;
+
+    args.iter()
+        .zip(args.iter().skip(1))
+        .filter(|(flag, _)| *flag == constants::BREAK_FLAG)
+        .map(|(_, target)| {
+            if let Some(index) = processor::parse_index(target) {
+                return Ok(index);
+            }
+
+            if symbols.is_none() {
+                symbols = Some(read_symbols(args.get(2).map(String::as_str).unwrap_or_default())?);
+            }
+
+            symbols
+                .iter()
+                .flatten()
+                .find(|symbol| symbol.name == *target)
+                .map(|symbol| symbol.byte_code_index as usize)
+                .ok_or_else(|| {
+                    Exception::Program(BaseException::new(
+                        format!(
+                            "Invalid {} '{}'. Expected a byte code index or a label in the program.",
+                            constants::BREAK_FLAG,
+                            target
+                        ),
+                        None,
+                    ))
+                })
+        })
+        .collect()
+}
+
+fn read_symbols(file_path: &str) -> Result<Vec<assembler::Symbol>, Exception> {
+    let symbols_path = Path::new(file_path).with_extension("symbols.json");
+
+    let text = read_to_string(&symbols_path).map_err(|e| {
+        Exception::Program(BaseException::caused_by(
+            format!(
+                "Failed to read symbol table '{}' to find {} labels. Rebuild the program or break at a byte code index.",
+                symbols_path.display(),
+                constants::BREAK_FLAG
+            ),
+            e,
+        ))
+    })?;
+
+    miniserde::json::from_str(&text).map_err(|e| {
         Exception::Program(BaseException::caused_by(
-            format!("{} must be set in the .env file", key),
-            format!("{:#?}", e),
+            format!("Failed to parse symbol table '{}'.", symbols_path.display()),
+            e,
         ))
     })
 }
 
-fn env_bool(key: &str) -> bool {
-    env::var(key).map(|v| v == "true").unwrap_or(false)
+// Resuming needs to know where checkpoints are kept, so --from-checkpoint requires --checkpoint-dir.
+fn from_checkpoint(args: &[String]) -> Result<Option<String>, Exception> {
+    let name = flag_value(args, constants::FROM_CHECKPOINT_FLAG);
+
+    if name.is_some() && flag_value(args, constants::CHECKPOINT_DIR_FLAG).is_none() {
+        return Err(Exception::Program(BaseException::new(
+            format!(
+                "{} requires {}.",
+                constants::FROM_CHECKPOINT_FLAG,
+                constants::CHECKPOINT_DIR_FLAG
+            ),
+            None,
+        )));
+    }
+
+    Ok(name.map(str::to_string))
+}
+
+fn llm_provider(environment: &Environment) -> Result<LlmProvider, Exception> {
+    let provider = environment.get(constants::LLM_PROVIDER_ENV);
+
+    match provider.as_deref() {
+        None | Some("openai") => Ok(LlmProvider::OpenAI),
+        Some("ollama") => Ok(LlmProvider::Ollama),
+        Some(other) => Err(Exception::Program(BaseException::new(
+            format!(
+                "Invalid {} '{}'. Expected 'openai' or 'ollama'.",
+                constants::LLM_PROVIDER_ENV,
+                other
+            ),
+            None,
+        ))),
+    }
 }
 
-fn env_opt_bool(key: &str) -> Option<bool> {
-    env::var(key).ok().map(|v| v == "true")
+fn similarity_backend(environment: &Environment) -> Result<SimilarityBackend, Exception> {
+    let backend = environment.get(constants::SIMILARITY_BACKEND_ENV);
+
+    match backend.as_deref() {
+        None | Some("embeddings") => Ok(SimilarityBackend::Embeddings),
+        Some("llm") => Ok(SimilarityBackend::Llm),
+        Some(other) => Err(Exception::Program(BaseException::new(
+            format!(
+                "Invalid {} '{}'. Expected 'embeddings' or 'llm'.",
+                constants::SIMILARITY_BACKEND_ENV,
+                other
+            ),
+            None,
+        ))),
+    }
 }
 
-fn env_opt<T: std::str::FromStr>(key: &str) -> Option<T> {
-    env::var(key).ok().and_then(|v| v.parse().ok())
+// Reads a model server URL, checked here so a bad value fails before the program starts running.
+fn base_url(environment: &Environment, key: &str) -> Result<Option<String>, Exception> {
+    let Some(url) = environment.get(key) else {
+        return Ok(None);
+    };
+
+    let invalid = |reason: &str| {
+        Err(Exception::Program(BaseException::new(
+            format!("Invalid {} '{}'. {}", key, url, reason),
+            None,
+        )))
+    };
+
+    let (scheme, rest) = match (url.strip_prefix("https://"), url.strip_prefix("http://")) {
+        (Some(_), _) if !cfg!(feature = "https") => {
+            return invalid(
+                "This build cannot reach https:// servers. Rebuild with the https feature, or use \
+                 an http:// URL.",
+            );
+        }
+        (Some(rest), _) => ("https://", rest),
+        (None, Some(rest)) => ("http://", rest),
+        (None, None) => return invalid("Expected a URL starting with http:// or https://."),
+    };
+
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+
+    if host.is_empty() || host.starts_with(':') || host.chars().any(char::is_whitespace) {
+        return invalid(&format!("Expected a host after {}.", scheme));
+    }
+
+    if let Some((_, port)) = host.rsplit_once(':')
+        && !host.ends_with(']')
+        && port.parse::<u16>().is_err()
+    {
+        return invalid("Expected the port after the host to be a number from 0 to 65535.");
+    }
+
+    Ok(Some(url.trim_end_matches('/').to_string()))
 }
 
-fn config() -> Result<Config, Exception> {
-    if dotenv::dotenv().ok().is_none() {
+fn recording_mode(environment: &Environment) -> Result<RecordingMode, Exception> {
+    let mode = environment.get(constants::LLM_CACHE_MODE_ENV);
+
+    let mode = match mode.as_deref() {
+        None | Some("record") => RecordingMode::Record,
+        Some("replay-only") => RecordingMode::ReplayOnly,
+        Some(other) => {
+            return Err(Exception::Program(BaseException::new(
+                format!(
+                    "Invalid {} '{}'. Expected 'record' or 'replay-only'.",
+                    constants::LLM_CACHE_MODE_ENV,
+                    other
+                ),
+                None,
+            )));
+        }
+    };
+
+    let has_dir = environment.get(constants::LLM_CACHE_DIR_ENV).is_some();
+
+    if mode == RecordingMode::ReplayOnly && !has_dir {
         return Err(Exception::Program(BaseException::new(
-            "Failed to load .env file".to_string(),
+            format!(
+                "{} is replay-only, but {} is not set.",
+                constants::LLM_CACHE_MODE_ENV,
+                constants::LLM_CACHE_DIR_ENV
+            ),
             None,
         )));
     }
 
+    Ok(mode)
+}
+
+fn on_empty_response(environment: &Environment) -> Result<EmptyResponsePolicy, Exception> {
+    let policy = environment.get(constants::ON_EMPTY_RESPONSE_ENV);
+
+    match policy.as_deref() {
+        None | Some("retry") => Ok(EmptyResponsePolicy::Retry),
+        Some("error") => Ok(EmptyResponsePolicy::Error),
+        Some("accept") => Ok(EmptyResponsePolicy::Accept),
+        Some(other) => Err(Exception::Program(BaseException::new(
+            format!(
+                "Invalid {} '{}'. Expected 'error', 'retry' or 'accept'.",
+                constants::ON_EMPTY_RESPONSE_ENV,
+                other
+            ),
+            None,
+        ))),
+    }
+}
+
+fn on_truncated_response(environment: &Environment) -> Result<TruncatedResponsePolicy, Exception> {
+    let policy = environment.get(constants::ON_TRUNCATED_RESPONSE_ENV);
+
+    match policy.as_deref() {
+        None | Some("accept") => Ok(TruncatedResponsePolicy::Accept),
+        Some("retry") => Ok(TruncatedResponsePolicy::Retry),
+        Some("error") => Ok(TruncatedResponsePolicy::Error),
+        Some(other) => Err(Exception::Program(BaseException::new(
+            format!(
+                "Invalid {} '{}'. Expected 'accept', 'retry' or 'error'.",
+                constants::ON_TRUNCATED_RESPONSE_ENV,
+                other
+            ),
+            None,
+        ))),
+    }
+}
+
+fn on_context_over_budget(environment: &Environment) -> Result<ContextBudgetPolicy, Exception> {
+    let policy = environment.get(constants::ON_CONTEXT_OVER_BUDGET_ENV);
+
+    match policy.as_deref() {
+        None | Some("warn") => Ok(ContextBudgetPolicy::Warn),
+        Some("error") => Ok(ContextBudgetPolicy::Error),
+        Some(other) => Err(Exception::Program(BaseException::new(
+            format!(
+                "Invalid {} '{}'. Expected 'warn' or 'error'.",
+                constants::ON_CONTEXT_OVER_BUDGET_ENV,
+                other
+            ),
+            None,
+        ))),
+    }
+}
+
+// The prompt template files in PROMPTS_DIR, which must exist, or in the default directory, which
+// may not.
+fn prompt_template_files(environment: &Environment) -> Result<KeyValues, Exception> {
+    match environment.get(constants::PROMPTS_DIR_ENV) {
+        Some(dir) => prompt_files::read(&dir, true),
+        None => prompt_files::read(constants::DEFAULT_PROMPTS_DIR, false),
+    }
+}
+
+// The prompt templates packed into the bundle, or else those on disk. Setting PROMPTS_DIR
+// explicitly in the process environment takes precedence over the bundle.
+fn prompts(environment: &Environment) -> Result<PromptTemplates, Exception> {
+    if !environment.bundle_prompts.is_empty()
+        && !environment
+            .explicit
+            .contains_key(constants::PROMPTS_DIR_ENV)
+    {
+        return prompt_files::parse(&environment.bundle_prompts);
+    }
+
+    prompt_files::parse(&prompt_template_files(environment)?)
+}
+
+fn config(environment: &Environment, args: &[String]) -> Result<Config, Exception> {
+    let e = environment;
+    let (path, file) = model_file(e)?;
+    let llm_provider = llm_provider(e)?;
+
     Ok(Config {
-        text_model: env_required(constants::TEXT_MODEL_ENV)?,
-        embedding_model: env_required(constants::EMBEDDING_MODEL_ENV)?,
-        debug_build: env_bool(constants::DEBUG_BUILD_ENV),
-        debug_run: env_bool(constants::DEBUG_RUN_ENV),
-        debug_chat: env_bool(constants::DEBUG_CHAT_ENV),
-        text_model_overrides: TextModelOverrides {
-            stream: env_opt_bool(constants::TEXT_MODEL_STREAM_ENV),
-            return_progress: env_opt_bool(constants::TEXT_MODEL_RETURN_PROGRESS_ENV),
-            reasoning_format: env::var(constants::TEXT_MODEL_REASONING_FORMAT_ENV).ok(),
-            temperature: env_opt(constants::TEXT_MODEL_TEMPERATURE_ENV),
-            dynatemp_range: env_opt(constants::TEXT_MODEL_DYNATEMP_RANGE_ENV),
-            dynatemp_exponent: env_opt(constants::TEXT_MODEL_DYNATEMP_EXPONENT_ENV),
-            top_k: env_opt(constants::TEXT_MODEL_TOP_K_ENV),
-            top_p: env_opt(constants::TEXT_MODEL_TOP_P_ENV),
-            min_p: env_opt(constants::TEXT_MODEL_MIN_P_ENV),
-            xtc_probability: env_opt(constants::TEXT_MODEL_XTC_PROBABILITY_ENV),
-            xtc_threshold: env_opt(constants::TEXT_MODEL_XTC_THRESHOLD_ENV),
-            typ_p: env_opt(constants::TEXT_MODEL_TYP_P_ENV),
-            max_tokens: env_opt(constants::TEXT_MODEL_MAX_TOKENS_ENV),
-            repeat_last_n: env_opt(constants::TEXT_MODEL_REPEAT_LAST_N_ENV),
-            repeat_penalty: env_opt(constants::TEXT_MODEL_REPEAT_PENALTY_ENV),
-            presence_penalty: env_opt(constants::TEXT_MODEL_PRESENCE_PENALTY_ENV),
-            frequency_penalty: env_opt(constants::TEXT_MODEL_FREQUENCY_PENALTY_ENV),
-            dry_multiplier: env_opt(constants::TEXT_MODEL_DRY_MULTIPLIER_ENV),
-            dry_base: env_opt(constants::TEXT_MODEL_DRY_BASE_ENV),
-            dry_allowed_length: env_opt(constants::TEXT_MODEL_DRY_ALLOWED_LENGTH_ENV),
-            dry_penalty_last_n: env_opt(constants::TEXT_MODEL_DRY_PENALTY_LAST_N_ENV),
-            timings_per_token: env_opt_bool(constants::TEXT_MODEL_TIMINGS_PER_TOKEN_ENV),
+        text_model: model_name(
+            e,
+            constants::TEXT_MODEL_ENV,
+            file.text_model,
+            "text_model",
+            &path,
+        )?,
+        embedding_model: model_name(
+            e,
+            constants::EMBEDDING_MODEL_ENV,
+            file.embedding_model,
+            "embeddings_model",
+            &path,
+        )?,
+        embedding_dimensions: env_opt(e, constants::EMBEDDING_DIMENSIONS_ENV)
+            .or(file.embedding_dimensions),
+        llm_provider,
+        llm_base_url: base_url(e, constants::LLM_BASE_URL_ENV)?.unwrap_or_else(|| {
+            match llm_provider {
+                LlmProvider::OpenAI => constants::DEFAULT_LLM_BASE_URL,
+                LlmProvider::Ollama => constants::DEFAULT_OLLAMA_BASE_URL,
+            }
+            .to_string()
+        }),
+        embeddings_base_url: base_url(e, constants::EMBEDDINGS_BASE_URL_ENV)?,
+        llm_api_key: e
+            .get(constants::LLM_API_KEY_ENV)
+            .filter(|key| !key.is_empty()),
+        proxy: ProxySettings::from_variables(|name| e.get(name)),
+        skip_healthcheck: env_bool(e, constants::SKIP_HEALTHCHECK_ENV),
+        llm_timeout_seconds: env_opt(e, constants::LLM_TIMEOUT_SECONDS_ENV)
+            .filter(|&seconds| seconds > 0)
+            .unwrap_or(constants::DEFAULT_LLM_TIMEOUT_SECONDS),
+        retry_policy: RetryPolicy {
+            attempts: env_opt(e, constants::LLM_RETRY_ATTEMPTS_ENV)
+                .filter(|&attempts| attempts > 0)
+                .unwrap_or(constants::DEFAULT_LLM_RETRY_ATTEMPTS),
+            base_delay: Duration::from_millis(
+                env_opt(e, constants::LLM_RETRY_DELAY_MS_ENV)
+                    .unwrap_or(constants::DEFAULT_LLM_RETRY_DELAY_MS),
+            ),
+            jitter: Duration::from_millis(
+                env_opt(e, constants::LLM_RETRY_JITTER_MS_ENV)
+                    .unwrap_or(constants::DEFAULT_LLM_RETRY_JITTER_MS),
+            ),
         },
+        llm_cache: env_bool(e, constants::LLM_CACHE_ENV),
+        llm_cache_size: env_opt(e, constants::LLM_CACHE_SIZE_ENV)
+            .filter(|&size| size > 0)
+            .unwrap_or(constants::DEFAULT_LLM_CACHE_SIZE),
+        llm_cache_dir: e.get(constants::LLM_CACHE_DIR_ENV),
+        llm_recording_mode: recording_mode(e)?,
+        vector_store_file: e.get(constants::VECTOR_STORE_FILE_ENV),
+        similarity_backend: similarity_backend(e)?,
+        on_empty_response: on_empty_response(e)?,
+        on_truncated_response: on_truncated_response(e)?,
+        strip_think_blocks: env_opt_bool(e, constants::STRIP_THINK_BLOCKS_ENV).unwrap_or(true),
+        system_prompt: e
+            .get(constants::SYSTEM_PROMPT_ENV)
+            .unwrap_or_else(|| constants::DEFAULT_SYSTEM_PROMPT.to_string()),
+        prompts: prompts(e)?,
+        max_concurrent_requests: env_opt(e, constants::MAX_CONCURRENT_REQUESTS_ENV)
+            .filter(|&limit| limit > 0)
+            .unwrap_or(constants::DEFAULT_MAX_CONCURRENT_REQUESTS),
+        max_register_bytes: env_opt(e, constants::MAX_REGISTER_BYTES_ENV)
+            .unwrap_or(constants::DEFAULT_MAX_REGISTER_BYTES),
+        max_data_words: env_opt(e, constants::MAX_DATA_WORDS_ENV)
+            .unwrap_or(constants::DEFAULT_MAX_DATA_WORDS),
+        context_max_messages: env_opt(e, constants::CONTEXT_MAX_MESSAGES_ENV)
+            .filter(|&limit| limit > 0),
+        context_max_chars: env_opt(e, constants::CONTEXT_MAX_CHARS_ENV).filter(|&limit| limit > 0),
+        context_token_budget: env_opt(e, constants::CONTEXT_TOKEN_BUDGET_ENV)
+            .filter(|&limit| limit > 0),
+        on_context_over_budget: on_context_over_budget(e)?,
+        tokenize_remote: env_bool(e, constants::TOKENIZE_REMOTE_ENV),
+        max_instructions: env_opt(e, constants::MAX_INSTRUCTIONS_ENV).filter(|&limit| limit > 0),
+        max_llm_calls: env_opt(e, constants::MAX_LLM_CALLS_ENV).filter(|&limit| limit > 0),
+        warn_unused_labels: env_opt_bool(e, constants::WARN_UNUSED_LABELS_ENV).unwrap_or(true),
+        debug_build: env_bool(e, constants::DEBUG_BUILD_ENV),
+        debug_run: env_bool(e, constants::DEBUG_RUN_ENV),
+        debug_chat: env_bool(e, constants::DEBUG_CHAT_ENV),
+        stats: env_bool(e, constants::STATS_ENV),
+        strict_utf8: args.iter().any(|arg| arg == constants::STRICT_UTF8_FLAG),
+        require_fresh: args.iter().any(|arg| arg == constants::REQUIRE_FRESH_FLAG),
+        checkpoint_dir: flag_value(args, constants::CHECKPOINT_DIR_FLAG).map(str::to_string),
+        from_checkpoint: from_checkpoint(args)?,
+        arguments: arguments(args)?,
+        positional_arguments: positional_arguments(args),
+        breakpoints: breakpoints(args)?,
+        trace_file: flag_value(args, constants::TRACE_FLAG)
+            .map(str::to_string)
+            .or_else(|| e.get(constants::TRACE_FILE_ENV)),
+        text_model_overrides: TextModelOverrides {
+            stream: env_opt_bool(e, constants::TEXT_MODEL_STREAM_ENV),
+            return_progress: env_opt_bool(e, constants::TEXT_MODEL_RETURN_PROGRESS_ENV),
+            reasoning_format: e.get(constants::TEXT_MODEL_REASONING_FORMAT_ENV),
+            temperature: env_opt(e, constants::TEXT_MODEL_TEMPERATURE_ENV),
+            dynatemp_range: env_opt(e, constants::TEXT_MODEL_DYNATEMP_RANGE_ENV),
+            dynatemp_exponent: env_opt(e, constants::TEXT_MODEL_DYNATEMP_EXPONENT_ENV),
+            top_k: env_opt(e, constants::TEXT_MODEL_TOP_K_ENV),
+            top_p: env_opt(e, constants::TEXT_MODEL_TOP_P_ENV),
+            min_p: env_opt(e, constants::TEXT_MODEL_MIN_P_ENV),
+            xtc_probability: env_opt(e, constants::TEXT_MODEL_XTC_PROBABILITY_ENV),
+            xtc_threshold: env_opt(e, constants::TEXT_MODEL_XTC_THRESHOLD_ENV),
+            typ_p: env_opt(e, constants::TEXT_MODEL_TYP_P_ENV),
+            max_tokens: env_opt(e, constants::TEXT_MODEL_MAX_TOKENS_ENV),
+            samplers: None,
+            repeat_last_n: env_opt(e, constants::TEXT_MODEL_REPEAT_LAST_N_ENV),
+            repeat_penalty: env_opt(e, constants::TEXT_MODEL_REPEAT_PENALTY_ENV),
+            presence_penalty: env_opt(e, constants::TEXT_MODEL_PRESENCE_PENALTY_ENV),
+            frequency_penalty: env_opt(e, constants::TEXT_MODEL_FREQUENCY_PENALTY_ENV),
+            dry_multiplier: env_opt(e, constants::TEXT_MODEL_DRY_MULTIPLIER_ENV),
+            dry_base: env_opt(e, constants::TEXT_MODEL_DRY_BASE_ENV),
+            dry_allowed_length: env_opt(e, constants::TEXT_MODEL_DRY_ALLOWED_LENGTH_ENV),
+            dry_penalty_last_n: env_opt(e, constants::TEXT_MODEL_DRY_PENALTY_LAST_N_ENV),
+            timings_per_token: env_opt_bool(e, constants::TEXT_MODEL_TIMINGS_PER_TOKEN_ENV),
+            seed: seed(args)?
+                .or_else(|| env_opt(e, constants::TEXT_MODEL_SEED_ENV))
+                .or_else(|| env_opt(e, constants::LPU_SEED_ENV))
+                .or_else(|| env_opt(e, constants::LLM_SEED_ENV)),
+            stop: stop_sequences(e)?,
+        }
+        .or(file.text_model_overrides)
+        .or(preset(e, file.preset, &path)?),
     })
 }
 
@@ -91,8 +673,16 @@ fn build(file_path: &str, config: &Config) -> Result<(), Exception> {
         Exception::Program(BaseException::caused_by("Failed to read source file.", e))
     })?;
 
-    let mut compiler = assembler::Assembler::new(source);
-    let byte_code = compiler.assemble().map_err(|e| {
+    let mut compiler = assembler::Assembler::new(source, file_path);
+    compiler.set_warn_unused_labels(config.warn_unused_labels);
+    compiler.set_debug_info(config.debug_build);
+    let result = compiler.assemble();
+
+    for diagnostic in compiler.diagnostics() {
+        eprintln!("{}", diagnostic);
+    }
+
+    let byte_code = result.map_err(|e| {
         Exception::Program(BaseException::caused_by(
             "Failed to assemble source file.",
             e,
@@ -100,14 +690,14 @@ fn build(file_path: &str, config: &Config) -> Result<(), Exception> {
     })?;
 
     if config.debug_build {
-        println!("Assembled byte code ({} bytes):", byte_code.len());
+        eprintln!("Assembled byte code ({} bytes):", byte_code.len());
 
         for (chunk_idx, chunk) in byte_code.chunks(4).enumerate() {
             let index = chunk_idx * 4;
-            println!("{} {:02X} ({}): {:?}", chunk_idx, index, index, chunk);
+            eprintln!("{} {:02X} ({}): {:?}", chunk_idx, index, index, chunk);
         }
 
-        println!();
+        eprintln!();
     }
 
     let path = Path::new(file_path);
@@ -120,19 +710,35 @@ fn build(file_path: &str, config: &Config) -> Result<(), Exception> {
 
     let output_file_name = format!("{}/{}.lpu", constants::BUILD_DIR, stem);
 
-    write(&output_file_name, byte_code).map_err(|e| {
+    atomic_file::write(&output_file_name, byte_code).map_err(|e| {
         Exception::Program(BaseException::caused_by(
             "Failed to write byte code to output file.",
             e,
         ))
     })?;
 
+    // Written next to the byte code for tools that map addresses back to labels.
+    let symbols_file_name = format!("{}/{}.symbols.json", constants::BUILD_DIR, stem);
+
+    atomic_file::write(
+        &symbols_file_name,
+        miniserde::json::to_string(&compiler.symbols()),
+    )
+    .map_err(|e| {
+        Exception::Program(BaseException::caused_by(
+            "Failed to write symbol table to output file.",
+            e,
+        ))
+    })?;
+
     println!("Build successful! Output written to {}", output_file_name);
 
     Ok(())
 }
 
-fn run(file_path: &str, config: &Config) -> Result<(), Exception> {
+// Reads a program from a .lpu file or a .lpub bundle, returning the byte code and the bundle, which is
+// empty for a .lpu file.
+fn read_program(file_path: &str) -> Result<(Vec<u8>, Bundle), Exception> {
     let data = read(file_path).map_err(|e| {
         Exception::Program(BaseException::caused_by(
             "Failed to read byte code file.",
@@ -140,54 +746,476 @@ fn run(file_path: &str, config: &Config) -> Result<(), Exception> {
         ))
     })?;
 
+    if !Bundle::is_bundle(&data) {
+        return Ok((data, Bundle::new()));
+    }
+
+    let bundle = Bundle::from_bytes(&data)
+        .map_err(|e| Exception::Program(BaseException::caused_by("Failed to read bundle.", e)))?;
+
+    let program = bundle
+        .get(bundle::PROGRAM_ENTRY)
+        .ok_or_else(|| {
+            Exception::Program(BaseException::new(
+                format!(
+                    "Bundle does not contain a '{}' entry.",
+                    bundle::PROGRAM_ENTRY
+                ),
+                None,
+            ))
+        })?
+        .to_vec();
+
+    Ok((program, bundle))
+}
+
+// Warns, or fails with --require-fresh, when the source recorded in the byte code has changed since it was built.
+// Programs whose source can no longer be read are not checked.
+fn check_freshness(data: &[u8], config: &Config) -> Result<(), Exception> {
+    let Some((source_path, hash)) = assembler::source_reference(data) else {
+        return Ok(());
+    };
+
+    let Ok(source) = read_to_string(&source_path) else {
+        return Ok(());
+    };
+
+    if assembler::source_hash(&source) == hash {
+        return Ok(());
+    }
+
+    let message = format!(
+        "Source {} has changed since this artifact was built. Rerun 'build'.",
+        source_path
+    );
+
+    if config.require_fresh {
+        return Err(Exception::Program(BaseException::new(message, None)));
+    }
+
+    eprintln!("Warning: {}", message);
+    Ok(())
+}
+
+// Settings that would fail, or send the API key unencrypted, at the first model request are reported
+// before the program runs.
+fn check_connections(config: &Config) -> Result<(), Exception> {
+    if config.llm_recording_mode == RecordingMode::ReplayOnly {
+        return Ok(());
+    }
+
+    let servers = [
+        (constants::LLM_BASE_URL_ENV, Some(&config.llm_base_url)),
+        (
+            constants::EMBEDDINGS_BASE_URL_ENV,
+            config.embeddings_base_url.as_ref(),
+        ),
+    ];
+
+    for (variable, url) in servers {
+        let Some(url) = url else {
+            continue;
+        };
+
+        config.proxy.check(url)?;
+
+        if config.llm_api_key.is_some() && proxy::is_cleartext(url) {
+            return Err(Exception::Program(BaseException::new(
+                format!(
+                    "{} is set, but {} '{}' is a plain http:// URL to another machine, which would \
+                     send the key unencrypted. Use an https:// URL, or unset {}.",
+                    constants::LLM_API_KEY_ENV,
+                    variable,
+                    url,
+                    constants::LLM_API_KEY_ENV
+                ),
+                None,
+            )));
+        }
+    }
+
+    // minreq would otherwise send requests through these whatever NO_PROXY says. The proxies were
+    // read into the config when it was built.
+    for variable in proxy::MINREQ_VARIABLES {
+        // SAFETY: the run has not started any threads yet, so none can be reading the environment.
+        unsafe { env::remove_var(variable) };
+    }
+
+    Ok(())
+}
+
+// Returns the status the program passed to EXIT.
+fn run(data: &[u8], config: &Config) -> Result<i32, Exception> {
+    check_freshness(data, config)?;
+    check_connections(config)?;
+
+    if config.debug_run {
+        if let Some(seed) = config.text_model_overrides.seed {
+            eprintln!("Seed = {}", seed);
+        }
+
+        for (name, value) in &config.arguments {
+            eprintln!("Argument {} = {:?}", name, value);
+        }
+    }
+
     let mut processor = processor::Processor::new(config.clone());
 
-    processor.load(&data).map_err(|e| {
+    if let Some(trace_file) = &config.trace_file {
+        processor.set_trace(Box::new(processor::JsonLinesTrace::create(trace_file)?));
+    }
+
+    processor.load(data).map_err(|e| {
         Exception::Program(BaseException::caused_by(
             "Failed to load byte code file.",
             e,
         ))
     })?;
 
-    processor
+    // Replayed responses need no server.
+    if !config.skip_healthcheck && config.llm_recording_mode != RecordingMode::ReplayOnly {
+        processor.check_model_server(&processor::Processor::verify(data)?)?;
+    }
+
+    if let (Some(directory), Some(name)) = (&config.checkpoint_dir, &config.from_checkpoint) {
+        processor.restore_checkpoint(directory, name).map_err(|e| {
+            Exception::Program(BaseException::caused_by("Failed to restore checkpoint.", e))
+        })?;
+
+        if config.debug_run {
+            eprintln!("Resumed from checkpoint '{}'", name);
+        }
+    }
+
+    let result = processor
         .run()
-        .map_err(|e| Exception::Program(BaseException::caused_by("Failed to run program.", e)))
+        .map_err(|e| Exception::Program(BaseException::caused_by("Failed to run program.", e)));
+
+    if config.debug_run
+        && let Some(timings) = processor.chat_timings()
+    {
+        eprintln!("Text model timings = {}", timings);
+    }
+
+    if config.debug_run || config.stats {
+        eprintln!("{}", processor.stats());
+    }
+
+    result.map(|()| processor.exit_status())
 }
 
-fn main() {
-    if let Err(e) = start_up() {
-        println!("Startup error: {}", e);
-        return;
+fn disassemble(data: &[u8]) -> Result<(), Exception> {
+    let lines = processor::Processor::disassemble(data).map_err(|e| {
+        Exception::Program(BaseException::caused_by(
+            "Failed to disassemble byte code file.",
+            e,
+        ))
+    })?;
+
+    for line in lines {
+        println!("{}", line);
     }
 
-    let config = match config() {
-        Ok(config) => config,
-        Err(e) => {
-            println!("Configuration error: {}", e);
-            return;
+    Ok(())
+}
+
+fn check(data: &[u8]) -> Result<(), Exception> {
+    let info = processor::Processor::verify(data).map_err(|e| {
+        Exception::Program(BaseException::caused_by("Byte code file is invalid.", e))
+    })?;
+
+    println!(
+        "OK: {} instructions, entry point 0x{:04X}, {} strings.",
+        info.instruction_count,
+        info.entry_point,
+        info.strings.len()
+    );
+    println!("Opcodes: {}", info.opcodes.join(", "));
+
+    Ok(())
+}
+
+// Steps through a recorded run using the trace written by 'run --trace'. Nothing is executed, so the
+// program is only read to show each step's instruction.
+fn debug_trace(data: &[u8], args: &[String]) -> Result<(), Exception> {
+    let trace_file = flag_value(args, constants::FROM_TRACE_FLAG).ok_or_else(|| {
+        Exception::Program(BaseException::new(
+            format!(
+                "'debug' requires {} <trace_file>.",
+                constants::FROM_TRACE_FLAG
+            ),
+            None,
+        ))
+    })?;
+
+    let replay = processor::TraceReplay::load(trace_file)?;
+    let listing = processor::Processor::disassemble(data).map_err(|e| {
+        Exception::Program(BaseException::caused_by(
+            "Failed to disassemble byte code file.",
+            e,
+        ))
+    })?;
+
+    replay.explore(&listing)
+}
+
+// The program path of 'debug', which may come before or after the --from-trace flag and its value.
+fn debug_program_path(args: &[String]) -> Option<&String> {
+    let mut rest = args.iter().skip(2);
+
+    while let Some(arg) = rest.next() {
+        if arg == constants::FROM_TRACE_FLAG {
+            rest.next();
+        } else if !arg.starts_with("--") {
+            return Some(arg);
         }
-    };
+    }
+
+    None
+}
+
+fn isa(mnemonic: &str) -> Result<(), Exception> {
+    let info = assembler::isa::lookup(mnemonic).ok_or_else(|| {
+        let known = assembler::isa::INSTRUCTIONS
+            .iter()
+            .map(|info| info.mnemonic)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        Exception::Program(BaseException::new(
+            format!(
+                "Unknown instruction '{}'. Known instructions: {}.",
+                mnemonic, known
+            ),
+            None,
+        ))
+    })?;
+
+    println!("{}", info.help());
+
+    Ok(())
+}
+
+fn bundle(
+    file_path: &str,
+    output_path: Option<&str>,
+    environment: &Environment,
+) -> Result<(), Exception> {
+    let data = read(file_path).map_err(|e| {
+        Exception::Program(BaseException::caused_by(
+            "Failed to read byte code file.",
+            e,
+        ))
+    })?;
+
+    if Bundle::is_bundle(&data) {
+        return Err(Exception::Program(BaseException::new(
+            format!("'{}' is already a bundle.", file_path),
+            None,
+        )));
+    }
+
+    let path = Path::new(file_path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).ok_or_else(|| {
+        Exception::Program(BaseException::new(
+            "Failed to determine output filename from byte code file.".to_string(),
+            None,
+        ))
+    })?;
+
+    let config = constants::BUNDLE_CONFIG_KEYS
+        .iter()
+        .filter_map(|key| environment.get(key).map(|value| (key.to_string(), value)))
+        .collect::<KeyValues>();
+
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let metadata = vec![
+        ("name".to_string(), stem.to_string()),
+        ("source".to_string(), file_path.to_string()),
+        (
+            "lpu_version".to_string(),
+            env!("CARGO_PKG_VERSION").to_string(),
+        ),
+        ("created".to_string(), created.to_string()),
+    ];
+
+    let mut bundle = Bundle::new();
+    bundle.add(bundle::PROGRAM_ENTRY, data);
+    bundle.add(bundle::CONFIG_ENTRY, Bundle::encode_key_values(&config)?);
+    bundle.add(
+        bundle::METADATA_ENTRY,
+        Bundle::encode_key_values(&metadata)?,
+    );
+
+    // The files are checked here, so a bundle never carries templates or settings that fail to load.
+    let prompts = prompt_template_files(environment)?;
+    prompt_files::parse(&prompts)?;
+    if !prompts.is_empty() {
+        bundle.add(bundle::PROMPTS_ENTRY, Bundle::encode_key_values(&prompts)?);
+    }
+
+    let (path, required) = model_file_path(environment);
+    if let Some(text) = ModelFile::read(&path, required)? {
+        ModelFile::parse(&path, &text, environment.strict)?;
+        bundle.add(
+            bundle::MODEL_CONFIG_ENTRY,
+            Bundle::encode_key_values(&[(path, text)])?,
+        );
+    }
+
+    let output_file_name = output_path.map(str::to_string).unwrap_or_else(|| {
+        format!(
+            "{}/{}.{}",
+            constants::BUILD_DIR,
+            stem,
+            constants::BUNDLE_EXTENSION
+        )
+    });
+
+    atomic_file::write(&output_file_name, bundle.to_bytes()?).map_err(|e| {
+        Exception::Program(BaseException::caused_by(
+            "Failed to write bundle to output file.",
+            e,
+        ))
+    })?;
+
+    println!("Bundle successful! Output written to {}", output_file_name);
+
+    Ok(())
+}
+
+fn info(file_path: &str) -> Result<(), Exception> {
+    let data = read(file_path).map_err(|e| {
+        Exception::Program(BaseException::caused_by("Failed to read bundle file.", e))
+    })?;
+
+    let bundle = Bundle::from_bytes(&data)
+        .map_err(|e| Exception::Program(BaseException::caused_by("Failed to read bundle.", e)))?;
+
+    println!("Bundle: {}", file_path);
+
+    for (name, data) in bundle.entries() {
+        println!("{} ({} bytes)", name, data.len());
+
+        if name == bundle::PROGRAM_ENTRY {
+            continue;
+        }
+
+        // Values may span lines, so they are escaped to keep one line per key.
+        for (key, value) in bundle.key_values(name)? {
+            println!("  {} = {}", key, value.escape_debug());
+        }
+    }
+
+    Ok(())
+}
+
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|index| args.get(index + 1))
+        .map(String::as_str)
+}
+
+// Exits with a failure status whenever an error is printed, so scripts can tell a failed run apart.
+fn main() -> ExitCode {
+    if let Err(e) = start_up() {
+        println!("Startup error: {}", e);
+        return ExitCode::FAILURE;
+    }
 
     let args: Vec<String> = env::args().collect();
 
-    let result = match (args.get(1).map(String::as_str), args.get(2)) {
+    let (command, file_path) = match (args.get(1).map(String::as_str), args.get(2)) {
         (None, _) => {
             println!("No command provided. {}", constants::HELP_USAGE);
-            return;
+            return ExitCode::FAILURE;
         }
         (_, None) => {
             println!("No file path provided. {}", constants::HELP_USAGE);
-            return;
+            return ExitCode::FAILURE;
+        }
+        (Some("debug"), Some(_)) => match debug_program_path(&args) {
+            Some(file_path) => ("debug", file_path.as_str()),
+            None => {
+                println!("No file path provided. {}", constants::HELP_USAGE);
+                return ExitCode::FAILURE;
+            }
+        },
+        (Some(command), Some(file_path)) => (command, file_path.as_str()),
+    };
+
+    // Only 'run', 'disasm', 'check' and 'debug' read a program, which may be a bundle carrying its own config.
+    let (program, program_bundle) = if matches!(command, "run" | "disasm" | "check" | "debug") {
+        match read_program(file_path) {
+            Ok(program) => program,
+            Err(e) => {
+                println!("Exception: {}", e);
+                return ExitCode::FAILURE;
+            }
         }
-        (Some("build"), Some(file_path)) => build(file_path, &config),
-        (Some("run"), Some(file_path)) => run(file_path, &config),
-        (Some(other), _) => {
+    } else {
+        (Vec::new(), Bundle::new())
+    };
+
+    let environment = match Environment::load(
+        &program_bundle,
+        args.iter().any(|arg| arg == constants::STRICT_CONFIG_FLAG),
+        matches!(command, "build" | "run" | "bundle"),
+    ) {
+        Ok(environment) => environment,
+        Err(e) => {
+            println!("Configuration error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match command {
+        "bundle" => bundle(file_path, flag_value(&args, "-o"), &environment),
+        "info" => info(file_path),
+        "disasm" => disassemble(&program),
+        "check" => check(&program),
+        "debug" => debug_trace(&program, &args),
+        "isa" => isa(file_path),
+        "build" | "run" => {
+            let config = match config(&environment, &args) {
+                Ok(config) => config,
+                Err(e) => {
+                    println!("Configuration error: {}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            if command == "build" {
+                build(file_path, &config)
+            } else {
+                match run(&program, &config) {
+                    Ok(0) => Ok(()),
+                    // std::process::exit flushes stdout before the process ends.
+                    Ok(status) => std::process::exit(status),
+                    Err(e) => Err(e),
+                }
+            }
+        }
+        other => {
             println!("Unknown command: {}. {}", other, constants::HELP_USAGE);
-            return;
+            return ExitCode::FAILURE;
         }
     };
 
-    if let Err(e) = result {
-        println!("Exception: {}", e);
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e @ Exception::StartUp(_)) => {
+            println!("Startup error: {}", e);
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            println!("Exception: {}", e);
+            ExitCode::FAILURE
+        }
     }
 }
@@ -0,0 +1,845 @@
+use std::{fs::read_to_string, io, path::Path};
+
+use language_processor_unit::{
+    config::TextModelOverrides,
+    constants,
+    exception::{BaseException, Exception},
+};
+
+use crate::env_schema;
+
+const TEXT_MODEL_TABLE: &str = "text_model";
+const EMBEDDINGS_MODEL_TABLE: &str = "embeddings_model";
+
+// Every key each table accepts, suggested when a key is misspelt.
+const TEXT_MODEL_KEYS: &[&str] = &[
+    "model",
+    "stream",
+    "return_progress",
+    "reasoning_format",
+    "temperature",
+    "dynatemp_range",
+    "dynatemp_exponent",
+    "top_k",
+    "top_p",
+    "min_p",
+    "xtc_probability",
+    "xtc_threshold",
+    "typ_p",
+    "max_tokens",
+    "samplers",
+    "stop",
+    "repeat_last_n",
+    "repeat_penalty",
+    "presence_penalty",
+    "frequency_penalty",
+    "dry_multiplier",
+    "dry_base",
+    "dry_allowed_length",
+    "dry_penalty_last_n",
+    "timings_per_token",
+    "seed",
+    "preset",
+];
+const EMBEDDINGS_MODEL_KEYS: &[&str] = &["model", "dimensions"];
+
+// The model settings read from lpu.toml. Every key is optional, and a key left out keeps its
+// default.
+#[derive(Debug, Default)]
+pub struct ModelFile {
+    pub text_model: Option<String>,
+    pub text_model_overrides: TextModelOverrides,
+    pub preset: Option<String>,
+    pub embedding_model: Option<String>,
+    pub embedding_dimensions: Option<usize>,
+    // Unknown tables and keys and out of range values, printed as warnings unless --strict-config made
+    // them errors.
+    pub warnings: Vec<String>,
+}
+
+// A value as written in the file. Only the parts of TOML the settings need are read: strings,
+// integers, floats, booleans and arrays of them.
+#[derive(Debug)]
+enum Value {
+    Text(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn describe(&self) -> String {
+        match self {
+            Value::Text(text) => format!("the string {:?}", text),
+            Value::Integer(integer) => format!("the integer {}", integer),
+            Value::Float(float) => format!("the float {}", float),
+            Value::Bool(value) => format!("the boolean {}", value),
+            Value::Array(_) => "an array".to_string(),
+        }
+    }
+}
+
+// A table header and the line it is on.
+struct Table {
+    name: String,
+    line: usize,
+}
+
+// Where a key was set, named in errors about its value. Keys before the first table have an empty
+// table name.
+struct Entry {
+    table: String,
+    key: String,
+    value: Value,
+    line: usize,
+}
+
+impl ModelFile {
+    // Reads the file at path. A missing file is only an error when required, so the default path
+    // can be left out. Unknown keys are warnings, or errors when strict is set.
+    pub fn load(path: &str, required: bool, strict: bool) -> Result<Option<Self>, Exception> {
+        Self::read(path, required)?
+            .map(|text| Self::parse(path, &text, strict))
+            .transpose()
+    }
+
+    // The text of the file at path, so bundles can carry it, or None when it is missing and not
+    // required.
+    pub fn read(path: &str, required: bool) -> Result<Option<String>, Exception> {
+        match read_to_string(Path::new(path)) {
+            Ok(text) => Ok(Some(text)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => Ok(None),
+            Err(e) => Err(Exception::Program(BaseException::caused_by(
+                format!("Failed to read model config '{}'", path),
+                e,
+            ))),
+        }
+    }
+
+    pub fn parse(path: &str, text: &str, strict: bool) -> Result<Self, Exception> {
+        let (tables, entries) = Parser::new(path, text).entries()?;
+        let lines = text.lines().collect::<Vec<_>>();
+        let location = |line: usize| {
+            let quoted = lines.get(line - 1).copied().unwrap_or_default().trim();
+            format!("{}:{}: '{}'", path, line, quoted)
+        };
+
+        let mut file = ModelFile::default();
+        let mut problems = Vec::new();
+
+        for table in &tables {
+            if Self::known_keys(&table.name).is_none() {
+                problems.push(Self::unknown_table(&table.name, &location(table.line)));
+            }
+        }
+
+        for entry in &entries {
+            if entry.table.is_empty() {
+                problems.push(Self::top_level_key(&entry.key, &location(entry.line)));
+            } else if Self::known_keys(&entry.table).is_none() {
+                // Reported once for the table.
+            } else if !file.set(path, entry)? {
+                problems.push(Self::unknown_key(entry, &location(entry.line)));
+            } else if let Some(message) = Self::check_range(entry) {
+                problems.push(format!(
+                    "Invalid value for {}.{} at {}. {}",
+                    entry.table,
+                    entry.key,
+                    location(entry.line),
+                    message
+                ));
+            }
+        }
+
+        file.warnings = env_schema::strict_problems(problems, strict)?;
+        Ok(file)
+    }
+
+    fn known_keys(table: &str) -> Option<&'static [&'static str]> {
+        match table {
+            TEXT_MODEL_TABLE => Some(TEXT_MODEL_KEYS),
+            EMBEDDINGS_MODEL_TABLE => Some(EMBEDDINGS_MODEL_KEYS),
+            _ => None,
+        }
+    }
+
+    fn unknown_table(table: &str, location: &str) -> String {
+        match env_schema::did_you_mean(table, [TEXT_MODEL_TABLE, EMBEDDINGS_MODEL_TABLE]) {
+            Some(suggestion) => format!(
+                "Unknown table [{}] at {}. Did you mean [{}]?",
+                table, location, suggestion
+            ),
+            None => format!(
+                "Unknown table [{}] at {}. Expected [{}] or [{}].",
+                table, location, TEXT_MODEL_TABLE, EMBEDDINGS_MODEL_TABLE
+            ),
+        }
+    }
+
+    fn top_level_key(key: &str, location: &str) -> String {
+        let table = [TEXT_MODEL_TABLE, EMBEDDINGS_MODEL_TABLE]
+            .into_iter()
+            .find(|table| Self::known_keys(table).is_some_and(|keys| keys.contains(&key)));
+
+        match table {
+            Some(table) => format!(
+                "Key '{}' at {} is not in a table. Did you mean to put it in [{}]?",
+                key, location, table
+            ),
+            None => format!(
+                "Key '{}' at {} is not in a table. Expected it in [{}] or [{}].",
+                key, location, TEXT_MODEL_TABLE, EMBEDDINGS_MODEL_TABLE
+            ),
+        }
+    }
+
+    fn unknown_key(entry: &Entry, location: &str) -> String {
+        let known = Self::known_keys(&entry.table).unwrap_or_default();
+
+        match env_schema::did_you_mean(&entry.key, known.iter().copied()) {
+            Some(suggestion) => format!(
+                "Unknown key '{}' in [{}] at {}. Did you mean '{}'?",
+                entry.key, entry.table, location, suggestion
+            ),
+            None => format!(
+                "Unknown key '{}' in [{}] at {}.",
+                entry.key, entry.table, location
+            ),
+        }
+    }
+
+    // Checks a value against the range of the .env key with the same meaning, so both places accept
+    // the same values.
+    fn check_range(entry: &Entry) -> Option<String> {
+        let key = match (entry.table.as_str(), entry.key.as_str()) {
+            (TEXT_MODEL_TABLE, "model") => constants::TEXT_MODEL_ENV.to_string(),
+            (TEXT_MODEL_TABLE, key) => format!("TEXT_MODEL_{}", key.to_uppercase()),
+            (EMBEDDINGS_MODEL_TABLE, "model") => constants::EMBEDDING_MODEL_ENV.to_string(),
+            (EMBEDDINGS_MODEL_TABLE, "dimensions") => {
+                constants::EMBEDDING_DIMENSIONS_ENV.to_string()
+            }
+            _ => return None,
+        };
+
+        let value = match &entry.value {
+            Value::Text(text) => text.clone(),
+            Value::Integer(integer) => integer.to_string(),
+            Value::Float(float) => float.to_string(),
+            Value::Bool(value) => value.to_string(),
+            Value::Array(_) => return None,
+        };
+
+        env_schema::check_setting(&key, &value)
+    }
+
+    // Sets the setting an entry names, returning false when its table has no such key.
+    fn set(&mut self, path: &str, entry: &Entry) -> Result<bool, Exception> {
+        let reader = Reader { path, entry };
+        let overrides = &mut self.text_model_overrides;
+
+        match (entry.table.as_str(), entry.key.as_str()) {
+            (TEXT_MODEL_TABLE, "model") => self.text_model = Some(reader.text()?),
+            (TEXT_MODEL_TABLE, "stream") => overrides.stream = Some(reader.bool()?),
+            (TEXT_MODEL_TABLE, "return_progress") => {
+                overrides.return_progress = Some(reader.bool()?)
+            }
+            (TEXT_MODEL_TABLE, "reasoning_format") => {
+                overrides.reasoning_format = Some(reader.text()?)
+            }
+            (TEXT_MODEL_TABLE, "temperature") => overrides.temperature = Some(reader.float()?),
+            (TEXT_MODEL_TABLE, "dynatemp_range") => {
+                overrides.dynatemp_range = Some(reader.float()?)
+            }
+            (TEXT_MODEL_TABLE, "dynatemp_exponent") => {
+                overrides.dynatemp_exponent = Some(reader.float()?)
+            }
+            (TEXT_MODEL_TABLE, "top_k") => overrides.top_k = Some(reader.integer()?),
+            (TEXT_MODEL_TABLE, "top_p") => overrides.top_p = Some(reader.float()?),
+            (TEXT_MODEL_TABLE, "min_p") => overrides.min_p = Some(reader.float()?),
+            (TEXT_MODEL_TABLE, "xtc_probability") => {
+                overrides.xtc_probability = Some(reader.float()?)
+            }
+            (TEXT_MODEL_TABLE, "xtc_threshold") => overrides.xtc_threshold = Some(reader.float()?),
+            (TEXT_MODEL_TABLE, "typ_p") => overrides.typ_p = Some(reader.float()?),
+            (TEXT_MODEL_TABLE, "max_tokens") => overrides.max_tokens = Some(reader.integer()?),
+            (TEXT_MODEL_TABLE, "samplers") => overrides.samplers = Some(reader.texts()?),
+            (TEXT_MODEL_TABLE, "stop") => overrides.stop = Some(reader.texts()?),
+            (TEXT_MODEL_TABLE, "repeat_last_n") => {
+                overrides.repeat_last_n = Some(reader.integer()?)
+            }
+            (TEXT_MODEL_TABLE, "repeat_penalty") => {
+                overrides.repeat_penalty = Some(reader.float()?)
+            }
+            (TEXT_MODEL_TABLE, "presence_penalty") => {
+                overrides.presence_penalty = Some(reader.float()?)
+            }
+            (TEXT_MODEL_TABLE, "frequency_penalty") => {
+                overrides.frequency_penalty = Some(reader.float()?)
+            }
+            (TEXT_MODEL_TABLE, "dry_multiplier") => {
+                overrides.dry_multiplier = Some(reader.float()?)
+            }
+            (TEXT_MODEL_TABLE, "dry_base") => overrides.dry_base = Some(reader.float()?),
+            (TEXT_MODEL_TABLE, "dry_allowed_length") => {
+                overrides.dry_allowed_length = Some(reader.integer()?)
+            }
+            (TEXT_MODEL_TABLE, "dry_penalty_last_n") => {
+                overrides.dry_penalty_last_n = Some(reader.integer()?)
+            }
+            (TEXT_MODEL_TABLE, "timings_per_token") => {
+                overrides.timings_per_token = Some(reader.bool()?)
+            }
+            (TEXT_MODEL_TABLE, "seed") => overrides.seed = Some(reader.integer()?),
+            (TEXT_MODEL_TABLE, "preset") => self.preset = Some(reader.text()?),
+            (EMBEDDINGS_MODEL_TABLE, "model") => self.embedding_model = Some(reader.text()?),
+            (EMBEDDINGS_MODEL_TABLE, "dimensions") => {
+                self.embedding_dimensions = Some(reader.integer()?)
+            }
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+}
+
+// Converts an entry's value to the type its key expects.
+struct Reader<'a> {
+    path: &'a str,
+    entry: &'a Entry,
+}
+
+impl Reader<'_> {
+    fn error(&self, message: String) -> Exception {
+        Exception::Program(BaseException::new(
+            format!("{}:{}: {}.", self.path, self.entry.line, message),
+            None,
+        ))
+    }
+
+    fn expected(&self, expected: &str) -> Exception {
+        self.error(format!(
+            "{}.{} must be {}, but is {}",
+            self.entry.table,
+            self.entry.key,
+            expected,
+            self.entry.value.describe()
+        ))
+    }
+
+    fn text(&self) -> Result<String, Exception> {
+        match &self.entry.value {
+            Value::Text(text) => Ok(text.clone()),
+            _ => Err(self.expected("a string")),
+        }
+    }
+
+    fn bool(&self) -> Result<bool, Exception> {
+        match self.entry.value {
+            Value::Bool(value) => Ok(value),
+            _ => Err(self.expected("a boolean (true or false)")),
+        }
+    }
+
+    // Integers are accepted too, so 'temperature = 1' reads as 1.0.
+    fn float(&self) -> Result<f32, Exception> {
+        match self.entry.value {
+            Value::Float(float) => Ok(float as f32),
+            Value::Integer(integer) => Ok(integer as f32),
+            _ => Err(self.expected("a number")),
+        }
+    }
+
+    fn integer<T: TryFrom<i64> + Bounded>(&self) -> Result<T, Exception> {
+        let expected = format!("an integer from {} to {}", T::MIN, T::MAX);
+
+        match self.entry.value {
+            Value::Integer(integer) if (T::MIN..=T::MAX).contains(&integer) => {
+                T::try_from(integer).map_err(|_| self.expected(&expected))
+            }
+            _ => Err(self.expected(&expected)),
+        }
+    }
+
+    fn texts(&self) -> Result<Vec<String>, Exception> {
+        match &self.entry.value {
+            Value::Array(values) => values
+                .iter()
+                .map(|value| match value {
+                    Value::Text(text) => Ok(text.clone()),
+                    _ => Err(self.expected("an array of strings")),
+                })
+                .collect(),
+            _ => Err(self.expected("an array of strings")),
+        }
+    }
+}
+
+// The range of an integer setting, named when a value does not fit.
+trait Bounded {
+    const MIN: i64;
+    const MAX: i64;
+}
+
+impl Bounded for u32 {
+    const MIN: i64 = 0;
+    const MAX: i64 = u32::MAX as i64;
+}
+
+impl Bounded for i32 {
+    const MIN: i64 = i32::MIN as i64;
+    const MAX: i64 = i32::MAX as i64;
+}
+
+impl Bounded for usize {
+    const MIN: i64 = 1;
+    const MAX: i64 = u32::MAX as i64;
+}
+
+struct Parser<'a> {
+    path: &'a str,
+    chars: Vec<char>,
+    position: usize,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(path: &'a str, text: &str) -> Self {
+        Parser {
+            path,
+            chars: text.chars().collect(),
+            position: 0,
+            line: 1,
+        }
+    }
+
+    fn error(&self, message: &str) -> Exception {
+        Exception::Program(BaseException::new(
+            format!("{}:{}: {}.", self.path, self.line, message),
+            None,
+        ))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += 1;
+
+        if c == '\n' {
+            self.line += 1;
+        }
+
+        Some(c)
+    }
+
+    // Skips spaces and tabs, and a comment up to the end of the line.
+    fn skip_blank(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => {
+                    self.advance();
+                }
+                '#' => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.advance();
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    // Skips blank lines too, which arrays may span.
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_blank();
+
+            if self.peek() != Some('\n') {
+                break;
+            }
+
+            self.advance();
+        }
+    }
+
+    // Every table in the file with the line it starts on, and every key set in the file with the
+    // table it is in.
+    fn entries(mut self) -> Result<(Vec<Table>, Vec<Entry>), Exception> {
+        let mut tables: Vec<Table> = Vec::new();
+        let mut entries: Vec<Entry> = Vec::new();
+        let mut table = String::new();
+
+        loop {
+            self.skip_blank_lines();
+
+            let Some(c) = self.peek() else {
+                return Ok((tables, entries));
+            };
+
+            if c == '[' {
+                let line = self.line;
+                table = self.table()?;
+
+                if tables.iter().all(|known| known.name != table) {
+                    tables.push(Table {
+                        name: table.clone(),
+                        line,
+                    });
+                }
+            } else {
+                let line = self.line;
+                let key = self.key()?;
+
+                if entries
+                    .iter()
+                    .any(|entry| entry.table == table && entry.key == key)
+                {
+                    return Err(self.error(&format!("{}.{} is set twice", table, key)));
+                }
+
+                self.skip_blank();
+                if self.advance() != Some('=') {
+                    return Err(self.error(&format!("expected '=' after '{}'", key)));
+                }
+
+                self.skip_blank();
+                let value = self.value()?;
+
+                entries.push(Entry {
+                    table: table.clone(),
+                    key,
+                    value,
+                    line,
+                });
+            }
+
+            self.end_of_line()?;
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), Exception> {
+        self.skip_blank();
+
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.advance();
+                Ok(())
+            }
+            Some(c) => Err(self.error(&format!("unexpected '{}' at the end of the line", c))),
+        }
+    }
+
+    fn table(&mut self) -> Result<String, Exception> {
+        self.advance();
+        self.skip_blank();
+        let name = self.bare_key();
+        self.skip_blank();
+
+        // Checked before advancing, so a missing ']' at the end of a line is reported on that line.
+        if self.peek() != Some(']') {
+            return Err(self.error("expected ']' after the table name"));
+        }
+
+        self.advance();
+
+        if name.is_empty() {
+            return Err(self.error("expected a table name after '['"));
+        }
+
+        Ok(name)
+    }
+
+    fn bare_key(&mut self) -> String {
+        let mut key = String::new();
+
+        while let Some(c) = self
+            .peek()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+        {
+            key.push(c);
+            self.advance();
+        }
+
+        key
+    }
+
+    fn key(&mut self) -> Result<String, Exception> {
+        if self.peek() == Some('"') {
+            return self.string();
+        }
+
+        let key = self.bare_key();
+
+        if key.is_empty() {
+            return Err(self.error("expected a key or a [table]"));
+        }
+
+        Ok(key)
+    }
+
+    fn value(&mut self) -> Result<Value, Exception> {
+        match self.peek() {
+            Some('"') => self.string().map(Value::Text),
+            Some('\'') => self.literal_string().map(Value::Text),
+            Some('[') => self.array(),
+            Some(_) => self.scalar(),
+            None => Err(self.error("expected a value")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Exception> {
+        self.advance();
+        let mut text = String::new();
+
+        loop {
+            // Checked before advancing, so the error is reported on the line the string is on.
+            if matches!(self.peek(), Some('\n') | None) {
+                return Err(self.error("unterminated string"));
+            }
+
+            match self.advance() {
+                Some('"') => return Ok(text),
+                Some('\\') => match self.advance() {
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some('r') => text.push('\r'),
+                    Some('"') => text.push('"'),
+                    Some('\\') => text.push('\\'),
+                    Some(c) => return Err(self.error(&format!("unknown escape '\\{}'", c))),
+                    None => return Err(self.error("unterminated string")),
+                },
+                Some(c) => text.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    // A single-quoted string, which has no escapes.
+    fn literal_string(&mut self) -> Result<String, Exception> {
+        self.advance();
+        let mut text = String::new();
+
+        loop {
+            if matches!(self.peek(), Some('\n') | None) {
+                return Err(self.error("unterminated string"));
+            }
+
+            match self.advance() {
+                Some('\'') => return Ok(text),
+                Some(c) => text.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, Exception> {
+        self.advance();
+        let mut values = Vec::new();
+
+        loop {
+            self.skip_blank_lines();
+
+            if self.peek() == Some(']') {
+                self.advance();
+                return Ok(Value::Array(values));
+            }
+
+            values.push(self.value()?);
+            self.skip_blank_lines();
+
+            match self.advance() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(values)),
+                _ => return Err(self.error("expected ',' or ']' in the array")),
+            }
+        }
+    }
+
+    // A boolean, integer or float, read up to the next space, comma, bracket or comment.
+    fn scalar(&mut self) -> Result<Value, Exception> {
+        let mut word = String::new();
+
+        while let Some(c) = self
+            .peek()
+            .filter(|c| !c.is_whitespace() && !matches!(c, ',' | ']' | '#'))
+        {
+            word.push(c);
+            self.advance();
+        }
+
+        match word.as_str() {
+            "true" => return Ok(Value::Bool(true)),
+            "false" => return Ok(Value::Bool(false)),
+            _ => {}
+        }
+
+        let digits = word.replace('_', "");
+
+        if let Ok(integer) = digits.parse::<i64>() {
+            return Ok(Value::Integer(integer));
+        }
+
+        if digits.contains(|c: char| c.is_ascii_digit())
+            && let Ok(float) = digits.parse::<f64>()
+        {
+            return Ok(Value::Float(float));
+        }
+
+        Err(self.error(&format!(
+            "'{}' is not a string, number or boolean; strings need quotes",
+            word
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_left_out_keep_their_defaults() {
+        let file = ModelFile::parse(
+            "lpu.toml",
+            "[text_model]\ntemperature = 1\n\n[embeddings_model]\ndimensions = 768\n",
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(file.text_model_overrides.temperature, Some(1.0));
+        assert_eq!(file.text_model_overrides.top_k, None);
+        assert_eq!(file.text_model_overrides.seed, None);
+        assert_eq!(file.text_model, None);
+        assert_eq!(file.embedding_model, None);
+        assert_eq!(file.embedding_dimensions, Some(768));
+        assert!(file.warnings.is_empty());
+    }
+
+    #[test]
+    fn an_empty_file_sets_nothing() {
+        let file = ModelFile::parse("lpu.toml", "# Nothing yet.\n", true).unwrap();
+
+        assert!(file.text_model_overrides.temperature.is_none());
+        assert!(file.embedding_dimensions.is_none());
+    }
+
+    #[test]
+    fn every_known_key_is_read() {
+        for key in TEXT_MODEL_KEYS {
+            let value = match *key {
+                "model" | "reasoning_format" => "\"x\"",
+                "preset" => "\"deterministic\"",
+                "stream" | "return_progress" | "timings_per_token" => "true",
+                "samplers" | "stop" => "[\"x\"]",
+                _ => "1",
+            };
+            let text = format!("[text_model]\n{} = {}\n", key, value);
+
+            let file = ModelFile::parse("lpu.toml", &text, true);
+            assert!(file.is_ok(), "{}", key);
+        }
+    }
+
+    #[test]
+    fn misspelt_keys_are_warnings_naming_the_nearest_key() {
+        let text = "[text_model]\ntemprature = 0.5\ntop_k = 40\n\n[embeddings_model]\ndimension = 768\nquality = 1\n";
+        let file = ModelFile::parse("lpu.toml", text, false).unwrap();
+
+        assert_eq!(
+            file.warnings,
+            [
+                "Unknown key 'temprature' in [text_model] at lpu.toml:2: 'temprature = 0.5'. Did you mean 'temperature'?",
+                "Unknown key 'dimension' in [embeddings_model] at lpu.toml:6: 'dimension = 768'. Did you mean 'dimensions'?",
+                "Unknown key 'quality' in [embeddings_model] at lpu.toml:7: 'quality = 1'.",
+            ]
+        );
+        assert_eq!(file.text_model_overrides.top_k, Some(40));
+        assert_eq!(file.text_model_overrides.temperature, None);
+    }
+
+    #[test]
+    fn strict_config_makes_unknown_keys_errors() {
+        let error = ModelFile::parse("lpu.toml", "[text_model]\ntemprature = 0.5\n", true)
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.contains("Did you mean 'temperature'?"), "{}", error);
+    }
+
+    #[test]
+    fn wrong_types_name_the_key_and_the_expected_type() {
+        let error = ModelFile::parse("lpu.toml", "[text_model]\ntop_k = \"forty\"\n", false)
+            .unwrap_err()
+            .to_string();
+
+        assert!(
+            error.contains("lpu.toml:2: text_model.top_k must be an integer from 0 to 4294967295"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn out_of_range_values_quote_the_line() {
+        let text = "[text_model]\ntemperature = 2.5\ntop_p = 0.9\npreset = \"creative\"\nmax_tokens = -5\n";
+        let file = ModelFile::parse("lpu.toml", text, false).unwrap();
+
+        assert_eq!(
+            file.warnings,
+            [
+                "Invalid value for text_model.temperature at lpu.toml:2: 'temperature = 2.5'. Expected a number between 0 and 2.",
+                "Invalid value for text_model.preset at lpu.toml:4: 'preset = \"creative\"'. Expected one of: deterministic.",
+                "Invalid value for text_model.max_tokens at lpu.toml:5: 'max_tokens = -5'. Expected a whole number between -1 and 2147483647.",
+            ]
+        );
+        assert!(ModelFile::parse("lpu.toml", text, true).is_err());
+    }
+
+    #[test]
+    fn unknown_tables_and_keys_outside_tables_are_warnings() {
+        let text = "temperature = 0\n\n[text_modle]\ntop_k = 1\n\n[server]\nport = 8080\n";
+        let file = ModelFile::parse("lpu.toml", text, false).unwrap();
+
+        assert_eq!(
+            file.warnings,
+            [
+                "Unknown table [text_modle] at lpu.toml:3: '[text_modle]'. Did you mean [text_model]?",
+                "Unknown table [server] at lpu.toml:6: '[server]'. Expected [text_model] or [embeddings_model].",
+                "Key 'temperature' at lpu.toml:1: 'temperature = 0' is not in a table. Did you mean to put it in [text_model]?",
+            ]
+        );
+        assert_eq!(file.text_model_overrides.top_k, None);
+        assert_eq!(file.text_model_overrides.temperature, None);
+    }
+
+    #[test]
+    fn malformed_files_are_errors_naming_the_line() {
+        for (text, expected) in [
+            (
+                "[text_model\n",
+                "lpu.toml:1: expected ']' after the table name.",
+            ),
+            (
+                "[text_model]\ntop_k 40\n",
+                "lpu.toml:2: expected '=' after 'top_k'.",
+            ),
+            (
+                "[text_model]\nmodel = \"lfm\n",
+                "lpu.toml:2: unterminated string.",
+            ),
+            (
+                "[text_model]\ntop_k = 1\ntop_k = 2\n",
+                "lpu.toml:3: text_model.top_k is set twice.",
+            ),
+        ] {
+            let error = ModelFile::parse("lpu.toml", text, false)
+                .unwrap_err()
+                .to_string();
+
+            assert!(error.contains(expected), "{:?}: {}", text, error);
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    fs::{create_dir_all, read_to_string},
+    path::{Path, PathBuf},
+};
+
+use miniserde::{Deserialize, Serialize, json};
+
+use crate::{
+    atomic_file,
+    exception::{BaseException, Exception},
+    processor::{
+        control_unit::LanguageLogicState,
+        memory::Memory,
+        registers::{ContextMessage, Registers, Value},
+    },
+};
+
+// A register value as saved in a checkpoint. Numbers are kept as text so every kind has the same
+// shape, and vectors as their comma-separated components.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CheckpointValue {
+    pub kind: String,
+    pub value: String,
+}
+
+impl From<&Value> for CheckpointValue {
+    fn from(value: &Value) -> Self {
+        let kind = match value {
+            Value::Text(_) => "text",
+            Value::Number(_) => "number",
+            Value::Float(_) => "float",
+            Value::Vector(_) => "vector",
+            Value::None => "none",
+        };
+
+        let value = match value {
+            Value::Vector(vector) => vector
+                .iter()
+                .map(|component| format!("{:?}", component))
+                .collect::<Vec<String>>()
+                .join(","),
+            value => value.to_string(),
+        };
+
+        CheckpointValue {
+            kind: kind.to_string(),
+            value,
+        }
+    }
+}
+
+impl TryFrom<&CheckpointValue> for Value {
+    type Error = Exception;
+
+    fn try_from(value: &CheckpointValue) -> Result<Self, Self::Error> {
+        let invalid = || {
+            Exception::Checkpoint(BaseException::new(
+                format!(
+                    "Invalid {} value '{}' in checkpoint.",
+                    value.kind, value.value
+                ),
+                None,
+            ))
+        };
+
+        match value.kind.as_str() {
+            "text" => Ok(Value::Text(value.value.clone())),
+            "number" => value
+                .value
+                .parse()
+                .map(Value::Number)
+                .map_err(|_| invalid()),
+            "float" => value.value.parse().map(Value::Float).map_err(|_| invalid()),
+            "vector" if value.value.is_empty() => Ok(Value::Vector(Vec::new())),
+            "vector" => value
+                .value
+                .split(',')
+                .map(|component| component.parse())
+                .collect::<Result<Vec<f32>, _>>()
+                .map(Value::Vector)
+                .map_err(|_| invalid()),
+            "none" => Ok(Value::None),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+// The state of a run after a CKPT instruction. Memory only holds the program, so registers, the data stack,
+// data memory and the language logic unit's settings and vector store are all that change.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub program_hash: u32,
+    pub instruction_pointer: usize,
+    pub registers: Vec<CheckpointValue>,
+    pub contexts: Vec<Vec<ContextMessage>>,
+    pub stack: Vec<CheckpointValue>,
+    pub data: Vec<CheckpointValue>,
+    // None in checkpoints saved before it was recorded, which resume with the configured settings.
+    pub language_logic: Option<LanguageLogicState>,
+}
+
+impl Checkpoint {
+    pub fn capture(
+        memory: &Memory,
+        registers: &Registers,
+        language_logic: LanguageLogicState,
+    ) -> Self {
+        Checkpoint {
+            program_hash: memory.hash(),
+            instruction_pointer: registers.get_instruction_pointer(),
+            registers: registers
+                .values()
+                .iter()
+                .map(CheckpointValue::from)
+                .collect(),
+            contexts: registers.contexts().to_vec(),
+            stack: registers
+                .stack()
+                .iter()
+                .map(CheckpointValue::from)
+                .collect(),
+            data: registers.data().iter().map(CheckpointValue::from).collect(),
+            language_logic: Some(language_logic),
+        }
+    }
+
+    pub fn restore(&self, memory: &Memory, registers: &mut Registers) -> Result<(), Exception> {
+        if self.program_hash != memory.hash() {
+            return Err(Exception::Checkpoint(BaseException::new(
+                "Checkpoint was saved by a different program. Rerun without --from-checkpoint."
+                    .to_string(),
+                None,
+            )));
+        }
+
+        let values = self
+            .registers
+            .iter()
+            .map(Value::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let stack = self
+            .stack
+            .iter()
+            .map(Value::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let data = self
+            .data
+            .iter()
+            .map(Value::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        registers.restore(values, self.contexts.clone(), stack, data)?;
+        registers.set_instruction_pointer(self.instruction_pointer);
+
+        Ok(())
+    }
+
+    // Names become file names, so they may not contain path separators or start with a dot.
+    fn path(directory: &str, name: &str) -> Result<PathBuf, Exception> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_' | '.'));
+
+        if !valid {
+            return Err(Exception::Checkpoint(BaseException::new(
+                format!(
+                    "Invalid checkpoint name '{}'. Use letters, digits, '-', '_' and '.'.",
+                    name
+                ),
+                None,
+            )));
+        }
+
+        Ok(Path::new(directory).join(format!("{}.json", name)))
+    }
+
+    pub fn save(&self, directory: &str, name: &str) -> Result<PathBuf, Exception> {
+        let path = Self::path(directory, name)?;
+
+        create_dir_all(directory).map_err(|e| {
+            Exception::Checkpoint(BaseException::caused_by(
+                format!("Failed to create checkpoint directory '{}'.", directory),
+                e,
+            ))
+        })?;
+
+        atomic_file::write(&path, json::to_string(self)).map_err(|e| {
+            Exception::Checkpoint(BaseException::caused_by(
+                format!("Failed to write checkpoint '{}'.", path.display()),
+                e,
+            ))
+        })?;
+
+        Ok(path)
+    }
+
+    pub fn load(directory: &str, name: &str) -> Result<Self, Exception> {
+        let path = Self::path(directory, name)?;
+
+        let text = read_to_string(&path).map_err(|e| {
+            Exception::Checkpoint(BaseException::caused_by(
+                format!("Failed to read checkpoint '{}'.", path.display()),
+                e,
+            ))
+        })?;
+
+        json::from_str(&text).map_err(|e| {
+            Exception::Checkpoint(BaseException::caused_by(
+                format!("Failed to parse checkpoint '{}'.", path.display()),
+                e,
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Assembler, Config, Processor, ScriptedBackend};
+
+    const PROGRAM: &str = "SETMODEL \"big\"\nSETEMB \"small-embed\"\nSETTEMP 30\nSETTOPP 80\nSETMAXTOK 64\nSETSYS \"Be brief.\"\nLS X1, \"stored text\"\nVPUT \"key\", X1\nCKPT \"settings\"\nLS X2, \"question\"\nINF X3, X2, C1\nVQUERY X4, X2, 1\nEXIT\n";
+
+    #[test]
+    fn a_resumed_run_keeps_the_models_sampling_system_prompt_and_vector_store() {
+        let byte_code = Assembler::new(PROGRAM.to_string(), "checkpoint.aasm")
+            .assemble()
+            .unwrap();
+        let directory = std::env::temp_dir().join(format!("lpu-ckpt-{}", std::process::id()));
+        let directory = directory.to_str().unwrap();
+
+        let mut config = Config::new("text", "embedding");
+        config.checkpoint_dir = Some(directory.to_string());
+
+        // Stops after the CKPT, before the instructions that send requests.
+        let mut first = config.clone();
+        first.max_instructions = Some(9);
+        let mut processor = Processor::new(first);
+        processor.load(&byte_code).unwrap();
+        assert!(processor.run().is_err());
+
+        let mut backend = ScriptedBackend::new();
+        backend.push_response("answer");
+        backend.set_embedding("stored text", vec![1.0, 0.0]);
+        backend.set_embedding("question", vec![1.0, 0.1]);
+        let (requests, embedding_requests) = (backend.requests(), backend.embedding_requests());
+
+        let mut processor = Processor::new(config);
+        processor.set_backend(Box::new(backend));
+        processor.load(&byte_code).unwrap();
+        processor.restore_checkpoint(directory, "settings").unwrap();
+        processor.run().unwrap();
+        std::fs::remove_dir_all(directory).unwrap();
+
+        let request = &requests.lock().unwrap()[0];
+        assert_eq!(request.model, "big");
+        assert_eq!(
+            (request.temperature, request.top_p, request.max_tokens),
+            (0.3, 0.8, 64)
+        );
+        assert_eq!(request.messages[0].content, "Be brief.");
+
+        let embedding_request = &embedding_requests.lock().unwrap()[0];
+        assert_eq!(embedding_request.model, "small-embed");
+        assert_eq!(embedding_request.input, ["question", "stored text"]);
+
+        assert_eq!(processor.register(4).unwrap().to_string(), "stored text");
+    }
+
+    #[test]
+    fn checkpoints_saved_before_the_language_logic_state_still_load() {
+        let text = r#"{"program_hash":1,"instruction_pointer":8,"registers":[],"contexts":[],"stack":[],"data":[]}"#;
+        let checkpoint: Checkpoint = json::from_str(text).unwrap();
+
+        assert!(checkpoint.language_logic.is_none());
+    }
+}
@@ -0,0 +1,91 @@
+// Places text may be split, from least to most preferred.
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Boundary {
+    Word,
+    Sentence,
+    Paragraph,
+}
+
+// The boundary at the whitespace that starts rest, when the text before it ends with previous.
+fn boundary(rest: &str, previous: char) -> Boundary {
+    if rest.starts_with("\n\n") || rest.starts_with("\r\n\r\n") {
+        Boundary::Paragraph
+    } else if matches!(previous, '.' | '!' | '?') {
+        Boundary::Sentence
+    } else {
+        Boundary::Word
+    }
+}
+
+// Splits text into chunks of at most size characters for CHUNK. Each chunk ends at the last
+// paragraph break that fits, failing that at the last sentence end, and failing that at the last
+// space, so a word is only split when it is longer than size. Chunks are trimmed, and text that is
+// only whitespace has no chunks. Sizes count characters, so a chunk never ends inside a UTF-8
+// sequence. size must be at least 1.
+pub fn split(text: &str, size: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = text.trim();
+
+    while !rest.is_empty() {
+        // Byte offset of the first character past the limit. None when the rest fits.
+        let Some((limit, _)) = rest.char_indices().nth(size) else {
+            chunks.push(rest);
+            break;
+        };
+
+        // The whitespace at the limit counts, since a chunk may end right before it.
+        let mut best: Option<(Boundary, usize)> = None;
+        let mut previous = None;
+
+        for (offset, character) in rest.char_indices().take(size + 1) {
+            if let Some(previous) = previous
+                && character.is_whitespace()
+            {
+                let kind = boundary(&rest[offset..], previous);
+
+                if best.is_none_or(|(best, _)| kind >= best) {
+                    best = Some((kind, offset));
+                }
+            }
+            previous = Some(character);
+        }
+
+        let end = best.map_or(limit, |(_, offset)| offset);
+        chunks.push(rest[..end].trim_end());
+        rest = rest[end..].trim_start();
+    }
+
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_end_at_the_strongest_boundary_that_fits() {
+        let text = "One. Two three.\n\nFour five. Six";
+        assert_eq!(split(text, 20), ["One. Two three.", "Four five. Six"]);
+        assert_eq!(split(text, 12), ["One.", "Two three.", "Four five.", "Six"]);
+        assert_eq!(split("alpha beta gamma", 12), ["alpha beta", "gamma"]);
+        assert_eq!(split("alpha beta", 10), ["alpha beta"]);
+    }
+
+    #[test]
+    fn only_words_longer_than_a_chunk_are_split() {
+        assert_eq!(split("a abcdefgh b", 4), ["a", "abcd", "efgh", "b"]);
+    }
+
+    #[test]
+    fn sizes_count_characters_not_bytes() {
+        assert_eq!(split("héllo wörld", 5), ["héllo", "wörld"]);
+        assert_eq!(split("日本語のテキスト", 3), ["日本語", "のテキ", "スト"]);
+        assert_eq!(split("🙂🙂🙂 🙂", 2), ["🙂🙂", "🙂", "🙂"]);
+    }
+
+    #[test]
+    fn whitespace_only_text_has_no_chunks() {
+        assert!(split("", 3).is_empty());
+        assert!(split(" \n\n\t", 3).is_empty());
+    }
+}
@@ -0,0 +1,96 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    assembler::unpack_string,
+    constants::DEBUG_INFO_MAGIC,
+    exception::{BaseException, Exception},
+};
+
+// Words per entry: byte code index, data segment offset of the file path, line and column.
+const ENTRY_SIZE: usize = 4;
+
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+// Source locations of instructions, by byte code index. Empty for byte code built without debug info.
+#[derive(Default)]
+pub struct DebugInfo {
+    locations: HashMap<usize, SourceLocation>,
+}
+
+impl DebugInfo {
+    fn invalid(message: String) -> Exception {
+        Exception::ControlUnit(BaseException::new(
+            format!("Invalid debug info: {}", message),
+            None,
+        ))
+    }
+
+    fn word(section: &[[u8; 4]], index: usize) -> Result<u32, Exception> {
+        section
+            .get(index)
+            .map(|word| u32::from_be_bytes(*word))
+            .ok_or_else(|| Self::invalid(format!("section ends at word {}.", index)))
+    }
+
+    fn path(data_segment: &[[u8; 4]], offset: usize) -> Result<String, Exception> {
+        let words = data_segment.get(offset..).ok_or_else(|| {
+            Self::invalid(format!("file path offset {} is out of bounds.", offset))
+        })?;
+        let bytes = unpack_string(words).ok_or_else(|| {
+            Self::invalid(format!(
+                "file path at offset {} runs past the data segment.",
+                offset
+            ))
+        })?;
+
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    // Reads the section written after the data segment: a magic word, the entry count and the entries.
+    pub fn read(section: &[[u8; 4]], data_segment: &[[u8; 4]]) -> Result<Self, Exception> {
+        if Self::word(section, 0)? != DEBUG_INFO_MAGIC {
+            return Err(Self::invalid(
+                "unexpected words after the data segment.".to_string(),
+            ));
+        }
+
+        let count = Self::word(section, 1)? as usize;
+
+        if section.len() != 2 + count.saturating_mul(ENTRY_SIZE) {
+            return Err(Self::invalid(format!(
+                "{} entries do not fit a section of {} words.",
+                count,
+                section.len()
+            )));
+        }
+
+        let mut locations = HashMap::new();
+
+        for entry in section[2..].chunks_exact(ENTRY_SIZE) {
+            let address = Self::word(entry, 0)? as usize;
+            let location = SourceLocation {
+                file: Self::path(data_segment, Self::word(entry, 1)? as usize)?,
+                line: Self::word(entry, 2)?,
+                column: Self::word(entry, 3)?,
+            };
+
+            locations.insert(address, location);
+        }
+
+        Ok(DebugInfo { locations })
+    }
+
+    pub fn location(&self, address: usize) -> Option<&SourceLocation> {
+        self.locations.get(&address)
+    }
+}
@@ -1,16 +1,34 @@
 use crate::{
-    assembler::opcode::OpCode,
+    assembler::{
+        opcode::{AddressMode, ImmediateType, OpCode},
+        string_words, unpack_string,
+    },
     exception::{BaseException, Exception},
     processor::{
         control_unit::instruction::{
-            BranchInstruction, BranchType, ContextDropInstruction, ContextPopInstruction,
-            ContextPushInstruction, EvalulateInstruction, ExitInstruction, InferenceInstruction,
-            Instruction, LoadContentInstruction, LoadImmediateInstruction, LoadStringInstruction,
-            MoveContextInstruction, MoveInstruction, PrintContextInstruction, PrintInstruction,
-            PrintLineInstruction, SimilarityInstruction, SubtractImmediateInstruction,
+            Address, ArithmeticImmediateInstruction, ArithmeticInstruction, ArithmeticOperation,
+            AskInstruction, BranchInstruction, BranchSimilarInstruction, BranchType,
+            CheckpointInstruction, ChunkInstruction, ClassifyInstruction, ConcatenateInstruction,
+            ContextDropInstruction, ContextInsertInstruction, ContextLengthInstruction,
+            ContextLoadInstruction, ContextPeekInstruction, ContextPopInstruction,
+            ContextPushInstruction, ContextSaveInstruction, ContextTrimInstruction,
+            EmbedInstruction, EscapeInstruction, EscapeSyntax, EvalulateInstruction,
+            ExitInstruction, ExitStatus, FormatNumberInstruction, InferenceInstruction,
+            InputInstruction, Instruction, JsonGetInstruction, JsonInstruction, JumpInstruction,
+            LoadArgumentCountInstruction, LoadArgumentInstruction, LoadContentInstruction,
+            LoadDirectoryInstruction, LoadEnvironmentInstruction, LoadImmediateInstruction,
+            LoadPositionalArgumentInstruction, LoadStringInstruction, LoadWordInstruction,
+            MoveContextInstruction, MoveInstruction, Placeholder, PrintContextInstruction,
+            PrintErrorInstruction, PrintInstruction, PrintLineInstruction, SamplingParameter,
+            SamplingValue, ScoreInstruction, SetModelInstruction, SetSamplingInstruction,
+            SetSystemPromptInstruction, SimilarityInstruction, StackPeekInstruction,
+            StackPopInstruction, StackPushInstruction, StoreWordInstruction, SystemPrompt,
+            TargetLanguage, TextInstruction, TextOperation, TokenCountInstruction,
+            TranslateInstruction, VectorKey, VectorPutInstruction, VectorQueryInstruction,
+            WriteFileInstruction,
         },
         memory::Memory,
-        registers::Registers,
+        registers::{Registers, Value},
     },
 };
 
@@ -33,38 +51,73 @@ impl Decoder {
         pointer: usize,
         context: &str,
     ) -> Result<String, Exception> {
-        let mut bytes = Vec::new();
-        let mut address = pointer + registers.get_data_section_pointer();
-
-        loop {
-            let word = memory.read(address).map_err(|e| {
-                Exception::Decoder(BaseException::caused_by(
-                    format!("{}: failed to read byte at address {}", context, address),
-                    e,
-                ))
-            })?;
-            let value: u8 = u32::from_be_bytes(*word).try_into().map_err(|e| {
-                Exception::Decoder(BaseException::caused_by(
-                    format!(
-                        "{}: value at address {} does not fit in a byte",
-                        context, address
-                    ),
-                    format!("{}", e),
-                ))
-            })?;
+        let start = pointer + registers.get_data_section_pointer();
+        let words = memory.read_from(start).map_err(|e| {
+            Exception::Decoder(BaseException::caused_by(
+                format!("{}: failed to read string at address {}", context, start),
+                e,
+            ))
+        })?;
+        let bytes = unpack_string(words).ok_or_else(|| {
+            Exception::Decoder(BaseException::new(
+                format!(
+                    "{}: string at address {} runs past the end of the byte code",
+                    context, start
+                ),
+                None,
+            ))
+        })?;
+
+        // Invalid bytes in the data section mean the artifact is corrupt, so never decode lossily.
+        String::from_utf8(bytes.to_vec()).map_err(|e| {
+            let offset = e.utf8_error().valid_up_to();
 
-            if value == 0 {
-                return String::from_utf8(bytes).map_err(|e| {
+            Exception::Decoder(BaseException::caused_by(
+                format!(
+                    "{}: invalid UTF-8 at address {} (byte offset {} of string at address {})",
+                    context,
+                    start + 1 + offset / 4,
+                    offset,
+                    start
+                ),
+                e.to_string(),
+            ))
+        })
+    }
+
+    fn placeholders(
+        memory: &Memory,
+        registers: &Registers,
+        pointer: usize,
+        count: u32,
+        context: &str,
+    ) -> Result<Vec<Placeholder>, Exception> {
+        let base = pointer + registers.get_data_section_pointer();
+        let read_word = |address: usize| -> Result<u32, Exception> {
+            memory
+                .read(address)
+                .map(|word| u32::from_be_bytes(*word))
+                .map_err(|e| {
                     Exception::Decoder(BaseException::caused_by(
-                        format!("{}: invalid UTF-8 at address {}", context, address),
-                        e.to_string(),
+                        format!(
+                            "{}: failed to read placeholder at address {}",
+                            context, address
+                        ),
+                        e,
                     ))
-                });
-            }
+                })
+        };
 
-            bytes.push(value);
-            address += 1;
-        }
+        (0..count as usize)
+            .map(|index| {
+                let address = base + index * 2;
+
+                Ok(Placeholder {
+                    offset: read_word(address)? as usize,
+                    register: read_word(address + 1)?,
+                })
+            })
+            .collect()
     }
 
     fn immediate(
@@ -76,7 +129,7 @@ impl Decoder {
         let register = u32::from_be_bytes(instruction_bytes[1]);
 
         match op_code {
-            OpCode::LoadString | OpCode::LoadContent => {
+            OpCode::LoadString | OpCode::LoadContent | OpCode::LoadArgument => {
                 let string_pointer = u32::from_be_bytes(instruction_bytes[2]) as usize;
                 let string = Self::string(
                     memory,
@@ -86,31 +139,118 @@ impl Decoder {
                 )?;
 
                 if op_code == OpCode::LoadString {
+                    // The placeholder table follows the string's last word.
+                    let placeholders = Self::placeholders(
+                        memory,
+                        registers,
+                        string_pointer + string_words(string.len()),
+                        u32::from_be_bytes(instruction_bytes[3]),
+                        &format!("Decoding placeholders for {:?}", op_code),
+                    )?;
+
                     Ok(Instruction::LoadString(LoadStringInstruction {
                         destination_register: register,
                         value: string,
+                        placeholders,
                     }))
-                } else {
+                } else if op_code == OpCode::LoadContent {
                     Ok(Instruction::LoadContent(LoadContentInstruction {
                         destination_register: register,
                         path: string,
                     }))
+                } else {
+                    Ok(Instruction::LoadArgument(LoadArgumentInstruction {
+                        destination_register: register,
+                        name: string,
+                    }))
                 }
             }
-            OpCode::LoadImmediate => Ok(Instruction::LoadImmediate(LoadImmediateInstruction {
-                destination_register: register,
-                value: u32::from_be_bytes(instruction_bytes[2]),
-            })),
+            OpCode::LoadImmediate => {
+                let immediate_type = u32::from_be_bytes(instruction_bytes[3]);
+                let value = match ImmediateType::try_from(immediate_type).map_err(|e| {
+                    Exception::Decoder(BaseException::caused_by(
+                        format!("Failed to decode immediate type: 0x{:08X}", immediate_type),
+                        e,
+                    ))
+                })? {
+                    ImmediateType::Integer => {
+                        Value::Number(i32::from_be_bytes(instruction_bytes[2]))
+                    }
+                    ImmediateType::Float => Value::Float(f32::from_be_bytes(instruction_bytes[2])),
+                };
+
+                Ok(Instruction::LoadImmediate(LoadImmediateInstruction {
+                    destination_register: register,
+                    value,
+                }))
+            }
+            OpCode::LoadPositionalArgument => Ok(Instruction::LoadPositionalArgument(
+                LoadPositionalArgumentInstruction {
+                    destination_register: register,
+                    index: i32::from_be_bytes(instruction_bytes[2]),
+                },
+            )),
             OpCode::Move => Ok(Instruction::Move(MoveInstruction {
                 destination_register: register,
                 source_register: u32::from_be_bytes(instruction_bytes[2]),
             })),
-            OpCode::SubtractImmediate => Ok(Instruction::SubtractImmediate(
-                SubtractImmediateInstruction {
-                    source_register: register,
-                    value: u32::from_be_bytes(instruction_bytes[2]),
-                },
-            )),
+            OpCode::Escape => {
+                let syntax = match u32::from_be_bytes(instruction_bytes[3]) {
+                    0 => EscapeSyntax::Interpolation,
+                    1 => EscapeSyntax::Positional,
+                    syntax => {
+                        return Err(Exception::Decoder(BaseException::new(
+                            format!(
+                                "Invalid escape syntax {}. Expected 0 (ls) or 1 (fmt).",
+                                syntax
+                            ),
+                            None,
+                        )));
+                    }
+                };
+
+                Ok(Instruction::Escape(EscapeInstruction {
+                    destination_register: register,
+                    source_register: u32::from_be_bytes(instruction_bytes[2]),
+                    syntax,
+                }))
+            }
+            OpCode::Embed => {
+                // Byte code from before EMB took a count pads it with zero.
+                let count = u32::from_be_bytes(instruction_bytes[3]).max(1);
+
+                if count > 33 {
+                    return Err(Exception::Decoder(BaseException::new(
+                        format!("EMB count {} is more than the 33 registers.", count),
+                        None,
+                    )));
+                }
+
+                Ok(Instruction::Embed(EmbedInstruction {
+                    destination_register: register,
+                    source_register: u32::from_be_bytes(instruction_bytes[2]),
+                    count,
+                }))
+            }
+            OpCode::AddImmediate
+            | OpCode::SubtractImmediate
+            | OpCode::MultiplyImmediate
+            | OpCode::DivideImmediate => {
+                let operation = match op_code {
+                    OpCode::AddImmediate => ArithmeticOperation::Add,
+                    OpCode::SubtractImmediate => ArithmeticOperation::Subtract,
+                    OpCode::MultiplyImmediate => ArithmeticOperation::Multiply,
+                    _ => ArithmeticOperation::Divide,
+                };
+
+                Ok(Instruction::ArithmeticImmediate(
+                    ArithmeticImmediateInstruction {
+                        operation,
+                        source_register: register,
+                        value: i32::from_be_bytes(instruction_bytes[2]),
+                    },
+                ))
+            }
             _ => Err(Exception::Decoder(BaseException::new(
                 format!(
                     "Failed to decode immediate instruction: invalid opcode '{:?}'.",
@@ -151,13 +291,303 @@ impl Decoder {
         }))
     }
 
-    fn no_register(op_code: OpCode) -> Result<Instruction, Exception> {
+    // The threshold shares the second register's word, in its upper 16 bits.
+    fn branch_similar(instruction_bytes: [[u8; 4]; 4]) -> Result<Instruction, Exception> {
+        let packed = u32::from_be_bytes(instruction_bytes[2]);
+
+        Ok(Instruction::BranchSimilar(BranchSimilarInstruction {
+            source_register_1: u32::from_be_bytes(instruction_bytes[1]),
+            source_register_2: packed & 0xFFFF,
+            threshold: packed >> 16,
+            instruction_pointer_jump_index: u32::from_be_bytes(instruction_bytes[3]),
+        }))
+    }
+
+    fn classify(
+        memory: &Memory,
+        registers: &Registers,
+        instruction_bytes: [[u8; 4]; 4],
+    ) -> Result<Instruction, Exception> {
+        let packed = u32::from_be_bytes(instruction_bytes[1]);
+
+        Ok(Instruction::Classify(ClassifyInstruction {
+            destination_register: packed & 0xFFFF,
+            source_register: u32::from_be_bytes(instruction_bytes[2]),
+            labels: Self::string(
+                memory,
+                registers,
+                u32::from_be_bytes(instruction_bytes[3]) as usize,
+                "Decoding string for Classify",
+            )?,
+            index_register: packed >> 16,
+        }))
+    }
+
+    fn context_trim(instruction_bytes: [[u8; 4]; 4]) -> Result<Instruction, Exception> {
+        Ok(Instruction::ContextTrim(ContextTrimInstruction {
+            destination_context_register: u32::from_be_bytes(instruction_bytes[1]),
+            count: u32::from_be_bytes(instruction_bytes[2]),
+        }))
+    }
+
+    fn context_insert(
+        memory: &Memory,
+        registers: &Registers,
+        instruction_bytes: [[u8; 4]; 4],
+    ) -> Result<Instruction, Exception> {
+        let packed = u32::from_be_bytes(instruction_bytes[1]);
+
+        Ok(Instruction::ContextInsert(ContextInsertInstruction {
+            destination_context_register: packed & 0xFFFF,
+            index: u32::from_be_bytes(instruction_bytes[2]),
+            source_register: packed >> 16,
+            role: Self::string(
+                memory,
+                registers,
+                u32::from_be_bytes(instruction_bytes[3]) as usize,
+                "Decoding string for ContextInsert",
+            )?,
+        }))
+    }
+
+    fn context_save(
+        memory: &Memory,
+        registers: &Registers,
+        instruction_bytes: [[u8; 4]; 4],
+    ) -> Result<Instruction, Exception> {
+        Ok(Instruction::ContextSave(ContextSaveInstruction {
+            path: Self::string(
+                memory,
+                registers,
+                u32::from_be_bytes(instruction_bytes[2]) as usize,
+                "Decoding path for ContextSave",
+            )?,
+            source_context_register: u32::from_be_bytes(instruction_bytes[1]),
+        }))
+    }
+
+    // The upper 16 bits of the first word say whether the last word holds a label address.
+    fn context_load(
+        memory: &Memory,
+        registers: &Registers,
+        instruction_bytes: [[u8; 4]; 4],
+    ) -> Result<Instruction, Exception> {
+        let packed = u32::from_be_bytes(instruction_bytes[1]);
+
+        Ok(Instruction::ContextLoad(ContextLoadInstruction {
+            destination_context_register: packed & 0xFFFF,
+            path: Self::string(
+                memory,
+                registers,
+                u32::from_be_bytes(instruction_bytes[2]) as usize,
+                "Decoding path for ContextLoad",
+            )?,
+            missing_jump_index: (packed >> 16 != 0)
+                .then(|| u32::from_be_bytes(instruction_bytes[3])),
+        }))
+    }
+
+    fn chunk(instruction_bytes: [[u8; 4]; 4]) -> Result<Instruction, Exception> {
+        let registers = u32::from_be_bytes(instruction_bytes[1]);
+
+        Ok(Instruction::Chunk(ChunkInstruction {
+            destination_register: registers & 0xFFFF,
+            source_register: registers >> 16,
+            size: u32::from_be_bytes(instruction_bytes[2]),
+            address: u32::from_be_bytes(instruction_bytes[3]),
+        }))
+    }
+
+    fn jump(instruction_bytes: [[u8; 4]; 4]) -> Result<Instruction, Exception> {
+        Ok(Instruction::Jump(JumpInstruction {
+            instruction_pointer_jump_index: u32::from_be_bytes(instruction_bytes[1]),
+        }))
+    }
+
+    // Byte code built before EXIT took a status has zeros here, which decode as 'exit 0'.
+    fn exit(instruction_bytes: [[u8; 4]; 4]) -> Result<Instruction, Exception> {
+        let status_word = u32::from_be_bytes(instruction_bytes[1]);
+        let status_mode = u32::from_be_bytes(instruction_bytes[2]);
+
+        let status = match AddressMode::try_from(status_mode).map_err(|e| {
+            Exception::Decoder(BaseException::caused_by(
+                format!("Failed to decode exit status mode: 0x{:08X}", status_mode),
+                e,
+            ))
+        })? {
+            AddressMode::Immediate => ExitStatus::Immediate(status_word.cast_signed()),
+            AddressMode::Register => ExitStatus::Register(status_word),
+        };
+
+        Ok(Instruction::Exit(ExitInstruction { status }))
+    }
+
+    fn set_sampling(
+        op_code: OpCode,
+        instruction_bytes: [[u8; 4]; 4],
+    ) -> Result<Instruction, Exception> {
+        let value_word = u32::from_be_bytes(instruction_bytes[1]);
+        let value_mode = u32::from_be_bytes(instruction_bytes[2]);
+
+        let value = match AddressMode::try_from(value_mode).map_err(|e| {
+            Exception::Decoder(BaseException::caused_by(
+                format!(
+                    "Failed to decode {:?} value mode: 0x{:08X}",
+                    op_code, value_mode
+                ),
+                e,
+            ))
+        })? {
+            AddressMode::Immediate => SamplingValue::Immediate(value_word.cast_signed()),
+            AddressMode::Register => SamplingValue::Register(value_word),
+        };
+
+        let parameter = match op_code {
+            OpCode::SetTemperature => SamplingParameter::Temperature,
+            OpCode::SetTopP => SamplingParameter::TopP,
+            OpCode::SetMaxTokens => SamplingParameter::MaxTokens,
+            _ => {
+                return Err(Exception::Decoder(BaseException::new(
+                    format!(
+                        "Failed to decode sampling instruction: invalid opcode '{:?}'.",
+                        op_code
+                    ),
+                    None,
+                )));
+            }
+        };
+
+        Ok(Instruction::SetSampling(SetSamplingInstruction {
+            parameter,
+            value,
+        }))
+    }
+
+    fn set_system_prompt(
+        memory: &Memory,
+        registers: &Registers,
+        instruction_bytes: [[u8; 4]; 4],
+    ) -> Result<Instruction, Exception> {
+        let value_word = u32::from_be_bytes(instruction_bytes[1]);
+        let value_mode = u32::from_be_bytes(instruction_bytes[2]);
+
+        let prompt = match AddressMode::try_from(value_mode).map_err(|e| {
+            Exception::Decoder(BaseException::caused_by(
+                format!("Failed to decode system prompt mode: 0x{:08X}", value_mode),
+                e,
+            ))
+        })? {
+            AddressMode::Immediate => SystemPrompt::Text(Self::string(
+                memory,
+                registers,
+                value_word as usize,
+                "Decoding string for SetSystemPrompt",
+            )?),
+            AddressMode::Register => SystemPrompt::Register(value_word),
+        };
+
+        Ok(Instruction::SetSystemPrompt(SetSystemPromptInstruction {
+            prompt,
+        }))
+    }
+
+    fn vector_put(
+        memory: &Memory,
+        registers: &Registers,
+        instruction_bytes: [[u8; 4]; 4],
+    ) -> Result<Instruction, Exception> {
+        let key_word = u32::from_be_bytes(instruction_bytes[1]);
+        let key_mode = u32::from_be_bytes(instruction_bytes[2]);
+
+        let key = match AddressMode::try_from(key_mode).map_err(|e| {
+            Exception::Decoder(BaseException::caused_by(
+                format!("Failed to decode vector key mode: 0x{:08X}", key_mode),
+                e,
+            ))
+        })? {
+            AddressMode::Immediate => VectorKey::Text(Self::string(
+                memory,
+                registers,
+                key_word as usize,
+                "Decoding string for VectorPut",
+            )?),
+            AddressMode::Register => VectorKey::Register(key_word),
+        };
+
+        Ok(Instruction::VectorPut(VectorPutInstruction {
+            key,
+            source_register: u32::from_be_bytes(instruction_bytes[3]),
+        }))
+    }
+
+    fn translate(
+        memory: &Memory,
+        registers: &Registers,
+        instruction_bytes: [[u8; 4]; 4],
+    ) -> Result<Instruction, Exception> {
+        let packed = u32::from_be_bytes(instruction_bytes[1]);
+        let language_word = u32::from_be_bytes(instruction_bytes[2]);
+        let language_mode = u32::from_be_bytes(instruction_bytes[3]);
+
+        let language = match AddressMode::try_from(language_mode).map_err(|e| {
+            Exception::Decoder(BaseException::caused_by(
+                format!(
+                    "Failed to decode target language mode: 0x{:08X}",
+                    language_mode
+                ),
+                e,
+            ))
+        })? {
+            AddressMode::Immediate => TargetLanguage::Text(Self::string(
+                memory,
+                registers,
+                language_word as usize,
+                "Decoding string for Translate",
+            )?),
+            AddressMode::Register => TargetLanguage::Register(language_word),
+        };
+
+        Ok(Instruction::Translate(TranslateInstruction {
+            destination_register: packed & 0xFFFF,
+            source_register: packed >> 16,
+            language,
+        }))
+    }
+
+    fn vector_query(instruction_bytes: [[u8; 4]; 4]) -> Result<Instruction, Exception> {
+        Ok(Instruction::VectorQuery(VectorQueryInstruction {
+            destination_register: u32::from_be_bytes(instruction_bytes[1]),
+            source_register: u32::from_be_bytes(instruction_bytes[2]),
+            count: u32::from_be_bytes(instruction_bytes[3]),
+        }))
+    }
+
+    fn no_register_string(
+        memory: &Memory,
+        registers: &Registers,
+        op_code: OpCode,
+        instruction_bytes: [[u8; 4]; 4],
+    ) -> Result<Instruction, Exception> {
+        let string = Self::string(
+            memory,
+            registers,
+            u32::from_be_bytes(instruction_bytes[1]) as usize,
+            &format!("Decoding string for {:?}", op_code),
+        )?;
+
         match op_code {
-            // Control flow.
-            OpCode::Exit => Ok(Instruction::Exit(ExitInstruction)),
+            OpCode::Checkpoint => Ok(Instruction::Checkpoint(CheckpointInstruction {
+                name: string,
+            })),
+            OpCode::SetModel | OpCode::SetEmbeddingModel => {
+                Ok(Instruction::SetModel(SetModelInstruction {
+                    model: string,
+                    embedding: op_code == OpCode::SetEmbeddingModel,
+                }))
+            }
             _ => Err(Exception::Decoder(BaseException::new(
                 format!(
-                    "Failed to decode zero-register instruction: invalid opcode '{:?}'.",
+                    "Failed to decode zero-register-string instruction: invalid opcode '{:?}'.",
                     op_code
                 ),
                 None,
@@ -165,6 +595,94 @@ impl Decoder {
         }
     }
 
+    fn memory_access(
+        op_code: OpCode,
+        instruction_bytes: [[u8; 4]; 4],
+    ) -> Result<Instruction, Exception> {
+        let register = u32::from_be_bytes(instruction_bytes[1]);
+        let address_word = u32::from_be_bytes(instruction_bytes[2]);
+        let address_mode = u32::from_be_bytes(instruction_bytes[3]);
+
+        let address = match AddressMode::try_from(address_mode).map_err(|e| {
+            Exception::Decoder(BaseException::caused_by(
+                format!("Failed to decode address mode: 0x{:08X}", address_mode),
+                e,
+            ))
+        })? {
+            AddressMode::Immediate => Address::Immediate(address_word),
+            AddressMode::Register => Address::Register(address_word),
+        };
+
+        match op_code {
+            OpCode::StoreWord => Ok(Instruction::StoreWord(StoreWordInstruction {
+                source_register: register,
+                address,
+            })),
+            OpCode::LoadWord => Ok(Instruction::LoadWord(LoadWordInstruction {
+                destination_register: register,
+                address,
+            })),
+            _ => Err(Exception::Decoder(BaseException::new(
+                format!(
+                    "Failed to decode memory access instruction: invalid opcode '{:?}'.",
+                    op_code
+                ),
+                None,
+            ))),
+        }
+    }
+
+    // The last word is 1 when the instruction has a prompt string and 0 when it does not.
+    fn input(
+        memory: &Memory,
+        registers: &Registers,
+        instruction_bytes: [[u8; 4]; 4],
+    ) -> Result<Instruction, Exception> {
+        let prompt = match u32::from_be_bytes(instruction_bytes[3]) {
+            0 => None,
+            1 => Some(Self::string(
+                memory,
+                registers,
+                u32::from_be_bytes(instruction_bytes[2]) as usize,
+                "Decoding prompt for Input",
+            )?),
+            flag => {
+                return Err(Exception::Decoder(BaseException::new(
+                    format!(
+                        "Failed to decode input instruction: invalid prompt flag {}.",
+                        flag
+                    ),
+                    None,
+                )));
+            }
+        };
+
+        Ok(Instruction::Input(InputInstruction {
+            destination_register: u32::from_be_bytes(instruction_bytes[1]),
+            prompt,
+        }))
+    }
+
+    fn write_file(
+        memory: &Memory,
+        registers: &Registers,
+        op_code: OpCode,
+        instruction_bytes: [[u8; 4]; 4],
+    ) -> Result<Instruction, Exception> {
+        let path = Self::string(
+            memory,
+            registers,
+            u32::from_be_bytes(instruction_bytes[2]) as usize,
+            &format!("Decoding path for {:?}", op_code),
+        )?;
+
+        Ok(Instruction::WriteFile(WriteFileInstruction {
+            path,
+            source_register: u32::from_be_bytes(instruction_bytes[1]),
+            append: op_code == OpCode::AppendFile,
+        }))
+    }
+
     fn single_register(
         op_code: OpCode,
         instruction_bytes: [[u8; 4]; 4],
@@ -179,6 +697,9 @@ impl Decoder {
             OpCode::PrintLine => Ok(Instruction::PrintLine(PrintLineInstruction {
                 source_register: register,
             })),
+            OpCode::PrintError => Ok(Instruction::PrintError(PrintErrorInstruction {
+                source_register: register,
+            })),
             OpCode::PrintContext => Ok(Instruction::PrintContext(PrintContextInstruction {
                 source_context_register: register,
             })),
@@ -186,6 +707,22 @@ impl Decoder {
             OpCode::ContextDrop => Ok(Instruction::ContextDrop(ContextDropInstruction {
                 source_context_register: register,
             })),
+            // Data movement.
+            OpCode::LoadArgumentCount => Ok(Instruction::LoadArgumentCount(
+                LoadArgumentCountInstruction {
+                    destination_register: register,
+                },
+            )),
+            // Stack operations.
+            OpCode::StackPush => Ok(Instruction::StackPush(StackPushInstruction {
+                source_register: register,
+            })),
+            OpCode::StackPop => Ok(Instruction::StackPop(StackPopInstruction {
+                destination_register: register,
+            })),
+            OpCode::StackPeek => Ok(Instruction::StackPeek(StackPeekInstruction {
+                destination_register: register,
+            })),
             _ => Err(Exception::Decoder(BaseException::new(
                 format!(
                     "Failed to decode single-register instruction: invalid opcode '{:?}'.",
@@ -212,6 +749,33 @@ impl Decoder {
                 destination_context_register: destination_register,
                 source_context_register: source_register,
             })),
+            OpCode::ContextLength => Ok(Instruction::ContextLength(ContextLengthInstruction {
+                destination_register,
+                source_context_register: source_register,
+            })),
+            OpCode::ContextPeek => Ok(Instruction::ContextPeek(ContextPeekInstruction {
+                destination_register,
+                source_context_register: source_register,
+            })),
+            // String operations.
+            OpCode::Length | OpCode::Upper | OpCode::Lower | OpCode::Trim => {
+                let operation = match op_code {
+                    OpCode::Length => TextOperation::Length,
+                    OpCode::Upper => TextOperation::Upper,
+                    OpCode::Lower => TextOperation::Lower,
+                    _ => TextOperation::Trim,
+                };
+
+                Ok(Instruction::Text(TextInstruction {
+                    operation,
+                    destination_register,
+                    source_register,
+                }))
+            }
+            OpCode::TokenCount => Ok(Instruction::TokenCount(TokenCountInstruction {
+                destination_register,
+                source_register,
+            })),
             _ => Err(Exception::Decoder(BaseException::new(
                 format!(
                     "Failed to decode double-register instruction: invalid opcode '{:?}'.",
@@ -228,7 +792,7 @@ impl Decoder {
         op_code: OpCode,
         instruction_bytes: [[u8; 4]; 4],
     ) -> Result<Instruction, Exception> {
-        let destination_context_register = u32::from_be_bytes(instruction_bytes[1]);
+        let destination_register = u32::from_be_bytes(instruction_bytes[1]);
         let source_register = u32::from_be_bytes(instruction_bytes[2]);
         let string_pointer = u32::from_be_bytes(instruction_bytes[3]) as usize;
 
@@ -236,15 +800,35 @@ impl Decoder {
             memory,
             registers,
             string_pointer,
-            &format!("Decoding role string for {:?}", op_code),
+            &format!("Decoding string for {:?}", op_code),
         )?;
 
         match op_code {
             OpCode::ContextPush => Ok(Instruction::ContextPush(ContextPushInstruction {
-                destination_context_register,
+                destination_context_register: destination_register,
                 source_register,
                 role: string,
             })),
+            OpCode::FormatNumber => Ok(Instruction::FormatNumber(FormatNumberInstruction {
+                destination_register,
+                source_register,
+                spec: string,
+            })),
+            OpCode::Score => Ok(Instruction::Score(ScoreInstruction {
+                destination_register,
+                source_register,
+                criteria: string,
+            })),
+            OpCode::Json => Ok(Instruction::Json(JsonInstruction {
+                destination_register,
+                source_register,
+                fields: string,
+            })),
+            OpCode::JsonGet => Ok(Instruction::JsonGet(JsonGetInstruction {
+                destination_register,
+                source_register,
+                path: string,
+            })),
             _ => Err(Exception::Decoder(BaseException::new(
                 format!(
                     "Failed to decode double-register-string instruction: invalid opcode '{:?}'.",
@@ -255,6 +839,84 @@ impl Decoder {
         }
     }
 
+    fn single_register_double_string(
+        memory: &Memory,
+        registers: &Registers,
+        op_code: OpCode,
+        instruction_bytes: [[u8; 4]; 4],
+    ) -> Result<Instruction, Exception> {
+        let destination_register = u32::from_be_bytes(instruction_bytes[1]);
+        let string_1 = Self::string(
+            memory,
+            registers,
+            u32::from_be_bytes(instruction_bytes[2]) as usize,
+            &format!("Decoding first string for {:?}", op_code),
+        )?;
+        let string_2 = Self::string(
+            memory,
+            registers,
+            u32::from_be_bytes(instruction_bytes[3]) as usize,
+            &format!("Decoding second string for {:?}", op_code),
+        )?;
+
+        match op_code {
+            OpCode::LoadDirectory => Ok(Instruction::LoadDirectory(LoadDirectoryInstruction {
+                destination_register,
+                path: string_1,
+                pattern: string_2,
+            })),
+            _ => Err(Exception::Decoder(BaseException::new(
+                format!(
+                    "Failed to decode single-register-double-string instruction: invalid opcode '{:?}'.",
+                    op_code
+                ),
+                None,
+            ))),
+        }
+    }
+
+    // A second string pointer of 0 means there is no second string.
+    fn single_register_string_optional_string(
+        memory: &Memory,
+        registers: &Registers,
+        op_code: OpCode,
+        instruction_bytes: [[u8; 4]; 4],
+    ) -> Result<Instruction, Exception> {
+        let destination_register = u32::from_be_bytes(instruction_bytes[1]);
+        let string_1 = Self::string(
+            memory,
+            registers,
+            u32::from_be_bytes(instruction_bytes[2]) as usize,
+            &format!("Decoding first string for {:?}", op_code),
+        )?;
+        let string_2 = match u32::from_be_bytes(instruction_bytes[3]) {
+            0 => None,
+            pointer => Some(Self::string(
+                memory,
+                registers,
+                pointer as usize,
+                &format!("Decoding second string for {:?}", op_code),
+            )?),
+        };
+
+        match op_code {
+            OpCode::LoadEnvironment => {
+                Ok(Instruction::LoadEnvironment(LoadEnvironmentInstruction {
+                    destination_register,
+                    name: string_1,
+                    default: string_2,
+                }))
+            }
+            _ => Err(Exception::Decoder(BaseException::new(
+                format!(
+                    "Failed to decode single-register-optional-string instruction: invalid opcode '{:?}'.",
+                    op_code
+                ),
+                None,
+            ))),
+        }
+    }
+
     fn triple_register(
         op_code: OpCode,
         instruction_bytes: [[u8; 4]; 4],
@@ -269,6 +931,11 @@ impl Decoder {
                 source_register: source_register_1,
                 context_register: source_register_2,
             })),
+            OpCode::Ask => Ok(Instruction::Ask(AskInstruction {
+                destination_register,
+                question_register: source_register_1,
+                grounding_register: source_register_2,
+            })),
             OpCode::Evaluate => Ok(Instruction::Evaluate(EvalulateInstruction {
                 destination_register,
                 source_register: source_register_1,
@@ -279,6 +946,29 @@ impl Decoder {
                 source_register_1,
                 source_register_2,
             })),
+            OpCode::Concatenate => Ok(Instruction::Concatenate(ConcatenateInstruction {
+                destination_register,
+                source_register_1,
+                source_register_2,
+            })),
+            OpCode::AddNumber
+            | OpCode::SubtractNumber
+            | OpCode::MultiplyNumber
+            | OpCode::DivideNumber => {
+                let operation = match op_code {
+                    OpCode::AddNumber => ArithmeticOperation::Add,
+                    OpCode::SubtractNumber => ArithmeticOperation::Subtract,
+                    OpCode::MultiplyNumber => ArithmeticOperation::Multiply,
+                    _ => ArithmeticOperation::Divide,
+                };
+
+                Ok(Instruction::Arithmetic(ArithmeticInstruction {
+                    operation,
+                    destination_register,
+                    source_register_1,
+                    source_register_2,
+                }))
+            }
             _ => Err(Exception::Decoder(BaseException::new(
                 format!(
                     "Failed to decode triple-register instruction: invalid opcode '{:?}'.",
@@ -296,45 +986,116 @@ impl Decoder {
     ) -> Result<Instruction, Exception> {
         let op_code = Self::op_code(&instruction_bytes[0])?;
 
-        if op_code == OpCode::NoOp {
-            return Err(Exception::Decoder(BaseException::new(
-                "NoOp is not a valid instruction and should not be decoded.".to_string(),
-                None,
-            )));
-        }
-
         match op_code {
             // Data movement.
             OpCode::LoadString
             | OpCode::LoadImmediate
             | OpCode::LoadContent
+            | OpCode::LoadArgument
+            | OpCode::LoadPositionalArgument
             | OpCode::Move
-            | OpCode::SubtractImmediate => {
-                Self::immediate(memory, registers, op_code, instruction_bytes)
+            | OpCode::Escape
+            | OpCode::Embed => Self::immediate(memory, registers, op_code, instruction_bytes),
+            OpCode::LoadDirectory => {
+                Self::single_register_double_string(memory, registers, op_code, instruction_bytes)
             }
+            OpCode::LoadEnvironment => Self::single_register_string_optional_string(
+                memory,
+                registers,
+                op_code,
+                instruction_bytes,
+            ),
+            OpCode::StoreWord | OpCode::LoadWord => Self::memory_access(op_code, instruction_bytes),
             // Control flow.
             OpCode::BranchEqual
             | OpCode::BranchLess
             | OpCode::BranchLessEqual
             | OpCode::BranchGreater
             | OpCode::BranchGreaterEqual => Self::branch(op_code, instruction_bytes),
-            OpCode::Exit => Self::no_register(op_code),
+            OpCode::Jump => Self::jump(instruction_bytes),
+            OpCode::BranchSimilar => Self::branch_similar(instruction_bytes),
+            OpCode::Exit => Self::exit(instruction_bytes),
+            OpCode::Checkpoint => {
+                Self::no_register_string(memory, registers, op_code, instruction_bytes)
+            }
+            // Model selection.
+            OpCode::SetModel | OpCode::SetEmbeddingModel => {
+                Self::no_register_string(memory, registers, op_code, instruction_bytes)
+            }
+            // Sampling.
+            OpCode::SetTemperature | OpCode::SetTopP | OpCode::SetMaxTokens => {
+                Self::set_sampling(op_code, instruction_bytes)
+            }
+            // System prompt.
+            OpCode::SetSystemPrompt => {
+                Self::set_system_prompt(memory, registers, instruction_bytes)
+            }
+            // Vector store.
+            OpCode::VectorPut => Self::vector_put(memory, registers, instruction_bytes),
+            OpCode::VectorQuery => Self::vector_query(instruction_bytes),
+            OpCode::VectorClear => Ok(Instruction::VectorClear),
             // I/O.
-            OpCode::Print | OpCode::PrintLine | OpCode::PrintContext | OpCode::ContextDrop => {
-                Self::single_register(op_code, instruction_bytes)
+            OpCode::Print
+            | OpCode::PrintLine
+            | OpCode::PrintError
+            | OpCode::PrintContext
+            | OpCode::ContextDrop => Self::single_register(op_code, instruction_bytes),
+            OpCode::Input => Self::input(memory, registers, instruction_bytes),
+            OpCode::WriteFile | OpCode::AppendFile => {
+                Self::write_file(memory, registers, op_code, instruction_bytes)
             }
-            // Context operations.
-            OpCode::ContextPush => {
+            // Stack operations.
+            OpCode::LoadArgumentCount
+            | OpCode::StackPush
+            | OpCode::StackPop
+            | OpCode::StackPeek => Self::single_register(op_code, instruction_bytes),
+            // Context and string operations.
+            OpCode::ContextPush | OpCode::FormatNumber => {
+                Self::double_register_string(memory, registers, op_code, instruction_bytes)
+            }
+            OpCode::ContextPop
+            | OpCode::MoveContext
+            | OpCode::ContextLength
+            | OpCode::ContextPeek
+            | OpCode::Length
+            | OpCode::TokenCount
+            | OpCode::Upper
+            | OpCode::Lower
+            | OpCode::Trim => Self::double_register(op_code, instruction_bytes),
+            OpCode::ContextTrim => Self::context_trim(instruction_bytes),
+            OpCode::ContextInsert => Self::context_insert(memory, registers, instruction_bytes),
+            OpCode::ContextSave => Self::context_save(memory, registers, instruction_bytes),
+            OpCode::ContextLoad => Self::context_load(memory, registers, instruction_bytes),
+            OpCode::Chunk => Self::chunk(instruction_bytes),
+            OpCode::JsonGet => {
                 Self::double_register_string(memory, registers, op_code, instruction_bytes)
             }
-            OpCode::ContextPop | OpCode::MoveContext => {
-                Self::double_register(op_code, instruction_bytes)
+            // Arithmetic operations.
+            OpCode::AddImmediate
+            | OpCode::SubtractImmediate
+            | OpCode::MultiplyImmediate
+            | OpCode::DivideImmediate => {
+                Self::immediate(memory, registers, op_code, instruction_bytes)
             }
+            OpCode::AddNumber
+            | OpCode::SubtractNumber
+            | OpCode::MultiplyNumber
+            | OpCode::DivideNumber => Self::triple_register(op_code, instruction_bytes),
             // Generative, cognitive, and guardrails operations.
-            OpCode::Inference | OpCode::Evaluate | OpCode::Similarity => {
-                Self::triple_register(op_code, instruction_bytes)
+            OpCode::Inference
+            | OpCode::Ask
+            | OpCode::Evaluate
+            | OpCode::Similarity
+            | OpCode::Concatenate => Self::triple_register(op_code, instruction_bytes),
+            OpCode::Translate => Self::translate(memory, registers, instruction_bytes),
+            OpCode::Classify => Self::classify(memory, registers, instruction_bytes),
+            OpCode::Score | OpCode::Json => {
+                Self::double_register_string(memory, registers, op_code, instruction_bytes)
             }
-            OpCode::NoOp => unreachable!(),
+            OpCode::NoOp => Err(Exception::Decoder(BaseException::new(
+                "NoOp is not a valid instruction and should not be decoded.".to_string(),
+                None,
+            ))),
         }
     }
 }
@@ -0,0 +1,51 @@
+use crate::{
+    constants::{DEFAULT_MAX_DATA_WORDS, DEFAULT_MAX_REGISTER_BYTES},
+    exception::Exception,
+    processor::{
+        control_unit::{ControlUnit, decoder::Decoder},
+        memory::Memory,
+        registers::Registers,
+    },
+};
+
+pub struct Disassembler;
+
+impl Disassembler {
+    // One line per instruction, prefixed with its byte code index and followed by its source location
+    // when the byte code has debug info. Words that do not decode are shown as '.word' so that damaged
+    // byte code can still be examined.
+    pub fn disassemble(byte_code: &[[u8; 4]]) -> Result<Vec<String>, Exception> {
+        let mut memory = Memory::new();
+        let mut registers = Registers::new(DEFAULT_MAX_REGISTER_BYTES, DEFAULT_MAX_DATA_WORDS);
+        let debug_info = ControlUnit::load_into(&mut memory, &mut registers, byte_code)?;
+
+        let end = registers.get_data_section_pointer();
+        let mut address = registers.get_instruction_pointer();
+        let mut lines = Vec::new();
+
+        while address < end {
+            let words = &byte_code[address..end.min(address + 4)];
+            let instruction = <[[u8; 4]; 4]>::try_from(words)
+                .ok()
+                .and_then(|bytes| Decoder::decode(&memory, &registers, bytes).ok());
+
+            match instruction {
+                Some(instruction) => lines.push(match debug_info.location(address) {
+                    Some(location) => format!("0x{:04X}  {}  ; {}", address, instruction, location),
+                    None => format!("0x{:04X}  {}", address, instruction),
+                }),
+                None => lines.extend(words.iter().enumerate().map(|(offset, word)| {
+                    format!(
+                        "0x{:04X}  .word 0x{:08X}",
+                        address + offset,
+                        u32::from_be_bytes(*word)
+                    )
+                })),
+            }
+
+            address += words.len();
+        }
+
+        Ok(lines)
+    }
+}
@@ -1,21 +1,56 @@
-use std::fs::read_to_string;
+use std::{
+    cmp::Ordering,
+    env::{self, VarError},
+    fs::{OpenOptions, create_dir_all, read, read_dir, read_to_string},
+    io::{BufRead, ErrorKind, Write, stdin},
+    path::Path,
+    time::Instant,
+};
+
+use miniserde::json::{self, Number};
 
 use crate::{
-    config::{Config, TextModelOverrides},
+    assembler::{json_fields, labels},
+    atomic_file,
+    config::{Config, SimilarityBackend},
+    constants,
     exception::{BaseException, Exception},
     processor::{
+        checkpoint::Checkpoint,
         control_unit::{
+            chunker,
             instruction::{
-                BranchInstruction, BranchType, ContextDropInstruction, ContextPopInstruction,
-                ContextPushInstruction, EvalulateInstruction, InferenceInstruction, Instruction,
-                LoadContentInstruction, LoadImmediateInstruction, LoadStringInstruction,
-                MoveContextInstruction, MoveInstruction, PrintContextInstruction, PrintInstruction,
-                PrintLineInstruction, SimilarityInstruction, SubtractImmediateInstruction,
+                Address, ArithmeticImmediateInstruction, ArithmeticInstruction,
+                ArithmeticOperation, AskInstruction, BranchInstruction, BranchSimilarInstruction,
+                BranchType, CheckpointInstruction, ChunkInstruction, ClassifyInstruction,
+                ConcatenateInstruction, ContextDropInstruction, ContextInsertInstruction,
+                ContextLengthInstruction, ContextLoadInstruction, ContextPeekInstruction,
+                ContextPopInstruction, ContextPushInstruction, ContextSaveInstruction,
+                ContextTrimInstruction, EmbedInstruction, EscapeInstruction, EscapeSyntax,
+                EvalulateInstruction, ExitInstruction, ExitStatus, FormatNumberInstruction,
+                InferenceInstruction, InputInstruction, Instruction, JsonGetInstruction,
+                JsonInstruction, JumpInstruction, LoadArgumentCountInstruction,
+                LoadArgumentInstruction, LoadContentInstruction, LoadDirectoryInstruction,
+                LoadEnvironmentInstruction, LoadImmediateInstruction,
+                LoadPositionalArgumentInstruction, LoadStringInstruction, LoadWordInstruction,
+                MoveContextInstruction, MoveInstruction, PrintContextInstruction,
+                PrintErrorInstruction, PrintInstruction, PrintLineInstruction, SamplingParameter,
+                SamplingValue, ScoreInstruction, SetModelInstruction, SetSamplingInstruction,
+                SetSystemPromptInstruction, SimilarityInstruction, StackPeekInstruction,
+                StackPopInstruction, StackPushInstruction, StoreWordInstruction, SystemPrompt,
+                TargetLanguage, TextInstruction, TextOperation, TokenCountInstruction,
+                TranslateInstruction, VectorKey, VectorPutInstruction, VectorQueryInstruction,
+                WriteFileInstruction,
+            },
+            language_logic_unit::{
+                BooleanEvalParams, ChatTimings, LanguageLogicUnit, SimilarityOperand,
             },
-            language_logic_unit::{BooleanEvalParams, LanguageLogicUnit},
         },
         memory::Memory,
-        registers::{ContextMessage, Registers, Value},
+        output::Output,
+        registers::{ContextMessage, Registers, Value, preview_text},
+        stats::Stats,
+        trace::{ContextLength, TraceEvent, TraceSink},
     },
 };
 
@@ -34,32 +69,130 @@ impl Executor {
             ))),
             other => Err(Exception::Executor(BaseException::new(
                 format!(
-                    "Register r{} contains {:?}, expected text.",
-                    register_number, other
+                    "Register r{} contains {}, expected text.",
+                    register_number,
+                    other.preview()
                 ),
                 None,
             ))),
         }
     }
 
-    fn read_number(registers: &Registers, register_number: u32) -> Result<u32, Exception> {
-        match registers.get_register(register_number)? {
-            Value::Number(number) => Ok(*number),
-            Value::None => Err(Exception::Executor(BaseException::new(
+    fn unexpected_value(register_number: u32, value: &Value, expected: &str) -> Exception {
+        match value {
+            Value::None => Exception::Executor(BaseException::new(
                 format!(
-                    "Register r{} is uninitialised, expected number.",
-                    register_number
+                    "Register r{} is uninitialised, expected {}.",
+                    register_number, expected
                 ),
                 None,
-            ))),
-            other => Err(Exception::Executor(BaseException::new(
+            )),
+            other => Exception::Executor(BaseException::new(
+                format!(
+                    "Register r{} contains {}, expected {}.",
+                    register_number,
+                    other.preview(),
+                    expected
+                ),
+                None,
+            )),
+        }
+    }
+
+    // Fetch has already advanced past the instruction being executed.
+    fn current_address(registers: &Registers) -> usize {
+        registers.get_instruction_pointer().saturating_sub(4)
+    }
+
+    // Appended to the trace of instructions that call the text model.
+    fn timings_suffix(timings: Option<ChatTimings>) -> String {
+        timings
+            .map(|timings| format!(" [{}]", timings))
+            .unwrap_or_default()
+    }
+
+    fn read_number(registers: &Registers, register_number: u32) -> Result<i32, Exception> {
+        match registers.get_register(register_number)? {
+            Value::Number(number) => Ok(*number),
+            other => Err(Self::unexpected_value(register_number, other, "integer")),
+        }
+    }
+
+    // Integers compare with integers and floats with floats; mixing the two is an error.
+    fn compare(
+        registers: &Registers,
+        register_a: u32,
+        register_b: u32,
+    ) -> Result<Option<Ordering>, Exception> {
+        match (
+            registers.get_register(register_a)?,
+            registers.get_register(register_b)?,
+        ) {
+            (Value::Number(a), Value::Number(b)) => Ok(Some(a.cmp(b))),
+            (Value::Float(a), Value::Float(b)) => Ok(a.partial_cmp(b)),
+            (
+                a @ (Value::Number(_) | Value::Float(_)),
+                b @ (Value::Number(_) | Value::Float(_)),
+            ) => Err(Exception::Executor(BaseException::new(
                 format!(
-                    "Register r{} contains {:?}, expected number.",
-                    register_number, other
+                    "Cannot compare r{} ({}) with r{} ({}): integer and float operands cannot be mixed.",
+                    register_a,
+                    a.preview(),
+                    register_b,
+                    b.preview()
                 ),
                 None,
             ))),
+            (Value::Number(_) | Value::Float(_), other) => {
+                Err(Self::unexpected_value(register_b, other, "number"))
+            }
+            (other, _) => Err(Self::unexpected_value(register_a, other, "number")),
+        }
+    }
+
+    fn render(
+        registers: &Registers,
+        instruction: &LoadStringInstruction,
+    ) -> Result<String, Exception> {
+        let mut rendered = String::with_capacity(instruction.value.len());
+        let mut last_offset = 0;
+
+        for placeholder in &instruction.placeholders {
+            let segment = instruction
+                .value
+                .get(last_offset..placeholder.offset)
+                .ok_or_else(|| {
+                    Exception::Executor(BaseException::new(
+                        format!(
+                            "Placeholder offset {} is out of bounds for string of length {}.",
+                            placeholder.offset,
+                            instruction.value.len()
+                        ),
+                        None,
+                    ))
+                })?;
+            rendered.push_str(segment);
+
+            match registers.get_register(placeholder.register)? {
+                Value::None => {
+                    return Err(Exception::Executor(BaseException::new(
+                        format!(
+                            "Register r{} is uninitialised, expected a value for placeholder '${{x{}}}'.",
+                            placeholder.register, placeholder.register
+                        ),
+                        None,
+                    )));
+                }
+                // Values are spliced in verbatim and never re-scanned, so a '${xN}' inside a register stays literal.
+                value => rendered.push_str(&value.to_string()),
+            }
+
+            last_offset = placeholder.offset;
         }
+
+        rendered.push_str(&instruction.value[last_offset..]);
+
+        Ok(rendered)
     }
 
     fn load_string(
@@ -67,14 +200,14 @@ impl Executor {
         instruction: &LoadStringInstruction,
         debug: bool,
     ) -> Result<(), Exception> {
-        let value = Value::Text(instruction.value.clone());
+        let value = Value::Text(Self::render(registers, instruction)?);
         registers.set_register(instruction.destination_register, &value)?;
 
         crate::debug_print!(
             debug,
-            "Executed LI  : r{} = {:?}",
+            "Executed LS  : r{} = {}",
             instruction.destination_register,
-            value
+            value.preview()
         );
 
         Ok(())
@@ -85,14 +218,14 @@ impl Executor {
         instruction: &LoadImmediateInstruction,
         debug: bool,
     ) -> Result<(), Exception> {
-        let value = Value::Number(instruction.value);
+        let value = instruction.value.clone();
         registers.set_register(instruction.destination_register, &value)?;
 
         crate::debug_print!(
             debug,
-            "Executed LI  : r{} = {:?}",
+            "Executed LI  : r{} = {}",
             instruction.destination_register,
-            value
+            value.preview()
         );
 
         Ok(())
@@ -102,14 +235,41 @@ impl Executor {
         registers: &mut Registers,
         instruction: &LoadContentInstruction,
         debug: bool,
+        strict_utf8: bool,
     ) -> Result<(), Exception> {
-        let file_contents = read_to_string(&instruction.path).map_err(|e| {
+        let bytes = read(&instruction.path).map_err(|e| {
             Exception::Executor(BaseException::caused_by(
                 format!("Failed to read file '{}'", instruction.path),
                 e,
             ))
         })?;
 
+        let file_contents = if strict_utf8 {
+            String::from_utf8(bytes).map_err(|e| {
+                Exception::Executor(BaseException::caused_by(
+                    format!(
+                        "File '{}' contains invalid UTF-8 at byte offset {}",
+                        instruction.path,
+                        e.utf8_error().valid_up_to()
+                    ),
+                    e.to_string(),
+                ))
+            })?
+        } else {
+            let replaced: usize = bytes.utf8_chunks().map(|chunk| chunk.invalid().len()).sum();
+
+            if replaced > 0 {
+                eprintln!(
+                    "Warning: file '{}' contains invalid UTF-8; replaced {} byte(s). Use {} to treat this as an error.",
+                    instruction.path,
+                    replaced,
+                    constants::STRICT_UTF8_FLAG
+                );
+            }
+
+            String::from_utf8_lossy(&bytes).into_owned()
+        };
+
         registers.set_register(
             instruction.destination_register,
             &Value::Text(file_contents.clone()),
@@ -117,9 +277,252 @@ impl Executor {
 
         crate::debug_print!(
             debug,
-            "Executed LC  : r{} = {:?}",
+            "Executed LC  : r{} = {}",
+            instruction.destination_register,
+            preview_text(&file_contents)
+        );
+
+        Ok(())
+    }
+
+    fn load_environment(
+        registers: &mut Registers,
+        instruction: &LoadEnvironmentInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let value = match (env::var(&instruction.name), &instruction.default) {
+            (Ok(value), _) => value,
+            (Err(VarError::NotPresent), Some(default)) => default.clone(),
+            (Err(VarError::NotPresent), None) => {
+                return Err(Exception::Executor(BaseException::new(
+                    format!(
+                        "Environment variable '{}' is not set. Add a default with 'le rd, \"{}\", \"default\"' to run without it.",
+                        instruction.name, instruction.name
+                    ),
+                    None,
+                )));
+            }
+            (Err(e @ VarError::NotUnicode(_)), _) => {
+                return Err(Exception::Executor(BaseException::caused_by(
+                    format!("Failed to read environment variable '{}'", instruction.name),
+                    e.to_string(),
+                )));
+            }
+        };
+
+        registers.set_register(
+            instruction.destination_register,
+            &Value::Text(value.clone()),
+        )?;
+
+        crate::debug_print!(
+            debug,
+            "Executed LE  : r{} = {}",
+            instruction.destination_register,
+            preview_text(&value)
+        );
+
+        Ok(())
+    }
+
+    fn load_argument(
+        registers: &mut Registers,
+        instruction: &LoadArgumentInstruction,
+        config: &Config,
+    ) -> Result<(), Exception> {
+        let value = config
+            .arguments
+            .iter()
+            .find(|(name, _)| *name == instruction.name)
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| {
+                let provided = config
+                    .arguments
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect::<Vec<&str>>();
+
+                Exception::Executor(BaseException::new(
+                    format!(
+                        "Unknown argument '{}'. Provided arguments: [{}].",
+                        instruction.name,
+                        provided.join(", ")
+                    ),
+                    None,
+                ))
+            })?;
+
+        registers.set_register(
+            instruction.destination_register,
+            &Value::Text(value.clone()),
+        )?;
+
+        crate::debug_print!(
+            config.debug_run,
+            "Executed ARGN: r{} = {}",
+            instruction.destination_register,
+            preview_text(&value)
+        );
+
+        Ok(())
+    }
+
+    fn load_positional_argument(
+        registers: &mut Registers,
+        instruction: &LoadPositionalArgumentInstruction,
+        config: &Config,
+    ) -> Result<(), Exception> {
+        let value = usize::try_from(instruction.index)
+            .ok()
+            .and_then(|index| config.positional_arguments.get(index))
+            .cloned()
+            .ok_or_else(|| {
+                Exception::Executor(BaseException::new(
+                    format!(
+                        "Argument index {} is out of range. The program was run with {} positional argument(s).",
+                        instruction.index,
+                        config.positional_arguments.len()
+                    ),
+                    None,
+                ))
+            })?;
+
+        registers.set_register(
+            instruction.destination_register,
+            &Value::Text(value.clone()),
+        )?;
+
+        crate::debug_print!(
+            config.debug_run,
+            "Executed ARG : r{} = {}",
+            instruction.destination_register,
+            preview_text(&value)
+        );
+
+        Ok(())
+    }
+
+    fn load_argument_count(
+        registers: &mut Registers,
+        instruction: &LoadArgumentCountInstruction,
+        config: &Config,
+    ) -> Result<(), Exception> {
+        let count = i32::try_from(config.positional_arguments.len()).unwrap_or(i32::MAX);
+
+        registers.set_register(instruction.destination_register, &Value::Number(count))?;
+
+        crate::debug_print!(
+            config.debug_run,
+            "Executed ARGC: r{} = {}",
+            instruction.destination_register,
+            count
+        );
+
+        Ok(())
+    }
+
+    // Matches a '/'-separated relative path against a glob pattern.
+    // '*' matches within a path segment, '**' matches across segments, and '?' matches one character.
+    fn glob_match(pattern: &[char], path: &[char]) -> bool {
+        match pattern {
+            [] => path.is_empty(),
+            ['*', '*', '/', rest @ ..] | ['*', '*', rest @ ..] => {
+                Self::glob_match(rest, path)
+                    || (!path.is_empty() && Self::glob_match(pattern, &path[1..]))
+            }
+            ['*', rest @ ..] => {
+                Self::glob_match(rest, path)
+                    || (!path.is_empty() && path[0] != '/' && Self::glob_match(pattern, &path[1..]))
+            }
+            ['?', rest @ ..] => {
+                !path.is_empty() && path[0] != '/' && Self::glob_match(rest, &path[1..])
+            }
+            [ch, rest @ ..] => path.first() == Some(ch) && Self::glob_match(rest, &path[1..]),
+        }
+    }
+
+    fn list_directory(
+        root: &Path,
+        directory: &Path,
+        files: &mut Vec<String>,
+    ) -> Result<(), Exception> {
+        let entries = read_dir(directory).map_err(|e| {
+            Exception::Executor(BaseException::caused_by(
+                format!("Failed to read directory '{}'", directory.display()),
+                e,
+            ))
+        })?;
+
+        for entry in entries {
+            let entry = entry.map_err(|e| {
+                Exception::Executor(BaseException::caused_by(
+                    format!(
+                        "Failed to read entry in directory '{}'",
+                        directory.display()
+                    ),
+                    e,
+                ))
+            })?;
+            let path = entry.path();
+            let file_type = entry.file_type().map_err(|e| {
+                Exception::Executor(BaseException::caused_by(
+                    format!("Failed to read the type of '{}'", path.display()),
+                    e,
+                ))
+            })?;
+
+            // Links to directories are not followed, so a link back up the tree cannot recurse forever.
+            if file_type.is_dir() {
+                Self::list_directory(root, &path, files)?;
+            } else if path.is_dir() {
+                continue;
+            } else if let Ok(relative) = path.strip_prefix(root) {
+                let components = relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>();
+                files.push(components.join("/"));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load_directory(
+        registers: &mut Registers,
+        instruction: &LoadDirectoryInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let root = Path::new(&instruction.path);
+
+        if !root.is_dir() {
+            return Err(Exception::Executor(BaseException::new(
+                format!("Directory '{}' does not exist.", instruction.path),
+                None,
+            )));
+        }
+
+        let mut files = Vec::new();
+        Self::list_directory(root, root, &mut files)?;
+
+        let pattern = instruction.pattern.chars().collect::<Vec<char>>();
+        let mut matches = files
+            .into_iter()
+            .filter(|file| Self::glob_match(&pattern, &file.chars().collect::<Vec<char>>()))
+            .collect::<Vec<String>>();
+        matches.sort();
+
+        let listing = matches.join("\n");
+        registers.set_register(
+            instruction.destination_register,
+            &Value::Text(listing.clone()),
+        )?;
+
+        crate::debug_print!(
+            debug,
+            "Executed LD  : r{} = {}",
             instruction.destination_register,
-            file_contents
+            preview_text(&listing)
         );
 
         Ok(())
@@ -137,7 +540,29 @@ impl Executor {
             debug,
             "Executed MV  : r{} = {}",
             instruction.destination_register,
-            value
+            value.preview()
+        );
+
+        Ok(())
+    }
+
+    fn jump(
+        registers: &mut Registers,
+        instruction: &JumpInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let pointer = usize::try_from(instruction.instruction_pointer_jump_index).map_err(|e| {
+            Exception::Executor(BaseException::caused_by(
+                "Invalid jump index",
+                e.to_string(),
+            ))
+        })?;
+        registers.set_instruction_pointer(pointer);
+
+        crate::debug_print!(
+            debug,
+            "Executed JMP : jump {}",
+            instruction.instruction_pointer_jump_index
         );
 
         Ok(())
@@ -148,16 +573,27 @@ impl Executor {
         instruction: &BranchInstruction,
         debug: bool,
     ) -> Result<(), Exception> {
-        let value_a = Self::read_number(registers, instruction.source_register_1)?;
-        let value_b = Self::read_number(registers, instruction.source_register_2)?;
+        let ordering = Self::compare(
+            registers,
+            instruction.source_register_1,
+            instruction.source_register_2,
+        )?;
 
-        let is_true = match instruction.branch_type {
-            BranchType::Equal => value_a == value_b,
-            BranchType::Less => value_a < value_b,
-            BranchType::LessEqual => value_a <= value_b,
-            BranchType::Greater => value_a > value_b,
-            BranchType::GreaterEqual => value_a >= value_b,
-        };
+        // NaN is unordered, so no branch is taken.
+        let is_true = matches!(
+            (&instruction.branch_type, ordering),
+            (BranchType::Equal, Some(Ordering::Equal))
+                | (BranchType::Less, Some(Ordering::Less))
+                | (
+                    BranchType::LessEqual,
+                    Some(Ordering::Less | Ordering::Equal)
+                )
+                | (BranchType::Greater, Some(Ordering::Greater))
+                | (
+                    BranchType::GreaterEqual,
+                    Some(Ordering::Greater | Ordering::Equal)
+                )
+        );
 
         if is_true {
             let pointer =
@@ -180,10 +616,14 @@ impl Executor {
 
         crate::debug_print!(
             debug,
-            "Executed {} : {:?} {:?} -> {} jump {}",
+            "Executed {} : {} {} -> {} jump {}",
             label,
-            value_a,
-            value_b,
+            registers
+                .get_register(instruction.source_register_1)?
+                .preview(),
+            registers
+                .get_register(instruction.source_register_2)?
+                .preview(),
             is_true,
             instruction.instruction_pointer_jump_index
         );
@@ -191,334 +631,3145 @@ impl Executor {
         Ok(())
     }
 
-    fn exit(memory: &Memory, registers: &mut Registers, debug: bool) {
-        crate::debug_print!(debug, "Executed EXIT: Halting execution.");
+    fn exit(
+        memory: &Memory,
+        registers: &mut Registers,
+        instruction: &ExitInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let status = match instruction.status {
+            ExitStatus::Immediate(status) => status,
+            ExitStatus::Register(register) => Self::read_number(registers, register)?,
+        };
+
+        // The process status is a byte, so 256 would become 0 and report a failure as success.
+        if !(0..=255).contains(&status) {
+            return Err(Exception::Executor(BaseException::new(
+                format!(
+                    "Exit status {} is out of range. Statuses are 0 to 255.",
+                    status
+                ),
+                None,
+            )));
+        }
+
+        registers.set_exit_status(status);
+
+        crate::debug_print!(
+            debug,
+            "Executed EXIT: Halting execution with status {}.",
+            status
+        );
         registers.set_instruction_pointer(memory.length());
+
+        Ok(())
+    }
+
+    // Saves the state of the run so that it can resume after this instruction. Does nothing without --checkpoint-dir.
+    fn checkpoint(
+        memory: &Memory,
+        registers: &Registers,
+        language_logic_unit: &mut LanguageLogicUnit,
+        instruction: &CheckpointInstruction,
+        config: &Config,
+    ) -> Result<(), Exception> {
+        let Some(directory) = &config.checkpoint_dir else {
+            crate::debug_print!(
+                config.debug_run,
+                "Executed CKPT: Skipped '{}' without --checkpoint-dir.",
+                instruction.name
+            );
+            return Ok(());
+        };
+
+        let state = language_logic_unit.capture()?;
+        let path =
+            Checkpoint::capture(memory, registers, state).save(directory, &instruction.name)?;
+
+        crate::debug_print!(
+            config.debug_run,
+            "Executed CKPT: Saved '{}' to {}.",
+            instruction.name,
+            path.display()
+        );
+
+        Ok(())
     }
 
     fn print(
         registers: &Registers,
         instruction: &PrintInstruction,
+        output: &mut Output,
         debug: bool,
     ) -> Result<(), Exception> {
         let value = registers.get_register(instruction.source_register)?.clone();
 
         crate::debug_print!(
             debug,
-            "Executed PUT : r{} = {:?}",
+            "Executed PUT : r{} = {}",
             instruction.source_register,
-            value
+            value.preview()
         );
 
-        if !debug {
-            print!("{}", value);
-        }
-
-        Ok(())
+        // PUT appends nothing, so flush now rather than waiting for the next newline.
+        write!(output.stdout, "{}", value)
+            .and_then(|()| output.stdout.flush())
+            .map_err(|e| {
+                Exception::Executor(BaseException::caused_by("Failed to write output.", e))
+            })
     }
 
     fn print_line(
         registers: &Registers,
         instruction: &PrintLineInstruction,
+        output: &mut Output,
         debug: bool,
     ) -> Result<(), Exception> {
         let value = registers.get_register(instruction.source_register)?.clone();
 
         crate::debug_print!(
             debug,
-            "Executed PLN : r{} = {:?}",
+            "Executed PLN : r{} = {}",
             instruction.source_register,
-            value
+            value.preview()
         );
 
-        if !debug {
-            println!("{}", value);
-        }
-
-        Ok(())
+        writeln!(output.stdout, "{}", value).map_err(|e| {
+            Exception::Executor(BaseException::caused_by("Failed to write output.", e))
+        })
     }
 
-    fn print_context(
+    fn print_error(
         registers: &Registers,
-        instruction: &PrintContextInstruction,
+        instruction: &PrintErrorInstruction,
+        output: &mut Output,
         debug: bool,
     ) -> Result<(), Exception> {
-        let context = registers.get_context(instruction.source_context_register)?;
+        let value = registers.get_register(instruction.source_register)?.clone();
 
         crate::debug_print!(
             debug,
-            "Executed PCX : c{} = {:?}",
-            instruction.source_context_register,
-            context
+            "Executed ERR : r{} = {}",
+            instruction.source_register,
+            value.preview()
         );
 
-        if !debug {
-            let context_json = miniserde::json::to_string(&context);
-            println!("{}", context_json);
-        }
-
-        Ok(())
+        writeln!(output.stderr, "{}", value).map_err(|e| {
+            Exception::Executor(BaseException::caused_by("Failed to write to stderr.", e))
+        })
     }
 
-    fn inference(
+    // Reads one line from stdin without its line ending. A closed stdin reads as an empty line.
+    fn input(
         registers: &mut Registers,
-        instruction: &InferenceInstruction,
-        text_model: &str,
-        text_model_overrides: &TextModelOverrides,
+        instruction: &InputInstruction,
+        output: &mut Output,
         debug: bool,
-        debug_chat: bool,
     ) -> Result<(), Exception> {
-        let value = Self::read_text(registers, instruction.source_register)?.clone();
-        let context = registers.get_context(instruction.context_register)?;
-        let result = LanguageLogicUnit::string(
-            &value,
-            context,
-            text_model,
-            text_model_overrides,
-            debug_chat,
-        )?;
+        if let Some(prompt) = &instruction.prompt {
+            write!(output.stdout, "{}", prompt)
+                .and_then(|()| output.stdout.flush())
+                .map_err(|e| {
+                    Exception::Executor(BaseException::caused_by("Failed to write output.", e))
+                })?;
+        }
+
+        let mut line = String::new();
+
+        let read = stdin().lock().read_line(&mut line).map_err(|e| {
+            Exception::Executor(BaseException::caused_by("Failed to read from stdin.", e))
+        })?;
+
+        if read == 0 {
+            crate::debug_print!(debug, "IN   : stdin is closed, reading an empty line.");
+        }
+
+        let line = line
+            .strip_suffix('\n')
+            .map(|line| line.strip_suffix('\r').unwrap_or(line))
+            .unwrap_or(&line)
+            .to_string();
+
+        registers.set_register(instruction.destination_register, &Value::Text(line.clone()))?;
 
         crate::debug_print!(
             debug,
-            "Executed INF : r{} = '{:?}'",
+            "Executed IN  : r{} = {}",
             instruction.destination_register,
-            result
+            preview_text(&line)
         );
 
-        registers.set_register(instruction.destination_register, &Value::Text(result))
+        Ok(())
     }
 
-    fn evaluate(
-        registers: &mut Registers,
-        instruction: &EvalulateInstruction,
-        text_model: &str,
-        embedding_model: &str,
-        text_model_overrides: &TextModelOverrides,
+    fn create_parent_directory(path: &str) -> Result<&Path, Exception> {
+        let parent = Path::new(path)
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty());
+
+        if let Some(parent) = parent {
+            create_dir_all(parent).map_err(|e| {
+                Exception::Executor(BaseException::caused_by(
+                    format!("Failed to create the directory for '{}'", path),
+                    e,
+                ))
+            })?;
+        }
+
+        Ok(Path::new(path))
+    }
+
+    // Writes the value exactly as PUT prints it, creating missing parent directories. WF replaces the file
+    // in one step, so a reader never sees it half written.
+    fn write_file(
+        registers: &Registers,
+        instruction: &WriteFileInstruction,
         debug: bool,
-        debug_chat: bool,
     ) -> Result<(), Exception> {
-        let value = Self::read_text(registers, instruction.source_register)?.clone();
-        let micro_prompt = format!(
-            "{}\nAnswer with exactly one word: YES or NO, TRUE or FALSE.\n\nAnswer only:",
-            value
-        );
-        let true_values = vec!["YES", "TRUE"];
-        let false_values = vec!["NO", "FALSE"];
+        let contents = match registers.get_register(instruction.source_register)? {
+            Value::None => {
+                return Err(Exception::Executor(BaseException::new(
+                    format!(
+                        "Register r{} is uninitialised, expected a value to write to '{}'.",
+                        instruction.source_register, instruction.path
+                    ),
+                    None,
+                )));
+            }
+            value => value.to_string(),
+        };
+
+        let path = Self::create_parent_directory(&instruction.path)?;
+
+        let result = if instruction.append {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(contents.as_bytes()))
+        } else {
+            atomic_file::write(path, &contents)
+        };
+
+        result.map_err(|e| {
+            Exception::Executor(BaseException::caused_by(
+                format!("Failed to write file '{}'", instruction.path),
+                e,
+            ))
+        })?;
+
+        crate::debug_print!(
+            debug,
+            "Executed {:<4}: Wrote r{} ({} bytes) to '{}'.",
+            if instruction.append { "AF" } else { "WF" },
+            instruction.source_register,
+            contents.len(),
+            instruction.path
+        );
+
+        Ok(())
+    }
+
+    fn print_context(
+        registers: &Registers,
+        instruction: &PrintContextInstruction,
+        output: &mut Output,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let context = registers.get_context(instruction.source_context_register)?;
+
+        crate::debug_print!(
+            debug,
+            "Executed PCX : c{} = {:?}",
+            instruction.source_context_register,
+            context
+        );
+
+        let context_json = miniserde::json::to_string(&context);
+        writeln!(output.stdout, "{}", context_json).map_err(|e| {
+            Exception::Executor(BaseException::caused_by("Failed to write output.", e))
+        })
+    }
+
+    // Trims the context register to the context window before INF or EVAL sends it, so later
+    // instructions and checkpoints see the same messages the model did.
+    fn window_context(
+        registers: &mut Registers,
+        language_logic_unit: &LanguageLogicUnit,
+        context_register: u32,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let Some(keep) =
+            language_logic_unit.context_window(registers.get_context(context_register)?)
+        else {
+            return Ok(());
+        };
+
+        let dropped = registers.trim_context(context_register, keep)?;
+
+        crate::debug_print!(
+            debug,
+            "Context c{} dropped its {} oldest message(s) to fit the context window.",
+            context_register,
+            dropped
+        );
+
+        Ok(())
+    }
+
+    fn inference(
+        registers: &mut Registers,
+        language_logic_unit: &mut LanguageLogicUnit,
+        instruction: &InferenceInstruction,
+        config: &Config,
+    ) -> Result<(), Exception> {
+        let value = Self::read_text(registers, instruction.source_register)?.clone();
+        Self::window_context(
+            registers,
+            language_logic_unit,
+            instruction.context_register,
+            config.debug_run,
+        )?;
+        let context = registers.get_context(instruction.context_register)?;
+        let text_model = language_logic_unit.text_model().to_string();
+        let text_model_overrides = language_logic_unit.text_model_overrides().clone();
+        language_logic_unit.set_instruction("INF", Self::current_address(registers));
+        let result = language_logic_unit.string(
+            &value,
+            context,
+            &text_model,
+            &text_model_overrides,
+            config.debug_chat,
+        )?;
+        let timings = language_logic_unit.take_instruction_timings();
+
+        crate::debug_print!(
+            config.debug_run,
+            "Executed INF : r{} = {}{}",
+            instruction.destination_register,
+            preview_text(&result),
+            Self::timings_suffix(timings)
+        );
+
+        registers.set_register(instruction.destination_register, &Value::Text(result))
+    }
+
+    fn ask(
+        registers: &mut Registers,
+        language_logic_unit: &mut LanguageLogicUnit,
+        instruction: &AskInstruction,
+        config: &Config,
+    ) -> Result<(), Exception> {
+        let question = Self::read_text(registers, instruction.question_register)?;
+        let grounding = Self::read_text(registers, instruction.grounding_register)?;
+        let micro_prompt = config.prompts.ask(question, grounding);
+        let text_model = language_logic_unit.text_model().to_string();
+        let text_model_overrides = language_logic_unit.text_model_overrides().clone();
+
+        language_logic_unit.set_instruction("ASK", Self::current_address(registers));
+        let response = language_logic_unit.string(
+            &micro_prompt,
+            &[],
+            &text_model,
+            &text_model_overrides,
+            config.debug_chat,
+        )?;
+        let timings = language_logic_unit.take_instruction_timings();
+
+        // Models often decorate the sentinel, as in "Unknown." or "**UNKNOWN**", so any reply that
+        // is only the sentinel is stored exactly, letting programs compare against it.
+        let result = match labels::find(&[constants::ASK_UNKNOWN], &response) {
+            Some(_) => constants::ASK_UNKNOWN.to_string(),
+            None => response.trim().to_string(),
+        };
+
+        crate::debug_print!(
+            config.debug_run,
+            "Executed ASK : r{} = {}{}",
+            instruction.destination_register,
+            preview_text(&result),
+            Self::timings_suffix(timings)
+        );
+
+        registers.set_register(instruction.destination_register, &Value::Text(result))
+    }
+
+    // A TRL reply split into the language its first line says the text is in, when there is such a
+    // line, and the translation. Models often bold the line, so '*' around it is ignored.
+    fn split_source_language(reply: &str) -> (Option<&str>, &str) {
+        let decoration = |character: char| character == '*' || character.is_whitespace();
+        let reply = reply.trim();
+        let (first_line, rest) = reply.split_once('\n').unwrap_or((reply, ""));
+        let first_line = first_line.trim_matches(decoration);
+        let prefix = constants::TRANSLATE_SOURCE_PREFIX;
+
+        match first_line.get(..prefix.len()) {
+            Some(start) if start.eq_ignore_ascii_case(prefix) => {
+                let language = first_line[prefix.len()..].trim_matches(decoration);
+                (
+                    Some(language).filter(|language| !language.is_empty()),
+                    rest.trim(),
+                )
+            }
+            _ => (None, reply),
+        }
+    }
+
+    fn translate(
+        registers: &mut Registers,
+        language_logic_unit: &mut LanguageLogicUnit,
+        instruction: &TranslateInstruction,
+        config: &Config,
+    ) -> Result<(), Exception> {
+        let text = Self::read_text(registers, instruction.source_register)?;
+        let language = match &instruction.language {
+            TargetLanguage::Text(language) => language,
+            TargetLanguage::Register(register) => Self::read_text(registers, *register)?,
+        }
+        .trim();
+
+        if language.is_empty() {
+            return Err(Exception::Executor(BaseException::new(
+                "Target language is empty.".to_string(),
+                None,
+            )));
+        }
+
+        let micro_prompt = config.prompts.translate(text, language);
+        let language = language.to_string();
+        let text_model = language_logic_unit.text_model().to_string();
+        let text_model_overrides = language_logic_unit.text_model_overrides().clone();
+
+        language_logic_unit.set_instruction("TRL", Self::current_address(registers));
+        let response = language_logic_unit.string(
+            &micro_prompt,
+            &[],
+            &text_model,
+            &text_model_overrides,
+            config.debug_chat,
+        )?;
+        let timings = language_logic_unit.take_instruction_timings();
+
+        let (source_language, translation) = Self::split_source_language(&response);
+
+        if translation.is_empty() {
+            return Err(Exception::Executor(BaseException::new(
+                format!(
+                    "TRL reply named the language of the text but had no translation: {}",
+                    preview_text(&response)
+                ),
+                None,
+            )));
+        }
+
+        crate::debug_print!(
+            config.debug_run,
+            "Executed TRL : r{} = {} ({}to {}){}",
+            instruction.destination_register,
+            preview_text(translation),
+            source_language.map_or(String::new(), |source| format!("from {} ", source)),
+            language,
+            Self::timings_suffix(timings)
+        );
+
+        registers.set_register(
+            instruction.destination_register,
+            &Value::Text(translation.to_string()),
+        )
+    }
+
+    fn evaluate(
+        registers: &mut Registers,
+        language_logic_unit: &mut LanguageLogicUnit,
+        instruction: &EvalulateInstruction,
+        config: &Config,
+    ) -> Result<(), Exception> {
+        let value = Self::read_text(registers, instruction.source_register)?.clone();
+        let micro_prompt = config.prompts.eval(&value);
+        let true_values = vec!["YES", "TRUE"];
+        let false_values = vec!["NO", "FALSE"];
+        Self::window_context(
+            registers,
+            language_logic_unit,
+            instruction.context_register,
+            config.debug_run,
+        )?;
         let context = registers.get_context(instruction.context_register)?;
+        let text_model = language_logic_unit.text_model().to_string();
+        let text_model_overrides = language_logic_unit.text_model_overrides().clone();
+        let embedding_model = language_logic_unit.embedding_model().to_string();
 
         let eval_params = BooleanEvalParams {
             true_values: &true_values,
             false_values: &false_values,
-            embedding_model,
+            embedding_model: &embedding_model,
         };
 
-        let result = LanguageLogicUnit::boolean(
+        language_logic_unit.set_instruction("EVAL", Self::current_address(registers));
+        let result = language_logic_unit.boolean(
             &micro_prompt,
             &eval_params,
             context,
-            text_model,
-            text_model_overrides,
-            debug_chat,
+            &text_model,
+            &text_model_overrides,
+            config.debug_chat,
         )?;
+        let timings = language_logic_unit.take_instruction_timings();
 
         crate::debug_print!(
-            debug,
-            "Executed EVAL: r{} = '{:?}'",
+            config.debug_run,
+            "Executed EVAL: r{} = {}{}",
             instruction.destination_register,
-            result
+            result,
+            Self::timings_suffix(timings)
         );
 
         registers.set_register(instruction.destination_register, &Value::Number(result))
     }
 
+    // SIM compares text, which is embedded each time, or vectors made by EMB, in any mix.
+    fn similarity_operand(
+        registers: &Registers,
+        register_number: u32,
+    ) -> Result<SimilarityOperand<'_>, Exception> {
+        match registers.get_register(register_number)? {
+            Value::Text(text) => Ok(SimilarityOperand::Text(text)),
+            Value::Vector(vector) => Ok(SimilarityOperand::Vector(vector)),
+            other => Err(Self::unexpected_value(
+                register_number,
+                other,
+                "text or a vector",
+            )),
+        }
+    }
+
     fn similarity(
         registers: &mut Registers,
+        language_logic_unit: &mut LanguageLogicUnit,
         instruction: &SimilarityInstruction,
-        embedding_model: &str,
-        debug: bool,
+        config: &Config,
     ) -> Result<(), Exception> {
-        let value_a = Self::read_text(registers, instruction.source_register_1)?.clone();
-        let value_b = Self::read_text(registers, instruction.source_register_2)?.clone();
+        let value_a = Self::similarity_operand(registers, instruction.source_register_1)?;
+        let value_b = Self::similarity_operand(registers, instruction.source_register_2)?;
 
-        let result = LanguageLogicUnit::cosine_similarity(&value_a, &value_b, embedding_model)?;
+        let embedding_model = language_logic_unit.embedding_model().to_string();
+        language_logic_unit.set_instruction("SIM", Self::current_address(registers));
+        let result = language_logic_unit.similarity(value_a, value_b, &embedding_model)?;
+        let timings = language_logic_unit.take_instruction_timings();
 
         crate::debug_print!(
-            debug,
-            "Executed SIM : '{:?}' vs '{:?}' -> r{} = {}",
-            value_a,
-            value_b,
+            config.debug_run,
+            "Executed SIM : {} vs {} -> r{} = {}{}{}",
+            registers
+                .get_register(instruction.source_register_1)?
+                .preview(),
+            registers
+                .get_register(instruction.source_register_2)?
+                .preview(),
             instruction.destination_register,
-            result
+            result,
+            match language_logic_unit.similarity_backend() {
+                SimilarityBackend::Embeddings => "",
+                SimilarityBackend::Llm => " (LLM-estimated)",
+            },
+            Self::timings_suffix(timings)
         );
 
         registers.set_register(instruction.destination_register, &Value::Number(result))
     }
 
-    fn context_push(
+    fn branch_similar(
         registers: &mut Registers,
-        instruction: &ContextPushInstruction,
-        debug: bool,
+        language_logic_unit: &mut LanguageLogicUnit,
+        instruction: &BranchSimilarInstruction,
+        config: &Config,
     ) -> Result<(), Exception> {
-        let register_value = registers.get_register(instruction.source_register)?;
+        let value_a = Self::similarity_operand(registers, instruction.source_register_1)?;
+        let value_b = Self::similarity_operand(registers, instruction.source_register_2)?;
 
-        let value = match register_value {
-            Value::Text(text) => text.clone(),
-            Value::Number(number) => number.to_string(),
-            Value::None => {
-                return Err(Exception::Executor(BaseException::new(
-                    format!(
-                        "Register r{} is uninitialised, expected text or number.",
-                        instruction.source_register
-                    ),
-                    None,
-                )));
-            }
-        };
+        let embedding_model = language_logic_unit.embedding_model().to_string();
+        language_logic_unit.set_instruction("BSIM", Self::current_address(registers));
+        let similarity = language_logic_unit.similarity(value_a, value_b, &embedding_model)?;
+        let timings = language_logic_unit.take_instruction_timings();
 
-        registers.push_context(
-            ContextMessage::new(&instruction.role, &value),
-            instruction.destination_context_register,
-        )?;
+        let is_true = similarity >= i32::try_from(instruction.threshold).unwrap_or(i32::MAX);
 
         crate::debug_print!(
-            debug,
-            "Executed PSH : Pushed value from r{} onto context stack with role '{}'.",
-            instruction.source_register,
-            instruction.role
+            config.debug_run,
+            "Executed BSIM: {} vs {} = {} >= {} -> {} jump {}{}",
+            registers
+                .get_register(instruction.source_register_1)?
+                .preview(),
+            registers
+                .get_register(instruction.source_register_2)?
+                .preview(),
+            similarity,
+            instruction.threshold,
+            is_true,
+            instruction.instruction_pointer_jump_index,
+            Self::timings_suffix(timings)
         );
 
+        if is_true {
+            let pointer =
+                usize::try_from(instruction.instruction_pointer_jump_index).map_err(|e| {
+                    Exception::Executor(BaseException::caused_by(
+                        "Invalid branch jump index",
+                        e.to_string(),
+                    ))
+                })?;
+            registers.set_instruction_pointer(pointer);
+        }
+
         Ok(())
     }
 
-    fn context_pop(
+    fn classify(
         registers: &mut Registers,
-        instruction: &ContextPopInstruction,
-        debug: bool,
+        language_logic_unit: &mut LanguageLogicUnit,
+        instruction: &ClassifyInstruction,
+        config: &Config,
     ) -> Result<(), Exception> {
-        let context = registers.pop_context(instruction.source_context_register)?;
+        // Byte code is checked the same as source, since the list is only split when it runs.
+        let labels = labels::parse(&instruction.labels)
+            .map_err(|message| Exception::Executor(BaseException::new(message, None)))?;
+        let value = Self::read_text(registers, instruction.source_register)?.clone();
+        let text_model = language_logic_unit.text_model().to_string();
+        let text_model_overrides = language_logic_unit.text_model_overrides().clone();
+
+        language_logic_unit.set_instruction("CLS", Self::current_address(registers));
+        let index = language_logic_unit.classify(
+            &value,
+            &labels,
+            &text_model,
+            &text_model_overrides,
+            config.debug_chat,
+        )?;
+        let timings = language_logic_unit.take_instruction_timings();
+
+        crate::debug_print!(
+            config.debug_run,
+            "Executed CLS: r{} = \"{}\" (index {}){}",
+            instruction.destination_register,
+            labels[index],
+            index,
+            Self::timings_suffix(timings)
+        );
 
         registers.set_register(
             instruction.destination_register,
-            &Value::Text(context.content.clone()),
+            &Value::Text(labels[index].to_string()),
         )?;
 
-        crate::debug_print!(debug, "Executed POP : Popped value from context stack.",);
+        if instruction.index_register != 0 {
+            registers.set_register(instruction.index_register, &Value::Number(index as i32))?;
+        }
 
         Ok(())
     }
 
-    fn context_drop(
+    fn score(
         registers: &mut Registers,
-        instruction: &ContextDropInstruction,
-        debug: bool,
+        language_logic_unit: &mut LanguageLogicUnit,
+        instruction: &ScoreInstruction,
+        config: &Config,
     ) -> Result<(), Exception> {
-        registers.pop_context(instruction.source_context_register)?;
+        let value = Self::read_text(registers, instruction.source_register)?.clone();
+        // A reply without a number is asked again once with a firmer prompt.
+        let micro_prompts = [
+            config.prompts.score(&value, &instruction.criteria),
+            config.prompts.score_retry(&value, &instruction.criteria),
+        ];
+        let text_model = language_logic_unit.text_model().to_string();
+        let text_model_overrides = language_logic_unit.text_model_overrides().clone();
+
+        language_logic_unit.set_instruction("SCORE", Self::current_address(registers));
+        let mut response = String::new();
+        let mut score = None;
+
+        for micro_prompt in &micro_prompts {
+            response = language_logic_unit.string(
+                micro_prompt,
+                &[],
+                &text_model,
+                &text_model_overrides,
+                config.debug_chat,
+            )?;
+            score = LanguageLogicUnit::parse_score(&response);
 
-        crate::debug_print!(debug, "Executed DRP : Dropped value from context stack.",);
+            if score.is_some() {
+                break;
+            }
+        }
+        let timings = language_logic_unit.take_instruction_timings();
 
-        Ok(())
+        let Some(score) = score else {
+            return Err(Exception::Executor(BaseException::new(
+                format!(
+                    "SCORE expected a score from 0 to 100, but the text model replied '{}'.",
+                    response
+                ),
+                None,
+            )));
+        };
+
+        crate::debug_print!(
+            config.debug_run,
+            "Executed SCORE: r{} = {}{}",
+            instruction.destination_register,
+            score,
+            Self::timings_suffix(timings)
+        );
+
+        registers.set_register(instruction.destination_register, &Value::Number(score))
     }
 
-    fn move_context(
+    fn json(
         registers: &mut Registers,
-        instruction: &MoveContextInstruction,
-        debug: bool,
+        language_logic_unit: &mut LanguageLogicUnit,
+        instruction: &JsonInstruction,
+        config: &Config,
     ) -> Result<(), Exception> {
-        let value = registers
-            .get_context(instruction.source_context_register)?
-            .to_vec();
-        registers.set_context(instruction.destination_context_register, &value)?;
+        // Byte code is checked the same as source, since the list is only split when it runs.
+        let fields = json_fields::parse(&instruction.fields)
+            .map_err(|message| Exception::Executor(BaseException::new(message, None)))?;
+        let description = json_fields::describe(&instruction.fields, &fields);
+        let value = Self::read_text(registers, instruction.source_register)?.clone();
+        let text_model = language_logic_unit.text_model().to_string();
+        let text_model_overrides = language_logic_unit.text_model_overrides().clone();
+
+        language_logic_unit.set_instruction("JSON", Self::current_address(registers));
+        let mut micro_prompt = config.prompts.json(&value, &description);
+        let mut repaired = false;
+
+        // A reply that is not usable is sent back once with the reason, then it is an error.
+        let result = loop {
+            let response = language_logic_unit.string(
+                &micro_prompt,
+                &[],
+                &text_model,
+                &text_model_overrides,
+                config.debug_chat,
+            )?;
+            let output = json_fields::unfenced(&response);
+
+            match json_fields::check(output, &fields) {
+                Ok(()) => break output.to_string(),
+                Err(reason) if !repaired => {
+                    micro_prompt =
+                        config
+                            .prompts
+                            .json_repair(&value, &description, &reason, output);
+                    repaired = true;
+                }
+                Err(reason) => {
+                    return Err(Exception::Executor(BaseException::new(
+                        format!(
+                            "JSON output from the text model is invalid because {}: '{}'.",
+                            reason, output
+                        ),
+                        None,
+                    )));
+                }
+            }
+        };
+        let timings = language_logic_unit.take_instruction_timings();
 
         crate::debug_print!(
-            debug,
-            "Executed MVC : c{} = c{}",
-            instruction.destination_context_register,
-            instruction.source_context_register
+            config.debug_run,
+            "Executed JSON: r{} = {}{}",
+            instruction.destination_register,
+            result,
+            Self::timings_suffix(timings)
         );
 
-        Ok(())
+        registers.set_register(instruction.destination_register, &Value::Text(result))
     }
 
-    fn subtract_immediate(
+    fn embed(
         registers: &mut Registers,
-        instruction: &SubtractImmediateInstruction,
+        language_logic_unit: &mut LanguageLogicUnit,
+        instruction: &EmbedInstruction,
+        config: &Config,
+    ) -> Result<(), Exception> {
+        // Every value is read before any vector is written, so the ranges may overlap.
+        let values = instruction
+            .register_pairs()
+            .map(|(source, _)| Ok(Self::read_text(registers, source)?.clone()))
+            .collect::<Result<Vec<_>, Exception>>()?;
+        let texts = values.iter().map(String::as_str).collect::<Vec<_>>();
+
+        let embedding_model = language_logic_unit.embedding_model().to_string();
+        language_logic_unit.set_instruction("EMB", Self::current_address(registers));
+        let vectors = language_logic_unit.embeddings_of(&texts, &embedding_model)?;
+        let timings = language_logic_unit.take_instruction_timings();
+
+        for ((_, destination), (value, vector)) in
+            instruction.register_pairs().zip(values.iter().zip(vectors))
+        {
+            let vector = Value::Vector(vector);
+
+            crate::debug_print!(
+                config.debug_run,
+                "Executed EMB : {} -> r{} = {}{}",
+                preview_text(value),
+                destination,
+                vector.preview(),
+                Self::timings_suffix(timings)
+            );
+
+            registers.set_register(destination, &vector)?;
+        }
+
+        Ok(())
+    }
+
+    // Last write wins: the model applies to every later instruction until the next SETMODEL or
+    // SETEMB.
+    fn set_model(
+        language_logic_unit: &mut LanguageLogicUnit,
+        instruction: &SetModelInstruction,
         debug: bool,
     ) -> Result<(), Exception> {
-        let value = Self::read_number(registers, instruction.source_register)?;
+        let opcode = if instruction.embedding {
+            "SETEMB"
+        } else {
+            "SETMODEL"
+        };
 
-        if value < instruction.value {
+        if instruction.model.trim().is_empty() {
             return Err(Exception::Executor(BaseException::new(
-                format!(
-                    "Cannot subtract {} from register r{} because it would result in a negative value.",
-                    instruction.value, instruction.source_register
-                ),
+                format!("{} needs a model name.", opcode),
                 None,
             )));
         }
 
-        let new_value = Value::Number(value - instruction.value);
-        registers.set_register(instruction.source_register, &new_value)?;
+        if instruction.embedding {
+            language_logic_unit.set_embedding_model(&instruction.model);
+        } else {
+            language_logic_unit.set_text_model(&instruction.model);
+        }
 
-        crate::debug_print!(
-            debug,
-            "Executed SUBI: Subtracted {} from r{} resulting in {}.",
-            instruction.value,
-            instruction.source_register,
-            new_value
-        );
+        crate::debug_print!(debug, "Executed {}: model = {}", opcode, instruction.model);
 
         Ok(())
     }
 
-    pub fn execute(
-        memory: &mut Memory,
-        registers: &mut Registers,
-        instruction: &Instruction,
-        config: &Config,
-    ) -> Result<(), Exception> {
+    // Temperature and top-p are given in hundredths, since immediates are integers.
+    fn set_sampling(
+        registers: &Registers,
+        language_logic_unit: &mut LanguageLogicUnit,
+        instruction: &SetSamplingInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let value = match instruction.value {
+            SamplingValue::Immediate(value) => value,
+            SamplingValue::Register(register) => Self::read_number(registers, register)?,
+        };
+
+        let (opcode, valid, expected) = match instruction.parameter {
+            SamplingParameter::Temperature => (
+                "SETTEMP",
+                (0..=200).contains(&value),
+                "a temperature from 0 to 200 (0.0 to 2.0)",
+            ),
+            SamplingParameter::TopP => (
+                "SETTOPP",
+                (0..=100).contains(&value),
+                "a top-p from 0 to 100 (0.0 to 1.0)",
+            ),
+            SamplingParameter::MaxTokens => (
+                "SETMAXTOK",
+                value == -1 || value > 0,
+                "a number of tokens of at least 1, or -1 for no limit",
+            ),
+        };
+
+        if !valid {
+            return Err(Exception::Executor(BaseException::new(
+                format!("{} takes {}, but got {}.", opcode, expected, value),
+                None,
+            )));
+        }
+
+        match instruction.parameter {
+            SamplingParameter::Temperature => {
+                language_logic_unit.set_temperature(value as f32 / 100.0)
+            }
+            SamplingParameter::TopP => language_logic_unit.set_top_p(value as f32 / 100.0),
+            SamplingParameter::MaxTokens => language_logic_unit.set_max_tokens(value),
+        }
+
+        crate::debug_print!(debug, "Executed {}: {}", opcode, value);
+
+        Ok(())
+    }
+
+    // Replaces the system message of every later chat request. Context pushed with PSH still
+    // follows it.
+    fn set_system_prompt(
+        registers: &Registers,
+        language_logic_unit: &mut LanguageLogicUnit,
+        instruction: &SetSystemPromptInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let prompt = match &instruction.prompt {
+            SystemPrompt::Text(prompt) => prompt,
+            SystemPrompt::Register(register) => Self::read_text(registers, *register)?,
+        };
+
+        language_logic_unit.set_system_prompt(prompt);
+
+        crate::debug_print!(debug, "Executed SETSYS: {:?}", prompt);
+
+        Ok(())
+    }
+
+    fn vector_put(
+        registers: &Registers,
+        language_logic_unit: &mut LanguageLogicUnit,
+        instruction: &VectorPutInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let key = match &instruction.key {
+            VectorKey::Text(key) => key,
+            VectorKey::Register(register) => Self::read_text(registers, *register)?,
+        };
+        let text = Self::read_text(registers, instruction.source_register)?;
+
+        let embedding_model = language_logic_unit.embedding_model().to_string();
+        language_logic_unit.vector_put(key, text, &embedding_model)?;
+
+        crate::debug_print!(debug, "Executed VPUT: {:?} = {}", key, preview_text(text));
+
+        Ok(())
+    }
+
+    // The matches are joined by a blank line, most similar first. No match, from an empty store,
+    // is an empty string.
+    fn vector_query(
+        registers: &mut Registers,
+        language_logic_unit: &mut LanguageLogicUnit,
+        instruction: &VectorQueryInstruction,
+        config: &Config,
+    ) -> Result<(), Exception> {
+        let query = Self::read_text(registers, instruction.source_register)?.clone();
+
+        let embedding_model = language_logic_unit.embedding_model().to_string();
+        language_logic_unit.set_instruction("VQUERY", Self::current_address(registers));
+        let matches = language_logic_unit.vector_query(
+            &query,
+            instruction.count as usize,
+            &embedding_model,
+        )?;
+        let timings = language_logic_unit.take_instruction_timings();
+
+        crate::debug_print!(
+            config.debug_run,
+            "Executed VQUERY: {} -> r{} = {} of {} match(es){}",
+            preview_text(&query),
+            instruction.destination_register,
+            matches.len(),
+            instruction.count,
+            Self::timings_suffix(timings)
+        );
+
+        registers.set_register(
+            instruction.destination_register,
+            &Value::Text(matches.join("\n\n")),
+        )
+    }
+
+    fn vector_clear(
+        language_logic_unit: &mut LanguageLogicUnit,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        language_logic_unit.vector_clear()?;
+
+        crate::debug_print!(debug, "Executed VCLR");
+
+        Ok(())
+    }
+
+    // The content of a message made from a register by PSH or CTXINS.
+    fn message_content(registers: &Registers, register_number: u32) -> Result<String, Exception> {
+        match registers.get_register(register_number)? {
+            Value::None => Err(Exception::Executor(BaseException::new(
+                format!(
+                    "Register r{} is uninitialised, expected text or number.",
+                    register_number
+                ),
+                None,
+            ))),
+            value => Ok(value.to_string()),
+        }
+    }
+
+    fn context_push(
+        registers: &mut Registers,
+        instruction: &ContextPushInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let value = Self::message_content(registers, instruction.source_register)?;
+
+        registers.push_context(
+            ContextMessage::new(&instruction.role, &value),
+            instruction.destination_context_register,
+        )?;
+
+        crate::debug_print!(
+            debug,
+            "Executed PSH : Pushed value from r{} onto context stack with role '{}'.",
+            instruction.source_register,
+            instruction.role
+        );
+
+        Ok(())
+    }
+
+    fn context_insert(
+        registers: &mut Registers,
+        instruction: &ContextInsertInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let value = Self::message_content(registers, instruction.source_register)?;
+
+        registers.insert_context(
+            ContextMessage::new(&instruction.role, &value),
+            instruction.destination_context_register,
+            instruction.index as usize,
+        )?;
+
+        crate::debug_print!(
+            debug,
+            "Executed CTXINS: Inserted value from r{} into c{} at {} with role '{}'.",
+            instruction.source_register,
+            instruction.destination_context_register,
+            instruction.index,
+            instruction.role
+        );
+
+        Ok(())
+    }
+
+    // Saves in the format PCX prints, so a saved session can be read or edited by hand. CTXSAVE
+    // replaces the file in one step, like WF, holding the file's lock so concurrent saves take turns.
+    fn context_save(
+        registers: &Registers,
+        instruction: &ContextSaveInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let context = registers.get_context(instruction.source_context_register)?;
+        let path = Self::create_parent_directory(&instruction.path)?;
+
+        let saved = atomic_file::write_locked(path, json::to_string(context)).map_err(|e| {
+            Exception::Executor(BaseException::caused_by(
+                format!("Failed to save the context to '{}'", instruction.path),
+                e,
+            ))
+        })?;
+
+        if !saved {
+            return Err(Exception::Executor(BaseException::new(
+                format!(
+                    "Failed to save the context to '{}': another writer holds its lock.",
+                    instruction.path
+                ),
+                None,
+            )));
+        }
+
+        crate::debug_print!(
+            debug,
+            "Executed CTXSAVE: Saved {} messages from c{} to '{}'.",
+            context.len(),
+            instruction.source_context_register,
+            instruction.path
+        );
+
+        Ok(())
+    }
+
+    fn context_load(
+        registers: &mut Registers,
+        instruction: &ContextLoadInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let text = match read_to_string(&instruction.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let Some(jump_index) = instruction.missing_jump_index else {
+                    return Err(Exception::Executor(BaseException::new(
+                        format!(
+                            "Context file '{}' does not exist. Give CTXLOAD a label to jump to instead.",
+                            instruction.path
+                        ),
+                        None,
+                    )));
+                };
+
+                crate::debug_print!(
+                    debug,
+                    "Executed CTXLOAD: '{}' does not exist -> jump {}",
+                    instruction.path,
+                    jump_index
+                );
+
+                registers.set_instruction_pointer(jump_index as usize);
+                return Ok(());
+            }
+            Err(e) => {
+                return Err(Exception::Executor(BaseException::caused_by(
+                    format!("Failed to read the context from '{}'", instruction.path),
+                    e,
+                )));
+            }
+        };
+
+        let context: Vec<ContextMessage> = json::from_str(&text).map_err(|e| {
+            Exception::Executor(BaseException::caused_by(
+                format!(
+                    "Failed to parse the context in '{}'. Expected a list of messages saved by CTXSAVE",
+                    instruction.path
+                ),
+                e,
+            ))
+        })?;
+
+        registers.set_context(instruction.destination_context_register, &context)?;
+
+        crate::debug_print!(
+            debug,
+            "Executed CTXLOAD: Loaded {} messages from '{}' into c{}.",
+            context.len(),
+            instruction.path,
+            instruction.destination_context_register
+        );
+
+        Ok(())
+    }
+
+    fn context_peek(
+        registers: &mut Registers,
+        instruction: &ContextPeekInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let content = registers
+            .peek_context(instruction.source_context_register)?
+            .content
+            .clone();
+
+        crate::debug_print!(
+            debug,
+            "Executed CTXPEEK: r{} = {}",
+            instruction.destination_register,
+            preview_text(&content)
+        );
+
+        registers.set_register(instruction.destination_register, &Value::Text(content))
+    }
+
+    fn context_pop(
+        registers: &mut Registers,
+        instruction: &ContextPopInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let context = registers.pop_context(instruction.source_context_register)?;
+
+        registers.set_register(
+            instruction.destination_register,
+            &Value::Text(context.content.clone()),
+        )?;
+
+        crate::debug_print!(
+            debug,
+            "Executed POP : r{} = {}",
+            instruction.destination_register,
+            preview_text(&context.content)
+        );
+
+        Ok(())
+    }
+
+    fn context_drop(
+        registers: &mut Registers,
+        instruction: &ContextDropInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        registers.pop_context(instruction.source_context_register)?;
+
+        crate::debug_print!(debug, "Executed DRP : Dropped value from context stack.",);
+
+        Ok(())
+    }
+
+    fn move_context(
+        registers: &mut Registers,
+        instruction: &MoveContextInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let value = registers
+            .get_context(instruction.source_context_register)?
+            .to_vec();
+        registers.set_context(instruction.destination_context_register, &value)?;
+
+        crate::debug_print!(
+            debug,
+            "Executed MVC : c{} = c{}",
+            instruction.destination_context_register,
+            instruction.source_context_register
+        );
+
+        Ok(())
+    }
+
+    fn context_length(
+        registers: &mut Registers,
+        instruction: &ContextLengthInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let length = registers
+            .get_context(instruction.source_context_register)?
+            .len();
+        let length = i32::try_from(length).unwrap_or(i32::MAX);
+
+        crate::debug_print!(
+            debug,
+            "Executed CTXLEN: r{} = {}",
+            instruction.destination_register,
+            length
+        );
+
+        registers.set_register(instruction.destination_register, &Value::Number(length))
+    }
+
+    fn context_trim(
+        registers: &mut Registers,
+        instruction: &ContextTrimInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let dropped = registers.trim_context(
+            instruction.destination_context_register,
+            instruction.count as usize,
+        )?;
+
+        crate::debug_print!(
+            debug,
+            "Executed CTXTRIM: c{} dropped {} message(s), keeping the {} most recent.",
+            instruction.destination_context_register,
+            dropped,
+            instruction.count
+        );
+
+        Ok(())
+    }
+
+    // Register addresses must hold an integer. The assembler never emits an immediate address past i32::MAX,
+    // and MAX_DATA_WORDS cannot reach it, so clamping one from other byte code still fails the range check.
+    fn address(registers: &Registers, address: &Address) -> Result<i32, Exception> {
+        match address {
+            Address::Immediate(address) => Ok(i32::try_from(*address).unwrap_or(i32::MAX)),
+            Address::Register(register) => Self::read_number(registers, *register),
+        }
+    }
+
+    fn store_word(
+        registers: &mut Registers,
+        instruction: &StoreWordInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let address = Self::address(registers, &instruction.address)?;
+        let value = registers.get_register(instruction.source_register)?.clone();
+        registers.store_word(address, &value)?;
+
+        crate::debug_print!(debug, "Executed SW  : [{}] = {}", address, value.preview());
+
+        Ok(())
+    }
+
+    fn load_word(
+        registers: &mut Registers,
+        instruction: &LoadWordInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let address = Self::address(registers, &instruction.address)?;
+        let value = registers.load_word(address)?.clone();
+        registers.set_register(instruction.destination_register, &value)?;
+
+        crate::debug_print!(
+            debug,
+            "Executed LW  : r{} = {}",
+            instruction.destination_register,
+            value.preview()
+        );
+
+        Ok(())
+    }
+
+    fn stack_push(
+        registers: &mut Registers,
+        instruction: &StackPushInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let value = registers.get_register(instruction.source_register)?.clone();
+        registers.push_stack(&value);
+
+        crate::debug_print!(
+            debug,
+            "Executed SPUSH: Pushed r{} ({}), stack depth {}.",
+            instruction.source_register,
+            value.preview(),
+            registers.stack().len()
+        );
+
+        Ok(())
+    }
+
+    fn stack_pop(
+        registers: &mut Registers,
+        instruction: &StackPopInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let value = registers.pop_stack()?;
+        registers.set_register(instruction.destination_register, &value)?;
+
+        crate::debug_print!(
+            debug,
+            "Executed SPOP: r{} = {}, stack depth {}.",
+            instruction.destination_register,
+            value.preview(),
+            registers.stack().len()
+        );
+
+        Ok(())
+    }
+
+    fn stack_peek(
+        registers: &mut Registers,
+        instruction: &StackPeekInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let value = registers.peek_stack()?.clone();
+        registers.set_register(instruction.destination_register, &value)?;
+
+        crate::debug_print!(
+            debug,
+            "Executed SPEEK: r{} = {}",
+            instruction.destination_register,
+            value.preview()
+        );
+
+        Ok(())
+    }
+
+    // Integer arithmetic runs locally and never reaches the model. Division truncates toward zero.
+    // Operands are described as they appear in the error message, e.g. "register r1 (5)".
+    fn calculate(
+        operation: &ArithmeticOperation,
+        left: i32,
+        right: i32,
+        left_operand: &str,
+        right_operand: &str,
+    ) -> Result<i32, Exception> {
+        let result = match operation {
+            ArithmeticOperation::Add => left.checked_add(right),
+            ArithmeticOperation::Subtract => left.checked_sub(right),
+            ArithmeticOperation::Multiply => left.checked_mul(right),
+            ArithmeticOperation::Divide => left.checked_div(right),
+        };
+
+        if let Some(result) = result {
+            return Ok(result);
+        }
+
+        if matches!(operation, ArithmeticOperation::Divide) && right == 0 {
+            return Err(Exception::Executor(BaseException::new(
+                format!(
+                    "Cannot divide {} by {} because it is zero.",
+                    left_operand, right_operand
+                ),
+                None,
+            )));
+        }
+
+        let action = match operation {
+            ArithmeticOperation::Add => format!("add {} to {}", right_operand, left_operand),
+            ArithmeticOperation::Subtract => {
+                format!("subtract {} from {}", right_operand, left_operand)
+            }
+            ArithmeticOperation::Multiply => {
+                format!("multiply {} by {}", left_operand, right_operand)
+            }
+            ArithmeticOperation::Divide => format!("divide {} by {}", left_operand, right_operand),
+        };
+
+        Err(Exception::Executor(BaseException::new(
+            format!(
+                "Cannot {} because the result is out of range ({} to {}).",
+                action,
+                i32::MIN,
+                i32::MAX
+            ),
+            None,
+        )))
+    }
+
+    fn arithmetic_opcode(operation: &ArithmeticOperation, immediate: bool) -> &'static str {
+        match (operation, immediate) {
+            (ArithmeticOperation::Add, false) => "ADDN",
+            (ArithmeticOperation::Subtract, false) => "SUBN",
+            (ArithmeticOperation::Multiply, false) => "MULN",
+            (ArithmeticOperation::Divide, false) => "DIVN",
+            (ArithmeticOperation::Add, true) => "ADDI",
+            (ArithmeticOperation::Subtract, true) => "SUBI",
+            (ArithmeticOperation::Multiply, true) => "MULI",
+            (ArithmeticOperation::Divide, true) => "DIVI",
+        }
+    }
+
+    fn arithmetic(
+        registers: &mut Registers,
+        instruction: &ArithmeticInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let left = Self::read_number(registers, instruction.source_register_1)?;
+        let right = Self::read_number(registers, instruction.source_register_2)?;
+
+        let result = Self::calculate(
+            &instruction.operation,
+            left,
+            right,
+            &format!("register r{} ({})", instruction.source_register_1, left),
+            &format!("register r{} ({})", instruction.source_register_2, right),
+        )?;
+
+        crate::debug_print!(
+            debug,
+            "Executed {}: r{} = {}",
+            Self::arithmetic_opcode(&instruction.operation, false),
+            instruction.destination_register,
+            result
+        );
+
+        registers.set_register(instruction.destination_register, &Value::Number(result))
+    }
+
+    fn arithmetic_immediate(
+        registers: &mut Registers,
+        instruction: &ArithmeticImmediateInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let value = Self::read_number(registers, instruction.source_register)?;
+
+        let result = Self::calculate(
+            &instruction.operation,
+            value,
+            instruction.value,
+            &format!("register r{} ({})", instruction.source_register, value),
+            &instruction.value.to_string(),
+        )?;
+
+        crate::debug_print!(
+            debug,
+            "Executed {}: r{} = {}",
+            Self::arithmetic_opcode(&instruction.operation, true),
+            instruction.source_register,
+            result
+        );
+
+        registers.set_register(instruction.source_register, &Value::Number(result))
+    }
+
+    // Formats a number using the spec [0][width][,][%]: a leading '0' pads with zeros instead of spaces,
+    // ',' groups thousands, and '%' appends a percent sign. The width includes the separators and the percent
+    // sign. Negative numbers keep their sign ahead of any padding zeros.
+    fn format_number(value: i32, spec: &str) -> Result<String, Exception> {
+        let invalid_spec = || {
+            Exception::Executor(BaseException::new(
+                format!(
+                    "Invalid number format spec '{}'. Expected [0][width][,][%].",
+                    spec
+                ),
+                None,
+            ))
+        };
+
+        let (zero_pad, rest) = match spec.strip_prefix('0') {
+            Some(rest) => (true, rest),
+            None => (false, spec),
+        };
+
+        let width_end = rest
+            .find(|ch: char| !ch.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let width = match &rest[..width_end] {
+            "" => 0,
+            digits => digits.parse::<usize>().map_err(|_| invalid_spec())?,
+        };
+
+        let rest = &rest[width_end..];
+        let (thousands, rest) = match rest.strip_prefix(',') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let (percent, rest) = match rest.strip_prefix('%') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+
+        if !rest.is_empty() {
+            return Err(invalid_spec());
+        }
+
+        let sign = if value < 0 { "-" } else { "" };
+        let mut digits = value.unsigned_abs().to_string();
+
+        if thousands {
+            let grouped = digits
+                .as_bytes()
+                .rchunks(3)
+                .rev()
+                .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+                .collect::<Vec<String>>();
+            digits = grouped.join(",");
+        }
+
+        if percent {
+            digits.push('%');
+        }
+
+        // The width covers the whole field, so padding is added last.
+        let formatted = if zero_pad {
+            let zeros = width.saturating_sub(sign.len() + digits.chars().count());
+            format!("{}{}{}", sign, "0".repeat(zeros), digits)
+        } else {
+            format!("{:>width$}", format!("{}{}", sign, digits), width = width)
+        };
+
+        Ok(formatted)
+    }
+
+    fn format_number_instruction(
+        registers: &mut Registers,
+        instruction: &FormatNumberInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let value = Self::read_number(registers, instruction.source_register)?;
+        let formatted = Self::format_number(value, &instruction.spec)?;
+
+        crate::debug_print!(
+            debug,
+            "Executed FMTN: r{} = {} (r{} = {}, spec '{}')",
+            instruction.destination_register,
+            preview_text(&formatted),
+            instruction.source_register,
+            value,
+            instruction.spec
+        );
+
+        registers.set_register(instruction.destination_register, &Value::Text(formatted))
+    }
+
+    // Escape the template syntax in text so it survives being spliced into an assembled template verbatim.
+    fn escape_template(text: &str, syntax: EscapeSyntax) -> String {
+        match syntax {
+            EscapeSyntax::Interpolation => text.replace("${", "$${"),
+            EscapeSyntax::Positional => text.replace('{', "{{").replace('}', "}}"),
+        }
+    }
+
+    fn escape(
+        registers: &mut Registers,
+        instruction: &EscapeInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let value = match registers.get_register(instruction.source_register)? {
+            Value::None => {
+                return Err(Exception::Executor(BaseException::new(
+                    format!(
+                        "Register r{} is uninitialised, expected a value to escape.",
+                        instruction.source_register
+                    ),
+                    None,
+                )));
+            }
+            value => Value::Text(Self::escape_template(
+                &value.to_string(),
+                instruction.syntax,
+            )),
+        };
+
+        crate::debug_print!(
+            debug,
+            "Executed ESC : r{} = {}",
+            instruction.destination_register,
+            value.preview()
+        );
+
+        registers.set_register(instruction.destination_register, &value)
+    }
+
+    // Text is joined as is and numbers as they print, with nothing between them.
+    fn concatenate(
+        registers: &mut Registers,
+        instruction: &ConcatenateInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let mut joined = String::new();
+
+        for register in [instruction.source_register_1, instruction.source_register_2] {
+            match registers.get_register(register)? {
+                Value::None => {
+                    return Err(Exception::Executor(BaseException::new(
+                        format!(
+                            "Register r{} is uninitialised, expected a value to concatenate.",
+                            register
+                        ),
+                        None,
+                    )));
+                }
+                value => joined.push_str(&value.to_string()),
+            }
+        }
+
+        let value = Value::Text(joined);
+
+        crate::debug_print!(
+            debug,
+            "Executed CAT : r{} = {}",
+            instruction.destination_register,
+            value.preview()
+        );
+
+        registers.set_register(instruction.destination_register, &value)
+    }
+
+    // LEN counts characters rather than bytes, and UPPER and LOWER follow Unicode case mapping.
+    fn text(
+        registers: &mut Registers,
+        instruction: &TextInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let text = Self::read_text(registers, instruction.source_register)?;
+
+        let value = match instruction.operation {
+            TextOperation::Length => {
+                Value::Number(i32::try_from(text.chars().count()).map_err(|e| {
+                    Exception::Executor(BaseException::caused_by(
+                        format!(
+                            "Length of r{} does not fit in a number.",
+                            instruction.source_register
+                        ),
+                        e.to_string(),
+                    ))
+                })?)
+            }
+            TextOperation::Upper => Value::Text(text.to_uppercase()),
+            TextOperation::Lower => Value::Text(text.to_lowercase()),
+            TextOperation::Trim => Value::Text(text.trim().to_string()),
+        };
+
+        let opcode = match instruction.operation {
+            TextOperation::Length => "LEN",
+            TextOperation::Upper => "UPPER",
+            TextOperation::Lower => "LOWER",
+            TextOperation::Trim => "TRIM",
+        };
+
+        crate::debug_print!(
+            debug,
+            "Executed {:<4}: r{} = {}",
+            opcode,
+            instruction.destination_register,
+            value.preview()
+        );
+
+        registers.set_register(instruction.destination_register, &value)
+    }
+
+    fn token_count(
+        registers: &mut Registers,
+        language_logic_unit: &mut LanguageLogicUnit,
+        instruction: &TokenCountInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let text = Self::read_text(registers, instruction.source_register)?.clone();
+        let tokens = language_logic_unit.count_tokens(&text)?;
+
+        let value = Value::Number(i32::try_from(tokens).map_err(|e| {
+            Exception::Executor(BaseException::caused_by(
+                format!(
+                    "Token count of r{} does not fit in a number.",
+                    instruction.source_register
+                ),
+                e.to_string(),
+            ))
+        })?);
+
+        crate::debug_print!(
+            debug,
+            "Executed TOK : r{} = {}",
+            instruction.destination_register,
+            value.preview()
+        );
+
+        registers.set_register(instruction.destination_register, &value)
+    }
+
+    fn chunk(
+        registers: &mut Registers,
+        instruction: &ChunkInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        // Byte code is checked the same as source, since a size of 0 would never make progress.
+        if instruction.size == 0 {
+            return Err(Exception::Executor(BaseException::new(
+                "CHUNK needs a chunk size of at least 1 character.".to_string(),
+                None,
+            )));
+        }
+
+        let text = Self::read_text(registers, instruction.source_register)?;
+        let chunks = chunker::split(text, instruction.size as usize)
+            .into_iter()
+            .map(|chunk| Value::Text(chunk.to_string()))
+            .collect::<Vec<_>>();
+
+        let count = i32::try_from(chunks.len()).map_err(|e| {
+            Exception::Executor(BaseException::caused_by(
+                format!(
+                    "Number of chunks of r{} does not fit in a number.",
+                    instruction.source_register
+                ),
+                e.to_string(),
+            ))
+        })?;
+
+        // The last chunk is stored first, so chunks that would run past MAX_DATA_WORDS fail before
+        // any word is changed. An address past i32::MAX is past every limit.
+        for (offset, chunk) in chunks.iter().enumerate().rev() {
+            let address = i32::try_from(instruction.address as usize + offset).unwrap_or(i32::MAX);
+            registers.store_word(address, chunk)?;
+        }
+
+        registers.set_register(instruction.destination_register, &Value::Number(count))?;
+
+        crate::debug_print!(
+            debug,
+            "Executed CHUNK: r{} = {} chunk(s) of at most {} characters at [{}]",
+            instruction.destination_register,
+            count,
+            instruction.size,
+            instruction.address
+        );
+
+        Ok(())
+    }
+
+    // Strings are stored as text and whole numbers that fit as numbers. Anything else is stored as
+    // its JSON text, so an object or array can be read by another JGET. null is empty text.
+    fn json_get(
+        registers: &mut Registers,
+        instruction: &JsonGetInstruction,
+        debug: bool,
+    ) -> Result<(), Exception> {
+        let keys = json_fields::path(&instruction.path)
+            .map_err(|message| Exception::Executor(BaseException::new(message, None)))?;
+        let text = Self::read_text(registers, instruction.source_register)?;
+
+        // miniserde does not say where parsing failed, so there is no cause to add.
+        let json = json::from_str::<json::Value>(text).map_err(|_| {
+            Exception::Executor(BaseException::new(
+                format!("r{} does not hold valid JSON.", instruction.source_register),
+                None,
+            ))
+        })?;
+
+        let found = json_fields::lookup(&json, &keys).ok_or_else(|| {
+            Exception::Executor(BaseException::new(
+                format!(
+                    "The JSON in r{} has no field '{}'.",
+                    instruction.source_register, instruction.path
+                ),
+                None,
+            ))
+        })?;
+
+        let value = match found {
+            json::Value::Null => Value::Text(String::new()),
+            json::Value::String(text) => Value::Text(text.clone()),
+            json::Value::Number(number) => match number {
+                Number::U64(number) => i32::try_from(*number).ok(),
+                Number::I64(number) => i32::try_from(*number).ok(),
+                Number::F64(_) => None,
+            }
+            .map_or_else(|| Value::Text(json::to_string(found)), Value::Number),
+            other => Value::Text(json::to_string(other)),
+        };
+
+        crate::debug_print!(
+            debug,
+            "Executed JGET: r{} = r{}[{}] = {}",
+            instruction.destination_register,
+            instruction.source_register,
+            instruction.path,
+            value.preview()
+        );
+
+        registers.set_register(instruction.destination_register, &value)
+    }
+
+    // Counts the instruction in the run's stats once it has run.
+    pub fn record_stats(registers: &Registers, instruction: &Instruction, stats: &mut Stats) {
+        stats.instructions += 1;
+        *stats.opcodes.entry(instruction.mnemonic()).or_default() += 1;
+
+        let longest_context = registers.contexts().iter().map(Vec::len).max();
+        stats.peak_context_messages = stats
+            .peak_context_messages
+            .max(longest_context.unwrap_or(0));
+    }
+
+    fn context_lengths(registers: &Registers) -> Vec<usize> {
+        registers.contexts().iter().map(Vec::len).collect()
+    }
+
+    // Runs the instruction, counts it in the stats and, when tracing, records what it did and how long it took.
+    pub fn execute(
+        memory: &mut Memory,
+        registers: &mut Registers,
+        language_logic_unit: &mut LanguageLogicUnit,
+        instruction: &Instruction,
+        config: &Config,
+        output: &mut Output,
+        trace: Option<&mut (dyn TraceSink + '_)>,
+    ) -> Result<(), Exception> {
+        let Some(trace) = trace else {
+            return Self::dispatch(
+                memory,
+                registers,
+                language_logic_unit,
+                instruction,
+                config,
+                output,
+            );
+        };
+
+        let address = Self::current_address(registers);
+        let lengths = Self::context_lengths(registers);
+        let requests = language_logic_unit.requests();
+        let usage = language_logic_unit.run_usage();
+        let start = Instant::now();
+
+        Self::dispatch(
+            memory,
+            registers,
+            language_logic_unit,
+            instruction,
+            config,
+            output,
+        )?;
+
+        let duration = start.elapsed();
+
+        let destination = instruction.destination_register();
+        let value = destination
+            .map(|register| registers.get_register(register).map(Value::preview))
+            .transpose()?;
+
+        let contexts = Self::context_lengths(registers)
+            .into_iter()
+            .zip(lengths)
+            .enumerate()
+            .filter(|(_, (after, before))| after != before)
+            .map(|(register, (messages, _))| ContextLength {
+                register: register as u32,
+                messages,
+            })
+            .collect();
+
+        trace.record(&TraceEvent {
+            instruction_pointer: address,
+            mnemonic: instruction.mnemonic().to_string(),
+            registers: instruction.registers(),
+            destination,
+            value,
+            contexts,
+            duration_ms: duration.as_secs_f64() * 1000.0,
+            model_call: language_logic_unit.requests() > requests,
+            tokens: language_logic_unit
+                .run_usage()
+                .filter(|&total| Some(total) != usage)
+                .map(|total| total.since(&usage.unwrap_or_default())),
+        })
+    }
+
+    fn dispatch(
+        memory: &mut Memory,
+        registers: &mut Registers,
+        language_logic_unit: &mut LanguageLogicUnit,
+        instruction: &Instruction,
+        config: &Config,
+        output: &mut Output,
+    ) -> Result<(), Exception> {
+        if instruction.destination_register() == Some(0) {
+            crate::debug_print!(
+                config.debug_run,
+                "Note: {} writes to x0, which always reads as 0, so its result is discarded.",
+                instruction.mnemonic().to_uppercase()
+            );
+        }
+
         match instruction {
             // Data movement operations.
             Instruction::LoadString(i) => Self::load_string(registers, i, config.debug_run),
             Instruction::LoadImmediate(i) => Self::load_immediate(registers, i, config.debug_run),
-            Instruction::LoadContent(i) => Self::load_content(registers, i, config.debug_run),
+            Instruction::LoadContent(i) => {
+                Self::load_content(registers, i, config.debug_run, config.strict_utf8)
+            }
+            Instruction::LoadArgument(i) => Self::load_argument(registers, i, config),
+            Instruction::LoadPositionalArgument(i) => {
+                Self::load_positional_argument(registers, i, config)
+            }
+            Instruction::LoadArgumentCount(i) => Self::load_argument_count(registers, i, config),
+            Instruction::LoadDirectory(i) => Self::load_directory(registers, i, config.debug_run),
+            Instruction::LoadEnvironment(i) => {
+                Self::load_environment(registers, i, config.debug_run)
+            }
+            Instruction::StoreWord(i) => Self::store_word(registers, i, config.debug_run),
+            Instruction::LoadWord(i) => Self::load_word(registers, i, config.debug_run),
             Instruction::Move(i) => Self::mov(registers, i, config.debug_run),
             // Control flow operations.
             Instruction::Branch(i) => Self::branch(registers, i, config.debug_run),
-            Instruction::Exit(_) => {
-                Self::exit(memory, registers, config.debug_run);
-                Ok(())
+            Instruction::Jump(i) => Self::jump(registers, i, config.debug_run),
+            Instruction::Exit(i) => Self::exit(memory, registers, i, config.debug_run),
+            Instruction::Checkpoint(i) => {
+                Self::checkpoint(memory, registers, language_logic_unit, i, config)
             }
             // I/O operations.
-            Instruction::Print(i) => Self::print(registers, i, config.debug_run),
-            Instruction::PrintLine(i) => Self::print_line(registers, i, config.debug_run),
-            Instruction::PrintContext(i) => Self::print_context(registers, i, config.debug_run),
+            Instruction::Print(i) => Self::print(registers, i, output, config.debug_run),
+            Instruction::PrintLine(i) => Self::print_line(registers, i, output, config.debug_run),
+            Instruction::PrintError(i) => Self::print_error(registers, i, output, config.debug_run),
+            Instruction::PrintContext(i) => {
+                Self::print_context(registers, i, output, config.debug_run)
+            }
+            Instruction::Input(i) => Self::input(registers, i, output, config.debug_run),
+            Instruction::WriteFile(i) => Self::write_file(registers, i, config.debug_run),
             // Generative operations.
-            Instruction::Inference(i) => Self::inference(
-                registers,
-                i,
-                &config.text_model,
-                &config.text_model_overrides,
-                config.debug_run,
-                config.debug_chat,
-            ),
+            Instruction::Inference(i) => Self::inference(registers, language_logic_unit, i, config),
+            Instruction::Ask(i) => Self::ask(registers, language_logic_unit, i, config),
+            Instruction::Translate(i) => Self::translate(registers, language_logic_unit, i, config),
             // Guardrails operations.
-            Instruction::Evaluate(i) => Self::evaluate(
-                registers,
-                i,
-                &config.text_model,
-                &config.embedding_model,
-                &config.text_model_overrides,
-                config.debug_run,
-                config.debug_chat,
-            ),
-            Instruction::Similarity(i) => Self::similarity(registers, i, &config.embedding_model, config.debug_run),
+            Instruction::Evaluate(i) => Self::evaluate(registers, language_logic_unit, i, config),
+            Instruction::Similarity(i) => {
+                Self::similarity(registers, language_logic_unit, i, config)
+            }
+            Instruction::Embed(i) => Self::embed(registers, language_logic_unit, i, config),
+            Instruction::BranchSimilar(i) => {
+                Self::branch_similar(registers, language_logic_unit, i, config)
+            }
+            Instruction::Classify(i) => Self::classify(registers, language_logic_unit, i, config),
+            Instruction::Score(i) => Self::score(registers, language_logic_unit, i, config),
+            Instruction::Json(i) => Self::json(registers, language_logic_unit, i, config),
+            // Model selection.
+            Instruction::SetModel(i) => Self::set_model(language_logic_unit, i, config.debug_run),
+            // Sampling.
+            Instruction::SetSampling(i) => {
+                Self::set_sampling(registers, language_logic_unit, i, config.debug_run)
+            }
+            Instruction::SetSystemPrompt(i) => {
+                Self::set_system_prompt(registers, language_logic_unit, i, config.debug_run)
+            }
+            // Vector store.
+            Instruction::VectorPut(i) => {
+                Self::vector_put(registers, language_logic_unit, i, config.debug_run)
+            }
+            Instruction::VectorQuery(i) => {
+                Self::vector_query(registers, language_logic_unit, i, config)
+            }
+            Instruction::VectorClear => Self::vector_clear(language_logic_unit, config.debug_run),
             // Context operations.
             Instruction::ContextPush(i) => Self::context_push(registers, i, config.debug_run),
             Instruction::ContextPop(i) => Self::context_pop(registers, i, config.debug_run),
             Instruction::ContextDrop(i) => Self::context_drop(registers, i, config.debug_run),
             Instruction::MoveContext(i) => Self::move_context(registers, i, config.debug_run),
+            Instruction::ContextLength(i) => Self::context_length(registers, i, config.debug_run),
+            Instruction::ContextTrim(i) => Self::context_trim(registers, i, config.debug_run),
+            Instruction::ContextPeek(i) => Self::context_peek(registers, i, config.debug_run),
+            Instruction::ContextInsert(i) => Self::context_insert(registers, i, config.debug_run),
+            Instruction::ContextSave(i) => Self::context_save(registers, i, config.debug_run),
+            Instruction::ContextLoad(i) => Self::context_load(registers, i, config.debug_run),
             // Arithmetic operations.
-            Instruction::SubtractImmediate(i) => Self::subtract_immediate(registers, i, config.debug_run),
+            // Stack operations.
+            Instruction::StackPush(i) => Self::stack_push(registers, i, config.debug_run),
+            Instruction::StackPop(i) => Self::stack_pop(registers, i, config.debug_run),
+            Instruction::StackPeek(i) => Self::stack_peek(registers, i, config.debug_run),
+            Instruction::Arithmetic(i) => Self::arithmetic(registers, i, config.debug_run),
+            Instruction::ArithmeticImmediate(i) => {
+                Self::arithmetic_immediate(registers, i, config.debug_run)
+            }
+            // String operations.
+            Instruction::FormatNumber(i) => {
+                Self::format_number_instruction(registers, i, config.debug_run)
+            }
+            Instruction::Escape(i) => Self::escape(registers, i, config.debug_run),
+            Instruction::Concatenate(i) => Self::concatenate(registers, i, config.debug_run),
+            Instruction::Text(i) => Self::text(registers, i, config.debug_run),
+            Instruction::TokenCount(i) => {
+                Self::token_count(registers, language_logic_unit, i, config.debug_run)
+            }
+            Instruction::Chunk(i) => Self::chunk(registers, i, config.debug_run),
+            Instruction::JsonGet(i) => Self::json_get(registers, i, config.debug_run),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_number_pads_after_grouping_and_percent() {
+        assert_eq!(Executor::format_number(6, "05,%").unwrap(), "0006%");
+        assert_eq!(Executor::format_number(1234, "07,%").unwrap(), "01,234%");
+        assert_eq!(Executor::format_number(-6, "05%").unwrap(), "-006%");
+        assert_eq!(Executor::format_number(7, "04%").unwrap(), "007%");
+        assert_eq!(Executor::format_number(1234567, ",").unwrap(), "1,234,567");
+        assert_eq!(Executor::format_number(42, "6%").unwrap(), "   42%");
+        assert_eq!(Executor::format_number(123456, "03").unwrap(), "123456");
+    }
+
+    #[test]
+    fn format_number_rejects_unknown_spec() {
+        assert!(Executor::format_number(1, "5x").is_err());
+        assert!(Executor::format_number(1, "%,").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn list_directory_does_not_follow_links_to_directories() {
+        let root = env::temp_dir().join(format!("lpu-ld-{}", std::process::id()));
+        let nested = root.join("a").join("b");
+        create_dir_all(&nested).unwrap();
+        std::fs::write(root.join("top.txt"), "top").unwrap();
+        std::fs::write(nested.join("deep.txt"), "deep").unwrap();
+        std::fs::write(root.join("target.txt"), "target").unwrap();
+        std::os::unix::fs::symlink(&root, nested.join("loop")).unwrap();
+        std::os::unix::fs::symlink(root.join("target.txt"), root.join("link.txt")).unwrap();
+
+        let mut files = Vec::new();
+        let result = Executor::list_directory(&root, &root, &mut files);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        result.unwrap();
+        files.sort();
+        assert_eq!(files, ["a/b/deep.txt", "link.txt", "target.txt", "top.txt"]);
+    }
+
+    // Runs source against a scripted backend answering every chat request with "ok". Returns the
+    // processor and the prompts it sent.
+    fn run_scripted(source: &str) -> (crate::Processor, Vec<String>) {
+        let byte_code = crate::Assembler::new(source.to_string(), "test.aasm")
+            .assemble()
+            .unwrap();
+
+        let mut backend = crate::ScriptedBackend::new();
+        (0..8).for_each(|_| backend.push_response("ok"));
+        let prompts = backend.prompts();
+
+        let mut processor = crate::Processor::new(Config::new("text", "embedding"));
+        processor.set_backend(Box::new(backend));
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+
+        let prompts = prompts.lock().unwrap().clone();
+        (processor, prompts)
+    }
+
+    fn text(processor: &crate::Processor, register: u32) -> String {
+        processor.register(register).unwrap().to_string()
+    }
+
+    #[test]
+    fn interpolated_values_reach_the_prompt_literally() {
+        let source = "LS X1, \"$${x1} {1} `x2` }\"\nFMT X2, \"Q: {1}\", X1\nLS X3, \"R: ${x1}\"\nINF X4, X2, C1\nINF X5, X3, C1\nEXIT\n";
+        let (_, prompts) = run_scripted(source);
+
+        assert_eq!(prompts, ["Q: ${x1} {1} `x2` }", "R: ${x1} {1} `x2` }"]);
+    }
+
+    #[test]
+    fn escaped_values_stay_literal_in_built_templates() {
+        let value = "${x1} {1} {{2}} `x3` }";
+        let source = format!(
+            "LS X1, \"{}\"\nESC X2, X1\nESC X3, X1, \"fmt\"\nEXIT\n",
+            Executor::escape_template(value, EscapeSyntax::Interpolation)
+        );
+        let (processor, _) = run_scripted(&source);
+        assert_eq!(text(&processor, 1), value);
+
+        // Programs a run might write out and build later, splicing the escaped values into templates.
+        let built = format!(
+            "LI X9, 5\nLS X1, \"A ${{x9}} {}\"\nFMT X2, \"B {{1}} {}\", X9\nINF X3, X1, C1\nINF X4, X2, C1\nEXIT\n",
+            text(&processor, 2),
+            text(&processor, 3)
+        );
+        let (_, prompts) = run_scripted(&built);
+
+        assert_eq!(
+            prompts,
+            [format!("A 5 {}", value), format!("B 5 {}", value)]
+        );
+    }
+
+    #[test]
+    fn escape_syntax_round_trips_through_the_disassembler() {
+        let source = "ESC X2, X1\nESC X3, X1, \"fmt\"\nEXIT\n";
+        let byte_code = crate::Assembler::new(source.to_string(), "esc.aasm")
+            .assemble()
+            .unwrap();
+        let listing = crate::Processor::disassemble(&byte_code)
+            .unwrap()
+            .join("\n");
+
+        assert!(listing.contains("esc x2, x1\n"), "{}", listing);
+        assert!(listing.contains("esc x3, x1, \"fmt\""), "{}", listing);
+        assert!(
+            crate::Assembler::new("ESC X2, X1, \"json\"\n".to_string(), "esc.aasm")
+                .assemble()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn an_oversized_register_names_the_instruction_that_grew_it() {
+        let source = "LS X1, \"abcdefgh\"\nLS X2, \"\"\nloop:\nCAT X2, X2, X1\nJMP loop\n";
+        let byte_code = crate::Assembler::new(source.to_string(), "grow.aasm")
+            .assemble()
+            .unwrap();
+
+        let mut config = Config::new("text", "embedding");
+        config.max_register_bytes = 100;
+
+        let mut processor = crate::Processor::new(config);
+        processor.load(&byte_code).unwrap();
+        let error = format!("{:?}", processor.run().unwrap_err());
+
+        assert!(error.contains("'cat x2, x2, x1'"), "{}", error);
+        assert!(error.contains("104 bytes for register r2"), "{}", error);
+        assert_eq!(processor.stats().largest_register, Some((2, 96)));
+    }
+
+    #[test]
+    fn output_is_written_byte_for_byte_to_its_stream() {
+        let source = "LS X1, \"50%\"\nLI X2, 7\nPUT X1\nPUT X2\nPLN X1\nERR X2\nPUT X1\nEXIT\n";
+        let byte_code = crate::Assembler::new(source.to_string(), "output.aasm")
+            .assemble()
+            .unwrap();
+        let (stdout, stderr) = (
+            crate::CapturedOutput::default(),
+            crate::CapturedOutput::default(),
+        );
+
+        let mut processor = crate::Processor::new(Config::new("text", "embedding"));
+        processor.set_output(Box::new(stdout.clone()), Box::new(stderr.clone()));
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+
+        // PUT adds no newline of its own, and ERR output stays out of stdout.
+        assert_eq!(stdout.bytes(), b"50%750%\n50%");
+        assert_eq!(stderr.bytes(), b"7\n");
+    }
+
+    // Runs source that must fail, returning the error.
+    fn run_error(source: &str) -> String {
+        let byte_code = crate::Assembler::new(source.to_string(), "test.aasm")
+            .assemble()
+            .unwrap();
+
+        let mut processor = crate::Processor::new(Config::new("text", "embedding"));
+        processor.load(&byte_code).unwrap();
+        format!("{:?}", processor.run().unwrap_err())
+    }
+
+    #[test]
+    fn arithmetic_overflow_and_division_by_zero_are_errors() {
+        let error = run_error("LI X1, 2147483647\nLI X2, 1\nADDN X3, X1, X2\nEXIT\n");
+        assert!(error.contains("result is out of range"), "{}", error);
+
+        let error = run_error("LI X1, 7\nDIVN X2, X1, X0\nEXIT\n");
+        assert!(error.contains("because it is zero"), "{}", error);
+
+        let error = run_error("LI X1, 7\nDIVI X1, 0\nEXIT\n");
+        assert!(error.contains("because it is zero"), "{}", error);
+
+        let (processor, _) = run_scripted("LI X1, 41\nADDI X1, 1\nLI X2, -7\nDIVI X2, 2\nEXIT\n");
+        assert_eq!(text(&processor, 1), "42");
+        assert_eq!(text(&processor, 2), "-3");
+    }
+
+    #[test]
+    fn inc_past_the_largest_integer_is_an_error() {
+        let error = run_error("LI X1, 2147483647\nINC X1, 1\nEXIT\n");
+        assert!(error.contains("result is out of range"), "{}", error);
+
+        let (processor, _) = run_scripted("LI X1, 41\nINC X1, 1\nEXIT\n");
+        assert_eq!(text(&processor, 1), "42");
+    }
+
+    #[test]
+    fn registers_saved_on_the_stack_are_restored() {
+        let source = "LI X1, 1\nLS X2, \"two\"\nLI X3, 3\nSPUSH X1\nSPUSH X2\nSPUSH X3\nLI X1, 0\nLS X2, \"clobbered\"\nMV X3, X2\nSPOP X3\nSPOP X2\nSPOP X1\nEXIT\n";
+        let (processor, _) = run_scripted(source);
+
+        assert_eq!(text(&processor, 1), "1");
+        assert_eq!(text(&processor, 2), "two");
+        assert_eq!(text(&processor, 3), "3");
+
+        let error = run_error("LI X1, 1\nSPUSH X1\nSPOP X2\nSPOP X3\nEXIT\n");
+        assert!(error.contains("Data stack is empty"), "{}", error);
+    }
+
+    #[test]
+    fn written_files_load_back_unchanged() {
+        let path = env::temp_dir().join(format!("lpu-wf-{}", std::process::id()));
+        let file = path.join("nested").join("out.txt");
+        let file = file.to_str().unwrap();
+        let value = "line one\nline two é\n";
+
+        let source = format!(
+            "LS X1, \"{}\"\nWF \"{}\", X1\nLC X2, \"{}\"\nLI X3, 7\nAF \"{}\", X3\nLC X4, \"{}\"\nEXIT\n",
+            value.replace('\n', "\\n"),
+            file,
+            file,
+            file,
+            file
+        );
+        let (processor, _) = run_scripted(&source);
+        std::fs::remove_dir_all(&path).unwrap();
+
+        assert_eq!(text(&processor, 2), value);
+        assert_eq!(text(&processor, 4), format!("{}7", value));
+    }
+
+    #[test]
+    fn invalid_utf8_in_loaded_files_is_replaced_or_an_error() {
+        let path = env::temp_dir().join(format!("lpu-lc-{}.txt", std::process::id()));
+        std::fs::write(&path, b"ok \xFF\xFE done").unwrap();
+        let source = format!("LC X1, \"{}\"\nEXIT\n", path.to_str().unwrap());
+
+        let (processor, _) = run_scripted(&source);
+        assert_eq!(text(&processor, 1), "ok \u{FFFD}\u{FFFD} done");
+
+        let byte_code = crate::Assembler::new(source, "strict.aasm")
+            .assemble()
+            .unwrap();
+        let mut processor = crate::Processor::new(Config {
+            strict_utf8: true,
+            ..Config::new("text", "embedding")
+        });
+        processor.load(&byte_code).unwrap();
+        let error = format!("{:?}", processor.run().unwrap_err());
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(
+            error.contains("contains invalid UTF-8 at byte offset 3"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_in_the_data_section_is_an_error() {
+        let mut byte_code =
+            crate::Assembler::new("LS X1, \"abcd\"\nEXIT\n".to_string(), "data.aasm")
+                .assemble()
+                .unwrap();
+        let at = byte_code
+            .windows(4)
+            .position(|window| window == b"abcd")
+            .unwrap();
+        byte_code[at + 1] = 0xFF;
+
+        let mut processor = crate::Processor::new(Config::new("text", "embedding"));
+        let error = format!("{:?}", processor.load(&byte_code).unwrap_err());
+        assert!(
+            error.contains("invalid UTF-8 at address 17 (byte offset 1 of string at address 16)"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn cat_joins_text_and_numbers_locally() {
+        let source = "LS X1, \"Hello\"\nLS X2, \"world\"\nLI X3, 42\nCAT X4, X1, X2\nCAT X5, X1, X2, \", \"\nCAT X6, X3, X1, \" \"\nEXIT\n";
+        let (processor, prompts) = run_scripted(source);
+
+        assert_eq!(text(&processor, 4), "Helloworld");
+        assert_eq!(text(&processor, 5), "Hello, world");
+        assert_eq!(text(&processor, 6), "42 Hello");
+        assert!(prompts.is_empty());
+
+        let error = run_error("LS X1, \"Hello\"\nCAT X2, X1, X7\nEXIT\n");
+        assert!(
+            error.contains("Register r7 is uninitialised, expected a value to concatenate."),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn text_instructions_handle_unicode() {
+        let source = "LS X1, \"  Straße é😀 \"\nLEN X2, X1\nUPPER X3, X1\nLOWER X4, X3\nTRIM X5, X1\nLEN X6, X5\nEXIT\n";
+        let (processor, prompts) = run_scripted(source);
+
+        assert_eq!(text(&processor, 2), "12");
+        assert_eq!(text(&processor, 3), "  STRASSE É😀 ");
+        assert_eq!(text(&processor, 4), "  strasse é😀 ");
+        assert_eq!(text(&processor, 5), "Straße é😀");
+        assert_eq!(text(&processor, 6), "9");
+        assert!(prompts.is_empty());
+
+        let error = run_error("LI X1, 3\nUPPER X2, X1\nEXIT\n");
+        assert!(
+            error.contains("Register r1 contains 3, expected text."),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn fmt_fills_numbered_placeholders_from_registers() {
+        let source = "LI X3, 87\nLI X4, 80\nFMT X1, \"score: {1} (threshold {2}) {{1}} {1}\", X3, X4\nEXIT\n";
+        let (processor, prompts) = run_scripted(source);
+
+        assert_eq!(text(&processor, 1), "score: 87 (threshold 80) {1} 87");
+        assert!(prompts.is_empty());
+
+        let error = run_error("FMT X1, \"{1}\", X9\nEXIT\n");
+        assert!(
+            error.contains("Register r9 is uninitialised, expected a value for placeholder"),
+            "{}",
+            error
+        );
+
+        for (source, message) in [
+            (
+                "FMT X1, \"{1} {3}\", X2, X3\n",
+                "Invalid placeholder '{3}'. Expected {N}, where N is between 1 and the number of operands (2).",
+            ),
+            (
+                "FMT X1, \"{1}\", X2, X3\n",
+                "Operand 2 (x3) is not used by any placeholder. Add '{2}' to the string or remove it.",
+            ),
+            (
+                "FMT X1, \"{1\", X2\n",
+                "Unterminated placeholder. Expected '}' after '{'.",
+            ),
+        ] {
+            let mut assembler = crate::Assembler::new(source.to_string(), "fmt.aasm");
+            assert!(assembler.assemble().is_err());
+            assert_eq!(assembler.diagnostics()[0].message, message);
+        }
+    }
+
+    #[test]
+    fn x0_reads_as_zero_and_ignores_writes() {
+        let source = "LI X0, 5\nLS X0, \"text\"\nMV X1, X0\nBEQ X0, X0, skip\nLI X2, 1\nskip:\nLI X3, 3\nEXIT\n";
+        let (processor, _) = run_scripted(source);
+
+        assert_eq!(text(&processor, 0), "0");
+        assert_eq!(text(&processor, 1), "0");
+        assert!(matches!(processor.register(2).unwrap(), Value::None));
+        assert_eq!(text(&processor, 3), "3");
+    }
+
+    #[test]
+    fn only_the_context_window_is_sent_upstream() {
+        // Pushes 100 messages, taking turns between the user and the assistant so none are merged.
+        let source = "LI X1, 50\nLS X2, \"question\"\nLS X3, \"answer\"\nloop:\nPSH C1, X2, \"user\"\nPSH C1, X3, \"assistant\"\nSUBI X1, 1\nBGT X1, X0, loop\nLS X4, \"last\"\nINF X5, X4, C1\nCTXLEN X6, C1\nEXIT\n";
+        let byte_code = crate::Assembler::new(source.to_string(), "window.aasm")
+            .assemble()
+            .unwrap();
+
+        let mut backend = crate::ScriptedBackend::new();
+        backend.push_response("ok");
+        let requests = backend.requests();
+
+        let mut config = Config::new("text", "embedding");
+        config.context_max_messages = Some(10);
+
+        let mut processor = crate::Processor::new(config);
+        processor.set_backend(Box::new(backend));
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+
+        let requests = requests.lock().unwrap();
+        let messages = requests[0].messages.iter().map(|message| &message.content);
+        let mut window = ["question", "answer"].repeat(5);
+        window.push("last");
+
+        // The system prompt comes first, then the ten most recent messages.
+        assert_eq!(messages.skip(1).collect::<Vec<_>>(), window);
+        assert_eq!(text(&processor, 6), "10");
+    }
+
+    #[test]
+    fn emb_embeds_a_range_of_registers_with_one_request() {
+        let source = "LS X1, \"a\"\nLS X2, \"b\"\nLS X3, \"c\"\nEMB X5, X1, 3\nEMB X1, X1, 2\nSIM X8, X1, X5\nSIM X9, X2, X7\nEXIT\n";
+        let byte_code = crate::Assembler::new(source.to_string(), "emb.aasm")
+            .assemble()
+            .unwrap();
+
+        let mut backend = crate::ScriptedBackend::new();
+        backend.set_embedding("a", vec![1.0, 0.0]);
+        backend.set_embedding("b", vec![0.0, 1.0]);
+        backend.set_embedding("c", vec![1.0, 0.0]);
+
+        let mut processor = crate::Processor::new(Config::new("text", "embedding"));
+        processor.set_backend(Box::new(backend));
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+
+        // One request for each EMB. The second reads X1 and X2 before it overwrites them.
+        assert_eq!(processor.counters().embeddings(), 2);
+        assert!(matches!(processor.register(6).unwrap(), Value::Vector(v) if v == &[0.0, 1.0]));
+        assert!(matches!(processor.register(2).unwrap(), Value::Vector(v) if v == &[0.0, 1.0]));
+        assert_eq!(text(&processor, 3), "c");
+        assert_eq!(text(&processor, 8), "100");
+        assert_eq!(text(&processor, 9), "0");
+
+        let listing = crate::Processor::disassemble(&byte_code).unwrap();
+        assert!(listing[3].ends_with("emb x5, x1, 3"), "{}", listing[3]);
+        assert!(listing[4].ends_with("emb x1, x1, 2"), "{}", listing[4]);
+
+        for source in ["EMB X1, X2, 0\n", "EMB X30, X1, 4\n"] {
+            assert!(
+                crate::Assembler::new(source.to_string(), "emb.aasm")
+                    .assemble()
+                    .is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn an_embedded_register_is_reused_without_new_requests() {
+        let mut source = "LS X1, \"query\"\nEMB X10, X1\nMV X11, X10\nPLN X11\n".to_string();
+        for document in 0..5 {
+            source.push_str(&format!(
+                "LS X2, \"doc {}\"\nSIM X{}, X11, X2\n",
+                document,
+                20 + document
+            ));
+        }
+        source.push_str("EXIT\n");
+
+        let byte_code = crate::Assembler::new(source, "reuse.aasm")
+            .assemble()
+            .unwrap();
+
+        let mut backend = crate::ScriptedBackend::new();
+        backend.set_embedding("query", vec![1.0, 0.0]);
+        for document in 0..5 {
+            let angle = document as f32 * std::f32::consts::FRAC_PI_8;
+            backend.set_embedding(format!("doc {}", document), vec![angle.cos(), angle.sin()]);
+        }
+        let requests = backend.embedding_requests();
+        let stdout = crate::CapturedOutput::default();
+
+        let mut processor = crate::Processor::new(Config::new("text", "embedding"));
+        processor.set_backend(Box::new(backend));
+        processor.set_output(
+            Box::new(stdout.clone()),
+            Box::new(crate::CapturedOutput::default()),
+        );
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+
+        // The query is embedded once, and each SIM only embeds its document.
+        let inputs = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.input.join(","))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            inputs,
+            ["query", "doc 0", "doc 1", "doc 2", "doc 3", "doc 4"]
+        );
+
+        let scores = (20..25).map(|register| text(&processor, register));
+        assert_eq!(scores.collect::<Vec<_>>(), ["100", "92", "71", "38", "0"]);
+        assert_eq!(stdout.bytes(), b"<vector of 2 dimensions>\n");
+    }
+
+    #[test]
+    fn bsim_branches_at_or_above_the_threshold() {
+        let branch = |threshold: u32| {
+            let source = format!(
+                "LS X1, \"cat\"\nLS X2, \"kitten\"\nLS X3, \"not taken\"\nBSIM X1, X2, {}, similar\nJMP done\nsimilar:\nLS X3, \"taken\"\ndone:\nEXIT\n",
+                threshold
+            );
+            let byte_code = crate::Assembler::new(source, "bsim.aasm")
+                .assemble()
+                .unwrap();
+
+            let mut backend = crate::ScriptedBackend::new();
+            backend.set_embedding("cat", vec![1.0, 0.0]);
+            backend.set_embedding("kitten", vec![1.0, 1.0]);
+
+            let mut processor = crate::Processor::new(Config::new("text", "embedding"));
+            processor.set_backend(Box::new(backend));
+            processor.load(&byte_code).unwrap();
+            processor.run().unwrap();
+
+            // BSIM stores no score, so the registers the program leaves alone stay empty.
+            for register in 4..=32 {
+                assert!(matches!(processor.register(register).unwrap(), Value::None));
+            }
+            text(&processor, 3)
+        };
+
+        // The similarity of the two embeddings is 71.
+        assert_eq!(branch(70), "taken");
+        assert_eq!(branch(71), "taken");
+        assert_eq!(branch(72), "not taken");
+    }
+
+    // Runs two SIMs on a scripted backend with the given embeddings, answering chat requests with
+    // scores. Returns the scores, the prompts, and the number of embeddings requests.
+    fn run_similarity(
+        similarity_backend: SimilarityBackend,
+        embeddings: &[(&str, Vec<f32>)],
+    ) -> (Vec<String>, Vec<String>, usize) {
+        let source = "LS X1, \"cat\"\nLS X2, \"dog\"\nSIM X3, X1, X2\nSIM X4, X2, X1\nEXIT\n";
+        let byte_code = crate::Assembler::new(source.to_string(), "sim.aasm")
+            .assemble()
+            .unwrap();
+
+        let mut backend = crate::ScriptedBackend::new();
+        backend.push_response("40");
+        backend.push_response("Score: 3/4");
+        embeddings
+            .iter()
+            .for_each(|(text, embedding)| backend.set_embedding(*text, embedding.clone()));
+        let (prompts, embedding_requests) = (backend.prompts(), backend.embedding_requests());
+
+        let mut processor = crate::Processor::new(Config {
+            similarity_backend,
+            ..Config::new("text", "embedding")
+        });
+        processor.set_backend(Box::new(backend));
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+
+        let prompts = prompts.lock().unwrap().clone();
+        let embedding_requests = embedding_requests.lock().unwrap().len();
+        (
+            vec![text(&processor, 3), text(&processor, 4)],
+            prompts,
+            embedding_requests,
+        )
+    }
+
+    #[test]
+    fn similarity_falls_back_to_the_text_model_once_per_run() {
+        let embeddings = [("cat", vec![1.0, 0.0]), ("dog", vec![1.0, 1.0])];
+
+        // Embeddings stay the default when the server has them.
+        let (scores, prompts, requests) =
+            run_similarity(SimilarityBackend::Embeddings, &embeddings);
+        assert_eq!(scores, ["71", "71"]);
+        assert!(prompts.is_empty());
+        assert_eq!(requests, 2);
+
+        // A server without an embeddings endpoint is asked once, then the text model scores.
+        let (scores, prompts, requests) = run_similarity(SimilarityBackend::Embeddings, &[]);
+        assert_eq!(scores, ["40", "75"]);
+        assert_eq!(prompts.len(), 2);
+        assert!(prompts[0].contains("cat") && prompts[0].contains("dog"));
+        assert_eq!(requests, 1);
+
+        // Configured to use the text model, the embeddings endpoint is never asked.
+        let (scores, prompts, requests) = run_similarity(SimilarityBackend::Llm, &embeddings);
+        assert_eq!(scores, ["40", "75"]);
+        assert_eq!(prompts.len(), 2);
+        assert_eq!(requests, 0);
+    }
+
+    // Runs an INF and an EVAL under the policy, with the text model answering from responses.
+    // Returns the result, the processor and the number of chat requests sent.
+    fn run_empty_responses(
+        policy: crate::config::EmptyResponsePolicy,
+        responses: &[&str],
+    ) -> (Result<(), Exception>, crate::Processor, usize) {
+        let source = "LS X1, \"question\"\nINF X2, X1, C1\nEVAL X3, X1, C1\nEXIT\n";
+        let byte_code = crate::Assembler::new(source.to_string(), "empty.aasm")
+            .assemble()
+            .unwrap();
+
+        let mut backend = crate::ScriptedBackend::new();
+        responses
+            .iter()
+            .for_each(|response| backend.push_response(*response));
+        let prompts = backend.prompts();
+
+        let mut processor = crate::Processor::new(Config {
+            on_empty_response: policy,
+            ..Config::new("text", "embedding")
+        });
+        processor.set_backend(Box::new(backend));
+        processor.load(&byte_code).unwrap();
+        let result = processor.run();
+
+        let requests = prompts.lock().unwrap().len();
+        (result, processor, requests)
+    }
+
+    #[test]
+    fn empty_responses_follow_the_policy() {
+        use crate::config::EmptyResponsePolicy;
+
+        // The default retries once, so a second empty response is an error.
+        let (result, processor, requests) =
+            run_empty_responses(EmptyResponsePolicy::Retry, &["", "answer", "", ""]);
+        let error = format!("{:?}", result.unwrap_err());
+        assert_eq!(text(&processor, 2), "answer");
+        assert_eq!(requests, 4);
+        assert!(
+            error.contains("EVAL at 16 received an empty response from the text model twice."),
+            "{}",
+            error
+        );
+
+        let (result, _, requests) = run_empty_responses(EmptyResponsePolicy::Error, &[""]);
+        let error = format!("{:?}", result.unwrap_err());
+        assert_eq!(requests, 1);
+        assert!(
+            error.contains("INF at 12 received an empty response from the text model."),
+            "{}",
+            error
+        );
+
+        // Accepted, an empty answer is stored and is never true.
+        let (result, processor, requests) =
+            run_empty_responses(EmptyResponsePolicy::Accept, &["", ""]);
+        result.unwrap();
+        assert_eq!(requests, 2);
+        assert_eq!(text(&processor, 2), "");
+        assert_eq!(text(&processor, 3), "0");
+    }
+
+    #[test]
+    fn exit_statuses_outside_a_byte_are_errors() {
+        for status in [256, 512, -1] {
+            let source = format!("LI X1, {}\nEXIT X1\n", status);
+            let error = run_error(&source);
+            assert!(error.contains("Statuses are 0 to 255"), "{}", error);
+        }
+
+        let (processor, _) = run_scripted("LI X1, 255\nEXIT X1\n");
+        assert_eq!(processor.exit_status(), 255);
+
+        // 256 would reach the process as 0, so it must not assemble.
+        let mut assembler = crate::Assembler::new("EXIT 256\n".to_string(), "exit.aasm");
+        assert!(assembler.assemble().is_err());
+        let diagnostic = &assembler.diagnostics()[0];
+        assert_eq!((diagnostic.column, diagnostic.lexeme.as_str()), (6, "256"));
+
+        assert!(
+            crate::Assembler::new("EXIT 255\n".to_string(), "exit.aasm")
+                .assemble()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn ls_placeholders_are_replaced_escaped_or_an_error() {
+        let source = "LI X1, 3\nLS X2, \"x1\"\nLS X3, \"${x1} of ${X1}, ${x2}!\"\nLS X4, \"$${x1} and ${x1}\"\nEXIT\n";
+        let (processor, _) = run_scripted(source);
+
+        assert_eq!(text(&processor, 3), "3 of 3, x1!");
+        assert_eq!(text(&processor, 4), "${x1} and 3");
+
+        let error = run_error("LI X1, 1\nLS X2, \"Value: ${x5}\"\nEXIT\n");
+        assert!(
+            error.contains(
+                "Register r5 is uninitialised, expected a value for placeholder '${x5}'."
+            ),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn setsys_replaces_the_system_message_before_the_context() {
+        let source = "LS X1, \"question\"\nLS X2, \"answer\"\nPSH C1, X1, \"user\"\nPSH C1, X2, \"assistant\"\nLS X3, \"last\"\nINF X4, X3, C1\nSETSYS \"Strict.\"\nINF X4, X3, C1\nSETSYS \"\"\nINF X4, X3, C1\nLS X5, \"From a register.\"\nSETSYS X5\nINF X4, X3, C1\nEXIT\n";
+        let byte_code = crate::Assembler::new(source.to_string(), "setsys.aasm")
+            .assemble()
+            .unwrap();
+
+        let mut backend = crate::ScriptedBackend::new();
+        (0..4).for_each(|_| backend.push_response("ok"));
+        let requests = backend.requests();
+
+        let mut config = Config::new("text", "embedding");
+        config.system_prompt = "From the run.".to_string();
+
+        let mut processor = crate::Processor::new(config);
+        processor.set_backend(Box::new(backend));
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+
+        let messages = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| {
+                request
+                    .messages
+                    .iter()
+                    .map(|message| format!("{}: {}", message.role, message.content))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let conversation = ["user: question", "assistant: answer", "user: last"];
+        let with_system = |system: &str| {
+            let mut messages = vec![format!("system: {}", system)];
+            messages.extend(conversation.map(String::from));
+            messages
+        };
+
+        assert_eq!(
+            messages,
+            [
+                with_system("From the run."),
+                with_system("Strict."),
+                conversation.map(String::from).to_vec(),
+                with_system("From a register."),
+            ]
+        );
+
+        let error = run_error("LI X1, 1\nSETSYS X1\nEXIT\n");
+        assert!(error.contains("expected text"), "{}", error);
+    }
+
+    #[test]
+    fn chunk_stores_the_chunks_in_data_memory_and_counts_them() {
+        let source = "LS X1, \"One. Two three.\n\nFour five.\"\nCHUNK X2, X1, 16, 100\nLW X3, 100\nLW X4, 101\nCHUNK X5, X1, 100\nLW X6, 0\nLS X7, \"   \"\nCHUNK X8, X7, 4\nEXIT\n";
+        let (processor, _) = run_scripted(source);
+
+        assert_eq!(text(&processor, 2), "2");
+        assert_eq!(text(&processor, 3), "One. Two three.");
+        assert_eq!(text(&processor, 4), "Four five.");
+        assert_eq!(text(&processor, 5), "1");
+        assert_eq!(text(&processor, 6), "One. Two three.\n\nFour five.");
+        assert_eq!(text(&processor, 8), "0");
+
+        // A size of 0 would never make progress.
+        let mut assembler = crate::Assembler::new(
+            "LS X1, \"text\"\nCHUNK X2, X1, 0\n".to_string(),
+            "chunk.aasm",
+        );
+        assert!(assembler.assemble().is_err());
+        assert_eq!(
+            assembler.diagnostics()[0].message,
+            "Chunk size 0 is out of range. Chunks hold at least 1 character."
+        );
+    }
+
+    // Runs source against a scripted backend giving replies in turn. Returns the result of the run,
+    // the processor and the prompts it sent.
+    fn run_replies(
+        source: &str,
+        replies: &[&str],
+    ) -> (Result<(), Exception>, crate::Processor, Vec<String>) {
+        let byte_code = crate::Assembler::new(source.to_string(), "test.aasm")
+            .assemble()
+            .unwrap();
+
+        let mut backend = crate::ScriptedBackend::new();
+        replies
+            .iter()
+            .for_each(|reply| backend.push_response(*reply));
+        let prompts = backend.prompts();
+
+        let mut processor = crate::Processor::new(Config::new("text", "embedding"));
+        processor.set_backend(Box::new(backend));
+        processor.load(&byte_code).unwrap();
+        let result = processor.run();
+
+        let prompts = prompts.lock().unwrap().clone();
+        (result, processor, prompts)
+    }
+
+    #[test]
+    fn cls_stores_the_label_and_its_index_or_fails_after_one_retry() {
+        let source =
+            "LS X1, \"I was charged twice.\"\nCLS X2, X1, \"billing | bug | feature\", X3\nEXIT\n";
+
+        let (result, processor, prompts) = run_replies(source, &["\"Billing.\""]);
+        result.unwrap();
+        assert_eq!(text(&processor, 2), "billing");
+        assert_eq!(text(&processor, 3), "0");
+        assert_eq!(prompts.len(), 1);
+        assert!(
+            prompts[0].contains("I was charged twice."),
+            "{}",
+            prompts[0]
+        );
+
+        let (result, processor, prompts) = run_replies(source, &["It is a bug report.", "bug"]);
+        result.unwrap();
+        assert_eq!(text(&processor, 2), "bug");
+        assert_eq!(text(&processor, 3), "1");
+        assert_eq!(prompts.len(), 2);
+        assert_ne!(prompts[0], prompts[1]);
+
+        let (result, _, prompts) = run_replies(source, &["refund", "refund please"]);
+        let error = format!("{:?}", result.unwrap_err());
+        assert!(
+            error.contains(
+                "The text model replied 'refund please', which is not one of the labels: billing, \
+                 bug, feature."
+            ),
+            "{}",
+            error
+        );
+        assert_eq!(prompts.len(), 2);
+
+        for (labels, message) in [
+            ("billing", "needs at least two labels"),
+            ("billing||bug", "contains an empty label"),
+            ("bug|Bug", "repeats the label 'Bug' at position 2"),
+        ] {
+            let source = format!("LS X1, \"text\"\nCLS X2, X1, \"{}\"\nEXIT\n", labels);
+            let mut assembler = crate::Assembler::new(source, "cls.aasm");
+            assert!(assembler.assemble().is_err());
+            let diagnostic = &assembler.diagnostics()[0].message;
+            assert!(diagnostic.contains(message), "{}", diagnostic);
         }
     }
+
+    #[test]
+    fn score_stores_a_number_or_fails_after_one_retry() {
+        let source = "LS X1, \"A polite reply.\"\nSCORE X2, X1, \"Is it polite?\"\nLI X3, 50\nBGE X2, X3, polite\nEXIT\npolite:\nLI X4, 1\nEXIT\n";
+
+        let (result, processor, prompts) = run_replies(source, &["Score: 85/100"]);
+        result.unwrap();
+        assert_eq!(text(&processor, 2), "85");
+        assert_eq!(text(&processor, 4), "1");
+        assert!(prompts[0].contains("Is it polite?"), "{}", prompts[0]);
+
+        let (result, processor, prompts) = run_replies(source, &["Very polite.", "70"]);
+        result.unwrap();
+        assert_eq!(text(&processor, 2), "70");
+        assert_eq!(prompts.len(), 2);
+
+        let (result, _, prompts) = run_replies(source, &["Very polite.", "Quite polite."]);
+        let error = format!("{:?}", result.unwrap_err());
+        assert!(
+            error.contains(
+                "SCORE expected a score from 0 to 100, but the text model replied 'Quite polite.'."
+            ),
+            "{}",
+            error
+        );
+        assert_eq!(prompts.len(), 2);
+    }
+
+    #[test]
+    fn json_checks_the_reply_and_repairs_it_once() {
+        let source = "LS X1, \"Order 7 for Ada.\"\nJSON X2, X1, \"name, order.id\"\nJGET X3, X2, \"name\"\nJGET X4, X2, \"order.id\"\nEXIT\n";
+        let valid = r#"{"name":"Ada","order":{"id":7}}"#;
+
+        let fenced = format!("```json\n{}\n```", valid);
+        let (result, processor, prompts) = run_replies(source, &[&fenced]);
+        result.unwrap();
+        assert_eq!(text(&processor, 2), valid);
+        assert_eq!(text(&processor, 3), "Ada");
+        assert_eq!(text(&processor, 4), "7");
+        assert_eq!(prompts.len(), 1);
+
+        let (result, processor, prompts) = run_replies(source, &[r#"{"name":"Ada"}"#, valid]);
+        result.unwrap();
+        assert_eq!(text(&processor, 4), "7");
+        assert!(
+            prompts[1].contains(
+                "Your previous output was invalid because it is missing the field 'order.id':\n{\"name\":\"Ada\"}"
+            ),
+            "{}",
+            prompts[1]
+        );
+
+        let (result, _, prompts) = run_replies(source, &["Sure! Here it is.", "[1, 2]"]);
+        let error = format!("{:?}", result.unwrap_err());
+        assert!(
+            error.contains(
+                "JSON output from the text model is invalid because it is not a JSON object: '[1, 2]'."
+            ),
+            "{}",
+            error
+        );
+        assert!(
+            prompts[1].contains("invalid because it is not valid JSON"),
+            "{}",
+            prompts[1]
+        );
+    }
+
+    #[test]
+    fn jget_reads_fields_without_a_request() {
+        let source = r#"LS X1, "{\"items\":[{\"id\":3},{\"id\":-4}],\"price\":1.5,\"note\":null,\"ok\":true}"
+JGET X2, X1, "items.1.id"
+JGET X3, X1, "items.0"
+JGET X4, X1, "price"
+JGET X5, X1, "note"
+JGET X6, X1, "ok"
+EXIT
+"#;
+        let (processor, prompts) = run_scripted(source);
+
+        assert_eq!(text(&processor, 2), "-4");
+        assert_eq!(text(&processor, 3), r#"{"id":3}"#);
+        assert_eq!(text(&processor, 4), "1.5");
+        assert_eq!(text(&processor, 5), "");
+        assert_eq!(text(&processor, 6), "true");
+        assert!(prompts.is_empty());
+
+        let error = run_error("LS X1, \"{}\"\nJGET X2, X1, \"missing\"\nEXIT\n");
+        assert!(
+            error.contains("The JSON in r1 has no field 'missing'."),
+            "{}",
+            error
+        );
+        let error = run_error("LS X1, \"not json\"\nJGET X2, X1, \"a\"\nEXIT\n");
+        assert!(error.contains("r1 does not hold valid JSON."), "{}", error);
+    }
+
+    #[test]
+    fn ask_answers_from_the_text_or_stores_unknown() {
+        let source = "LS X1, \"Who wrote it?\"\nLS X2, \"Ada wrote it.\"\nASK X3, X1, X2\nEXIT\n";
+
+        let (result, processor, prompts) = run_replies(source, &["  Ada.\n"]);
+        result.unwrap();
+        assert_eq!(text(&processor, 3), "Ada.");
+        assert_eq!(
+            prompts,
+            [constants::DEFAULT_ASK_PROMPT
+                .replace("{b}", "Ada wrote it.")
+                .replace("{a}", "Who wrote it?")]
+        );
+
+        let (result, processor, _) = run_replies(source, &["**Unknown.**"]);
+        result.unwrap();
+        assert_eq!(text(&processor, 3), constants::ASK_UNKNOWN);
+
+        // The text is a register, not a context.
+        let mut assembler = crate::Assembler::new("ASK X3, X1, C1\nEXIT\n".to_string(), "ask.aasm");
+        assert!(assembler.assemble().is_err());
+    }
+
+    #[test]
+    fn trl_sends_the_translation_prompt_and_drops_the_source_language() {
+        let source = "LS X1, \"Guten Morgen.\"\nTRL X2, X1, \"French\"\nLS X3, \"Spanish\"\nTRL X4, X1, X3\nEXIT\n";
+        let replies = ["**Source language: German**\nBonjour.", "  Buenos días.\n"];
+
+        let (result, processor, prompts) = run_replies(source, &replies);
+        result.unwrap();
+        assert_eq!(text(&processor, 2), "Bonjour.");
+        assert_eq!(text(&processor, 4), "Buenos días.");
+        let prompt = |language: &str| {
+            constants::DEFAULT_TRANSLATE_PROMPT
+                .replace("{b}", language)
+                .replace("{a}", "Guten Morgen.")
+        };
+        assert_eq!(prompts, [prompt("French"), prompt("Spanish")]);
+
+        assert_eq!(
+            Executor::split_source_language("Source language: German\nBonjour."),
+            (Some("German"), "Bonjour.")
+        );
+        assert_eq!(
+            Executor::split_source_language("Bonjour."),
+            (None, "Bonjour.")
+        );
+
+        let (result, _, prompts) = run_replies(
+            "LS X1, \"text\"\nLS X2, \"  \"\nTRL X3, X1, X2\nEXIT\n",
+            &[],
+        );
+        let error = format!("{:?}", result.unwrap_err());
+        assert!(error.contains("Target language is empty."), "{}", error);
+        assert!(prompts.is_empty());
+
+        let mut assembler =
+            crate::Assembler::new("TRL X2, X1, \"\"\nEXIT\n".to_string(), "trl.aasm");
+        assert!(assembler.assemble().is_err());
+    }
+
+    #[test]
+    fn role_pushes_inserts_and_peeks_build_the_context_sent() {
+        let source = "LS X1, \"Be brief.\"\nLS X2, \"q1\"\nLS X3, \"a1\"\nLS X4, \"q0\"\nLS X5, \"a0\"\nPSHS C1, X1\nPSHU C1, X4\nPSHA C1, X5\nCTXINS C1, 3, X2, \"user\"\nCTXINS C1, 4, X3, \"assistant\"\nCTXPEEK X6, C1\nCTXLEN X7, C1\nLS X8, \"last\"\nINF X9, X8, C1\nEXIT\n";
+        let byte_code = crate::Assembler::new(source.to_string(), "context.aasm")
+            .assemble()
+            .unwrap();
+
+        let mut backend = crate::ScriptedBackend::new();
+        backend.push_response("ok");
+        let requests = backend.requests();
+
+        let mut config = Config::new("text", "embedding");
+        config.system_prompt = "System.".to_string();
+
+        let mut processor = crate::Processor::new(config);
+        processor.set_backend(Box::new(backend));
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+
+        assert_eq!(text(&processor, 6), "a1");
+        assert_eq!(text(&processor, 7), "5");
+
+        let messages = requests.lock().unwrap()[0]
+            .messages
+            .iter()
+            .map(|message| format!("{}: {}", message.role, message.content))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                "system: System.\nBe brief.",
+                "user: q0",
+                "assistant: a0",
+                "user: q1",
+                "assistant: a1",
+                "user: last"
+            ]
+        );
+
+        let error = run_error("LS X1, \"q\"\nPSHU C1, X1\nCTXINS C1, 2, X1, \"user\"\nEXIT\n");
+        assert!(
+            error.contains("Message index 2 is out of range for context register 1"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn ctxsave_and_ctxload_round_trip_a_session() {
+        let directory = env::temp_dir().join(format!("lpu-ctx-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let path = directory.join("sessions/session.json");
+        let path = path.to_str().unwrap();
+        let system = "Line one.\nLine \\\"two\\\", café, 日本語.";
+        let user = "Привет 🙂";
+
+        // The first run finds no session and starts a fresh one. The second loads it.
+        let source = format!(
+            "LI X9, 0\nCTXLOAD C1, \"{path}\", fresh\nLI X9, 1\nPOP X3, C1\nCTXPEEK X4, C1\nEXIT\nfresh:\nLS X1, \"{system}\"\nPSHS C1, X1\nLS X2, \"{user}\"\nPSHU C1, X2\nCTXSAVE \"{path}\", C1\nEXIT\n"
+        );
+        let (processor, _) = run_scripted(&source);
+        assert_eq!(text(&processor, 9), "0");
+        let (processor, _) = run_scripted(&source);
+        assert_eq!(text(&processor, 9), "1");
+        assert_eq!(text(&processor, 3), user);
+        assert_eq!(
+            text(&processor, 4),
+            "Line one.\nLine \"two\", café, 日本語."
+        );
+
+        // Nothing but the saved file is left in its directory.
+        let files = std::fs::read_dir(directory.join("sessions"))
+            .unwrap()
+            .count();
+        assert_eq!(files, 1);
+
+        std::fs::write(path, "not json").unwrap();
+        let error = run_error(&format!("CTXLOAD C1, \"{path}\", fresh\nfresh:\nEXIT\n"));
+        assert!(
+            error.contains(&format!("Failed to parse the context in '{path}'")),
+            "{}",
+            error
+        );
+
+        std::fs::remove_dir_all(&directory).unwrap();
+        let error = run_error(&format!("CTXLOAD C1, \"{path}\"\nEXIT\n"));
+        assert!(
+            error.contains(&format!(
+                "Context file '{path}' does not exist. Give CTXLOAD a label to jump to instead."
+            )),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn requests_over_the_context_token_budget_warn_or_stop() {
+        use crate::config::ContextBudgetPolicy;
+
+        let source =
+            "LS X1, \"A prompt well over five tokens long.\"\nTOK X2, X1\nINF X3, X1, C1\nEXIT\n";
+        let byte_code = crate::Assembler::new(source.to_string(), "budget.aasm")
+            .assemble()
+            .unwrap();
+        let run = |policy: ContextBudgetPolicy| {
+            let mut backend = crate::ScriptedBackend::new();
+            backend.push_response("ok");
+            let prompts = backend.prompts();
+
+            let mut processor = crate::Processor::new(Config {
+                context_token_budget: Some(5),
+                on_context_over_budget: policy,
+                system_prompt: String::new(),
+                ..Config::new("text", "embedding")
+            });
+            processor.set_backend(Box::new(backend));
+            processor.load(&byte_code).unwrap();
+            let result = processor.run();
+
+            let requests = prompts.lock().unwrap().len();
+            (result, processor, requests)
+        };
+
+        let (result, processor, requests) = run(ContextBudgetPolicy::Warn);
+        result.unwrap();
+        assert_eq!(text(&processor, 2), "9");
+        assert_eq!(requests, 1);
+
+        let (result, _, requests) = run(ContextBudgetPolicy::Error);
+        let error = format!("{:?}", result.unwrap_err());
+        assert!(
+            error.contains("INF at 16 sends about 9 tokens, over the CONTEXT_TOKEN_BUDGET of 5."),
+            "{}",
+            error
+        );
+        assert_eq!(requests, 0);
+    }
+
+    #[test]
+    fn composed_output_keeps_program_whitespace_and_model_newlines() {
+        let source = "LS X1, \"  padded \"\nLI X2, 3\nCAT X3, X1, X2\nFMT X4, \"[{1}]\", X1\nLS X5, \"List two items.\"\nINF X6, X5, C1\nPUT X3\nPUT X4\nPLN X0\nPLN X6\nPUT X1\nEXIT\n";
+        let byte_code = crate::Assembler::new(source.to_string(), "compose.aasm")
+            .assemble()
+            .unwrap();
+
+        let mut backend = crate::ScriptedBackend::new();
+        backend.push_response("\n- one\n\n- two  \n");
+        let stdout = crate::CapturedOutput::default();
+
+        let mut processor = crate::Processor::new(Config::new("text", "embedding"));
+        processor.set_backend(Box::new(backend));
+        processor.set_output(
+            Box::new(stdout.clone()),
+            Box::new(crate::CapturedOutput::default()),
+        );
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+
+        // Only the ends of the model response are trimmed.
+        assert_eq!(
+            String::from_utf8(stdout.bytes()).unwrap(),
+            "  padded 3[  padded ]0\n- one\n\n- two\n  padded "
+        );
+    }
 }
@@ -1,12 +1,23 @@
+use std::fmt;
+
+use crate::processor::registers::Value;
+
+#[derive(Debug)]
+pub struct Placeholder {
+    pub offset: usize,
+    pub register: u32,
+}
+
 #[derive(Debug)]
 pub struct LoadStringInstruction {
     pub destination_register: u32,
     pub value: String,
+    pub placeholders: Vec<Placeholder>,
 }
 #[derive(Debug)]
 pub struct LoadImmediateInstruction {
     pub destination_register: u32,
-    pub value: u32,
+    pub value: Value,
 }
 
 #[derive(Debug)]
@@ -15,6 +26,57 @@ pub struct LoadContentInstruction {
     pub path: String,
 }
 
+#[derive(Debug)]
+pub struct LoadArgumentInstruction {
+    pub destination_register: u32,
+    pub name: String,
+}
+
+#[derive(Debug)]
+pub struct LoadPositionalArgumentInstruction {
+    pub destination_register: u32,
+    pub index: i32,
+}
+
+#[derive(Debug)]
+pub struct LoadArgumentCountInstruction {
+    pub destination_register: u32,
+}
+
+#[derive(Debug)]
+pub struct LoadDirectoryInstruction {
+    pub destination_register: u32,
+    pub path: String,
+    pub pattern: String,
+}
+
+#[derive(Debug)]
+pub struct LoadEnvironmentInstruction {
+    pub destination_register: u32,
+    pub name: String,
+    // Loaded when the variable is not set. Without one, an unset variable is an error.
+    pub default: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum Address {
+    Immediate(u32),
+    // The register holding the address.
+    Register(u32),
+}
+
+#[derive(Debug)]
+pub struct StoreWordInstruction {
+    pub source_register: u32,
+    pub address: Address,
+}
+
+#[derive(Debug)]
+pub struct LoadWordInstruction {
+    pub destination_register: u32,
+    pub address: Address,
+}
+
 #[derive(Debug)]
 pub struct MoveInstruction {
     pub destination_register: u32,
@@ -28,6 +90,30 @@ pub struct InferenceInstruction {
     pub context_register: u32,
 }
 
+#[derive(Debug)]
+pub enum TargetLanguage {
+    Text(String),
+    // The register holding the language.
+    Register(u32),
+}
+
+// Writes the text in the source register, translated into the target language, into the
+// destination.
+#[derive(Debug)]
+pub struct TranslateInstruction {
+    pub destination_register: u32,
+    pub source_register: u32,
+    pub language: TargetLanguage,
+}
+
+// Writes the answer to the question found in the grounding text, or UNKNOWN, into the destination.
+#[derive(Debug)]
+pub struct AskInstruction {
+    pub destination_register: u32,
+    pub question_register: u32,
+    pub grounding_register: u32,
+}
+
 #[derive(Debug)]
 pub struct EvalulateInstruction {
     pub destination_register: u32,
@@ -35,6 +121,13 @@ pub struct EvalulateInstruction {
     pub context_register: u32,
 }
 
+#[derive(Debug)]
+pub struct ConcatenateInstruction {
+    pub destination_register: u32,
+    pub source_register_1: u32,
+    pub source_register_2: u32,
+}
+
 #[derive(Debug)]
 pub struct SimilarityInstruction {
     pub destination_register: u32,
@@ -42,6 +135,62 @@ pub struct SimilarityInstruction {
     pub source_register_2: u32,
 }
 
+// Jumps when the similarity of the two sources is at least the threshold.
+#[derive(Debug)]
+pub struct BranchSimilarInstruction {
+    pub source_register_1: u32,
+    pub source_register_2: u32,
+    pub threshold: u32,
+    pub instruction_pointer_jump_index: u32,
+}
+
+// Writes the label the text model picks for the source into the destination, and its index in the
+// '|' separated label list into the index register. Index register 0 means no index is written.
+#[derive(Debug)]
+pub struct ClassifyInstruction {
+    pub destination_register: u32,
+    pub source_register: u32,
+    pub labels: String,
+    pub index_register: u32,
+}
+
+// Writes how well the source meets the criteria, from 0 to 100, as rated by the text model.
+#[derive(Debug)]
+pub struct ScoreInstruction {
+    pub destination_register: u32,
+    pub source_register: u32,
+    pub criteria: String,
+}
+
+// Writes a JSON object with the fields the text model extracts from the source into the
+// destination, as text.
+#[derive(Debug)]
+pub struct JsonInstruction {
+    pub destination_register: u32,
+    pub source_register: u32,
+    pub fields: String,
+}
+
+#[derive(Debug)]
+pub struct EmbedInstruction {
+    pub destination_register: u32,
+    pub source_register: u32,
+    // Registers embedded, counting up from source_register into those from destination_register.
+    pub count: u32,
+}
+
+impl EmbedInstruction {
+    // Each source register with the destination register its vector goes to.
+    pub fn register_pairs(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        (0..self.count).map(|offset| {
+            (
+                self.source_register.saturating_add(offset),
+                self.destination_register.saturating_add(offset),
+            )
+        })
+    }
+}
+
 #[derive(Debug)]
 pub enum BranchType {
     Equal,
@@ -51,6 +200,59 @@ pub enum BranchType {
     Greater,
 }
 
+#[derive(Debug)]
+pub enum TextOperation {
+    Length,
+    Upper,
+    Lower,
+    Trim,
+}
+
+#[derive(Debug)]
+pub enum ArithmeticOperation {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+#[derive(Debug)]
+pub struct TextInstruction {
+    pub operation: TextOperation,
+    pub destination_register: u32,
+    pub source_register: u32,
+}
+
+// Writes about how many tokens the text in the source is into the destination.
+#[derive(Debug)]
+pub struct TokenCountInstruction {
+    pub destination_register: u32,
+    pub source_register: u32,
+}
+
+// Writes the value at the path of the JSON text in the source into the destination.
+#[derive(Debug)]
+pub struct JsonGetInstruction {
+    pub destination_register: u32,
+    pub source_register: u32,
+    pub path: String,
+}
+
+// Splits the source into chunks of at most size characters, stored in data memory from the address
+// on. The destination gets the number of chunks.
+#[derive(Debug)]
+pub struct ChunkInstruction {
+    pub destination_register: u32,
+    pub source_register: u32,
+    pub size: u32,
+    pub address: u32,
+}
+
+#[derive(Debug)]
+pub struct JumpInstruction {
+    pub instruction_pointer_jump_index: u32,
+}
+
 #[derive(Debug)]
 pub struct BranchInstruction {
     pub branch_type: BranchType,
@@ -83,10 +285,103 @@ pub struct MoveContextInstruction {
     pub source_context_register: u32,
 }
 
+// Writes the number of messages in the context register, a first system message included.
 #[derive(Debug)]
-pub struct SubtractImmediateInstruction {
+pub struct ContextLengthInstruction {
+    pub destination_register: u32,
+    pub source_context_register: u32,
+}
+
+// Copies the content of the most recent message of the context register without removing it.
+#[derive(Debug)]
+pub struct ContextPeekInstruction {
+    pub destination_register: u32,
+    pub source_context_register: u32,
+}
+
+// Inserts the source register as a message with the role at the index, counted from the oldest
+// message. An index equal to the number of messages adds it after the most recent one.
+#[derive(Debug)]
+pub struct ContextInsertInstruction {
+    pub destination_context_register: u32,
+    pub index: u32,
     pub source_register: u32,
-    pub value: u32,
+    pub role: String,
+}
+
+// Writes the messages of the context register to a file as JSON, in the format PCX prints.
+#[derive(Debug)]
+pub struct ContextSaveInstruction {
+    pub path: String,
+    pub source_context_register: u32,
+}
+
+// Replaces the context register with the messages saved in a file. A missing file jumps to the
+// label, if there is one, and leaves the register unchanged.
+#[derive(Debug)]
+pub struct ContextLoadInstruction {
+    pub destination_context_register: u32,
+    pub path: String,
+    pub missing_jump_index: Option<u32>,
+}
+
+// Keeps only the count most recent messages of the context register, and a first system message.
+#[derive(Debug)]
+pub struct ContextTrimInstruction {
+    pub destination_context_register: u32,
+    pub count: u32,
+}
+
+#[derive(Debug)]
+pub struct StackPushInstruction {
+    pub source_register: u32,
+}
+
+#[derive(Debug)]
+pub struct StackPopInstruction {
+    pub destination_register: u32,
+}
+
+#[derive(Debug)]
+pub struct StackPeekInstruction {
+    pub destination_register: u32,
+}
+
+#[derive(Debug)]
+pub struct ArithmeticInstruction {
+    pub operation: ArithmeticOperation,
+    pub destination_register: u32,
+    pub source_register_1: u32,
+    pub source_register_2: u32,
+}
+
+// The immediate forms update their register in place.
+#[derive(Debug)]
+pub struct ArithmeticImmediateInstruction {
+    pub operation: ArithmeticOperation,
+    pub source_register: u32,
+    pub value: i32,
+}
+
+#[derive(Debug)]
+pub struct FormatNumberInstruction {
+    pub destination_register: u32,
+    pub source_register: u32,
+    pub spec: String,
+}
+
+// The template syntax ESC escapes: '${' for LS strings, or '{' and '}' for FMT strings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EscapeSyntax {
+    Interpolation,
+    Positional,
+}
+
+#[derive(Debug)]
+pub struct EscapeInstruction {
+    pub destination_register: u32,
+    pub source_register: u32,
+    pub syntax: EscapeSyntax,
 }
 
 #[derive(Debug)]
@@ -99,13 +394,110 @@ pub struct PrintLineInstruction {
     pub source_register: u32,
 }
 
+#[derive(Debug)]
+pub struct PrintErrorInstruction {
+    pub source_register: u32,
+}
+
 #[derive(Debug)]
 pub struct PrintContextInstruction {
     pub source_context_register: u32,
 }
 
 #[derive(Debug)]
-pub struct ExitInstruction;
+pub enum ExitStatus {
+    Immediate(i32),
+    // The register holding the status.
+    Register(u32),
+}
+
+#[derive(Debug)]
+pub struct ExitInstruction {
+    pub status: ExitStatus,
+}
+
+#[derive(Debug)]
+pub struct InputInstruction {
+    pub destination_register: u32,
+    pub prompt: Option<String>,
+}
+
+// AF appends to the file, WF replaces it.
+#[derive(Debug)]
+pub struct WriteFileInstruction {
+    pub path: String,
+    pub source_register: u32,
+    pub append: bool,
+}
+
+#[derive(Debug)]
+pub struct CheckpointInstruction {
+    pub name: String,
+}
+
+// SETEMB sets the embedding model, SETMODEL the text model.
+#[derive(Debug)]
+pub struct SetModelInstruction {
+    pub model: String,
+    pub embedding: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplingParameter {
+    // In hundredths.
+    Temperature,
+    // In hundredths.
+    TopP,
+    MaxTokens,
+}
+
+#[derive(Debug)]
+pub enum SamplingValue {
+    Immediate(i32),
+    // The register holding the value.
+    Register(u32),
+}
+
+#[derive(Debug)]
+pub struct SetSamplingInstruction {
+    pub parameter: SamplingParameter,
+    pub value: SamplingValue,
+}
+
+#[derive(Debug)]
+pub enum SystemPrompt {
+    Text(String),
+    // The register holding the prompt.
+    Register(u32),
+}
+
+// An empty prompt sends no system message.
+#[derive(Debug)]
+pub struct SetSystemPromptInstruction {
+    pub prompt: SystemPrompt,
+}
+
+#[derive(Debug)]
+pub enum VectorKey {
+    Text(String),
+    // The register holding the key.
+    Register(u32),
+}
+
+// Stores the text in the source register under the key, replacing any text stored under it before.
+#[derive(Debug)]
+pub struct VectorPutInstruction {
+    pub key: VectorKey,
+    pub source_register: u32,
+}
+
+// Writes the stored texts most similar to the query, up to count of them, into the destination.
+#[derive(Debug)]
+pub struct VectorQueryInstruction {
+    pub destination_register: u32,
+    pub source_register: u32,
+    pub count: u32,
+}
 
 #[derive(Debug)]
 pub enum Instruction {
@@ -113,24 +505,874 @@ pub enum Instruction {
     LoadString(LoadStringInstruction),
     LoadImmediate(LoadImmediateInstruction),
     LoadContent(LoadContentInstruction),
+    LoadArgument(LoadArgumentInstruction),
+    LoadPositionalArgument(LoadPositionalArgumentInstruction),
+    LoadArgumentCount(LoadArgumentCountInstruction),
+    LoadDirectory(LoadDirectoryInstruction),
+    LoadEnvironment(LoadEnvironmentInstruction),
+    StoreWord(StoreWordInstruction),
+    LoadWord(LoadWordInstruction),
     Move(MoveInstruction),
     // Control flow.
     Branch(BranchInstruction),
+    Jump(JumpInstruction),
     Exit(ExitInstruction),
+    Checkpoint(CheckpointInstruction),
     // I/O.
     Print(PrintInstruction),
     PrintLine(PrintLineInstruction),
+    PrintError(PrintErrorInstruction),
     PrintContext(PrintContextInstruction),
+    Input(InputInstruction),
+    WriteFile(WriteFileInstruction),
     // Generative operations.
     Inference(InferenceInstruction),
+    Ask(AskInstruction),
+    Translate(TranslateInstruction),
     // Guardrails operations.
     Evaluate(EvalulateInstruction),
     Similarity(SimilarityInstruction),
+    Embed(EmbedInstruction),
+    BranchSimilar(BranchSimilarInstruction),
+    Classify(ClassifyInstruction),
+    Score(ScoreInstruction),
+    Json(JsonInstruction),
+    // Model selection.
+    SetModel(SetModelInstruction),
+    // Sampling.
+    SetSampling(SetSamplingInstruction),
+    // System prompt.
+    SetSystemPrompt(SetSystemPromptInstruction),
+    // Vector store.
+    VectorPut(VectorPutInstruction),
+    VectorQuery(VectorQueryInstruction),
+    VectorClear,
     // Context operations.
     ContextPush(ContextPushInstruction),
     ContextPop(ContextPopInstruction),
     ContextDrop(ContextDropInstruction),
     MoveContext(MoveContextInstruction),
+    ContextLength(ContextLengthInstruction),
+    ContextTrim(ContextTrimInstruction),
+    ContextPeek(ContextPeekInstruction),
+    ContextInsert(ContextInsertInstruction),
+    ContextSave(ContextSaveInstruction),
+    ContextLoad(ContextLoadInstruction),
+    // Stack operations.
+    StackPush(StackPushInstruction),
+    StackPop(StackPopInstruction),
+    StackPeek(StackPeekInstruction),
     // Arithmetic operations.
-    SubtractImmediate(SubtractImmediateInstruction),
+    Arithmetic(ArithmeticInstruction),
+    ArithmeticImmediate(ArithmeticImmediateInstruction),
+    // String operations.
+    FormatNumber(FormatNumberInstruction),
+    Escape(EscapeInstruction),
+    Concatenate(ConcatenateInstruction),
+    Text(TextInstruction),
+    TokenCount(TokenCountInstruction),
+    Chunk(ChunkInstruction),
+    JsonGet(JsonGetInstruction),
+}
+
+// Quotes a string the way the assembler reads it back.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Immediate(address) => write!(formatter, "{}", address),
+            Address::Register(register) => write!(formatter, "x{}", register),
+        }
+    }
+}
+
+impl Address {
+    fn register(&self) -> Option<u32> {
+        match self {
+            Address::Immediate(_) => None,
+            Address::Register(register) => Some(*register),
+        }
+    }
+}
+
+impl LoadStringInstruction {
+    // The string as written in source, with '${' escaped and placeholders put back.
+    fn template(&self) -> String {
+        let mut template = String::new();
+        let mut last_offset = 0;
+
+        for placeholder in &self.placeholders {
+            let segment = self
+                .value
+                .get(last_offset..placeholder.offset)
+                .unwrap_or_default();
+            template.push_str(&segment.replace("${", "$${"));
+            template.push_str(&format!("${{x{}}}", placeholder.register));
+            last_offset = placeholder.offset;
+        }
+
+        template.push_str(
+            &self
+                .value
+                .get(last_offset..)
+                .unwrap_or_default()
+                .replace("${", "$${"),
+        );
+        template
+    }
+
+    // 'ls' cannot write a '$' directly before a placeholder, since '$${' escapes a literal '${'.
+    // 'fmt' can, so such strings are shown in its syntax instead.
+    fn needs_format(&self) -> bool {
+        self.placeholders.iter().any(|placeholder| {
+            self.value
+                .get(..placeholder.offset)
+                .is_some_and(|text| text.ends_with('$'))
+        })
+    }
+
+    // The string in 'fmt' syntax, with braces escaped, and the registers its '{N}' placeholders refer to.
+    fn format_template(&self) -> (String, Vec<u32>) {
+        let mut template = String::new();
+        let mut operands: Vec<u32> = Vec::new();
+        let mut last_offset = 0;
+
+        for placeholder in &self.placeholders {
+            let segment = self
+                .value
+                .get(last_offset..placeholder.offset)
+                .unwrap_or_default();
+            template.push_str(&segment.replace('{', "{{").replace('}', "}}"));
+
+            let index = match operands.iter().position(|r| *r == placeholder.register) {
+                Some(index) => index,
+                None => {
+                    operands.push(placeholder.register);
+                    operands.len() - 1
+                }
+            };
+
+            template.push_str(&format!("{{{}}}", index + 1));
+            last_offset = placeholder.offset;
+        }
+
+        let rest = self.value.get(last_offset..).unwrap_or_default();
+        template.push_str(&rest.replace('{', "{{").replace('}', "}}"));
+        (template, operands)
+    }
+}
+
+// Assembly syntax of the instruction. Label operands are shown as the byte code index they jump to.
+impl fmt::Display for Instruction {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::LoadString(i) if i.needs_format() => {
+                let (template, operands) = i.format_template();
+                write!(
+                    formatter,
+                    "fmt x{}, {}",
+                    i.destination_register,
+                    quote(&template)
+                )?;
+                operands
+                    .iter()
+                    .try_for_each(|register| write!(formatter, ", x{}", register))
+            }
+            Instruction::LoadString(i) => write!(
+                formatter,
+                "ls x{}, {}",
+                i.destination_register,
+                quote(&i.template())
+            ),
+            Instruction::LoadImmediate(i) => {
+                write!(
+                    formatter,
+                    "li x{}, {}",
+                    i.destination_register,
+                    i.value.preview()
+                )
+            }
+            Instruction::LoadContent(i) => {
+                write!(
+                    formatter,
+                    "lc x{}, {}",
+                    i.destination_register,
+                    quote(&i.path)
+                )
+            }
+            Instruction::LoadArgument(i) => {
+                write!(
+                    formatter,
+                    "argn x{}, {}",
+                    i.destination_register,
+                    quote(&i.name)
+                )
+            }
+            Instruction::LoadPositionalArgument(i) => {
+                write!(formatter, "arg x{}, {}", i.destination_register, i.index)
+            }
+            Instruction::LoadArgumentCount(i) => {
+                write!(formatter, "argc x{}", i.destination_register)
+            }
+            Instruction::LoadDirectory(i) => write!(
+                formatter,
+                "ld x{}, {}, {}",
+                i.destination_register,
+                quote(&i.path),
+                quote(&i.pattern)
+            ),
+            Instruction::LoadEnvironment(i) => match &i.default {
+                Some(default) => write!(
+                    formatter,
+                    "le x{}, {}, {}",
+                    i.destination_register,
+                    quote(&i.name),
+                    quote(default)
+                ),
+                None => write!(
+                    formatter,
+                    "le x{}, {}",
+                    i.destination_register,
+                    quote(&i.name)
+                ),
+            },
+            Instruction::StoreWord(i) => {
+                write!(formatter, "sw {}, x{}", i.address, i.source_register)
+            }
+            Instruction::LoadWord(i) => {
+                write!(formatter, "lw x{}, {}", i.destination_register, i.address)
+            }
+            Instruction::Move(i) => {
+                write!(
+                    formatter,
+                    "mv x{}, x{}",
+                    i.destination_register, i.source_register
+                )
+            }
+            Instruction::Branch(i) => write!(
+                formatter,
+                "{} x{}, x{}, 0x{:X}",
+                self.mnemonic(),
+                i.source_register_1,
+                i.source_register_2,
+                i.instruction_pointer_jump_index
+            ),
+            Instruction::Jump(i) => {
+                write!(formatter, "jmp 0x{:X}", i.instruction_pointer_jump_index)
+            }
+            Instruction::Exit(i) => match i.status {
+                ExitStatus::Immediate(0) => write!(formatter, "exit"),
+                ExitStatus::Immediate(status) => write!(formatter, "exit {}", status),
+                ExitStatus::Register(register) => write!(formatter, "exit x{}", register),
+            },
+            Instruction::Checkpoint(i) => write!(formatter, "ckpt {}", quote(&i.name)),
+            Instruction::SetModel(i) => {
+                write!(formatter, "{} {}", self.mnemonic(), quote(&i.model))
+            }
+            Instruction::SetSampling(i) => match i.value {
+                SamplingValue::Immediate(value) => {
+                    write!(formatter, "{} {}", self.mnemonic(), value)
+                }
+                SamplingValue::Register(register) => {
+                    write!(formatter, "{} x{}", self.mnemonic(), register)
+                }
+            },
+            Instruction::SetSystemPrompt(i) => match &i.prompt {
+                SystemPrompt::Text(prompt) => write!(formatter, "setsys {}", quote(prompt)),
+                SystemPrompt::Register(register) => write!(formatter, "setsys x{}", register),
+            },
+            Instruction::VectorPut(i) => match &i.key {
+                VectorKey::Text(key) => {
+                    write!(formatter, "vput {}, x{}", quote(key), i.source_register)
+                }
+                VectorKey::Register(register) => {
+                    write!(formatter, "vput x{}, x{}", register, i.source_register)
+                }
+            },
+            Instruction::VectorQuery(i) => write!(
+                formatter,
+                "vquery x{}, x{}, {}",
+                i.destination_register, i.source_register, i.count
+            ),
+            Instruction::VectorClear => write!(formatter, "vclr"),
+            Instruction::Print(i) => write!(formatter, "put x{}", i.source_register),
+            Instruction::PrintLine(i) => write!(formatter, "pln x{}", i.source_register),
+            Instruction::PrintError(i) => write!(formatter, "err x{}", i.source_register),
+            Instruction::PrintContext(i) => write!(formatter, "pcx c{}", i.source_context_register),
+            Instruction::Input(i) => match &i.prompt {
+                Some(prompt) => write!(
+                    formatter,
+                    "in x{}, {}",
+                    i.destination_register,
+                    quote(prompt)
+                ),
+                None => write!(formatter, "in x{}", i.destination_register),
+            },
+            Instruction::WriteFile(i) => write!(
+                formatter,
+                "{} {}, x{}",
+                self.mnemonic(),
+                quote(&i.path),
+                i.source_register
+            ),
+            Instruction::Inference(i) => write!(
+                formatter,
+                "inf x{}, x{}, c{}",
+                i.destination_register, i.source_register, i.context_register
+            ),
+            Instruction::Ask(i) => write!(
+                formatter,
+                "ask x{}, x{}, x{}",
+                i.destination_register, i.question_register, i.grounding_register
+            ),
+            Instruction::Translate(i) => match &i.language {
+                TargetLanguage::Text(language) => write!(
+                    formatter,
+                    "trl x{}, x{}, {}",
+                    i.destination_register,
+                    i.source_register,
+                    quote(language)
+                ),
+                TargetLanguage::Register(register) => write!(
+                    formatter,
+                    "trl x{}, x{}, x{}",
+                    i.destination_register, i.source_register, register
+                ),
+            },
+            Instruction::Evaluate(i) => write!(
+                formatter,
+                "eval x{}, x{}, c{}",
+                i.destination_register, i.source_register, i.context_register
+            ),
+            Instruction::Similarity(i) => write!(
+                formatter,
+                "sim x{}, x{}, x{}",
+                i.destination_register, i.source_register_1, i.source_register_2
+            ),
+            Instruction::Embed(i) => {
+                write!(
+                    formatter,
+                    "emb x{}, x{}",
+                    i.destination_register, i.source_register
+                )?;
+
+                if i.count > 1 {
+                    write!(formatter, ", {}", i.count)?;
+                }
+
+                Ok(())
+            }
+            Instruction::BranchSimilar(i) => write!(
+                formatter,
+                "bsim x{}, x{}, {}, 0x{:X}",
+                i.source_register_1,
+                i.source_register_2,
+                i.threshold,
+                i.instruction_pointer_jump_index
+            ),
+            Instruction::Classify(i) if i.index_register == 0 => write!(
+                formatter,
+                "cls x{}, x{}, {}",
+                i.destination_register,
+                i.source_register,
+                quote(&i.labels)
+            ),
+            Instruction::Classify(i) => write!(
+                formatter,
+                "cls x{}, x{}, {}, x{}",
+                i.destination_register,
+                i.source_register,
+                quote(&i.labels),
+                i.index_register
+            ),
+            Instruction::Score(i) => write!(
+                formatter,
+                "score x{}, x{}, {}",
+                i.destination_register,
+                i.source_register,
+                quote(&i.criteria)
+            ),
+            Instruction::Json(i) => write!(
+                formatter,
+                "json x{}, x{}, {}",
+                i.destination_register,
+                i.source_register,
+                quote(&i.fields)
+            ),
+            Instruction::ContextPush(i) => write!(
+                formatter,
+                "psh c{}, x{}, {}",
+                i.destination_context_register,
+                i.source_register,
+                quote(&i.role)
+            ),
+            Instruction::ContextPop(i) => write!(
+                formatter,
+                "pop x{}, c{}",
+                i.destination_register, i.source_context_register
+            ),
+            Instruction::ContextDrop(i) => write!(formatter, "drp c{}", i.source_context_register),
+            Instruction::MoveContext(i) => write!(
+                formatter,
+                "mvc c{}, c{}",
+                i.destination_context_register, i.source_context_register
+            ),
+            Instruction::ContextLength(i) => write!(
+                formatter,
+                "ctxlen x{}, c{}",
+                i.destination_register, i.source_context_register
+            ),
+            Instruction::ContextTrim(i) => write!(
+                formatter,
+                "ctxtrim c{}, {}",
+                i.destination_context_register, i.count
+            ),
+            Instruction::ContextPeek(i) => write!(
+                formatter,
+                "ctxpeek x{}, c{}",
+                i.destination_register, i.source_context_register
+            ),
+            Instruction::ContextInsert(i) => write!(
+                formatter,
+                "ctxins c{}, {}, x{}, {}",
+                i.destination_context_register,
+                i.index,
+                i.source_register,
+                quote(&i.role)
+            ),
+            Instruction::ContextSave(i) => write!(
+                formatter,
+                "ctxsave {}, c{}",
+                quote(&i.path),
+                i.source_context_register
+            ),
+            Instruction::ContextLoad(i) => match i.missing_jump_index {
+                Some(index) => write!(
+                    formatter,
+                    "ctxload c{}, {}, 0x{:X}",
+                    i.destination_context_register,
+                    quote(&i.path),
+                    index
+                ),
+                None => write!(
+                    formatter,
+                    "ctxload c{}, {}",
+                    i.destination_context_register,
+                    quote(&i.path)
+                ),
+            },
+            Instruction::StackPush(i) => write!(formatter, "spush x{}", i.source_register),
+            Instruction::StackPop(i) => write!(formatter, "spop x{}", i.destination_register),
+            Instruction::StackPeek(i) => write!(formatter, "speek x{}", i.destination_register),
+            Instruction::Arithmetic(i) => write!(
+                formatter,
+                "{} x{}, x{}, x{}",
+                self.mnemonic(),
+                i.destination_register,
+                i.source_register_1,
+                i.source_register_2
+            ),
+            Instruction::ArithmeticImmediate(i) => {
+                write!(
+                    formatter,
+                    "{} x{}, {}",
+                    self.mnemonic(),
+                    i.source_register,
+                    i.value
+                )
+            }
+            Instruction::FormatNumber(i) => write!(
+                formatter,
+                "fmtn x{}, x{}, {}",
+                i.destination_register,
+                i.source_register,
+                quote(&i.spec)
+            ),
+            Instruction::Escape(i) => match i.syntax {
+                EscapeSyntax::Interpolation => write!(
+                    formatter,
+                    "esc x{}, x{}",
+                    i.destination_register, i.source_register
+                ),
+                EscapeSyntax::Positional => write!(
+                    formatter,
+                    "esc x{}, x{}, \"fmt\"",
+                    i.destination_register, i.source_register
+                ),
+            },
+            Instruction::Concatenate(i) => write!(
+                formatter,
+                "cat x{}, x{}, x{}",
+                i.destination_register, i.source_register_1, i.source_register_2
+            ),
+            Instruction::Text(i) => write!(
+                formatter,
+                "{} x{}, x{}",
+                self.mnemonic(),
+                i.destination_register,
+                i.source_register
+            ),
+            Instruction::TokenCount(i) => write!(
+                formatter,
+                "tok x{}, x{}",
+                i.destination_register, i.source_register
+            ),
+            Instruction::Chunk(i) if i.address == 0 => write!(
+                formatter,
+                "chunk x{}, x{}, {}",
+                i.destination_register, i.source_register, i.size
+            ),
+            Instruction::Chunk(i) => write!(
+                formatter,
+                "chunk x{}, x{}, {}, {}",
+                i.destination_register, i.source_register, i.size, i.address
+            ),
+            Instruction::JsonGet(i) => write!(
+                formatter,
+                "jget x{}, x{}, {}",
+                i.destination_register,
+                i.source_register,
+                quote(&i.path)
+            ),
+        }
+    }
+}
+
+impl Instruction {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::LoadString(_) => "ls",
+            Instruction::LoadImmediate(_) => "li",
+            Instruction::LoadContent(_) => "lc",
+            Instruction::LoadArgument(_) => "argn",
+            Instruction::LoadPositionalArgument(_) => "arg",
+            Instruction::LoadArgumentCount(_) => "argc",
+            Instruction::LoadDirectory(_) => "ld",
+            Instruction::LoadEnvironment(_) => "le",
+            Instruction::StoreWord(_) => "sw",
+            Instruction::LoadWord(_) => "lw",
+            Instruction::Move(_) => "mv",
+            Instruction::Branch(i) => match i.branch_type {
+                BranchType::Equal => "beq",
+                BranchType::LessEqual => "ble",
+                BranchType::Less => "blt",
+                BranchType::GreaterEqual => "bge",
+                BranchType::Greater => "bgt",
+            },
+            Instruction::Jump(_) => "jmp",
+            Instruction::Exit(_) => "exit",
+            Instruction::Checkpoint(_) => "ckpt",
+            Instruction::Print(_) => "put",
+            Instruction::PrintLine(_) => "pln",
+            Instruction::PrintError(_) => "err",
+            Instruction::PrintContext(_) => "pcx",
+            Instruction::Input(_) => "in",
+            Instruction::WriteFile(i) => {
+                if i.append {
+                    "af"
+                } else {
+                    "wf"
+                }
+            }
+            Instruction::Inference(_) => "inf",
+            Instruction::Ask(_) => "ask",
+            Instruction::Translate(_) => "trl",
+            Instruction::Evaluate(_) => "eval",
+            Instruction::Similarity(_) => "sim",
+            Instruction::Embed(_) => "emb",
+            Instruction::BranchSimilar(_) => "bsim",
+            Instruction::Classify(_) => "cls",
+            Instruction::Score(_) => "score",
+            Instruction::Json(_) => "json",
+            Instruction::SetModel(i) => {
+                if i.embedding {
+                    "setemb"
+                } else {
+                    "setmodel"
+                }
+            }
+            Instruction::SetSampling(i) => match i.parameter {
+                SamplingParameter::Temperature => "settemp",
+                SamplingParameter::TopP => "settopp",
+                SamplingParameter::MaxTokens => "setmaxtok",
+            },
+            Instruction::SetSystemPrompt(_) => "setsys",
+            Instruction::VectorPut(_) => "vput",
+            Instruction::VectorQuery(_) => "vquery",
+            Instruction::VectorClear => "vclr",
+            Instruction::ContextPush(_) => "psh",
+            Instruction::ContextPop(_) => "pop",
+            Instruction::ContextDrop(_) => "drp",
+            Instruction::MoveContext(_) => "mvc",
+            Instruction::ContextLength(_) => "ctxlen",
+            Instruction::ContextTrim(_) => "ctxtrim",
+            Instruction::ContextPeek(_) => "ctxpeek",
+            Instruction::ContextInsert(_) => "ctxins",
+            Instruction::ContextSave(_) => "ctxsave",
+            Instruction::ContextLoad(_) => "ctxload",
+            Instruction::StackPush(_) => "spush",
+            Instruction::StackPop(_) => "spop",
+            Instruction::StackPeek(_) => "speek",
+            Instruction::Arithmetic(i) => match i.operation {
+                ArithmeticOperation::Add => "addn",
+                ArithmeticOperation::Subtract => "subn",
+                ArithmeticOperation::Multiply => "muln",
+                ArithmeticOperation::Divide => "divn",
+            },
+            Instruction::ArithmeticImmediate(i) => match i.operation {
+                ArithmeticOperation::Add => "addi",
+                ArithmeticOperation::Subtract => "subi",
+                ArithmeticOperation::Multiply => "muli",
+                ArithmeticOperation::Divide => "divi",
+            },
+            Instruction::FormatNumber(_) => "fmtn",
+            Instruction::Escape(_) => "esc",
+            Instruction::Concatenate(_) => "cat",
+            Instruction::Text(i) => match i.operation {
+                TextOperation::Length => "len",
+                TextOperation::Upper => "upper",
+                TextOperation::Lower => "lower",
+                TextOperation::Trim => "trim",
+            },
+            Instruction::TokenCount(_) => "tok",
+            Instruction::Chunk(_) => "chunk",
+            Instruction::JsonGet(_) => "jget",
+        }
+    }
+
+    // Every register the instruction names, general purpose and context alike, including placeholders.
+    pub fn registers(&self) -> Vec<u32> {
+        match self {
+            Instruction::LoadString(i) => std::iter::once(i.destination_register)
+                .chain(
+                    i.placeholders
+                        .iter()
+                        .map(|placeholder| placeholder.register),
+                )
+                .collect(),
+            Instruction::LoadImmediate(i) => vec![i.destination_register],
+            Instruction::LoadContent(i) => vec![i.destination_register],
+            Instruction::LoadArgument(i) => vec![i.destination_register],
+            Instruction::LoadPositionalArgument(i) => vec![i.destination_register],
+            Instruction::LoadArgumentCount(i) => vec![i.destination_register],
+            Instruction::LoadDirectory(i) => vec![i.destination_register],
+            Instruction::LoadEnvironment(i) => vec![i.destination_register],
+            Instruction::StoreWord(i) => i
+                .address
+                .register()
+                .into_iter()
+                .chain([i.source_register])
+                .collect(),
+            Instruction::LoadWord(i) => std::iter::once(i.destination_register)
+                .chain(i.address.register())
+                .collect(),
+            Instruction::Move(i) => vec![i.destination_register, i.source_register],
+            Instruction::Branch(i) => vec![i.source_register_1, i.source_register_2],
+            Instruction::Exit(i) => match i.status {
+                ExitStatus::Immediate(_) => Vec::new(),
+                ExitStatus::Register(register) => vec![register],
+            },
+            Instruction::Jump(_)
+            | Instruction::Checkpoint(_)
+            | Instruction::SetModel(_)
+            | Instruction::VectorClear => Vec::new(),
+            Instruction::SetSampling(i) => match i.value {
+                SamplingValue::Immediate(_) => Vec::new(),
+                SamplingValue::Register(register) => vec![register],
+            },
+            Instruction::SetSystemPrompt(i) => match i.prompt {
+                SystemPrompt::Text(_) => Vec::new(),
+                SystemPrompt::Register(register) => vec![register],
+            },
+            Instruction::VectorPut(i) => match i.key {
+                VectorKey::Text(_) => vec![i.source_register],
+                VectorKey::Register(register) => vec![register, i.source_register],
+            },
+            Instruction::VectorQuery(i) => vec![i.destination_register, i.source_register],
+            Instruction::Print(i) => vec![i.source_register],
+            Instruction::PrintLine(i) => vec![i.source_register],
+            Instruction::PrintError(i) => vec![i.source_register],
+            Instruction::PrintContext(i) => vec![i.source_context_register],
+            Instruction::Input(i) => vec![i.destination_register],
+            Instruction::WriteFile(i) => vec![i.source_register],
+            Instruction::Inference(i) => {
+                vec![
+                    i.destination_register,
+                    i.source_register,
+                    i.context_register,
+                ]
+            }
+            Instruction::Ask(i) => vec![
+                i.destination_register,
+                i.question_register,
+                i.grounding_register,
+            ],
+            Instruction::Translate(i) => match i.language {
+                TargetLanguage::Text(_) => vec![i.destination_register, i.source_register],
+                TargetLanguage::Register(register) => {
+                    vec![i.destination_register, i.source_register, register]
+                }
+            },
+            Instruction::Evaluate(i) => {
+                vec![
+                    i.destination_register,
+                    i.source_register,
+                    i.context_register,
+                ]
+            }
+            Instruction::Similarity(i) => {
+                vec![
+                    i.destination_register,
+                    i.source_register_1,
+                    i.source_register_2,
+                ]
+            }
+            Instruction::Embed(i) => i
+                .register_pairs()
+                .flat_map(|(source, destination)| [destination, source])
+                .collect(),
+            Instruction::BranchSimilar(i) => vec![i.source_register_1, i.source_register_2],
+            Instruction::Classify(i) => [i.destination_register, i.source_register]
+                .into_iter()
+                .chain((i.index_register != 0).then_some(i.index_register))
+                .collect(),
+            Instruction::Score(i) => vec![i.destination_register, i.source_register],
+            Instruction::Json(i) => vec![i.destination_register, i.source_register],
+            Instruction::ContextPush(i) => vec![i.destination_context_register, i.source_register],
+            Instruction::ContextPop(i) => vec![i.destination_register, i.source_context_register],
+            Instruction::ContextDrop(i) => vec![i.source_context_register],
+            Instruction::MoveContext(i) => {
+                vec![i.destination_context_register, i.source_context_register]
+            }
+            Instruction::ContextLength(i) => {
+                vec![i.destination_register, i.source_context_register]
+            }
+            Instruction::ContextTrim(i) => vec![i.destination_context_register],
+            Instruction::ContextPeek(i) => vec![i.destination_register, i.source_context_register],
+            Instruction::ContextInsert(i) => {
+                vec![i.destination_context_register, i.source_register]
+            }
+            Instruction::ContextSave(i) => vec![i.source_context_register],
+            Instruction::ContextLoad(i) => vec![i.destination_context_register],
+            Instruction::StackPush(i) => vec![i.source_register],
+            Instruction::StackPop(i) => vec![i.destination_register],
+            Instruction::StackPeek(i) => vec![i.destination_register],
+            Instruction::Arithmetic(i) => {
+                vec![
+                    i.destination_register,
+                    i.source_register_1,
+                    i.source_register_2,
+                ]
+            }
+            Instruction::ArithmeticImmediate(i) => vec![i.source_register],
+            Instruction::FormatNumber(i) => vec![i.destination_register, i.source_register],
+            Instruction::Escape(i) => vec![i.destination_register, i.source_register],
+            Instruction::Concatenate(i) => {
+                vec![
+                    i.destination_register,
+                    i.source_register_1,
+                    i.source_register_2,
+                ]
+            }
+            Instruction::Text(i) => vec![i.destination_register, i.source_register],
+            Instruction::TokenCount(i) => vec![i.destination_register, i.source_register],
+            Instruction::Chunk(i) => vec![i.destination_register, i.source_register],
+            Instruction::JsonGet(i) => vec![i.destination_register, i.source_register],
+        }
+    }
+
+    // The general purpose register the instruction writes, if any.
+    pub fn destination_register(&self) -> Option<u32> {
+        match self {
+            Instruction::LoadString(i) => Some(i.destination_register),
+            Instruction::LoadImmediate(i) => Some(i.destination_register),
+            Instruction::LoadContent(i) => Some(i.destination_register),
+            Instruction::LoadArgument(i) => Some(i.destination_register),
+            Instruction::LoadPositionalArgument(i) => Some(i.destination_register),
+            Instruction::LoadArgumentCount(i) => Some(i.destination_register),
+            Instruction::LoadDirectory(i) => Some(i.destination_register),
+            Instruction::LoadEnvironment(i) => Some(i.destination_register),
+            Instruction::LoadWord(i) => Some(i.destination_register),
+            Instruction::Move(i) => Some(i.destination_register),
+            Instruction::Inference(i) => Some(i.destination_register),
+            Instruction::Ask(i) => Some(i.destination_register),
+            Instruction::Translate(i) => Some(i.destination_register),
+            Instruction::Evaluate(i) => Some(i.destination_register),
+            Instruction::Similarity(i) => Some(i.destination_register),
+            Instruction::Embed(i) => Some(i.destination_register),
+            Instruction::Classify(i) => Some(i.destination_register),
+            Instruction::Score(i) => Some(i.destination_register),
+            Instruction::Json(i) => Some(i.destination_register),
+            Instruction::VectorQuery(i) => Some(i.destination_register),
+            Instruction::ContextPop(i) => Some(i.destination_register),
+            Instruction::ContextLength(i) => Some(i.destination_register),
+            Instruction::ContextPeek(i) => Some(i.destination_register),
+            Instruction::Input(i) => Some(i.destination_register),
+            Instruction::StackPop(i) => Some(i.destination_register),
+            Instruction::StackPeek(i) => Some(i.destination_register),
+            Instruction::Arithmetic(i) => Some(i.destination_register),
+            Instruction::ArithmeticImmediate(i) => Some(i.source_register),
+            Instruction::FormatNumber(i) => Some(i.destination_register),
+            Instruction::Escape(i) => Some(i.destination_register),
+            Instruction::Concatenate(i) => Some(i.destination_register),
+            Instruction::Text(i) => Some(i.destination_register),
+            Instruction::TokenCount(i) => Some(i.destination_register),
+            Instruction::Chunk(i) => Some(i.destination_register),
+            Instruction::JsonGet(i) => Some(i.destination_register),
+            _ => None,
+        }
+    }
+
+    // Every string operand, as stored in the data segment.
+    pub fn strings(&self) -> Vec<&str> {
+        match self {
+            Instruction::LoadString(i) => vec![&i.value],
+            Instruction::LoadContent(i) => vec![&i.path],
+            Instruction::WriteFile(i) => vec![&i.path],
+            Instruction::LoadArgument(i) => vec![&i.name],
+            Instruction::LoadDirectory(i) => vec![&i.path, &i.pattern],
+            Instruction::LoadEnvironment(i) => std::iter::once(i.name.as_str())
+                .chain(i.default.as_deref())
+                .collect(),
+            Instruction::Checkpoint(i) => vec![&i.name],
+            Instruction::SetModel(i) => vec![&i.model],
+            Instruction::SetSystemPrompt(i) => match &i.prompt {
+                SystemPrompt::Text(prompt) => vec![prompt],
+                SystemPrompt::Register(_) => Vec::new(),
+            },
+            Instruction::VectorPut(i) => match &i.key {
+                VectorKey::Text(key) => vec![key],
+                VectorKey::Register(_) => Vec::new(),
+            },
+            Instruction::Translate(i) => match &i.language {
+                TargetLanguage::Text(language) => vec![language],
+                TargetLanguage::Register(_) => Vec::new(),
+            },
+            Instruction::Input(i) => i.prompt.iter().map(String::as_str).collect(),
+            Instruction::ContextPush(i) => vec![&i.role],
+            Instruction::ContextInsert(i) => vec![&i.role],
+            Instruction::ContextSave(i) => vec![&i.path],
+            Instruction::ContextLoad(i) => vec![&i.path],
+            Instruction::FormatNumber(i) => vec![&i.spec],
+            Instruction::Classify(i) => vec![&i.labels],
+            Instruction::Score(i) => vec![&i.criteria],
+            Instruction::Json(i) => vec![&i.fields],
+            Instruction::JsonGet(i) => vec![&i.path],
+            _ => Vec::new(),
+        }
+    }
+
+    // The byte code index a branch or jump continues at.
+    pub fn jump_target(&self) -> Option<u32> {
+        match self {
+            Instruction::Branch(i) => Some(i.instruction_pointer_jump_index),
+            Instruction::Jump(i) => Some(i.instruction_pointer_jump_index),
+            Instruction::BranchSimilar(i) => Some(i.instruction_pointer_jump_index),
+            Instruction::ContextLoad(i) => i.missing_jump_index,
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,331 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    assembler::roles,
+    exception::{BaseException, Exception},
+    processor::control_unit::language_logic_unit::{
+        ollama::OllamaClient,
+        openai::{
+            OpenAIClient,
+            chat_completion_models::{
+                OpenAIChatCompletionPayload, OpenAIChatCompletionRequest,
+                OpenAIChatCompletionResponse, OpenAIChatCompletionResponseChoice,
+                OpenAIChatCompletionResponseMessage,
+            },
+            embeddings_models::{
+                OpenAIEmbeddingsRequest, OpenAIEmbeddingsResponse,
+                OpenAIEmbeddingsResponseEmbedding,
+            },
+            tokenize_models::{OpenAITokenizeRequest, OpenAITokenizeResponse},
+        },
+    },
+};
+
+// Where the language logic unit sends its chat and embedding requests. Requests and responses are
+// those of the OpenAI API. The processor uses the client for LLM_PROVIDER unless another backend
+// is set.
+pub trait LlmBackend: Send {
+    fn chat_completion(
+        &mut self,
+        request: &OpenAIChatCompletionPayload,
+    ) -> Result<OpenAIChatCompletionResponse, Exception>;
+
+    // Returns None when the backend has no embeddings endpoint.
+    fn embeddings(
+        &mut self,
+        request: &OpenAIEmbeddingsRequest,
+    ) -> Result<Option<OpenAIEmbeddingsResponse>, Exception>;
+
+    // Returns None when the backend cannot count tokens, so they are estimated instead. Only
+    // llama.cpp servers can, so other backends need not implement it.
+    fn tokenize(
+        &mut self,
+        _request: &OpenAITokenizeRequest,
+    ) -> Result<Option<OpenAITokenizeResponse>, Exception> {
+        Ok(None)
+    }
+
+    // Time requests have spent waiting for MAX_CONCURRENT_REQUESTS to allow them, over the backend's
+    // life. Backends without a server send nothing to wait for.
+    fn request_wait(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    // Checks that the server answers and has the models before a program runs. A model is None
+    // when the program sends it no requests. Backends without a server have nothing to check.
+    fn health_check(
+        &mut self,
+        _text_model: Option<&str>,
+        _embedding_model: Option<&str>,
+    ) -> Result<(), Exception> {
+        Ok(())
+    }
+}
+
+impl LlmBackend for OpenAIClient {
+    fn chat_completion(
+        &mut self,
+        request: &OpenAIChatCompletionPayload,
+    ) -> Result<OpenAIChatCompletionResponse, Exception> {
+        OpenAIClient::chat_completion(self, request)
+    }
+
+    fn embeddings(
+        &mut self,
+        request: &OpenAIEmbeddingsRequest,
+    ) -> Result<Option<OpenAIEmbeddingsResponse>, Exception> {
+        OpenAIClient::embeddings(self, request)
+    }
+
+    fn tokenize(
+        &mut self,
+        request: &OpenAITokenizeRequest,
+    ) -> Result<Option<OpenAITokenizeResponse>, Exception> {
+        OpenAIClient::tokenize(self, request)
+    }
+
+    fn request_wait(&self) -> Duration {
+        OpenAIClient::request_wait(self)
+    }
+
+    fn health_check(
+        &mut self,
+        text_model: Option<&str>,
+        embedding_model: Option<&str>,
+    ) -> Result<(), Exception> {
+        OpenAIClient::health_check(self, text_model, embedding_model)
+    }
+}
+
+impl LlmBackend for OllamaClient {
+    fn chat_completion(
+        &mut self,
+        request: &OpenAIChatCompletionPayload,
+    ) -> Result<OpenAIChatCompletionResponse, Exception> {
+        OllamaClient::chat_completion(self, request)
+    }
+
+    fn embeddings(
+        &mut self,
+        request: &OpenAIEmbeddingsRequest,
+    ) -> Result<Option<OpenAIEmbeddingsResponse>, Exception> {
+        OllamaClient::embeddings(self, request)
+    }
+
+    fn request_wait(&self) -> Duration {
+        OllamaClient::request_wait(self)
+    }
+
+    fn health_check(
+        &mut self,
+        text_model: Option<&str>,
+        embedding_model: Option<&str>,
+    ) -> Result<(), Exception> {
+        OllamaClient::health_check(self, text_model, embedding_model)
+    }
+}
+
+// Answers from a script instead of a model server, so programs can be run without one. Chat
+// requests take the queued responses in order. Embeddings are looked up by input text, and a
+// script with none acts like a server without an embeddings endpoint.
+#[derive(Debug, Default)]
+pub struct ScriptedBackend {
+    responses: VecDeque<String>,
+    embeddings: HashMap<String, Vec<f32>>,
+    prompts: Arc<Mutex<Vec<String>>>,
+    requests: Arc<Mutex<Vec<OpenAIChatCompletionRequest>>>,
+    embedding_requests: Arc<Mutex<Vec<OpenAIEmbeddingsRequest>>>,
+}
+
+impl ScriptedBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_response(&mut self, response: impl Into<String>) {
+        self.responses.push_back(response.into());
+    }
+
+    pub fn set_embedding(&mut self, input: impl Into<String>, embedding: Vec<f32>) {
+        self.embeddings.insert(input.into(), embedding);
+    }
+
+    // The last message of every chat request answered, in order. The list is shared, so it can still
+    // be read once the backend has been given to a processor.
+    pub fn prompts(&self) -> Arc<Mutex<Vec<String>>> {
+        Arc::clone(&self.prompts)
+    }
+
+    // Every chat request answered and every embeddings request received, in order, shared like the
+    // prompts.
+    pub fn requests(&self) -> Arc<Mutex<Vec<OpenAIChatCompletionRequest>>> {
+        Arc::clone(&self.requests)
+    }
+
+    pub fn embedding_requests(&self) -> Arc<Mutex<Vec<OpenAIEmbeddingsRequest>>> {
+        Arc::clone(&self.embedding_requests)
+    }
+}
+
+impl LlmBackend for ScriptedBackend {
+    fn chat_completion(
+        &mut self,
+        request: &OpenAIChatCompletionPayload,
+    ) -> Result<OpenAIChatCompletionResponse, Exception> {
+        if let Some(message) = request.request.messages.last() {
+            self.prompts
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .push(message.content.clone());
+        }
+
+        self.requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(request.request.clone());
+
+        let content = self.responses.pop_front().ok_or_else(|| {
+            let last = request.request.messages.last();
+            let prompt = last.map(|message| message.content.as_str());
+
+            Exception::OpenAIChatCompletion(BaseException::new(
+                format!(
+                    "The script has no response left for the chat request ending with '{}'.",
+                    prompt.unwrap_or_default()
+                ),
+                None,
+            ))
+        })?;
+
+        Ok(OpenAIChatCompletionResponse {
+            model: request.request.model.clone(),
+            choices: vec![OpenAIChatCompletionResponseChoice {
+                index: 0,
+                message: OpenAIChatCompletionResponseMessage {
+                    role: roles::ASSISTANT_ROLE.to_string(),
+                    content,
+                },
+                finish_reason: Some("stop".to_string()),
+            }],
+            timings: None,
+            usage: None,
+        })
+    }
+
+    fn embeddings(
+        &mut self,
+        request: &OpenAIEmbeddingsRequest,
+    ) -> Result<Option<OpenAIEmbeddingsResponse>, Exception> {
+        self.embedding_requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(request.clone());
+
+        if self.embeddings.is_empty() {
+            return Ok(None);
+        }
+
+        let data = request
+            .input
+            .iter()
+            .enumerate()
+            .map(|(index, input)| {
+                let embedding = self.embeddings.get(input).ok_or_else(|| {
+                    Exception::OpenAIEmbeddings(BaseException::new(
+                        format!("The script has no embedding for '{}'.", input),
+                        None,
+                    ))
+                })?;
+
+                Ok(OpenAIEmbeddingsResponseEmbedding {
+                    object: "embedding".to_string(),
+                    embedding: embedding.clone(),
+                    index,
+                })
+            })
+            .collect::<Result<Vec<_>, Exception>>()?;
+
+        Ok(Some(OpenAIEmbeddingsResponse {
+            object: "list".to_string(),
+            data,
+            usage: None,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::{Assembler, Config, Processor};
+
+    // Runs a program sending a chat and an embeddings request against a script, with the model
+    // server set to a port nothing listens on.
+    fn run_offline(backend: ScriptedBackend) -> (Result<(), Exception>, Processor) {
+        let byte_code = Assembler::new(
+            "LS X1, \"Is the sky blue?\"\nINF X2, X1, C1\nLS X4, \"The sky is blue.\"\nSIM X5, X1, X4\nEXIT\n"
+                .to_string(),
+            "offline.aasm",
+        )
+        .assemble()
+        .unwrap();
+        let closed = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+
+        let mut config = Config::new("text", "embedding");
+        config.llm_base_url = closed;
+
+        let mut processor = Processor::new(config);
+        processor.set_backend(Box::new(backend));
+        processor.load(&byte_code).unwrap();
+        (processor.run(), processor)
+    }
+
+    #[test]
+    fn programs_run_end_to_end_against_a_script() {
+        let mut backend = ScriptedBackend::new();
+        backend.push_response("Yes, it is.");
+        backend.set_embedding("Is the sky blue?", vec![1.0, 0.0]);
+        backend.set_embedding("The sky is blue.", vec![1.0, 0.0]);
+        let (requests, embedding_requests) = (backend.requests(), backend.embedding_requests());
+
+        let (result, processor) = run_offline(backend);
+        result.unwrap();
+        assert_eq!(processor.register(2).unwrap().to_string(), "Yes, it is.");
+        assert_eq!(processor.register(5).unwrap().to_string(), "100");
+        assert_eq!(requests.lock().unwrap().len(), 1);
+        assert_eq!(embedding_requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn a_script_that_runs_out_names_the_request() {
+        let (result, _) = run_offline(ScriptedBackend::new());
+        let error = format!("{:?}", result.unwrap_err());
+        assert!(
+            error.contains(
+                "The script has no response left for the chat request ending with 'Is the sky blue?'."
+            ),
+            "{}",
+            error
+        );
+
+        let mut backend = ScriptedBackend::new();
+        backend.push_response("Yes, it is.");
+        backend.set_embedding("Is the sky blue?", vec![1.0, 0.0]);
+
+        let (result, _) = run_offline(backend);
+        let error = format!("{:?}", result.unwrap_err());
+        assert!(
+            error.contains("The script has no embedding for 'The sky is blue.'."),
+            "{}",
+            error
+        );
+    }
+}
@@ -1,24 +1,74 @@
+use std::{
+    borrow::Cow,
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use miniserde::{Deserialize, Serialize, json};
+
 use crate::{
-    assembler::roles,
-    config::TextModelOverrides,
+    assembler::{labels, roles},
+    config::{
+        Config, ContextBudgetPolicy, EmptyResponsePolicy, LlmProvider, PromptTemplates,
+        SimilarityBackend, TextModelOverrides, TruncatedResponsePolicy,
+    },
+    constants,
     exception::{BaseException, Exception},
     processor::{
         control_unit::language_logic_unit::openai::{
             OpenAIClient,
             chat_completion_models::{
-                OpenAIChatCompletionRequest, OpenAIChatCompletionRequestText,
+                OpenAIChatCompletionPayload, OpenAIChatCompletionRequestText,
+                OpenAIChatCompletionResponseTimings, OpenAIResponseUsage,
             },
             embeddings_models::OpenAIEmbeddingsRequest,
             model_config::{ModelEmbeddingsConfig, ModelTextConfig},
+            tokenize_models::OpenAITokenizeRequest,
+        },
+        control_unit::language_logic_unit::{
+            response_cache::ResponseCache,
+            vector_store::{VectorStore, VectorStoreState},
         },
         registers::ContextMessage,
+        stats::{RunCounters, Stats, TokenUsage},
     },
 };
 
-mod openai;
+pub use backend::{LlmBackend, ScriptedBackend};
+
+mod backend;
+mod ollama;
+pub mod openai;
+mod response_cache;
+mod vector_store;
 
-const SYSTEM_PROMPT: &str =
-    "Provide exactly the requested output. Follow structural markers strictly.";
+// finish_reason of a response that stopped at the token limit.
+const FINISH_REASON_LENGTH: &str = "length";
+
+// Tags reasoning models wrap their chain of thought in.
+const THINK_OPEN_TAG: &str = "<think>";
+const THINK_CLOSE_TAG: &str = "</think>";
+
+// A SIM operand. Text is embedded each time it is compared, and a vector made by EMB is used as is.
+#[derive(Clone, Copy)]
+pub enum SimilarityOperand<'a> {
+    Text(&'a str),
+    Vector(&'a [f32]),
+}
+
+// What SETMODEL, SETEMB, SETTEMP, SETTOPP, SETMAXTOK, SETSYS, VPUT and VCLR leave behind, saved in a
+// checkpoint so a resumed run carries on with the models, sampling and store it stopped with.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LanguageLogicState {
+    pub text_model: String,
+    pub embedding_model: String,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<i32>,
+    pub system_prompt: String,
+    pub vector_store: VectorStoreState,
+}
 
 pub struct BooleanEvalParams<'a> {
     pub true_values: &'a [&'a str],
@@ -26,9 +76,426 @@ pub struct BooleanEvalParams<'a> {
     pub embedding_model: &'a str,
 }
 
-pub struct LanguageLogicUnit;
+// Server-side timings summed over one or more chat requests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChatTimings {
+    pub prompt_tokens: u64,
+    pub prompt_ms: f64,
+    pub predicted_tokens: u64,
+    pub predicted_ms: f64,
+}
+
+impl ChatTimings {
+    fn from_response(timings: &OpenAIChatCompletionResponseTimings) -> Self {
+        // Servers that only report a rate still let the time be worked out from the token count.
+        let elapsed_ms = |tokens: Option<u64>, ms: Option<f64>, per_second: Option<f64>| {
+            ms.or_else(|| match (tokens, per_second) {
+                (Some(tokens), Some(rate)) if rate > 0.0 => Some(tokens as f64 / rate * 1000.0),
+                _ => None,
+            })
+            .unwrap_or(0.0)
+        };
+
+        ChatTimings {
+            prompt_tokens: timings.prompt_n.unwrap_or(0),
+            prompt_ms: elapsed_ms(
+                timings.prompt_n,
+                timings.prompt_ms,
+                timings.prompt_per_second,
+            ),
+            predicted_tokens: timings.predicted_n.unwrap_or(0),
+            predicted_ms: elapsed_ms(
+                timings.predicted_n,
+                timings.predicted_ms,
+                timings.predicted_per_second,
+            ),
+        }
+    }
+
+    fn add(&mut self, other: &ChatTimings) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.prompt_ms += other.prompt_ms;
+        self.predicted_tokens += other.predicted_tokens;
+        self.predicted_ms += other.predicted_ms;
+    }
+
+    pub fn tokens_per_second(&self) -> f64 {
+        if self.predicted_ms > 0.0 {
+            self.predicted_tokens as f64 / self.predicted_ms * 1000.0
+        } else {
+            0.0
+        }
+    }
+}
+
+impl fmt::Display for ChatTimings {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "prompt {} tokens in {:.0} ms, predicted {} tokens in {:.0} ms, {:.1} tokens/s",
+            self.prompt_tokens,
+            self.prompt_ms,
+            self.predicted_tokens,
+            self.predicted_ms,
+            self.tokens_per_second()
+        )
+    }
+}
+
+pub struct LanguageLogicUnit {
+    client: Box<dyn LlmBackend>,
+    // Set from config, or recorded from the first embedding of the run when unset. Recorded again
+    // once SETEMB selects another model.
+    embedding_dimensions: Option<usize>,
+    // Switches to Llm for the rest of the run the first time the server turns out to have no embeddings endpoint.
+    similarity_backend: SimilarityBackend,
+    // Models the program has selected, TEXT_MODEL and EMBEDDING_MODEL until SETMODEL or SETEMB
+    // runs. The text model is also used to estimate similarity.
+    text_model: String,
+    embedding_model: String,
+    // Sampling settings from config, as changed since by SETTEMP, SETTOPP and SETMAXTOK.
+    text_model_overrides: TextModelOverrides,
+    // SYSTEM_PROMPT until SETSYS runs. Empty sends no system message.
+    system_prompt: String,
+    // CONTEXT_MAX_MESSAGES and CONTEXT_MAX_CHARS. None is unlimited.
+    context_max_messages: Option<usize>,
+    context_max_chars: Option<usize>,
+    // CONTEXT_TOKEN_BUDGET and ON_CONTEXT_OVER_BUDGET. None is unlimited.
+    context_token_budget: Option<usize>,
+    on_context_over_budget: ContextBudgetPolicy,
+    // Switches off for the rest of the run the first time the server turns out to have no tokenize
+    // endpoint.
+    tokenize_remote: bool,
+    prompts: PromptTemplates,
+    on_empty_response: EmptyResponsePolicy,
+    on_truncated_response: TruncatedResponsePolicy,
+    strip_think_blocks: bool,
+    debug_run: bool,
+    debug_chat: bool,
+    // Opcode and address of the instruction being executed, named in errors about its responses.
+    instruction: (&'static str, usize),
+    // Timings reported by the server since the last instruction took them, and over the whole run.
+    // None until a response carries timings.
+    instruction_timings: Option<ChatTimings>,
+    run_timings: Option<ChatTimings>,
+    // Tokens the server reported using over the whole run. None until a response reports usage.
+    run_usage: Option<TokenUsage>,
+    // Requests this unit has sent, which the trace uses to mark the instructions that sent one.
+    requests: usize,
+    // Chat and embeddings requests sent to the model server, the time spent waiting on them and cache
+    // lookups, shared with the units of processors this one shares with. MAX_LLM_CALLS caps the
+    // requests counted there.
+    counters: Arc<RunCounters>,
+    max_requests: Option<usize>,
+    // Responses reused for identical requests when LLM_CACHE is on, also shared. Empty chat responses
+    // are never cached, so the empty response policy still gets to retry them, and responses cut short
+    // at the token limit are only cached when the truncated response policy accepts them.
+    chat_cache: Option<Arc<ResponseCache<String>>>,
+    embeddings_cache: Option<Arc<ResponseCache<Vec<f32>>>>,
+    vector_store: VectorStore,
+}
 
 impl LanguageLogicUnit {
+    pub fn new(config: &Config) -> Self {
+        LanguageLogicUnit {
+            client: match config.llm_provider {
+                LlmProvider::OpenAI => Box::new(OpenAIClient::new(config)),
+                LlmProvider::Ollama => Box::new(ollama::OllamaClient::new(config)),
+            },
+            embedding_dimensions: config.embedding_dimensions,
+            similarity_backend: config.similarity_backend,
+            text_model: config.text_model.clone(),
+            embedding_model: config.embedding_model.clone(),
+            text_model_overrides: config.text_model_overrides.clone(),
+            system_prompt: config.system_prompt.clone(),
+            context_max_messages: config.context_max_messages,
+            context_max_chars: config.context_max_chars,
+            context_token_budget: config.context_token_budget,
+            on_context_over_budget: config.on_context_over_budget,
+            tokenize_remote: config.tokenize_remote,
+            prompts: config.prompts.clone(),
+            on_empty_response: config.on_empty_response,
+            on_truncated_response: config.on_truncated_response,
+            strip_think_blocks: config.strip_think_blocks,
+            debug_run: config.debug_run,
+            debug_chat: config.debug_chat,
+            instruction: ("", 0),
+            instruction_timings: None,
+            run_timings: None,
+            run_usage: None,
+            requests: 0,
+            counters: Arc::default(),
+            max_requests: config.max_llm_calls,
+            chat_cache: config
+                .llm_cache
+                .then(|| Arc::new(ResponseCache::new(config.llm_cache_size))),
+            embeddings_cache: config
+                .llm_cache
+                .then(|| Arc::new(ResponseCache::new(config.llm_cache_size))),
+            vector_store: VectorStore::new(config.vector_store_file.clone()),
+        }
+    }
+
+    pub fn set_backend(&mut self, backend: Box<dyn LlmBackend>) {
+        self.client = backend;
+    }
+
+    // Counts requests in the other unit's counters from now on, and answers from its caches where
+    // both have LLM_CACHE on.
+    pub fn share_with(&mut self, other: &LanguageLogicUnit) {
+        self.counters = Arc::clone(&other.counters);
+
+        if let (Some(_), Some(cache)) = (&self.chat_cache, &other.chat_cache) {
+            self.chat_cache = Some(Arc::clone(cache));
+        }
+
+        if let (Some(_), Some(cache)) = (&self.embeddings_cache, &other.embeddings_cache) {
+            self.embeddings_cache = Some(Arc::clone(cache));
+        }
+    }
+
+    pub fn counters(&self) -> Arc<RunCounters> {
+        Arc::clone(&self.counters)
+    }
+
+    pub fn text_model(&self) -> &str {
+        &self.text_model
+    }
+
+    pub fn set_text_model(&mut self, model: &str) {
+        self.text_model = model.to_string();
+    }
+
+    pub fn embedding_model(&self) -> &str {
+        &self.embedding_model
+    }
+
+    // EMBEDDING_DIMENSIONS describes EMBEDDING_MODEL, so another model's dimensions are recorded
+    // from its first embedding instead.
+    pub fn set_embedding_model(&mut self, model: &str) {
+        if self.embedding_model != model {
+            self.embedding_model = model.to_string();
+            self.embedding_dimensions = None;
+        }
+    }
+
+    pub fn text_model_overrides(&self) -> &TextModelOverrides {
+        &self.text_model_overrides
+    }
+
+    pub fn set_temperature(&mut self, temperature: f32) {
+        self.text_model_overrides.temperature = Some(temperature);
+    }
+
+    pub fn set_top_p(&mut self, top_p: f32) {
+        self.text_model_overrides.top_p = Some(top_p);
+    }
+
+    pub fn set_max_tokens(&mut self, max_tokens: i32) {
+        self.text_model_overrides.max_tokens = Some(max_tokens);
+    }
+
+    pub fn set_system_prompt(&mut self, prompt: &str) {
+        self.system_prompt = prompt.to_string();
+    }
+
+    // How many of the most recent messages of a context to keep so it fits the context window, not
+    // counting a first system message, which is always kept. The kept messages start with a user
+    // message, as requests must. None when the whole context fits.
+    pub fn context_window(&self, context: &[ContextMessage]) -> Option<usize> {
+        let messages = match context.first() {
+            Some(message) if message.role == roles::SYSTEM_ROLE => &context[1..],
+            _ => context,
+        };
+
+        let mut characters = 0;
+        let mut keep = messages
+            .iter()
+            .rev()
+            .take(self.context_max_messages.unwrap_or(usize::MAX))
+            .take_while(|message| {
+                characters += message.content.chars().count();
+                self.context_max_chars
+                    .is_none_or(|limit| characters <= limit)
+            })
+            .count();
+
+        if keep == messages.len() {
+            return None;
+        }
+
+        while keep > 0 && messages[messages.len() - keep].role != roles::USER_ROLE {
+            keep -= 1;
+        }
+
+        Some(keep)
+    }
+
+    // Counts the tokens of a text with the server's tokenizer when TOKENIZE_REMOTE is on, or
+    // estimates them. Tokenize requests do not count towards MAX_LLM_CALLS.
+    pub fn count_tokens(&mut self, text: &str) -> Result<usize, Exception> {
+        if self.tokenize_remote {
+            if let Some(response) = self.client.tokenize(&OpenAITokenizeRequest::new(text))? {
+                return Ok(response.tokens.len());
+            }
+
+            eprintln!(
+                "Warning: The server has no tokenize endpoint. Token counts are estimated for the rest of the run."
+            );
+            self.tokenize_remote = false;
+        }
+
+        Ok(Self::estimate_tokens(text))
+    }
+
+    // A token for every ESTIMATED_CHARS_PER_TOKEN characters of ASCII text, and one for every other
+    // character. Most tokenizers split text that is not English more finely, so this errs high.
+    pub fn estimate_tokens(text: &str) -> usize {
+        let ascii = text.bytes().filter(u8::is_ascii).count();
+        let other = text.chars().filter(|ch| !ch.is_ascii()).count();
+
+        ascii.div_ceil(constants::ESTIMATED_CHARS_PER_TOKEN) + other
+    }
+
+    // Counts the content of the messages against CONTEXT_TOKEN_BUDGET. The few tokens a chat
+    // template adds around each message are not counted.
+    fn check_context_budget(
+        &mut self,
+        messages: &[OpenAIChatCompletionRequestText],
+    ) -> Result<(), Exception> {
+        let Some(budget) = self.context_token_budget else {
+            return Ok(());
+        };
+
+        let content = messages
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let tokens = self.count_tokens(&content)?;
+
+        if tokens <= budget {
+            return Ok(());
+        }
+
+        let (opcode, address) = self.instruction;
+        let message = format!(
+            "{} at {} sends about {} tokens, over the {} of {}.",
+            opcode,
+            address,
+            tokens,
+            constants::CONTEXT_TOKEN_BUDGET_ENV,
+            budget
+        );
+
+        match self.on_context_over_budget {
+            ContextBudgetPolicy::Warn => {
+                eprintln!("Warning: {}", message);
+                Ok(())
+            }
+            ContextBudgetPolicy::Error => {
+                Err(Exception::LanguageLogic(BaseException::new(message, None)))
+            }
+        }
+    }
+
+    pub fn similarity_backend(&self) -> SimilarityBackend {
+        self.similarity_backend
+    }
+
+    // Checks the model server for the models the program will send requests to.
+    pub fn health_check(&mut self, text: bool, embeddings: bool) -> Result<(), Exception> {
+        self.client.health_check(
+            text.then_some(self.text_model.as_str()),
+            embeddings.then_some(self.embedding_model.as_str()),
+        )
+    }
+
+    pub fn set_instruction(&mut self, opcode: &'static str, address: usize) {
+        self.instruction = (opcode, address);
+    }
+
+    // Timings of the chat requests made since the last call, if the server reported any.
+    pub fn take_instruction_timings(&mut self) -> Option<ChatTimings> {
+        self.instruction_timings.take()
+    }
+
+    pub fn run_timings(&self) -> Option<ChatTimings> {
+        self.run_timings
+    }
+
+    pub fn run_usage(&self) -> Option<TokenUsage> {
+        self.run_usage
+    }
+
+    pub fn requests(&self) -> usize {
+        self.requests
+    }
+
+    // Adds the time since a request started to the model time, less any the client spent waiting for
+    // a permit, which request_wait counts instead.
+    fn add_model_time(&self, start: Instant, wait_before: Duration) {
+        let waited = self.client.request_wait().saturating_sub(wait_before);
+        self.counters
+            .add_model_time(start.elapsed().saturating_sub(waited));
+    }
+
+    // Fills in the model request counts of the run.
+    pub fn add_stats(&self, stats: &mut Stats) {
+        stats.chat_completions = self.counters.chat_completions();
+        stats.embeddings = self.counters.embeddings();
+        stats.model_time = self.counters.model_time();
+        stats.request_wait = self.client.request_wait();
+        stats.token_usage = self.run_usage;
+        stats.seed = self.text_model_overrides.seed;
+        stats.cache_hits = self.counters.cache_hits();
+        stats.cache_misses = self.counters.cache_misses();
+    }
+
+    // Fails instead of sending another request once the run has used up its budget.
+    fn claim_request(&mut self) -> Result<(), Exception> {
+        if let Err(sent) = self.counters.claim_request(self.max_requests) {
+            let (opcode, address) = self.instruction;
+
+            return Err(Exception::LanguageLogic(BaseException::new(
+                format!(
+                    "{} at {} needs a model request, but the run has already sent {} ({} = {}).",
+                    opcode,
+                    address,
+                    sent,
+                    constants::MAX_LLM_CALLS_ENV,
+                    self.max_requests.unwrap_or(sent)
+                ),
+                None,
+            )));
+        }
+
+        self.requests += 1;
+        Ok(())
+    }
+
+    fn record_timings(&mut self, timings: &ChatTimings) {
+        self.instruction_timings
+            .get_or_insert_with(ChatTimings::default)
+            .add(timings);
+        self.run_timings
+            .get_or_insert_with(ChatTimings::default)
+            .add(timings);
+    }
+
+    fn record_usage(&mut self, usage: Option<&OpenAIResponseUsage>) {
+        let Some(usage) = usage else {
+            return;
+        };
+
+        self.run_usage
+            .get_or_insert_with(Default::default)
+            .add(&TokenUsage {
+                prompt_tokens: usage.prompt_tokens.unwrap_or(0),
+                completion_tokens: usage.completion_tokens.unwrap_or(0),
+            });
+    }
+
     fn default_text_model(model: &str, overrides: &TextModelOverrides) -> ModelTextConfig {
         ModelTextConfig {
             stream: overrides.stream.unwrap_or(false),
@@ -48,17 +515,21 @@ impl LanguageLogicUnit {
             xtc_threshold: overrides.xtc_threshold.unwrap_or(0.1),
             typ_p: overrides.typ_p.unwrap_or(1.0),
             max_tokens: overrides.max_tokens.unwrap_or(-1),
-            samplers: vec![
-                "penalties".to_string(),
-                "dry".to_string(),
-                "top_n_sigma".to_string(),
-                "top_k".to_string(),
-                "typ_p".to_string(),
-                "top_p".to_string(),
-                "min_p".to_string(),
-                "xtc".to_string(),
-                "temperature".to_string(),
-            ],
+            samplers: overrides.samplers.clone().unwrap_or_else(|| {
+                [
+                    "penalties",
+                    "dry",
+                    "top_n_sigma",
+                    "top_k",
+                    "typ_p",
+                    "top_p",
+                    "min_p",
+                    "xtc",
+                    "temperature",
+                ]
+                .map(str::to_string)
+                .to_vec()
+            }),
             repeat_last_n: overrides.repeat_last_n.unwrap_or(64),
             repeat_penalty: overrides.repeat_penalty.unwrap_or(1.0),
             presence_penalty: overrides.presence_penalty.unwrap_or(0.0),
@@ -68,6 +539,8 @@ impl LanguageLogicUnit {
             dry_allowed_length: overrides.dry_allowed_length.unwrap_or(2),
             dry_penalty_last_n: overrides.dry_penalty_last_n.unwrap_or(-1),
             timings_per_token: overrides.timings_per_token.unwrap_or(false),
+            seed: overrides.seed,
+            stop: overrides.stop.clone().unwrap_or_default(),
         }
     }
 
@@ -78,8 +551,39 @@ impl LanguageLogicUnit {
         }
     }
 
+    // Only the whitespace around a response is dropped; line breaks inside it are part of the answer.
     fn clean_string(value: &str) -> String {
-        value.trim().replace("\n", "").to_string()
+        value.trim().to_string()
+    }
+
+    // Removes <think>...</think> blocks. Some chat templates open the block in the prompt, so text
+    // before a closing tag with no opening tag is reasoning too, and a block the token limit cut
+    // short runs to the end of the response.
+    fn strip_think_blocks(value: &str) -> Cow<'_, str> {
+        let mut rest = value;
+
+        if let Some(close) = rest.find(THINK_CLOSE_TAG)
+            && rest.find(THINK_OPEN_TAG).is_none_or(|open| close < open)
+        {
+            rest = &rest[close + THINK_CLOSE_TAG.len()..];
+        }
+
+        if !rest.contains(THINK_OPEN_TAG) {
+            return Cow::Borrowed(rest);
+        }
+
+        let mut stripped = String::with_capacity(rest.len());
+
+        while let Some(open) = rest.find(THINK_OPEN_TAG) {
+            stripped.push_str(&rest[..open]);
+            rest = match rest[open..].find(THINK_CLOSE_TAG) {
+                Some(close) => &rest[open + close + THINK_CLOSE_TAG.len()..],
+                None => "",
+            };
+        }
+
+        stripped.push_str(rest);
+        Cow::Owned(stripped)
     }
 
     // Merge consecutive messages with the same role into a single message,
@@ -110,7 +614,7 @@ impl LanguageLogicUnit {
         Ok(merged_messages)
     }
 
-    // Message must always start with system role, and then followed by a user role. Assistant role can only be after a user role, and never at the end.
+    // Message must always start with an optional system role, and then followed by a user role. Assistant role can only be after a user role, and never at the end.
     // This is because the assistant role is meant to provide additional context to the model, and should not be the final message that
     // the model sees before generating a response. By enforcing this structure, we can ensure that the model receives a clear and consistent
     // input format, which can help improve the quality of the generated responses.
@@ -118,22 +622,24 @@ impl LanguageLogicUnit {
         let validation_err =
             |msg: String| Err(Exception::LanguageLogic(BaseException::new(msg, None)));
 
-        if messages.len() < 2 {
-            return validation_err(
-                "Messages must contain at least a system and a user message.".to_string(),
-            );
-        }
+        // An empty system prompt sends no system message, so the conversation starts straight away.
+        let conversation = match messages.first() {
+            Some(message) if message.role == roles::SYSTEM_ROLE => &messages[1..],
+            _ => messages,
+        };
 
-        if messages[0].role != roles::SYSTEM_ROLE {
-            return validation_err("The first message must be a system message.".to_string());
+        if conversation.is_empty() {
+            return validation_err("Messages must contain at least a user message.".to_string());
         }
 
-        if messages[1].role != roles::USER_ROLE {
-            return validation_err("The second message must be a user message.".to_string());
+        if conversation[0].role != roles::USER_ROLE {
+            return validation_err(
+                "The first message after the system message must be a user message.".to_string(),
+            );
         }
 
         let mut expected_role = roles::ASSISTANT_ROLE;
-        for message in messages.iter().skip(2) {
+        for message in conversation.iter().skip(1) {
             if message.role != expected_role {
                 return validation_err(format!(
                     "Unexpected role '{}' in messages, expected '{}'.",
@@ -161,45 +667,196 @@ impl LanguageLogicUnit {
         Ok(())
     }
 
+    // Empty responses are errors, retried once or accepted, depending on the on_empty_response policy.
     fn chat(
+        &mut self,
         content: &str,
         context: &[ContextMessage],
         text_model: &str,
         text_model_overrides: &TextModelOverrides,
         debug_chat: bool,
     ) -> Result<String, Exception> {
-        let model = Self::default_text_model(text_model, text_model_overrides);
-        let messages = std::iter::once(OpenAIChatCompletionRequestText {
+        let system = (!self.system_prompt.is_empty()).then(|| OpenAIChatCompletionRequestText {
             role: roles::SYSTEM_ROLE.to_string(),
-            content: SYSTEM_PROMPT.to_string(),
-        })
-        .chain(
-            context
-                .iter()
-                .map(|message| OpenAIChatCompletionRequestText {
-                    role: message.role.clone(),
-                    content: message.content.clone(),
-                }),
-        )
-        .chain(std::iter::once(OpenAIChatCompletionRequestText {
-            role: roles::USER_ROLE.to_string(),
-            content: content.to_string(),
-        }))
-        .collect::<Vec<OpenAIChatCompletionRequestText>>();
+            content: self.system_prompt.clone(),
+        });
+
+        let messages = system
+            .into_iter()
+            .chain(
+                context
+                    .iter()
+                    .map(|message| OpenAIChatCompletionRequestText {
+                        role: message.role.clone(),
+                        content: message.content.clone(),
+                    }),
+            )
+            .chain(std::iter::once(OpenAIChatCompletionRequestText {
+                role: roles::USER_ROLE.to_string(),
+                content: content.to_string(),
+            }))
+            .collect::<Vec<OpenAIChatCompletionRequestText>>();
 
         let messages = Self::merge_messages_by_role(&messages)?;
         Self::validate_messages(&messages)?;
+        self.check_context_budget(&messages)?;
 
         if debug_chat {
-            println!("--- Chat Messages ---");
+            eprintln!("--- Chat Messages ---");
             for message in &messages {
-                println!("Role: {}, Content: {}", message.role, message.content);
+                eprintln!("Role: {}, Content: {}", message.role, message.content);
+            }
+            eprintln!("---------------------");
+        }
+
+        let content =
+            self.complete_within_limit(&messages, text_model, text_model_overrides, debug_chat)?;
+
+        if !content.is_empty() || self.on_empty_response == EmptyResponsePolicy::Accept {
+            return Ok(content);
+        }
+
+        let (opcode, address) = self.instruction;
+
+        if self.on_empty_response == EmptyResponsePolicy::Retry {
+            if self.debug_run || debug_chat {
+                eprintln!(
+                    "{} at {} received an empty response from the text model. Retrying once.",
+                    opcode, address
+                );
+            }
+
+            let content = self.complete_within_limit(
+                &messages,
+                text_model,
+                text_model_overrides,
+                debug_chat,
+            )?;
+
+            if !content.is_empty() {
+                return Ok(content);
+            }
+        }
+
+        Err(Exception::LanguageLogic(BaseException::new(
+            format!(
+                "{} at {} received an empty response from the text model{}.",
+                opcode,
+                address,
+                match self.on_empty_response {
+                    EmptyResponsePolicy::Retry => " twice",
+                    _ => "",
+                }
+            ),
+            None,
+        )))
+    }
+
+    // Responses cut short at the token limit are accepted, errors or retried once with twice the
+    // limit, depending on the on_truncated_response policy. Without a limit set there is nothing to
+    // raise, so a retry fails straight away.
+    fn complete_within_limit(
+        &mut self,
+        messages: &[OpenAIChatCompletionRequestText],
+        text_model: &str,
+        text_model_overrides: &TextModelOverrides,
+        debug_chat: bool,
+    ) -> Result<String, Exception> {
+        let (content, truncated) =
+            self.complete(messages, text_model, text_model_overrides, debug_chat)?;
+
+        if !truncated || self.on_truncated_response == TruncatedResponsePolicy::Accept {
+            return Ok(content);
+        }
+
+        let (opcode, address) = self.instruction;
+        let max_tokens = text_model_overrides.max_tokens.filter(|&max| max > 0);
+
+        if self.on_truncated_response == TruncatedResponsePolicy::Retry
+            && let Some(max_tokens) = max_tokens
+        {
+            let max_tokens = max_tokens.saturating_mul(2);
+
+            if self.debug_run || debug_chat {
+                eprintln!(
+                    "{} at {} received a response cut short at the token limit. Retrying once with \
+                     a limit of {}.",
+                    opcode, address, max_tokens
+                );
+            }
+
+            let overrides = TextModelOverrides {
+                max_tokens: Some(max_tokens),
+                ..text_model_overrides.clone()
+            };
+            let (content, truncated) =
+                self.complete(messages, text_model, &overrides, debug_chat)?;
+
+            if !truncated {
+                return Ok(content);
             }
-            println!("---------------------");
         }
 
-        let request = OpenAIChatCompletionRequest::new(messages, model);
-        let response = OpenAIClient::chat_completion(request)?;
+        Err(Exception::LanguageLogic(BaseException::new(
+            format!(
+                "{} at {} received a response cut short at the token limit{}. Raise the limit \
+                 with {} or SETMAXTOK.",
+                opcode,
+                address,
+                match (self.on_truncated_response, max_tokens) {
+                    (TruncatedResponsePolicy::Retry, Some(_)) => " twice",
+                    _ => "",
+                },
+                constants::TEXT_MODEL_MAX_TOKENS_ENV
+            ),
+            None,
+        )))
+    }
+
+    // Sends one chat request and returns the cleaned response, and whether it stopped at the token
+    // limit.
+    fn complete(
+        &mut self,
+        messages: &[OpenAIChatCompletionRequestText],
+        text_model: &str,
+        text_model_overrides: &TextModelOverrides,
+        debug_chat: bool,
+    ) -> Result<(String, bool), Exception> {
+        let model = Self::default_text_model(text_model, text_model_overrides);
+        let request = OpenAIChatCompletionPayload::new(messages.to_vec(), model);
+        let cache_key = self.chat_cache.is_some().then(|| json::to_string(&request));
+
+        if let (Some(cache), Some(key)) = (&self.chat_cache, &cache_key) {
+            let cached = cache.get(key);
+            self.counters.add_cache_lookup(cached.is_some());
+
+            if let Some(content) = cached {
+                if debug_chat {
+                    eprintln!("Using the cached response.");
+                }
+
+                return Ok((content, false));
+            }
+        }
+
+        self.claim_request()?;
+
+        let (start, wait) = (Instant::now(), self.client.request_wait());
+        let response = self.client.chat_completion(&request);
+        self.counters.add_chat_completion();
+        self.add_model_time(start, wait);
+        let response = response?;
+        self.record_usage(response.usage.as_ref());
+
+        if let Some(timings) = &response.timings {
+            let timings = ChatTimings::from_response(timings);
+
+            if debug_chat {
+                eprintln!("Timings: {}", timings);
+            }
+
+            self.record_timings(&timings);
+        }
 
         let choice = response.choices.first().ok_or_else(|| {
             Exception::LanguageLogic(BaseException::new(
@@ -208,54 +865,513 @@ impl LanguageLogicUnit {
             ))
         })?;
 
-        Ok(Self::clean_string(&choice.message.content))
+        let content = if self.strip_think_blocks {
+            Self::strip_think_blocks(&choice.message.content)
+        } else {
+            Cow::Borrowed(choice.message.content.as_str())
+        };
+        let content = Self::clean_string(&content);
+        let truncated = choice.finish_reason.as_deref() == Some(FINISH_REASON_LENGTH);
+
+        if let (Some(cache), Some(key)) = (&self.chat_cache, cache_key)
+            && !content.is_empty()
+            && (!truncated || self.on_truncated_response == TruncatedResponsePolicy::Accept)
+        {
+            cache.insert(key, content.clone());
+        }
+
+        Ok((content, truncated))
     }
 
-    fn embeddings(content: &str, embedding_model: &str) -> Result<Vec<f32>, Exception> {
+    // Embeds every text with one request, leaving out those already in the cache. Returns None when
+    // the server has no embeddings endpoint.
+    fn embeddings(
+        &mut self,
+        contents: &[&str],
+        embedding_model: &str,
+    ) -> Result<Option<Vec<Vec<f32>>>, Exception> {
         let model = Self::default_embeddings_model(embedding_model);
-        let request = OpenAIEmbeddingsRequest::new(content, model);
-        let response = OpenAIClient::embeddings(request)?;
+        let mut embeddings = vec![None; contents.len()];
+        // Texts to request and the cache key of each, in request order. A text repeated in contents
+        // is requested once.
+        let mut missing: Vec<(&str, Option<String>)> = Vec::new();
+
+        for (content, embedding) in contents.iter().zip(embeddings.iter_mut()) {
+            let cache_key = self
+                .embeddings_cache
+                .is_some()
+                .then(|| Self::embeddings_cache_key(content, &model));
+
+            let cached = match (&self.embeddings_cache, &cache_key) {
+                (Some(cache), Some(key)) => {
+                    let cached = cache.get(key);
+                    self.counters.add_cache_lookup(cached.is_some());
+                    cached
+                }
+                _ => None,
+            };
+
+            if cached.is_some() {
+                *embedding = cached;
+            } else if !missing.iter().any(|(text, _)| text == content) {
+                missing.push((content, cache_key));
+            }
+        }
+
+        if !missing.is_empty() {
+            let inputs = missing.iter().map(|(text, _)| *text).collect::<Vec<_>>();
+            let Some(fetched) = self.request_embeddings(&inputs, model, embedding_model)? else {
+                return Ok(None);
+            };
+
+            for ((text, cache_key), vector) in missing.into_iter().zip(fetched) {
+                for (content, embedding) in contents.iter().zip(embeddings.iter_mut()) {
+                    if *content == text && embedding.is_none() {
+                        *embedding = Some(vector.clone());
+                    }
+                }
 
-        let embedding = response.data.first().ok_or_else(|| {
+                if let (Some(cache), Some(key)) = (&self.embeddings_cache, cache_key) {
+                    cache.insert(key, vector);
+                }
+            }
+        }
+
+        Ok(Some(embeddings.into_iter().flatten().collect()))
+    }
+
+    // The request for the text alone, so a text is cached the same whether it was embedded alone or
+    // with others.
+    fn embeddings_cache_key(content: &str, model: &ModelEmbeddingsConfig) -> String {
+        json::to_string(&OpenAIEmbeddingsRequest::new(&[content], model.clone()))
+    }
+
+    // Sends one embeddings request for every input and returns the vectors in input order, matched
+    // by the index the server gives each one.
+    fn request_embeddings(
+        &mut self,
+        inputs: &[&str],
+        model: ModelEmbeddingsConfig,
+        embedding_model: &str,
+    ) -> Result<Option<Vec<Vec<f32>>>, Exception> {
+        let request = OpenAIEmbeddingsRequest::new(inputs, model);
+        self.claim_request()?;
+
+        let (start, wait) = (Instant::now(), self.client.request_wait());
+        let response = self.client.embeddings(&request);
+        self.counters.add_embeddings();
+        self.add_model_time(start, wait);
+
+        let Some(response) = response? else {
+            return Ok(None);
+        };
+        self.record_usage(response.usage.as_ref());
+
+        let mut embeddings = vec![None; inputs.len()];
+
+        for data in response.data {
+            let slot = embeddings.get_mut(data.index).ok_or_else(|| {
+                Exception::LanguageLogic(BaseException::new(
+                    format!(
+                        "Embedding index {} is out of range for a request of {} input(s).",
+                        data.index,
+                        inputs.len()
+                    ),
+                    None,
+                ))
+            })?;
+            *slot = Some(data.embedding);
+        }
+
+        embeddings
+            .into_iter()
+            .enumerate()
+            .map(|(index, embedding)| {
+                let embedding = embedding.ok_or_else(|| {
+                    Exception::LanguageLogic(BaseException::new(
+                        format!(
+                            "No embedding returned from client for input {} of {}.",
+                            index,
+                            inputs.len()
+                        ),
+                        None,
+                    ))
+                })?;
+                self.check_dimensions(&embedding, embedding_model)?;
+
+                Ok(embedding)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some)
+    }
+
+    fn check_dimensions(
+        &mut self,
+        embedding: &[f32],
+        embedding_model: &str,
+    ) -> Result<(), Exception> {
+        let dimensions = embedding.len();
+
+        match self.embedding_dimensions {
+            Some(expected) if expected != dimensions => {
+                return Err(Exception::LanguageLogic(BaseException::new(
+                    format!(
+                        "Embedding model '{}' returned a vector with {} dimensions, expected {}.",
+                        embedding_model, dimensions, expected
+                    ),
+                    None,
+                )));
+            }
+            Some(_) => {}
+            None => self.embedding_dimensions = Some(dimensions),
+        }
+
+        Ok(())
+    }
+
+    // Embeddings of the texts to score, or None when similarity is estimated by the text model.
+    // Switches to the text model for the rest of the run the first time the server turns out to
+    // have no embeddings endpoint.
+    fn similarity_embeddings(
+        &mut self,
+        texts: &[&str],
+        embedding_model: &str,
+    ) -> Result<Option<Vec<Vec<f32>>>, Exception> {
+        if texts.is_empty() {
+            return Ok(Some(Vec::new()));
+        }
+
+        if self.similarity_backend == SimilarityBackend::Embeddings {
+            if let Some(embeddings) = self.embeddings(texts, embedding_model)? {
+                return Ok(Some(embeddings));
+            }
+
+            eprintln!(
+                "Warning: The server has no embeddings endpoint. Similarity is estimated by the text model for the rest of the run."
+            );
+            self.similarity_backend = SimilarityBackend::Llm;
+        }
+
+        Ok(None)
+    }
+
+    fn cosine(value_a: &[f32], value_b: &[f32]) -> Result<f32, Exception> {
+        // A vector restored from a checkpoint or the vector store file may come from a different
+        // embedding model.
+        if value_a.len() != value_b.len() {
+            return Err(Exception::LanguageLogic(BaseException::new(
+                format!(
+                    "Cannot compare a vector of {} dimensions with a vector of {} dimensions.",
+                    value_a.len(),
+                    value_b.len()
+                ),
+                None,
+            )));
+        }
+
+        // Compute cosine similarity.
+        let dot_product: f32 = value_a.iter().zip(value_b.iter()).map(|(a, b)| a * b).sum();
+        let x_euclidean_length: f32 = value_a.iter().map(|x| x * x).sum::<f32>().sqrt();
+        let y_euclidean_length: f32 = value_b.iter().map(|y| y * y).sum::<f32>().sqrt();
+
+        Ok(dot_product / (x_euclidean_length * y_euclidean_length))
+    }
+
+    fn cosine_similarity(value_a: &[f32], value_b: &[f32]) -> Result<i32, Exception> {
+        let percentage_similarity = Self::cosine(value_a, value_b)?.clamp(0.0, 1.0) * 100.0;
+
+        Ok(percentage_similarity.round() as i32)
+    }
+
+    fn estimated_similarity(&mut self, value_a: &str, value_b: &str) -> Result<i32, Exception> {
+        let micro_prompt = self.prompts.similarity(value_a, value_b);
+        let text_model = self.text_model.clone();
+        let text_model_overrides = self.text_model_overrides.clone();
+        let response = self.chat(
+            &micro_prompt,
+            &[],
+            &text_model,
+            &text_model_overrides,
+            self.debug_chat,
+        )?;
+
+        Self::parse_score(&response).ok_or_else(|| {
             Exception::LanguageLogic(BaseException::new(
-                "No embeddings returned from client.".to_string(),
+                format!(
+                    "Expected a similarity score from 0 to 100, but the text model replied '{}'.",
+                    response
+                ),
                 None,
             ))
-        })?;
+        })
+    }
 
-        Ok(embedding.embedding.to_owned())
+    // The score in a reply such as '85', 'Score: 85/100' or '4/5', from 0 to 100. The first number
+    // counts, out of the number after a '/' when there is one. Words around it are ignored, and a
+    // score above 100 is clamped. None when the reply has no number.
+    pub fn parse_score(response: &str) -> Option<i32> {
+        // The number text starts with and the text after it. Too many digits for a u64 is far
+        // above any scale anyway.
+        fn digits(text: &str) -> Option<(u64, &str)> {
+            let end = text
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(text.len());
+
+            (end > 0).then(|| (text[..end].parse().unwrap_or(u64::MAX), &text[end..]))
+        }
+
+        let start = response.find(|c: char| c.is_ascii_digit())?;
+        let (score, rest) = digits(&response[start..])?;
+        let out_of = rest
+            .trim_start()
+            .strip_prefix('/')
+            .and_then(|rest| digits(rest.trim_start()))
+            .map(|(out_of, _)| out_of)
+            .filter(|out_of| *out_of > 0);
+
+        let score = match out_of {
+            Some(out_of) => score.saturating_mul(100) / out_of,
+            None => score,
+        };
+
+        Some(score.min(100) as i32)
     }
 
-    pub fn cosine_similarity(
-        value_a: &str,
-        value_b: &str,
+    // Similarity from 0 to 100. Both text operands are embedded with one request. Falls back to a
+    // score estimated by the text model, for the rest of the run, the first time the server turns
+    // out to have no embeddings endpoint.
+    pub fn similarity(
+        &mut self,
+        value_a: SimilarityOperand,
+        value_b: SimilarityOperand,
         embedding_model: &str,
-    ) -> Result<u32, Exception> {
-        let value_a_embeddings = Self::embeddings(value_a, embedding_model)?;
-        let value_b_embeddings = Self::embeddings(value_b, embedding_model)?;
+    ) -> Result<i32, Exception> {
+        let texts = [value_a, value_b]
+            .into_iter()
+            .filter_map(|operand| match operand {
+                SimilarityOperand::Text(text) => Some(text),
+                SimilarityOperand::Vector(_) => None,
+            })
+            .collect::<Vec<_>>();
 
-        // Compute cosine similarity.
-        let dot_product: f32 = value_a_embeddings
+        if let Some(embeddings) = self.similarity_embeddings(&texts, embedding_model)? {
+            let mut embeddings = embeddings.into_iter();
+            let mut vector = |operand| match operand {
+                SimilarityOperand::Text(_) => Cow::Owned(embeddings.next().unwrap_or_default()),
+                SimilarityOperand::Vector(vector) => Cow::Borrowed(vector),
+            };
+            let (vector_a, vector_b) = (vector(value_a), vector(value_b));
+
+            return Self::cosine_similarity(&vector_a, &vector_b);
+        }
+
+        match (value_a, value_b) {
+            (SimilarityOperand::Text(value_a), SimilarityOperand::Text(value_b)) => {
+                self.estimated_similarity(value_a, value_b)
+            }
+            _ => Err(Exception::LanguageLogic(BaseException::new(
+                "Cannot compare a vector from EMB because the server has no embeddings endpoint."
+                    .to_string(),
+                None,
+            ))),
+        }
+    }
+
+    // Similarity of text to each candidate, with every text embedded in one request.
+    fn similarities(
+        &mut self,
+        text: &str,
+        candidates: &[&str],
+        embedding_model: &str,
+    ) -> Result<Vec<i32>, Exception> {
+        let texts = std::iter::once(text)
+            .chain(candidates.iter().copied())
+            .collect::<Vec<_>>();
+
+        if let Some(embeddings) = self.similarity_embeddings(&texts, embedding_model)? {
+            let Some((text, candidates)) = embeddings.split_first() else {
+                return Ok(Vec::new());
+            };
+
+            return candidates
+                .iter()
+                .map(|candidate| Self::cosine_similarity(text, candidate))
+                .collect();
+        }
+
+        candidates
+            .iter()
+            .map(|candidate| self.estimated_similarity(text, candidate))
+            .collect()
+    }
+
+    // Embeddings for EMB and the vector store. Unlike SIM there is no text model fallback.
+    fn required_embeddings(
+        &mut self,
+        values: &[&str],
+        embedding_model: &str,
+    ) -> Result<Vec<Vec<f32>>, Exception> {
+        let embeddings = match self.similarity_backend {
+            SimilarityBackend::Embeddings => self.embeddings(values, embedding_model)?,
+            SimilarityBackend::Llm => None,
+        };
+
+        embeddings.ok_or_else(|| {
+            Exception::LanguageLogic(BaseException::new(
+                "Cannot make an embedding because the server has no embeddings endpoint."
+                    .to_string(),
+                None,
+            ))
+        })
+    }
+
+    // Embeddings to keep in registers for EMB, in the order of the values and made with one request.
+    pub fn embeddings_of(
+        &mut self,
+        values: &[&str],
+        embedding_model: &str,
+    ) -> Result<Vec<Vec<f32>>, Exception> {
+        self.required_embeddings(values, embedding_model)
+    }
+
+    // Stores the text for VQUERY to search. It is embedded later, with every other text put before
+    // the next VQUERY or the end of the run.
+    pub fn vector_put(
+        &mut self,
+        key: &str,
+        text: &str,
+        embedding_model: &str,
+    ) -> Result<(), Exception> {
+        self.vector_store.put(key, text, embedding_model)
+    }
+
+    // Embeds the texts waiting in the vector store and the queries with one request, stores the
+    // texts and returns the embeddings of the queries.
+    fn embed_pending(
+        &mut self,
+        queries: &[&str],
+        embedding_model: &str,
+    ) -> Result<Vec<Vec<f32>>, Exception> {
+        let pending = self.vector_store.take_pending();
+        let texts = queries
             .iter()
-            .zip(value_b_embeddings.iter())
-            .map(|(a, b)| a * b)
-            .sum();
-        let x_euclidean_length: f32 = value_a_embeddings.iter().map(|x| x * x).sum::<f32>().sqrt();
-        let y_euclidean_length: f32 = value_b_embeddings.iter().map(|y| y * y).sum::<f32>().sqrt();
-        let similarity = dot_product / (x_euclidean_length * y_euclidean_length);
-        let percentage_similarity = similarity.clamp(0.0, 1.0) * 100.0;
+            .copied()
+            .chain(pending.iter().map(|(_, text)| text.as_str()))
+            .collect::<Vec<_>>();
+
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut embeddings = self.required_embeddings(&texts, embedding_model)?;
+        let stored = embeddings.split_off(queries.len().min(embeddings.len()));
+
+        if !pending.is_empty() {
+            self.vector_store.insert(pending, stored)?;
+            self.vector_store.save()?;
+        }
+
+        Ok(embeddings)
+    }
+
+    // The stored texts most similar to the query, most similar first and at most count of them.
+    // Texts put since the last VQUERY are embedded with the query.
+    pub fn vector_query(
+        &mut self,
+        query: &str,
+        count: usize,
+        embedding_model: &str,
+    ) -> Result<Vec<String>, Exception> {
+        match self.vector_store.model()? {
+            None => return Ok(Vec::new()),
+            Some(model) if model != embedding_model => {
+                return Err(Exception::LanguageLogic(BaseException::new(
+                    format!(
+                        "Cannot search the vector store with model '{}' because it holds embeddings from model '{}'. Clear it with VCLR or select '{}' with SETEMB.",
+                        embedding_model, model, model
+                    ),
+                    None,
+                )));
+            }
+            Some(_) => {}
+        }
+
+        let query = self
+            .embed_pending(&[query], embedding_model)?
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+
+        let mut scores = self
+            .vector_store
+            .entries()
+            .iter()
+            .map(|entry| {
+                // A zero vector scores NaN, which ranks below every other text.
+                let score = Self::cosine(&query, &entry.vector)?;
+                Ok((if score.is_nan() { -1.0 } else { score }, entry))
+            })
+            .collect::<Result<Vec<_>, Exception>>()?;
+
+        // Stable, so texts that score the same stay in the order they were put.
+        scores.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+        Ok(scores
+            .into_iter()
+            .take(count)
+            .map(|(_, entry)| entry.text.clone())
+            .collect())
+    }
 
-        Ok(percentage_similarity.round() as u32)
+    pub fn capture(&mut self) -> Result<LanguageLogicState, Exception> {
+        Ok(LanguageLogicState {
+            text_model: self.text_model.clone(),
+            embedding_model: self.embedding_model.clone(),
+            temperature: self.text_model_overrides.temperature,
+            top_p: self.text_model_overrides.top_p,
+            max_tokens: self.text_model_overrides.max_tokens,
+            system_prompt: self.system_prompt.clone(),
+            vector_store: self.vector_store.capture()?,
+        })
+    }
+
+    pub fn restore(&mut self, state: &LanguageLogicState) {
+        self.set_text_model(&state.text_model);
+        self.set_embedding_model(&state.embedding_model);
+        self.text_model_overrides.temperature = state.temperature;
+        self.text_model_overrides.top_p = state.top_p;
+        self.text_model_overrides.max_tokens = state.max_tokens;
+        self.set_system_prompt(&state.system_prompt);
+        self.vector_store.restore(&state.vector_store);
+    }
+
+    pub fn vector_clear(&mut self) -> Result<(), Exception> {
+        self.vector_store.clear()
+    }
+
+    // Embeds the texts put since the last VQUERY so the vector store file has them for the next
+    // run. Without a file they could never be searched, so nothing is sent.
+    pub fn save_vector_store(&mut self) -> Result<(), Exception> {
+        if !self.vector_store.is_persistent() || !self.vector_store.has_pending() {
+            return Ok(());
+        }
+
+        let Some(model) = self.vector_store.model()?.map(str::to_string) else {
+            return Ok(());
+        };
+
+        self.embed_pending(&[], &model).map(|_| ())
     }
 
     pub fn string(
+        &mut self,
         micro_prompt: &str,
         context: &[ContextMessage],
         text_model: &str,
         text_model_overrides: &TextModelOverrides,
         debug_chat: bool,
     ) -> Result<String, Exception> {
-        Self::chat(
+        self.chat(
             micro_prompt,
             context,
             text_model,
@@ -265,14 +1381,15 @@ impl LanguageLogicUnit {
     }
 
     pub fn boolean(
+        &mut self,
         micro_prompt: &str,
         eval_params: &BooleanEvalParams,
         context: &[ContextMessage],
         text_model: &str,
         text_model_overrides: &TextModelOverrides,
         debug_chat: bool,
-    ) -> Result<u32, Exception> {
-        let value = Self::string(
+    ) -> Result<i32, Exception> {
+        let value = self.string(
             micro_prompt,
             context,
             text_model,
@@ -280,27 +1397,24 @@ impl LanguageLogicUnit {
             debug_chat,
         )?;
 
-        let max_true_score = eval_params
+        // Only reached with an empty response when the policy accepts it, and nothing empty is true.
+        if value.is_empty() {
+            return Ok(0);
+        }
+
+        let value = value.to_lowercase();
+        let candidates = eval_params
             .true_values
             .iter()
-            .map(|tv| {
-                Self::cosine_similarity(&value.to_lowercase(), &tv.to_lowercase(), eval_params.embedding_model)
-            })
-            .collect::<Result<Vec<_>, _>>()?
-            .into_iter()
-            .max()
-            .unwrap_or(0);
+            .chain(eval_params.false_values)
+            .map(|candidate| candidate.to_lowercase())
+            .collect::<Vec<_>>();
+        let candidates = candidates.iter().map(String::as_str).collect::<Vec<_>>();
 
-        let max_false_score = eval_params
-            .false_values
-            .iter()
-            .map(|fv| {
-                Self::cosine_similarity(&value.to_lowercase(), &fv.to_lowercase(), eval_params.embedding_model)
-            })
-            .collect::<Result<Vec<_>, _>>()?
-            .into_iter()
-            .max()
-            .unwrap_or(0);
+        let scores = self.similarities(&value, &candidates, eval_params.embedding_model)?;
+        let (true_scores, false_scores) = scores.split_at(eval_params.true_values.len());
+        let max_true_score = true_scores.iter().copied().max().unwrap_or(0);
+        let max_false_score = false_scores.iter().copied().max().unwrap_or(0);
 
         if max_true_score > max_false_score {
             Ok(100)
@@ -308,4 +1422,132 @@ impl LanguageLogicUnit {
             Ok(0)
         }
     }
+
+    // The index of the label the text model picks for text. A reply that is not one of the labels
+    // is asked again once with a firmer prompt before it is an error.
+    pub fn classify(
+        &mut self,
+        text: &str,
+        labels: &[&str],
+        text_model: &str,
+        text_model_overrides: &TextModelOverrides,
+        debug_chat: bool,
+    ) -> Result<usize, Exception> {
+        let micro_prompts = [
+            self.prompts.classify(text, labels),
+            self.prompts.classify_retry(text, labels),
+        ];
+        let mut reply = String::new();
+
+        for micro_prompt in &micro_prompts {
+            reply = self.string(
+                micro_prompt,
+                &[],
+                text_model,
+                text_model_overrides,
+                debug_chat,
+            )?;
+
+            if let Some(index) = labels::find(labels, &reply) {
+                return Ok(index);
+            }
+        }
+
+        Err(Exception::LanguageLogic(BaseException::new(
+            format!(
+                "The text model replied '{}', which is not one of the labels: {}.",
+                reply,
+                labels.join(", ")
+            ),
+            None,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Assembler, Processor};
+
+    // Raw responses from reasoning models, and what is left of them once cleaned.
+    const RAW_RESPONSES: [(&str, &str); 6] = [
+        (
+            "<think>\nThe user wants a greeting.\n</think>\n\nHello!",
+            "Hello!",
+        ),
+        // The chat template opened the block in the prompt.
+        ("The user wants a greeting.\n</think>\nHello!", "Hello!"),
+        (
+            "<think>Plan.</think>First paragraph.\n\n```rust\nfn main() {}\n```\n\nLast paragraph.",
+            "First paragraph.\n\n```rust\nfn main() {}\n```\n\nLast paragraph.",
+        ),
+        // The token limit cut the block short.
+        ("<think>Still thinking about", ""),
+        ("<think>a</think>One <think>b</think>two", "One two"),
+        ("  Plain text.\n", "Plain text."),
+    ];
+
+    #[test]
+    fn think_blocks_are_removed_and_interior_line_breaks_kept() {
+        for (raw, cleaned) in RAW_RESPONSES {
+            let stripped = LanguageLogicUnit::strip_think_blocks(raw);
+            assert_eq!(
+                LanguageLogicUnit::clean_string(&stripped),
+                cleaned,
+                "{}",
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn think_blocks_are_kept_when_stripping_is_off() {
+        let byte_code = Assembler::new(
+            "LS X1, \"Greet me.\"\nINF X2, X1, C1\nEXIT\n".to_string(),
+            "think.aasm",
+        )
+        .assemble()
+        .unwrap();
+        let raw = RAW_RESPONSES[0].0;
+
+        for (strip_think_blocks, expected) in [(true, "Hello!"), (false, raw.trim())] {
+            let mut backend = ScriptedBackend::new();
+            backend.push_response(raw);
+
+            let mut config = Config::new("text", "embedding");
+            config.strip_think_blocks = strip_think_blocks;
+
+            let mut processor = Processor::new(config);
+            processor.set_backend(Box::new(backend));
+            processor.load(&byte_code).unwrap();
+            processor.run().unwrap();
+            assert_eq!(processor.register(2).unwrap().to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn scores_are_read_from_clean_numbers_scales_and_words() {
+        for (reply, score) in [
+            ("85", Some(85)),
+            ("Score: 85/100", Some(85)),
+            ("I would say 4/5.", Some(80)),
+            ("250", Some(100)),
+            ("12345678901234567890123", Some(100)),
+            // A scale of 0 is ignored.
+            ("8 / 0", Some(8)),
+            ("It meets the criteria well.", None),
+            ("", None),
+        ] {
+            assert_eq!(LanguageLogicUnit::parse_score(reply), score, "{}", reply);
+        }
+    }
+
+    #[test]
+    fn tokens_are_estimated_from_ascii_and_other_characters() {
+        let english = "The quick brown fox jumps over the dog";
+        assert_eq!(english.len(), 38);
+        assert_eq!(LanguageLogicUnit::estimate_tokens(english), 10);
+        assert_eq!(LanguageLogicUnit::estimate_tokens("日本語のテキスト"), 8);
+        assert_eq!(LanguageLogicUnit::estimate_tokens(""), 0);
+    }
 }
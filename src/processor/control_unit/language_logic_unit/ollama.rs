@@ -0,0 +1,442 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use miniserde::{
+    Deserialize, Serialize,
+    json::{self, Array, Number, Value},
+};
+
+use crate::{
+    assembler::roles,
+    config::Config,
+    constants,
+    exception::{BaseException, Exception},
+    processor::control_unit::language_logic_unit::openai::{
+        OpenAIClient, Reply,
+        chat_completion_models::{
+            OpenAIChatCompletionPayload, OpenAIChatCompletionRequestText,
+            OpenAIChatCompletionResponse, OpenAIChatCompletionResponseChoice,
+            OpenAIChatCompletionResponseMessage, OpenAIChatCompletionResponseTimings,
+            OpenAIResponseUsage,
+        },
+        embeddings_models::{
+            OpenAIEmbeddingsRequest, OpenAIEmbeddingsResponse, OpenAIEmbeddingsResponseEmbedding,
+        },
+    },
+};
+
+const CHAT_ENDPOINT: &str = "api/chat";
+const EMBEDDINGS_ENDPOINT: &str = "api/embeddings";
+const TAGS_ENDPOINT: &str = "api/tags";
+
+// Status Ollama answers with for a model it does not have, and for an endpoint it does not have.
+const NOT_FOUND_STATUS_CODE: i32 = 404;
+
+#[derive(Debug, Serialize)]
+struct OllamaChatRequest {
+    model: String,
+    messages: Vec<OpenAIChatCompletionRequestText>,
+    stream: bool,
+    // Sampling settings Ollama has an equivalent for. Settings it does not support, such as DRY and
+    // XTC, are left out.
+    options: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaMessage {
+    role: String,
+    content: String,
+}
+
+// Counts and durations in nanoseconds are only in the final response, and may be missing.
+#[derive(Debug, Deserialize)]
+struct OllamaChatResponse {
+    model: String,
+    message: OllamaMessage,
+    prompt_eval_count: Option<u64>,
+    prompt_eval_duration: Option<u64>,
+    eval_count: Option<u64>,
+    eval_duration: Option<u64>,
+    // "stop" or "length", as OpenAI's finish_reason.
+    done_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaEmbeddingsRequest {
+    model: String,
+    prompt: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaEmbeddingsResponse {
+    embedding: Vec<f32>,
+}
+
+// The models Ollama has pulled. Names carry a tag, such as "llama3.2:latest".
+#[derive(Debug, Deserialize)]
+struct OllamaTags {
+    models: Vec<OllamaModel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaModel {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaError {
+    error: String,
+}
+
+// Talks to an Ollama server, converting the OpenAI requests the language logic unit makes into
+// Ollama's and its responses back. Requests are sent, retried and recorded like OpenAIClient's.
+pub struct OllamaClient {
+    transport: OpenAIClient,
+}
+
+impl OllamaClient {
+    pub fn new(config: &Config) -> Self {
+        OllamaClient {
+            transport: OpenAIClient::new(config),
+        }
+    }
+
+    pub fn request_wait(&self) -> Duration {
+        self.transport.request_wait()
+    }
+
+    fn chat_request(payload: &OpenAIChatCompletionPayload) -> OllamaChatRequest {
+        let request = &payload.request;
+        let mut options = [
+            ("temperature", f64::from(request.temperature)),
+            ("top_k", f64::from(request.top_k)),
+            ("top_p", f64::from(request.top_p)),
+            ("min_p", f64::from(request.min_p)),
+            ("typical_p", f64::from(request.typ_p)),
+            ("repeat_last_n", f64::from(request.repeat_last_n)),
+            ("repeat_penalty", f64::from(request.repeat_penalty)),
+            ("presence_penalty", f64::from(request.presence_penalty)),
+            ("frequency_penalty", f64::from(request.frequency_penalty)),
+            ("num_predict", f64::from(request.max_tokens)),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), Value::Number(Number::F64(value))))
+        .collect::<BTreeMap<_, _>>();
+
+        if let Some(seed) = payload.seed {
+            options.insert("seed".to_string(), Value::Number(Number::U64(seed.into())));
+        }
+
+        if !payload.stop.is_empty() {
+            let stop = payload.stop.iter().cloned().map(Value::String);
+            options.insert("stop".to_string(), Value::Array(stop.collect::<Array>()));
+        }
+
+        OllamaChatRequest {
+            model: request.model.clone(),
+            messages: request.messages.clone(),
+            stream: false,
+            options,
+        }
+    }
+
+    // Ollama explains a failed request in an "error" field. A missing model is the usual cause, so
+    // it gets a message of its own.
+    fn status_error(
+        status: i32,
+        reason: &str,
+        body: &str,
+        model: &str,
+        error_variant: fn(BaseException) -> Exception,
+        context: &str,
+    ) -> Exception {
+        let Ok(error) = json::from_str::<OllamaError>(body) else {
+            return OpenAIClient::status_error(status, reason, body, error_variant, context);
+        };
+
+        if status == NOT_FOUND_STATUS_CODE && error.error.contains("not found") {
+            return (error_variant)(BaseException::new(
+                format!(
+                    "Ollama does not have the model '{}' ({}). Pull it with 'ollama pull {}'.",
+                    model, error.error, model
+                ),
+                None,
+            ));
+        }
+
+        OpenAIClient::status_error(status, &error.error, body, error_variant, context)
+    }
+
+    pub fn chat_completion(
+        &self,
+        request: &OpenAIChatCompletionPayload,
+    ) -> Result<OpenAIChatCompletionResponse, Exception> {
+        let (variant, context) = (Exception::OpenAIChatCompletion, "Ollama chat");
+        let model = &request.request.model;
+        let body = json::to_string(&Self::chat_request(request));
+
+        let response: OllamaChatResponse =
+            match self
+                .transport
+                .exchange(false, CHAT_ENDPOINT, &body, variant, context)?
+            {
+                Reply::Text(text) => OpenAIClient::parse(&text, variant, context)?,
+                Reply::Status {
+                    status,
+                    reason,
+                    body,
+                } => {
+                    return Err(Self::status_error(
+                        status, &reason, &body, model, variant, context,
+                    ));
+                }
+            };
+
+        let milliseconds = |nanoseconds: Option<u64>| nanoseconds.map(|ns| ns as f64 / 1e6);
+
+        Ok(OpenAIChatCompletionResponse {
+            model: response.model,
+            choices: vec![OpenAIChatCompletionResponseChoice {
+                index: 0,
+                message: OpenAIChatCompletionResponseMessage {
+                    role: if response.message.role.is_empty() {
+                        roles::ASSISTANT_ROLE.to_string()
+                    } else {
+                        response.message.role
+                    },
+                    content: response.message.content,
+                },
+                finish_reason: response.done_reason,
+            }],
+            timings: Some(OpenAIChatCompletionResponseTimings {
+                prompt_n: response.prompt_eval_count,
+                prompt_ms: milliseconds(response.prompt_eval_duration),
+                prompt_per_second: None,
+                predicted_n: response.eval_count,
+                predicted_ms: milliseconds(response.eval_duration),
+                predicted_per_second: None,
+            }),
+            usage: Some(OpenAIResponseUsage {
+                prompt_tokens: response.prompt_eval_count,
+                completion_tokens: response.eval_count,
+            }),
+        })
+    }
+
+    // Ollama embeds one text per request. Returns None when the server has no embeddings endpoint.
+    pub fn embeddings(
+        &self,
+        request: &OpenAIEmbeddingsRequest,
+    ) -> Result<Option<OpenAIEmbeddingsResponse>, Exception> {
+        let (variant, context) = (Exception::OpenAIEmbeddings, "Ollama embedding");
+        let mut data = Vec::with_capacity(request.input.len());
+
+        for (index, input) in request.input.iter().enumerate() {
+            let body = json::to_string(&OllamaEmbeddingsRequest {
+                model: request.model.clone(),
+                prompt: input.clone(),
+            });
+
+            let response: OllamaEmbeddingsResponse =
+                match self
+                    .transport
+                    .exchange(true, EMBEDDINGS_ENDPOINT, &body, variant, context)?
+                {
+                    Reply::Text(text) => OpenAIClient::parse(&text, variant, context)?,
+                    Reply::Status { status, body, .. }
+                        if status == NOT_FOUND_STATUS_CODE
+                            && json::from_str::<OllamaError>(&body).is_err() =>
+                    {
+                        return Ok(None);
+                    }
+                    Reply::Status {
+                        status,
+                        reason,
+                        body,
+                    } => {
+                        return Err(Self::status_error(
+                            status,
+                            &reason,
+                            &body,
+                            &request.model,
+                            variant,
+                            context,
+                        ));
+                    }
+                };
+
+            data.push(OpenAIEmbeddingsResponseEmbedding {
+                object: "embedding".to_string(),
+                embedding: response.embedding,
+                index,
+            });
+        }
+
+        Ok(Some(OpenAIEmbeddingsResponse {
+            object: "list".to_string(),
+            data,
+            usage: None,
+        }))
+    }
+
+    // Checks that Ollama answers and has pulled each model given, before a program runs. A name
+    // without a tag is the model's latest.
+    pub fn health_check(
+        &self,
+        text_model: Option<&str>,
+        embedding_model: Option<&str>,
+    ) -> Result<(), Exception> {
+        let checks = [
+            (false, text_model, constants::TEXT_MODEL_ENV),
+            (true, embedding_model, constants::EMBEDDING_MODEL_ENV),
+        ];
+
+        for (embeddings, model, model_variable) in checks {
+            let Some(model) = model else {
+                continue;
+            };
+
+            let (base_url, variable) = self.transport.server(embeddings);
+            let url = format!("{}/{}", base_url, TAGS_ENDPOINT);
+            let response = self.transport.probe(&url, variable)?;
+
+            if response.status_code != 200 {
+                return Err(OpenAIClient::unhealthy(&url, &response));
+            }
+
+            let Ok(Ok(tags)) = response.as_str().map(json::from_str::<OllamaTags>) else {
+                continue;
+            };
+            let models = tags
+                .models
+                .into_iter()
+                .map(|model| model.name)
+                .collect::<Vec<_>>();
+            let latest = format!("{}:latest", model);
+
+            if !models.iter().any(|name| name == model || *name == latest) {
+                return Err(OpenAIClient::missing_model(
+                    base_url,
+                    model,
+                    model_variable,
+                    &models,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::{
+        Assembler, Processor,
+        config::{LlmProvider, TextModelOverrides},
+        processor::control_unit::language_logic_unit::{
+            LanguageLogicUnit, openai::tests::scripted_server,
+        },
+    };
+
+    // Responses recorded from an Ollama server.
+    const CHAT_TRANSCRIPT: &str = r#"{"model":"stub","created_at":"2026-01-01T00:00:00Z","message":{"role":"assistant","content":"ok"},"done_reason":"stop","done":true,"total_duration":200000000,"load_duration":1000000,"prompt_eval_count":26,"prompt_eval_duration":130000000,"eval_count":2,"eval_duration":50000000}"#;
+    const EMBEDDINGS_TRANSCRIPT: &str = r#"{"embedding":[1.0,0.0]}"#;
+    const MISSING_MODEL_TRANSCRIPT: &str =
+        r#"{"error":"model \"stub\" not found, try pulling it first"}"#;
+
+    // Runs a program sending one chat request and embedding two texts against an Ollama server at
+    // url, without a health check or retries.
+    fn run_ollama(url: String) -> Result<Processor, Exception> {
+        let byte_code = Assembler::new(
+            "LS X1, \"Say ok.\"\nINF X2, X1, C1\nSIM X3, X1, X2\nEXIT\n".to_string(),
+            "ollama.aasm",
+        )
+        .assemble()
+        .unwrap();
+
+        let mut config = Config::new("stub", "stub");
+        config.llm_provider = LlmProvider::Ollama;
+        config.llm_base_url = url;
+        config.skip_healthcheck = true;
+        config.retry_policy.attempts = 1;
+
+        let mut processor = Processor::new(config);
+        processor.load(&byte_code).unwrap();
+        processor.run()?;
+        Ok(processor)
+    }
+
+    #[test]
+    fn ollama_responses_are_adapted_to_the_openai_structures() {
+        let server = scripted_server(&[
+            ("200 OK", CHAT_TRANSCRIPT),
+            ("200 OK", EMBEDDINGS_TRANSCRIPT),
+        ]);
+        let processor = run_ollama(server.url.clone()).unwrap();
+
+        assert_eq!(processor.register(2).unwrap().to_string(), "ok");
+        assert_eq!(processor.register(3).unwrap().to_string(), "100");
+
+        let timings = processor.chat_timings().unwrap();
+        assert_eq!((timings.prompt_tokens, timings.prompt_ms), (26, 130.0));
+        assert_eq!((timings.predicted_tokens, timings.predicted_ms), (2, 50.0));
+
+        // One chat request, then one embeddings request for each text.
+        let heads = server.heads.lock().unwrap();
+        let targets = heads
+            .iter()
+            .map(|head| head.split(' ').take(2).collect::<Vec<_>>().join(" "))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            targets,
+            [
+                "POST /api/chat",
+                "POST /api/embeddings",
+                "POST /api/embeddings"
+            ]
+        );
+    }
+
+    #[test]
+    fn chat_requests_carry_the_sampling_settings_as_options() {
+        let overrides = TextModelOverrides {
+            max_tokens: Some(64),
+            seed: Some(7),
+            ..TextModelOverrides::default()
+        };
+        let config = LanguageLogicUnit::default_text_model("stub", &overrides);
+        let payload = OpenAIChatCompletionPayload::new(Vec::new(), config);
+        let body = json::to_string(&OllamaClient::chat_request(&payload));
+
+        assert!(body.contains(r#""stream":false"#), "{}", body);
+        assert!(body.contains(r#""num_predict":64.0"#), "{}", body);
+        assert!(body.contains(r#""seed":7"#), "{}", body);
+        assert!(!body.contains("dry"), "{}", body);
+    }
+
+    #[test]
+    fn missing_models_and_unreachable_servers_are_readable_errors() {
+        let server = scripted_server(&[("404 Not Found", MISSING_MODEL_TRANSCRIPT)]);
+        let error = format!("{:?}", run_ollama(server.url).err().unwrap());
+        assert!(
+            error.contains("Ollama does not have the model 'stub'"),
+            "{}",
+            error
+        );
+        assert!(error.contains("ollama pull stub"), "{}", error);
+
+        // Nothing listens on the port once the listener is dropped.
+        let closed = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let error = format!("{:?}", run_ollama(closed).err().unwrap());
+        assert!(
+            error.contains("Failed to send Ollama chat request"),
+            "{}",
+            error
+        );
+    }
+}
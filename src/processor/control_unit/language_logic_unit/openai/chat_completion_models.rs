@@ -1,4 +1,9 @@
-use miniserde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+use miniserde::{
+    Deserialize, Serialize,
+    ser::{Fragment, Map},
+};
 
 use super::model_config::ModelTextConfig;
 
@@ -8,7 +13,7 @@ pub struct OpenAIChatCompletionRequestText {
     pub content: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpenAIChatCompletionRequest {
     pub messages: Vec<OpenAIChatCompletionRequestText>,
     pub stream: bool,
@@ -69,6 +74,58 @@ impl OpenAIChatCompletionRequest {
     }
 }
 
+// miniserde serialises None as null, so optional fields are added to the request only when they are set.
+// Some servers reject an empty stop list, so it is left out too.
+#[derive(Debug)]
+pub struct OpenAIChatCompletionPayload {
+    pub request: OpenAIChatCompletionRequest,
+    pub seed: Option<u32>,
+    pub stop: Vec<String>,
+}
+
+impl OpenAIChatCompletionPayload {
+    pub fn new(messages: Vec<OpenAIChatCompletionRequestText>, config: ModelTextConfig) -> Self {
+        Self {
+            seed: config.seed,
+            stop: config.stop.clone(),
+            request: OpenAIChatCompletionRequest::new(messages, config),
+        }
+    }
+}
+
+impl Serialize for OpenAIChatCompletionPayload {
+    fn begin(&self) -> Fragment<'_> {
+        match self.request.begin() {
+            Fragment::Map(fields) => Fragment::Map(Box::new(OpenAIChatCompletionPayloadStream {
+                fields,
+                seed: self.seed.as_ref(),
+                stop: (!self.stop.is_empty()).then_some(&self.stop),
+            })),
+            fragment => fragment,
+        }
+    }
+}
+
+struct OpenAIChatCompletionPayloadStream<'a> {
+    fields: Box<dyn Map + 'a>,
+    seed: Option<&'a u32>,
+    stop: Option<&'a Vec<String>>,
+}
+
+impl Map for OpenAIChatCompletionPayloadStream<'_> {
+    fn next(&mut self) -> Option<(Cow<'_, str>, &dyn Serialize)> {
+        if let Some(seed) = self.seed.take() {
+            return Some((Cow::Borrowed("seed"), seed));
+        }
+
+        if let Some(stop) = self.stop.take() {
+            return Some((Cow::Borrowed("stop"), stop));
+        }
+
+        self.fields.next()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OpenAIChatCompletionResponseMessage {
     pub role: String,
@@ -79,10 +136,33 @@ pub struct OpenAIChatCompletionResponseMessage {
 pub struct OpenAIChatCompletionResponseChoice {
     pub index: u8,
     pub message: OpenAIChatCompletionResponseMessage,
+    // Why generation stopped: "stop" at a natural end, "length" at the token limit. Some servers
+    // leave it out.
+    pub finish_reason: Option<String>,
+}
+
+// Server-side timings llama.cpp adds when timings_per_token is set. Any field may be missing.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct OpenAIChatCompletionResponseTimings {
+    pub prompt_n: Option<u64>,
+    pub prompt_ms: Option<f64>,
+    pub prompt_per_second: Option<f64>,
+    pub predicted_n: Option<u64>,
+    pub predicted_ms: Option<f64>,
+    pub predicted_per_second: Option<f64>,
+}
+
+// Tokens counted by the server. Embeddings responses leave out completion_tokens.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct OpenAIResponseUsage {
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OpenAIChatCompletionResponse {
     pub model: String,
     pub choices: Vec<OpenAIChatCompletionResponseChoice>,
+    pub timings: Option<OpenAIChatCompletionResponseTimings>,
+    pub usage: Option<OpenAIResponseUsage>,
 }
@@ -1,19 +1,20 @@
 use miniserde::{Deserialize, Serialize};
 
-use super::model_config::ModelEmbeddingsConfig;
+use super::{chat_completion_models::OpenAIResponseUsage, model_config::ModelEmbeddingsConfig};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpenAIEmbeddingsRequest {
     pub model: String,
-    pub input: String,
+    // The server returns one embedding per input, each with the index of its input.
+    pub input: Vec<String>,
     pub encoding_format: String,
 }
 
 impl OpenAIEmbeddingsRequest {
-    pub fn new(content: &str, config: ModelEmbeddingsConfig) -> Self {
+    pub fn new(contents: &[&str], config: ModelEmbeddingsConfig) -> Self {
         Self {
             model: config.model,
-            input: content.to_string(),
+            input: contents.iter().map(|content| content.to_string()).collect(),
             encoding_format: config.encoding_format,
         }
     }
@@ -23,11 +24,12 @@ impl OpenAIEmbeddingsRequest {
 pub struct OpenAIEmbeddingsResponseEmbedding {
     pub object: String,
     pub embedding: Vec<f32>,
-    pub index: u8,
+    pub index: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OpenAIEmbeddingsResponse {
     pub object: String,
     pub data: Vec<OpenAIEmbeddingsResponseEmbedding>,
+    pub usage: Option<OpenAIResponseUsage>,
 }
@@ -0,0 +1,45 @@
+use std::fmt;
+
+use miniserde::{Deserialize, json::Value};
+
+// The body OpenAI and compatible servers send with a failed request.
+#[derive(Debug, Deserialize)]
+pub struct OpenAIErrorResponse {
+    pub error: OpenAIErrorDetail,
+}
+
+// The code is a string for OpenAI, such as "model_not_found", and a number for llama.cpp.
+#[derive(Debug, Deserialize)]
+pub struct OpenAIErrorDetail {
+    pub message: String,
+    #[serde(rename = "type")]
+    pub error_type: Option<String>,
+    pub code: Option<Value>,
+}
+
+impl OpenAIErrorDetail {
+    pub fn code(&self) -> Option<String> {
+        match &self.code {
+            Some(Value::String(code)) => Some(code.clone()),
+            Some(Value::Number(code)) => Some(code.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for OpenAIErrorDetail {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let details = self
+            .error_type
+            .iter()
+            .cloned()
+            .chain(self.code())
+            .collect::<Vec<_>>();
+
+        if details.is_empty() {
+            write!(formatter, "{}", self.message)
+        } else {
+            write!(formatter, "{} ({})", self.message, details.join(", "))
+        }
+    }
+}
@@ -1,57 +1,414 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
 use miniserde::json::{self, from_str};
-use minreq::post;
+use minreq::{Request, Response, get, post};
 
 use crate::{
+    config::{Config, RecordingMode, RetryPolicy},
+    constants,
     exception::{BaseException, Exception},
     processor::control_unit::language_logic_unit::openai::{
-        chat_completion_models::{OpenAIChatCompletionRequest, OpenAIChatCompletionResponse},
+        chat_completion_models::{OpenAIChatCompletionPayload, OpenAIChatCompletionResponse},
         embeddings_models::{OpenAIEmbeddingsRequest, OpenAIEmbeddingsResponse},
+        error_models::OpenAIErrorResponse,
+        model_list_models::OpenAIModelList,
+        recordings::Recordings,
+        semaphore::Semaphore,
+        tokenize_models::{OpenAITokenizeRequest, OpenAITokenizeResponse},
     },
+    proxy::{self, ProxySettings},
 };
 
 pub mod chat_completion_models;
 pub mod embeddings_models;
+pub mod error_models;
 pub mod model_config;
+pub mod model_list_models;
+mod recordings;
+mod semaphore;
+pub mod tokenize_models;
 
-const BASE_URL: &str = "http://127.0.0.1:8080";
 const CHAT_COMPLETION_ENDPOINT: &str = "v1/chat/completions";
 const EMBEDDINGS_ENDPOINT: &str = "v1/embeddings";
+// Provided by llama.cpp, not by OpenAI.
+const TOKENIZE_ENDPOINT: &str = "tokenize";
+const MODELS_ENDPOINT: &str = "v1/models";
+// Provided by llama.cpp, and asked instead when the server has no models endpoint.
+const HEALTH_ENDPOINT: &str = "health";
+
+// Statuses a server without an embeddings or tokenize endpoint answers with.
+const UNSUPPORTED_STATUS_CODES: &[i32] = &[404, 501];
+
+// Error code OpenAI answers an unknown model with, also with a 404.
+const MODEL_NOT_FOUND_CODE: &str = "model_not_found";
 
-pub struct OpenAIClient;
+// Statuses worth sending the request again for: rate limiting and server errors that may pass.
+const RETRY_STATUS_CODES: &[i32] = &[429, 500, 502, 503, 504];
+
+// What a server answered a request with: the body of a 200 response, or another status.
+pub(super) enum Reply {
+    Text(String),
+    Status {
+        status: i32,
+        reason: String,
+        body: String,
+    },
+}
+
+pub struct OpenAIClient {
+    base_url: String,
+    embeddings_base_url: String,
+    api_key: Option<String>,
+    #[cfg_attr(not(feature = "proxy"), allow(dead_code))]
+    proxy: ProxySettings,
+    timeout_seconds: u64,
+    retry_policy: RetryPolicy,
+    // Where responses are recorded to and replayed from, when LLM_CACHE_DIR is set.
+    recordings: Option<Recordings>,
+    replay_only: bool,
+    // Shared with every client in the process sending to the same server, so it is never sent more
+    // requests at once than MAX_CONCURRENT_REQUESTS.
+    permits: Arc<Semaphore>,
+    embeddings_permits: Arc<Semaphore>,
+    // Nanoseconds spent waiting for a permit, over the client's life.
+    request_wait: AtomicU64,
+}
 
 impl OpenAIClient {
-    fn post_json<T: miniserde::Deserialize>(
+    pub fn new(config: &Config) -> Self {
+        let embeddings_base_url = config
+            .embeddings_base_url
+            .clone()
+            .unwrap_or_else(|| config.llm_base_url.clone());
+
+        OpenAIClient {
+            base_url: config.llm_base_url.clone(),
+            permits: Semaphore::for_server(&config.llm_base_url, config.max_concurrent_requests),
+            embeddings_permits: Semaphore::for_server(
+                &embeddings_base_url,
+                config.max_concurrent_requests,
+            ),
+            request_wait: AtomicU64::new(0),
+            embeddings_base_url,
+            api_key: config.llm_api_key.clone(),
+            proxy: config.proxy.clone(),
+            timeout_seconds: config.llm_timeout_seconds,
+            retry_policy: config.retry_policy,
+            recordings: config.llm_cache_dir.as_ref().map(Recordings::new),
+            replay_only: config.llm_recording_mode == RecordingMode::ReplayOnly,
+        }
+    }
+
+    pub fn request_wait(&self) -> Duration {
+        Duration::from_nanos(self.request_wait.load(Ordering::Relaxed))
+    }
+
+    // Adds the API key and the proxy for the server to a request. The key is never sent unencrypted
+    // to another machine.
+    fn prepare(
+        &self,
+        mut request: Request,
+        base_url: &str,
+        error_variant: fn(BaseException) -> Exception,
+    ) -> Result<Request, Exception> {
+        if let Some(api_key) = &self.api_key {
+            if proxy::is_cleartext(base_url) {
+                return Err((error_variant)(BaseException::new(
+                    format!(
+                        "Refusing to send {} over plain HTTP to '{}'. Use an https:// URL.",
+                        constants::LLM_API_KEY_ENV,
+                        base_url
+                    ),
+                    None,
+                )));
+            }
+
+            request = request.with_header("Authorization", format!("Bearer {}", api_key));
+        }
+
+        #[cfg(feature = "proxy")]
+        if let Some(proxy) = self.proxy.for_url(base_url) {
+            let proxy = minreq::Proxy::new(proxy).map_err(|e| {
+                (error_variant)(BaseException::caused_by(
+                    format!("Invalid proxy for '{}'.", base_url),
+                    e,
+                ))
+            })?;
+            request = request.with_proxy(proxy);
+        }
+
+        Ok(request)
+    }
+
+    // Sends the request, retrying transport errors and RETRY_STATUS_CODES as the retry policy
+    // allows. Any other status is returned for the caller to handle.
+    fn send(
+        &self,
+        base_url: &str,
+        permits: &Semaphore,
         endpoint: &str,
         body: String,
         error_variant: fn(BaseException) -> Exception,
         context: &str,
-    ) -> Result<T, Exception> {
-        let url = format!("{}/{}", BASE_URL, endpoint);
-        let response = post(&url).with_body(body).send().map_err(|e| {
+    ) -> Result<Response, Exception> {
+        let url = format!("{}/{}", base_url, endpoint);
+        let attempts = self.retry_policy.attempts.max(1);
+        let mut attempt = 1;
+
+        loop {
+            let request = self.prepare(
+                post(&url)
+                    .with_body(body.as_str())
+                    .with_timeout(self.timeout_seconds),
+                base_url,
+                error_variant,
+            )?;
+
+            let result = {
+                let (_permit, wait) = permits.acquire();
+                let wait = u64::try_from(wait.as_nanos()).unwrap_or(u64::MAX);
+                self.request_wait.fetch_add(wait, Ordering::Relaxed);
+                request.send()
+            };
+
+            let failure = match result {
+                Ok(response) if !RETRY_STATUS_CODES.contains(&response.status_code) => {
+                    return Ok(response);
+                }
+                Ok(response) if attempt == attempts => {
+                    return Err((error_variant)(BaseException::new(
+                        format!(
+                            "{} request failed with status {} after {} attempt(s): {}",
+                            context, response.status_code, attempt, response.reason_phrase
+                        ),
+                        None,
+                    )));
+                }
+                Ok(response) => format!("status {}", response.status_code),
+                // Sending again cannot change the certificate the server presents.
+                Err(e) if e.to_string().contains("certificate") => {
+                    return Err((error_variant)(BaseException::caused_by(
+                        format!(
+                            "The TLS certificate of '{}' was not accepted. It must be issued by a \
+                             certificate authority in the Mozilla root store for the host name in \
+                             the URL.",
+                            base_url
+                        ),
+                        e,
+                    )));
+                }
+                Err(e) if attempt == attempts => {
+                    return Err((error_variant)(BaseException::caused_by(
+                        format!(
+                            "Failed to send {} request after {} attempt(s).",
+                            context, attempt
+                        ),
+                        e,
+                    )));
+                }
+                Err(e) => e.to_string(),
+            };
+
+            let delay = self.retry_delay(attempt);
+            eprintln!(
+                "Warning: {} request failed ({}). Retrying in {} ms (attempt {} of {}).",
+                context,
+                failure,
+                delay.as_millis(),
+                attempt + 1,
+                attempts
+            );
+            thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    // Base delay doubled for each attempt already made, plus up to the jitter. The clock's
+    // nanoseconds are random enough to keep clients from retrying in step.
+    fn retry_delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .retry_policy
+            .base_delay
+            .saturating_mul(1 << (attempt - 1).min(16));
+        let jitter_ms = self.retry_policy.jitter.as_millis() as u64;
+
+        if jitter_ms == 0 {
+            return backoff;
+        }
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos() as u64)
+            .unwrap_or(0);
+
+        backoff.saturating_add(Duration::from_millis(nanos % (jitter_ms + 1)))
+    }
+
+    // Returns the recorded response to this request. A request that has not been recorded is an
+    // error in replay only mode, so a run never reaches the network by accident.
+    fn recorded(
+        &self,
+        endpoint: &str,
+        body: &str,
+        error_variant: fn(BaseException) -> Exception,
+        context: &str,
+    ) -> Result<Option<String>, Exception> {
+        let Some(recordings) = &self.recordings else {
+            return Ok(None);
+        };
+
+        let path = recordings.path(endpoint, body);
+        let text = recordings.load(endpoint, body).map_err(|e| {
             (error_variant)(BaseException::caused_by(
-                format!("Failed to send {} request.", context),
+                format!(
+                    "Failed to read recorded {} response '{}'.",
+                    context,
+                    path.display()
+                ),
                 e,
             ))
         })?;
 
-        if response.status_code != 200 {
+        if text.is_none() && self.replay_only {
             return Err((error_variant)(BaseException::new(
                 format!(
-                    "{} request failed with status {}: {}",
-                    context, response.status_code, response.reason_phrase
+                    "No recorded {} response for this request ('{}' does not exist) and {} is \
+                     replay-only.",
+                    context,
+                    path.display(),
+                    constants::LLM_CACHE_MODE_ENV
                 ),
                 None,
             )));
         }
 
-        let text = response.as_str().map_err(|e| {
+        Ok(text)
+    }
+
+    // Records the response text when recording is on, then hands it back.
+    fn record(
+        &self,
+        endpoint: &str,
+        body: &str,
+        text: String,
+        error_variant: fn(BaseException) -> Exception,
+        context: &str,
+    ) -> Result<String, Exception> {
+        if let Some(recordings) = &self.recordings {
+            // A recording another writer holds the lock on is skipped, bypassing the cache.
+            recordings.store(endpoint, body, &text).map_err(|e| {
+                (error_variant)(BaseException::caused_by(
+                    format!(
+                        "Failed to record {} response to '{}'.",
+                        context,
+                        recordings.path(endpoint, body).display()
+                    ),
+                    e,
+                ))
+            })?;
+        }
+
+        Ok(text)
+    }
+
+    // The body the server answered a request with, replayed from or recorded to LLM_CACHE_DIR when
+    // that is set. Only 200 responses are recorded. Any other status is returned with the body, for
+    // the caller to turn into an error.
+    pub(super) fn exchange(
+        &self,
+        embeddings: bool,
+        endpoint: &str,
+        body: &str,
+        error_variant: fn(BaseException) -> Exception,
+        context: &str,
+    ) -> Result<Reply, Exception> {
+        if let Some(text) = self.recorded(endpoint, body, error_variant, context)? {
+            return Ok(Reply::Text(text));
+        }
+
+        let (base_url, permits) = if embeddings {
+            (&self.embeddings_base_url, &self.embeddings_permits)
+        } else {
+            (&self.base_url, &self.permits)
+        };
+        let response = self.send(
+            base_url,
+            permits,
+            endpoint,
+            body.to_string(),
+            error_variant,
+            context,
+        )?;
+        let status = response.status_code;
+        let reason = response.reason_phrase.clone();
+        let text = response.as_str().map(str::to_string).map_err(|e| {
             (error_variant)(BaseException::caused_by(
                 format!("Failed to read {} response.", context),
                 e,
             ))
-        })?;
+        });
+
+        if status != 200 {
+            return Ok(Reply::Status {
+                status,
+                reason,
+                body: text.unwrap_or_default(),
+            });
+        }
 
+        self.record(endpoint, body, text?, error_variant, context)
+            .map(Reply::Text)
+    }
+
+    // The error the server explained in the body, or the status line when the body is not an
+    // OpenAI error.
+    pub(super) fn status_error(
+        status: i32,
+        reason: &str,
+        body: &str,
+        error_variant: fn(BaseException) -> Exception,
+        context: &str,
+    ) -> Exception {
+        let reason = match from_str::<OpenAIErrorResponse>(body) {
+            Ok(response) => response.error.to_string(),
+            Err(_) => reason.to_string(),
+        };
+
+        (error_variant)(BaseException::new(
+            format!(
+                "{} request failed with status {}: {}",
+                context, status, reason
+            ),
+            None,
+        ))
+    }
+
+    fn model_not_found(body: &str) -> bool {
+        from_str::<OpenAIErrorResponse>(body)
+            .is_ok_and(|response| response.error.code().as_deref() == Some(MODEL_NOT_FOUND_CODE))
+    }
+
+    pub(super) fn parse<T: miniserde::Deserialize>(
+        text: &str,
+        error_variant: fn(BaseException) -> Exception,
+        context: &str,
+    ) -> Result<T, Exception> {
         from_str::<T>(text).map_err(|e| {
+            // Some servers and proxies send errors with a 200 status.
+            if let Ok(response) = from_str::<OpenAIErrorResponse>(text) {
+                return (error_variant)(BaseException::new(
+                    format!("{} request failed: {}", context, response.error),
+                    None,
+                ));
+            }
+
             (error_variant)(BaseException::caused_by(
                 format!("Failed to deserialise {} response: {}", context, text),
                 e,
@@ -60,24 +417,1087 @@ impl OpenAIClient {
     }
 
     pub fn chat_completion(
-        request: OpenAIChatCompletionRequest,
+        &self,
+        request: &OpenAIChatCompletionPayload,
     ) -> Result<OpenAIChatCompletionResponse, Exception> {
-        Self::post_json(
-            CHAT_COMPLETION_ENDPOINT,
-            json::to_string(&request),
-            Exception::OpenAIChatCompletion,
-            "chat",
-        )
+        let (variant, context) = (Exception::OpenAIChatCompletion, "chat");
+        let body = json::to_string(request);
+
+        match self.exchange(false, CHAT_COMPLETION_ENDPOINT, &body, variant, context)? {
+            Reply::Text(text) => Self::parse(&text, variant, context),
+            Reply::Status {
+                status,
+                reason,
+                body,
+            } => Err(Self::status_error(status, &reason, &body, variant, context)),
+        }
     }
 
+    // Returns None when the server does not provide an embeddings endpoint.
     pub fn embeddings(
-        request: OpenAIEmbeddingsRequest,
-    ) -> Result<OpenAIEmbeddingsResponse, Exception> {
-        Self::post_json(
-            EMBEDDINGS_ENDPOINT,
-            json::to_string(&request),
-            Exception::OpenAIEmbeddings,
-            "embedding",
+        &self,
+        request: &OpenAIEmbeddingsRequest,
+    ) -> Result<Option<OpenAIEmbeddingsResponse>, Exception> {
+        let (variant, context) = (Exception::OpenAIEmbeddings, "embedding");
+        let body = json::to_string(request);
+
+        match self.exchange(true, EMBEDDINGS_ENDPOINT, &body, variant, context)? {
+            Reply::Text(text) => Self::parse(&text, variant, context).map(Some),
+            Reply::Status { status, body, .. }
+                if UNSUPPORTED_STATUS_CODES.contains(&status) && !Self::model_not_found(&body) =>
+            {
+                Ok(None)
+            }
+            Reply::Status {
+                status,
+                reason,
+                body,
+            } => Err(Self::status_error(status, &reason, &body, variant, context)),
+        }
+    }
+
+    // Returns None when the server does not provide a tokenize endpoint.
+    pub fn tokenize(
+        &self,
+        request: &OpenAITokenizeRequest,
+    ) -> Result<Option<OpenAITokenizeResponse>, Exception> {
+        let (variant, context) = (Exception::OpenAITokenize, "tokenize");
+        let body = json::to_string(request);
+
+        match self.exchange(false, TOKENIZE_ENDPOINT, &body, variant, context)? {
+            Reply::Text(text) => Self::parse(&text, variant, context).map(Some),
+            Reply::Status { status, .. } if UNSUPPORTED_STATUS_CODES.contains(&status) => Ok(None),
+            Reply::Status {
+                status,
+                reason,
+                body,
+            } => Err(Self::status_error(status, &reason, &body, variant, context)),
+        }
+    }
+
+    // The server the requests go to, and the variable that sets it.
+    pub(super) fn server(&self, embeddings: bool) -> (&str, &'static str) {
+        if embeddings && self.embeddings_base_url != self.base_url {
+            (
+                &self.embeddings_base_url,
+                constants::EMBEDDINGS_BASE_URL_ENV,
+            )
+        } else {
+            (&self.base_url, constants::LLM_BASE_URL_ENV)
+        }
+    }
+
+    // Sends a GET to the server once, without retries, so a server that is down is reported at
+    // once. Any status is returned for the caller to judge.
+    pub(super) fn probe(&self, url: &str, variable: &str) -> Result<Response, Exception> {
+        let request = self.prepare(
+            get(url).with_timeout(
+                self.timeout_seconds
+                    .min(constants::HEALTHCHECK_TIMEOUT_SECONDS),
+            ),
+            url,
+            Exception::StartUp,
+        )?;
+
+        request.send().map_err(|e| {
+            Exception::StartUp(BaseException::caused_by(
+                format!(
+                    "Could not reach the model server at {}. Check that the server is running and that {} is right, or set {}=true to skip this check.",
+                    url,
+                    variable,
+                    constants::SKIP_HEALTHCHECK_ENV
+                ),
+                e,
+            ))
+        })
+    }
+
+    pub(super) fn unhealthy(url: &str, response: &Response) -> Exception {
+        let reason = match response.as_str().map(from_str::<OpenAIErrorResponse>) {
+            Ok(Ok(error)) => error.error.to_string(),
+            _ => response.reason_phrase.clone(),
+        };
+
+        Exception::StartUp(BaseException::new(
+            format!(
+                "The model server at {} answered with status {}: {}. Check the server, or set {}=true to skip this check.",
+                url,
+                response.status_code,
+                reason,
+                constants::SKIP_HEALTHCHECK_ENV
+            ),
+            None,
+        ))
+    }
+
+    pub(super) fn missing_model(
+        base_url: &str,
+        model: &str,
+        variable: &str,
+        models: &[String],
+    ) -> Exception {
+        Exception::StartUp(BaseException::new(
+            format!(
+                "The model server at {} does not have the model '{}' set by {}. It has: {}.",
+                base_url,
+                model,
+                variable,
+                if models.is_empty() {
+                    "no models".to_string()
+                } else {
+                    models.join(", ")
+                }
+            ),
+            None,
+        ))
+    }
+
+    // Checks that the server answers and lists each model given, before a program runs. A server
+    // with no models endpoint only has to answer /health. A llama.cpp server started with -m lists
+    // its one model but answers requests for any name, so a single model is not checked.
+    pub fn health_check(
+        &self,
+        text_model: Option<&str>,
+        embedding_model: Option<&str>,
+    ) -> Result<(), Exception> {
+        let checks = [
+            (false, text_model, constants::TEXT_MODEL_ENV),
+            (true, embedding_model, constants::EMBEDDING_MODEL_ENV),
+        ];
+
+        for (embeddings, model, model_variable) in checks {
+            let Some(model) = model else {
+                continue;
+            };
+
+            let (base_url, variable) = self.server(embeddings);
+            let url = format!("{}/{}", base_url, MODELS_ENDPOINT);
+            let response = self.probe(&url, variable)?;
+
+            if UNSUPPORTED_STATUS_CODES.contains(&response.status_code) {
+                let url = format!("{}/{}", base_url, HEALTH_ENDPOINT);
+                let response = self.probe(&url, variable)?;
+
+                if response.status_code != 200 {
+                    return Err(Self::unhealthy(&url, &response));
+                }
+
+                continue;
+            }
+
+            if response.status_code != 200 {
+                return Err(Self::unhealthy(&url, &response));
+            }
+
+            // A list that cannot be read still shows the server is up.
+            let Ok(Ok(list)) = response.as_str().map(from_str::<OpenAIModelList>) else {
+                continue;
+            };
+            let models = list
+                .data
+                .into_iter()
+                .map(|model| model.id)
+                .collect::<Vec<_>>();
+
+            if models.len() > 1 && !models.iter().any(|id| id == model) {
+                return Err(Self::missing_model(
+                    base_url,
+                    model,
+                    model_variable,
+                    &models,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::{
+            Mutex,
+            atomic::{AtomicUsize, Ordering},
+        },
+    };
+
+    use super::*;
+    use crate::{
+        Assembler, ChatTimings, Processor, TextModelOverrides, config::TruncatedResponsePolicy,
+    };
+
+    const CHAT_RESPONSE: &str = r#"{"model":"stub","choices":[{"index":0,"message":{"role":"assistant","content":"ok"},"finish_reason":"stop"}]}"#;
+
+    struct StubServer {
+        url: String,
+        // The most requests the server handled at once.
+        peak: Arc<AtomicUsize>,
+        // The body of every request, in the order they arrived.
+        bodies: Arc<Mutex<Vec<String>>>,
+        // The target of every CONNECT the server answered as a proxy.
+        #[cfg_attr(not(feature = "proxy"), allow(dead_code))]
+        connects: Arc<Mutex<Vec<String>>>,
+    }
+
+    // Answers every request with CHAT_RESPONSE after a pause, counting the requests it is handling at
+    // once. Asked to CONNECT, it acts as the proxy and then as the server at the other end.
+    fn counting_server() -> StubServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let connects = Arc::new(Mutex::new(Vec::new()));
+        let server = StubServer {
+            url,
+            peak: Arc::clone(&peak),
+            bodies: Arc::clone(&bodies),
+            connects: Arc::clone(&connects),
+        };
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { break };
+                let (in_flight, peak, bodies, connects) = (
+                    Arc::clone(&in_flight),
+                    Arc::clone(&peak),
+                    Arc::clone(&bodies),
+                    Arc::clone(&connects),
+                );
+
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut length = 0;
+
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+
+                        if let Some(target) = line.strip_prefix("CONNECT ") {
+                            let target = target.split_whitespace().next().unwrap_or_default();
+                            connects.lock().unwrap().push(target.to_string());
+                            // The blank line ending the CONNECT request.
+                            reader.read_line(&mut String::new()).unwrap();
+                            stream
+                                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                                .unwrap();
+                            continue;
+                        }
+
+                        if line.trim().is_empty() {
+                            break;
+                        }
+
+                        if let Some((name, value)) = line.split_once(':')
+                            && name.eq_ignore_ascii_case("content-length")
+                        {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+
+                    let mut body = vec![0; length];
+                    reader.read_exact(&mut body).unwrap();
+                    bodies
+                        .lock()
+                        .unwrap()
+                        .push(String::from_utf8(body).unwrap());
+
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(40));
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        CHAT_RESPONSE.len(),
+                        CHAT_RESPONSE
+                    )
+                    .unwrap();
+                });
+            }
+        });
+
+        server
+    }
+
+    #[test]
+    fn requests_in_flight_never_exceed_the_limit() {
+        let StubServer { url, peak, .. } = counting_server();
+        let source = "LS X1, \"Say ok.\"\nINF X2, X1, C1\nINF X3, X1, C1\nEXIT\n";
+        let byte_code = Assembler::new(source.to_string(), "parallel.aasm")
+            .assemble()
+            .unwrap();
+
+        let runs = (0..6)
+            .map(|_| {
+                let (url, byte_code) = (url.clone(), byte_code.clone());
+
+                thread::spawn(move || {
+                    let mut config = Config::new("stub", "stub");
+                    config.llm_base_url = url;
+                    config.max_concurrent_requests = 2;
+
+                    let mut processor = Processor::new(config);
+                    processor.load(&byte_code).unwrap();
+                    processor.run().unwrap();
+                    processor.stats()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let stats = runs
+            .into_iter()
+            .map(|run| run.join().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert!(stats.iter().all(|stats| stats.chat_completions == 2));
+        // Twelve requests of 40 ms through two permits cannot all be sent at once.
+        assert!(
+            stats
+                .iter()
+                .map(|stats| stats.request_wait)
+                .sum::<Duration>()
+                > Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn the_first_client_sets_a_servers_limit() {
+        let StubServer { url, peak, .. } = counting_server();
+        let mut config = Config::new("stub", "stub");
+        config.llm_base_url = url;
+
+        config.max_concurrent_requests = 1;
+        let first = OpenAIClient::new(&config);
+        config.max_concurrent_requests = 4;
+        let second = OpenAIClient::new(&config);
+
+        assert!(Arc::ptr_eq(&first.permits, &second.permits));
+
+        let threads = (0..4)
+            .map(|_| {
+                let client = OpenAIClient::new(&config);
+
+                thread::spawn(move || {
+                    client
+                        .exchange(
+                            false,
+                            CHAT_COMPLETION_ENDPOINT,
+                            "{}",
+                            Exception::OpenAIChatCompletion,
+                            "chat",
+                        )
+                        .unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(peak.load(Ordering::SeqCst), 1);
+    }
+
+    // The chat request bodies a one-instruction program sends with the given settings.
+    fn chat_bodies(overrides: TextModelOverrides) -> Vec<String> {
+        let server = counting_server();
+        let byte_code = Assembler::new(
+            "LS X1, \"Say ok.\"\nINF X2, X1, C1\nEXIT\n".to_string(),
+            "seed.aasm",
+        )
+        .assemble()
+        .unwrap();
+
+        let mut config = Config::new("stub", "stub");
+        config.llm_base_url = server.url.clone();
+        let seed = overrides.seed;
+        config.text_model_overrides = overrides;
+
+        let mut processor = Processor::new(config);
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+
+        assert_eq!(processor.stats().seed, seed);
+        server.bodies.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn the_seed_is_sent_exactly_when_configured() {
+        let seeded = chat_bodies(TextModelOverrides {
+            seed: Some(1234),
+            ..TextModelOverrides::default()
+        });
+        assert_eq!(seeded.len(), 1);
+        assert!(seeded[0].contains(r#""seed":1234"#), "{}", seeded[0]);
+
+        let unseeded = chat_bodies(TextModelOverrides::default());
+        assert_eq!(unseeded.len(), 1);
+        assert!(!unseeded[0].contains("seed"), "{}", unseeded[0]);
+    }
+
+    #[test]
+    fn stop_sequences_are_sent_only_when_configured() {
+        let stopped = chat_bodies(TextModelOverrides {
+            stop: Some(vec!["END".to_string(), "\n\n".to_string()]),
+            ..TextModelOverrides::default()
+        });
+        assert!(
+            stopped[0].contains(r#""stop":["END","\n\n"]"#),
+            "{}",
+            stopped[0]
+        );
+
+        let unstopped = chat_bodies(TextModelOverrides::default());
+        assert!(!unstopped[0].contains("stop"), "{}", unstopped[0]);
+    }
+
+    // Runs a program sending one chat request, without a health check or retries.
+    fn run_chat(config: Config) -> Result<(), Exception> {
+        let byte_code = Assembler::new(
+            "LS X1, \"Say ok.\"\nINF X2, X1, C1\nEXIT\n".to_string(),
+            "chat.aasm",
+        )
+        .assemble()
+        .unwrap();
+
+        let mut config = config;
+        config.skip_healthcheck = true;
+        config.retry_policy.attempts = 1;
+
+        let mut processor = Processor::new(config);
+        processor.load(&byte_code).unwrap();
+        processor.run()
+    }
+
+    #[cfg(feature = "proxy")]
+    #[test]
+    fn requests_go_through_the_proxy_unless_no_proxy_lists_the_host() {
+        let server = counting_server();
+        let mut config = Config::new("stub", "stub");
+        config.llm_base_url = "http://model.invalid:8080".to_string();
+        config.proxy = ProxySettings {
+            http: Some(server.url.clone()),
+            ..ProxySettings::default()
+        };
+
+        run_chat(config.clone()).unwrap();
+        assert_eq!(*server.connects.lock().unwrap(), ["model.invalid:8080"]);
+        assert_eq!(server.bodies.lock().unwrap().len(), 1);
+
+        // Sent directly, the request cannot reach a host that does not exist.
+        config.proxy.no_proxy = Some("invalid".to_string());
+        assert!(run_chat(config).is_err());
+        assert_eq!(server.connects.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn the_api_key_is_never_sent_in_clear() {
+        let server = counting_server();
+        let mut config = Config::new("stub", "stub");
+        config.llm_api_key = Some("secret".to_string());
+        config.proxy = ProxySettings::default();
+
+        config.llm_base_url = "http://model.invalid:8080".to_string();
+        let error = format!("{:?}", run_chat(config.clone()).unwrap_err());
+        assert!(error.contains("Refusing to send LLM_API_KEY"), "{}", error);
+
+        // Loopback traffic never leaves the machine.
+        config.llm_base_url = server.url.clone();
+        run_chat(config).unwrap();
+        assert_eq!(server.bodies.lock().unwrap().len(), 1);
+    }
+
+    // Shared with the tests of the other backends.
+    pub(in super::super) struct ScriptedServer {
+        pub url: String,
+        // The request line and headers of every request, in the order they arrived.
+        pub heads: Arc<Mutex<Vec<String>>>,
+        // The body of every request, in the same order.
+        pub bodies: Arc<Mutex<Vec<String>>>,
+    }
+
+    // Answers the requests with the given statuses and bodies in turn, repeating the last one.
+    pub(in super::super) fn scripted_server(
+        replies: &[(&'static str, &'static str)],
+    ) -> ScriptedServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let heads = Arc::new(Mutex::new(Vec::new()));
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let server = ScriptedServer {
+            url,
+            heads: Arc::clone(&heads),
+            bodies: Arc::clone(&bodies),
+        };
+        let replies = replies.to_vec();
+
+        thread::spawn(move || {
+            for (index, stream) in listener.incoming().enumerate() {
+                let Ok(mut stream) = stream else { break };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut head = String::new();
+                let mut length = 0;
+
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+
+                    if line.trim().is_empty() {
+                        break;
+                    }
+
+                    if let Some((name, value)) = line.split_once(':')
+                        && name.eq_ignore_ascii_case("content-length")
+                    {
+                        length = value.trim().parse().unwrap();
+                    }
+                    head.push_str(&line);
+                }
+
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                heads.lock().unwrap().push(head);
+                bodies
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf8(body).unwrap());
+
+                let (status, body) = replies[index.min(replies.len() - 1)];
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+
+        server
+    }
+
+    // Answers every request with the status and body given.
+    fn status_server(status: &'static str, body: &'static str) -> String {
+        scripted_server(&[(status, body)]).url
+    }
+
+    #[test]
+    fn a_server_without_an_embeddings_endpoint_answers_none() {
+        let request = OpenAIEmbeddingsRequest {
+            model: "stub".to_string(),
+            input: vec!["text".to_string()],
+            encoding_format: "float".to_string(),
+        };
+        let client = |url: String| {
+            let mut config = Config::new("stub", "stub");
+            config.llm_base_url = url;
+            config.retry_policy.attempts = 1;
+            OpenAIClient::new(&config)
+        };
+
+        for status in ["404 Not Found", "501 Not Implemented"] {
+            let url = status_server(status, "{}");
+            assert!(client(url).embeddings(&request).unwrap().is_none());
+        }
+
+        // A missing model is a mistake in the settings, not a missing endpoint.
+        let url = status_server(
+            "404 Not Found",
+            r#"{"error":{"code":"model_not_found","message":"The model 'stub' does not exist."}}"#,
+        );
+        assert!(client(url).embeddings(&request).is_err());
+    }
+
+    // The timings the server reported over a program sending two chat requests.
+    fn run_timings(url: String) -> Option<ChatTimings> {
+        let byte_code = Assembler::new(
+            "LS X1, \"Say ok.\"\nINF X2, X1, C1\nINF X3, X1, C1\nEXIT\n".to_string(),
+            "timings.aasm",
+        )
+        .assemble()
+        .unwrap();
+
+        let mut config = Config::new("stub", "stub");
+        config.llm_base_url = url;
+        config.skip_healthcheck = true;
+
+        let mut processor = Processor::new(config);
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+        processor.chat_timings()
+    }
+
+    #[test]
+    fn server_timings_are_summed_when_the_server_reports_them() {
+        let timed = status_server(
+            "200 OK",
+            r#"{"model":"stub","choices":[{"index":0,"message":{"role":"assistant","content":"ok"},"finish_reason":"stop"}],"timings":{"prompt_n":10,"prompt_ms":50.0,"predicted_n":20,"predicted_per_second":40.0}}"#,
+        );
+        let timings = run_timings(timed).unwrap();
+
+        assert_eq!((timings.prompt_tokens, timings.prompt_ms), (20, 100.0));
+        // The predicted time is worked out from the rate when the server leaves it out.
+        assert_eq!(
+            (timings.predicted_tokens, timings.predicted_ms),
+            (40, 1000.0)
+        );
+        assert_eq!(timings.tokens_per_second(), 40.0);
+
+        assert!(run_timings(counting_server().url).is_none());
+    }
+
+    #[test]
+    fn chat_and_embeddings_go_to_their_own_servers_with_the_key() {
+        let chat = scripted_server(&[("200 OK", CHAT_RESPONSE)]);
+        let embeddings = scripted_server(&[(
+            "200 OK",
+            r#"{"object":"list","data":[{"object":"embedding","embedding":[1.0,0.0],"index":0},{"object":"embedding","embedding":[1.0,0.0],"index":1}]}"#,
+        )]);
+        let byte_code = Assembler::new(
+            "LS X1, \"Say ok.\"\nINF X2, X1, C1\nSIM X3, X1, X2\nEXIT\n".to_string(),
+            "servers.aasm",
         )
+        .assemble()
+        .unwrap();
+
+        let mut config = Config::new("stub", "stub");
+        config.llm_base_url = chat.url.clone();
+        config.embeddings_base_url = Some(embeddings.url.clone());
+        config.llm_api_key = Some("secret".to_string());
+        config.skip_healthcheck = true;
+
+        let mut processor = Processor::new(config);
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+        assert_eq!(processor.register(3).unwrap().to_string(), "100");
+
+        let chat = chat.heads.lock().unwrap().clone();
+        let embeddings = embeddings.heads.lock().unwrap().clone();
+        assert_eq!(chat.len(), 1);
+        assert_eq!(embeddings.len(), 1);
+        assert!(
+            chat[0].starts_with("POST /v1/chat/completions "),
+            "{}",
+            chat[0]
+        );
+        assert!(
+            embeddings[0].starts_with("POST /v1/embeddings "),
+            "{}",
+            embeddings[0]
+        );
+
+        for head in [&chat[0], &embeddings[0]] {
+            assert!(
+                head.contains("Authorization: Bearer secret\r\n"),
+                "{}",
+                head
+            );
+        }
+
+        // Without a key, no Authorization header is sent.
+        let server = scripted_server(&[("200 OK", CHAT_RESPONSE)]);
+        let mut config = Config::new("stub", "stub");
+        config.llm_base_url = server.url.clone();
+        run_chat(config).unwrap();
+        assert!(!server.heads.lock().unwrap()[0].contains("Authorization"));
+    }
+
+    #[test]
+    fn failed_requests_are_retried_until_the_attempts_run_out() {
+        let retrying = |attempts: u32| {
+            let mut config = Config::new("stub", "stub");
+            config.retry_policy = RetryPolicy {
+                attempts,
+                base_delay: Duration::from_millis(1),
+                jitter: Duration::ZERO,
+            };
+            config
+        };
+        let flaky = [
+            ("503 Service Unavailable", "{}"),
+            ("429 Too Many Requests", "{}"),
+            ("200 OK", CHAT_RESPONSE),
+        ];
+
+        let server = scripted_server(&flaky);
+        let mut config = retrying(3);
+        config.llm_base_url = server.url.clone();
+        config.skip_healthcheck = true;
+        let byte_code = Assembler::new(
+            "LS X1, \"Say ok.\"\nINF X2, X1, C1\nEXIT\n".to_string(),
+            "retry.aasm",
+        )
+        .assemble()
+        .unwrap();
+
+        let mut processor = Processor::new(config);
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+        assert_eq!(processor.register(2).unwrap().to_string(), "ok");
+        assert_eq!(server.heads.lock().unwrap().len(), 3);
+
+        let server = scripted_server(&flaky);
+        let mut config = retrying(2);
+        config.llm_base_url = server.url.clone();
+        let client = OpenAIClient::new(&config);
+        let error = format!(
+            "{:?}",
+            client
+                .exchange(
+                    false,
+                    CHAT_COMPLETION_ENDPOINT,
+                    "{}",
+                    Exception::OpenAIChatCompletion,
+                    "chat"
+                )
+                .err()
+                .unwrap()
+        );
+        assert!(
+            error.contains("chat request failed with status 429 after 2 attempt(s)"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn a_server_that_never_answers_times_out() {
+        // Connections are queued by the operating system but never read.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut config = Config::new("stub", "stub");
+        config.llm_base_url = format!("http://{}", listener.local_addr().unwrap());
+        config.llm_timeout_seconds = 1;
+
+        let error = format!("{:?}", run_chat(config).unwrap_err());
+        assert!(
+            error.contains("Failed to send chat request after 1 attempt(s)."),
+            "{}",
+            error
+        );
+        drop(listener);
+    }
+
+    #[test]
+    fn identical_requests_are_answered_from_the_cache() {
+        // Two identical chat requests and two identical embeddings of the same pair of texts.
+        let run = |llm_cache: bool| {
+            let chat = scripted_server(&[("200 OK", CHAT_RESPONSE)]);
+            let embeddings = scripted_server(&[(
+                "200 OK",
+                r#"{"object":"list","data":[{"object":"embedding","embedding":[1.0,0.0],"index":0},{"object":"embedding","embedding":[1.0,0.0],"index":1}]}"#,
+            )]);
+            let byte_code = Assembler::new(
+                "LS X1, \"Say ok.\"\nINF X2, X1, C1\nINF X2, X1, C1\nSIM X3, X1, X2\nSIM X3, X1, X2\nEXIT\n"
+                    .to_string(),
+                "cache.aasm",
+            )
+            .assemble()
+            .unwrap();
+
+            let mut config = Config::new("stub", "stub");
+            config.llm_base_url = chat.url.clone();
+            config.embeddings_base_url = Some(embeddings.url.clone());
+            config.skip_healthcheck = true;
+            config.llm_cache = llm_cache;
+
+            let mut processor = Processor::new(config);
+            processor.load(&byte_code).unwrap();
+            processor.run().unwrap();
+            let stats = processor.stats();
+            let chat = chat.heads.lock().unwrap().len();
+            let embeddings = embeddings.heads.lock().unwrap().len();
+            (chat, embeddings, stats.cache_hits, stats.cache_misses)
+        };
+
+        assert_eq!(run(false), (2, 2, 0, 0));
+        // The second INF is one hit, and the second SIM finds both of its texts.
+        assert_eq!(run(true), (1, 1, 3, 3));
+    }
+
+    #[test]
+    fn error_bodies_report_the_servers_message() {
+        const INVALID_KEY: &str = r#"{"error":{"message":"Incorrect API key provided.","type":"invalid_request_error","code":"invalid_api_key"}}"#;
+        let expected = "Incorrect API key provided. (invalid_request_error, invalid_api_key)";
+
+        let mut config = Config::new("stub", "stub");
+        config.llm_base_url = status_server("401 Unauthorized", INVALID_KEY);
+        let error = format!("{:?}", run_chat(config).unwrap_err());
+        assert!(
+            error.contains(&format!(
+                "chat request failed with status 401: {}",
+                expected
+            )),
+            "{}",
+            error
+        );
+
+        // Some servers send the error with a 200 status.
+        let mut config = Config::new("stub", "stub");
+        config.llm_base_url = status_server("200 OK", INVALID_KEY);
+        let error = format!("{:?}", run_chat(config).unwrap_err());
+        assert!(
+            error.contains(&format!("chat request failed: {}", expected)),
+            "{}",
+            error
+        );
+        assert!(!error.contains("deserialise"), "{}", error);
+
+        let mut config = Config::new("stub", "stub");
+        config.llm_base_url = status_server("401 Unauthorized", INVALID_KEY);
+        config.retry_policy.attempts = 1;
+        let request = OpenAIEmbeddingsRequest {
+            model: "stub".to_string(),
+            input: vec!["text".to_string()],
+            encoding_format: "float".to_string(),
+        };
+        let error = format!(
+            "{:?}",
+            OpenAIClient::new(&config).embeddings(&request).unwrap_err()
+        );
+        assert!(error.contains(expected), "{}", error);
+    }
+
+    // Runs a program sending one chat request with a limit of 16 tokens against a server giving
+    // replies in turn. Returns the response or the error, and the limit sent with each request.
+    fn run_truncated(
+        policy: TruncatedResponsePolicy,
+        replies: &[(&'static str, &'static str)],
+    ) -> (Result<String, String>, Vec<String>) {
+        let server = scripted_server(replies);
+        let byte_code = Assembler::new(
+            "LS X1, \"Say ok.\"\nINF X2, X1, C1\nEXIT\n".to_string(),
+            "truncated.aasm",
+        )
+        .assemble()
+        .unwrap();
+
+        let mut config = Config::new("stub", "stub");
+        config.llm_base_url = server.url.clone();
+        config.skip_healthcheck = true;
+        config.on_truncated_response = policy;
+        config.text_model_overrides.max_tokens = Some(16);
+
+        let mut processor = Processor::new(config);
+        processor.load(&byte_code).unwrap();
+        let result = match processor.run() {
+            Ok(()) => Ok(processor.register(2).unwrap().to_string()),
+            Err(error) => Err(format!("{:?}", error)),
+        };
+        let limits = server
+            .bodies
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|body| {
+                let start = body.find(r#""max_tokens":"#).unwrap();
+                body[start..].split(',').next().unwrap().to_string()
+            })
+            .collect();
+
+        (result, limits)
+    }
+
+    #[test]
+    fn responses_cut_short_follow_the_truncated_response_policy() {
+        const CUT_SHORT: &str = r#"{"model":"stub","choices":[{"index":0,"message":{"role":"assistant","content":"cut"},"finish_reason":"length"}]}"#;
+        let cut_then_whole = [("200 OK", CUT_SHORT), ("200 OK", CHAT_RESPONSE)];
+
+        let (result, limits) = run_truncated(TruncatedResponsePolicy::Accept, &cut_then_whole);
+        assert_eq!(result.unwrap(), "cut");
+        assert_eq!(limits, [r#""max_tokens":16"#]);
+
+        let (result, limits) = run_truncated(TruncatedResponsePolicy::Retry, &cut_then_whole);
+        assert_eq!(result.unwrap(), "ok");
+        assert_eq!(limits, [r#""max_tokens":16"#, r#""max_tokens":32"#]);
+
+        let (result, limits) =
+            run_truncated(TruncatedResponsePolicy::Retry, &[("200 OK", CUT_SHORT)]);
+        let error = result.unwrap_err();
+        assert!(
+            error.contains("INF at 12 received a response cut short at the token limit twice."),
+            "{}",
+            error
+        );
+        assert_eq!(limits.len(), 2);
+
+        let (result, limits) = run_truncated(TruncatedResponsePolicy::Error, &cut_then_whole);
+        let error = result.unwrap_err();
+        assert!(
+            error.contains(
+                "INF at 12 received a response cut short at the token limit. Raise the limit with \
+                 TEXT_MODEL_MAX_TOKENS or SETMAXTOK."
+            ),
+            "{}",
+            error
+        );
+        assert_eq!(limits.len(), 1);
+    }
+
+    // The TOK count of a 38-character sentence against a server giving replies in turn.
+    fn run_tokenize(replies: &[(&'static str, &'static str)]) -> (Result<String, String>, String) {
+        let server = scripted_server(replies);
+        let byte_code = Assembler::new(
+            "LS X1, \"The quick brown fox jumps over the dog\"\nTOK X2, X1\nTOK X3, X1\nEXIT\n"
+                .to_string(),
+            "tok.aasm",
+        )
+        .assemble()
+        .unwrap();
+
+        let mut config = Config::new("stub", "stub");
+        config.llm_base_url = server.url.clone();
+        config.skip_healthcheck = true;
+        config.retry_policy.attempts = 1;
+        config.tokenize_remote = true;
+
+        let mut processor = Processor::new(config);
+        processor.load(&byte_code).unwrap();
+        let result = match processor.run() {
+            Ok(()) => Ok(format!(
+                "{} {}",
+                processor.register(2).unwrap(),
+                processor.register(3).unwrap()
+            )),
+            Err(error) => Err(format!("{:?}", error)),
+        };
+        let first = server.bodies.lock().unwrap().first().cloned();
+
+        (result, first.unwrap_or_default())
+    }
+
+    #[test]
+    fn remote_token_counts_fall_back_to_the_estimate_without_an_endpoint() {
+        let (result, body) = run_tokenize(&[("200 OK", r#"{"tokens":[1,2,3,4,5,6,7]}"#)]);
+        assert_eq!(result.unwrap(), "7 7");
+        assert!(body.contains(r#""add_special":false"#), "{}", body);
+
+        // The estimate is used from the first 404 on.
+        let (result, _) = run_tokenize(&[("404 Not Found", "{}"), ("200 OK", r#"{"tokens":[1]}"#)]);
+        assert_eq!(result.unwrap(), "10 10");
+
+        let (result, _) = run_tokenize(&[(
+            "400 Bad Request",
+            r#"{"error":{"message":"tokenizer broke"}}"#,
+        )]);
+        let error = result.unwrap_err();
+        assert!(
+            error.contains("tokenize request failed with status 400: tokenizer broke"),
+            "{}",
+            error
+        );
+    }
+
+    // Checks the model server for a program that prints before its chat request, then runs it as
+    // the run command does. Returns the result and what was printed.
+    fn run_checked(url: &str) -> (Result<(), String>, String) {
+        let byte_code = Assembler::new(
+            "LS X1, \"started\"\nPLN X1\nINF X2, X1, C1\nEXIT\n".to_string(),
+            "health.aasm",
+        )
+        .assemble()
+        .unwrap();
+
+        let mut config = Config::new("text", "embedding");
+        config.llm_base_url = url.to_string();
+        config.retry_policy.attempts = 1;
+        let stdout = crate::CapturedOutput::default();
+
+        let mut processor = Processor::new(config);
+        processor.set_output(
+            Box::new(stdout.clone()),
+            Box::new(crate::CapturedOutput::default()),
+        );
+        processor.load(&byte_code).unwrap();
+        let result = processor
+            .check_model_server(&Processor::verify(&byte_code).unwrap())
+            .and_then(|()| processor.run())
+            .map_err(|error| format!("{:?}", error));
+
+        (result, String::from_utf8(stdout.bytes()).unwrap())
+    }
+
+    fn targets(server: &ScriptedServer) -> Vec<String> {
+        let heads = server.heads.lock().unwrap();
+        heads
+            .iter()
+            .map(|head| head.split(' ').take(2).collect::<Vec<_>>().join(" "))
+            .collect()
+    }
+
+    #[test]
+    fn the_model_server_is_checked_before_the_first_instruction() {
+        // Nothing listens on the port once the listener is dropped.
+        let closed = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let (result, printed) = run_checked(&closed);
+        let error = result.unwrap_err();
+        assert!(
+            error.contains(&format!(
+                "Could not reach the model server at {}/v1/models.",
+                closed
+            )),
+            "{}",
+            error
+        );
+        assert!(error.contains("SKIP_HEALTHCHECK=true"), "{}", error);
+        assert_eq!(printed, "");
+
+        const MODELS: &str = r#"{"object":"list","data":[{"id":"other"},{"id":"another"}]}"#;
+        let server = scripted_server(&[("200 OK", MODELS)]);
+        let error = run_checked(&server.url).0.unwrap_err();
+        assert!(
+            error.contains(&format!(
+                "The model server at {} does not have the model 'text' set by TEXT_MODEL. It has: \
+                 other, another.",
+                server.url
+            )),
+            "{}",
+            error
+        );
+
+        // A llama.cpp server lists its one model but answers to any name.
+        let server = scripted_server(&[
+            ("200 OK", r#"{"object":"list","data":[{"id":"other"}]}"#),
+            ("200 OK", CHAT_RESPONSE),
+        ]);
+        let (result, printed) = run_checked(&server.url);
+        result.unwrap();
+        assert_eq!(printed, "started\n");
+        assert_eq!(
+            targets(&server),
+            ["GET /v1/models", "POST /v1/chat/completions"]
+        );
+
+        // A server without a models endpoint only has to answer /health.
+        let server = scripted_server(&[
+            ("404 Not Found", "{}"),
+            ("200 OK", r#"{"status":"ok"}"#),
+            ("200 OK", CHAT_RESPONSE),
+        ]);
+        run_checked(&server.url).0.unwrap();
+        assert_eq!(
+            targets(&server),
+            ["GET /v1/models", "GET /health", "POST /v1/chat/completions"]
+        );
+
+        let server = scripted_server(&[("503 Service Unavailable", "{}")]);
+        let error = run_checked(&server.url).0.unwrap_err();
+        assert!(
+            error.contains(&format!(
+                "The model server at {}/v1/models answered with status 503",
+                server.url
+            )),
+            "{}",
+            error
+        );
     }
 }
@@ -24,9 +24,12 @@ pub struct ModelTextConfig {
     pub dry_allowed_length: u32,
     pub dry_penalty_last_n: i32,
     pub timings_per_token: bool,
+    pub seed: Option<u32>,
+    // Text that ends generation when the model writes it. Not included in the response.
+    pub stop: Vec<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ModelEmbeddingsConfig {
     pub model: String,
     pub encoding_format: String,
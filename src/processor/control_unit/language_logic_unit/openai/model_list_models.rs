@@ -0,0 +1,12 @@
+use miniserde::{Deserialize, Serialize};
+
+// The /v1/models response. Only the model ids are read.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAIModelList {
+    pub data: Vec<OpenAIModel>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAIModel {
+    pub id: String,
+}
@@ -0,0 +1,66 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::atomic_file;
+
+// Response bodies recorded to a directory, one file per request. A file is named after the endpoint
+// and a hash of the request body, and holds the body the server answered with, byte for byte.
+pub struct Recordings {
+    dir: PathBuf,
+}
+
+impl Recordings {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Recordings {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn path(&self, endpoint: &str, body: &str) -> PathBuf {
+        self.dir.join(format!(
+            "{}-{:016x}.json",
+            endpoint.replace('/', "_"),
+            Self::hash(endpoint, body)
+        ))
+    }
+
+    // Returns None when the request has not been recorded.
+    pub fn load(&self, endpoint: &str, body: &str) -> io::Result<Option<String>> {
+        match fs::read_to_string(self.path(endpoint, body)) {
+            Ok(text) => Ok(Some(text)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Holds the recording's lock while writing, so processors sharing the directory take turns.
+    // Returns false without recording when another writer keeps the lock, as the response is still
+    // returned to the program and can be recorded by a later run.
+    pub fn store(&self, endpoint: &str, body: &str, response: &str) -> io::Result<bool> {
+        fs::create_dir_all(&self.dir)?;
+        atomic_file::write_locked(self.path(endpoint, body), response)
+    }
+
+    // 64 bit FNV-1a. File names have to stay the same across runs and Rust versions, which the
+    // standard library hashers do not promise.
+    fn hash(endpoint: &str, body: &str) -> u64 {
+        const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        endpoint
+            .bytes()
+            .chain(std::iter::once(0))
+            .chain(body.bytes())
+            .fold(OFFSET_BASIS, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(PRIME)
+            })
+    }
+}
+
+// Recordings hold only their directory; concurrent writers are kept apart by the file lock.
+const _: fn() = || {
+    fn assert_sync<T: Sync>() {}
+    assert_sync::<Recordings>();
+};
@@ -0,0 +1,81 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, LazyLock, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+struct State {
+    in_flight: usize,
+    limit: usize,
+}
+
+// One semaphore per server, shared by every client in the process that sends requests to it.
+static SERVERS: LazyLock<Mutex<HashMap<String, Arc<Semaphore>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Counting semaphore bounding the number of requests in flight to one server across every thread in
+// the process.
+pub struct Semaphore {
+    state: Mutex<State>,
+    available: Condvar,
+}
+
+pub struct Permit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Semaphore {
+    pub fn new(limit: usize) -> Self {
+        Semaphore {
+            state: Mutex::new(State {
+                in_flight: 0,
+                limit: limit.max(1),
+            }),
+            available: Condvar::new(),
+        }
+    }
+
+    // The semaphore for a server. The first client to send to a server sets its limit, so a processor
+    // created later cannot change the limit under processors already running against it.
+    pub fn for_server(base_url: &str, limit: usize) -> Arc<Semaphore> {
+        let mut servers = SERVERS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        Arc::clone(
+            servers
+                .entry(base_url.to_string())
+                .or_insert_with(|| Arc::new(Semaphore::new(limit))),
+        )
+    }
+
+    // A poisoned lock only means another thread panicked while holding it; the counters are still valid.
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Waits for a request to be allowed, returning the permit and how long it waited.
+    pub fn acquire(&self) -> (Permit<'_>, Duration) {
+        let start = Instant::now();
+        let mut state = self.lock();
+
+        while state.in_flight >= state.limit {
+            state = self
+                .available
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+
+        state.in_flight += 1;
+        (Permit { semaphore: self }, start.elapsed())
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.lock().in_flight -= 1;
+        self.semaphore.available.notify_one();
+    }
+}
@@ -0,0 +1,23 @@
+use miniserde::{Deserialize, Serialize};
+
+// The llama.cpp /tokenize request. Special tokens are left out, since a chat template adds them
+// once per request rather than once per text.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAITokenizeRequest {
+    pub content: String,
+    pub add_special: bool,
+}
+
+impl OpenAITokenizeRequest {
+    pub fn new(content: &str) -> Self {
+        Self {
+            content: content.to_string(),
+            add_special: false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAITokenizeResponse {
+    pub tokens: Vec<i64>,
+}
@@ -0,0 +1,101 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+struct Entries<T> {
+    entries: HashMap<String, (T, u64)>,
+    // Stamped on an entry each time it is used. The lowest stamp is the least recently used entry.
+    clock: u64,
+}
+
+// Responses from one endpoint keyed by the serialised request that produced them, so the key
+// covers the model, messages or input and every sampling parameter. Holds at most capacity entries
+// and evicts the least recently used one to make room. Guarded by a mutex, so processors in several
+// threads can share one cache.
+pub struct ResponseCache<T> {
+    entries: Mutex<Entries<T>>,
+    capacity: usize,
+}
+
+impl<T: Clone> ResponseCache<T> {
+    pub fn new(capacity: usize) -> Self {
+        ResponseCache {
+            entries: Mutex::new(Entries {
+                entries: HashMap::new(),
+                clock: 0,
+            }),
+            capacity: capacity.max(1),
+        }
+    }
+
+    // A poisoned lock only means another thread panicked while holding it; the entries are still
+    // valid.
+    fn lock(&self) -> MutexGuard<'_, Entries<T>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn get(&self, key: &str) -> Option<T> {
+        let mut entries = self.lock();
+        entries.clock += 1;
+        let clock = entries.clock;
+
+        entries.entries.get_mut(key).map(|(value, used)| {
+            *used = clock;
+            value.clone()
+        })
+    }
+
+    pub fn insert(&self, key: String, value: T) {
+        let mut entries = self.lock();
+
+        if entries.entries.len() >= self.capacity
+            && !entries.entries.contains_key(&key)
+            && let Some(oldest) = entries
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone())
+        {
+            entries.entries.remove(&oldest);
+        }
+
+        entries.clock += 1;
+        let clock = entries.clock;
+        entries.entries.insert(key, (value, clock));
+    }
+}
+
+// Fails to compile if a change ever stops processors in several threads from sharing a cache.
+const _: fn() = || {
+    fn assert_sync<T: Sync>() {}
+    assert_sync::<ResponseCache<String>>();
+    assert_sync::<ResponseCache<Vec<f32>>>();
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_least_recently_used_entry_is_evicted_when_full() {
+        let cache = ResponseCache::new(2);
+        cache.insert("a".to_string(), 1);
+        cache.insert("b".to_string(), 2);
+
+        // Using a makes b the least recently used entry.
+        assert_eq!(cache.get("a"), Some(1));
+        cache.insert("c".to_string(), 3);
+
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("c"), Some(3));
+
+        // Replacing an entry never evicts another.
+        cache.insert("c".to_string(), 4);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("c"), Some(4));
+    }
+}
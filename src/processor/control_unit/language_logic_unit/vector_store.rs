@@ -0,0 +1,440 @@
+use std::{fs::read_to_string, io::ErrorKind, mem};
+
+use miniserde::{Deserialize, Serialize, json};
+
+use crate::{
+    atomic_file,
+    exception::{BaseException, Exception},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VectorEntry {
+    pub key: String,
+    pub text: String,
+    pub vector: Vec<f32>,
+}
+
+// What the file holds. Every vector is made by one embedding model and has the same number of
+// dimensions, since vectors from different models or of different lengths cannot be compared.
+// Files saved before the dimensions were recorded take them from their first entry.
+#[derive(Default, Serialize, Deserialize)]
+struct Contents {
+    model: Option<String>,
+    dimensions: Option<usize>,
+    entries: Vec<VectorEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingText {
+    pub key: String,
+    pub text: String,
+}
+
+// The store as saved in a checkpoint, with the texts put since it was last embedded.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VectorStoreState {
+    pub model: Option<String>,
+    pub dimensions: Option<usize>,
+    pub entries: Vec<VectorEntry>,
+    pub pending: Vec<PendingText>,
+}
+
+// Texts VPUT stores for VQUERY to search, in the order their keys were first put. A key put again
+// replaces its text in place. Texts are only embedded when they are searched or the run ends, so
+// every text put in between is embedded by one request.
+pub struct VectorStore {
+    path: Option<String>,
+    // The file is read on first use, so runs that never use the store never read it.
+    loaded: bool,
+    contents: Contents,
+    // Keys and texts put since the store was last embedded, in the order they were put.
+    pending: Vec<(String, String)>,
+}
+
+impl VectorStore {
+    pub fn new(path: Option<String>) -> Self {
+        VectorStore {
+            path,
+            loaded: false,
+            contents: Default::default(),
+            pending: Vec::new(),
+        }
+    }
+
+    fn load(&mut self) -> Result<(), Exception> {
+        if self.loaded {
+            return Ok(());
+        }
+        self.loaded = true;
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let text = match read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => {
+                return Err(Exception::LanguageLogic(BaseException::caused_by(
+                    format!("Failed to read vector store '{}'.", path),
+                    e,
+                )));
+            }
+        };
+
+        let mut contents: Contents = json::from_str(&text).map_err(|e| {
+            Exception::LanguageLogic(BaseException::caused_by(
+                format!("Failed to parse vector store '{}'.", path),
+                e,
+            ))
+        })?;
+
+        let dimensions = contents
+            .dimensions
+            .or_else(|| contents.entries.first().map(|entry| entry.vector.len()));
+
+        if let Some(dimensions) = dimensions
+            && let Some(entry) = contents
+                .entries
+                .iter()
+                .find(|entry| entry.vector.len() != dimensions)
+        {
+            return Err(Exception::LanguageLogic(BaseException::new(
+                format!(
+                    "Vector store '{}' holds {}-dimensional embeddings, but the embedding for key '{}' has {} dimensions. Clear it with VCLR.",
+                    path,
+                    dimensions,
+                    entry.key,
+                    entry.vector.len()
+                ),
+                None,
+            )));
+        }
+
+        contents.dimensions = dimensions;
+        self.contents = contents;
+
+        Ok(())
+    }
+
+    // The model every stored and pending text is embedded with, or None while the store is empty.
+    pub fn model(&mut self) -> Result<Option<&str>, Exception> {
+        self.load()?;
+
+        Ok(self.contents.model.as_deref())
+    }
+
+    pub fn is_persistent(&self) -> bool {
+        self.path.is_some()
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn put(&mut self, key: &str, text: &str, model: &str) -> Result<(), Exception> {
+        if let Some(stored) = self.model()?
+            && stored != model
+        {
+            return Err(Exception::LanguageLogic(BaseException::new(
+                format!(
+                    "The vector store holds embeddings from model '{}', which cannot be compared with embeddings from model '{}'. Clear it with VCLR or select '{}' with SETEMB.",
+                    stored, model, stored
+                ),
+                None,
+            )));
+        }
+
+        self.contents.model = Some(model.to_string());
+        self.pending.retain(|(pending, _)| pending != key);
+        self.pending.push((key.to_string(), text.to_string()));
+
+        Ok(())
+    }
+
+    pub fn take_pending(&mut self) -> Vec<(String, String)> {
+        mem::take(&mut self.pending)
+    }
+
+    // Stores the embeddings of a batch of put texts, one vector for each text in order. The first
+    // embedding sets the dimensions of the store, and every later one must match them. Nothing is
+    // stored unless the whole batch is valid.
+    pub fn insert(
+        &mut self,
+        texts: Vec<(String, String)>,
+        vectors: Vec<Vec<f32>>,
+    ) -> Result<(), Exception> {
+        if vectors.len() != texts.len() {
+            return Err(Exception::LanguageLogic(BaseException::new(
+                format!(
+                    "Received {} embedding(s) for {} text(s) put in the vector store.",
+                    vectors.len(),
+                    texts.len()
+                ),
+                None,
+            )));
+        }
+
+        let dimensions = self
+            .contents
+            .dimensions
+            .or_else(|| vectors.first().map(Vec::len));
+
+        if let Some(dimensions) = dimensions
+            && let Some(((key, _), vector)) = texts
+                .iter()
+                .zip(&vectors)
+                .find(|(_, vector)| vector.len() != dimensions)
+        {
+            return Err(Exception::LanguageLogic(BaseException::new(
+                format!(
+                    "The embedding for key '{}' has {} dimensions, but the vector store holds {}-dimensional embeddings. Clear it with VCLR.",
+                    key,
+                    vector.len(),
+                    dimensions
+                ),
+                None,
+            )));
+        }
+
+        self.contents.dimensions = dimensions;
+
+        for ((key, text), vector) in texts.into_iter().zip(vectors) {
+            let entries = &mut self.contents.entries;
+
+            match entries.iter_mut().find(|entry| entry.key == key) {
+                Some(entry) => {
+                    entry.text = text;
+                    entry.vector = vector;
+                }
+                None => entries.push(VectorEntry { key, text, vector }),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn capture(&mut self) -> Result<VectorStoreState, Exception> {
+        self.load()?;
+
+        Ok(VectorStoreState {
+            model: self.contents.model.clone(),
+            dimensions: self.contents.dimensions,
+            entries: self.contents.entries.clone(),
+            pending: self
+                .pending
+                .iter()
+                .map(|(key, text)| PendingText {
+                    key: key.clone(),
+                    text: text.clone(),
+                })
+                .collect(),
+        })
+    }
+
+    // Replaces the store with a checkpoint's, without reading the file. The file is left as it is
+    // until the store next changes.
+    pub fn restore(&mut self, state: &VectorStoreState) {
+        self.loaded = true;
+        self.contents = Contents {
+            model: state.model.clone(),
+            dimensions: state.dimensions,
+            entries: state.entries.clone(),
+        };
+        self.pending = state
+            .pending
+            .iter()
+            .map(|pending| (pending.key.clone(), pending.text.clone()))
+            .collect();
+    }
+
+    pub fn entries(&self) -> &[VectorEntry] {
+        &self.contents.entries
+    }
+
+    // Empties the store and its file, without reading the file first.
+    pub fn clear(&mut self) -> Result<(), Exception> {
+        self.loaded = true;
+        self.contents = Default::default();
+        self.pending.clear();
+
+        self.save()
+    }
+
+    pub fn save(&self) -> Result<(), Exception> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let saved =
+            atomic_file::write_locked(path, json::to_string(&self.contents)).map_err(|e| {
+                Exception::LanguageLogic(BaseException::caused_by(
+                    format!("Failed to write vector store '{}'.", path),
+                    e,
+                ))
+            })?;
+
+        if !saved {
+            return Err(Exception::LanguageLogic(BaseException::new(
+                format!(
+                    "Failed to write vector store '{}': another writer holds its lock.",
+                    path
+                ),
+                None,
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs, process};
+
+    use super::*;
+    use crate::{Assembler, Config, Processor, ScriptedBackend};
+
+    fn run(source: &str, path: &str, embeddings: &[(&str, Vec<f32>)]) -> Result<(), Exception> {
+        let byte_code = Assembler::new(source.to_string(), "store.aasm")
+            .assemble()
+            .unwrap();
+
+        let mut backend = ScriptedBackend::new();
+        for (text, vector) in embeddings {
+            backend.set_embedding(*text, vector.clone());
+        }
+
+        let mut config = Config::new("text", "embedding");
+        config.vector_store_file = Some(path.to_string());
+
+        let mut processor = Processor::new(config);
+        processor.set_backend(Box::new(backend));
+        processor.load(&byte_code).unwrap();
+        processor.run()
+    }
+
+    #[test]
+    fn embeddings_of_another_length_are_refused() {
+        let path = temp_dir().join(format!("lpu-store-dimensions-{}.json", process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        run(
+            "LS X1, \"first\"\nVPUT \"a\", X1\nEXIT\n",
+            path,
+            &[("first", vec![1.0, 0.0])],
+        )
+        .unwrap();
+        assert!(
+            fs::read_to_string(path)
+                .unwrap()
+                .contains(r#""dimensions":2"#)
+        );
+
+        // The same model name now gives longer vectors, as after changing EMBEDDING_DIMENSIONS.
+        let error = run(
+            "LS X1, \"second\"\nVPUT \"b\", X1\nLS X2, \"question\"\nVQUERY X3, X2, 1\nEXIT\n",
+            path,
+            &[
+                ("second", vec![1.0, 0.0, 0.0]),
+                ("question", vec![1.0, 0.0, 0.0]),
+            ],
+        )
+        .unwrap_err();
+        let error = format!("{:?}", error);
+        assert!(
+            error.contains("The embedding for key 'b' has 3 dimensions, but the vector store holds 2-dimensional embeddings."),
+            "{}",
+            error
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn a_file_with_embeddings_of_different_lengths_is_refused() {
+        let path = temp_dir().join(format!("lpu-store-mixed-{}.json", process::id()));
+        let path = path.to_str().unwrap();
+        // Written before the dimensions were recorded, so the first entry sets them.
+        fs::write(
+            path,
+            r#"{"model":"embedding","entries":[{"key":"a","text":"first","vector":[1.0,0.0]},{"key":"b","text":"second","vector":[1.0,0.0,0.0]}]}"#,
+        )
+        .unwrap();
+
+        let mut store = VectorStore::new(Some(path.to_string()));
+        let error = format!("{:?}", store.model().unwrap_err());
+        fs::remove_file(path).unwrap();
+
+        assert!(
+            error.contains(
+                "holds 2-dimensional embeddings, but the embedding for key 'b' has 3 dimensions"
+            ),
+            "{}",
+            error
+        );
+    }
+
+    fn texts(keys: &[&str]) -> Vec<(String, String)> {
+        keys.iter()
+            .map(|key| (key.to_string(), format!("text {}", key)))
+            .collect()
+    }
+
+    #[test]
+    fn a_batch_with_fewer_vectors_than_texts_is_refused() {
+        let mut store = VectorStore::new(None);
+
+        let error = store
+            .insert(
+                texts(&["a", "b", "c"]),
+                vec![vec![1.0, 0.0], vec![0.0, 1.0]],
+            )
+            .unwrap_err();
+        assert!(
+            format!("{:?}", error)
+                .contains("Received 2 embedding(s) for 3 text(s) put in the vector store."),
+            "{:?}",
+            error
+        );
+        assert!(store.entries().is_empty());
+    }
+
+    #[test]
+    fn a_batch_with_a_shorter_vector_is_refused() {
+        let mut store = VectorStore::new(None);
+        store.insert(texts(&["a"]), vec![vec![1.0, 0.0]]).unwrap();
+
+        let error = store
+            .insert(texts(&["b", "c"]), vec![vec![0.0, 1.0], vec![1.0]])
+            .unwrap_err();
+        assert!(
+            format!("{:?}", error).contains(
+                "The embedding for key 'c' has 1 dimensions, but the vector store holds 2-dimensional embeddings."
+            ),
+            "{:?}",
+            error
+        );
+
+        // Nothing from the refused batch is stored.
+        let keys = store
+            .entries()
+            .iter()
+            .map(|entry| entry.key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["a"]);
+
+        // Within the first batch, the first vector sets the dimensions.
+        let mut empty = VectorStore::new(None);
+        assert!(
+            empty
+                .insert(
+                    texts(&["a", "b"]),
+                    vec![vec![1.0, 0.0, 0.0], vec![1.0, 0.0]]
+                )
+                .is_err()
+        );
+        assert!(empty.entries().is_empty());
+    }
+}
@@ -1,27 +1,65 @@
+use std::{collections::HashMap, sync::Arc};
+
 use crate::config::Config;
+use crate::constants::{
+    BYTE_CODE_MAGIC, BYTE_CODE_VERSION, MAX_DATA_SEGMENT_WORDS, MAX_TEXT_SEGMENT_WORDS,
+};
 use crate::exception::{BaseException, Exception};
+use crate::processor::control_unit::debug_info::{DebugInfo, SourceLocation};
 use crate::processor::control_unit::decoder::Decoder;
 use crate::processor::control_unit::executor::Executor;
-use crate::processor::{memory::Memory, registers::Registers};
+use crate::processor::control_unit::language_logic_unit::LanguageLogicUnit;
+use crate::processor::{
+    checkpoint::Checkpoint,
+    memory::Memory,
+    output::Output,
+    registers::{Registers, Value},
+    stats::{RunCounters, Stats, TokenUsage},
+    trace::{TraceHeader, TraceSink},
+};
 
 use crate::processor::control_unit::instruction::Instruction;
 
+mod chunker;
+mod debug_info;
 mod decoder;
+mod disassembler;
 mod executor;
-mod instruction;
+pub mod instruction;
 mod language_logic_unit;
 mod utils;
+mod verifier;
+
+pub use disassembler::Disassembler;
+pub use language_logic_unit::{
+    ChatTimings, LanguageLogicState, LlmBackend, ScriptedBackend, openai,
+};
+pub use verifier::{ProgramInfo, Verifier};
 
 pub struct ControlUnit {
     memory: Memory,
     registers: Registers,
+    language_logic_unit: LanguageLogicUnit,
+    debug_info: DebugInfo,
+    // Instructions decoded so far, by byte code index. Code does not change once loaded, so each is
+    // decoded on the first pass and reused on every later one. Cleared on load.
+    decoded: HashMap<usize, Arc<Instruction>>,
+    trace: Option<Box<dyn TraceSink>>,
+    output: Output,
+    stats: Stats,
 }
 
 impl ControlUnit {
-    pub fn new() -> Self {
+    pub fn new(config: &Config) -> Self {
         ControlUnit {
             memory: Memory::new(),
-            registers: Registers::new(),
+            registers: Registers::new(config.max_register_bytes, config.max_data_words),
+            language_logic_unit: LanguageLogicUnit::new(config),
+            debug_info: DebugInfo::default(),
+            decoded: HashMap::new(),
+            trace: None,
+            output: Output::default(),
+            stats: Stats::default(),
         }
     }
 
@@ -41,7 +79,7 @@ impl ControlUnit {
         Ok(buffer)
     }
 
-    fn header_pointer(&self, index: usize, byte_code: &[[u8; 4]]) -> Result<usize, Exception> {
+    fn header_pointer(index: usize, byte_code: &[[u8; 4]]) -> Result<usize, Exception> {
         let pointer_bytes = byte_code.get(index).ok_or_else(|| {
             Exception::ControlUnit(BaseException::new(
                 format!("Header pointer at index {} not found", index),
@@ -57,26 +95,123 @@ impl ControlUnit {
         })
     }
 
+    // Byte code from before the header had a magic word, or from another format version, cannot be
+    // decoded reliably, so it has to be rebuilt.
+    fn check_format(byte_code: &[[u8; 4]]) -> Result<(), Exception> {
+        let word = |index: usize| byte_code.get(index).map(|bytes| u32::from_be_bytes(*bytes));
+
+        if word(0) != Some(BYTE_CODE_MAGIC) {
+            return Err(Exception::ControlUnit(BaseException::new(
+                "Not LPU byte code, or byte code built by an older version without a header. Rebuild it with 'build'."
+                    .to_string(),
+                None,
+            )));
+        }
+
+        match word(1) {
+            Some(BYTE_CODE_VERSION) => Ok(()),
+            version => Err(Exception::ControlUnit(BaseException::new(
+                format!(
+                    "Unsupported byte code format version {}. Expected version {}. Rebuild it with 'build'.",
+                    version.map_or("(missing)".to_string(), |version| version.to_string()),
+                    BYTE_CODE_VERSION
+                ),
+                None,
+            ))),
+        }
+    }
+
     pub fn load(&mut self, byte_code: &[[u8; 4]]) -> Result<(), Exception> {
-        let instruction_section_pointer = self.header_pointer(0, byte_code).map_err(|e| {
+        self.debug_info = Self::load_into(&mut self.memory, &mut self.registers, byte_code)?;
+        self.decoded.clear();
+        Ok(())
+    }
+
+    // Checks the header against the byte code and points the registers at its sections. Debug info after
+    // the data segment is returned rather than loaded, so memory is the same with or without it.
+    fn load_into(
+        memory: &mut Memory,
+        registers: &mut Registers,
+        byte_code: &[[u8; 4]],
+    ) -> Result<DebugInfo, Exception> {
+        Self::check_format(byte_code)?;
+
+        let instruction_section_pointer = Self::header_pointer(2, byte_code).map_err(|e| {
             Exception::ControlUnit(BaseException::caused_by(
                 "Invalid instruction section pointer",
                 e,
             ))
         })?;
-        let data_section_pointer = self.header_pointer(1, byte_code).map_err(|e| {
+        let data_section_pointer = Self::header_pointer(3, byte_code).map_err(|e| {
             Exception::ControlUnit(BaseException::caused_by("Invalid data section pointer", e))
         })?;
+        let text_segment_size = Self::header_pointer(4, byte_code).map_err(|e| {
+            Exception::ControlUnit(BaseException::caused_by("Invalid text segment size", e))
+        })?;
+        let data_segment_size = Self::header_pointer(5, byte_code).map_err(|e| {
+            Exception::ControlUnit(BaseException::caused_by("Invalid data segment size", e))
+        })?;
 
-        self.memory.load(byte_code);
+        if text_segment_size > MAX_TEXT_SEGMENT_WORDS || data_segment_size > MAX_DATA_SEGMENT_WORDS
+        {
+            return Err(Exception::ControlUnit(BaseException::new(
+                format!(
+                    "Section sizes exceed the limits: text segment {} words (limit {}), data segment {} words (limit {}).",
+                    text_segment_size,
+                    MAX_TEXT_SEGMENT_WORDS,
+                    data_segment_size,
+                    MAX_DATA_SEGMENT_WORDS
+                ),
+                None,
+            )));
+        }
 
-        self.registers
-            .set_instruction_pointer(instruction_section_pointer);
-        self.registers.set_instruction(None);
-        self.registers
-            .set_data_section_pointer(data_section_pointer);
+        let data_section_end = data_section_pointer + data_segment_size;
 
-        Ok(())
+        if instruction_section_pointer + text_segment_size != data_section_pointer
+            || data_section_end > byte_code.len()
+        {
+            return Err(Exception::ControlUnit(BaseException::new(
+                format!(
+                    "Byte code header does not match its contents: text segment of {} words at {}, data segment of {} words at {}, but byte code is {} words long.",
+                    text_segment_size,
+                    instruction_section_pointer,
+                    data_segment_size,
+                    data_section_pointer,
+                    byte_code.len()
+                ),
+                None,
+            )));
+        }
+
+        let debug_info = if data_section_end == byte_code.len() {
+            DebugInfo::default()
+        } else {
+            DebugInfo::read(
+                &byte_code[data_section_end..],
+                &byte_code[data_section_pointer..data_section_end],
+            )?
+        };
+
+        memory.load(&byte_code[..data_section_end]);
+
+        registers.set_instruction_pointer(instruction_section_pointer);
+        registers.set_instruction(None);
+        registers.set_data_section_pointer(data_section_pointer);
+
+        Ok(debug_info)
+    }
+
+    // The instruction's source location when the byte code has debug info, otherwise its byte code index.
+    pub fn describe_address(&self, address: usize) -> String {
+        match self.debug_info.location(address) {
+            Some(location) => format!("{} (byte code index {})", location, address),
+            None => address.to_string(),
+        }
+    }
+
+    pub fn source_location(&self, address: usize) -> Option<&SourceLocation> {
+        self.debug_info.location(address)
     }
 
     pub fn fetch(&mut self) -> Result<bool, Exception> {
@@ -84,17 +219,36 @@ impl ControlUnit {
             return Ok(false);
         }
 
-        let instruction_bytes = self.read_instruction().map_err(|e| {
-            Exception::ControlUnit(BaseException::caused_by("Failed to fetch instruction", e))
-        })?;
+        // An instruction already decoded needs no words read for it.
+        let instruction_bytes = if self
+            .decoded
+            .contains_key(&self.registers.get_instruction_pointer())
+        {
+            None
+        } else {
+            Some(self.read_instruction().map_err(|e| {
+                Exception::ControlUnit(BaseException::caused_by("Failed to fetch instruction", e))
+            })?)
+        };
 
-        self.registers.set_instruction(Some(instruction_bytes));
+        self.registers.set_instruction(instruction_bytes);
         self.registers.advance_instruction_pointer(4);
 
         Ok(true)
     }
 
-    pub fn decode(&self) -> Result<Instruction, Exception> {
+    // The byte code index of the instruction last fetched, since fetch advances past it.
+    pub fn instruction_address(&self) -> usize {
+        self.registers.get_instruction_pointer().saturating_sub(4)
+    }
+
+    pub fn decode(&mut self) -> Result<Arc<Instruction>, Exception> {
+        let address = self.instruction_address();
+
+        if let Some(instruction) = self.decoded.get(&address) {
+            return Ok(Arc::clone(instruction));
+        }
+
         let bytes = self.registers.get_instruction().ok_or_else(|| {
             Exception::ControlUnit(BaseException::new(
                 "No instruction bytes to decode".to_string(),
@@ -102,24 +256,264 @@ impl ControlUnit {
             ))
         })?;
 
-        Decoder::decode(&self.memory, &self.registers, bytes).map_err(|e| {
-            Exception::ControlUnit(BaseException::caused_by("Failed to decode instruction", e))
-        })
+        let instruction = Decoder::decode(&self.memory, &self.registers, bytes).map_err(|e| {
+            Exception::ControlUnit(BaseException::caused_by(
+                format!(
+                    "Failed to decode instruction at {}",
+                    self.describe_address(address)
+                ),
+                e,
+            ))
+        })?;
+        let instruction = Arc::new(instruction);
+
+        self.decoded.insert(address, Arc::clone(&instruction));
+
+        Ok(instruction)
     }
 
-    pub fn execute(
-        &mut self,
-        instruction: Instruction,
-        config: &Config,
-    ) -> Result<(), Exception> {
+    pub fn execute(&mut self, instruction: &Instruction, config: &Config) -> Result<(), Exception> {
+        // Fetch has already advanced past the instruction being executed.
+        let address = self.registers.get_instruction_pointer().saturating_sub(4);
+
+        let description = self.describe_address(address);
+
         Executor::execute(
             &mut self.memory,
             &mut self.registers,
-            &instruction,
+            &mut self.language_logic_unit,
+            instruction,
             config,
+            &mut self.output,
+            self.trace.as_deref_mut(),
         )
         .map_err(|e| {
-            Exception::ControlUnit(BaseException::caused_by("Failed to execute instruction", e))
-        })
+            Exception::ControlUnit(BaseException::caused_by(
+                format!("Failed to execute '{}' at {}", instruction, description),
+                e,
+            ))
+        })?;
+
+        Executor::record_stats(&self.registers, instruction, &mut self.stats);
+        Ok(())
+    }
+
+    pub fn set_trace(&mut self, trace: Box<dyn TraceSink>) {
+        self.trace = Some(trace);
+    }
+
+    pub fn start_trace(&mut self, header: &TraceHeader) -> Result<(), Exception> {
+        match self.trace.as_deref_mut() {
+            Some(trace) => trace.start(header),
+            None => Ok(()),
+        }
+    }
+
+    pub fn set_backend(&mut self, backend: Box<dyn LlmBackend>) {
+        self.language_logic_unit.set_backend(backend);
+    }
+
+    pub fn set_output(&mut self, output: Output) {
+        self.output = output;
+    }
+
+    pub fn share_with(&mut self, other: &ControlUnit) {
+        self.language_logic_unit
+            .share_with(&other.language_logic_unit);
+    }
+
+    pub fn counters(&self) -> Arc<RunCounters> {
+        self.language_logic_unit.counters()
+    }
+
+    // Called before the program runs. Programs that send no model requests need no server.
+    pub fn check_model_server(&mut self, info: &ProgramInfo) -> Result<(), Exception> {
+        let text = info.uses_text_model();
+        let embeddings = info.uses_embedding_model(self.language_logic_unit.similarity_backend());
+
+        if !text && !embeddings {
+            return Ok(());
+        }
+
+        self.language_logic_unit.health_check(text, embeddings)
+    }
+
+    // Called once the program has finished running.
+    pub fn save_vector_store(&mut self) -> Result<(), Exception> {
+        self.language_logic_unit.save_vector_store()
+    }
+
+    pub fn restore_checkpoint(&mut self, directory: &str, name: &str) -> Result<(), Exception> {
+        let checkpoint = Checkpoint::load(directory, name)?;
+        checkpoint.restore(&self.memory, &mut self.registers)?;
+
+        if let Some(state) = &checkpoint.language_logic {
+            self.language_logic_unit.restore(state);
+        }
+
+        Ok(())
+    }
+
+    pub fn register(&self, register_number: u32) -> Result<&Value, Exception> {
+        self.registers.get_register(register_number)
+    }
+
+    pub fn context_length(&self, register_number: u32) -> Result<usize, Exception> {
+        Ok(self.registers.get_context(register_number)?.len())
+    }
+
+    pub fn exit_status(&self) -> i32 {
+        self.registers.exit_status()
+    }
+
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.stats.clone();
+        stats.largest_register = self.registers.largest_register();
+        self.language_logic_unit.add_stats(&mut stats);
+        stats
+    }
+
+    pub fn chat_timings(&self) -> Option<ChatTimings> {
+        self.language_logic_unit.run_timings()
+    }
+
+    pub fn token_usage(&self) -> Option<TokenUsage> {
+        self.language_logic_unit.run_usage()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Assembler;
+
+    // Nested loops, a forward jump over an instruction and two branches to the same label.
+    const BRANCHES: &str = "LI X1, 3\nLI X3, 0\nouter:\nLI X2, 2\ninner:\nADDI X3, 1\nSUBI X2, 1\nBGT X2, X0, inner\nBEQ X1, X0, done\nSUBI X1, 1\nBLT X0, X1, outer\nJMP done\nLI X4, 99\ndone:\nEXIT\n";
+
+    fn load(source: &str, config: &Config) -> ControlUnit {
+        let byte_code = Assembler::new(source.to_string(), "branches.aasm")
+            .assemble()
+            .unwrap();
+        let words = byte_code
+            .chunks(4)
+            .map(|chunk| chunk.try_into().unwrap())
+            .collect::<Vec<_>>();
+
+        let mut control_unit = ControlUnit::new(config);
+        control_unit.load(&words).unwrap();
+        control_unit
+    }
+
+    // Runs to the end, returning the address of every instruction executed. Without the cache, every
+    // instruction is decoded from its words each time it runs.
+    fn run(control_unit: &mut ControlUnit, config: &Config, cache: bool) -> Vec<usize> {
+        let mut addresses = Vec::new();
+
+        loop {
+            if !cache {
+                control_unit.decoded.clear();
+            }
+
+            if !control_unit.fetch().unwrap() {
+                break;
+            }
+
+            let instruction = control_unit.decode().unwrap();
+            addresses.push(control_unit.instruction_address());
+            control_unit.execute(&instruction, config).unwrap();
+        }
+
+        addresses
+    }
+
+    #[test]
+    fn branch_targets_decode_the_same_through_the_cache() {
+        let config = Config::new("text", "embedding");
+        let mut cached = load(BRANCHES, &config);
+        let mut uncached = load(BRANCHES, &config);
+
+        let addresses = run(&mut cached, &config, true);
+        assert_eq!(addresses, run(&mut uncached, &config, false));
+
+        assert_eq!(cached.register(3).unwrap().to_string(), "6");
+        assert!(matches!(cached.register(4).unwrap(), Value::None));
+
+        // Every instruction the branches reached is cached under its own address.
+        for (&address, instruction) in &cached.decoded {
+            let words = (0..4).map(|offset| *cached.memory.read(address + offset).unwrap());
+            let words = words.collect::<Vec<_>>().try_into().unwrap();
+            let fresh = Decoder::decode(&cached.memory, &cached.registers, words).unwrap();
+
+            assert_eq!(instruction.to_string(), fresh.to_string(), "at {}", address);
+
+            if let Some(target) = instruction.jump_target() {
+                assert!(cached.decoded.contains_key(&(target as usize)));
+            }
+        }
+
+        // LI X4 is jumped over, so only it is never decoded.
+        assert_eq!(cached.decoded.len(), 11);
+    }
+
+    #[test]
+    fn a_hot_loop_decodes_each_instruction_once() {
+        let config = Config::new("text", "embedding");
+        let source = "LI X1, 10000\nloop:\nLS X2, \"text\"\nSUBI X1, 1\nBGT X1, X0, loop\nEXIT\n";
+        let mut control_unit = load(source, &config);
+
+        let addresses = run(&mut control_unit, &config, true);
+
+        assert_eq!(addresses.len(), 30_002);
+        assert_eq!(control_unit.decoded.len(), 5);
+    }
+
+    #[test]
+    fn the_header_written_by_build_is_read_back_by_load() {
+        let byte_code = Assembler::new(BRANCHES.to_string(), "branches.aasm")
+            .assemble()
+            .unwrap();
+        let words = byte_code
+            .chunks(4)
+            .map(|chunk| chunk.try_into().unwrap())
+            .collect::<Vec<[u8; 4]>>();
+        let header = words[..6]
+            .iter()
+            .map(|word| u32::from_be_bytes(*word))
+            .collect::<Vec<_>>();
+
+        assert_eq!(&byte_code[..4], b"LPU\0");
+        assert_eq!(header[1], BYTE_CODE_VERSION);
+        // The data section follows the text segment, and the sizes cover the whole file.
+        assert_eq!(header[3], header[2] + header[4]);
+        assert_eq!(words.len(), (header[3] + header[5]) as usize);
+
+        let config = Config::new("text", "embedding");
+        let mut control_unit = ControlUnit::new(&config);
+        control_unit.load(&words).unwrap();
+        assert_eq!(
+            crate::assembler::source_reference(&byte_code),
+            Some((
+                "branches.aasm".to_string(),
+                crate::assembler::source_hash(BRANCHES)
+            ))
+        );
+
+        let mut wrong_version = words.clone();
+        wrong_version[1] = 1u32.to_be_bytes();
+        let headerless = words[2..].to_vec();
+        let mut wrong_magic = words.clone();
+        wrong_magic[0] = *b"ELF\0";
+
+        for (words, expected) in [
+            (
+                wrong_version,
+                "Unsupported byte code format version 1. Expected version",
+            ),
+            (headerless, "without a header. Rebuild it with 'build'."),
+            (wrong_magic, "Not LPU byte code"),
+        ] {
+            let error = format!("{:?}", ControlUnit::new(&config).load(&words).unwrap_err());
+            assert!(error.contains(expected), "{}", error);
+        }
     }
 }
@@ -1,8 +1,9 @@
+// Debug output always goes to stderr so that it never mixes with program output on stdout.
 #[macro_export]
 macro_rules! debug_print {
     ($debug:expr, $($arg:tt)*) => {
         if $debug {
-            println!($($arg)*);
+            eprintln!($($arg)*);
         }
     };
 }
@@ -0,0 +1,125 @@
+use crate::{
+    assembler::isa,
+    config::SimilarityBackend,
+    constants::{DEFAULT_MAX_DATA_WORDS, DEFAULT_MAX_REGISTER_BYTES},
+    exception::{BaseException, Exception},
+    processor::{
+        control_unit::{ControlUnit, decoder::Decoder},
+        memory::Memory,
+        registers::Registers,
+    },
+};
+
+// What a program contains, found without running it.
+pub struct ProgramInfo {
+    pub instruction_count: usize,
+    // Mnemonics of the instructions used, sorted and without repeats.
+    pub opcodes: Vec<&'static str>,
+    pub entry_point: usize,
+    pub strings: Vec<String>,
+}
+
+// Instructions that only send embedding requests, and those that send them instead of chat
+// requests when SIMILARITY_BACKEND is embeddings.
+const EMBEDDING_OPCODES: &[&str] = &["emb", "vput", "vquery"];
+const SIMILARITY_OPCODES: &[&str] = &["sim", "bsim", "eval"];
+
+impl ProgramInfo {
+    pub fn uses_text_model(&self) -> bool {
+        self.opcodes.iter().any(|mnemonic| {
+            !EMBEDDING_OPCODES.contains(mnemonic)
+                && isa::lookup(mnemonic).is_some_and(|info| info.uses_model)
+        })
+    }
+
+    pub fn uses_embedding_model(&self, similarity_backend: SimilarityBackend) -> bool {
+        self.opcodes.iter().any(|mnemonic| {
+            EMBEDDING_OPCODES.contains(mnemonic)
+                || (similarity_backend == SimilarityBackend::Embeddings
+                    && SIMILARITY_OPCODES.contains(mnemonic))
+        })
+    }
+}
+
+pub struct Verifier;
+
+impl Verifier {
+    // Checks the header, decodes every instruction and checks its registers and jump target. Only reads
+    // the given byte code, so it is cheap enough to run on every load.
+    pub fn verify(byte_code: &[[u8; 4]]) -> Result<ProgramInfo, Exception> {
+        let mut memory = Memory::new();
+        let mut registers = Registers::new(DEFAULT_MAX_REGISTER_BYTES, DEFAULT_MAX_DATA_WORDS);
+        ControlUnit::load_into(&mut memory, &mut registers, byte_code)?;
+
+        let entry_point = registers.get_instruction_pointer();
+        let end = registers.get_data_section_pointer();
+
+        if !(end - entry_point).is_multiple_of(4) {
+            return Err(Exception::ControlUnit(BaseException::new(
+                format!(
+                    "Text segment of {} words is not a whole number of instructions.",
+                    end - entry_point
+                ),
+                None,
+            )));
+        }
+
+        let mut info = ProgramInfo {
+            instruction_count: 0,
+            opcodes: Vec::new(),
+            entry_point,
+            strings: Vec::new(),
+        };
+
+        for address in (entry_point..end).step_by(4) {
+            let bytes = [
+                byte_code[address],
+                byte_code[address + 1],
+                byte_code[address + 2],
+                byte_code[address + 3],
+            ];
+
+            let instruction = Decoder::decode(&memory, &registers, bytes).map_err(|e| {
+                Exception::ControlUnit(BaseException::caused_by(
+                    format!("Failed to decode instruction at {}", address),
+                    e,
+                ))
+            })?;
+
+            for register in instruction.registers() {
+                Registers::to_index(register).map_err(|e| {
+                    Exception::ControlUnit(BaseException::caused_by(
+                        format!("Invalid register in instruction at {}", address),
+                        e,
+                    ))
+                })?;
+            }
+
+            // A jump to the end of the text segment ends the program, like running past the last instruction.
+            if let Some(target) = instruction.jump_target() {
+                let target = target as usize;
+
+                if target < entry_point || target > end || !(target - entry_point).is_multiple_of(4)
+                {
+                    return Err(Exception::ControlUnit(BaseException::new(
+                        format!(
+                            "Jump target {} of instruction at {} is not the start of an instruction.",
+                            target, address
+                        ),
+                        None,
+                    )));
+                }
+            }
+
+            info.instruction_count += 1;
+            info.opcodes.push(instruction.mnemonic());
+            info.strings
+                .extend(instruction.strings().into_iter().map(str::to_string));
+        }
+
+        info.opcodes.sort();
+        info.opcodes.dedup();
+
+        Ok(info)
+    }
+}
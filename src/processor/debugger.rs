@@ -0,0 +1,175 @@
+use std::{
+    collections::HashSet,
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    assembler::isa,
+    exception::{BaseException, Exception},
+    processor::control_unit::{ControlUnit, instruction::Instruction},
+};
+
+const PROMPT_HELP: &str = "Commands: c (continue), s (step), b <index> (add breakpoint), \
+d <index> (delete breakpoint), l (list breakpoints), p xN (print register), h <mnemonic> (describe \
+an instruction), q (quit).";
+
+// A byte code index as shown by 'disasm', either hexadecimal with a 0x prefix or decimal.
+pub fn parse_index(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+// Breakpoints and step mode for a run. The program runs at full speed until it reaches a breakpoint,
+// then reads commands from stdin until told to continue.
+pub struct Debugger {
+    breakpoints: HashSet<usize>,
+    stepping: bool,
+}
+
+impl Debugger {
+    pub fn new(breakpoints: &[usize]) -> Self {
+        Debugger {
+            breakpoints: breakpoints.iter().copied().collect(),
+            stepping: false,
+        }
+    }
+
+    // Breakpoints must be at the start of an instruction, or they would never be hit.
+    pub fn check(&self, entry_point: usize, instruction_count: usize) -> Result<(), Exception> {
+        let end = entry_point + instruction_count * 4;
+
+        let mut breakpoints = self.breakpoints.iter().copied().collect::<Vec<_>>();
+        breakpoints.sort_unstable();
+
+        for address in breakpoints {
+            if !(entry_point..end).contains(&address) || !(address - entry_point).is_multiple_of(4)
+            {
+                return Err(Exception::Processor(BaseException::new(
+                    format!(
+                        "Breakpoint 0x{:04X} is not the start of an instruction. Instructions are every 4 words from 0x{:04X} to 0x{:04X}.",
+                        address,
+                        entry_point,
+                        end.saturating_sub(4)
+                    ),
+                    None,
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Addresses are shown as 'disasm' shows them, with the source location when there is debug info.
+    fn describe(control_unit: &ControlUnit, address: usize) -> String {
+        match control_unit.source_location(address) {
+            Some(location) => format!("0x{:04X} ({})", address, location),
+            None => format!("0x{:04X}", address),
+        }
+    }
+
+    pub fn should_pause(&self, address: usize) -> bool {
+        self.stepping || self.breakpoints.contains(&address)
+    }
+
+    // Shows the instruction about to run and reads commands until one resumes the run.
+    // Returns false when the run should stop.
+    pub fn pause(
+        &mut self,
+        control_unit: &ControlUnit,
+        address: usize,
+        instruction: &Instruction,
+    ) -> Result<bool, Exception> {
+        let reason = if self.breakpoints.contains(&address) {
+            "Breakpoint"
+        } else {
+            "Step"
+        };
+
+        eprintln!(
+            "{} at {}: {}",
+            reason,
+            Self::describe(control_unit, address),
+            instruction
+        );
+
+        let stdin = io::stdin();
+
+        loop {
+            eprint!("(lpu) ");
+            io::stderr().flush().ok();
+
+            let mut line = String::new();
+
+            let read = stdin.lock().read_line(&mut line).map_err(|e| {
+                Exception::Processor(BaseException::caused_by(
+                    "Failed to read debugger command.",
+                    e,
+                ))
+            })?;
+
+            // Without input there is no one to step through the program, so run it to the end.
+            if read == 0 {
+                eprintln!();
+                self.stepping = false;
+                self.breakpoints.clear();
+                return Ok(true);
+            }
+
+            let mut words = line.split_whitespace();
+
+            match (words.next(), words.next()) {
+                (Some("c"), None) => {
+                    self.stepping = false;
+                    return Ok(true);
+                }
+                (Some("s"), None) => {
+                    self.stepping = true;
+                    return Ok(true);
+                }
+                (Some("q"), None) => return Ok(false),
+                (Some("l"), None) => {
+                    let mut breakpoints = self.breakpoints.iter().copied().collect::<Vec<_>>();
+                    breakpoints.sort_unstable();
+
+                    if breakpoints.is_empty() {
+                        eprintln!("No breakpoints.");
+                    }
+
+                    for breakpoint in breakpoints {
+                        eprintln!("  {}", Self::describe(control_unit, breakpoint));
+                    }
+                }
+                (Some(command @ ("b" | "d")), Some(index)) => match parse_index(index) {
+                    Some(breakpoint) if command == "b" => {
+                        self.breakpoints.insert(breakpoint);
+                    }
+                    Some(breakpoint) => {
+                        if !self.breakpoints.remove(&breakpoint) {
+                            eprintln!("No breakpoint at {}.", index);
+                        }
+                    }
+                    None => eprintln!("Invalid byte code index '{}'.", index),
+                },
+                (Some("p"), Some(register)) => {
+                    let value = register
+                        .strip_prefix(['x', 'X'])
+                        .and_then(|number| number.parse().ok())
+                        .and_then(|number| control_unit.register(number).ok());
+
+                    match value {
+                        Some(value) => eprintln!("{} = {}", register, value.preview()),
+                        None => eprintln!("Invalid register '{}'.", register),
+                    }
+                }
+                (Some("h" | "help"), Some(mnemonic)) => match isa::lookup(mnemonic) {
+                    Some(info) => eprintln!("{}", info.help()),
+                    None => eprintln!("Unknown instruction '{}'.", mnemonic),
+                },
+                (None, _) => {}
+                _ => eprintln!("{}", PROMPT_HELP),
+            }
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::assembler::hash_bytes;
 use crate::exception::{BaseException, Exception};
 
 pub struct Memory {
@@ -23,6 +24,21 @@ impl Memory {
         }
     }
 
+    // The words from the address to the end of memory.
+    pub fn read_from(&self, address: usize) -> Result<&[[u8; 4]], Exception> {
+        self.data.get(address..).ok_or_else(|| {
+            Exception::Memory(BaseException::new(
+                format!("Address out of bounds: {}", address),
+                None,
+            ))
+        })
+    }
+
+    // Identifies the loaded program, so that state saved by one program is never restored into another.
+    pub fn hash(&self) -> u32 {
+        hash_bytes(self.data.as_flattened())
+    }
+
     pub fn length(&self) -> usize {
         self.data.len()
     }
@@ -1,27 +1,55 @@
+use std::{io::Write, sync::Arc};
+
 use crate::{
     config::Config,
+    constants,
     exception::{BaseException, Exception},
-    processor::control_unit::ControlUnit,
+    processor::{
+        control_unit::{ControlUnit, Disassembler, Verifier},
+        debugger::Debugger,
+        output::Output,
+    },
+};
+
+pub use control_unit::{
+    ChatTimings, LlmBackend, ProgramInfo, ScriptedBackend, instruction, openai,
 };
+pub use debugger::parse_index;
+pub use output::CapturedOutput;
+pub use registers::Value;
+pub use replay::{ReplayState, TraceReplay};
+pub use stats::{RunCounters, Stats, TokenUsage};
+pub use trace::{ContextLength, JsonLinesTrace, TraceEvent, TraceHeader, TraceSink};
 
+mod checkpoint;
 mod control_unit;
+mod debugger;
 mod memory;
+mod output;
 mod registers;
+mod replay;
+mod stats;
+mod trace;
 
 pub struct Processor {
     config: Config,
     control_unit: ControlUnit,
+    debugger: Debugger,
+    // Instructions executed by the current run, checked against max_instructions.
+    executed: u64,
 }
 
 impl Processor {
     pub fn new(config: Config) -> Self {
         Processor {
+            control_unit: ControlUnit::new(&config),
+            debugger: Debugger::new(&config.breakpoints),
+            executed: 0,
             config,
-            control_unit: ControlUnit::new(),
         }
     }
 
-    pub fn load(&mut self, data: &[u8]) -> Result<(), Exception> {
+    fn words(data: &[u8]) -> Result<Vec<[u8; 4]>, Exception> {
         if !data.len().is_multiple_of(4) {
             return Err(Exception::Processor(BaseException::new(
                 format!(
@@ -32,8 +60,7 @@ impl Processor {
             )));
         }
 
-        let byte_code: Vec<[u8; 4]> = data
-            .chunks(4)
+        data.chunks(4)
             .map(|chunk| {
                 chunk.try_into().map_err(|e| {
                     Exception::Processor(BaseException::caused_by(
@@ -42,7 +69,25 @@ impl Processor {
                     ))
                 })
             })
-            .collect::<Result<_, _>>()?;
+            .collect()
+    }
+
+    // Checks that the byte code would load and decode, without creating a processor or any model clients.
+    pub fn verify(data: &[u8]) -> Result<ProgramInfo, Exception> {
+        let byte_code = Self::words(data)?;
+
+        Verifier::verify(&byte_code).map_err(|e| {
+            Exception::Processor(BaseException::caused_by("Failed to verify byte code.", e))
+        })
+    }
+
+    pub fn load(&mut self, data: &[u8]) -> Result<(), Exception> {
+        let info = Self::verify(data)?;
+
+        self.debugger
+            .check(info.entry_point, info.instruction_count)?;
+
+        let byte_code = Self::words(data)?;
 
         self.control_unit.load(&byte_code).map_err(|e| {
             Exception::Processor(BaseException::caused_by(
@@ -52,23 +97,148 @@ impl Processor {
         })
     }
 
+    // Assembly listing of the byte code. Needs no model configuration, so it does not create a processor.
+    pub fn disassemble(data: &[u8]) -> Result<Vec<String>, Exception> {
+        let byte_code = Self::words(data)?;
+
+        Disassembler::disassemble(&byte_code).map_err(|e| {
+            Exception::Processor(BaseException::caused_by(
+                "Failed to disassemble byte code.",
+                e,
+            ))
+        })
+    }
+
+    // Checks that the model server answers and has the models the program uses, so a server that is
+    // down is reported before any instruction runs rather than by the first that needs it.
+    pub fn check_model_server(&mut self, info: &ProgramInfo) -> Result<(), Exception> {
+        self.control_unit.check_model_server(info)
+    }
+
+    // Every instruction run from now on is recorded to the sink, after it has run.
+    pub fn set_trace(&mut self, trace: Box<dyn TraceSink>) {
+        self.control_unit.set_trace(trace);
+    }
+
+    // Chat and embedding requests go to this backend from now on instead of the model server.
+    pub fn set_backend(&mut self, backend: Box<dyn LlmBackend>) {
+        self.control_unit.set_backend(backend);
+    }
+
+    // PUT, PLN, PCX and the IN prompt write to stdout, and ERR to stderr, from now on instead of the
+    // process's standard streams.
+    pub fn set_output(&mut self, stdout: Box<dyn Write + Send>, stderr: Box<dyn Write + Send>) {
+        self.control_unit.set_output(Output { stdout, stderr });
+    }
+
+    // Counts model requests together with the other processor from now on, so Stats and MAX_LLM_CALLS
+    // cover the requests of both, and reuses its response cache when both have LLM_CACHE on. Each
+    // processor can then be moved to its own thread.
+    pub fn share_with(&mut self, other: &Processor) {
+        self.control_unit.share_with(&other.control_unit);
+    }
+
+    // The model request counters of the run, which another thread can read while it runs.
+    pub fn counters(&self) -> Arc<RunCounters> {
+        self.control_unit.counters()
+    }
+
+    // A register as the run left it, for hosts to read after a run.
+    pub fn register(&self, register_number: u32) -> Result<&Value, Exception> {
+        self.control_unit.register(register_number)
+    }
+
+    // Messages held by a context register.
+    pub fn context_length(&self, register_number: u32) -> Result<usize, Exception> {
+        self.control_unit.context_length(register_number)
+    }
+
+    pub fn restore_checkpoint(&mut self, directory: &str, name: &str) -> Result<(), Exception> {
+        self.control_unit
+            .restore_checkpoint(directory, name)
+            .map_err(|e| {
+                Exception::Processor(BaseException::caused_by(
+                    format!("Failed to resume from checkpoint '{}'.", name),
+                    e,
+                ))
+            })
+    }
+
+    // The status passed to EXIT, or 0 if the program ended without one.
+    pub fn exit_status(&self) -> i32 {
+        self.control_unit.exit_status()
+    }
+
+    // Instructions executed and model requests sent so far, for hosts to read after a run.
+    pub fn stats(&self) -> Stats {
+        self.control_unit.stats()
+    }
+
+    // Server-side timings summed over every chat request of the run, if the server reported any.
+    pub fn chat_timings(&self) -> Option<ChatTimings> {
+        self.control_unit.chat_timings()
+    }
+
+    // Tokens the server reported using over the run, for budgeting. None if no response reported
+    // usage.
+    pub fn token_usage(&self) -> Option<TokenUsage> {
+        self.control_unit.token_usage()
+    }
+
+    // The settings a trace of this processor's runs starts with.
+    pub fn trace_header(&self) -> TraceHeader {
+        TraceHeader {
+            lpu_version: env!("CARGO_PKG_VERSION").to_string(),
+            text_model: self.config.text_model.clone(),
+            embedding_model: self.config.embedding_model.clone(),
+            seed: self.config.text_model_overrides.seed,
+        }
+    }
+
     pub fn run(&mut self) -> Result<(), Exception> {
+        self.executed = 0;
+        self.control_unit.start_trace(&self.trace_header())?;
+
         loop {
             if !self.control_unit.fetch().map_err(|e| {
                 Exception::Processor(BaseException::caused_by("Failed to fetch instruction.", e))
             })? {
-                return Ok(());
+                break;
             }
 
             let instruction = self.control_unit.decode().map_err(|e| {
                 Exception::Processor(BaseException::caused_by("Failed to decode instruction.", e))
             })?;
 
+            let address = self.control_unit.instruction_address();
+
+            if let Some(limit) = self.config.max_instructions
+                && self.executed >= limit
+            {
+                return Err(Exception::Processor(BaseException::new(
+                    format!(
+                        "Stopped before the instruction at {} after executing {} instructions ({} = {}).",
+                        self.control_unit.describe_address(address),
+                        self.executed,
+                        constants::MAX_INSTRUCTIONS_ENV,
+                        limit
+                    ),
+                    None,
+                )));
+            }
+
+            self.executed += 1;
+
+            if self.debugger.should_pause(address)
+                && !self
+                    .debugger
+                    .pause(&self.control_unit, address, &instruction)?
+            {
+                break;
+            }
+
             self.control_unit
-                .execute(
-                    instruction,
-                    &self.config,
-                )
+                .execute(&instruction, &self.config)
                 .map_err(|e| {
                     Exception::Processor(BaseException::caused_by(
                         "Failed to execute instruction.",
@@ -76,5 +246,158 @@ impl Processor {
                     ))
                 })?;
         }
+
+        self.control_unit.save_vector_store().map_err(|e| {
+            Exception::Processor(BaseException::caused_by(
+                "Failed to save the vector store.",
+                e,
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Assembler, ScriptedBackend};
+
+    const LOOP: &str = "LS X1, \"again\"\nloop:\nINF X2, X1, C1\nJMP loop\n";
+
+    fn assemble(source: &str) -> Vec<u8> {
+        Assembler::new(source.to_string(), "test.aasm")
+            .assemble()
+            .unwrap()
+    }
+
+    fn load_error(byte_code: &[u8]) -> String {
+        let mut processor = Processor::new(Config::new("text", "embedding"));
+        format!("{:?}", processor.load(byte_code).unwrap_err())
+    }
+
+    #[test]
+    fn malformed_byte_code_is_an_error() {
+        let byte_code = assemble("LI X1, 1\nEXIT\n");
+
+        let error = load_error(&byte_code[..byte_code.len() - 1]);
+        assert!(error.contains("Must be a multiple of 4 bytes"), "{}", error);
+
+        let error = load_error(&byte_code[..byte_code.len() - 4]);
+        assert!(error.contains("does not match its contents"), "{}", error);
+
+        let error = load_error(&[0; 32]);
+        assert!(error.contains("Not LPU byte code"), "{}", error);
+
+        let mut version = byte_code.clone();
+        version[7] = 99;
+        let error = load_error(&version);
+        assert!(
+            error.contains("Unsupported byte code format version 99"),
+            "{}",
+            error
+        );
+
+        // The first word of the first instruction holds its opcode.
+        let mut opcode = byte_code.clone();
+        opcode[32..36].copy_from_slice(&u32::MAX.to_be_bytes());
+        let error = load_error(&opcode);
+        assert!(error.contains("Failed to decode instruction"), "{}", error);
+    }
+
+    #[test]
+    fn verify_describes_a_program_without_running_it() {
+        let byte_code = assemble("LS X1, \"hi\"\nLI X2, 3\nJMP end\nINF X3, X1, C1\nend:\nEXIT\n");
+        let info = Processor::verify(&byte_code).unwrap();
+
+        assert_eq!(info.instruction_count, 5);
+        assert_eq!(info.opcodes, ["exit", "inf", "jmp", "li", "ls"]);
+        assert_eq!(info.entry_point, 8);
+        assert!(info.strings.contains(&"hi".to_string()));
+
+        let verify_error =
+            |byte_code: &[u8]| format!("{:?}", Processor::verify(byte_code).err().unwrap());
+
+        // The second word of the first instruction holds its register.
+        let mut register = byte_code.clone();
+        register[36..40].copy_from_slice(&99u32.to_be_bytes());
+        let error = verify_error(&register);
+        assert!(error.contains("Invalid register"), "{}", error);
+
+        // Every operand word of the JMP points into the header.
+        let mut target = byte_code.clone();
+        (68..80)
+            .step_by(4)
+            .for_each(|at| target[at..at + 4].copy_from_slice(&3u32.to_be_bytes()));
+        let error = verify_error(&target);
+        assert!(
+            error.contains("is not the start of an instruction"),
+            "{}",
+            error
+        );
+
+        let error = verify_error(&[0; 32]);
+        assert!(error.contains("Not LPU byte code"), "{}", error);
+    }
+
+    #[test]
+    fn a_large_program_verifies_quickly() {
+        let source = (0..20_000)
+            .map(|index| {
+                format!(
+                    "LI X1, 3\nloop_{0}:\nADDI X2, 1\nBLT X2, X1, loop_{0}\n",
+                    index
+                )
+            })
+            .collect::<String>();
+        let byte_code = assemble(&format!("{}EXIT\n", source));
+
+        let start = std::time::Instant::now();
+        let info = Processor::verify(&byte_code).unwrap();
+
+        assert_eq!(info.instruction_count, 60_001);
+        assert!(
+            start.elapsed() < std::time::Duration::from_secs(2),
+            "{:?}",
+            start.elapsed()
+        );
+    }
+
+    #[test]
+    fn a_tight_loop_stops_at_max_instructions() {
+        let mut config = Config::new("text", "embedding");
+        config.max_instructions = Some(100);
+
+        let mut processor = Processor::new(config);
+        processor.load(&assemble("loop:\nJMP loop\n")).unwrap();
+
+        let error = format!("{:?}", processor.run().unwrap_err());
+        assert!(
+            error.contains("after executing 100 instructions"),
+            "{}",
+            error
+        );
+        assert!(error.contains("MAX_INSTRUCTIONS = 100"), "{}", error);
+    }
+
+    #[test]
+    fn a_tight_loop_stops_at_max_llm_calls() {
+        let mut backend = ScriptedBackend::new();
+        (0..10).for_each(|_| backend.push_response("ok"));
+        let prompts = backend.prompts();
+
+        let mut config = Config::new("text", "embedding");
+        config.max_llm_calls = Some(3);
+
+        let mut processor = Processor::new(config);
+        processor.set_backend(Box::new(backend));
+        processor.load(&assemble(LOOP)).unwrap();
+
+        let error = format!("{:?}", processor.run().unwrap_err());
+        assert!(
+            error.contains("already sent 3 (MAX_LLM_CALLS = 3)"),
+            "{}",
+            error
+        );
+        assert_eq!(prompts.lock().unwrap().len(), 3);
+        assert_eq!(processor.counters().requests(), 3);
     }
 }
@@ -0,0 +1,51 @@
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+// Where PUT, PLN, PCX and the IN prompt write, and where ERR writes. Standard output and standard
+// error unless the host captures them with Processor::set_output. Debug output always goes to
+// standard error.
+pub struct Output {
+    pub(crate) stdout: Box<dyn Write + Send>,
+    pub(crate) stderr: Box<dyn Write + Send>,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Output {
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+        }
+    }
+}
+
+// A stream kept in memory, for hosts and tests to read what a program printed. Clones share the
+// same bytes, so one clone can be given to the processor and another read after the run.
+#[derive(Clone, Default)]
+pub struct CapturedOutput {
+    bytes: Arc<Mutex<Vec<u8>>>,
+}
+
+impl CapturedOutput {
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+}
+
+impl Write for CapturedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -2,20 +2,61 @@ use std::fmt;
 
 use miniserde::{Deserialize, Serialize};
 
-use crate::exception::{BaseException, Exception};
+use crate::{
+    assembler::roles,
+    exception::{BaseException, Exception},
+};
 
 #[derive(Debug, Clone)]
 pub enum Value {
     Text(String),
-    Number(u32),
+    Number(i32),
+    Float(f32),
+    // An embedding made by EMB, so SIM can reuse it without another request.
+    Vector(Vec<f32>),
     None,
 }
 
+const PREVIEW_CHARS: usize = 60;
+
+// Quoted, escaped, and truncated form of text for traces and error messages.
+pub fn preview_text(text: &str) -> String {
+    let mut preview = text
+        .chars()
+        .take(PREVIEW_CHARS)
+        .collect::<String>()
+        .escape_debug()
+        .to_string();
+
+    if text.chars().count() > PREVIEW_CHARS {
+        preview.push_str("...");
+    }
+
+    format!("\"{}\"", preview)
+}
+
+impl Value {
+    // Display is the raw content used for program output; preview is for traces and error messages.
+    pub fn preview(&self) -> String {
+        match self {
+            Value::Text(text) => preview_text(text),
+            Value::Number(number) => number.to_string(),
+            Value::Float(number) => format!("{:?}", number),
+            Value::Vector(vector) => format!("<vector of {} dimensions>", vector.len()),
+            Value::None => "<none>".to_string(),
+        }
+    }
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Value::Text(text) => write!(formatter, "{}", text),
             Value::Number(number) => write!(formatter, "{}", number),
+            // Debug formatting keeps the fractional part, so 2.0 prints as "2.0" rather than "2".
+            Value::Float(number) => write!(formatter, "{:?}", number),
+            // Thousands of components are no use in program output, so only the size is shown.
+            Value::Vector(vector) => write!(formatter, "<vector of {} dimensions>", vector.len()),
             Value::None => write!(formatter, ""),
         }
     }
@@ -39,23 +80,44 @@ impl ContextMessage {
 pub struct Registers {
     general_purpose: [Value; 33],
     context: [Vec<ContextMessage>; 33],
+    // Values saved with SPUSH, last in first out.
+    stack: Vec<Value>,
+    // Words stored with SW. Only grows as far as the highest address written, up to max_data_words.
+    data: Vec<Value>,
+    max_data_words: usize,
     instruction_pointer: usize,
     instruction: Option<[[u8; 4]; 4]>,
     data_section_pointer: usize,
+    max_register_bytes: usize,
+    largest_register: Option<(u32, usize)>,
+    // Set by EXIT, for the host to return to the operating system.
+    exit_status: i32,
 }
 
+// x0 always reads as zero, so comparisons against zero need no register of their own.
+const ZERO_REGISTER: Value = Value::Number(0);
+
 impl Registers {
-    pub fn new() -> Self {
+    pub fn new(max_register_bytes: usize, max_data_words: usize) -> Self {
+        let mut general_purpose = [const { Value::None }; 33];
+        general_purpose[0] = ZERO_REGISTER;
+
         Registers {
-            general_purpose: [const { Value::None }; 33],
+            general_purpose,
             context: [const { Vec::new() }; 33],
+            stack: Vec::new(),
+            data: Vec::new(),
+            max_data_words,
             instruction_pointer: 0,
             instruction: None,
             data_section_pointer: 0,
+            max_register_bytes,
+            largest_register: None,
+            exit_status: 0,
         }
     }
 
-    fn to_index(register_number: u32) -> Result<usize, Exception> {
+    pub fn to_index(register_number: u32) -> Result<usize, Exception> {
         let idx = usize::try_from(register_number).map_err(|_| {
             Exception::Register(BaseException::new(
                 format!("Invalid register number: {}", register_number),
@@ -81,15 +143,95 @@ impl Registers {
     pub fn set_register(&mut self, register_number: u32, value: &Value) -> Result<(), Exception> {
         let idx = Self::to_index(register_number)?;
 
+        // Writes to x0 are discarded.
         if idx == 0 {
+            return Ok(());
+        }
+
+        if let Value::Text(text) = value {
+            if text.len() > self.max_register_bytes {
+                return Err(Exception::Register(BaseException::new(
+                    format!(
+                        "Value of {} bytes for register r{} exceeds the register size limit of {} bytes.",
+                        text.len(),
+                        register_number,
+                        self.max_register_bytes
+                    ),
+                    None,
+                )));
+            }
+
+            if self
+                .largest_register
+                .is_none_or(|(_, largest)| text.len() > largest)
+            {
+                self.largest_register = Some((register_number, text.len()));
+            }
+        }
+
+        self.general_purpose[idx] = value.clone();
+        Ok(())
+    }
+
+    // Register number and size in bytes of the largest text value written during the run.
+    pub fn largest_register(&self) -> Option<(u32, usize)> {
+        self.largest_register
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.general_purpose
+    }
+
+    pub fn contexts(&self) -> &[Vec<ContextMessage>] {
+        &self.context
+    }
+
+    pub fn stack(&self) -> &[Value] {
+        &self.stack
+    }
+
+    pub fn data(&self) -> &[Value] {
+        &self.data
+    }
+
+    // Replaces every register and the data stack with saved values, such as those in a checkpoint.
+    pub fn restore(
+        &mut self,
+        values: Vec<Value>,
+        contexts: Vec<Vec<ContextMessage>>,
+        stack: Vec<Value>,
+        data: Vec<Value>,
+    ) -> Result<(), Exception> {
+        let count = (values.len(), contexts.len());
+
+        if data.len() > self.max_data_words {
             return Err(Exception::Register(BaseException::new(
-                "Cannot write to register 0 (reserved for none value).".to_string(),
+                format!(
+                    "Saved data memory has {} words, more than MAX_DATA_WORDS allows ({}).",
+                    data.len(),
+                    self.max_data_words
+                ),
                 None,
             )));
         }
 
-        self.general_purpose[idx] = value.clone();
-        Ok(())
+        match (values.try_into(), contexts.try_into()) {
+            (Ok(values), Ok(contexts)) => {
+                self.general_purpose = values;
+                self.general_purpose[0] = ZERO_REGISTER;
+                self.context = contexts;
+                self.stack = stack;
+                self.data = data;
+                Ok(())
+            }
+            _ => Err(Exception::Register(BaseException::new(
+                format!(
+                    "Expected 33 general-purpose and 33 context registers, found {} and {}.",
+                    count.0, count.1
+                ),
+                None,
+            ))),
+        }
     }
 
     pub fn get_context(&self, register_number: u32) -> Result<&[ContextMessage], Exception> {
@@ -151,6 +293,129 @@ impl Registers {
         })
     }
 
+    pub fn peek_context(&self, register_number: u32) -> Result<&ContextMessage, Exception> {
+        let idx = Self::to_index(register_number)?;
+
+        self.context[idx].last().ok_or_else(|| {
+            Exception::Register(BaseException::new(
+                format!("Context stack for register {} is empty.", register_number),
+                None,
+            ))
+        })
+    }
+
+    // The index counts from the oldest message. One past the most recent message appends.
+    pub fn insert_context(
+        &mut self,
+        message: ContextMessage,
+        register_number: u32,
+        index: usize,
+    ) -> Result<(), Exception> {
+        let idx = Self::to_index(register_number)?;
+
+        if idx == 0 {
+            return Err(Exception::Register(BaseException::new(
+                "Cannot write to context register 0 (reserved for empty value).".to_string(),
+                None,
+            )));
+        }
+
+        let context = &mut self.context[idx];
+
+        if index > context.len() {
+            return Err(Exception::Register(BaseException::new(
+                format!(
+                    "Message index {} is out of range for context register {}, which has {} messages (0 to {}).",
+                    index,
+                    register_number,
+                    context.len(),
+                    context.len()
+                ),
+                None,
+            )));
+        }
+
+        context.insert(index, message);
+        Ok(())
+    }
+
+    // Drops the oldest messages of a context register until at most keep remain, besides a first
+    // system message, which is always kept. Returns how many were dropped.
+    pub fn trim_context(&mut self, register_number: u32, keep: usize) -> Result<usize, Exception> {
+        let idx = Self::to_index(register_number)?;
+
+        if idx == 0 {
+            return Err(Exception::Register(BaseException::new(
+                "Cannot write to context register 0 (reserved for empty value).".to_string(),
+                None,
+            )));
+        }
+
+        let context = &mut self.context[idx];
+        let start = usize::from(
+            context
+                .first()
+                .is_some_and(|message| message.role == roles::SYSTEM_ROLE),
+        );
+        let dropped = context.len().saturating_sub(start + keep);
+        context.drain(start..start + dropped);
+
+        Ok(dropped)
+    }
+
+    pub fn push_stack(&mut self, value: &Value) {
+        self.stack.push(value.clone());
+    }
+
+    pub fn pop_stack(&mut self) -> Result<Value, Exception> {
+        self.stack.pop().ok_or_else(Self::empty_stack)
+    }
+
+    pub fn peek_stack(&self) -> Result<&Value, Exception> {
+        self.stack.last().ok_or_else(Self::empty_stack)
+    }
+
+    fn empty_stack() -> Exception {
+        Exception::Register(BaseException::new(
+            "Data stack is empty. Every SPOP and SPEEK needs an earlier SPUSH.".to_string(),
+            None,
+        ))
+    }
+
+    // Addresses are checked against the limit rather than the words written so far, so that any address
+    // below MAX_DATA_WORDS can be loaded. Words never stored read as none, like unused registers.
+    fn data_index(&self, address: i32) -> Result<usize, Exception> {
+        usize::try_from(address)
+            .ok()
+            .filter(|&index| index < self.max_data_words)
+            .ok_or_else(|| {
+                Exception::Register(BaseException::new(
+                    format!(
+                        "Data memory address {} is out of range (0 to {}, set by MAX_DATA_WORDS).",
+                        address,
+                        self.max_data_words.saturating_sub(1)
+                    ),
+                    None,
+                ))
+            })
+    }
+
+    pub fn load_word(&self, address: i32) -> Result<&Value, Exception> {
+        let index = self.data_index(address)?;
+        Ok(self.data.get(index).unwrap_or(&Value::None))
+    }
+
+    pub fn store_word(&mut self, address: i32, value: &Value) -> Result<(), Exception> {
+        let index = self.data_index(address)?;
+
+        if index >= self.data.len() {
+            self.data.resize(index + 1, Value::None);
+        }
+
+        self.data[index] = value.clone();
+        Ok(())
+    }
+
     pub fn get_instruction_pointer(&self) -> usize {
         self.instruction_pointer
     }
@@ -159,6 +424,14 @@ impl Registers {
         self.instruction_pointer = address;
     }
 
+    pub fn exit_status(&self) -> i32 {
+        self.exit_status
+    }
+
+    pub fn set_exit_status(&mut self, status: i32) {
+        self.exit_status = status;
+    }
+
     pub fn advance_instruction_pointer(&mut self, offset: usize) {
         self.instruction_pointer += offset;
     }
@@ -179,3 +452,69 @@ impl Registers {
         self.data_section_pointer = address;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_addresses_outside_memory_are_errors() {
+        let mut registers = Registers::new(1024, 4);
+
+        registers.store_word(3, &Value::Number(7)).unwrap();
+        assert!(matches!(registers.load_word(3).unwrap(), Value::Number(7)));
+        // Words below the one stored were never written.
+        assert!(matches!(registers.load_word(0).unwrap(), Value::None));
+        assert_eq!(registers.data().len(), 4);
+
+        for address in [-1, 4, i32::MAX] {
+            let error = format!(
+                "{:?}",
+                registers.store_word(address, &Value::None).unwrap_err()
+            );
+            assert!(error.contains("is out of range (0 to 3"), "{}", error);
+            assert!(registers.load_word(address).is_err());
+        }
+
+        assert_eq!(registers.data().len(), 4);
+    }
+
+    #[test]
+    fn values_display_raw_and_preview_quoted() {
+        let long = "x".repeat(PREVIEW_CHARS + 1);
+        let values = [
+            Value::Text("say \"hi\"\n".to_string()),
+            Value::Text(long.clone()),
+            Value::Number(-7),
+            Value::Float(2.0),
+            Value::Vector(vec![0.5; 3]),
+            Value::None,
+        ];
+
+        let displayed = values.iter().map(Value::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            displayed,
+            [
+                "say \"hi\"\n",
+                long.as_str(),
+                "-7",
+                "2.0",
+                "<vector of 3 dimensions>",
+                ""
+            ]
+        );
+
+        let previews = values.iter().map(Value::preview).collect::<Vec<_>>();
+        assert_eq!(
+            previews,
+            [
+                "\"say \\\"hi\\\"\\n\"".to_string(),
+                format!("\"{}...\"", &long[..PREVIEW_CHARS]),
+                "-7".to_string(),
+                "2.0".to_string(),
+                "<vector of 3 dimensions>".to_string(),
+                "<none>".to_string()
+            ]
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use std::{
+    collections::BTreeMap,
+    fs::read_to_string,
+    io::{self, BufRead, Write},
+};
+
+use miniserde::json;
+
+use crate::{
+    exception::{BaseException, Exception},
+    processor::{
+        debugger::parse_index,
+        trace::{TraceEvent, TraceHeader},
+    },
+};
+
+const PROMPT_HELP: &str = "Commands: s (step), j <step> (jump to step), p xN (print register), \
+p cN (print context length), r (print every register), q (quit).";
+
+// What the registers held before a step of a recorded run. Only registers the run has written so far
+// are present, as previews like those in the trace.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayState {
+    pub registers: BTreeMap<u32, String>,
+    // Messages held by each context register.
+    pub contexts: BTreeMap<u32, usize>,
+}
+
+// A run read back from its JSON lines trace. State at any step is rebuilt from the values the trace
+// recorded, so nothing is executed and no model requests are sent.
+pub struct TraceReplay {
+    header: Option<TraceHeader>,
+    events: Vec<TraceEvent>,
+}
+
+impl TraceReplay {
+    pub fn new(events: Vec<TraceEvent>) -> Self {
+        TraceReplay {
+            header: None,
+            events,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, Exception> {
+        let text = read_to_string(path).map_err(|e| {
+            Exception::Processor(BaseException::caused_by(
+                format!("Failed to read trace file '{}'.", path),
+                e,
+            ))
+        })?;
+
+        let mut replay = TraceReplay::new(Vec::new());
+
+        for (index, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match json::from_str::<TraceEvent>(line) {
+                Ok(event) => replay.events.push(event),
+                // Traces written before headers were added start with an event.
+                Err(e) => match json::from_str::<TraceHeader>(line) {
+                    Ok(header) => {
+                        replay.header.get_or_insert(header);
+                    }
+                    Err(_) => {
+                        return Err(Exception::Processor(BaseException::caused_by(
+                            format!("Invalid trace event on line {} of '{}'.", index + 1, path),
+                            e,
+                        )));
+                    }
+                },
+            }
+        }
+
+        Ok(replay)
+    }
+
+    // The settings the run was recorded with, if the trace starts with them.
+    pub fn header(&self) -> Option<&TraceHeader> {
+        self.header.as_ref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn event(&self, step: usize) -> Option<&TraceEvent> {
+        self.events.get(step)
+    }
+
+    // The state before the given step ran, that is after the steps before it. A step past the end gives
+    // the state the run finished with.
+    pub fn state_at(&self, step: usize) -> ReplayState {
+        let mut state = ReplayState::default();
+
+        for event in self.events.iter().take(step) {
+            Self::apply(&mut state, event);
+        }
+
+        state
+    }
+
+    fn apply(state: &mut ReplayState, event: &TraceEvent) {
+        if let (Some(register), Some(value)) = (event.destination, &event.value) {
+            state.registers.insert(register, value.clone());
+        }
+
+        for context in &event.contexts {
+            state.contexts.insert(context.register, context.messages);
+        }
+    }
+
+    fn show(&self, step: usize, listing: &BTreeMap<usize, String>) {
+        let Some(event) = self.event(step) else {
+            eprintln!("End of trace after {} steps.", self.len());
+            return;
+        };
+
+        let instruction = listing
+            .get(&event.instruction_pointer)
+            .cloned()
+            .unwrap_or_else(|| format!("0x{:04X}  {}", event.instruction_pointer, event.mnemonic));
+
+        eprintln!("Step {} of {}: {}", step, self.len(), instruction);
+    }
+
+    // Reads commands from stdin to move through the trace and print state. The listing is the program's
+    // disassembly, used to show each step's instruction in full.
+    pub fn explore(&self, listing: &[String]) -> Result<(), Exception> {
+        let listing = listing
+            .iter()
+            .filter_map(|line| {
+                let index = parse_index(line.split_whitespace().next()?)?;
+                Some((index, line.clone()))
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let stdin = io::stdin();
+        let mut step = 0;
+        let mut state = ReplayState::default();
+
+        if let Some(header) = &self.header {
+            let seed = header
+                .seed
+                .map_or_else(|| "none".to_string(), |seed| seed.to_string());
+
+            eprintln!(
+                "Recorded by lpu {} with text model {}, embedding model {} and seed {}.",
+                header.lpu_version, header.text_model, header.embedding_model, seed
+            );
+        }
+
+        eprintln!("{}", PROMPT_HELP);
+        self.show(step, &listing);
+
+        loop {
+            eprint!("(lpu) ");
+            io::stderr().flush().ok();
+
+            let mut line = String::new();
+
+            let read = stdin.lock().read_line(&mut line).map_err(|e| {
+                Exception::Processor(BaseException::caused_by(
+                    "Failed to read debugger command.",
+                    e,
+                ))
+            })?;
+
+            if read == 0 {
+                eprintln!();
+                return Ok(());
+            }
+
+            let mut words = line.split_whitespace();
+
+            match (words.next(), words.next()) {
+                (Some("s"), None) => {
+                    if let Some(event) = self.event(step) {
+                        Self::apply(&mut state, event);
+                        step += 1;
+                    }
+
+                    self.show(step, &listing);
+                }
+                (Some("j"), Some(target)) => match target.parse::<usize>() {
+                    Ok(target) if target <= self.len() => {
+                        step = target;
+                        state = self.state_at(step);
+                        self.show(step, &listing);
+                    }
+                    _ => eprintln!(
+                        "Invalid step '{}'. Expected a number from 0 to {}.",
+                        target,
+                        self.len()
+                    ),
+                },
+                (Some("p"), Some(register)) => {
+                    let number = register
+                        .get(1..)
+                        .and_then(|number| number.parse::<u32>().ok());
+
+                    match (register.chars().next(), number) {
+                        (Some('x' | 'X'), Some(number)) => match state.registers.get(&number) {
+                            Some(value) => eprintln!("{} = {}", register, value),
+                            None => eprintln!("{} has not been written yet.", register),
+                        },
+                        (Some('c' | 'C'), Some(number)) => eprintln!(
+                            "{} = {} messages",
+                            register,
+                            state.contexts.get(&number).copied().unwrap_or(0)
+                        ),
+                        _ => eprintln!("Invalid register '{}'.", register),
+                    }
+                }
+                (Some("r"), None) => {
+                    if state.registers.is_empty() {
+                        eprintln!("No registers written yet.");
+                    }
+
+                    for (register, value) in &state.registers {
+                        eprintln!("  x{} = {}", register, value);
+                    }
+
+                    for (register, messages) in &state.contexts {
+                        eprintln!("  c{} = {} messages", register, messages);
+                    }
+                }
+                (Some("q"), None) => return Ok(()),
+                (None, _) => {}
+                _ => eprintln!("{}", PROMPT_HELP),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::{Assembler, Config, JsonLinesTrace, Processor, Value};
+
+    const PROGRAM: &str = "LI X1, 3\nLS X2, \"start\"\nLS X4, \"hello\"\nloop:\nPSH C1, X4, \"user\"\nSUBI X1, 1\nCAT X2, X2, X4\nBGT X1, X0, loop\nPOP X3, C1\nEXIT\n";
+
+    fn snapshot(processor: &Processor, registers: &[u32]) -> BTreeMap<u32, String> {
+        registers
+            .iter()
+            .filter_map(|&register| {
+                let value = processor.register(register).ok()?;
+                (!matches!(value, Value::None)).then(|| (register, value.preview()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn replayed_state_matches_a_fresh_run() {
+        let byte_code = Assembler::new(PROGRAM.to_string(), "replay.aasm")
+            .assemble()
+            .unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let mut processor = Processor::new(Config::new("text", "embedding"));
+        processor.set_trace(Box::new(sender));
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+
+        let replay = TraceReplay::new(receiver.try_iter().collect());
+        assert_eq!(replay.len(), 17);
+
+        for step in [0, 1, 4, 9, 16, 17] {
+            let mut config = Config::new("text", "embedding");
+            config.max_instructions = Some(step as u64);
+
+            let mut fresh = Processor::new(config);
+            fresh.load(&byte_code).unwrap();
+            // Stops at the budget before the step runs, except at the end where it finishes.
+            let _ = fresh.run();
+
+            let state = replay.state_at(step);
+            assert_eq!(
+                state.registers,
+                snapshot(&fresh, &[1, 2, 3, 4]),
+                "step {}",
+                step
+            );
+            assert_eq!(
+                state.contexts.get(&1).copied().unwrap_or(0),
+                fresh.context_length(1).unwrap(),
+                "step {}",
+                step
+            );
+        }
+    }
+
+    #[test]
+    fn the_header_records_the_seed() {
+        let byte_code = Assembler::new("LI X1, 1\nEXIT\n".to_string(), "header.aasm")
+            .assemble()
+            .unwrap();
+
+        let mut config = Config::new("text", "embedding");
+        config.text_model_overrides.seed = Some(7);
+
+        let path = std::env::temp_dir().join(format!("lpu-header-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut processor = Processor::new(config);
+        processor.set_trace(Box::new(JsonLinesTrace::create(path).unwrap()));
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+        drop(processor);
+
+        let replay = TraceReplay::load(path).unwrap();
+        std::fs::remove_file(path).ok();
+
+        assert_eq!(replay.header().unwrap().seed, Some(7));
+        assert_eq!(replay.header().unwrap().text_model, "text");
+        assert_eq!(replay.len(), 2);
+    }
+
+    #[test]
+    fn trace_lines_round_trip() {
+        let line = r#"{"instruction_pointer":16,"mnemonic":"psh","registers":[1,4],"destination":null,"value":null,"contexts":[{"register":1,"messages":2}],"duration_ms":0.01,"model_call":false,"tokens":null}"#;
+        let event: TraceEvent = json::from_str(line).unwrap();
+
+        assert_eq!(json::to_string(&event), line);
+
+        let replay = TraceReplay::new(vec![event]);
+        assert_eq!(replay.state_at(1).contexts.get(&1), Some(&2));
+    }
+}
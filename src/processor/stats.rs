@@ -0,0 +1,204 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use miniserde::{Deserialize, Serialize};
+
+// Tokens the server reported using, summed over the requests whose responses include usage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    pub fn add(&mut self, other: &TokenUsage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+
+    // The tokens used since an earlier total of the same run.
+    pub fn since(&self, earlier: &TokenUsage) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens: self.prompt_tokens.saturating_sub(earlier.prompt_tokens),
+            completion_tokens: self
+                .completion_tokens
+                .saturating_sub(earlier.completion_tokens),
+        }
+    }
+}
+
+impl fmt::Display for TokenUsage {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "{} prompt + {} completion = {} tokens",
+            self.prompt_tokens,
+            self.completion_tokens,
+            self.total_tokens()
+        )
+    }
+}
+
+// Model requests a run sent and the time spent on them, counted through &self so processors sharing
+// one set of counters (see Processor::share_with) can count from several threads without losing
+// updates. Each counter is independent, so a relaxed ordering is enough.
+#[derive(Debug, Default)]
+pub struct RunCounters {
+    // Request slots claimed against MAX_LLM_CALLS, taken before a request is sent.
+    requests: AtomicUsize,
+    chat_completions: AtomicUsize,
+    embeddings: AtomicUsize,
+    model_time_nanos: AtomicU64,
+    cache_hits: AtomicUsize,
+    cache_misses: AtomicUsize,
+}
+
+impl RunCounters {
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    pub fn chat_completions(&self) -> usize {
+        self.chat_completions.load(Ordering::Relaxed)
+    }
+
+    pub fn embeddings(&self) -> usize {
+        self.embeddings.load(Ordering::Relaxed)
+    }
+
+    pub fn model_time(&self) -> Duration {
+        Duration::from_nanos(self.model_time_nanos.load(Ordering::Relaxed))
+    }
+
+    pub fn cache_hits(&self) -> usize {
+        self.cache_hits.load(Ordering::Relaxed)
+    }
+
+    pub fn cache_misses(&self) -> usize {
+        self.cache_misses.load(Ordering::Relaxed)
+    }
+
+    // Claims a slot for one request, unless limit requests have already been claimed. Checking and
+    // claiming in one step means two threads cannot both take the last slot. Returns the number
+    // already claimed when the limit is reached.
+    pub(crate) fn claim_request(&self, limit: Option<usize>) -> Result<(), usize> {
+        self.requests
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |claimed| {
+                limit
+                    .is_none_or(|limit| claimed < limit)
+                    .then_some(claimed + 1)
+            })
+            .map(|_| ())
+    }
+
+    pub(crate) fn add_chat_completion(&self) {
+        self.chat_completions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_embeddings(&self) {
+        self.embeddings.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_model_time(&self, time: Duration) {
+        let nanos = u64::try_from(time.as_nanos()).unwrap_or(u64::MAX);
+        self.model_time_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_cache_lookup(&self, hit: bool) {
+        let counter = if hit {
+            &self.cache_hits
+        } else {
+            &self.cache_misses
+        };
+
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// How much work a run did. Model requests count failed requests too, since they still reached the server.
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pub instructions: u64,
+    // Executed instructions by mnemonic.
+    pub opcodes: BTreeMap<&'static str, u64>,
+    pub chat_completions: usize,
+    pub embeddings: usize,
+    // Wall time spent waiting on the model server, summed over every request.
+    pub model_time: Duration,
+    // Time requests spent queued for MAX_CONCURRENT_REQUESTS before being sent, not counted in
+    // model_time. Much of it means the limit, rather than the server, is holding the run back.
+    pub request_wait: Duration,
+    // Model requests answered from the response cache, and those that had to be sent.
+    pub cache_hits: usize,
+    pub cache_misses: usize,
+    // None when no response reported usage.
+    pub token_usage: Option<TokenUsage>,
+    // Most messages held by any one context register at once.
+    pub peak_context_messages: usize,
+    // Register number and size in bytes of the largest text written to a register. None when no text
+    // was written.
+    pub largest_register: Option<(u32, usize)>,
+    // Sampling seed sent with every chat request, so the run can be repeated. None when unset.
+    pub seed: Option<u32>,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(formatter, "Instructions executed = {}", self.instructions)?;
+
+        for (opcode, count) in &self.opcodes {
+            writeln!(formatter, "  {:<6} {}", opcode, count)?;
+        }
+
+        writeln!(formatter, "Chat completions = {}", self.chat_completions)?;
+        writeln!(formatter, "Embeddings requests = {}", self.embeddings)?;
+        writeln!(
+            formatter,
+            "Model time = {:.2}s",
+            self.model_time.as_secs_f64()
+        )?;
+        writeln!(
+            formatter,
+            "Request wait = {:.2}s",
+            self.request_wait.as_secs_f64()
+        )?;
+
+        if let Some(usage) = &self.token_usage {
+            writeln!(formatter, "Tokens = {}", usage)?;
+        }
+
+        if let Some(seed) = self.seed {
+            writeln!(formatter, "Seed = {}", seed)?;
+        }
+
+        if self.cache_hits + self.cache_misses > 0 {
+            writeln!(
+                formatter,
+                "Response cache = {} hits, {} misses",
+                self.cache_hits, self.cache_misses
+            )?;
+        }
+
+        if let Some((register, bytes)) = self.largest_register {
+            writeln!(
+                formatter,
+                "Largest register = x{} ({} bytes)",
+                register, bytes
+            )?;
+        }
+
+        write!(
+            formatter,
+            "Peak context = {} messages",
+            self.peak_context_messages
+        )
+    }
+}
@@ -0,0 +1,155 @@
+use std::{fs::File, io::Write, sync::mpsc::Sender};
+
+use miniserde::{Deserialize, Serialize, json};
+
+use crate::{
+    exception::{BaseException, Exception},
+    processor::stats::TokenUsage,
+};
+
+// One executed instruction. Values are previews, quoted and truncated like those in error messages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceEvent {
+    pub instruction_pointer: usize,
+    pub mnemonic: String,
+    // Register numbers of every operand, general purpose and context alike, as in the instruction.
+    pub registers: Vec<u32>,
+    // The general purpose register the instruction writes, if it writes one, and its value after it ran.
+    pub destination: Option<u32>,
+    pub value: Option<String>,
+    // Context registers whose number of messages the instruction changed, with the number afterwards.
+    pub contexts: Vec<ContextLength>,
+    pub duration_ms: f64,
+    pub model_call: bool,
+    // Tokens the server reported using for the instruction's requests, if it reported any.
+    pub tokens: Option<TokenUsage>,
+}
+
+// Written once before the first event of a run, so the run can be repeated with the same settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceHeader {
+    pub lpu_version: String,
+    pub text_model: String,
+    pub embedding_model: String,
+    // Sampling seed sent with every chat request. None when the run was not seeded.
+    pub seed: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ContextLength {
+    pub register: u32,
+    pub messages: usize,
+}
+
+// Where the executor sends a trace of the instructions it runs.
+pub trait TraceSink: Send {
+    // Called when a run starts, before any event is recorded.
+    fn start(&mut self, _header: &TraceHeader) -> Result<(), Exception> {
+        Ok(())
+    }
+
+    fn record(&mut self, event: &TraceEvent) -> Result<(), Exception>;
+}
+
+// Writes each event as one line of JSON, so a trace can be read while the program is still running.
+pub struct JsonLinesTrace<W: Write + Send> {
+    writer: W,
+}
+
+impl<W: Write + Send> JsonLinesTrace<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesTrace { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl JsonLinesTrace<File> {
+    pub fn create(path: &str) -> Result<Self, Exception> {
+        let file = File::create(path).map_err(|e| {
+            Exception::Processor(BaseException::caused_by(
+                format!("Failed to create trace file '{}'.", path),
+                e,
+            ))
+        })?;
+
+        Ok(JsonLinesTrace::new(file))
+    }
+}
+
+impl<W: Write + Send> JsonLinesTrace<W> {
+    fn write_line(&mut self, mut line: String) -> Result<(), Exception> {
+        line.push('\n');
+
+        self.writer.write_all(line.as_bytes()).map_err(|e| {
+            Exception::Processor(BaseException::caused_by("Failed to write trace.", e))
+        })
+    }
+}
+
+// The header is the first line, so events start on the second.
+impl<W: Write + Send> TraceSink for JsonLinesTrace<W> {
+    fn start(&mut self, header: &TraceHeader) -> Result<(), Exception> {
+        self.write_line(json::to_string(header))
+    }
+
+    fn record(&mut self, event: &TraceEvent) -> Result<(), Exception> {
+        self.write_line(json::to_string(event))
+    }
+}
+
+// Collects events in memory when the processor is used as a library. Events sent after the receiver
+// has been dropped are discarded rather than failing the run.
+impl TraceSink for Sender<TraceEvent> {
+    fn record(&mut self, event: &TraceEvent) -> Result<(), Exception> {
+        self.send(event.clone()).ok();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Assembler, CapturedOutput, Config, Processor, ScriptedBackend};
+
+    #[test]
+    fn a_three_instruction_program_writes_three_events() {
+        let byte_code = Assembler::new(
+            "LS X1, \"hi\"\nINF X2, X1, C1\nEXIT\n".to_string(),
+            "trace.aasm",
+        )
+        .assemble()
+        .unwrap();
+
+        let mut backend = ScriptedBackend::new();
+        backend.push_response("hello");
+
+        let trace = CapturedOutput::default();
+        let mut processor = Processor::new(Config::new("text", "embedding"));
+        processor.set_backend(Box::new(backend));
+        processor.set_trace(Box::new(JsonLinesTrace::new(trace.clone())));
+        processor.load(&byte_code).unwrap();
+        processor.run().unwrap();
+
+        let text = String::from_utf8(trace.bytes()).unwrap();
+        let mut lines = text.lines();
+
+        let header: TraceHeader = json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(header.text_model, "text");
+
+        let events = lines
+            .map(|line| json::from_str::<TraceEvent>(line).unwrap())
+            .collect::<Vec<_>>();
+
+        let mnemonics = events.iter().map(|event| event.mnemonic.as_str());
+        assert_eq!(mnemonics.collect::<Vec<_>>(), ["ls", "inf", "exit"]);
+
+        let model_calls = events.iter().map(|event| event.model_call);
+        assert_eq!(model_calls.collect::<Vec<_>>(), [false, true, false]);
+
+        assert_eq!(events[1].destination, Some(2));
+        assert_eq!(events[1].value.as_deref(), Some("\"hello\""));
+    }
+}
@@ -0,0 +1,157 @@
+use std::{fs, io, path::Path};
+
+use language_processor_unit::{
+    config::PromptTemplates,
+    exception::{BaseException, Exception},
+};
+
+use crate::bundle::KeyValues;
+
+// Templates that can be replaced, by file name without '.txt', with the placeholders each one must
+// contain and what they stand for.
+const TEMPLATES: &[(&str, &[(&str, &str)])] = &[
+    ("eval", &[("{a}", "the statement EVAL checks")]),
+    (
+        "sim",
+        &[
+            ("{a}", "the first text SIM compares"),
+            ("{b}", "the second text SIM compares"),
+        ],
+    ),
+    (
+        "cls",
+        &[
+            ("{a}", "the text CLS classifies"),
+            ("{b}", "the labels CLS chooses from, one per line"),
+        ],
+    ),
+    (
+        "score",
+        &[
+            ("{a}", "the text SCORE rates"),
+            ("{b}", "the criteria SCORE rates it against"),
+        ],
+    ),
+    (
+        "json",
+        &[
+            ("{a}", "the text JSON extracts fields from"),
+            ("{b}", "the fields JSON asks for"),
+        ],
+    ),
+    (
+        "ask",
+        &[
+            ("{a}", "the question ASK answers"),
+            ("{b}", "the context ASK answers from"),
+        ],
+    ),
+    (
+        "trl",
+        &[
+            ("{a}", "the text TRL translates"),
+            ("{b}", "the language TRL translates into"),
+        ],
+    ),
+];
+
+fn error(message: String) -> Exception {
+    Exception::Program(BaseException::new(message, None))
+}
+
+// Reads the prompt template files in dir, one '<mnemonic>.txt' file per instruction, keyed by
+// path so bundles can carry them. A missing directory is only an error when required, so the
+// default directory can be left out.
+pub fn read(dir: &str, required: bool) -> Result<KeyValues, Exception> {
+    let entries = match fs::read_dir(Path::new(dir)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound && !required => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(Exception::Program(BaseException::caused_by(
+                format!("Failed to read prompt templates in '{}'", dir),
+                e,
+            )));
+        }
+    };
+
+    let mut paths = entries
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| {
+            Exception::Program(BaseException::caused_by(
+                format!("Failed to read prompt templates in '{}'", dir),
+                e,
+            ))
+        })?;
+    paths.sort();
+
+    // Other files, such as notes on the prompts, are left alone.
+    paths
+        .iter()
+        .filter(|path| path.extension().is_some_and(|extension| extension == "txt"))
+        .map(|path| {
+            fs::read_to_string(path)
+                .map(|text| (path.display().to_string(), text))
+                .map_err(|e| {
+                    Exception::Program(BaseException::caused_by(
+                        format!("Failed to read prompt template '{}'", path.display()),
+                        e,
+                    ))
+                })
+        })
+        .collect()
+}
+
+// Checks the template files read by 'read' and fills in the templates they replace. Instructions
+// without a file keep their built-in prompt.
+pub fn parse(files: &KeyValues) -> Result<PromptTemplates, Exception> {
+    let mut prompts = PromptTemplates::default();
+
+    for (path, text) in files {
+        let name = Path::new(path)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default();
+        let Some((_, placeholders)) = TEMPLATES.iter().find(|(template, _)| *template == name)
+        else {
+            return Err(error(format!(
+                "Unknown prompt template '{}', expected one of: {}.",
+                path,
+                TEMPLATES
+                    .iter()
+                    .map(|(template, _)| format!("{}.txt", template))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
+        };
+
+        // Editors end files with a line break, which is not part of the prompt.
+        let template = text
+            .strip_suffix('\n')
+            .map(|text| text.strip_suffix('\r').unwrap_or(text))
+            .unwrap_or(text)
+            .to_string();
+
+        if let Some((placeholder, meaning)) = placeholders
+            .iter()
+            .find(|(placeholder, _)| !template.contains(placeholder))
+        {
+            return Err(error(format!(
+                "Prompt template '{}' must contain {}, which stands for {}.",
+                path, placeholder, meaning
+            )));
+        }
+
+        match name {
+            "eval" => prompts.eval = template,
+            "sim" => prompts.similarity = template,
+            "cls" => prompts.classify = template,
+            "score" => prompts.score = template,
+            "json" => prompts.json = template,
+            "ask" => prompts.ask = template,
+            _ => prompts.translate = template,
+        }
+    }
+
+    Ok(prompts)
+}
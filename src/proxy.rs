@@ -0,0 +1,203 @@
+use std::env;
+
+use crate::exception::{BaseException, Exception};
+
+// Variables each proxy is read from, in the order curl reads them.
+const HTTPS_PROXY_VARIABLES: &[&str] = &["https_proxy", "HTTPS_PROXY", "all_proxy", "ALL_PROXY"];
+const HTTP_PROXY_VARIABLES: &[&str] = &["http_proxy", "HTTP_PROXY", "all_proxy", "ALL_PROXY"];
+const NO_PROXY_VARIABLES: &[&str] = &["no_proxy", "NO_PROXY"];
+
+// Variables minreq reads a proxy from by itself, whatever NO_PROXY says. A program that decides on
+// proxies through ProxySettings removes them before sending requests.
+pub const MINREQ_VARIABLES: &[&str] = &["https_proxy", "http_proxy", "all_proxy"];
+
+// The proxies requests to the model servers are sent through, as set in the environment. Only HTTP
+// CONNECT proxies are supported, and only when built with the proxy feature.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProxySettings {
+    // Proxy for https:// and http:// servers.
+    pub https: Option<String>,
+    pub http: Option<String>,
+    // Hosts and domains separated by commas that are reached without a proxy.
+    pub no_proxy: Option<String>,
+}
+
+impl ProxySettings {
+    // Reads each setting from the first of its variables that lookup finds set.
+    pub fn from_variables(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let first = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|&name| lookup(name).filter(|value| !value.trim().is_empty()))
+        };
+
+        ProxySettings {
+            https: first(HTTPS_PROXY_VARIABLES),
+            http: first(HTTP_PROXY_VARIABLES),
+            no_proxy: first(NO_PROXY_VARIABLES),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::from_variables(|name| env::var(name).ok())
+    }
+
+    // The proxy for a server URL. Loopback hosts and hosts NO_PROXY lists have none.
+    pub fn for_url(&self, url: &str) -> Option<&str> {
+        let (https, host) = scheme_and_host(url)?;
+
+        if is_loopback(&host)
+            || self
+                .no_proxy
+                .as_deref()
+                .is_some_and(|no_proxy| excluded(&host, no_proxy))
+        {
+            return None;
+        }
+
+        if https {
+            self.https.as_deref()
+        } else {
+            self.http.as_deref()
+        }
+    }
+
+    // A proxy that requests to the server cannot be sent through is found before the program runs
+    // rather than at its first model request. The proxy is left out of the message, as it may hold
+    // credentials.
+    pub fn check(&self, url: &str) -> Result<(), Exception> {
+        let Some(proxy) = self.for_url(url) else {
+            return Ok(());
+        };
+
+        let reason = match proxy.split_once("://") {
+            Some((scheme, _)) if scheme != "http" => "Only http:// CONNECT proxies are supported.",
+            _ if !cfg!(feature = "proxy") => {
+                "This build cannot send requests through a proxy. Rebuild with the proxy feature, \
+                 or list the host in NO_PROXY."
+            }
+            _ => return Ok(()),
+        };
+
+        Err(Exception::Program(BaseException::new(
+            format!("Invalid proxy for the model server at {}. {}", url, reason),
+            None,
+        )))
+    }
+}
+
+// Whether a URL is https://, and its host without the port or the brackets around an IPv6 address.
+fn scheme_and_host(url: &str) -> Option<(bool, String)> {
+    let (https, rest) = match url.strip_prefix("https://") {
+        Some(rest) => (true, rest),
+        None => (false, url.strip_prefix("http://")?),
+    };
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => host,
+        _ => authority,
+    };
+
+    Some((https, host.trim_matches(['[', ']']).to_ascii_lowercase()))
+}
+
+fn is_loopback(host: &str) -> bool {
+    host == "localhost" || host == "::1" || host.starts_with("127.")
+}
+
+// Whether requests to the URL cross the network unencrypted: plain http:// to a host other than
+// this machine.
+pub fn is_cleartext(url: &str) -> bool {
+    scheme_and_host(url).is_some_and(|(https, host)| !https && !is_loopback(&host))
+}
+
+// NO_PROXY lists hosts and domains separated by commas. An entry matches the host itself and its
+// subdomains, and '*' matches every host.
+fn excluded(host: &str, no_proxy: &str) -> bool {
+    no_proxy.split(',').any(|entry| {
+        let entry = entry.trim().to_ascii_lowercase();
+        let entry = entry.trim_start_matches("*.").trim_start_matches('.');
+        let entry = match entry.split_once(':') {
+            Some((name, port)) if !port.contains(':') => name,
+            _ => entry,
+        };
+
+        entry == "*"
+            || (!entry.is_empty() && (host == entry || host.ends_with(&format!(".{}", entry))))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(variables: &[(&str, &str)]) -> ProxySettings {
+        ProxySettings::from_variables(|name| {
+            variables
+                .iter()
+                .find(|(variable, _)| *variable == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn each_scheme_uses_its_own_proxy() {
+        let proxies = settings(&[
+            ("HTTPS_PROXY", "http://secure:3128"),
+            ("http_proxy", "http://plain:3128"),
+            ("NO_PROXY", "internal.example, .corp"),
+        ]);
+
+        assert_eq!(
+            proxies.for_url("https://api.example.com/v1"),
+            Some("http://secure:3128")
+        );
+        assert_eq!(
+            proxies.for_url("http://gpu-box:8080"),
+            Some("http://plain:3128")
+        );
+        assert_eq!(proxies.for_url("https://internal.example"), None);
+        assert_eq!(proxies.for_url("https://models.internal.example"), None);
+        assert_eq!(proxies.for_url("http://llm.corp:8080"), None);
+        assert_eq!(proxies.for_url("http://127.0.0.1:8080"), None);
+        assert_eq!(proxies.for_url("http://[::1]:8080"), None);
+    }
+
+    #[test]
+    fn all_proxy_covers_both_schemes() {
+        let proxies = settings(&[("ALL_PROXY", "proxy:8080")]);
+
+        assert_eq!(
+            proxies.for_url("https://api.example.com"),
+            Some("proxy:8080")
+        );
+        assert_eq!(proxies.for_url("http://gpu-box:8080"), Some("proxy:8080"));
+        assert_eq!(settings(&[]).for_url("https://api.example.com"), None);
+    }
+
+    #[test]
+    fn only_remote_plain_http_is_cleartext() {
+        assert!(is_cleartext("http://gpu-box:8080"));
+        assert!(is_cleartext("http://user@10.0.0.2"));
+        assert!(!is_cleartext("https://api.example.com"));
+        assert!(!is_cleartext("http://localhost:8080"));
+        assert!(!is_cleartext("http://127.0.0.1:8080"));
+    }
+
+    #[test]
+    fn socks_proxies_are_rejected() {
+        let proxies = settings(&[("HTTPS_PROXY", "socks5://proxy:1080")]);
+
+        assert!(proxies.check("https://api.example.com").is_err());
+        assert!(proxies.check("https://127.0.0.1:8443").is_ok());
+        assert_eq!(
+            settings(&[("HTTPS_PROXY", "proxy:3128")])
+                .check("https://api.example.com")
+                .is_ok(),
+            cfg!(feature = "proxy")
+        );
+    }
+}
@@ -0,0 +1,67 @@
+// Assembles many programs in one process, as a long-running host would, counting the bytes left
+// allocated. This file has a single test, so no other test allocates while it counts.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicIsize, Ordering},
+};
+
+use language_processor_unit::Assembler;
+
+// Bytes currently allocated by the test binary.
+static LIVE_BYTES: AtomicIsize = AtomicIsize::new(0);
+
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE_BYTES.fetch_add(layout.size() as isize, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size() as isize, Ordering::Relaxed);
+        unsafe { System.dealloc(pointer, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// A small program built at run time, so its source cannot be 'static. Every tenth one has an error,
+// so the diagnostics that quote the source are built too.
+fn assemble(index: usize) {
+    let source = if index.is_multiple_of(10) {
+        format!("LI X1, {}\nLS X2, \"unterminated\n", index)
+    } else {
+        format!(
+            "LI X1, {}\nLS X2, \"program {}\"\nloop:\nSUBI X1, 1\nBGT X1, X0, loop\nEXIT\n",
+            index % 7,
+            index
+        )
+    };
+    let mut assembler = Assembler::new(source, &format!("program_{}.aasm", index));
+
+    if index.is_multiple_of(10) {
+        assert!(assembler.assemble().is_err());
+        assert!(!assembler.diagnostics().is_empty());
+    } else {
+        assert!(!assembler.assemble().unwrap().is_empty());
+    }
+}
+
+#[test]
+fn assembling_many_programs_does_not_grow_memory() {
+    // Lets one-time allocations, such as lazily built tables, happen before counting.
+    (0..100).for_each(assemble);
+    let before = LIVE_BYTES.load(Ordering::Relaxed);
+
+    (100..1_100).for_each(assemble);
+    let after = LIVE_BYTES.load(Ordering::Relaxed);
+
+    assert!(
+        after - before < 4096,
+        "{} bytes were left allocated by 1,000 programs",
+        after - before
+    );
+}
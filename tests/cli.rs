@@ -0,0 +1,743 @@
+// Runs the lpu binary the way a user would, in a scratch directory with its own .env file.
+
+use std::{
+    env::temp_dir,
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::{self, Command, Output},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+const CHAT_RESPONSE: &str = r#"{"model":"stub","choices":[{"index":0,"message":{"role":"assistant","content":"bundled answer"},"finish_reason":"stop"}]}"#;
+
+// Answers every chat request with CHAT_RESPONSE, keeping the request bodies.
+fn chat_server() -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let bodies = Arc::new(Mutex::new(Vec::new()));
+    let kept = Arc::clone(&bodies);
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { break };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut length = 0;
+
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+
+                if line.trim().is_empty() {
+                    break;
+                }
+
+                if let Some((name, value)) = line.split_once(':')
+                    && name.eq_ignore_ascii_case("content-length")
+                {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            kept.lock().unwrap().push(String::from_utf8(body).unwrap());
+
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                CHAT_RESPONSE.len(),
+                CHAT_RESPONSE
+            )
+            .unwrap();
+        }
+    });
+
+    (url, bodies)
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let directory = temp_dir().join(format!("lpu-cli-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn lpu(directory: &Path, args: &[&str], variables: &[(&str, &str)]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_language_processor_unit"))
+        .current_dir(directory)
+        .args(args)
+        .envs(variables.iter().copied())
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "{:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stdout)
+    );
+    output
+}
+
+#[test]
+fn a_bundle_carries_its_config_prompt_templates_and_model_file() {
+    let (url, bodies) = chat_server();
+    let env_file = format!("LLM_BASE_URL={}\nSKIP_HEALTHCHECK=true\n", url);
+
+    let build = scratch("bundle-build");
+    fs::write(build.join(".env"), &env_file).unwrap();
+    fs::write(
+        build.join("lpu.toml"),
+        "[text_model]\nmodel = \"bundled-model\"\n\n[embeddings_model]\nmodel = \"bundled-embeddings\"\n",
+    )
+    .unwrap();
+    fs::create_dir_all(build.join("prompts")).unwrap();
+    fs::write(
+        build.join("prompts/ask.txt"),
+        "Bundled template.\nQuestion: {a}\nText: {b}\n",
+    )
+    .unwrap();
+    fs::write(
+        build.join("ask.aasm"),
+        "LS X1, \"Who?\"\nLS X2, \"Ada wrote it.\"\nASK X3, X1, X2\nPLN X3\nEXIT\n",
+    )
+    .unwrap();
+
+    lpu(&build, &["build", "ask.aasm"], &[]);
+    lpu(
+        &build,
+        &["bundle", "build/ask.lpu", "-o", "ask.lpub"],
+        &[("SYSTEM_PROMPT", "line one\nline two")],
+    );
+
+    let info = lpu(&build, &["info", "ask.lpub"], &[]);
+    let info = String::from_utf8(info.stdout).unwrap();
+    assert!(
+        info.contains("  SYSTEM_PROMPT = line one\\nline two"),
+        "{}",
+        info
+    );
+    assert!(info.contains("prompts ("), "{}", info);
+    assert!(info.contains("model_config ("), "{}", info);
+
+    // The run happens elsewhere, with neither the templates nor lpu.toml on disk.
+    let run = scratch("bundle-run");
+    fs::write(run.join(".env"), &env_file).unwrap();
+    fs::copy(build.join("ask.lpub"), run.join("ask.lpub")).unwrap();
+
+    let output = lpu(&run, &["run", "ask.lpub"], &[]);
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("bundled answer")
+    );
+
+    let bodies = bodies.lock().unwrap();
+    assert_eq!(bodies.len(), 1);
+    assert!(
+        bodies[0].contains(r#""model":"bundled-model""#),
+        "{}",
+        bodies[0]
+    );
+    assert!(bodies[0].contains("line one\\nline two"), "{}", bodies[0]);
+    assert!(
+        bodies[0].contains("Bundled template.\\nQuestion: Who?\\nText: Ada wrote it."),
+        "{}",
+        bodies[0]
+    );
+
+    fs::remove_dir_all(&build).unwrap();
+    fs::remove_dir_all(&run).unwrap();
+}
+
+#[test]
+fn commands_that_need_no_model_run_without_a_env_file() {
+    let build = scratch("no-env-build");
+    fs::write(
+        build.join(".env"),
+        "TEXT_MODEL=stub\nEMBEDDING_MODEL=stub\nSKIP_HEALTHCHECK=true\n",
+    )
+    .unwrap();
+    fs::write(build.join("hello.aasm"), "LS X1, \"hello\"\nPLN X1\nEXIT\n").unwrap();
+    lpu(&build, &["build", "hello.aasm"], &[]);
+    lpu(
+        &build,
+        &["bundle", "build/hello.lpu", "-o", "hello.lpub"],
+        &[],
+    );
+
+    let elsewhere = scratch("no-env");
+    fs::copy(build.join("build/hello.lpu"), elsewhere.join("hello.lpu")).unwrap();
+    fs::copy(build.join("hello.lpub"), elsewhere.join("hello.lpub")).unwrap();
+
+    let isa = lpu(&elsewhere, &["isa", "inf"], &[]);
+    assert!(
+        String::from_utf8(isa.stdout)
+            .unwrap()
+            .contains("inf rd, rs, rsc")
+    );
+    lpu(&elsewhere, &["info", "hello.lpub"], &[]);
+    lpu(&elsewhere, &["disasm", "hello.lpu"], &[]);
+    lpu(&elsewhere, &["check", "hello.lpub"], &[]);
+
+    // Commands that talk to a model still need one.
+    let run = Command::new(env!("CARGO_BIN_EXE_language_processor_unit"))
+        .current_dir(&elsewhere)
+        .args(["run", "hello.lpu"])
+        .output()
+        .unwrap();
+    assert!(!run.status.success());
+    assert!(
+        String::from_utf8(run.stdout)
+            .unwrap()
+            .contains("Failed to load .env file")
+    );
+
+    fs::remove_dir_all(&build).unwrap();
+    fs::remove_dir_all(&elsewhere).unwrap();
+}
+
+#[test]
+fn debug_output_goes_to_stderr_and_program_output_to_stdout() {
+    let directory = scratch("debug-streams");
+    fs::write(
+        directory.join(".env"),
+        "TEXT_MODEL=stub\nEMBEDDING_MODEL=stub\nSKIP_HEALTHCHECK=true\n",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("out.aasm"),
+        "LS X1, \"hello\"\nPUT X1\nPLN X1\nPCX C1\nEXIT\n",
+    )
+    .unwrap();
+    lpu(&directory, &["build", "out.aasm"], &[]);
+
+    let quiet = lpu(
+        &directory,
+        &["run", "build/out.lpu"],
+        &[("DEBUG_RUN", "false")],
+    );
+    let debug = lpu(
+        &directory,
+        &["run", "build/out.lpu"],
+        &[("DEBUG_RUN", "true")],
+    );
+    fs::remove_dir_all(&directory).unwrap();
+
+    // The program output is the same either way, and the trace never reaches stdout.
+    assert_eq!(String::from_utf8(quiet.stdout).unwrap(), "hellohello\n[]\n");
+    assert_eq!(String::from_utf8(debug.stdout).unwrap(), "hellohello\n[]\n");
+    assert!(quiet.stderr.is_empty());
+
+    let trace = String::from_utf8(debug.stderr).unwrap();
+    assert!(trace.contains("Executed PLN : r1 = \"hello\""), "{}", trace);
+    assert!(
+        trace.contains("Halting execution with status 0."),
+        "{}",
+        trace
+    );
+}
+
+#[test]
+fn named_arguments_are_loaded_by_argn() {
+    let directory = scratch("arguments");
+    fs::write(
+        directory.join(".env"),
+        "TEXT_MODEL=stub\nEMBEDDING_MODEL=stub\nSKIP_HEALTHCHECK=true\n",
+    )
+    .unwrap();
+    fs::write(directory.join("report.txt"), "line one\nline two\n").unwrap();
+    fs::write(
+        directory.join("args.aasm"),
+        "ARGN X1, \"question\"\nARGN X2, \"doc\"\nPLN X1\nPUT X2\nEXIT\n",
+    )
+    .unwrap();
+    lpu(&directory, &["build", "args.aasm"], &[]);
+
+    let output = lpu(
+        &directory,
+        &[
+            "run",
+            "build/args.lpu",
+            "--arg",
+            "question=What is = for?",
+            "--arg",
+            "doc=@report.txt",
+        ],
+        &[("DEBUG_RUN", "true")],
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "What is = for?\nline one\nline two\n"
+    );
+
+    // The run trace records the arguments so that the run can be repeated.
+    let trace = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        trace.contains(
+            "Argument question = \"What is = for?\"\nArgument doc = \"line one\\nline two\\n\""
+        ),
+        "{}",
+        trace
+    );
+
+    let unknown = Command::new(env!("CARGO_BIN_EXE_language_processor_unit"))
+        .current_dir(&directory)
+        .args(["run", "build/args.lpu", "--arg", "doc=@report.txt"])
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+
+    let stdout = String::from_utf8(unknown.stdout).unwrap();
+    assert!(!unknown.status.success());
+    assert!(
+        stdout.contains("Unknown argument 'question'. Provided arguments: [doc]."),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn running_byte_code_older_than_its_source_warns() {
+    let directory = scratch("stale");
+    fs::write(
+        directory.join(".env"),
+        "TEXT_MODEL=stub\nEMBEDDING_MODEL=stub\nSKIP_HEALTHCHECK=true\n",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("stale.aasm"),
+        "LS X1, \"old\"\nPLN X1\nEXIT\n",
+    )
+    .unwrap();
+    lpu(&directory, &["build", "stale.aasm"], &[]);
+
+    let fresh = lpu(
+        &directory,
+        &["run", "build/stale.lpu", "--require-fresh"],
+        &[],
+    );
+    assert!(fresh.stderr.is_empty());
+
+    fs::write(
+        directory.join("stale.aasm"),
+        "LS X1, \"new\"\nPLN X1\nEXIT\n",
+    )
+    .unwrap();
+    let stale = lpu(&directory, &["run", "build/stale.lpu"], &[]);
+    assert_eq!(String::from_utf8(stale.stdout).unwrap(), "old\n");
+    assert_eq!(
+        String::from_utf8(stale.stderr).unwrap(),
+        format!(
+            "Warning: Source {} has changed since this artifact was built. Rerun 'build'.\n",
+            directory.join("stale.aasm").display()
+        )
+    );
+
+    let refused = Command::new(env!("CARGO_BIN_EXE_language_processor_unit"))
+        .current_dir(&directory)
+        .args(["run", "build/stale.lpu", "--require-fresh"])
+        .output()
+        .unwrap();
+    assert!(!refused.status.success());
+    assert!(!String::from_utf8(refused.stdout).unwrap().contains("old"));
+
+    // Without the source there is nothing to compare against, so the check is skipped.
+    fs::remove_file(directory.join("stale.aasm")).unwrap();
+    let missing = lpu(
+        &directory,
+        &["run", "build/stale.lpu", "--require-fresh"],
+        &[],
+    );
+    fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!(String::from_utf8(missing.stdout).unwrap(), "old\n");
+    assert!(missing.stderr.is_empty());
+}
+
+#[test]
+fn debug_traces_preview_values_and_output_prints_them_raw() {
+    let directory = scratch("value-formats");
+    fs::write(
+        directory.join(".env"),
+        "TEXT_MODEL=stub\nEMBEDDING_MODEL=stub\nSKIP_HEALTHCHECK=true\n",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("values.aasm"),
+        "LS X1, \"say \\\"hi\\\"\\nthen a much longer line that goes past the preview limit for sure\"\nLI X2, -7\nMV X3, X4\nPUT X2\nPLN X3\nPLN X1\nEXIT\n",
+    )
+    .unwrap();
+    lpu(&directory, &["build", "values.aasm"], &[]);
+
+    let output = lpu(
+        &directory,
+        &["run", "build/values.lpu"],
+        &[("DEBUG_RUN", "true")],
+    );
+    fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "-7\nsay \"hi\"\nthen a much longer line that goes past the preview limit for sure\n"
+    );
+
+    let trace = String::from_utf8(output.stderr).unwrap();
+    let trace = trace.lines().take(7).collect::<Vec<_>>();
+    assert_eq!(
+        trace,
+        [
+            r#"Executed LS  : r1 = "say \"hi\"\nthen a much longer line that goes past the preview ...""#,
+            "Executed LI  : r2 = -7",
+            "Executed MV  : r3 = <none>",
+            "Executed PUT : r2 = -7",
+            "Executed PLN : r3 = <none>",
+            r#"Executed PLN : r1 = "say \"hi\"\nthen a much longer line that goes past the preview ...""#,
+            "Executed EXIT: Halting execution with status 0.",
+        ]
+    );
+}
+
+#[test]
+fn le_reads_environment_variables_with_an_optional_default() {
+    let directory = scratch("environment");
+    fs::write(
+        directory.join(".env"),
+        "TEXT_MODEL=stub\nEMBEDDING_MODEL=stub\nSKIP_HEALTHCHECK=true\n",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("le.aasm"),
+        "LE X1, \"LPU_TEST_LANGUAGE\", \"English\"\nPLN X1\nLE X2, \"LPU_TEST_USER\"\nPLN X2\nEXIT\n",
+    )
+    .unwrap();
+    lpu(&directory, &["build", "le.aasm"], &[]);
+
+    let set = lpu(
+        &directory,
+        &["run", "build/le.lpu"],
+        &[("LPU_TEST_LANGUAGE", "French"), ("LPU_TEST_USER", "Ada")],
+    );
+    assert_eq!(String::from_utf8(set.stdout).unwrap(), "French\nAda\n");
+
+    let unset = Command::new(env!("CARGO_BIN_EXE_language_processor_unit"))
+        .current_dir(&directory)
+        .args(["run", "build/le.lpu"])
+        .env_remove("LPU_TEST_LANGUAGE")
+        .env_remove("LPU_TEST_USER")
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+
+    let stdout = String::from_utf8(unset.stdout).unwrap();
+    assert!(!unset.status.success());
+    assert!(stdout.starts_with("English\n"), "{}", stdout);
+    assert!(
+        stdout.contains("Environment variable 'LPU_TEST_USER' is not set."),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn positional_arguments_are_loaded_by_arg_and_counted_by_argc() {
+    let directory = scratch("positional");
+    fs::write(
+        directory.join(".env"),
+        "TEXT_MODEL=stub\nEMBEDDING_MODEL=stub\nSKIP_HEALTHCHECK=true\n",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("arg.aasm"),
+        "ARGC X1\nPLN X1\nARG X2, 0\nPLN X2\nARG X3, 2\nPLN X3\nEXIT\n",
+    )
+    .unwrap();
+    lpu(&directory, &["build", "arg.aasm"], &[]);
+
+    // Flags and their values are not arguments, and everything after '--' is.
+    let output = lpu(
+        &directory,
+        &[
+            "run",
+            "build/arg.lpu",
+            "first arg",
+            "--strict-utf8",
+            "--seed",
+            "7",
+            "second",
+            "--",
+            "--third",
+        ],
+        &[],
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "3\nfirst arg\n--third\n"
+    );
+
+    let missing = Command::new(env!("CARGO_BIN_EXE_language_processor_unit"))
+        .current_dir(&directory)
+        .args(["run", "build/arg.lpu", "only"])
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+
+    let stdout = String::from_utf8(missing.stdout).unwrap();
+    assert!(!missing.status.success());
+    assert!(
+        stdout.contains(
+            "Argument index 2 is out of range. The program was run with 1 positional argument(s)."
+        ),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn an_invalid_server_url_stops_the_run_before_it_starts() {
+    let directory = scratch("base-url");
+    fs::write(
+        directory.join(".env"),
+        "TEXT_MODEL=stub\nEMBEDDING_MODEL=stub\nSKIP_HEALTHCHECK=true\n",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("url.aasm"),
+        "LS X1, \"started\"\nPLN X1\nINF X2, X1, C1\nEXIT\n",
+    )
+    .unwrap();
+    lpu(&directory, &["build", "url.aasm"], &[]);
+
+    for (url, reason) in [
+        (
+            "localhost:8080",
+            "Expected a URL starting with http:// or https://.",
+        ),
+        ("http://:8080", "Expected a host after http://."),
+        (
+            "http://localhost:80800",
+            "Expected the port after the host to be a number from 0 to 65535.",
+        ),
+    ] {
+        let run = Command::new(env!("CARGO_BIN_EXE_language_processor_unit"))
+            .current_dir(&directory)
+            .args(["run", "build/url.lpu"])
+            .env("LLM_BASE_URL", url)
+            .output()
+            .unwrap();
+
+        let stdout = String::from_utf8(run.stdout).unwrap();
+        assert!(!run.status.success());
+        assert!(!stdout.contains("started"), "{}", stdout);
+        assert!(
+            stdout.contains(&format!("Invalid LLM_BASE_URL '{}'. {}", url, reason)),
+            "{}",
+            stdout
+        );
+    }
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn recorded_responses_are_replayed_without_the_server() {
+    let (url, bodies) = chat_server();
+    let directory = scratch("recordings");
+    fs::write(
+        directory.join(".env"),
+        "TEXT_MODEL=stub\nEMBEDDING_MODEL=stub\nSKIP_HEALTHCHECK=true\n",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("say.aasm"),
+        "LS X1, \"Say ok.\"\nINF X2, X1, C1\nPLN X2\nEXIT\n",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("other.aasm"),
+        "LS X1, \"Say something else.\"\nINF X2, X1, C1\nPLN X2\nEXIT\n",
+    )
+    .unwrap();
+    lpu(&directory, &["build", "say.aasm"], &[]);
+    lpu(&directory, &["build", "other.aasm"], &[]);
+
+    let recorded = lpu(
+        &directory,
+        &["run", "build/say.lpu"],
+        &[("LLM_BASE_URL", &url), ("LLM_CACHE_DIR", "fixtures")],
+    );
+    assert_eq!(recorded.stdout, b"bundled answer\n");
+    assert_eq!(bodies.lock().unwrap().len(), 1);
+    let recordings = fs::read_dir(directory.join("fixtures")).unwrap().count();
+    assert_eq!(recordings, 1);
+
+    // Nothing listens on the port, so only the recording can answer.
+    let closed = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    };
+    let replay = [
+        ("LLM_BASE_URL", closed.as_str()),
+        ("LLM_CACHE_DIR", "fixtures"),
+        ("LLM_CACHE_MODE", "replay-only"),
+    ];
+    let replayed = lpu(&directory, &["run", "build/say.lpu"], &replay);
+    assert_eq!(replayed.stdout, recorded.stdout);
+
+    let missing = Command::new(env!("CARGO_BIN_EXE_language_processor_unit"))
+        .current_dir(&directory)
+        .args(["run", "build/other.lpu"])
+        .envs(replay)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(missing.stdout).unwrap();
+    assert!(!missing.status.success());
+    assert!(
+        stdout.contains("No recorded chat response for this request"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("replay-only"), "{}", stdout);
+
+    let undirected = Command::new(env!("CARGO_BIN_EXE_language_processor_unit"))
+        .current_dir(&directory)
+        .args(["run", "build/say.lpu"])
+        .env("LLM_CACHE_MODE", "replay-only")
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+
+    let stdout = String::from_utf8(undirected.stdout).unwrap();
+    assert!(!undirected.status.success());
+    assert!(
+        stdout.contains("LLM_CACHE_MODE is replay-only, but LLM_CACHE_DIR is not set."),
+        "{}",
+        stdout
+    );
+    assert_eq!(bodies.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn the_startup_health_check_can_be_skipped() {
+    let directory = scratch("health");
+    fs::write(
+        directory.join(".env"),
+        "TEXT_MODEL=stub\nEMBEDDING_MODEL=stub\n",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("health.aasm"),
+        "LS X1, \"started\"\nPLN X1\nINF X2, X1, C1\nEXIT\n",
+    )
+    .unwrap();
+    lpu(&directory, &["build", "health.aasm"], &[]);
+
+    // Nothing listens on the port once the listener is dropped.
+    let closed = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    };
+    let run = |skip: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_language_processor_unit"))
+            .current_dir(&directory)
+            .args(["run", "build/health.lpu"])
+            .env("LLM_BASE_URL", &closed)
+            .env("SKIP_HEALTHCHECK", skip)
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let checked = run("false");
+    assert!(!checked.contains("started"), "{}", checked);
+    assert!(
+        checked.contains(&format!(
+            "Could not reach the model server at {}/v1/models.",
+            closed
+        )),
+        "{}",
+        checked
+    );
+
+    // Without the check, the program starts and fails at its first request instead.
+    let skipped = run("true");
+    fs::remove_dir_all(&directory).unwrap();
+
+    assert!(skipped.starts_with("started\n"), "{}", skipped);
+    assert!(!skipped.contains("Could not reach"), "{}", skipped);
+}
+
+#[test]
+fn a_failed_run_resumes_from_its_last_checkpoint() {
+    let (url, bodies) = chat_server();
+    let directory = scratch("checkpoint");
+    fs::write(
+        directory.join(".env"),
+        format!(
+            "LLM_BASE_URL={}\nTEXT_MODEL=stub\nEMBEDDING_MODEL=stub\nSKIP_HEALTHCHECK=true\n",
+            url
+        ),
+    )
+    .unwrap();
+    // The file the second step reads is missing on the first run.
+    let program = "LS X1, \"Summarise.\"\nINF X2, X1, C1\nCKPT \"summarised\"\nLC X3, \"input.txt\"\nPUT X2\nPLN X3\nEXIT\n";
+    fs::write(directory.join("pipeline.aasm"), program).unwrap();
+    lpu(&directory, &["build", "pipeline.aasm"], &[]);
+
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_language_processor_unit"))
+            .current_dir(&directory)
+            .args(["run", "build/pipeline.lpu"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    let saving = ["--checkpoint-dir", "checkpoints"];
+    let resuming = [
+        "--checkpoint-dir",
+        "checkpoints",
+        "--from-checkpoint",
+        "summarised",
+    ];
+
+    // Without a checkpoint directory CKPT does nothing.
+    assert!(!run(&[]).status.success());
+    assert!(!directory.join("checkpoints").exists());
+
+    assert!(!run(&saving).status.success());
+    assert!(directory.join("checkpoints/summarised.json").exists());
+    assert_eq!(bodies.lock().unwrap().len(), 2);
+
+    fs::write(directory.join("input.txt"), " and more").unwrap();
+    let resumed = run(&resuming);
+    assert!(resumed.status.success());
+    assert_eq!(
+        String::from_utf8(resumed.stdout).unwrap(),
+        "bundled answer and more\n"
+    );
+    // The request before the checkpoint is not sent again.
+    assert_eq!(bodies.lock().unwrap().len(), 2);
+
+    fs::write(
+        directory.join("pipeline.aasm"),
+        program.replace("Summarise.", "Summarise briefly."),
+    )
+    .unwrap();
+    lpu(&directory, &["build", "pipeline.aasm"], &[]);
+    let changed = run(&resuming);
+    fs::remove_dir_all(&directory).unwrap();
+
+    let stdout = String::from_utf8(changed.stdout).unwrap();
+    assert!(!changed.status.success());
+    assert!(
+        stdout.contains(
+            "Checkpoint was saved by a different program. Rerun without --from-checkpoint."
+        ),
+        "{}",
+        stdout
+    );
+}
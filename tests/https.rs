@@ -0,0 +1,76 @@
+// Sends a model request to a local TLS server whose certificate is self-signed, which the client must
+// refuse with an error naming the certificate rather than a bare transport error.
+#![cfg(feature = "https")]
+
+use std::{
+    io::Read,
+    net::TcpListener,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+use language_processor_unit::{Assembler, Config, Processor};
+
+const CERTIFICATE: &[u8] = include_bytes!("fixtures/self_signed_cert.der");
+const PRIVATE_KEY: &[u8] = include_bytes!("fixtures/self_signed_key.der");
+
+// Serves TLS for localhost with the self-signed certificate, counting the handshakes clients start.
+fn self_signed_server() -> (String, Arc<AtomicUsize>) {
+    let config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![rustls::Certificate(CERTIFICATE.to_vec())],
+            rustls::PrivateKey(PRIVATE_KEY.to_vec()),
+        )
+        .unwrap();
+    let config = Arc::new(config);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!(
+        "https://localhost:{}",
+        listener.local_addr().unwrap().port()
+    );
+    let handshakes = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&handshakes);
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { break };
+            counted.fetch_add(1, Ordering::SeqCst);
+
+            let mut connection = rustls::ServerConnection::new(Arc::clone(&config)).unwrap();
+            let mut tls = rustls::Stream::new(&mut connection, &mut stream);
+            // Fails once the client rejects the certificate.
+            let _ = tls.read(&mut [0; 1024]);
+        }
+    });
+
+    (url, handshakes)
+}
+
+#[test]
+fn an_untrusted_certificate_is_reported_without_retrying() {
+    let (url, handshakes) = self_signed_server();
+    let byte_code = Assembler::new(
+        "LS X1, \"Say ok.\"\nINF X2, X1, C1\nEXIT\n".to_string(),
+        "tls.aasm",
+    )
+    .assemble()
+    .unwrap();
+
+    let mut config = Config::new("stub", "stub");
+    config.llm_base_url = url;
+    config.skip_healthcheck = true;
+    config.retry_policy.attempts = 3;
+
+    let mut processor = Processor::new(config);
+    processor.load(&byte_code).unwrap();
+
+    let error = format!("{:?}", processor.run().unwrap_err());
+    assert!(error.contains("TLS certificate"), "{}", error);
+    assert_eq!(handshakes.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,155 @@
+// Assembles and runs programs in-process through the library, answering model requests from a script
+// so no model server is needed.
+
+use std::thread;
+
+use language_processor_unit::{Assembler, Config, Processor, ScriptedBackend};
+
+fn config() -> Config {
+    Config::new("LFM2-2.6B-Q5_K_M", "Qwen3-Embedding-0.6B-Q4_1-imat")
+}
+
+#[test]
+fn a_program_assembles_and_runs_in_process() {
+    let source = "\
+LS X1, \"Name a colour.\"
+INF X2, X1, C1
+LS X3, \"red\"
+SIM X4, X2, X3
+LS X5, \"You said ${x2}.\"
+EXIT 3
+";
+    let byte_code = Assembler::new(source.to_string(), "colour.aasm")
+        .assemble()
+        .unwrap();
+
+    let mut backend = ScriptedBackend::new();
+    backend.push_response("Red");
+    backend.set_embedding("Red", vec![1.0, 0.0]);
+    backend.set_embedding("red", vec![1.0, 0.0]);
+    let prompts = backend.prompts();
+
+    let mut processor = Processor::new(config());
+    processor.set_backend(Box::new(backend));
+    processor.load(&byte_code).unwrap();
+    processor.run().unwrap();
+
+    assert_eq!(processor.exit_status(), 3);
+    assert_eq!(processor.register(2).unwrap().to_string(), "Red");
+    assert_eq!(processor.register(5).unwrap().to_string(), "You said Red.");
+    assert_eq!(processor.register(4).unwrap().to_string(), "100");
+    assert_eq!(*prompts.lock().unwrap(), ["Name a colour."]);
+
+    let stats = processor.stats();
+    assert_eq!(stats.instructions, 6);
+    assert_eq!(stats.chat_completions, 1);
+    assert_eq!(stats.embeddings, 1);
+}
+
+#[test]
+fn a_run_fails_when_the_script_runs_out_of_responses() {
+    let source = "LS X1, \"Hello\"\nINF X2, X1, C1\nINF X3, X1, C1\nEXIT\n";
+    let byte_code = Assembler::new(source.to_string(), "hello.aasm")
+        .assemble()
+        .unwrap();
+
+    let mut backend = ScriptedBackend::new();
+    backend.push_response("Hi");
+
+    let mut processor = Processor::new(config());
+    processor.set_backend(Box::new(backend));
+    processor.load(&byte_code).unwrap();
+
+    let error = format!("{:?}", processor.run().unwrap_err());
+    assert!(error.contains("no response left"), "{}", error);
+    assert_eq!(processor.register(2).unwrap().to_string(), "Hi");
+}
+
+// Each thread asks the same question ROUNDS times, the count LOOP starts X1 at, keeping the answers in
+// its own context.
+const THREADS: usize = 8;
+const ROUNDS: usize = 25;
+const LOOP: &str = "\
+LI X1, 25
+LOOP:
+LS X2, \"Hello\"
+INF X3, X2, C1
+SUBI X1, 1
+BGT X1, X0, LOOP
+EXIT
+";
+
+// Runs LOOP on THREADS processors sharing counters and caches with a first one, which does not run.
+// Returns it and whether each run finished.
+fn run_shared(config: Config) -> (Processor, Vec<bool>) {
+    let byte_code = Assembler::new(LOOP.to_string(), "loop.aasm")
+        .assemble()
+        .unwrap();
+    let first = Processor::new(config.clone());
+
+    let runs = (0..THREADS)
+        .map(|_| {
+            let mut backend = ScriptedBackend::new();
+
+            for _ in 0..ROUNDS {
+                backend.push_response("Hi");
+            }
+
+            let mut processor = Processor::new(config.clone());
+            processor.set_backend(Box::new(backend));
+            processor.share_with(&first);
+            processor.load(&byte_code).unwrap();
+
+            thread::spawn(move || processor.run().is_ok())
+        })
+        .collect::<Vec<_>>();
+
+    let finished = runs.into_iter().map(|run| run.join().unwrap()).collect();
+    (first, finished)
+}
+
+#[test]
+fn processors_sharing_counters_lose_no_updates() {
+    let (first, finished) = run_shared(config());
+    let counters = first.counters();
+
+    assert!(finished.iter().all(|&finished| finished));
+    assert_eq!(counters.chat_completions(), THREADS * ROUNDS);
+    assert_eq!(counters.requests(), THREADS * ROUNDS);
+    assert_eq!(first.stats().chat_completions, THREADS * ROUNDS);
+}
+
+#[test]
+fn a_shared_request_budget_is_never_overspent() {
+    let mut config = config();
+    config.max_llm_calls = Some(60);
+
+    let (first, finished) = run_shared(config);
+
+    assert!(finished.iter().any(|&finished| !finished));
+    assert_eq!(first.counters().chat_completions(), 60);
+}
+
+#[test]
+fn a_shared_cache_counts_every_lookup() {
+    let mut config = config();
+    config.llm_cache = true;
+
+    let (first, finished) = run_shared(config);
+    let counters = first.counters();
+
+    assert!(finished.iter().all(|&finished| finished));
+    assert_eq!(
+        counters.cache_hits() + counters.cache_misses(),
+        THREADS * ROUNDS
+    );
+    assert_eq!(counters.chat_completions(), counters.cache_misses());
+}
+
+#[test]
+fn assembly_errors_are_returned_with_diagnostics() {
+    let mut assembler = Assembler::new("LI X1,\nEXIT\n".to_string(), "broken.aasm");
+
+    assert!(assembler.assemble().is_err());
+    assert!(!assembler.diagnostics().is_empty());
+}
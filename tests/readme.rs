@@ -0,0 +1,74 @@
+// Assembles every assembly example in the README, so the examples keep up with the language.
+
+use std::{env::temp_dir, fs, process};
+
+use language_processor_unit::Assembler;
+
+const README: &str = include_str!("../README.md");
+
+// The fenced code blocks of the README, with their language tag and the line of their opening fence.
+fn code_blocks() -> Vec<(String, usize, String)> {
+    let mut blocks = Vec::new();
+    let mut open: Option<(String, usize, String)> = None;
+
+    for (index, line) in README.lines().enumerate() {
+        let trimmed = line.trim_start();
+
+        match (open.take(), trimmed.strip_prefix("```")) {
+            (None, Some(tag)) => open = Some((tag.to_string(), index + 1, String::new())),
+            (Some(block), Some(_)) => blocks.push(block),
+            (Some((tag, line_number, mut text)), None) => {
+                text.push_str(trimmed);
+                text.push('\n');
+                open = Some((tag, line_number, text));
+            }
+            (None, None) => {}
+        }
+    }
+
+    blocks
+}
+
+#[test]
+fn every_readme_assembly_example_assembles() {
+    // Files the examples include exist, but are empty.
+    let directory = temp_dir().join(format!("lpu-readme-{}", process::id()));
+    fs::create_dir_all(directory.join("lib")).unwrap();
+    fs::write(directory.join("lib/prompts.aasm"), "").unwrap();
+
+    let blocks = code_blocks();
+    let mut assembled = 0;
+
+    for (tag, line, source) in &blocks {
+        assert!(
+            !tag.is_empty(),
+            "The code block on README line {} needs a language tag, such as 'asm'.",
+            line
+        );
+
+        if tag != "asm" {
+            continue;
+        }
+
+        let path = directory.join(format!("readme-{}.aasm", line));
+        let mut assembler = Assembler::new(source.clone(), path.to_str().unwrap());
+
+        if assembler.assemble().is_err() {
+            let diagnostics = assembler
+                .diagnostics()
+                .iter()
+                .map(|diagnostic| diagnostic.to_string())
+                .collect::<Vec<_>>();
+            panic!(
+                "The example on README line {} does not assemble:\n{}",
+                line,
+                diagnostics.join("\n")
+            );
+        }
+
+        assembled += 1;
+    }
+
+    fs::remove_dir_all(&directory).unwrap();
+    assert!(assembled > 20, "only {} examples were found", assembled);
+}